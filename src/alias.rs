@@ -1,30 +1,61 @@
 //! エイリアスに関連する関数を提供するモジュール
 
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use crate::state::{self, LockExt};
+use std::cell::RefCell;
 
-static ALIASES: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+thread_local! {
+    /// 展開中のエイリアス（エイリアスが自分自身を含む場合の再帰を防ぐ）
+    static EXPANDING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
 
 /// エイリアスを取得
 pub fn get_alias(name: &str) -> Option<String> {
-    ALIASES.lock().unwrap().get(name).cloned()
+    state::current()
+        .aliases
+        .lock_or_recover()
+        .get(name)
+        .cloned()
+}
+
+/// エイリアスを展開して処理を実行する
+///
+/// 展開中のエイリアスは再び展開しない（`alias ls="ls -a"` の中の `ls` はコマンドとして扱う）
+///
+/// # Arguments
+/// * `name` - コマンド名
+/// * `f` - エイリアスの値を受け取る処理（この間 `name` は展開中として扱う）
+///
+/// # Returns
+/// * `None` - エイリアスが定義されていない、または展開中の場合
+pub fn with_alias_expanded<T>(name: &str, f: impl FnOnce(&str) -> T) -> Option<T> {
+    if EXPANDING.with(|expanding| expanding.borrow().iter().any(|n| n == name)) {
+        return None;
+    }
+    let value = get_alias(name)?;
+
+    EXPANDING.with(|expanding| expanding.borrow_mut().push(name.to_string()));
+    let result = f(&value);
+    EXPANDING.with(|expanding| expanding.borrow_mut().pop());
+
+    Some(result)
 }
 
 /// エイリアスを設定
 pub fn set_alias(name: &str, command: &str) {
-    ALIASES
-        .lock()
-        .unwrap()
+    state::current()
+        .aliases
+        .lock_or_recover()
         .insert(name.to_string(), command.to_string());
 }
 
-/// 全エイリアスを取得
+/// 全エイリアスを名前順で取得
 pub fn list_aliases() -> Vec<(String, String)> {
-    ALIASES
-        .lock()
-        .unwrap()
+    let mut aliases: Vec<(String, String)> = state::current()
+        .aliases
+        .lock_or_recover()
         .iter()
         .map(|(k, v)| (k.clone(), v.clone()))
-        .collect()
+        .collect();
+    aliases.sort();
+    aliases
 }
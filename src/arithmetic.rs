@@ -0,0 +1,468 @@
+//! 算術式の評価を提供するモジュール
+//!
+//! `$((...))` 展開で使われる整数演算を扱う。
+//! 対応する演算子（優先順位の低い順）:
+//!
+//! - `==` `!=`
+//! - `<` `<=` `>` `>=`
+//! - `+` `-`
+//! - `*` `/` `%`
+//! - 単項 `+` `-` `!`
+//!
+//! 比較演算の結果は真なら1、偽なら0になる。
+//! 変数名は `get_var` で解決し、未定義または空の場合は0として扱う。
+
+use crate::environment::{get_var, is_valid_var_name, set_var};
+use crate::error::{Result, RucliError};
+
+/// 算術式の字句
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+/// 算術式を評価する
+///
+/// # Arguments
+/// * `expr` - 評価する式（例: "I + 1", "(2 * 3) % 4"）
+///
+/// # Errors
+/// - 式の構文が不正な場合
+/// - 0で除算した場合
+/// - 変数の値が整数でない場合
+pub fn evaluate(expr: &str) -> Result<i64> {
+    let tokens = tokenize(expr)?;
+
+    // 空の式は0
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let value = parser.parse_equality()?;
+
+    // 余分なトークンが残っていればエラー
+    if let Some(token) = parser.peek() {
+        return Err(RucliError::ParseError(format!(
+            "arithmetic: unexpected token {token:?} in '{expr}'"
+        )));
+    }
+
+    Ok(value)
+}
+
+/// 代入を含む算術式を評価する（`let` や C言語風 for で使用）
+///
+/// 以下の形式に対応し、カンマ区切りの場合は最後の値を返す:
+///
+/// - `NAME = expr`、`NAME += expr` などの複合代入
+/// - `NAME++`、`NAME--`、`++NAME`、`--NAME`
+/// - 代入を含まない通常の式
+///
+/// # Errors
+/// - 式の構文が不正な場合
+/// - 0で除算した場合
+pub fn evaluate_assignment(expr: &str) -> Result<i64> {
+    let mut last = 0;
+
+    for part in split_top_level_commas(expr) {
+        last = evaluate_single_assignment(part.trim())?;
+    }
+
+    Ok(last)
+}
+
+/// 単一の代入式を評価する
+fn evaluate_single_assignment(expr: &str) -> Result<i64> {
+    // インクリメント・デクリメント
+    for (op, delta) in [("++", 1), ("--", -1)] {
+        if let Some(name) = expr.strip_suffix(op).map(str::trim)
+            && is_valid_var_name(name)
+        {
+            let old = resolve_variable(name)?;
+            set_var(name, &old.wrapping_add(delta).to_string());
+            return Ok(old);
+        }
+        if let Some(name) = expr.strip_prefix(op).map(str::trim)
+            && is_valid_var_name(name)
+        {
+            let new = resolve_variable(name)?.wrapping_add(delta);
+            set_var(name, &new.to_string());
+            return Ok(new);
+        }
+    }
+
+    // 代入（==, <=, >=, != は比較なので除外）
+    if let Some(pos) = find_assignment(expr) {
+        let (lhs, rhs) = (&expr[..pos], &expr[pos + 1..]);
+        let (name, op) = match lhs.trim_end().char_indices().last() {
+            Some((i, c)) if "+-*/%".contains(c) => (lhs[..i].trim(), Some(c)),
+            _ => (lhs.trim(), None),
+        };
+
+        if !is_valid_var_name(name) {
+            return Err(RucliError::ParseError(format!(
+                "arithmetic: invalid assignment target '{name}'"
+            )));
+        }
+
+        let value = match op {
+            Some(op) => evaluate(&format!("{name} {op} ({rhs})"))?,
+            None => evaluate(rhs)?,
+        };
+        set_var(name, &value.to_string());
+        return Ok(value);
+    }
+
+    evaluate(expr)
+}
+
+/// 代入演算子 `=` の位置を探す
+fn find_assignment(expr: &str) -> Option<usize> {
+    let bytes = expr.as_bytes();
+    (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && bytes.get(i + 1) != Some(&b'=')
+            && !(i > 0 && matches!(bytes[i - 1], b'=' | b'<' | b'>' | b'!'))
+    })
+}
+
+/// 括弧の外側にあるカンマで分割する
+fn split_top_level_commas(expr: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, ch) in expr.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&expr[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&expr[start..]);
+
+    parts
+}
+
+/// 文字列をトークン列に分解する
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&d) = chars.peek() {
+                    if d.is_ascii_digit() {
+                        number.push(d);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let value = number.parse::<i64>().map_err(|_| {
+                    RucliError::ParseError(format!("arithmetic: number too large: {number}"))
+                })?;
+                tokens.push(Token::Number(value));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&d) = chars.peek() {
+                    if d.is_alphanumeric() || d == '_' {
+                        name.push(d);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(name));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            _ => {
+                chars.next();
+                // 2文字演算子を優先
+                let op = match (ch, chars.peek()) {
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('!', _) => "!",
+                    ('+', _) => "+",
+                    ('-', _) => "-",
+                    ('*', _) => "*",
+                    ('/', _) => "/",
+                    ('%', _) => "%",
+                    _ => {
+                        return Err(RucliError::ParseError(format!(
+                            "arithmetic: invalid character '{ch}' in '{expr}'"
+                        )));
+                    }
+                };
+                if op.len() == 2 {
+                    chars.next();
+                }
+                tokens.push(Token::Op(op));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// 再帰下降パーサ
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// 現在のトークンが指定した演算子のいずれかであれば消費して返す
+    fn take_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    // equality := relational (("==" | "!=") relational)*
+    fn parse_equality(&mut self) -> Result<i64> {
+        let mut left = self.parse_relational()?;
+        while let Some(op) = self.take_op(&["==", "!="]) {
+            let right = self.parse_relational()?;
+            left = match op {
+                "==" => (left == right) as i64,
+                _ => (left != right) as i64,
+            };
+        }
+        Ok(left)
+    }
+
+    // relational := additive (("<" | "<=" | ">" | ">=") additive)*
+    fn parse_relational(&mut self) -> Result<i64> {
+        let mut left = self.parse_additive()?;
+        while let Some(op) = self.take_op(&["<", "<=", ">", ">="]) {
+            let right = self.parse_additive()?;
+            left = match op {
+                "<" => (left < right) as i64,
+                "<=" => (left <= right) as i64,
+                ">" => (left > right) as i64,
+                _ => (left >= right) as i64,
+            };
+        }
+        Ok(left)
+    }
+
+    // additive := term (("+" | "-") term)*
+    fn parse_additive(&mut self) -> Result<i64> {
+        let mut left = self.parse_term()?;
+        while let Some(op) = self.take_op(&["+", "-"]) {
+            let right = self.parse_term()?;
+            left = match op {
+                "+" => left.wrapping_add(right),
+                _ => left.wrapping_sub(right),
+            };
+        }
+        Ok(left)
+    }
+
+    // term := unary (("*" | "/" | "%") unary)*
+    fn parse_term(&mut self) -> Result<i64> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.take_op(&["*", "/", "%"]) {
+            let right = self.parse_unary()?;
+            if op != "*" && right == 0 {
+                return Err(RucliError::RuntimeError(
+                    "arithmetic: division by zero".to_string(),
+                ));
+            }
+            left = match op {
+                "*" => left.wrapping_mul(right),
+                "/" => left.wrapping_div(right),
+                _ => left.wrapping_rem(right),
+            };
+        }
+        Ok(left)
+    }
+
+    // unary := ("+" | "-" | "!") unary | primary
+    fn parse_unary(&mut self) -> Result<i64> {
+        match self.take_op(&["+", "-", "!"]) {
+            Some("-") => Ok(self.parse_unary()?.wrapping_neg()),
+            Some("!") => Ok((self.parse_unary()? == 0) as i64),
+            Some(_) => self.parse_unary(),
+            None => self.parse_primary(),
+        }
+    }
+
+    // primary := number | ident | "(" equality ")"
+    fn parse_primary(&mut self) -> Result<i64> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Ident(name)) => resolve_variable(&name),
+            Some(Token::LParen) => {
+                let value = self.parse_equality()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(value),
+                    _ => Err(RucliError::ParseError(
+                        "arithmetic: missing ')'".to_string(),
+                    )),
+                }
+            }
+            Some(token) => Err(RucliError::ParseError(format!(
+                "arithmetic: unexpected token {token:?}"
+            ))),
+            None => Err(RucliError::ParseError(
+                "arithmetic: unexpected end of expression".to_string(),
+            )),
+        }
+    }
+}
+
+/// 変数の値を整数として取得する（未定義・空は0）
+fn resolve_variable(name: &str) -> Result<i64> {
+    match get_var(name) {
+        Some(value) if !value.trim().is_empty() => value.trim().parse::<i64>().map_err(|_| {
+            RucliError::InvalidArgument(format!(
+                "arithmetic: {name}: value '{value}' is not an integer"
+            ))
+        }),
+        _ => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::set_var;
+
+    #[test]
+    fn test_basic_operators() {
+        assert_eq!(evaluate("1 + 2").unwrap(), 3);
+        assert_eq!(evaluate("10 - 4").unwrap(), 6);
+        assert_eq!(evaluate("3 * 4").unwrap(), 12);
+        assert_eq!(evaluate("7 / 2").unwrap(), 3);
+        assert_eq!(evaluate("7 % 3").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_precedence_and_parentheses() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(evaluate("-(2 + 3)").unwrap(), -5);
+        assert_eq!(evaluate("10 - 2 - 3").unwrap(), 5);
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        assert_eq!(evaluate("1 < 2").unwrap(), 1);
+        assert_eq!(evaluate("2 <= 1").unwrap(), 0);
+        assert_eq!(evaluate("3 > 2").unwrap(), 1);
+        assert_eq!(evaluate("3 >= 4").unwrap(), 0);
+        assert_eq!(evaluate("5 == 5").unwrap(), 1);
+        assert_eq!(evaluate("5 != 5").unwrap(), 0);
+        assert_eq!(evaluate("!0").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_variables() {
+        set_var("ARITH_X", "4");
+        set_var("ARITH_EMPTY", "");
+
+        assert_eq!(evaluate("ARITH_X * 2").unwrap(), 8);
+        assert_eq!(evaluate("ARITH_EMPTY + 1").unwrap(), 1);
+        assert_eq!(evaluate("ARITH_UNSET + 1").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            evaluate("1 / 0"),
+            Err(RucliError::RuntimeError(msg)) if msg.contains("division by zero")
+        ));
+        assert!(evaluate("5 % 0").is_err());
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+        assert!(evaluate("1 2").is_err());
+        assert!(evaluate("1 & 2").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_assignment() {
+        assert_eq!(evaluate_assignment("ASSIGN_A = 2 * 3").unwrap(), 6);
+        assert_eq!(get_var("ASSIGN_A"), Some("6".to_string()));
+
+        assert_eq!(evaluate_assignment("ASSIGN_A=ASSIGN_A+1").unwrap(), 7);
+        assert_eq!(evaluate_assignment("ASSIGN_A += 3").unwrap(), 10);
+        assert_eq!(evaluate_assignment("ASSIGN_A *= 2").unwrap(), 20);
+        assert_eq!(get_var("ASSIGN_A"), Some("20".to_string()));
+
+        // 比較演算子は代入とみなさない
+        assert_eq!(evaluate_assignment("ASSIGN_A == 20").unwrap(), 1);
+        assert_eq!(evaluate_assignment("ASSIGN_A <= 19").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_evaluate_increment() {
+        set_var("ASSIGN_I", "1");
+
+        // 後置は元の値、前置は新しい値を返す
+        assert_eq!(evaluate_assignment("ASSIGN_I++").unwrap(), 1);
+        assert_eq!(evaluate_assignment("++ASSIGN_I").unwrap(), 3);
+        assert_eq!(evaluate_assignment("ASSIGN_I--").unwrap(), 3);
+        assert_eq!(get_var("ASSIGN_I"), Some("2".to_string()));
+    }
+
+    #[test]
+    fn test_evaluate_assignment_commas_and_errors() {
+        assert_eq!(
+            evaluate_assignment("ASSIGN_X=1, ASSIGN_Y=ASSIGN_X+1").unwrap(),
+            2
+        );
+        assert_eq!(get_var("ASSIGN_Y"), Some("2".to_string()));
+
+        assert!(evaluate_assignment("1 = 2").is_err());
+        assert!(evaluate_assignment("ASSIGN_Z = 1 / 0").is_err());
+    }
+
+    #[test]
+    fn test_empty_expression() {
+        assert_eq!(evaluate("").unwrap(), 0);
+        assert_eq!(evaluate("   ").unwrap(), 0);
+    }
+}
@@ -1,95 +1,291 @@
 //! コマンドの定義と実行を管理するモジュール
 
-use crate::environment::expand_variables;
-use crate::error::Result;
+use crate::arithmetic::evaluate_assignment;
+use crate::environment::{
+    expand_assignment_value, expand_variables, expand_word, substitution_deferred,
+    with_substitution_deferred,
+};
+use crate::error::{Result, RucliError};
 use crate::handlers::*;
-use crate::parser::parse_command;
+use crate::job::JobSpec;
+use crate::parser::{is_quoted_word, parse_command, parse_resolved, split_quoted_words};
 use crate::pipeline::{PipelineCommand, PipelineExecutor};
 use crate::redirect::execute_redirect;
+use crate::trap::TrapSignal;
 use log::debug;
+use serde::{Serialize, Serializer};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// 出力をファイルや次のステージにバイト列のまま渡しているか
+    static PASSING_BYTES: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 出力をバイト列のまま渡す間の印を付け、スコープを抜けると元に戻すガード
+///
+/// この間はオプションなしのcatの出力に改行を足さない（`cat < image.png > copy.png`）。
+/// 端末に表示する出力は、これまでどおり改行で終わる形にそろえる
+pub(crate) struct PassingBytes(bool);
+
+impl PassingBytes {
+    pub(crate) fn enter() -> Self {
+        Self(PASSING_BYTES.with(|flag| flag.replace(true)))
+    }
+}
+
+impl Drop for PassingBytes {
+    fn drop(&mut self) {
+        PASSING_BYTES.with(|flag| flag.set(self.0));
+    }
+}
 
 /// コマンドの実行結果を表す列挙型
+///
+/// 出力は書き出す内容そのままで、空でなければ改行で終わる（`echo -n` の出力だけは改行を付けない）。
+/// 表示・リダイレクト・パイプラインの次のステージへの入力はこの出力をそのまま使い、
+/// コマンド置換は末尾の改行をすべて取り除く
 pub enum CommandResult {
-    /// 通常のコマンド実行結果（出力文字列）
-    Continue(String),
-    /// プログラムの終了要求
-    Exit,
+    /// 通常のコマンド実行結果（出力のバイト列）
+    Continue(Vec<u8>),
+    /// プログラムの終了要求（exitまでに出力したバイト列）
+    ///
+    /// exitはどの制御構文・パイプライン・関数の中でも残りのコマンドを実行せずに外へ伝わり、
+    /// トップレベルでシェルを終了する。サブシェルとコマンド置換の中のexitはその中だけを終える
+    Exit(Vec<u8>),
+}
+
+/// 一覧系コマンド（ls, jobs, env, history）の出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum OutputFormat {
+    #[default]
+    Text, // 人間向けのテキスト
+    Json, // オブジェクトのJSON配列（--json / -o json）
 }
 
-#[derive(Debug, Clone)]
+/// lsの並び順（同じ値のエントリは名前順）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum LsSort {
+    #[default]
+    Name, // 名前順
+    Time,     // -t: 更新日時の新しい順
+    Size,     // -S: サイズの大きい順
+    Unsorted, // --unsorted: ディレクトリから読んだ順（大きなディレクトリで並べ替えを省く）
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum HistoryAction {
-    List,           // 全履歴表示
-    Search(String), // 検索
-    Execute(usize), // 番号で実行
+    List,          // 全履歴表示
+    Recent(usize), // 最新N件を表示
+    // 検索: 新しい順に、同じコマンドは一度だけ（regexなら正規表現、allでなければ最大20件）
+    Search {
+        query: String,
+        regex: bool,
+        all: bool,
+    },
+    // 番号で実行（assume_yesなら破壊的なコマンドも確認せずに実行する）
+    Execute {
+        index: usize,
+        assume_yes: bool,
+    },
+    Clear,         // 全履歴を削除
+    Delete(usize), // 番号で削除
+    // fc -s: 置換（old=new）してから再実行
+    Substitute {
+        replacement: Option<(String, String)>,
+        target: Option<String>, // 省略時は最新のコマンド
+    },
+    Edit(Option<String>), // fc: エディタで編集してから実行
+}
+
+/// ディレクトリスタックの操作
+#[derive(Debug, Clone, Serialize)]
+pub enum DirStackAction {
+    Push(Option<String>), // pushd: 移動して元の場所を積む（Noneなら先頭の二つを入れ替える）
+    Pop,                  // popd: 先頭の場所に戻り、スタックから取り除く
+    Show { verbose: bool }, // dirs: スタックを表示（-vなら番号付き）
+}
+
+/// trapコマンドのアクション
+#[derive(Debug, Clone, Serialize)]
+pub enum TrapAction {
+    List, // trap: 登録されている trap を表示
+    Set {
+        command: String,
+        signals: Vec<TrapSignal>,
+    }, // trap command SIG...: 登録
+    Remove(Vec<TrapSignal>), // trap - SIG...: 削除
 }
 
 /// 実行可能なコマンドを表す列挙型
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Command {
     /// ヘルプを表示
     Help,
+    /// 画面を消去
+    Clear,
     /// メッセージを出力
-    Echo { message: String },
+    Echo {
+        message: String,
+        newline: bool, // 末尾に改行を付けるか（-n でfalse）
+    },
     /// メッセージを繰り返し出力
-    Repeat { count: i32, message: String },
+    Repeat {
+        count: i32,
+        message: String,
+        delay: Option<Duration>, // -d: 各出力の間に待つ時間
+    },
     /// ファイルの内容を表示
-    Cat { filename: String },
+    Cat {
+        filename: String,
+        number: bool,           // -n: 行番号を付ける
+        show_nonprinting: bool, // -v: 制御文字を ^X の形で表示
+        show_ends: bool,        // -E: 行末に $ を表示
+        show_tabs: bool,        // -T: タブを ^I と表示
+    },
     /// ファイルに内容を書き込む
     Write { filename: String, content: String },
     /// ディレクトリの内容を一覧表示
-    Ls,
+    Ls {
+        format: OutputFormat,
+        recursive: bool, // -R: サブディレクトリも `dir:` の見出しごとに表示
+        sort: LsSort,    // -t / -S: 並び順（既定は名前順）
+        reverse: bool,   // -r: 並び順を逆にする
+    },
     /// ディレクトリを変更
     Cd { path: String },
     /// 現在の作業ディレクトリを表示
     Pwd,
+    /// ディレクトリスタックを操作（pushd / popd / dirs）
+    DirStack { action: DirStackAction },
     /// ディレクトリを作成
-    Mkdir { path: String, parents: bool },
+    Mkdir {
+        paths: Vec<String>,
+        parents: bool,
+        mode: Option<u32>, // -m: umaskに関係なく設定するパーミッション
+    },
     /// ファイル/ディレクトリを削除
     Rm {
         path: String,
         recursive: bool,
         force: bool,
+        preserve_root: bool, // false（--no-preserve-root）なら / や ~ も確認せずに削除する
     },
     /// ファイル/ディレクトリをコピー
     Cp {
         source: String,
         destination: String,
         recursive: bool,
+        interactive: bool, // 既存のファイルを上書きする前に確認する（-i）
+        verbose: bool,     // コピーしたファイルを表示する（-v）
     },
     /// ファイル/ディレクトリの移動
-    Mv { source: String, destination: String },
+    Mv {
+        source: String,
+        destination: String,
+        interactive: bool,
+        verbose: bool,
+    },
+    /// ワイルドカードのパターンに一致するファイルをまとめて名前変更
+    Rename {
+        pattern: String,     // 変更するファイルのパターン（例: *.jpeg）
+        replacement: String, // 新しい名前（`*` `?` にパターンで一致した部分が入る）
+        dry_run: bool,       // -n: 名前を変えずに変更内容だけ表示する
+    },
     /// ファイルの検索
     Find {
-        path: Option<String>, // 検索開始ディレクトリ(何もなければホームポジション)
-        name: String,         // 検索するファイル名
+        path: Option<String>,     // 検索開始ディレクトリ(何もなければホームポジション)
+        name: String,             // 検索するファイル名
+        filters: Vec<FindFilter>, // すべて満たすエントリだけを表示する条件
+        no_ignore: bool,          // --no-ignore: target や .git なども辿る
+        print0: bool,             // -print0: 改行ではなくNULで区切る（末尾にもNUL）
     },
     /// ファイル内のテキスト検索
-    Grep { pattern: String, files: Vec<String> },
+    Grep {
+        pattern: String,
+        files: Vec<String>,
+        count: bool, // -c: 一致した行ではなく行数を表示
+        stats: bool, // --stats: 検索したファイル数などの集計を標準エラーに表示
+    },
+    /// ディレクトリごとの使用量を表示
+    Du {
+        paths: Vec<String>,   // 対象のパス（空ならカレントディレクトリ）
+        human_readable: bool, // -h: K/M/G 単位で表示
+        summarize: bool,      // -s: 合計のみ表示
+        no_ignore: bool,      // --no-ignore: target や .git なども集計する
+    },
+    /// 各行からフィールド・文字を切り出す
+    Cut {
+        selection: CutSelection, // -f または -c で指定した範囲
+        delimiter: char,         // -d: フィールドの区切り文字（既定はタブ）
+        only_delimited: bool,    // -s: 区切り文字を含まない行を出力しない
+        files: Vec<String>,      // 入力ファイル（空ならパイプラインの入力）
+    },
+    /// パイプラインの入力の文字を置換・削除する
+    Tr {
+        set1: String,         // 置換元・削除する文字集合
+        set2: Option<String>, // 置換先の文字集合
+        delete: bool,         // -d: set1の文字を削除
+        squeeze: bool,        // -s: 連続する文字を一つにまとめる
+    },
+    /// 二つのファイルの差分を表示
+    Diff {
+        file1: String,
+        file2: String,
+        brief: bool, // -q: 異なるかどうかだけ表示
+    },
+    /// ディレクトリ構造をツリー表示
+    Tree {
+        path: Option<String>,     // 起点のディレクトリ（なければカレントディレクトリ）
+        max_depth: Option<usize>, // -L: 表示する最大の深さ
+        show_hidden: bool,        // -a: ドットファイルも表示
+        no_ignore: bool,          // --no-ignore: target や .git なども表示する
+    },
     /// アライアス設定
     Alias {
         name: Option<String>,
         command: Option<String>,
     },
-    /// パイプラインコマンド
-    Pipeline { commands: Vec<String> },
+    /// パイプラインコマンド（各段はパース済み）
+    Pipeline { commands: Vec<Command> },
     /// バージョン表示
     Version,
     /// リダイレクト付きコマンド
     Redirect {
-        command: Box<Command>, // 実行するコマンド
-        redirect_type: String, // ">", ">>", "<"
-        target: String,        // ファイル名
+        command: Box<Command>,       // 実行するコマンド
+        redirects: Vec<Redirection>, // 左から順に適用するリダイレクト
     },
     /// バックグラウンド実行
-    Background { command: Box<Command> },
+    Background {
+        command: Box<Command>,
+        text: String, // 入力されたとおりのコマンド（末尾の `&` を除く、jobs・fgでの表示用）
+        stopped: bool, // 停止した状態で作り、bg / fg まで実行しない（`command &?`）
+    },
+    /// 終了ステータスの反転（`! command`）
+    Not { command: Box<Command> },
+    /// 実行時間の計測（`time command`）
+    Time { command: Box<Command> },
+    /// 引数をつなげた文字列をコマンドとして現在のシェルで実行（`eval args...`）
+    Eval { args: Vec<String> },
+    /// サブシェル（`( ... )`、中での変数やカレントディレクトリの変更は外に影響しない）
+    Subshell { command: Box<Command> },
     /// スリープ
-    Sleep { seconds: u64 },
+    Sleep { duration: Duration },
     /// ジョブ一覧表示
-    Jobs,
+    Jobs { format: OutputFormat },
     /// フォアグラウンド処理切り替え
-    Fg { job_id: Option<u32> },
+    Fg { job: Option<JobSpec> },
+    /// 停止しているジョブをバックグラウンドで実行する
+    Bg { job: Option<JobSpec> },
     /// 環境変数コマンド
-    Environment { action: EnvironmentAction },
+    Environment {
+        action: EnvironmentAction,
+        format: OutputFormat,
+    },
+    /// 変数をシステム環境変数にエクスポート（`export NAME[=value]...`、値がなければ今の値）
+    Export {
+        variables: Vec<(String, Option<String>)>,
+    },
     /// if条件分岐
     If {
         condition: Box<Command>,         // 条件コマンド
@@ -107,24 +303,164 @@ pub enum Command {
         items: Vec<String>,
         body: Box<Command>,
     },
+    /// C言語風のFor繰り返し
+    ArithmeticFor {
+        init: String,      // 初期化式
+        condition: String, // 継続条件（空なら常に真）
+        step: String,      // 更新式
+        body: Box<Command>,
+    },
+    /// 算術式の評価と代入
+    Let { expressions: Vec<String> },
+    /// 入力から一行読み込んで変数に代入
+    Read {
+        prompt: Option<String>,
+        variables: Vec<String>,
+    },
+    /// 数列を一行ずつ出力（引数は実行時に数値として解釈）
+    Seq {
+        args: Vec<String>,
+        equal_width: bool,
+    },
+    /// 入力をコマンドの引数にして実行
+    Xargs {
+        command: Vec<String>,    // 実行するコマンドと初期引数
+        max_args: Option<usize>, // -n: 一回あたりの引数の数
+        replace: Option<String>, // -I: 置換する文字列（一行ごとに実行）
+        exit_on_error: bool,     // -e: 失敗したら残りを実行しない
+    },
+    /// コマンド名の解決方法を表示（external_onlyならPATHのみ検索）
+    Type {
+        names: Vec<String>,
+        external_only: bool,
+    },
+    /// 変数代入（commandがあればその実行中だけ有効）
+    Assignment {
+        assignments: Vec<(String, String)>,
+        command: Option<Box<Command>>,
+    },
     /// 関数定義
     Function { name: String, body: Box<Command> },
     /// 関数呼び出し
     FunctionCall { name: String, args: Vec<String> },
+    /// 組み込みでも定義済みの関数でもないコマンド（実行するときにエイリアス・関数として解決する）
+    Unresolved { text: String },
     /// 複数のコマンドを順次実行
     Compound { commands: Vec<Command> },
+    /// シェルオプションを設定（空なら一覧を表示）
+    Set { options: Vec<(String, bool)> },
     /// 履歴を表示
-    History { action: HistoryAction },
-    /// プログラムを終了
-    Exit,
+    History {
+        action: HistoryAction,
+        format: OutputFormat,
+    },
+    /// プログラムを終了（statusを省略すると0）
+    Exit { status: Option<i32> },
+    /// `.` だけの行まで読んだ複数行を、確認してからまとめて実行する（読み取りはシェルが行う）
+    Paste,
+    /// ファイル作成時のマスクを表示・設定（maskがNoneなら表示）
+    Umask { mask: Option<u32> },
+    /// 終了時・シグナル受信時に実行するコマンドを登録・削除・表示
+    Trap { action: TrapAction },
+    /// シェルを外部コマンドで置き換える（argsが空ならリダイレクトをシェル自身に適用する）
+    Exec {
+        args: Vec<String>,
+        redirects: Vec<Redirection>,
+    },
 }
 
 /// 環境変数のアクション
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum EnvironmentAction {
-    List,                // env
+    // env / env PATTERN / env --session: 名前順の一覧（パターンに一致する名前、セッション変数のみ）
+    List {
+        pattern: Option<String>,
+        session_only: bool,
+    },
     Show(String),        // env VAR
     Set(String, String), // env VAR=value
+    // env [-i] VAR=value... command: 変数を上書きしてコマンドを実行（-i なら上書きした変数だけ）
+    Run {
+        isolated: bool,
+        overrides: Vec<(String, String)>,
+        command: Box<Command>,
+    },
+}
+
+/// 一つのリダイレクト
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Redirection {
+    /// ">", ">>", "<", "2>", "2>>", "2>&1", "&>", "&>>", ">&"
+    pub redirect_type: String,
+    /// ファイル名（"2>&1" では空）
+    pub target: String,
+}
+
+impl Redirection {
+    /// 既存のファイルの内容を消してから書き込むか（/dev/null は除く）
+    pub fn truncates(&self) -> bool {
+        matches!(self.redirect_type.as_str(), ">" | ">|" | "2>" | "&>" | ">&")
+            && self.target != "/dev/null"
+    }
+}
+
+/// cutで切り出す範囲（1始まり、両端を含む）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CutRange {
+    pub start: usize,
+    pub end: Option<usize>, // Noneなら行末まで
+}
+
+impl CutRange {
+    /// 指定した位置（1始まり）が範囲に含まれるか
+    pub fn contains(&self, position: usize) -> bool {
+        position >= self.start && self.end.is_none_or(|end| position <= end)
+    }
+}
+
+/// cutの切り出し方
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum CutSelection {
+    Fields(Vec<CutRange>),     // -f: 区切り文字で分けたフィールド
+    Characters(Vec<CutRange>), // -c: 文字
+}
+
+/// findの数値の条件（`+N` はNより大きい、`-N` はNより小さい、`N` はちょうどN）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FindNumber {
+    #[serde(serialize_with = "serialize_ordering")]
+    pub ordering: std::cmp::Ordering, // 実際の値とNを比べた結果
+    pub value: u64,
+}
+
+/// 比較の結果を "less" / "equal" / "greater" として書き出す
+fn serialize_ordering<S: Serializer>(
+    ordering: &std::cmp::Ordering,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(match ordering {
+        std::cmp::Ordering::Less => "less",
+        std::cmp::Ordering::Equal => "equal",
+        std::cmp::Ordering::Greater => "greater",
+    })
+}
+
+impl FindNumber {
+    /// 実際の値が条件を満たすか
+    pub fn matches(&self, actual: u64) -> bool {
+        actual.cmp(&self.value) == self.ordering
+    }
+}
+
+/// findの絞り込み条件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum FindFilter {
+    /// -mtime: 最後の更新からの経過日数（24時間単位で切り捨て）
+    ModifiedDays(FindNumber),
+    /// -size: unitバイト単位のサイズ（切り上げ）
+    Size { size: FindNumber, unit: u64 },
+    /// -newer: 指定したファイルより後に更新された
+    Newer(String),
 }
 
 /// コマンドのメタ情報を保持する構造体
@@ -139,6 +475,11 @@ pub struct CommandInfo {
     pub min_args: usize,
     /// コマンドの最大引数個数(無制限であればNone)
     pub max_args: Option<usize>,
+    /// 受け付けるオプション（値を取るものは `"-m MODE"` のように書く）
+    ///
+    /// 空でなければ、ここにない `-` で始まる語はエラーになり、
+    /// 引数の個数はオプションを除いた位置引数だけで数える
+    pub flags: &'static [&'static str],
 }
 
 /// 利用可能なコマンド一覧
@@ -149,20 +490,31 @@ pub const COMMANDS: &[CommandInfo] = &[
         usage: "help",
         min_args: 0,
         max_args: Some(0),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "clear",
+        description: "Clear the terminal screen",
+        usage: "clear",
+        min_args: 0,
+        max_args: Some(0),
+        flags: &[],
     },
     CommandInfo {
         name: "echo",
         description: "Display message",
-        usage: "echo <message...>",
-        min_args: 1,
+        usage: "echo [-n] [message...]",
+        min_args: 0,
         max_args: None,
+        flags: &[],
     },
     CommandInfo {
         name: "cat",
-        description: "Display file contents",
-        usage: "cat <filename>",
+        description: "Display file contents (standard input if no file)",
+        usage: "cat [-n] [-A | -v -E -T] [filename]",
         min_args: 0,
         max_args: Some(1),
+        flags: &["-n", "-A", "-v", "-E", "-T"],
     },
     CommandInfo {
         name: "write",
@@ -170,27 +522,31 @@ pub const COMMANDS: &[CommandInfo] = &[
         usage: "write <filename> <content...>",
         min_args: 2,
         max_args: None,
+        flags: &[],
     },
     CommandInfo {
         name: "ls",
         description: "List directory contents",
-        usage: "ls",
+        usage: "ls [-R] [-t | -S | --unsorted] [-r] [--json | -o json]",
         min_args: 0,
         max_args: Some(0),
+        flags: &["-R", "-t", "-S", "-r", "--unsorted", "--json", "-o FORMAT"],
     },
     CommandInfo {
         name: "repeat",
-        description: "Repeat message count times",
-        usage: "repeat <count> <message...>",
+        description: "Repeat message count times, optionally waiting between lines",
+        usage: "repeat [-d seconds] <count> <message...>",
         min_args: 2,
         max_args: None,
+        flags: &[],
     },
     CommandInfo {
         name: "exit",
         description: "Exit the program",
-        usage: "exit",
+        usage: "exit [status]",
         min_args: 0,
-        max_args: Some(0),
+        max_args: Some(1),
+        flags: &[],
     },
     CommandInfo {
         name: "cd",
@@ -198,13 +554,39 @@ pub const COMMANDS: &[CommandInfo] = &[
         usage: "cd <directory>",
         min_args: 0,
         max_args: Some(1),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "pushd",
+        description: "Change directory and push the previous one onto the stack",
+        usage: "pushd [directory]",
+        min_args: 0,
+        max_args: Some(1),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "popd",
+        description: "Return to the directory on top of the stack",
+        usage: "popd",
+        min_args: 0,
+        max_args: Some(0),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "dirs",
+        description: "Show the directory stack",
+        usage: "dirs [-v]",
+        min_args: 0,
+        max_args: Some(1),
+        flags: &[],
     },
     CommandInfo {
         name: "quit",
         description: "Exit the program",
-        usage: "quit",
+        usage: "quit [status]",
         min_args: 0,
-        max_args: Some(0),
+        max_args: Some(1),
+        flags: &[],
     },
     CommandInfo {
         name: "pwd",
@@ -212,62 +594,141 @@ pub const COMMANDS: &[CommandInfo] = &[
         usage: "pwd",
         min_args: 0,
         max_args: Some(0),
+        flags: &[],
     },
     CommandInfo {
         name: "rm",
         description: "Remove files",
-        usage: "rm <file>",
+        usage: "rm [-r] [-f] [--no-preserve-root] <path>",
         min_args: 1,
-        max_args: Some(2),
+        max_args: Some(1),
+        flags: &["-r", "-f", "--no-preserve-root"],
     },
     CommandInfo {
         name: "cp",
         description: "Copy files",
-        usage: "cp <source> <destination>",
+        usage: "cp [-r] [-i] [-v] <source> <destination>",
         min_args: 2,
-        max_args: Some(3),
+        max_args: Some(2),
+        flags: &["-r", "-i", "-v"],
     },
     CommandInfo {
         name: "mv",
         description: "Move/rename files or directories",
-        usage: "mv <source> <destination>",
+        usage: "mv [-i] [-v] <source> <destination>",
+        min_args: 2,
+        max_args: Some(2),
+        flags: &["-i", "-v"],
+    },
+    CommandInfo {
+        name: "rename",
+        description: "Rename every file matching a wildcard pattern",
+        usage: "rename [-n] <pattern> <replacement>",
         min_args: 2,
         max_args: Some(2),
+        flags: &["-n"],
     },
     CommandInfo {
         name: "mkdir",
         description: "Make directories",
-        usage: "mkdir <directory>",
+        usage: "mkdir [-p] [-m mode] <directory...>",
         min_args: 1,
-        max_args: Some(2),
+        max_args: None,
+        flags: &["-p", "-m MODE"],
     },
     CommandInfo {
         name: "grep",
         description: "Search for pattern in files",
-        usage: "grep <pattern> <file...>",
+        usage: "grep [-c] [--stats] <pattern> [file...]",
         min_args: 1,
         max_args: None, // 複数ファイル対応
+        flags: &["-c", "--stats"],
     },
     CommandInfo {
         name: "alias",
         description: "Set or show command aliases",
         usage: "alias [name=command]",
         min_args: 0,
-        max_args: Some(1),
+        max_args: None,
+        flags: &[],
     },
     CommandInfo {
         name: "find",
-        description: "Find files by name",
-        usage: "find [directory] <filename>",
+        description: "Find files by name, age and size",
+        usage: "find [directory] <filename> [-mtime [+-]N] [-size [+-]N[k|M|G]] [-newer <file>] [--no-ignore] [-print0]",
         min_args: 1,
         max_args: Some(2),
+        flags: &[
+            "-mtime N",
+            "-size N",
+            "-newer FILE",
+            "--no-ignore",
+            "-print0",
+        ],
+    },
+    CommandInfo {
+        name: "du",
+        description: "Show disk usage of directories",
+        usage: "du [-h] [-s] [--no-ignore] [path...]",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "cut",
+        description: "Select fields or characters from each line",
+        usage: "cut (-f list [-d delim] [-s] | -c list) [file...]",
+        min_args: 1,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "tr",
+        description: "Translate, delete or squeeze characters from piped input",
+        usage: "tr [-d] [-s] <set1> [set2]",
+        min_args: 1,
+        max_args: Some(4),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "diff",
+        description: "Compare two files line by line",
+        usage: "diff [-q] <file1> <file2>",
+        min_args: 2,
+        max_args: Some(3),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "tree",
+        description: "Show directory structure as a tree",
+        usage: "tree [path] [-L depth] [-a] [--no-ignore]",
+        min_args: 0,
+        max_args: Some(5),
+        flags: &[],
     },
     CommandInfo {
         name: "sleep",
-        description: "Sleep for specified seconds",
-        usage: "sleep <seconds>",
+        description: "Sleep for specified seconds (fractions allowed)",
+        usage: "sleep <seconds[.fraction]>",
         min_args: 1,
         max_args: Some(1),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "time",
+        description: "Run a command and report its elapsed time on stderr",
+        usage: "time <command>",
+        min_args: 1,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "eval",
+        description: "Run the arguments as a command in the current shell",
+        usage: "eval [args...]",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
     },
     CommandInfo {
         name: "version",
@@ -275,288 +736,1189 @@ pub const COMMANDS: &[CommandInfo] = &[
         usage: "version",
         min_args: 0,
         max_args: Some(0),
+        flags: &[],
     },
     CommandInfo {
         name: "jobs",
         description: "List background jobs",
-        usage: "jobs",
+        usage: "jobs [--json | -o json]",
         min_args: 0,
-        max_args: Some(0),
+        max_args: Some(2),
+        flags: &[],
     },
     CommandInfo {
         name: "fg",
         description: "Show job status",
-        usage: "fg [job_id]",
+        usage: "fg [job_id | %job]",
         min_args: 0,
         max_args: Some(1),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "bg",
+        description: "Resume a stopped job in the background",
+        usage: "bg [job_id | %job]",
+        min_args: 0,
+        max_args: Some(1),
+        flags: &[],
     },
     CommandInfo {
         name: "env",
         description: "Show or set environment variables",
-        usage: "env [VAR[=value]]",
+        usage: "env [--json | -o json] [--session] [PATTERN | VAR[=value]] | env [-i] [VAR=value...] [command]",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "export",
+        description: "Export variables to the environment of external commands",
+        usage: "export NAME[=value]...",
+        min_args: 1,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "let",
+        description: "Evaluate arithmetic expressions",
+        usage: "let <expression...>",
+        min_args: 1,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "read",
+        description: "Read a line of input into variables",
+        usage: "read [-p prompt] [name...]",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "seq",
+        description: "Print a sequence of numbers",
+        usage: "seq [-w] [first [step]] last",
+        min_args: 1,
+        max_args: Some(4),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "xargs",
+        description: "Build and run commands from piped input",
+        usage: "xargs [-n N] [-I replace] [-e] [command [initial-args...]]",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "type",
+        description: "Show how each name would be resolved",
+        usage: "type <name...>",
+        min_args: 1,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "which",
+        description: "Locate external commands in PATH",
+        usage: "which <name...>",
+        min_args: 1,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "umask",
+        description: "Show or set the file creation mask",
+        usage: "umask [mode]",
         min_args: 0,
         max_args: Some(1),
+        flags: &[],
+    },
+    CommandInfo {
+        name: "trap",
+        description: "Run a command when the shell exits or receives a signal",
+        usage: "trap [command signal... | - signal...]",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "exec",
+        description: "Replace the shell with a command, or redirect the shell's output",
+        usage: "exec <command> [args...] | exec > <file>",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "set",
+        description: "Set or show shell options",
+        usage: "set [-o | +o option] [-e | +e] [-C | +C] [-n | +n] [-v | +v]",
+        min_args: 0,
+        max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "fc",
+        description: "List, edit or re-run previous commands",
+        usage: "fc -l | fc -s [old=new] [command] | fc [command]",
+        min_args: 0,
+        max_args: Some(3),
+        flags: &[],
     },
     CommandInfo {
         name: "history",
         description: "Show command history or search",
-        usage: "history [search <query>]",
+        usage: "history [--json | -o json] [count | -n count | exec [-y] <number> | clear | delete <number> | search [-r] [--all] <query>]",
         min_args: 0,
         max_args: None,
+        flags: &[],
+    },
+    CommandInfo {
+        name: "paste",
+        description: "Read lines up to a lone '.', show them and run them after confirmation",
+        usage: "paste",
+        min_args: 0,
+        max_args: Some(0),
+        flags: &[],
     },
 ];
 
 impl Command {
-    /// コマンド内の全ての変数を展開
-    pub fn expand_variables(self) -> Self {
+    /// 実行するとファイルを削除・上書きするコマンドか
+    ///
+    /// rm・mv と、ファイルを切り詰めるリダイレクト（`>` など）を破壊的とみなす。
+    /// パイプラインや複数のコマンドは、どれか一つが破壊的なら破壊的とする
+    pub fn is_destructive(&self) -> bool {
         match self {
-            Command::Echo { message } => Command::Echo {
-                message: expand_variables(&message),
+            Command::Rm { .. } | Command::Mv { .. } | Command::Rename { .. } => true,
+            Command::Redirect { command, redirects } => {
+                redirects.iter().any(Redirection::truncates) || command.is_destructive()
+            }
+            Command::Pipeline { commands } | Command::Compound { commands } => {
+                commands.iter().any(Command::is_destructive)
+            }
+            Command::Background { command, .. }
+            | Command::Not { command }
+            | Command::Time { command }
+            | Command::Subshell { command }
+            | Command::Environment {
+                action: EnvironmentAction::Run { command, .. },
+                ..
+            } => command.is_destructive(),
+            _ => false,
+        }
+    }
+
+    /// コマンド内の全ての変数を展開
+    ///
+    /// 各文字列に対して算術展開 `$((...))` を行った後に変数展開を行う。
+    /// 制御構文の中のコマンドでは、その前にコマンド置換も行う
+    ///
+    /// # Errors
+    /// - 算術式が不正な場合
+    pub fn expand_variables(self) -> Result<Self> {
+        let command = match self {
+            Command::Echo { message, newline } => Command::Echo {
+                message: expand_word(&message)?,
+                newline,
             },
-            Command::Cat { filename } => Command::Cat {
-                filename: expand_variables(&filename),
+            Command::Cat {
+                filename,
+                number,
+                show_nonprinting,
+                show_ends,
+                show_tabs,
+            } => Command::Cat {
+                filename: expand_word(&filename)?,
+                number,
+                show_nonprinting,
+                show_ends,
+                show_tabs,
             },
             Command::Write { filename, content } => Command::Write {
-                filename: expand_variables(&filename),
-                content: expand_variables(&content),
+                filename: expand_word(&filename)?,
+                content: expand_word(&content)?,
             },
             Command::Cd { path } => Command::Cd {
-                path: expand_variables(&path),
+                path: expand_word(&path)?,
             },
-            Command::Mkdir { path, parents } => Command::Mkdir {
-                path: expand_variables(&path),
+            Command::Mkdir {
+                paths,
+                parents,
+                mode,
+            } => Command::Mkdir {
+                paths: paths
+                    .iter()
+                    .map(|path| expand_word(path))
+                    .collect::<Result<Vec<_>>>()?,
                 parents,
+                mode,
             },
             Command::Rm {
                 path,
                 recursive,
                 force,
+                preserve_root,
             } => Command::Rm {
-                path: expand_variables(&path),
+                path: expand_word(&path)?,
                 recursive,
                 force,
+                preserve_root,
             },
             Command::Cp {
                 source,
                 destination,
                 recursive,
+                interactive,
+                verbose,
             } => Command::Cp {
-                source: expand_variables(&source),
-                destination: expand_variables(&destination),
+                source: expand_word(&source)?,
+                destination: expand_word(&destination)?,
                 recursive,
+                interactive,
+                verbose,
             },
             Command::Mv {
                 source,
                 destination,
+                interactive,
+                verbose,
             } => Command::Mv {
-                source: expand_variables(&source),
-                destination: expand_variables(&destination),
+                source: expand_word(&source)?,
+                destination: expand_word(&destination)?,
+                interactive,
+                verbose,
+            },
+            Command::Rename {
+                pattern,
+                replacement,
+                dry_run,
+            } => Command::Rename {
+                pattern: expand_word(&pattern)?,
+                replacement: expand_word(&replacement)?,
+                dry_run,
             },
-            Command::Find { path, name } => Command::Find {
-                path: path.map(|p| expand_variables(&p)),
-                name: expand_variables(&name),
+            Command::Find {
+                path,
+                name,
+                filters,
+                no_ignore,
+                print0,
+            } => Command::Find {
+                path: path.map(|p| expand_word(&p)).transpose()?,
+                name: expand_word(&name)?,
+                filters: filters
+                    .into_iter()
+                    .map(|filter| match filter {
+                        FindFilter::Newer(reference) => {
+                            Ok(FindFilter::Newer(expand_word(&reference)?))
+                        }
+                        filter => Ok(filter),
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+                no_ignore,
+                print0,
+            },
+            Command::Grep {
+                pattern,
+                files,
+                count,
+                stats,
+            } => Command::Grep {
+                pattern: expand_word(&pattern)?,
+                files: files
+                    .iter()
+                    .map(|f| expand_word(f))
+                    .collect::<Result<Vec<_>>>()?,
+                count,
+                stats,
             },
-            Command::Grep { pattern, files } => Command::Grep {
-                pattern: expand_variables(&pattern),
-                files: files.into_iter().map(|f| expand_variables(&f)).collect(),
+            Command::Du {
+                paths,
+                human_readable,
+                summarize,
+                no_ignore,
+            } => Command::Du {
+                paths: paths
+                    .iter()
+                    .map(|p| expand_word(p))
+                    .collect::<Result<Vec<_>>>()?,
+                human_readable,
+                summarize,
+                no_ignore,
+            },
+            Command::Cut {
+                selection,
+                delimiter,
+                only_delimited,
+                files,
+            } => Command::Cut {
+                selection,
+                delimiter,
+                only_delimited,
+                files: files
+                    .iter()
+                    .map(|f| expand_word(f))
+                    .collect::<Result<Vec<_>>>()?,
+            },
+            Command::Tr {
+                set1,
+                set2,
+                delete,
+                squeeze,
+            } => Command::Tr {
+                set1: expand_word(&set1)?,
+                set2: set2.map(|set| expand_word(&set)).transpose()?,
+                delete,
+                squeeze,
+            },
+            Command::Diff {
+                file1,
+                file2,
+                brief,
+            } => Command::Diff {
+                file1: expand_word(&file1)?,
+                file2: expand_word(&file2)?,
+                brief,
+            },
+            Command::Tree {
+                path,
+                max_depth,
+                show_hidden,
+                no_ignore,
+            } => Command::Tree {
+                path: path.map(|p| expand_word(&p)).transpose()?,
+                max_depth,
+                show_hidden,
+                no_ignore,
             },
             Command::Alias { name, command } => Command::Alias {
-                name: name.map(|n| expand_variables(&n)),
-                command: command.map(|c| expand_variables(&c)),
+                name: name.map(|n| expand_word(&n)).transpose()?,
+                command: command.map(|c| expand_word(&c)).transpose()?,
             },
-            Command::Repeat { count, message } => Command::Repeat {
+            Command::Repeat {
+                count,
+                message,
+                delay,
+            } => Command::Repeat {
                 count,
-                message: expand_variables(&message),
+                message: expand_word(&message)?,
+                delay,
             },
             Command::FunctionCall { name, args } => Command::FunctionCall {
                 name,
-                args: args.into_iter().map(|arg| expand_variables(&arg)).collect(),
+                args: expand_words(&args)?,
             },
-            Command::Compound { commands } => Command::Compound {
-                commands: commands
+            Command::Seq { args, equal_width } => Command::Seq {
+                args: args
+                    .iter()
+                    .map(|arg| expand_word(arg))
+                    .collect::<Result<Vec<_>>>()?,
+                equal_width,
+            },
+            Command::Let { expressions } => Command::Let {
+                expressions: expressions
+                    .iter()
+                    .map(|e| expand_word(e))
+                    .collect::<Result<Vec<_>>>()?,
+            },
+            // 内側のコマンドの引数も代入前に展開する（`X=1 echo $X` は代入前の値）
+            Command::Assignment {
+                assignments,
+                command,
+            } => Command::Assignment {
+                assignments: assignments
+                    .iter()
+                    .map(|(name, value)| Ok((name.clone(), expand_assignment_value(value)?)))
+                    .collect::<Result<Vec<_>>>()?,
+                command: command
+                    .map(|c| c.expand_variables().map(Box::new))
+                    .transpose()?,
+            },
+            Command::Environment {
+                action: EnvironmentAction::Set(name, value),
+                format,
+            } => Command::Environment {
+                action: EnvironmentAction::Set(name, expand_word(&value)?),
+                format,
+            },
+            // 上書きする値だけを展開し、コマンドは上書きした変数で実行時に展開する
+            Command::Environment {
+                action:
+                    EnvironmentAction::Run {
+                        isolated,
+                        overrides,
+                        command,
+                    },
+                format,
+            } => Command::Environment {
+                action: EnvironmentAction::Run {
+                    isolated,
+                    overrides: overrides
+                        .iter()
+                        .map(|(name, value)| Ok((name.clone(), expand_assignment_value(value)?)))
+                        .collect::<Result<Vec<_>>>()?,
+                    command,
+                },
+                format,
+            },
+            Command::Export { variables } => Command::Export {
+                variables: variables
                     .into_iter()
-                    .map(|cmd| cmd.expand_variables())
-                    .collect(),
+                    .map(|(name, value)| {
+                        Ok((
+                            name,
+                            value.as_deref().map(expand_assignment_value).transpose()?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
             },
 
             // 複合コマンドはそのまま（実行時に再度展開される）
+            // 複数のコマンドは、前のコマンドの代入を反映するため一つずつ実行時に展開する
+            Command::Compound { .. } => self,
             Command::If { .. } => self,
             Command::While { .. } => self,
             Command::For { .. } => self,
+            Command::ArithmeticFor { .. } => self,
+            // パイプラインの各段は実行するときに一度だけ展開する
             Command::Pipeline { .. } => self,
+            // 解決してから、解決したコマンドとして展開する
+            Command::Unresolved { .. } => self,
+            // リダイレクト先はexecute_redirectで、対象のコマンドは実行時に展開する
             Command::Redirect { .. } => self,
-            Command::Background { .. } => self,
+            // バックグラウンドのコマンドは起動した時点の値で展開する
+            Command::Background {
+                command,
+                text,
+                stopped,
+            } => Command::Background {
+                command: Box::new(command.expand_variables()?),
+                text,
+                stopped,
+            },
+            Command::Not { .. } => self,
+            Command::Time { .. } => self,
+            Command::Eval { args } => Command::Eval {
+                args: args
+                    .iter()
+                    .map(|arg| expand_word(arg))
+                    .collect::<Result<_>>()?,
+            },
+            Command::Subshell { .. } => self,
+            Command::Xargs { .. } => self,
             Command::Function { .. } => self,
             Command::History { .. } => self,
 
             // 変数を含まないコマンド
             Command::Help => self,
+            Command::Clear => self,
             Command::Version => self,
             Command::Pwd => self,
-            Command::Ls => self,
-            Command::Jobs => self,
-            Command::Exit => self,
+            Command::DirStack {
+                action: DirStackAction::Push(Some(dir)),
+            } => Command::DirStack {
+                action: DirStackAction::Push(Some(expand_word(&dir)?)),
+            },
+            Command::DirStack { .. } => self,
+            Command::Ls { .. } => self,
+            Command::Jobs { .. } => self,
+            Command::Exit { .. } => self,
+            Command::Paste => self,
             Command::Sleep { .. } => self,
+            Command::Read { .. } => self,
+            Command::Type { .. } => self,
+            Command::Umask { .. } => self,
+            // trapのコマンドは実行するときに展開する
+            Command::Trap { .. } => self,
+            Command::Exec { args, redirects } => Command::Exec {
+                args: args
+                    .iter()
+                    .map(|arg| expand_word(arg))
+                    .collect::<Result<_>>()?,
+                redirects: redirects
+                    .into_iter()
+                    .map(|redirect| {
+                        Ok(Redirection {
+                            target: expand_word(&redirect.target)?,
+                            ..redirect
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            },
+            Command::Set { .. } => self,
             Command::Fg { .. } => self,
+            Command::Bg { .. } => self,
             Command::Environment { .. } => self,
+        };
+
+        Ok(command)
+    }
+}
+
+/// forの項目や関数の引数を実行時に展開する
+///
+/// 展開結果（`expand_word`）を空白で分割し、ワイルドカードを含む語は
+/// マッチするファイル名に展開する。展開結果が空なら項目はない。
+/// クォートを含む項目は分割もファイル名の展開もせず、クォートを取り除いた一つの項目にする。
+///
+/// # Errors
+/// - コマンド置換の構文が不正な場合や、算術式が不正な場合
+fn expand_words(items: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for item in items {
+        let value = expand_word(item)?;
+        if is_quoted_word(item) {
+            expanded.push(split_quoted_words(&value).concat());
+        } else {
+            expanded.extend(value.split_whitespace().flat_map(expand_glob));
         }
     }
+    Ok(expanded)
+}
+
+/// 制御構文（if・while・for・複数コマンド）の中で実行したコマンドの出力をためるバッファ
+///
+/// 出力はその場で表示せず、制御構文の実行結果としてまとめて返す。
+/// 表示はトップレベルの `execute_command` が行うので、コマンド置換やパイプラインでも受け取れる。
+#[derive(Default)]
+struct OutputBuffer {
+    outputs: Vec<Vec<u8>>,
+}
+
+impl OutputBuffer {
+    /// コマンドを実行して出力をためる（条件の判定に使う）
+    ///
+    /// # Returns
+    /// * `Ok(true)` - exitが実行された場合
+    ///
+    /// # Errors
+    /// - コマンドが失敗した場合（出力付きで失敗した場合も出力はためる）
+    fn run(&mut self, command: Command, input: Option<&[u8]>) -> Result<bool> {
+        match execute_command_internal(command, input) {
+            Ok(CommandResult::Continue(output)) => {
+                self.push(output);
+                Ok(false)
+            }
+            Ok(CommandResult::Exit(output)) => {
+                self.push(output);
+                Ok(true)
+            }
+            Err(RucliError::CommandFailed { output, status }) => {
+                self.push(output);
+                Err(RucliError::CommandFailed {
+                    output: Vec::new(),
+                    status,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 本体のコマンドを実行して出力をためる
+    ///
+    /// # Errors
+    /// - コマンドが失敗した場合（それまでにためた出力を引き継ぐ）
+    fn run_body(&mut self, command: Command, input: Option<&[u8]>) -> Result<bool> {
+        self.run(command, input).map_err(|e| self.fail(e))
+    }
+
+    fn push(&mut self, output: Vec<u8>) {
+        if !output.is_empty() {
+            self.outputs.push(output);
+        }
+    }
+
+    /// エラーにためた出力を引き継ぐ
+    ///
+    /// 出力を持てないエラーの場合は、失わないようにためた出力を先に書き出す
+    fn fail(&mut self, error: RucliError) -> RucliError {
+        let output = std::mem::take(&mut self.outputs).concat();
+        match error {
+            RucliError::CommandFailed { status, .. } => {
+                RucliError::CommandFailed { output, status }
+            }
+            error => {
+                crate::output::write_stdout(&output);
+                error
+            }
+        }
+    }
+
+    /// exitが実行された場合の結果（ためた出力は外側へ引き継ぐ）
+    fn exit(self) -> Result<CommandResult> {
+        Ok(CommandResult::Exit(self.outputs.concat()))
+    }
+
+    /// ためた出力を実行結果として返す
+    fn finish(self) -> Result<CommandResult> {
+        self.finish_with_status(0)
+    }
+
+    /// ためた出力を、指定したステータスの実行結果として返す
+    ///
+    /// # Errors
+    /// - ステータスが0以外の場合（出力付きの失敗として返す）
+    fn finish_with_status(self, status: i32) -> Result<CommandResult> {
+        let output = self.outputs.concat();
+        match status {
+            0 => Ok(CommandResult::Continue(output)),
+            status => Err(RucliError::CommandFailed { output, status }),
+        }
+    }
+}
+
+/// RUCLI_MAX_ITERATIONS からwhileループの最大反復回数を取得
+///
+/// 未設定・不正な値の場合は制限しない
+fn configured_max_iterations() -> Option<usize> {
+    crate::environment::get_var("RUCLI_MAX_ITERATIONS").and_then(|max| max.trim().parse().ok())
 }
 
 /// コマンドの実行
 ///
+/// 結果の出力を表示する。端末に直接表示するlsの一覧は端末の幅に合わせて列に並べる。
+/// 端末に直接表示する長い出力は `pager` でページ送り・切り詰めする（パイプラインの出力は除く）
+///
 /// # Returns
 /// * `Ok(true)` - プログラムを終了すべき場合
 /// * `Ok(false)` - 実行を継続する場合
 /// * `Err(...)` - エラーが発生した場合
-pub fn execute_command(command: Command, input: Option<&str>) -> Result<bool> {
-    match execute_command_internal(command, input)? {
+pub fn execute_command(command: Command, input: Option<&[u8]>) -> Result<bool> {
+    // 端末に直接表示するlsは、端末の幅に合わせて列に並べる
+    let columns = match command {
+        Command::Ls {
+            format: OutputFormat::Text,
+            recursive: false,
+            ..
+        } => crate::output::terminal_width(),
+        _ => None,
+    };
+    // `| cat` で全体を表示できるよう、パイプラインの出力はページ送りしない
+    let paged = !matches!(command, Command::Pipeline { .. } | Command::Redirect { .. });
+
+    let result = match execute_command_internal(command, input) {
+        // 失敗したコマンドの出力も通常の出力として表示する
+        Err(RucliError::CommandFailed { output, status }) => {
+            crate::output::write_stdout(&output);
+            return Err(RucliError::CommandFailed {
+                output: Vec::new(),
+                status,
+            });
+        }
+        result => result?,
+    };
+
+    match result {
         CommandResult::Continue(output) => {
-            if !output.is_empty() {
-                println!("{output}");
+            let output = match columns {
+                Some(width) => as_lines(
+                    crate::output::format_columns(
+                        &String::from_utf8_lossy(&output).lines().collect::<Vec<_>>(),
+                        width,
+                    )
+                    .into_bytes(),
+                ),
+                None => output,
+            };
+            if paged {
+                crate::pager::write_paged(&output);
+            } else {
+                crate::output::write_stdout(&output);
             }
             Ok(false)
         }
-        CommandResult::Exit => Ok(true),
+        CommandResult::Exit(output) => {
+            crate::output::write_stdout(&output);
+            crate::output::print_line(&handle_exit());
+            Ok(true)
+        }
+    }
+}
+
+/// コマンドを実行し、実行中に逐次書き出された標準出力も結果の出力に含める
+///
+/// リダイレクト・パイプライン・コマンド置換のように出力を受け取る側で使う。
+/// 標準エラーへの出力はそのまま書き出す。
+pub fn execute_command_collecting(command: Command, input: Option<&[u8]>) -> Result<CommandResult> {
+    let (result, captured) = crate::output::capture(|| execute_command_internal(command, input));
+    crate::output::write_stderr(&captured.stderr);
+
+    if captured.stdout.is_empty() {
+        return result;
+    }
+
+    // 逐次出力も改行で終わっているので、戻り値の出力の前にそのままつなぐ
+    let join = |output: Vec<u8>| [captured.stdout.as_slice(), &output].concat();
+
+    match result {
+        Ok(CommandResult::Continue(output)) => Ok(CommandResult::Continue(join(output))),
+        Ok(CommandResult::Exit(output)) => Ok(CommandResult::Exit(join(output))),
+        Err(RucliError::CommandFailed { output, status }) => Err(RucliError::CommandFailed {
+            output: join(output),
+            status,
+        }),
+        // 出力を渡せない場合は失わないようにそのまま書き出す
+        result => {
+            crate::output::write_stdout(&captured.stdout);
+            result
+        }
     }
 }
 
+/// ドライラン（`set -n`）で実行せずに表示するコマンドライン
+///
+/// ファイルシステムの変更・ジョブの起動・スリープ・外部コマンドの実行といった
+/// 副作用のあるコマンドが対象。入力のリダイレクト（`<`）だけのコマンドはそのまま実行する
+///
+/// # Returns
+/// * 展開後のコマンドライン（副作用のないコマンドならNone）
+///
+/// # Errors
+/// * リダイレクト先の展開に失敗した場合
+fn dry_run_line(command: &Command) -> Result<Option<String>> {
+    let line = match command {
+        Command::Redirect { command, redirects } => {
+            if redirects
+                .iter()
+                .all(|redirect| redirect.redirect_type == "<" || redirect.target.is_empty())
+            {
+                return Ok(None);
+            }
+
+            // 対象のコマンドとリダイレクト先は実行時に展開されるので、ここで展開して表示する
+            let redirects = redirects
+                .iter()
+                .map(|redirect| {
+                    Ok(Redirection {
+                        redirect_type: redirect.redirect_type.clone(),
+                        target: expand_word(&redirect.target)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Command::Redirect {
+                command: Box::new(command.clone().expand_variables()?),
+                redirects,
+            }
+            .to_string()
+        }
+        Command::Background { .. }
+        | Command::Write { .. }
+        | Command::Rm { .. }
+        | Command::Cp { .. }
+        | Command::Mv { .. }
+        | Command::Rename { .. }
+        | Command::Mkdir { .. }
+        | Command::Sleep { .. }
+        | Command::Exec { .. } => command.to_string(),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(line))
+}
+
 /// execute_commandの内部処理
-pub fn execute_command_internal(command: Command, input: Option<&str>) -> Result<CommandResult> {
+///
+/// 変数を展開し、登録されているフックを呼びながらコマンドを実行する
+pub fn execute_command_internal(command: Command, input: Option<&[u8]>) -> Result<CommandResult> {
     // コマンド実行開始を記録
     debug!("Executing command: {command:?}");
 
-    let command = command.expand_variables();
+    let command = command.expand_variables()?;
+    execute_expanded(command, input)
+}
+
+/// 展開済みのコマンドを、登録されているフックを呼びながら実行する
+pub(crate) fn execute_expanded(command: Command, input: Option<&[u8]>) -> Result<CommandResult> {
+    // 実行するときに解決するコマンドは、解決したコマンドとして展開してフックを呼ぶ
+    if let Command::Unresolved { text } = command {
+        return execute_command_internal(parse_resolved(&text)?, input);
+    }
+
+    // 実行前のフックはコマンドを拒否できる
+    let hooks = crate::hooks::current();
+    hooks.before(&command)?;
+    if !hooks.has_post() {
+        return dispatch_command(command, input);
+    }
+
+    let executed = command.clone();
+    let start = Instant::now();
+    let result = dispatch_command(command, input);
+    let status = match &result {
+        Ok(CommandResult::Continue(_)) => 0,
+        Ok(CommandResult::Exit(_)) => crate::environment::get_last_status(),
+        Err(e) => e.exit_code(),
+    };
+    hooks.after(&executed, start.elapsed(), status);
+
+    result
+}
+
+/// 出力を改行で終わる形にする（空の出力はそのまま）
+pub(crate) fn as_lines(mut output: Vec<u8>) -> Vec<u8> {
+    if !output.is_empty() && !output.ends_with(b"\n") {
+        output.push(b'\n');
+    }
+    output
+}
+
+/// 中のコマンドの出力をそのまま返すコマンドか
+///
+/// これらの出力の改行は中のコマンドでそろえてあるので、改めて付けない。
+/// オプションなしのcatは、出力をバイト列のまま渡す間（[`PassingBytes`]）は読んだバイト列をそのまま渡す
+fn passes_output_through(command: &Command) -> bool {
+    if let Command::Cat {
+        number: false,
+        show_nonprinting: false,
+        show_ends: false,
+        show_tabs: false,
+        ..
+    } = command
+    {
+        return PASSING_BYTES.with(Cell::get);
+    }
+
+    matches!(
+        command,
+        Command::Echo { newline: false, .. }
+            | Command::Find { print0: true, .. }
+            | Command::Pipeline { .. }
+            | Command::Redirect { .. }
+            | Command::Not { .. }
+            | Command::Time { .. }
+            | Command::Eval { .. }
+            | Command::Subshell { .. }
+            | Command::Fg { .. }
+            | Command::If { .. }
+            | Command::While { .. }
+            | Command::For { .. }
+            | Command::ArithmeticFor { .. }
+            | Command::Xargs { .. }
+            | Command::Assignment { .. }
+            | Command::Environment {
+                action: EnvironmentAction::Run { .. },
+                ..
+            }
+            | Command::FunctionCall { .. }
+            | Command::Compound { .. }
+            | Command::History {
+                action: HistoryAction::Execute { .. }
+                    | HistoryAction::Substitute { .. }
+                    | HistoryAction::Edit(_),
+                ..
+            }
+    )
+}
+
+/// 展開済みのコマンドを実行し、出力を改行で終わる形にそろえる
+///
+/// 出力の改行をそろえるのはここだけで、表示・リダイレクト・パイプライン・コマンド置換は
+/// そろえた出力をそのまま使う。
+fn dispatch_command(command: Command, input: Option<&[u8]>) -> Result<CommandResult> {
+    if passes_output_through(&command) {
+        return dispatch_by_kind(command, input);
+    }
+
+    match dispatch_by_kind(command, input) {
+        Ok(CommandResult::Continue(output)) => Ok(CommandResult::Continue(as_lines(output))),
+        Ok(CommandResult::Exit(output)) => Ok(CommandResult::Exit(as_lines(output))),
+        Err(RucliError::CommandFailed { output, status }) => Err(RucliError::CommandFailed {
+            output: as_lines(output),
+            status,
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// 展開済みのコマンドを種類ごとの処理に振り分ける
+fn dispatch_by_kind(command: Command, input: Option<&[u8]>) -> Result<CommandResult> {
+    // ドライランでは副作用のあるコマンドを実行せず、内容を表示して成功したことにする
+    if crate::options::current().dryrun
+        && let Some(line) = dry_run_line(&command)?
+    {
+        return Ok(CommandResult::Continue(
+            format!("would run: {line}\n").into(),
+        ));
+    }
+
+    // 制御構文の本体はコマンド置換を残してパースしてあるので、実行時の展開で置換する
+    if matches!(
+        command,
+        Command::If { .. }
+            | Command::While { .. }
+            | Command::For { .. }
+            | Command::ArithmeticFor { .. }
+    ) && !substitution_deferred()
+    {
+        return with_substitution_deferred(true, || dispatch_command(command, input));
+    }
 
     match command {
-        Command::Help => Ok(CommandResult::Continue(handle_help())),
-        Command::Cat { filename } => Ok(CommandResult::Continue(handle_cat(&filename, input)?)),
-        Command::Echo { message } => Ok(CommandResult::Continue(handle_echo(&message))),
-        Command::Write { filename, content } => {
-            handle_write(&filename, &content)?;
-            Ok(CommandResult::Continue(String::new()))
-        }
-        Command::Repeat { count, message } => {
-            Ok(CommandResult::Continue(handle_repeat(count, &message)))
+        Command::Help => Ok(CommandResult::Continue(handle_help().into())),
+        Command::Clear => {
+            // 改行を付けずに書き出し、カーソルを左上に置いたままにする
+            crate::output::write_stdout(handle_clear());
+            Ok(CommandResult::Continue(Vec::new()))
         }
-        Command::Ls => Ok(CommandResult::Continue(handle_ls()?)),
+        Command::Cat {
+            filename,
+            number,
+            show_nonprinting,
+            show_ends,
+            show_tabs,
+        } => Ok(CommandResult::Continue(handle_cat(
+            &filename,
+            number,
+            show_nonprinting,
+            show_ends,
+            show_tabs,
+            input,
+        )?)),
+        Command::Echo { message, newline } => Ok(CommandResult::Continue(
+            handle_echo(&message, newline).into(),
+        )),
+        Command::Write { filename, content } => Ok(CommandResult::Continue(
+            handle_write(&filename, &content)?.into(),
+        )),
+        Command::Repeat {
+            count,
+            message,
+            delay,
+        } => Ok(CommandResult::Continue(
+            handle_repeat(count, &message, delay)?.into(),
+        )),
+        Command::Ls {
+            format,
+            recursive,
+            sort,
+            reverse,
+        } => Ok(CommandResult::Continue(
+            handle_ls(format, recursive, sort, reverse)?.into(),
+        )),
         Command::Cd { path } => {
             handle_cd(&path)?;
-            Ok(CommandResult::Continue(String::new()))
+            Ok(CommandResult::Continue(Vec::new()))
+        }
+        Command::Pwd => Ok(CommandResult::Continue(handle_pwd()?.into())),
+        Command::DirStack { action } => {
+            Ok(CommandResult::Continue(handle_dir_stack(action)?.into()))
         }
-        Command::Pwd => Ok(CommandResult::Continue(handle_pwd()?)),
-        Command::Mkdir { path, parents } => {
-            handle_mkdir(&path, parents)?;
-            Ok(CommandResult::Continue(String::new()))
+        Command::Mkdir {
+            paths,
+            parents,
+            mode,
+        } => {
+            handle_mkdir(&paths, parents, mode)?;
+            Ok(CommandResult::Continue(Vec::new()))
         }
         Command::Rm {
             path,
             recursive,
             force,
+            preserve_root,
         } => {
-            handle_rm(&path, recursive, force)?;
-            Ok(CommandResult::Continue(String::new()))
+            handle_rm(&path, recursive, force, preserve_root)?;
+            Ok(CommandResult::Continue(Vec::new()))
         }
         Command::Cp {
             source,
             destination,
             recursive,
+            interactive,
+            verbose,
         } => {
-            handle_cp(&source, &destination, recursive)?;
-            Ok(CommandResult::Continue(String::new()))
+            if interactive {
+                ensure_can_confirm("cp", input)?;
+            }
+            Ok(CommandResult::Continue(
+                handle_cp(&source, &destination, recursive, interactive, verbose)?.into(),
+            ))
         }
         Command::Mv {
             source,
             destination,
+            interactive,
+            verbose,
         } => {
-            handle_mv(&source, &destination)?;
-            Ok(CommandResult::Continue(String::new()))
-        }
-        Command::Find { path, name } => Ok(CommandResult::Continue(handle_find(
-            path.as_deref(),
-            &name,
-        )?)),
-        Command::Grep { pattern, files } => Ok(CommandResult::Continue(handle_grep(
-            &pattern, &files, input,
-        )?)),
-        Command::Alias { name, command } => {
-            handle_alias(name.as_deref(), command.as_deref())?;
-            Ok(CommandResult::Continue(String::new()))
-        }
-        Command::Version => Ok(CommandResult::Continue(handle_version())),
-        Command::Pipeline { commands } => {
-            let pipeline = PipelineCommand::new(commands);
-            Ok(CommandResult::Continue(PipelineExecutor::execute(
-                &pipeline,
-            )?))
+            if interactive {
+                ensure_can_confirm("mv", input)?;
+            }
+            Ok(CommandResult::Continue(
+                handle_mv(&source, &destination, interactive, verbose)?.into(),
+            ))
         }
-        Command::Redirect {
+        Command::Rename {
+            pattern,
+            replacement,
+            dry_run,
+        } => Ok(CommandResult::Continue(
+            handle_rename(&pattern, &replacement, dry_run)?.into(),
+        )),
+        Command::Find {
+            path,
+            name,
+            filters,
+            no_ignore,
+            print0,
+        } => Ok(CommandResult::Continue(
+            handle_find(path.as_deref(), &name, &filters, no_ignore, print0)?.into(),
+        )),
+        Command::Grep {
+            pattern,
+            files,
+            count,
+            stats,
+        } => Ok(CommandResult::Continue(
+            handle_grep(&pattern, &files, count, stats, input)?.into(),
+        )),
+        Command::Du {
+            paths,
+            human_readable,
+            summarize,
+            no_ignore,
+        } => Ok(CommandResult::Continue(
+            handle_du(&paths, human_readable, summarize, no_ignore)?.into(),
+        )),
+        Command::Cut {
+            selection,
+            delimiter,
+            only_delimited,
+            files,
+        } => Ok(CommandResult::Continue(
+            handle_cut(&selection, delimiter, only_delimited, &files, input)?.into(),
+        )),
+        Command::Tr {
+            set1,
+            set2,
+            delete,
+            squeeze,
+        } => Ok(CommandResult::Continue(
+            handle_tr(&set1, set2.as_deref(), delete, squeeze, input)?.into(),
+        )),
+        Command::Diff {
+            file1,
+            file2,
+            brief,
+        } => Ok(CommandResult::Continue(
+            handle_diff(&file1, &file2, brief)?.into(),
+        )),
+        Command::Tree {
+            path,
+            max_depth,
+            show_hidden,
+            no_ignore,
+        } => Ok(CommandResult::Continue(
+            handle_tree(path.as_deref(), max_depth, show_hidden, no_ignore)?.into(),
+        )),
+        Command::Alias { name, command } => Ok(CommandResult::Continue(
+            handle_alias(name.as_deref(), command.as_deref())?.into(),
+        )),
+        Command::Version => Ok(CommandResult::Continue(handle_version().into())),
+        Command::Pipeline { commands } => PipelineExecutor::execute(PipelineCommand::new(commands)),
+        Command::Redirect { command, redirects } => execute_redirect(*command, &redirects),
+        Command::Background {
             command,
-            redirect_type,
-            target,
-        } => Ok(CommandResult::Continue(execute_redirect(
-            *command,
-            &redirect_type,
-            &target,
-        )?)),
-        Command::Background { command } => Ok(CommandResult::Continue(
-            handle_background_execution(command)?,
+            text,
+            stopped,
+        } => Ok(CommandResult::Continue(
+            handle_background_execution(command, text, stopped)?.into(),
         )),
-        Command::Sleep { seconds } => {
-            handle_sleep(seconds)?;
-            Ok(CommandResult::Continue(String::new()))
+        Command::Not { command } => match execute_command_internal(*command, input) {
+            // 出力はそのままに、成功と失敗だけを入れ替える
+            Ok(CommandResult::Continue(output)) => {
+                Err(RucliError::CommandFailed { output, status: 1 })
+            }
+            Ok(CommandResult::Exit(output)) => Ok(CommandResult::Exit(output)),
+            Err(RucliError::CommandFailed { output, .. }) => Ok(CommandResult::Continue(output)),
+            Err(RucliError::Interrupted) => Err(RucliError::Interrupted),
+            Err(e) => {
+                // エラーメッセージは表示するが、ステータスは成功にする
+                crate::output::eprint_line(&e.to_string());
+                Ok(CommandResult::Continue(Vec::new()))
+            }
+        },
+        Command::Time { command } => handle_time(*command, input),
+        Command::Eval { args } => handle_eval(&args, input),
+        Command::Subshell { command } => {
+            Ok(CommandResult::Continue(handle_subshell(*command, input)?))
         }
-        Command::Jobs => Ok(CommandResult::Continue(handle_jobs()?)),
-        Command::Fg { job_id } => {
-            handle_fg(job_id)?;
-            Ok(CommandResult::Continue(String::new()))
+        Command::Sleep { duration } => {
+            handle_sleep(duration)?;
+            Ok(CommandResult::Continue(Vec::new()))
+        }
+        Command::Jobs { format } => Ok(CommandResult::Continue(handle_jobs(format)?.into())),
+        Command::Fg { job } => Ok(CommandResult::Continue(handle_fg(job)?.into())),
+        Command::Bg { job } => Ok(CommandResult::Continue(handle_bg(job)?.into())),
+        Command::Environment {
+            action:
+                EnvironmentAction::Run {
+                    isolated,
+                    overrides,
+                    command,
+                },
+            ..
+        } => handle_env_run(isolated, &overrides, *command, input),
+        Command::Environment { action, format } => Ok(CommandResult::Continue(
+            handle_environment(action, format)?.into(),
+        )),
+        Command::Export { variables } => {
+            handle_export(&variables);
+            Ok(CommandResult::Continue(Vec::new()))
         }
-        Command::Environment { action } => Ok(CommandResult::Continue(handle_environment(action)?)),
         Command::If {
             condition,
             then_part,
             else_part,
         } => {
+            let mut buffer = OutputBuffer::default();
+
             // conditionが成功すればthen,失敗すればelseパートを実行
-            match execute_command(*condition, input) {
-                Ok(should_exit) => {
-                    if should_exit {
-                        return Ok(CommandResult::Exit);
-                    }
-                    // thenの出力
-                    if execute_command(*then_part, input)? {
-                        return Ok(CommandResult::Exit);
-                    }
-                }
-                Err(_) => {
-                    if let Some(else_cmd) = else_part
-                        && execute_command(*else_cmd, input)?
-                    {
-                        return Ok(CommandResult::Exit);
-                    }
-                }
+            let branch = match buffer.run(*condition, input) {
+                Ok(true) => return buffer.exit(),
+                Ok(false) => Some(*then_part),
+                Err(_) => else_part.map(|else_cmd| *else_cmd),
+            };
+
+            if let Some(branch) = branch
+                && buffer.run_body(branch, input)?
+            {
+                return buffer.exit();
             }
-            Ok(CommandResult::Continue(String::new()))
+
+            buffer.finish()
         }
         Command::While { condition, body } => {
             let mut loop_count = 0;
-            const MAX_ITERATIONS: usize = 1000;
+            let max_iterations = configured_max_iterations();
+
+            // 入力があればループ内のreadで一行ずつ読めるようにする（テキストの行として扱う）
+            let _input_guard =
+                input.map(|input| crate::input::push_input(&String::from_utf8_lossy(input)));
+
+            let mut buffer = OutputBuffer::default();
 
             loop {
-                if loop_count >= MAX_ITERATIONS {
-                    return Err(crate::error::RucliError::RuntimeError(
-                        "While loop exceeded maximum iterations".to_string(),
-                    ));
+                // 中断（Ctrl-Cやkill）が要求されていれば打ち切る
+                if crate::job::is_cancelled() {
+                    return Err(buffer.fail(RucliError::Interrupted));
                 }
 
-                // inputは無視してexecute_commandを使う
-                match execute_command(*condition.clone(), None) {
-                    Ok(should_exit) => {
-                        if should_exit {
-                            return Ok(CommandResult::Exit);
-                        }
-                        if execute_command(*body.clone(), None)? {
-                            return Ok(CommandResult::Exit);
+                if let Some(max) = max_iterations
+                    && loop_count >= max
+                {
+                    return Err(buffer.fail(RucliError::RuntimeError(format!(
+                        "While loop exceeded maximum iterations ({max})"
+                    ))));
+                }
+
+                // inputはreadが行単位で消費するので直接は渡さない
+                match buffer.run(*condition.clone(), None) {
+                    Ok(true) => return buffer.exit(),
+                    Ok(false) => {
+                        if buffer.run_body(*body.clone(), None)? {
+                            return buffer.exit();
                         }
                     }
                     Err(_) => break,
@@ -565,63 +1927,169 @@ pub fn execute_command_internal(command: Command, input: Option<&str>) -> Result
                 loop_count += 1;
             }
 
-            Ok(CommandResult::Continue(String::new()))
+            buffer.finish()
         }
         Command::For {
             variable,
             items,
             body,
         } => {
-            for item in items {
-                // ループ変数を環境変数として設定
-                unsafe {
-                    std::env::set_var(&variable, &item);
-                }
+            let mut buffer = OutputBuffer::default();
+
+            for item in expand_words(&items)? {
+                // ループ変数はセッション変数として設定する（ループ後も最後の値が残る）
+                crate::environment::set_var(&variable, &item);
 
                 // bodyを実行
-                if execute_command(*body.clone(), None)? {
-                    unsafe {
-                        std::env::remove_var(&variable);
-                    }
-                    return Ok(CommandResult::Exit);
+                if buffer.run_body(*body.clone(), None)? {
+                    return buffer.exit();
                 }
             }
 
-            // ループ変数をクリア
-            unsafe {
-                std::env::remove_var(&variable);
+            buffer.finish()
+        }
+        Command::ArithmeticFor {
+            init,
+            condition,
+            step,
+            body,
+        } => {
+            let mut buffer = OutputBuffer::default();
+
+            // 各式は評価のたびに変数展開する
+            evaluate_assignment(&expand_variables(&init))?;
+
+            loop {
+                // 空の条件は常に真
+                if !condition.trim().is_empty()
+                    && evaluate_assignment(&expand_variables(&condition))
+                        .map_err(|e| buffer.fail(e))?
+                        == 0
+                {
+                    break;
+                }
+
+                if buffer.run_body(*body.clone(), None)? {
+                    return buffer.exit();
+                }
+
+                evaluate_assignment(&expand_variables(&step)).map_err(|e| buffer.fail(e))?;
             }
 
-            Ok(CommandResult::Continue(String::new()))
+            buffer.finish()
         }
+        Command::Let { expressions } => {
+            for expression in expressions {
+                evaluate_assignment(&expression)?;
+            }
+            Ok(CommandResult::Continue(Vec::new()))
+        }
+        Command::Read { prompt, variables } => Ok(CommandResult::Continue(
+            handle_read(prompt.as_deref(), &variables, input)?.into(),
+        )),
+        Command::Xargs {
+            command,
+            max_args,
+            replace,
+            exit_on_error,
+        } => handle_xargs(&command, max_args, replace.as_deref(), exit_on_error, input),
+        Command::Type {
+            names,
+            external_only,
+        } => Ok(CommandResult::Continue(
+            handle_type(&names, external_only)?.into(),
+        )),
+        Command::Seq { args, equal_width } => Ok(CommandResult::Continue(
+            handle_seq(&args, equal_width)?.into(),
+        )),
+        Command::Set { options } => Ok(CommandResult::Continue(handle_set(&options)?.into())),
+        Command::Umask { mask } => Ok(CommandResult::Continue(handle_umask(mask).into())),
+        Command::Trap { action } => Ok(CommandResult::Continue(handle_trap(action)?.into())),
+        Command::Exec { args, redirects } => Ok(CommandResult::Continue(
+            handle_exec(&args, &redirects)?.into(),
+        )),
+        Command::Assignment {
+            assignments,
+            command,
+        } => handle_assignment(&assignments, command.map(|c| *c), input),
         Command::Function { name, body } => {
             handle_function_definition(&name, *body)?;
-            Ok(CommandResult::Continue(String::new()))
-        }
-        Command::FunctionCall { name, args } => {
-            Ok(CommandResult::Continue(handle_function_call(&name, &args)?))
+            Ok(CommandResult::Continue(Vec::new()))
         }
+        Command::FunctionCall { name, args } => handle_function_call(&name, &args),
+        // execute_expandedで解決してある
+        Command::Unresolved { .. } => unreachable!(),
         Command::Compound { commands } => {
+            let mut buffer = OutputBuffer::default();
+            let mut status = 0;
+
+            // 失敗したコマンドがあってもエラーを表示して次へ進む（set -e なら止める）
             for cmd in commands {
-                if execute_command(cmd, input)? {
-                    return Ok(CommandResult::Exit);
-                }
+                status = match buffer.run(cmd, input) {
+                    Ok(true) => return buffer.exit(),
+                    Ok(false) => 0,
+                    // 中断と深すぎる入れ子は、残りを実行せずに外側へ伝える
+                    Err(e @ (RucliError::Interrupted | RucliError::NestingTooDeep { .. })) => {
+                        return Err(buffer.fail(e));
+                    }
+                    Err(e) if crate::options::current().errexit => return Err(buffer.fail(e)),
+                    // 出力付きの失敗は出力をためてあるので、ステータスだけ記録する
+                    Err(RucliError::CommandFailed { status, .. }) => status,
+                    Err(e) => {
+                        let e = buffer.fail(e);
+                        crate::output::eprint_line(&e.to_string());
+                        e.exit_code()
+                    }
+                };
+                crate::environment::set_last_status(status);
             }
-            Ok(CommandResult::Continue(String::new()))
+
+            // 全体のステータスは最後のコマンドのステータス
+            buffer.finish_with_status(status)
         }
-        Command::History { action } => match action {
-            HistoryAction::List | HistoryAction::Search(_) => {
-                Ok(CommandResult::Continue(handle_history(action)?))
-            }
-            HistoryAction::Execute(_) => {
-                let cmd_str = handle_history(action)?;
-                let cmd = parse_command(&cmd_str)?;
-                execute_command_internal(cmd, input)
+        Command::History { action, format } => match action {
+            HistoryAction::Execute { .. }
+            | HistoryAction::Substitute { .. }
+            | HistoryAction::Edit(_) => {
+                // 番号で再実行する破壊的なコマンドは、-y がなければ確認する
+                let confirm_index = match action {
+                    HistoryAction::Execute {
+                        index,
+                        assume_yes: false,
+                    } => Some(index),
+                    _ => None,
+                };
+                let cmd_str = handle_history(action, format)?;
+                // エディタで空にした場合は何も実行しない
+                if cmd_str.trim().is_empty() {
+                    return Ok(CommandResult::Continue(Vec::new()));
+                }
+
+                // 実際に実行するコマンドを表示して履歴に残し、出力とステータスはそのまま返す
+                crate::history::with_history_executing(&cmd_str, || {
+                    crate::output::print_line(&cmd_str);
+                    crate::history::add_history(cmd_str.clone());
+                    let command = parse_command(&cmd_str)?;
+                    if let Some(index) = confirm_index
+                        && command.is_destructive()
+                    {
+                        confirm_history_execute(index, input)?;
+                    }
+                    execute_command_internal(command, input)
+                })
             }
+            _ => Ok(CommandResult::Continue(
+                handle_history(action, format)?.into(),
+            )),
         },
-        Command::Exit => {
-            handle_exit();
-            Ok(CommandResult::Exit)
+        // 入力を読めるのはシェルの入力ループだけなので、一行に単独で書かれた場合しか使えない
+        Command::Paste => Err(RucliError::RuntimeError(
+            "paste: must be used on a line of its own".to_string(),
+        )),
+        Command::Exit { status } => {
+            // 終了ステータスは$?として保持し、mainが終了時に使う
+            crate::environment::set_last_status(status.unwrap_or(0));
+            Ok(CommandResult::Exit(Vec::new()))
         }
     }
 }
@@ -650,4 +2118,25 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_is_destructive() {
+        let destructive = |input: &str| {
+            crate::parser::parse_command(input)
+                .unwrap()
+                .is_destructive()
+        };
+
+        assert!(destructive("rm -rf build"));
+        assert!(destructive("mv a b"));
+        assert!(destructive("echo hi > out.txt"));
+        assert!(destructive("cat missing 2> err.txt"));
+        assert!(destructive("cd build; rm old.txt"));
+        assert!(destructive("ls | grep x > list.txt"));
+
+        assert!(!destructive("echo hi >> out.txt"));
+        assert!(!destructive("echo hi > /dev/null"));
+        assert!(!destructive("cp a b"));
+        assert!(!destructive("ls -R"));
+    }
 }
@@ -0,0 +1,324 @@
+//! Tabキーによる補完の候補を作るモジュール
+//!
+//! 補完する語がコマンドの最初の語ならコマンド名を、それ以外はコマンド名ごとの
+//! `CompletionProvider` が候補を返す（`cd` はディレクトリだけ、`env` は変数名など）。
+//! 専用のものがないコマンドはファイル名で補完する。
+//! 端末がなくても `complete(line, cursor)` を呼べば候補を確かめられる。
+
+use crate::alias::list_aliases;
+use crate::commands::COMMANDS;
+use crate::environment::{get_var, list_all_vars};
+use crate::{functions, job};
+use std::fs;
+use std::path::PathBuf;
+
+/// 補完の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// 補完する語が始まる位置（バイト単位、候補はここからカーソルまでを置き換える）
+    pub start: usize,
+    /// 名前順の候補（置き換える語全体）
+    pub candidates: Vec<String>,
+}
+
+/// コマンドの引数の補完候補を返すもの
+pub trait CompletionProvider {
+    /// 補完候補を返す
+    ///
+    /// # Arguments
+    /// * `args` - 補完する語より前の引数（コマンド名は含まない）
+    /// * `word` - 補完する語（カーソルより前の部分）
+    fn candidates(&self, args: &[&str], word: &str) -> Vec<String>;
+}
+
+/// ファイル名（`directories_only` ならディレクトリ名だけ）で補完する
+struct PathProvider {
+    directories_only: bool,
+}
+
+/// 変数名で補完する（env）
+struct VariableProvider;
+
+/// ジョブ番号で補完する（fg・bg、`%` で始まる語は `%N` の形）
+struct JobProvider;
+
+/// エイリアス名で補完する（alias）
+struct AliasProvider;
+
+/// 組み込みコマンドの名前で補完する（help）
+struct BuiltinProvider;
+
+/// historyのサブコマンドで補完する（番号を取る位置では何も補完しない）
+struct HistoryProvider;
+
+static FILES: PathProvider = PathProvider {
+    directories_only: false,
+};
+static DIRECTORIES: PathProvider = PathProvider {
+    directories_only: true,
+};
+
+/// コマンド名に対応する補完方法（専用のものがなければファイル名）
+fn provider_for(command: &str) -> &'static dyn CompletionProvider {
+    match command {
+        "cd" | "pushd" => &DIRECTORIES,
+        "env" | "export" => &VariableProvider,
+        "fg" | "bg" => &JobProvider,
+        "alias" => &AliasProvider,
+        "help" => &BuiltinProvider,
+        "history" => &HistoryProvider,
+        _ => &FILES,
+    }
+}
+
+/// カーソル位置の語の補完候補を返す
+///
+/// コマンドの区切り（`|` `;` `&`）の後の最初の語はコマンド名として補完する
+///
+/// # Arguments
+/// * `line` - 入力中の行
+/// * `cursor` - カーソルの位置（バイト単位）
+pub fn complete(line: &str, cursor: usize) -> Completion {
+    let before = &line[..cursor];
+    let start = before
+        .rfind(|c: char| c.is_whitespace() || matches!(c, '|' | ';' | '&'))
+        .map_or(0, |pos| pos + 1);
+    let word = &before[start..];
+
+    let segment_start = before[..start]
+        .rfind(['|', ';', '&'])
+        .map_or(0, |pos| pos + 1);
+    let words: Vec<&str> = before[segment_start..start].split_whitespace().collect();
+
+    let mut candidates = match words.split_first() {
+        None => command_names(word),
+        Some((command, args)) => provider_for(command).candidates(args, word),
+    };
+    candidates.sort();
+    candidates.dedup();
+
+    Completion { start, candidates }
+}
+
+/// 候補に共通する先頭部分
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let Some(first) = candidates.first() else {
+        return "";
+    };
+
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// 組み込みコマンド・関数・エイリアスの名前のうち、`word` で始まるもの
+fn command_names(word: &str) -> Vec<String> {
+    let builtins = COMMANDS.iter().map(|cmd| cmd.name.to_string());
+    let aliases = list_aliases().into_iter().map(|(name, _)| name);
+
+    builtins
+        .chain(functions::list_functions())
+        .chain(aliases)
+        .filter(|name| name.starts_with(word))
+        .collect()
+}
+
+impl CompletionProvider for PathProvider {
+    fn candidates(&self, _args: &[&str], word: &str) -> Vec<String> {
+        // 最後の / までがディレクトリ、その後ろが名前の先頭部分
+        let (dir, prefix) = match word.rfind('/') {
+            Some(pos) => word.split_at(pos + 1),
+            None => ("", word),
+        };
+        let search_dir = match dir.strip_prefix("~/") {
+            Some(rest) => PathBuf::from(get_var("HOME").unwrap_or_default()).join(rest),
+            None if dir.is_empty() => PathBuf::from("."),
+            None => PathBuf::from(dir),
+        };
+
+        let Ok(entries) = fs::read_dir(&search_dir) else {
+            return Vec::new();
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                // 隠しファイルは . で始まる語を補完するときだけ出す
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+
+                let is_dir = entry.path().is_dir();
+                if self.directories_only && !is_dir {
+                    return None;
+                }
+                Some(format!("{dir}{name}{}", if is_dir { "/" } else { "" }))
+            })
+            .collect()
+    }
+}
+
+impl CompletionProvider for VariableProvider {
+    fn candidates(&self, _args: &[&str], word: &str) -> Vec<String> {
+        list_all_vars()
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(word))
+            .collect()
+    }
+}
+
+impl CompletionProvider for JobProvider {
+    fn candidates(&self, _args: &[&str], word: &str) -> Vec<String> {
+        let percent = if word.starts_with('%') { "%" } else { "" };
+        job::job_ids()
+            .into_iter()
+            .map(|id| format!("{percent}{id}"))
+            .filter(|spec| spec.starts_with(word))
+            .collect()
+    }
+}
+
+impl CompletionProvider for AliasProvider {
+    fn candidates(&self, _args: &[&str], word: &str) -> Vec<String> {
+        list_aliases()
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(word))
+            .collect()
+    }
+}
+
+impl CompletionProvider for BuiltinProvider {
+    fn candidates(&self, _args: &[&str], word: &str) -> Vec<String> {
+        COMMANDS
+            .iter()
+            .map(|cmd| cmd.name.to_string())
+            .filter(|name| name.starts_with(word))
+            .collect()
+    }
+}
+
+impl CompletionProvider for HistoryProvider {
+    fn candidates(&self, args: &[&str], word: &str) -> Vec<String> {
+        if !args.is_empty() {
+            return Vec::new();
+        }
+        ["clear", "delete", "exec", "search"]
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::set_var;
+    use crate::state::{ShellState, enter};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn fixture() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::create_dir(dir.path().join("scripts")).unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join("setup.sh"), "").unwrap();
+        fs::write(dir.path().join("README.md"), "").unwrap();
+        dir
+    }
+
+    fn candidates(line: &str) -> Vec<String> {
+        complete(line, line.len()).candidates
+    }
+
+    #[test]
+    fn test_cd_offers_only_directories() {
+        let _state = enter(Arc::new(ShellState::new()));
+        let dir = fixture();
+        let root = dir.path().display();
+
+        assert_eq!(
+            candidates(&format!("cd {root}/s")),
+            [format!("{root}/scripts/"), format!("{root}/src/")]
+        );
+        assert_eq!(
+            candidates(&format!("cd {root}/.")),
+            [format!("{root}/.git/")]
+        );
+
+        // 他のコマンドはファイルも補完する
+        assert_eq!(
+            candidates(&format!("cat {root}/s")),
+            [
+                format!("{root}/scripts/"),
+                format!("{root}/setup.sh"),
+                format!("{root}/src/")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_completion_replaces_the_word_before_the_cursor() {
+        let _state = enter(Arc::new(ShellState::new()));
+        let dir = fixture();
+        let line = format!("ls | cat {}/REA && echo", dir.path().display());
+        let cursor = line.find(" &&").unwrap();
+
+        let completion = complete(&line, cursor);
+        assert_eq!(completion.start, "ls | cat ".len());
+        assert_eq!(
+            completion.candidates,
+            [format!("{}/README.md", dir.path().display())]
+        );
+    }
+
+    #[test]
+    fn test_command_specific_candidates() {
+        let _state = enter(Arc::new(ShellState::new()));
+        set_var("RUCLI_415_FIRST", "1");
+        set_var("RUCLI_415_SECOND", "2");
+        crate::alias::set_alias("rucli415ll", "ls");
+
+        assert_eq!(
+            candidates("env RUCLI_415_"),
+            ["RUCLI_415_FIRST", "RUCLI_415_SECOND"]
+        );
+        assert_eq!(candidates("alias rucli415"), ["rucli415ll"]);
+        assert_eq!(candidates("help hist"), ["history"]);
+        assert_eq!(candidates("echo a; hist"), ["history"]);
+        assert_eq!(candidates("rucli415"), ["rucli415ll"]);
+        assert_eq!(candidates("history e"), ["exec"]);
+        assert!(candidates("history exec ").is_empty());
+        assert!(candidates("fg %").is_empty());
+
+        for command in ["sleep 10", "sleep 20"] {
+            let id = job::get_next_job_id();
+            let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+            job::create_job_with_id(id, command.to_string(), std::thread::current().id(), cancel);
+        }
+        assert_eq!(candidates("fg %"), ["%1", "%2"]);
+        assert_eq!(candidates("fg 2"), ["2"]);
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let words = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(common_prefix(&words(&["scripts/", "src/"])), "s");
+        assert_eq!(common_prefix(&words(&["日本語", "日本"])), "日本");
+        assert_eq!(common_prefix(&words(&["only"])), "only");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
@@ -0,0 +1,223 @@
+//! 行単位の差分（unified形式）を計算するモジュール
+//!
+//! 最長共通部分列（LCS）から編集手順を求め、前後3行の文脈を付けたハンクにまとめる。
+
+/// ハンクの前後に付ける文脈の行数
+const CONTEXT_LINES: usize = 3;
+
+/// 一行ごとの編集操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal,  // 両方にある行
+    Delete, // 旧ファイルにだけある行
+    Insert, // 新ファイルにだけある行
+}
+
+/// 二つのテキストのunified形式の差分を返す
+///
+/// # Arguments
+/// * `old` - 比較元のテキスト
+/// * `new` - 比較先のテキスト
+/// * `old_name` - `---` ヘッダに表示する名前
+/// * `new_name` - `+++` ヘッダに表示する名前
+///
+/// # Returns
+/// * 差分（末尾の改行なし）、同一なら None
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> Option<String> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    let edits = diff_lines(&old_lines, &new_lines);
+    if edits.iter().all(|edit| *edit == Edit::Equal) {
+        return None;
+    }
+
+    let mut output = vec![format!("--- {old_name}"), format!("+++ {new_name}")];
+
+    // 各編集操作の直前の旧・新ファイルの行位置（0始まり）
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in &edits {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Equal => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Delete => old_pos += 1,
+            Edit::Insert => new_pos += 1,
+        }
+    }
+
+    for (start, end) in hunk_ranges(&edits) {
+        let (old_start, new_start) = positions[start];
+        let old_count = edits[start..end]
+            .iter()
+            .filter(|edit| **edit != Edit::Insert)
+            .count();
+        let new_count = edits[start..end]
+            .iter()
+            .filter(|edit| **edit != Edit::Delete)
+            .count();
+
+        output.push(format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+
+        for (edit, &(old_index, new_index)) in edits[start..end].iter().zip(&positions[start..end])
+        {
+            output.push(match edit {
+                Edit::Equal => format!(" {}", old_lines[old_index]),
+                Edit::Delete => format!("-{}", old_lines[old_index]),
+                Edit::Insert => format!("+{}", new_lines[new_index]),
+            });
+        }
+    }
+
+    Some(output.join("\n"))
+}
+
+/// ハンクヘッダの範囲表記（`開始,行数`、1行なら行数を省略）
+///
+/// 行数が0の場合、開始位置は直前の行番号になる
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// LCSに基づいて編集手順を求める
+///
+/// 共通の先頭・末尾を除いてから表を作る。変更箇所では削除を挿入より先に並べる。
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    let (n, m) = (old_mid.len(), new_mid.len());
+
+    // lcs[i][j] = old_mid[i..] と new_mid[j..] のLCSの長さ
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = vec![Edit::Equal; prefix];
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_mid[i] == new_mid[j] {
+            edits.push(Edit::Equal);
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(Edit::Delete);
+            i += 1;
+        } else {
+            edits.push(Edit::Insert);
+            j += 1;
+        }
+    }
+    edits.extend(std::iter::repeat_n(Edit::Equal, suffix));
+
+    edits
+}
+
+/// 変更箇所を文脈付きのハンク（編集操作の範囲）にまとめる
+///
+/// 変更の間の共通行が文脈2つ分以下なら同じハンクにする
+fn hunk_ranges(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| **edit != Edit::Equal)
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &change in &changes {
+        let start = change.saturating_sub(CONTEXT_LINES);
+        let end = (change + 1 + CONTEXT_LINES).min(edits.len());
+
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_texts_have_no_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "x", "y"), None);
+        assert_eq!(unified_diff("", "", "x", "y"), None);
+    }
+
+    #[test]
+    fn test_one_line_change() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+
+        assert_eq!(
+            unified_diff(old, new, "a.txt", "b.txt").unwrap(),
+            "--- a.txt\n+++ b.txt\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8"
+        );
+    }
+
+    #[test]
+    fn test_insertion_at_start_and_end() {
+        assert_eq!(
+            unified_diff("a\nb\n", "new\na\nb\n", "x", "y").unwrap(),
+            "--- x\n+++ y\n@@ -1,2 +1,3 @@\n+new\n a\n b"
+        );
+        assert_eq!(
+            unified_diff("a\nb\n", "a\nb\nnew\n", "x", "y").unwrap(),
+            "--- x\n+++ y\n@@ -1,2 +1,3 @@\n a\n b\n+new"
+        );
+        // 空のファイルへの追加は開始位置0・行数0で表す
+        assert_eq!(
+            unified_diff("", "only\n", "x", "y").unwrap(),
+            "--- x\n+++ y\n@@ -0,0 +1 @@\n+only"
+        );
+    }
+
+    #[test]
+    fn test_distant_changes_make_separate_hunks() {
+        let old: String = (1..=20).map(|i| format!("{i}\n")).collect();
+        let new: String = (1..=20)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                18 => "eighteen\n".to_string(),
+                _ => format!("{i}\n"),
+            })
+            .collect();
+
+        let diff = unified_diff(&old, &new, "x", "y").unwrap();
+        let headers: Vec<&str> = diff.lines().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(headers, vec!["@@ -1,5 +1,5 @@", "@@ -15,6 +15,6 @@"]);
+    }
+}
@@ -0,0 +1,745 @@
+//! コマンドをrucliの構文で表示する（`Display` の実装）
+//!
+//! 表示した文字列をパースし直すと同じコマンドになる。ジョブ一覧やドライランの表示に使う。
+//! パース時にクォートを外す引数（alias・let・read・trap・eval・exec・代入の値）は、
+//! 空白や演算子を含む場合にクォートし直す。それ以外の引数はパースしたときのまま表示する。
+
+use crate::commands::{
+    Command, CutRange, CutSelection, DirStackAction, EnvironmentAction, FindFilter, FindNumber,
+    HistoryAction, LsSort, OutputFormat, Redirection, TrapAction,
+};
+use std::cmp::Ordering;
+use std::fmt;
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Help => write!(f, "help"),
+            Command::Clear => write!(f, "clear"),
+            Command::Version => write!(f, "version"),
+            Command::Pwd => write!(f, "pwd"),
+            Command::Echo {
+                message,
+                newline: true,
+            } => write!(f, "echo {message}"),
+            Command::Echo {
+                message,
+                newline: false,
+            } => write!(f, "{}", join(["echo", "-n", message])),
+            Command::Repeat {
+                count,
+                message,
+                delay,
+            } => {
+                let delay = delay
+                    .map(|delay| format!("-d {} ", delay.as_secs_f64()))
+                    .unwrap_or_default();
+                write!(
+                    f,
+                    "{}",
+                    format!("repeat {delay}{count} {message}").trim_end()
+                )
+            }
+            Command::Cat {
+                filename,
+                number,
+                show_nonprinting,
+                show_ends,
+                show_tabs,
+            } => {
+                let all = *show_nonprinting && *show_ends && *show_tabs;
+                let flag = |on: bool, flag| if on && !all { flag } else { "" };
+                write!(
+                    f,
+                    "{}",
+                    join([
+                        "cat",
+                        if *number { "-n" } else { "" },
+                        if all { "-A" } else { "" },
+                        flag(*show_nonprinting, "-v"),
+                        flag(*show_ends, "-E"),
+                        flag(*show_tabs, "-T"),
+                        filename,
+                    ])
+                )
+            }
+            Command::Write { filename, content } => {
+                write!(f, "{}", join(["write", filename, content]))
+            }
+            Command::Ls {
+                format,
+                recursive,
+                sort,
+                reverse,
+            } => {
+                let sort = match sort {
+                    LsSort::Name => "",
+                    LsSort::Time => "-t",
+                    LsSort::Size => "-S",
+                    LsSort::Unsorted => "--unsorted",
+                };
+                write!(
+                    f,
+                    "{}",
+                    join([
+                        "ls",
+                        if *recursive { "-R" } else { "" },
+                        sort,
+                        if *reverse { "-r" } else { "" },
+                        format_flag(*format).trim(),
+                    ])
+                )
+            }
+            Command::Jobs { format } => write!(f, "jobs{}", format_flag(*format)),
+            Command::Cd { path } => write!(f, "cd {path}"),
+            Command::DirStack { action } => match action {
+                DirStackAction::Push(dir) => {
+                    write!(f, "{}", join(["pushd", dir.as_deref().unwrap_or_default()]))
+                }
+                DirStackAction::Pop => write!(f, "popd"),
+                DirStackAction::Show { verbose } => {
+                    write!(f, "dirs{}", if *verbose { " -v" } else { "" })
+                }
+            },
+            Command::Mkdir {
+                paths,
+                parents,
+                mode,
+            } => {
+                let mut words = vec!["mkdir".to_string()];
+                if *parents {
+                    words.push("-p".to_string());
+                }
+                if let Some(mode) = mode {
+                    words.push(format!("-m {mode:o}"));
+                }
+                words.extend(paths.iter().cloned());
+                write!(f, "{}", words.join(" "))
+            }
+            Command::Rm {
+                path,
+                recursive,
+                force,
+                preserve_root,
+            } => {
+                let flags = match (recursive, force) {
+                    (true, true) => "-rf",
+                    (true, false) => "-r",
+                    (false, true) => "-f",
+                    (false, false) => "",
+                };
+                let no_preserve_root = if *preserve_root {
+                    ""
+                } else {
+                    "--no-preserve-root"
+                };
+                write!(f, "{}", join(["rm", flags, no_preserve_root, path]))
+            }
+            Command::Cp {
+                source,
+                destination,
+                recursive,
+                interactive,
+                verbose,
+            } => {
+                let recursive = if *recursive { "-r" } else { "" };
+                let interactive = if *interactive { "-i" } else { "" };
+                let verbose = if *verbose { "-v" } else { "" };
+                write!(
+                    f,
+                    "{}",
+                    join(["cp", recursive, interactive, verbose, source, destination])
+                )
+            }
+            Command::Mv {
+                source,
+                destination,
+                interactive,
+                verbose,
+            } => {
+                let interactive = if *interactive { "-i" } else { "" };
+                let verbose = if *verbose { "-v" } else { "" };
+                write!(
+                    f,
+                    "{}",
+                    join(["mv", interactive, verbose, source, destination])
+                )
+            }
+            Command::Rename {
+                pattern,
+                replacement,
+                dry_run,
+            } => {
+                let dry_run = if *dry_run { "-n" } else { "" };
+                write!(f, "{}", join(["rename", dry_run, pattern, replacement]))
+            }
+            Command::Find {
+                path,
+                name,
+                filters,
+                no_ignore,
+                print0,
+            } => {
+                let mut words = vec!["find".to_string()];
+                words.extend(path.clone());
+                words.push(name.clone());
+                words.extend(filters.iter().map(find_filter));
+                if *no_ignore {
+                    words.push("--no-ignore".to_string());
+                }
+                if *print0 {
+                    words.push("-print0".to_string());
+                }
+                write!(f, "{}", words.join(" "))
+            }
+            Command::Grep {
+                pattern,
+                files,
+                count,
+                stats,
+            } => {
+                let count = if *count { "-c" } else { "" };
+                let stats = if *stats { "--stats" } else { "" };
+                write!(f, "{}", join_all(["grep", count, stats, pattern], files))
+            }
+            Command::Du {
+                paths,
+                human_readable,
+                summarize,
+                no_ignore,
+            } => {
+                let human_readable = if *human_readable { "-h" } else { "" };
+                let summarize = if *summarize { "-s" } else { "" };
+                let no_ignore = if *no_ignore { "--no-ignore" } else { "" };
+                let options = join(["du", human_readable, summarize, no_ignore]);
+                write!(f, "{}", join_all([options.as_str()], paths))
+            }
+            Command::Cut {
+                selection,
+                delimiter,
+                only_delimited,
+                files,
+            } => {
+                let selection = match selection {
+                    CutSelection::Fields(ranges) => format!("-f {}", cut_list(ranges)),
+                    CutSelection::Characters(ranges) => format!("-c {}", cut_list(ranges)),
+                };
+                // 既定のタブは省略する
+                let delimiter = match delimiter {
+                    '\t' => String::new(),
+                    delimiter => format!("-d {}", quote(&delimiter.to_string())),
+                };
+                let only_delimited = if *only_delimited { "-s" } else { "" };
+                let options = join(["cut", &selection, &delimiter, only_delimited]);
+                write!(f, "{}", join_all([options.as_str()], files))
+            }
+            Command::Tr {
+                set1,
+                set2,
+                delete,
+                squeeze,
+            } => {
+                let delete = if *delete { "-d" } else { "" };
+                let squeeze = if *squeeze { "-s" } else { "" };
+                let set2 = set2.as_deref().unwrap_or_default();
+                write!(f, "{}", join(["tr", delete, squeeze, set1, set2]))
+            }
+            Command::Diff {
+                file1,
+                file2,
+                brief,
+            } => {
+                let brief = if *brief { "-q" } else { "" };
+                write!(f, "{}", join(["diff", brief, file1, file2]))
+            }
+            Command::Tree {
+                path,
+                max_depth,
+                show_hidden,
+                no_ignore,
+            } => {
+                let max_depth = max_depth
+                    .map(|depth| format!("-L {depth}"))
+                    .unwrap_or_default();
+                let show_hidden = if *show_hidden { "-a" } else { "" };
+                let no_ignore = if *no_ignore { "--no-ignore" } else { "" };
+                let path = path.as_deref().unwrap_or_default();
+                write!(
+                    f,
+                    "{}",
+                    join(["tree", path, &max_depth, show_hidden, no_ignore])
+                )
+            }
+            Command::Alias { name, command } => match (name, command) {
+                (Some(name), Some(command)) => write!(f, "alias {name}={}", quote(command)),
+                _ => write!(f, "alias"),
+            },
+            Command::Pipeline { commands } => {
+                let commands: Vec<String> = commands
+                    .iter()
+                    .map(|command| Simple(command).to_string())
+                    .collect();
+                write!(f, "{}", commands.join(" | "))
+            }
+            Command::Redirect { command, redirects } => {
+                write!(f, "{}", Simple(command))?;
+                redirects
+                    .iter()
+                    .try_for_each(|redirect| write!(f, " {redirect}"))
+            }
+            Command::Background {
+                command, stopped, ..
+            } => write!(
+                f,
+                "{} &{}",
+                Simple(command),
+                if *stopped { "?" } else { "" }
+            ),
+            Command::Not { command } => write!(f, "! {}", Simple(command)),
+            Command::Time { command } => write!(f, "time {}", Simple(command)),
+            Command::Eval { args } => write!(f, "{}", join_quoted("eval", args)),
+            Command::Subshell { command } => write!(f, "({command})"),
+            Command::Sleep { duration } => write!(f, "sleep {}", duration.as_secs_f64()),
+            Command::Fg { job } => match job {
+                Some(job) => write!(f, "fg {job}"),
+                None => write!(f, "fg"),
+            },
+            Command::Bg { job } => match job {
+                Some(job) => write!(f, "bg {job}"),
+                None => write!(f, "bg"),
+            },
+            Command::Environment { action, format } => {
+                let (session, argument) = match action {
+                    EnvironmentAction::List {
+                        pattern,
+                        session_only,
+                    } => (
+                        if *session_only { "--session" } else { "" },
+                        pattern.clone().unwrap_or_default(),
+                    ),
+                    EnvironmentAction::Show(name) => ("", name.clone()),
+                    EnvironmentAction::Set(name, value) => ("", format!("{name}={value}")),
+                    EnvironmentAction::Run {
+                        isolated,
+                        overrides,
+                        command,
+                    } => {
+                        let words: Vec<String> = overrides
+                            .iter()
+                            .map(|(name, value)| format!("{name}={value}"))
+                            .chain(std::iter::once(command.to_string()))
+                            .collect();
+                        return write!(
+                            f,
+                            "{}",
+                            join_all(["env", if *isolated { "-i" } else { "" }], &words)
+                        );
+                    }
+                };
+                write!(
+                    f,
+                    "{}",
+                    join(["env", format_flag(*format).trim(), session, &argument])
+                )
+            }
+            Command::Export { variables } => {
+                let words: Vec<String> = variables
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Some(value) => format!("{name}={value}"),
+                        None => name.clone(),
+                    })
+                    .collect();
+                write!(f, "{}", join_all(["export"], &words))
+            }
+            Command::If {
+                condition,
+                then_part,
+                else_part,
+            } => {
+                write!(f, "if {condition}; then {then_part}; ")?;
+                if let Some(else_part) = else_part {
+                    write!(f, "else {else_part}; ")?;
+                }
+                write!(f, "fi")
+            }
+            Command::While { condition, body } => {
+                write!(f, "while {condition}; do {body}; done")
+            }
+            Command::For {
+                variable,
+                items,
+                body,
+            } => write!(f, "for {variable} in {}; do {body}; done", items.join(" ")),
+            Command::ArithmeticFor {
+                init,
+                condition,
+                step,
+                body,
+            } => write!(f, "for (({init}; {condition}; {step})); do {body}; done"),
+            Command::Let { expressions } => write!(f, "{}", join_quoted("let", expressions)),
+            Command::Read { prompt, variables } => {
+                let prompt = prompt
+                    .as_deref()
+                    .map(|prompt| format!("-p {}", quote(prompt)))
+                    .unwrap_or_default();
+                write!(f, "{}", join_all(["read", &prompt], variables))
+            }
+            Command::Seq { args, equal_width } => {
+                let equal_width = if *equal_width { "-w" } else { "" };
+                write!(f, "{}", join_all(["seq", equal_width], args))
+            }
+            Command::Xargs {
+                command,
+                max_args,
+                replace,
+                exit_on_error,
+            } => {
+                let max_args = max_args.map(|n| format!("-n {n}")).unwrap_or_default();
+                let replace = replace
+                    .as_deref()
+                    .map(|replace| format!("-I {replace}"))
+                    .unwrap_or_default();
+                let exit_on_error = if *exit_on_error { "-e" } else { "" };
+                let options = join(["xargs", &max_args, &replace, exit_on_error]);
+                write!(f, "{}", join_all([options.as_str()], command))
+            }
+            Command::Type {
+                names,
+                external_only,
+            } => {
+                let name = if *external_only { "which" } else { "type" };
+                write!(f, "{}", join_all([name], names))
+            }
+            Command::Assignment {
+                assignments,
+                command,
+            } => {
+                let assignments = assignments
+                    .iter()
+                    .map(|(name, value)| format!("{name}={}", quote(value)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                match command {
+                    Some(command) => write!(f, "{assignments} {}", Simple(command)),
+                    None => write!(f, "{assignments}"),
+                }
+            }
+            Command::Function { name, body } => write!(f, "function {name}() {{ {body}; }}"),
+            Command::FunctionCall { name, args } => {
+                write!(f, "{}", join_all([name.as_str()], args))
+            }
+            Command::Unresolved { text } => write!(f, "{text}"),
+            Command::Compound { commands } => {
+                let commands: Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+                write!(f, "{}", commands.join("; "))
+            }
+            Command::Set { options } => {
+                let mut words = vec!["set".to_string()];
+                words.extend(options.iter().map(|(name, enabled)| {
+                    format!("{}o {name}", if *enabled { '-' } else { '+' })
+                }));
+                write!(f, "{}", words.join(" "))
+            }
+            Command::History { action, format } => {
+                let line = match action {
+                    HistoryAction::List => "history".to_string(),
+                    HistoryAction::Recent(count) => format!("history -n {count}"),
+                    HistoryAction::Search { query, regex, all } => {
+                        let regex = if *regex { "-r" } else { "" };
+                        let all = if *all { "--all" } else { "" };
+                        join(["history search", regex, all, query])
+                    }
+                    HistoryAction::Execute {
+                        index,
+                        assume_yes: false,
+                    } => format!("history exec {index}"),
+                    HistoryAction::Execute {
+                        index,
+                        assume_yes: true,
+                    } => format!("history exec -y {index}"),
+                    HistoryAction::Clear => "history clear".to_string(),
+                    HistoryAction::Delete(index) => format!("history delete {index}"),
+                    HistoryAction::Substitute {
+                        replacement,
+                        target,
+                    } => {
+                        let replacement = replacement
+                            .as_ref()
+                            .map(|(old, new)| format!("{old}={new}"))
+                            .unwrap_or_default();
+                        let target = target.as_deref().unwrap_or_default();
+                        return write!(f, "{}", join(["fc -s", &replacement, target]));
+                    }
+                    HistoryAction::Edit(target) => {
+                        return write!(
+                            f,
+                            "{}",
+                            join(["fc", target.as_deref().unwrap_or_default()])
+                        );
+                    }
+                };
+                write!(f, "{line}{}", format_flag(*format))
+            }
+            Command::Paste => write!(f, "paste"),
+            Command::Exit { status } => match status {
+                Some(status) => write!(f, "exit {status}"),
+                None => write!(f, "exit"),
+            },
+            Command::Umask { mask } => match mask {
+                Some(mask) => write!(f, "umask {mask:03o}"),
+                None => write!(f, "umask"),
+            },
+            Command::Trap { action } => match action {
+                TrapAction::List => write!(f, "trap"),
+                TrapAction::Set { command, signals } => {
+                    write!(f, "trap {}", quote(command))?;
+                    signals
+                        .iter()
+                        .try_for_each(|signal| write!(f, " {}", signal.name()))
+                }
+                TrapAction::Remove(signals) => {
+                    write!(f, "trap -")?;
+                    signals
+                        .iter()
+                        .try_for_each(|signal| write!(f, " {}", signal.name()))
+                }
+            },
+            Command::Exec { args, redirects } => {
+                write!(f, "{}", join_quoted("exec", args))?;
+                redirects
+                    .iter()
+                    .try_for_each(|redirect| write!(f, " {redirect}"))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", join([self.redirect_type.as_str(), &self.target]))
+    }
+}
+
+/// 一つのコマンドとして書く位置（`&`・`!`・`time`・リダイレクトの対象）に置くコマンド
+///
+/// 複数のコマンドは `{ ...; }` で囲む
+struct Simple<'a>(&'a Command);
+
+impl fmt::Display for Simple<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Command::Compound { .. } => write!(f, "{{ {}; }}", self.0),
+            command => write!(f, "{command}"),
+        }
+    }
+}
+
+/// 空の語を除いて空白でつなげる
+fn join<'a>(words: impl IntoIterator<Item = &'a str>) -> String {
+    words
+        .into_iter()
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 先頭の語と残りの引数を空白でつなげる
+fn join_all<'a>(head: impl IntoIterator<Item = &'a str>, rest: &'a [String]) -> String {
+    join(head.into_iter().chain(rest.iter().map(String::as_str)))
+}
+
+/// 引数をクォートし直してコマンド名の後ろにつなげる
+fn join_quoted(name: &str, args: &[String]) -> String {
+    std::iter::once(name.to_string())
+        .chain(args.iter().map(|arg| quote(arg)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 出力形式の指定（テキストなら空）
+fn format_flag(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Text => "",
+        OutputFormat::Json => " --json",
+    }
+}
+
+/// 一つの語として読めるようにクォートする（必要なければそのまま）
+///
+/// シングルクォートを含む場合はダブルクォートで囲み、`"` と `\` をエスケープする
+pub(crate) fn quote(word: &str) -> String {
+    const SPECIAL: &[char] = &['\'', '"', '\\', '|', '&', ';', '<', '>', '(', ')', '{', '}'];
+
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || SPECIAL.contains(&c)) {
+        word.to_string()
+    } else if !word.contains('\'') {
+        format!("'{word}'")
+    } else {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// findの条件を表示する
+fn find_filter(filter: &FindFilter) -> String {
+    match filter {
+        FindFilter::ModifiedDays(days) => format!("-mtime {}", find_number(days)),
+        FindFilter::Size { size, unit } => {
+            let suffix = match unit {
+                1024 => "k",
+                1048576 => "M",
+                1073741824 => "G",
+                _ => "",
+            };
+            format!("-size {}{suffix}", find_number(size))
+        }
+        FindFilter::Newer(file) => format!("-newer {file}"),
+    }
+}
+
+/// findの数値の条件を表示する（`+N`・`-N`・`N`）
+fn find_number(number: &FindNumber) -> String {
+    let sign = match number.ordering {
+        Ordering::Greater => "+",
+        Ordering::Less => "-",
+        Ordering::Equal => "",
+    };
+    format!("{sign}{}", number.value)
+}
+
+/// cutの範囲リストを表示する（`1,3-5,7-`）
+fn cut_list(ranges: &[CutRange]) -> String {
+    ranges
+        .iter()
+        .map(|range| match range.end {
+            Some(end) if end == range.start => end.to_string(),
+            Some(end) => format!("{}-{end}", range.start),
+            None => format!("{}-", range.start),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_command;
+
+    #[test]
+    fn test_display_examples() {
+        let display = |input: &str| parse_command(input).unwrap().to_string();
+
+        assert_eq!(display("echo hi &"), "echo hi &");
+        assert_eq!(
+            display("if cat f;   then echo y; fi"),
+            "if cat f; then echo y; fi"
+        );
+        assert_eq!(display("ls|grep a"), "ls | grep a");
+        assert_eq!(display("sleep 30 &"), "sleep 30 &");
+        assert_eq!(display("rm -f -r x"), "rm -rf x");
+        assert_eq!(display("exec sh -c \"echo hi\""), "exec sh -c 'echo hi'");
+    }
+
+    #[test]
+    fn test_display_round_trips_through_parser() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+        crate::functions::define_function(
+            "greet",
+            Command::Echo {
+                message: "hello".to_string(),
+                newline: true,
+            },
+        );
+
+        let corpus = [
+            "help",
+            "echo hello world",
+            "echo \"a | b\" > out.txt",
+            "echo hi &",
+            "cat",
+            "cat notes.txt",
+            "cat -n -A notes.txt",
+            "cat -E -T",
+            "write out.txt some content",
+            "repeat -d 0.5 3 tick tock",
+            "ls --json",
+            "ls -R -S -r",
+            "jobs",
+            "cd ~",
+            "pushd src",
+            "dirs -v",
+            "mkdir -p -m 750 a/b c",
+            "rm -r --no-preserve-root build",
+            "cp -r src dst",
+            "mv a b",
+            "rename -n *.jpeg *.jpg",
+            "find logs *.log -mtime -1 -size +2M -newer ref.txt --no-ignore",
+            "find . *.txt -print0",
+            "grep TODO a.rs b.rs",
+            "du -sh --no-ignore src",
+            "cut -f 1,3-5,7- -d : -s /etc/passwd",
+            "cut -c -3",
+            "tr -d -s a-z x",
+            "diff -q a b",
+            "tree src -L 2 -a",
+            "alias ll=\"ls --json\"",
+            "cat a.txt | grep x | tr a-z A-Z",
+            "cat a.txt | grep x >> out.txt 2>&1",
+            "{ echo a; echo b; } &",
+            "! grep x f",
+            "time sleep 1",
+            "eval \"echo $X\" done",
+            "(cd src; ls)",
+            "sleep 1.25",
+            "fg 2",
+            "fg %-",
+            "env -i A=1 B=\"x y\" env",
+            "env TZ=UTC cat notes.txt",
+            "fg %sleep",
+            "env --json --session RUST*",
+            "env PATH",
+            "env FOO=bar",
+            "export FOO=bar BAZ",
+            "if cat f; then echo y; else echo n; fi",
+            "while read LINE; do echo $LINE; done < input.txt",
+            "for i in 1 2 3; do echo $i; echo again; done",
+            "for name in 'Alice Smith' \"$LIST\" $(seq 1 3); do echo Hi $name; done",
+            "for ((i = 0; i < 3; i++)); do echo $i; done",
+            "let I=I+1 \"X = 2 * Y\"",
+            "read -p \"Name: \" FIRST LAST",
+            "seq -w 1 2 10",
+            "grep -c --stats error a.log b.log",
+            "xargs -n 1 -I {} -e echo {}",
+            "which ls cat",
+            "A=1 MSG=\"hello world\"",
+            "FOO=bar echo $FOO",
+            "function f() { echo a; echo b; }",
+            "greet world",
+            "echo one; echo two",
+            "set -o noclobber +o errexit",
+            "history --json",
+            "history -n 5",
+            "history exec -y 3",
+            "history search cargo build",
+            "fc -s old=new echo",
+            "fc 12",
+            "exit 3",
+            "umask 022",
+            "trap \"rm -f lock; echo bye\" EXIT INT",
+            "trap - TERM",
+            "exec 2> err.log",
+        ];
+
+        for input in corpus {
+            let first = parse_command(input).unwrap();
+            let printed = first.to_string();
+            let second = parse_command(&printed)
+                .unwrap_or_else(|e| panic!("'{printed}' (from '{input}') does not parse: {e}"));
+            assert_eq!(
+                format!("{first:?}"),
+                format!("{second:?}"),
+                "'{input}' was printed as '{printed}'"
+            );
+        }
+    }
+}
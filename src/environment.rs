@@ -1,58 +1,268 @@
-use crate::error::Result;
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
-use std::sync::Mutex;
-
-use crate::commands::{CommandResult, execute_command_internal};
+use crate::arithmetic;
+use crate::error::{Result, RucliError};
+use crate::state::{self, LockExt};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+use crate::commands::{CommandResult, execute_command_collecting};
+use crate::nesting::nested;
 use crate::parser::parse_command;
 
-/// セッション固有の環境変数ストレージ
-static SESSION_VARS: Lazy<Mutex<HashMap<String, String>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+thread_local! {
+    /// 直前に実行したコマンドの終了ステータス（`$?`）
+    ///
+    /// バックグラウンドジョブが`$?`を書き換えないようにスレッドごとに保持する
+    static LAST_STATUS: Cell<i32> = const { Cell::new(0) };
+
+    /// 直前に実行したパイプラインの各ステージの終了ステータス
+    static PIPE_STATUS: RefCell<Vec<i32>> = const { RefCell::new(Vec::new()) };
+
+    /// コマンド置換を実行せずにそのまま残すか（構文だけを調べるとき）
+    static SUBSTITUTION_DISABLED: Cell<bool> = const { Cell::new(false) };
+
+    /// コマンド置換をパース時ではなく実行時の展開で行うか（制御構文の中のコマンドを実行しているとき）
+    static SUBSTITUTION_DEFERRED: Cell<bool> = const { Cell::new(false) };
+
+    /// `env [-i] VAR=value command` の実行中だけ有効な変数（内側のものほど後ろ）
+    static LAYERS: RefCell<Vec<EnvironmentLayer>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 一時的に上書きした変数の層
+struct EnvironmentLayer {
+    vars: HashMap<String, String>,
+    isolated: bool, // -i: 外側の変数（セッション変数・システム環境変数を含む）を隠す
+}
+
+/// 終了ステータスを記録
+pub fn set_last_status(status: i32) {
+    LAST_STATUS.with(|last| last.set(status));
+}
+
+/// 直前の終了ステータスを取得
+pub fn get_last_status() -> i32 {
+    LAST_STATUS.with(|last| last.get())
+}
+
+/// パイプラインの各ステージの終了ステータスを記録
+pub fn set_pipe_status(statuses: Vec<i32>) {
+    PIPE_STATUS.with(|pipe| *pipe.borrow_mut() = statuses);
+}
+
+/// 直前のパイプラインの各ステージの終了ステータスを取得
+pub fn get_pipe_status() -> Vec<i32> {
+    PIPE_STATUS.with(|pipe| pipe.borrow().clone())
+}
+
+/// コマンド置換を実行しないようにして処理を行う（`$(...)` はそのまま残る）
+pub fn without_command_substitution<T>(f: impl FnOnce() -> T) -> T {
+    let previous = SUBSTITUTION_DISABLED.with(|disabled| disabled.replace(true));
+    let result = f();
+    SUBSTITUTION_DISABLED.with(|disabled| disabled.set(previous));
+    result
+}
+
+/// コマンド置換を行う時点を切り替えて処理を行う
+///
+/// if・while・for・関数の本体はコマンド置換を残したままパースし、
+/// 実行時に変数を展開するときに置換する（ループ変数や引数を置換の中で参照できるように）。
+/// `deferred` が true の間は、パース時のコマンド置換も行わない。
+pub fn with_substitution_deferred<T>(deferred: bool, f: impl FnOnce() -> T) -> T {
+    let previous = SUBSTITUTION_DEFERRED.with(|flag| flag.replace(deferred));
+    let result = f();
+    SUBSTITUTION_DEFERRED.with(|flag| flag.set(previous));
+    result
+}
+
+/// コマンド置換を実行時の展開で行っているか
+pub fn substitution_deferred() -> bool {
+    SUBSTITUTION_DEFERRED.with(|flag| flag.get())
+}
+
+/// 実行時に一つの文字列を展開する
+///
+/// コマンド置換（制御構文の中のコマンドのみ）・算術展開・変数展開の順に行う
+///
+/// # Errors
+/// - コマンド置換が閉じていない場合や、算術式が不正な場合
+pub fn expand_word(input: &str) -> Result<String> {
+    expand_at_runtime(input, Splitting::Unquoted)
+}
+
+/// 実行時に代入の値（`NAME=value` の value）を展開する
+///
+/// [`expand_word`] と同じ順に展開するが、コマンド置換の結果は語に分けずにそのまま使う
+///
+/// # Errors
+/// - コマンド置換が閉じていない場合や、算術式が不正な場合
+pub fn expand_assignment_value(input: &str) -> Result<String> {
+    expand_at_runtime(input, Splitting::None)
+}
+
+/// 実行時の展開（コマンド置換の結果は `splitting` のとおりに埋め込む）
+fn expand_at_runtime(input: &str, splitting: Splitting) -> Result<String> {
+    let substituted = if substitution_deferred() {
+        substitute(input, splitting)?.join(" ")
+    } else {
+        input.to_string()
+    };
+    Ok(expand_variables(&expand_arithmetic(&substituted)?))
+}
+
+/// 変数を一時的に上書きして処理を行う（`env VAR=value command`）
+///
+/// 上書きは処理の間だけ、このスレッドでだけ有効で、セッション変数は変更しない
+///
+/// # Arguments
+/// * `isolated` - 上書きした変数以外を見えなくするか（`env -i`）
+/// * `overrides` - 上書きする (変数名, 値) の一覧（同じ名前は後のものが優先）
+pub fn with_environment<T>(
+    isolated: bool,
+    overrides: &[(String, String)],
+    f: impl FnOnce() -> T,
+) -> T {
+    LAYERS.with(|layers| {
+        layers.borrow_mut().push(EnvironmentLayer {
+            vars: overrides.iter().cloned().collect(),
+            isolated,
+        })
+    });
+    let result = f();
+    LAYERS.with(|layers| layers.borrow_mut().pop());
+    result
+}
+
+/// 上書きしている変数（外側の層から順に重ねたもの）と、外側の変数を隠しているか
+fn overridden_vars() -> (BTreeMap<String, String>, bool) {
+    LAYERS.with(|layers| {
+        let layers = layers.borrow();
+        let isolated_from = layers.iter().rposition(|layer| layer.isolated);
+        let vars = layers[isolated_from.unwrap_or(0)..]
+            .iter()
+            .flat_map(|layer| layer.vars.clone())
+            .collect();
+        (vars, isolated_from.is_some())
+    })
+}
+
+/// `env -i` で実行中か（外部コマンドには上書きした変数だけを渡す）
+pub fn is_environment_isolated() -> bool {
+    overridden_vars().1
+}
 
 /// 環境変数を取得
 pub fn get_var(name: &str) -> Option<String> {
-    // SESSION_VARSをロックして取得
-    let session_vars = SESSION_VARS.lock().unwrap();
+    // 一時的に上書きした変数を優先する（-i なら他の変数は見えない）
+    let (overrides, isolated) = overridden_vars();
+    if let Some(value) = overrides.get(name) {
+        return Some(value.clone());
+    }
+    if isolated {
+        return None;
+    }
+
+    // 現在のシェルのセッション変数をロックして取得
+    let state = state::current();
+    let session_vars = state.vars.lock_or_recover();
 
     // セッション変数から検索
     if let Some(value) = session_vars.get(name) {
         return Some(value.clone());
     }
+    drop(session_vars);
+
+    // 特殊変数（セッション変数で上書きできる）
+    if let Some(value) = special_var(name) {
+        return Some(value);
+    }
 
     // セッション変数になければシステム変数から検索
     std::env::var(name).ok()
 }
 
+/// 参照するたびに値が決まる特殊変数を取得
+///
+/// - `PPID`: 親プロセスのID
+/// - `RANDOM`: 参照ごとに変わる 0〜32767 の値
+/// - `SECONDS`: シェルを作成してからの経過秒数
+fn special_var(name: &str) -> Option<String> {
+    let value = match name {
+        "PPID" => u64::from(std::os::unix::process::parent_id()),
+        // RandomStateは作るたびに異なるキーを持つので、空のハッシュ値を乱数として使う
+        "RANDOM" => RandomState::new().build_hasher().finish() % 32768,
+        "SECONDS" => state::current().started.0.elapsed().as_secs(),
+        _ => return None,
+    };
+    Some(value.to_string())
+}
+
 /// 環境変数を設定
 pub fn set_var(name: &str, value: &str) {
-    // SESSION_VARSをロックして取得
-    let mut session_vars = SESSION_VARS.lock().unwrap();
+    // 現在のシェルのセッション変数をロックして取得
+    let state = state::current();
+    let mut session_vars = state.vars.lock_or_recover();
     session_vars.insert(name.to_string(), value.to_string());
 }
 
-// 環境変数をすべて表示
-pub fn list_all_vars() -> Vec<(String, String)> {
-    let mut result = Vec::new();
-
-    // システム環境変数を取得
-    for (key, value) in std::env::vars() {
-        result.push((key, value));
+/// 変数をエクスポートし、システム環境変数にも設定する
+///
+/// シェルの変数はセッション変数に持ち、システム環境変数を変更するのはエクスポートしたときだけ。
+/// 値のない変数は何もしない
+pub fn export_var(name: &str) {
+    if let Some(value) = get_var(name) {
+        unsafe {
+            std::env::set_var(name, value);
+        }
     }
+}
 
-    // セッション変数を取得
-    let session_vars = SESSION_VARS.lock().unwrap();
+/// セッション変数のみを取得（システム環境変数は参照しない）
+pub fn get_session_var(name: &str) -> Option<String> {
+    state::current().vars.lock_or_recover().get(name).cloned()
+}
 
-    for (key, value) in session_vars.iter() {
-        // 既存のシステム変数があれば上書き、なければ追加
-        if let Some(pos) = result.iter().position(|(k, _)| k == key) {
-            result[pos] = (key.clone(), value.clone());
-        } else {
-            result.push((key.clone(), value.clone()));
-        }
-    }
+/// セッション変数を削除
+pub fn unset_var(name: &str) {
+    state::current().vars.lock_or_recover().remove(name);
+}
 
-    result
+/// 変数名として有効かチェック（英字または_で始まり、英数字と_のみ）
+pub fn is_valid_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// システム環境変数とセッション変数をすべて名前順で取得
+///
+/// 同じ名前があればセッション変数の値を使う
+pub fn list_all_vars() -> Vec<(String, String)> {
+    let mut vars: BTreeMap<String, String> = if is_environment_isolated() {
+        BTreeMap::new()
+    } else {
+        std::env::vars().collect()
+    };
+    vars.extend(list_session_vars());
+    vars.into_iter().collect()
+}
+
+/// セッション変数だけを名前順で取得
+///
+/// `env VAR=value command` の実行中は上書きした値を含める（-i なら上書きした変数だけ）
+pub fn list_session_vars() -> Vec<(String, String)> {
+    let (overrides, isolated) = overridden_vars();
+    let mut vars: BTreeMap<String, String> = if isolated {
+        BTreeMap::new()
+    } else {
+        state::current()
+            .vars
+            .lock_or_recover()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    };
+    vars.extend(overrides);
+    vars.into_iter().collect()
 }
 
 /// 変数展開を行う関数
@@ -98,6 +308,14 @@ pub fn expand_variables(input: &str) -> String {
                     ans_string.push_str("${");
                     ans_string.push_str(&var_name);
                 }
+            } else if chars.peek() == Some(&'?') {
+                // 直前の終了ステータス
+                chars.next();
+                ans_string.push_str(&get_last_status().to_string());
+            } else if chars.peek() == Some(&'$') {
+                // シェルのプロセスID
+                chars.next();
+                ans_string.push_str(&std::process::id().to_string());
             } else {
                 let mut var_name = String::new();
                 while let Some(&next_char) = chars.peek() {
@@ -126,17 +344,153 @@ pub fn expand_variables(input: &str) -> String {
     ans_string
 }
 
+/// 算術展開 `$((expr))` を行う関数
+///
+/// 展開の順序は「算術展開 → 変数展開」とする。
+/// 式の中の `$VAR` は評価前に展開され、裸の変数名は評価時に解決される。
+/// コマンド置換はパース時に先に実行されるが、`$((` には手を付けない。
+///
+/// # Errors
+/// - 式の構文が不正な場合
+/// - 0で除算した場合
+pub fn expand_arithmetic(input: &str) -> Result<String> {
+    let mut ans_string = String::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find("$((") {
+        ans_string.push_str(&rest[..start]);
+        let after = &rest[start + "$((".len()..];
+
+        // 対応する "))" を探す
+        let mut depth = 0;
+        let mut end = None;
+        for (i, ch) in after.char_indices() {
+            match ch {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' => {
+                    if after[i + 1..].starts_with(')') {
+                        end = Some(i);
+                    }
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        let Some(end) = end else {
+            return Err(RucliError::ParseError(format!(
+                "arithmetic: missing '))' in '{input}'"
+            )));
+        };
+
+        let expr = expand_variables(&after[..end]);
+        ans_string.push_str(&arithmetic::evaluate(&expr)?.to_string());
+        rest = &after[end + "))".len()..];
+    }
+
+    ans_string.push_str(rest);
+    Ok(ans_string)
+}
+
 /// コマンド置換を実行する関数
+///
+/// `$(command)` とバッククォート形式の `` `command` `` の両方に対応する。
+/// バッククォート内ではネストは扱わず、`` \` ``・`\\`・`\$` のみをエスケープとして解釈する。
+/// 置換結果はそのまま埋め込む（ヒアドキュメントの本文のように語に分けない文字列用）
 pub fn expand_command_substitution(input: &str) -> Result<String> {
-    // 結果を格納する文字列
-    let mut ans_string = String::new();
+    Ok(substitute(input, Splitting::None)?.concat())
+}
+
+/// コマンドラインの一つの語のコマンド置換を実行し、引数の一覧にする
+///
+/// クォートの中の置換結果は改行も含めてそのまま語の一部にする。
+/// クォートの外の置換結果は空白（改行を含む）で区切り、それぞれ別の引数にする。
+/// 置換結果は構文として解釈しないので、`|` や `>` や `;` もそのまま引数になる
+///
+/// 例: `"$(seq 2)"` → [`"1\n2"`]、`a$(seq 2)` → [`a1`, `2`]
+pub fn expand_word_substitution(word: &str) -> Result<Vec<String>> {
+    substitute(word, Splitting::Unquoted)
+}
+
+/// コマンド置換の結果の埋め込み方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Splitting {
+    /// 語に分けずにそのまま埋め込む
+    None,
+    /// クォートの外の結果を語に分ける
+    Unquoted,
+}
+
+/// コマンド置換の結果を埋め込みながら組み立てる語の一覧
+#[derive(Default)]
+struct Fields {
+    done: Vec<String>,
+    current: String, // 組み立て中の語
+}
+
+impl Fields {
+    /// 組み立て中の語を区切る（空の語は作らない）
+    fn split(&mut self) {
+        if !self.current.is_empty() {
+            self.done.push(std::mem::take(&mut self.current));
+        }
+    }
+
+    /// 置換結果を埋め込む（`split` なら空白ごとに語を区切る）
+    fn embed(&mut self, output: &str, split: bool) {
+        if !split {
+            self.current.push_str(output);
+            return;
+        }
+
+        if output.starts_with(char::is_whitespace) {
+            self.split();
+        }
+        for (i, word) in output.split_whitespace().enumerate() {
+            if i > 0 {
+                self.split();
+            }
+            self.current.push_str(word);
+        }
+        if output.ends_with(char::is_whitespace) {
+            self.split();
+        }
+    }
+}
+
+/// コマンド置換を実行する（結果は `splitting` のとおりに埋め込む）
+///
+/// # Returns
+/// * 置換後の語の一覧（`Splitting::None` なら常に一つ）
+fn substitute(input: &str, splitting: Splitting) -> Result<Vec<String>> {
+    if SUBSTITUTION_DISABLED.with(|disabled| disabled.get()) {
+        return Ok(vec![input.to_string()]);
+    }
+
+    let mut fields = Fields::default();
     let mut chars = input.chars().peekable();
+    // 置換の外で開いているクォート
+    let mut quote: Option<char> = None;
 
     // 文字列をスキャンして$以降の単語を置換
     while let Some(ch) = chars.next() {
-        if ch == '$' {
+        let split = splitting == Splitting::Unquoted && quote.is_none();
+
+        if matches!(ch, '"' | '\'') && quote.is_none_or(|q| q == ch) {
+            quote = if quote.is_some() { None } else { Some(ch) };
+            fields.current.push(ch);
+        } else if ch == '\\' && quote != Some('\'') && chars.peek() == Some(&'"') {
+            // エスケープされたダブルクォートはクォートの開始・終了にしない
+            fields.current.push(ch);
+            fields.current.push(chars.next().unwrap());
+        } else if ch == '$' {
+            // 算術展開 $(( は実行時に処理するのでそのまま残す
+            if chars.peek() == Some(&'(') && chars.clone().nth(1) == Some('(') {
+                fields.current.push(ch);
+            }
             // 次の文字が(かチェック
-            if chars.peek() == Some(&'(') {
+            else if chars.peek() == Some(&'(') {
                 // (を消費
                 chars.next();
 
@@ -161,30 +515,10 @@ pub fn expand_command_substitution(input: &str) -> Result<String> {
                     }
                 }
 
-                // 変数名が取得できた場合は置換
+                // 変数名が取得できた場合は置換（内側の置換は中のコマンドをパースするときに行う）
                 if found_closing_brace && !cmd_string.is_empty() {
-                    // 再帰的に内部のコマンド置換を実行
-                    let inner_expanded = expand_command_substitution(&cmd_string)?;
-
-                    match parse_command(&inner_expanded) {
-                        Ok(cmd) => {
-                            match execute_command_internal(cmd, None) {
-                                Ok(CommandResult::Continue(output)) => {
-                                    // 末尾の改行を削除
-                                    ans_string.push_str(output.trim_end());
-                                }
-                                Ok(CommandResult::Exit) => {
-                                    // コマンド置換内でのExitは無視
-                                }
-                                Err(_) => {
-                                    // エラーなのでなにもしない
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            // パースエラーなのでなにもしない
-                        }
-                    }
+                    let output = nested("command substitution", || run_substitution(&cmd_string))?;
+                    fields.embed(&output, split);
                 }
                 // 空文字列はそのまま出力
                 else if found_closing_brace && cmd_string.is_empty() {
@@ -192,26 +526,104 @@ pub fn expand_command_substitution(input: &str) -> Result<String> {
                 }
                 // 閉じカッコが存在しない場合も元の文字列をそのまま出力
                 else {
-                    ans_string.push_str("$(");
-                    ans_string.push_str(&cmd_string);
+                    fields.current.push_str("$(");
+                    fields.current.push_str(&cmd_string);
                 }
             }
             // $だけなのでそのまま残しておく
             else {
-                ans_string.push(ch);
+                fields.current.push(ch);
+            }
+        } else if ch == '\\' && chars.peek() == Some(&'`') {
+            // エスケープされたバッククォートは文字として扱う
+            fields.current.push(chars.next().unwrap());
+        } else if ch == '`' {
+            let mut cmd_string = String::new();
+            let mut found_closing_quote = false;
+
+            // 次のエスケープされていない`まで読み取る
+            while let Some(next_ch) = chars.next() {
+                match next_ch {
+                    '`' => {
+                        found_closing_quote = true;
+                        break;
+                    }
+                    '\\' => match chars.peek() {
+                        Some(&escaped @ ('`' | '\\' | '$')) => {
+                            chars.next();
+                            cmd_string.push(escaped);
+                        }
+                        _ => cmd_string.push(next_ch),
+                    },
+                    _ => cmd_string.push(next_ch),
+                }
+            }
+
+            if found_closing_quote {
+                if !cmd_string.trim().is_empty() {
+                    let output = nested("command substitution", || run_substitution(&cmd_string))?;
+                    fields.embed(&output, split);
+                }
+            }
+            // 閉じていない場合は元の文字列をそのまま出力
+            else {
+                fields.current.push('`');
+                fields.current.push_str(&cmd_string);
             }
         } else {
-            ans_string.push(ch);
+            fields.current.push(ch);
         }
     }
 
-    Ok(ans_string)
+    // 語に分けない場合は空でも一つの文字列にする
+    if splitting == Splitting::None {
+        return Ok(vec![fields.current]);
+    }
+    fields.split();
+    Ok(fields.done)
+}
+
+/// 置換対象のコマンドを実行し、その出力を返す
+///
+/// 出力はテキストにする（UTF-8でないバイトは U+FFFD にする）。
+/// 失敗した場合はエラーを表示し、`$?` に非ゼロのステータスを設定して空文字列を返す
+///
+/// # Errors
+/// - 入れ子が深すぎる場合（表示せずに呼び出し元へ伝える）
+fn run_substitution(cmd_string: &str) -> Result<String> {
+    // 置換するコマンドは、中の置換をパース時に行う
+    let result = with_substitution_deferred(false, || {
+        parse_command(cmd_string).and_then(|cmd| execute_command_collecting(cmd, None))
+    });
+
+    match result {
+        // コマンド置換内でのExitは置換だけを終え、それまでの出力を使う
+        Ok(CommandResult::Continue(output) | CommandResult::Exit(output)) => {
+            // 末尾の改行をすべて削除（空白は残す）
+            Ok(String::from_utf8_lossy(&output)
+                .trim_end_matches('\n')
+                .to_string())
+        }
+        Err(RucliError::CommandFailed { output, status }) => {
+            // 出力は置換結果として使い、ステータスだけ記録する
+            set_last_status(status);
+            Ok(String::from_utf8_lossy(&output)
+                .trim_end_matches('\n')
+                .to_string())
+        }
+        Err(e @ RucliError::NestingTooDeep { .. }) => Err(e),
+        Err(e) => {
+            crate::output::eprint_line(&e.to_string());
+            set_last_status(e.exit_code());
+            Ok(String::new())
+        }
+    }
 }
 
 #[cfg(test)]
 mod environment_tests {
     use super::*;
-    use crate::commands::{Command, EnvironmentAction};
+    use crate::commands::{Command, EnvironmentAction, OutputFormat};
     use crate::environment::{expand_variables, set_var};
     use crate::handlers::handle_environment;
     use crate::parser::parse_command;
@@ -223,24 +635,59 @@ mod environment_tests {
     #[test]
     fn test_env_command_list_all() {
         // When: env コマンドを引数なしで実行
-        let result = handle_environment(EnvironmentAction::List).unwrap();
+        let result = handle_environment(
+            EnvironmentAction::List {
+                pattern: None,
+                session_only: false,
+            },
+            OutputFormat::Text,
+        )
+        .unwrap();
 
         // Then: システム環境変数が表示される
         assert!(result.contains("PATH="));
         assert!(!result.is_empty());
     }
 
+    #[test]
+    fn test_env_list_is_sorted_and_filtered() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+        set_var("LIST_TEST_B", "2");
+        set_var("LIST_TEST_A", "1");
+        set_var("OTHER_LIST_TEST", "3");
+
+        // When: パターンを付けて一覧表示
+        let result = handle_environment(
+            EnvironmentAction::List {
+                pattern: Some("LIST_TEST_*".to_string()),
+                session_only: true,
+            },
+            OutputFormat::Text,
+        )
+        .unwrap();
+
+        // Then: 一致する名前だけが名前順に表示される
+        assert_eq!(result, "LIST_TEST_A=1\nLIST_TEST_B=2");
+
+        let names: Vec<String> = list_all_vars().into_iter().map(|(name, _)| name).collect();
+        assert!(names.is_sorted());
+    }
+
     #[test]
     fn test_env_set_and_get() {
         // Given: 新しい環境変数を設定
-        handle_environment(EnvironmentAction::Set(
-            "TEST_VAR".to_string(),
-            "test_value".to_string(),
-        ))
+        handle_environment(
+            EnvironmentAction::Set("TEST_VAR".to_string(), "test_value".to_string()),
+            OutputFormat::Text,
+        )
         .unwrap();
 
         // When: その変数を取得
-        let result = handle_environment(EnvironmentAction::Show("TEST_VAR".to_string())).unwrap();
+        let result = handle_environment(
+            EnvironmentAction::Show("TEST_VAR".to_string()),
+            OutputFormat::Text,
+        )
+        .unwrap();
 
         // Then: 設定した値が返される
         assert_eq!(result.trim(), "test_value");
@@ -249,29 +696,35 @@ mod environment_tests {
     #[test]
     fn test_env_set_overwrites_existing() {
         // Given: 変数を設定
-        handle_environment(EnvironmentAction::Set(
-            "OVERWRITE_TEST".to_string(),
-            "original".to_string(),
-        ))
+        handle_environment(
+            EnvironmentAction::Set("OVERWRITE_TEST".to_string(), "original".to_string()),
+            OutputFormat::Text,
+        )
         .unwrap();
 
         // When: 同じ変数に別の値を設定
-        handle_environment(EnvironmentAction::Set(
-            "OVERWRITE_TEST".to_string(),
-            "updated".to_string(),
-        ))
+        handle_environment(
+            EnvironmentAction::Set("OVERWRITE_TEST".to_string(), "updated".to_string()),
+            OutputFormat::Text,
+        )
         .unwrap();
 
         // Then: 新しい値で上書きされる
-        let result =
-            handle_environment(EnvironmentAction::Show("OVERWRITE_TEST".to_string())).unwrap();
+        let result = handle_environment(
+            EnvironmentAction::Show("OVERWRITE_TEST".to_string()),
+            OutputFormat::Text,
+        )
+        .unwrap();
         assert_eq!(result.trim(), "updated");
     }
 
     #[test]
     fn test_env_show_nonexistent_variable() {
         // When: 存在しない変数を表示しようとする
-        let result = handle_environment(EnvironmentAction::Show("NONEXISTENT_VAR".to_string()));
+        let result = handle_environment(
+            EnvironmentAction::Show("NONEXISTENT_VAR".to_string()),
+            OutputFormat::Text,
+        );
 
         // Then: エラーが返される
         assert!(result.is_err());
@@ -292,7 +745,11 @@ mod environment_tests {
         assert!(matches!(
             cmd,
             Command::Environment {
-                action: EnvironmentAction::List
+                action: EnvironmentAction::List {
+                    pattern: None,
+                    session_only: false,
+                },
+                format: OutputFormat::Text,
             }
         ));
     }
@@ -304,7 +761,8 @@ mod environment_tests {
 
         // Then: Environment::Set コマンドが生成される
         assert!(matches!(cmd, Command::Environment { 
-            action: EnvironmentAction::Set(var, val) 
+            action: EnvironmentAction::Set(var, val), 
+            format: OutputFormat::Text,
         } if var == "TEST_VAR" && val == "test_value"));
     }
 
@@ -315,7 +773,8 @@ mod environment_tests {
 
         // Then: Environment::Show コマンドが生成される
         assert!(matches!(cmd, Command::Environment { 
-            action: EnvironmentAction::Show(var) 
+            action: EnvironmentAction::Show(var), 
+            format: OutputFormat::Text,
         } if var == "PATH"));
     }
 
@@ -375,10 +834,44 @@ mod environment_tests {
     fn test_dollar_edge_cases() {
         // When/Then: $ のエッジケース
         assert_eq!(expand_variables("Price $100"), "Price "); // 数字始まり変数は無効→空文字列
-        assert_eq!(expand_variables("$$"), "$$"); // 連続$
         assert_eq!(expand_variables("$"), "$"); // 単独$
     }
 
+    #[test]
+    fn test_process_id_expansion() {
+        // When/Then: $$ は数値で、同じシェルの中では変わらない
+        let pid = expand_variables("/tmp/work.$$");
+        assert_eq!(pid, format!("/tmp/work.{}", std::process::id()));
+        assert_eq!(expand_variables("/tmp/work.$$"), pid);
+
+        let ppid = expand_variables("$PPID");
+        assert!(ppid.parse::<u32>().is_ok());
+        assert_eq!(expand_variables("${PPID}"), ppid);
+    }
+
+    #[test]
+    fn test_random_expansion_varies() {
+        let values: Vec<u32> = (0..10)
+            .map(|_| expand_variables("$RANDOM").parse().unwrap())
+            .collect();
+
+        assert!(values.iter().all(|value| *value < 32768));
+        assert!(values.iter().any(|value| *value != values[0]));
+    }
+
+    #[test]
+    fn test_seconds_increase_after_sleep() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+        assert_eq!(expand_variables("$SECONDS"), "0");
+
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert_eq!(expand_variables("$SECONDS"), "1");
+
+        // セッション変数で上書きできる
+        set_var("SECONDS", "100");
+        assert_eq!(expand_variables("$SECONDS"), "100");
+    }
+
     #[test]
     fn test_system_variable_expansion() {
         // When/Then: システム環境変数の展開
@@ -399,11 +892,11 @@ mod environment_tests {
         let cmd = parse_command("cat $FILENAME").unwrap();
 
         // パース時点では変数展開されない
-        assert!(matches!(cmd.clone(), Command::Cat { filename } if filename == "$FILENAME"));
+        assert!(matches!(cmd.clone(), Command::Cat { filename, .. } if filename == "$FILENAME"));
 
         // expand_variablesメソッドで展開
-        let expanded_cmd = cmd.expand_variables();
-        assert!(matches!(expanded_cmd, Command::Cat { filename } if filename == "test.txt"));
+        let expanded_cmd = cmd.expand_variables().unwrap();
+        assert!(matches!(expanded_cmd, Command::Cat { filename, .. } if filename == "test.txt"));
     }
 
     #[test]
@@ -425,7 +918,7 @@ mod environment_tests {
         }
 
         // expand_variablesメソッドで展開
-        let expanded_cmd = cmd.expand_variables();
+        let expanded_cmd = cmd.expand_variables().unwrap();
         assert!(matches!(expanded_cmd, Command::Write { filename, content } 
             if filename == "output.txt" && content == "Hello File"));
     }
@@ -439,12 +932,13 @@ mod environment_tests {
         // When: パイプラインで変数展開
         let cmd = parse_command("cat $LOGFILE | grep $PATTERN").unwrap();
 
-        // Then: パイプライン内の変数はまだ展開されていない（文字列のまま）
+        // Then: パイプライン内の変数はまだ展開されていない（各段の実行時に展開する）
         if let Command::Pipeline { commands } = cmd {
             assert_eq!(commands.len(), 2);
-            // パイプラインのコマンドは文字列として保持
-            assert!(commands[0].contains("$LOGFILE"));
-            assert!(commands[1].contains("$PATTERN"));
+            assert!(
+                matches!(&commands[0], Command::Cat { filename, .. } if filename == "$LOGFILE")
+            );
+            assert!(matches!(&commands[1], Command::Grep { pattern, .. } if pattern == "$PATTERN"));
         } else {
             panic!("Expected pipeline command");
         }
@@ -460,17 +954,12 @@ mod environment_tests {
         let cmd = parse_command("cat $INPUT > $OUTPUT").unwrap();
 
         // Then: パース時点では展開されていない
-        if let Command::Redirect {
-            command,
-            redirect_type,
-            target,
-        } = cmd
-        {
-            assert_eq!(redirect_type, ">");
-            assert_eq!(target, "$OUTPUT");
+        if let Command::Redirect { command, redirects } = cmd {
+            assert_eq!(redirects[0].redirect_type, ">");
+            assert_eq!(redirects[0].target, "$OUTPUT");
 
             match *command {
-                Command::Cat { filename } => {
+                Command::Cat { filename, .. } => {
                     assert_eq!(filename, "$INPUT");
                 }
                 _ => panic!("Expected Cat command"),
@@ -702,6 +1191,48 @@ mod environment_tests {
         assert_eq!(result, r#"Message: "hello world""#);
     }
 
+    #[test]
+    fn test_word_substitution_splits_only_unquoted_output() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+
+        assert_eq!(
+            expand_word_substitution(r#""$(seq 2)""#).unwrap(),
+            ["\"1\n2\""]
+        );
+        assert_eq!(
+            expand_word_substitution("a$(seq 2)b").unwrap(),
+            ["a1", "2b"]
+        );
+        assert_eq!(
+            expand_word_substitution(r#"f"$(seq 2)"$(seq 2)"#).unwrap(),
+            ["f\"1\n2\"1", "2"]
+        );
+        // エスケープされたクォートではクォートの中にならない
+        assert_eq!(
+            expand_word_substitution(r#"\"$(seq 2)\""#).unwrap(),
+            [r#"\"1"#, r#"2\""#]
+        );
+        // 空の結果だけの語はなくなる
+        assert!(expand_word_substitution("$(true)").unwrap().is_empty());
+        // ヒアドキュメントなどの置換は結果をそのまま埋め込む
+        assert_eq!(expand_command_substitution("$(seq 2)").unwrap(), "1\n2");
+    }
+
+    #[test]
+    fn test_word_substitution_output_is_not_syntax() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("ops.txt");
+        std::fs::write(&file, "a | b > c; d").unwrap();
+
+        // 演算子を含む結果もただの語になる
+        assert_eq!(
+            expand_word_substitution(&format!("$(cat {})", file.display())).unwrap(),
+            ["a", "|", "b", ">", "c;", "d"]
+        );
+    }
+
     #[test]
     fn test_command_substitution_with_variables() {
         // Given: 変数を含むコマンド
@@ -767,6 +1298,104 @@ mod environment_tests {
         assert_eq!(result, "Hello World!");
     }
 
+    #[test]
+    fn test_backtick_command_substitution() {
+        // Given: バッククォート形式のコマンド置換
+        let input = "result: `pwd`";
+
+        // When: 置換実行
+        let result = expand_command_substitution(input).unwrap();
+
+        // Then: pwdの出力に置換される
+        assert!(result.starts_with("result: /"));
+        assert!(!result.contains('`'));
+    }
+
+    #[test]
+    fn test_backtick_escapes() {
+        // エスケープされたバッククォートは置換されない
+        assert_eq!(
+            expand_command_substitution(r"echo \`pwd\`").unwrap(),
+            "echo `pwd`"
+        );
+
+        // バッククォート内のエスケープは解除されてから実行される
+        assert_eq!(
+            expand_command_substitution(r"`echo \$HOME`").unwrap(),
+            expand_variables("$HOME")
+        );
+
+        // 閉じていないバッククォートはそのまま
+        assert_eq!(
+            expand_command_substitution("echo `pwd").unwrap(),
+            "echo `pwd"
+        );
+    }
+
+    #[test]
+    fn test_command_substitution_captures_write_message() {
+        // Given: 一時ディレクトリ内のファイル
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("f.txt");
+        let input = format!("$(write {} x)", path.display());
+
+        // When: writeコマンドを置換
+        let result = expand_command_substitution(&input).unwrap();
+
+        // Then: メッセージが出力として取り込まれる
+        assert_eq!(
+            result,
+            format!("File written successfully: {}", path.display())
+        );
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_failed_command_substitution_sets_status() {
+        // Given: 成功ステータスから開始
+        set_last_status(0);
+
+        // When: 失敗するコマンドを置換
+        expand_command_substitution("$(nonexistent_command)").unwrap();
+
+        // Then: $? が非ゼロになる
+        assert_ne!(get_last_status(), 0);
+        assert_ne!(expand_variables("$?"), "0");
+    }
+
+    #[test]
+    fn test_arithmetic_expansion() {
+        // Given: 変数を設定
+        set_var("COUNT", "4");
+
+        // When/Then: 算術展開
+        assert_eq!(expand_arithmetic("$((1 + 2))").unwrap(), "3");
+        assert_eq!(expand_arithmetic("n=$((COUNT+1))").unwrap(), "n=5");
+        assert_eq!(expand_arithmetic("$(($COUNT * (2 + 1)))").unwrap(), "12");
+        assert_eq!(
+            expand_arithmetic("$((COUNT > 3)) $((COUNT == 3))").unwrap(),
+            "1 0"
+        );
+        assert_eq!(expand_arithmetic("no expansion").unwrap(), "no expansion");
+    }
+
+    #[test]
+    fn test_arithmetic_expansion_errors() {
+        // 0除算はエラー
+        assert!(expand_arithmetic("$((1 / 0))").is_err());
+        // 閉じ括弧なしはエラー
+        assert!(expand_arithmetic("$((1 + 2").is_err());
+    }
+
+    #[test]
+    fn test_command_substitution_skips_arithmetic() {
+        // コマンド置換では $(( をそのまま残す
+        assert_eq!(
+            expand_command_substitution("$((1 + 2))").unwrap(),
+            "$((1 + 2))"
+        );
+    }
+
     #[test]
     fn test_expand_variables_method() {
         // Given: 環境変数を設定
@@ -776,16 +1405,21 @@ mod environment_tests {
         // Catコマンドのテスト
         let cat_cmd = Command::Cat {
             filename: "$FILE".to_string(),
+            number: false,
+            show_nonprinting: false,
+            show_ends: false,
+            show_tabs: false,
         };
-        let expanded_cat = cat_cmd.expand_variables();
-        assert!(matches!(expanded_cat, Command::Cat { filename } if filename == "test.txt"));
+        let expanded_cat = cat_cmd.expand_variables().unwrap();
+        assert!(matches!(expanded_cat, Command::Cat { filename, .. } if filename == "test.txt"));
 
         // Echoコマンドのテスト
         let echo_cmd = Command::Echo {
             message: "$MSG".to_string(),
+            newline: true,
         };
-        let expanded_echo = echo_cmd.expand_variables();
-        assert!(matches!(expanded_echo, Command::Echo { message } if message == "Hello World"));
+        let expanded_echo = echo_cmd.expand_variables().unwrap();
+        assert!(matches!(expanded_echo, Command::Echo { message, .. } if message == "Hello World"));
 
         // 複数変数のテスト
         unsafe {
@@ -795,7 +1429,7 @@ mod environment_tests {
             filename: "$FILE".to_string(),
             content: "$MSG from $USER".to_string(),
         };
-        let expanded_write = write_cmd.expand_variables();
+        let expanded_write = write_cmd.expand_variables().unwrap();
         match expanded_write {
             Command::Write { filename, content } => {
                 assert_eq!(filename, "test.txt");
@@ -807,4 +1441,115 @@ mod environment_tests {
             std::env::remove_var("USER");
         }
     }
+
+    fn run(input: &str) -> String {
+        match execute_command_collecting(parse_command(input).unwrap(), None).unwrap() {
+            CommandResult::Continue(output) => String::from_utf8(output).unwrap(),
+            CommandResult::Exit(_) => panic!("unexpected exit"),
+        }
+    }
+
+    #[test]
+    fn test_substitution_in_loop_sees_loop_variable() {
+        let _state = state::enter(std::sync::Arc::new(state::ShellState::new()));
+
+        assert_eq!(run("for i in a b; do echo $(echo x$i); done"), "xa\nxb\n");
+        assert_eq!(
+            run("for i in 1 2; do if echo ok; then echo `echo n$i`; fi; done"),
+            "ok\nn1\nok\nn2\n"
+        );
+    }
+
+    #[test]
+    fn test_self_referential_substitution_stops_at_depth_limit() {
+        let _state = state::enter(std::sync::Arc::new(state::ShellState::new()));
+
+        run("function rucli_412_f() { echo $(rucli_412_f); }");
+        let Err(err) = execute_command_collecting(parse_command("rucli_412_f").unwrap(), None)
+        else {
+            panic!("recursion should hit the depth limit");
+        };
+        assert!(matches!(
+            err,
+            RucliError::NestingTooDeep {
+                limit: crate::nesting::DEFAULT_MAX_DEPTH,
+                ..
+            }
+        ));
+
+        // 上限は変数で変えられ、展開が終わればまた同じ深さまで使える
+        set_var("RUCLI_MAX_DEPTH", "4");
+        let Err(err) = execute_command_collecting(parse_command("rucli_412_f").unwrap(), None)
+        else {
+            panic!("recursion should hit the depth limit");
+        };
+        assert_eq!(
+            err.to_string(),
+            "function 'rucli_412_f' nested too deeply (limit 4)"
+        );
+        assert_eq!(run("echo $(echo $(echo deep))"), "deep\n");
+        assert_eq!(
+            expand_command_substitution("$(echo $(echo $(echo $(echo $(echo x)))))")
+                .unwrap_err()
+                .to_string(),
+            "command substitution nested too deeply (limit 4)"
+        );
+    }
+
+    #[test]
+    fn test_shell_variables_stay_out_of_process_environment() {
+        let _state = state::enter(std::sync::Arc::new(state::ShellState::new()));
+
+        run("RUCLI_402_LOCAL=session");
+        run("for RUCLI_402_ITEM in x; do echo $RUCLI_402_ITEM; done");
+        run("function rucli_402_f() { echo $1; }");
+        assert_eq!(run("rucli_402_f arg | grep arg"), "arg\n");
+        assert_eq!(run("echo one | echo $RUCLI_402_LOCAL"), "session\n");
+
+        let leaked: Vec<String> = std::env::vars()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with("RUCLI_402") || name == "1")
+            .collect();
+        assert!(leaked.is_empty(), "leaked into std::env: {leaked:?}");
+
+        run("export RUCLI_402_LOCAL RUCLI_402_NEW=exported");
+        assert_eq!(std::env::var("RUCLI_402_LOCAL").unwrap(), "session");
+        assert_eq!(std::env::var("RUCLI_402_NEW").unwrap(), "exported");
+        unsafe {
+            std::env::remove_var("RUCLI_402_LOCAL");
+            std::env::remove_var("RUCLI_402_NEW");
+        }
+    }
+
+    #[test]
+    fn test_overrides_are_scoped_to_the_command() {
+        let _state = state::enter(std::sync::Arc::new(state::ShellState::new()));
+        set_var("RUCLI_431_KEPT", "session");
+        let overrides = [("RUCLI_431_TEMP".to_string(), "temp".to_string())];
+
+        with_environment(false, &overrides, || {
+            assert_eq!(get_var("RUCLI_431_TEMP").as_deref(), Some("temp"));
+            assert_eq!(get_var("RUCLI_431_KEPT").as_deref(), Some("session"));
+            assert!(!is_environment_isolated());
+        });
+        assert_eq!(get_var("RUCLI_431_TEMP"), None);
+        assert_eq!(get_session_var("RUCLI_431_TEMP"), None);
+
+        // -i では上書きした変数だけが見え、内側の層は外側の上書きも隠す
+        with_environment(false, &overrides, || {
+            let inner = [("RUCLI_431_INNER".to_string(), "inner".to_string())];
+            with_environment(true, &inner, || {
+                assert!(is_environment_isolated());
+                assert_eq!(get_var("RUCLI_431_KEPT"), None);
+                assert_eq!(get_var("RUCLI_431_TEMP"), None);
+                assert_eq!(get_var("PATH"), None);
+                assert_eq!(
+                    list_all_vars(),
+                    [("RUCLI_431_INNER".to_string(), "inner".to_string())]
+                );
+            });
+        });
+        assert!(!is_environment_isolated());
+        assert_eq!(get_var("RUCLI_431_KEPT").as_deref(), Some("session"));
+    }
 }
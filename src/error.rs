@@ -20,11 +20,40 @@ pub enum RucliError {
     /// 存在しないコマンドが入力された場合のエラー
     UnknownCommand(String),
 
+    /// 権限不足で操作できない場合のエラー
+    PermissionDenied(String),
+
     /// 無効な正規表現パターン
     InvalidRegex(String),
 
     // ランタイムエラー
     RuntimeError(String),
+
+    /// 中断の要求（フォアグラウンドの中断やジョブの停止）で打ち切られた場合
+    Interrupted,
+
+    /// 出力はあるが、失敗のステータスで終了したコマンド（diffで差分があった場合など）
+    ///
+    /// 出力はエラーメッセージではなく通常の出力として扱う
+    CommandFailed {
+        output: Vec<u8>,
+        status: i32,
+    },
+
+    /// パイプラインのステージで発生したエラー（何番目のどのコマンドで失敗したか）
+    PipelineStage {
+        stage: usize, // 1から数えたステージの番号
+        command: String,
+        source: Box<RucliError>,
+    },
+
+    /// コマンド置換・エイリアス展開・関数呼び出しの入れ子が深すぎる場合
+    ///
+    /// 途中のコマンド置換で握りつぶさず、一番外側まで伝える
+    NestingTooDeep {
+        what: String, // 入れ子にしていた処理（"command substitution" など）
+        limit: usize,
+    },
     // その他のエラー
     // Other(String),
 }
@@ -36,16 +65,57 @@ impl fmt::Display for RucliError {
             RucliError::IoError(err) => write!(f, "IO error: {err}"),
             RucliError::InvalidArgument(msg) => write!(f, "argument error: {msg}"),
             RucliError::UnknownCommand(msg) => write!(f, "unknown command error: {msg}"),
+            RucliError::PermissionDenied(msg) => write!(f, "permission denied: {msg}"),
             RucliError::InvalidRegex(msg) => write!(f, "Invalid syntax error: {msg}"),
             RucliError::RuntimeError(msg) => write!(f, "runtime error: {msg}"),
-            // RucliError::Other(msg) => write!(f, "Other error: {}", msg),
+            RucliError::Interrupted => write!(f, "interrupted"),
+            RucliError::CommandFailed { status, .. } => {
+                write!(f, "command exited with status {status}")
+            }
+            RucliError::PipelineStage {
+                stage,
+                command,
+                source,
+            } => write!(f, "pipeline stage {stage} ('{command}'): {source}"),
+            RucliError::NestingTooDeep { what, limit } => {
+                write!(f, "{what} nested too deeply (limit {limit})")
+            } // RucliError::Other(msg) => write!(f, "Other error: {}", msg),
+        }
+    }
+}
+
+impl RucliError {
+    /// エラーの種類に応じた終了ステータスを返す
+    ///
+    /// - 存在しないコマンド → 127
+    /// - 権限不足 → 126
+    /// - パースエラー・引数エラー → 2
+    /// - 中断 → 130
+    /// - CommandFailed → コマンドが返したステータス
+    /// - PipelineStage → 元のエラーのステータス
+    /// - その他 → 1
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RucliError::UnknownCommand(_) => 127,
+            RucliError::PermissionDenied(_) => 126,
+            RucliError::ParseError(_) | RucliError::InvalidArgument(_) => 2,
+            RucliError::Interrupted => 130,
+            RucliError::CommandFailed { status, .. } => *status,
+            RucliError::PipelineStage { source, .. } => source.exit_code(),
+            RucliError::IoError(_)
+            | RucliError::InvalidRegex(_)
+            | RucliError::RuntimeError(_)
+            | RucliError::NestingTooDeep { .. } => 1,
         }
     }
 }
 
 impl From<io::Error> for RucliError {
     fn from(error: io::Error) -> Self {
-        RucliError::IoError(error)
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => RucliError::PermissionDenied(error.to_string()),
+            _ => RucliError::IoError(error),
+        }
     }
 }
 
@@ -53,3 +123,32 @@ impl std::error::Error for RucliError {}
 
 /// Result型のエイリアス
 pub type Result<T> = std::result::Result<T, RucliError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_by_category() {
+        assert_eq!(
+            RucliError::UnknownCommand("foo".to_string()).exit_code(),
+            127
+        );
+        assert_eq!(RucliError::ParseError("bad".to_string()).exit_code(), 2);
+        assert_eq!(RucliError::RuntimeError("oops".to_string()).exit_code(), 1);
+
+        let not_found: RucliError = io::Error::from(io::ErrorKind::NotFound).into();
+        assert!(matches!(not_found, RucliError::IoError(_)));
+        assert_eq!(not_found.exit_code(), 1);
+
+        let denied: RucliError = io::Error::from(io::ErrorKind::PermissionDenied).into();
+        assert!(matches!(denied, RucliError::PermissionDenied(_)));
+        assert_eq!(denied.exit_code(), 126);
+
+        let failed = RucliError::CommandFailed {
+            output: Vec::new(),
+            status: 3,
+        };
+        assert_eq!(failed.exit_code(), 3);
+    }
+}
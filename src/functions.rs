@@ -1,10 +1,5 @@
-use std::{collections::HashMap, sync::Mutex};
-
-use once_cell::sync::Lazy;
-
 use crate::commands::Command;
-
-static FUNCTIONS: Lazy<Mutex<HashMap<String, Command>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+use crate::state::{self, LockExt};
 
 /// シェル関数を定義（保存）する
 ///
@@ -13,10 +8,11 @@ static FUNCTIONS: Lazy<Mutex<HashMap<String, Command>>> = Lazy::new(|| Mutex::ne
 /// * `body` - 関数の本体となるコマンド
 ///
 pub fn define_function(name: &str, body: Command) {
-    // FUNCTIONSのロックを取得
-    let mut functions = FUNCTIONS.lock().unwrap();
+    // 現在のシェルの関数テーブルのロックを取得
+    let state = state::current();
+    let mut functions = state.functions.lock_or_recover();
 
-    // FUNCTIONSに登録
+    // 関数テーブルに登録
     functions.insert(name.to_string(), body);
 }
 
@@ -30,8 +26,9 @@ pub fn define_function(name: &str, body: Command) {
 /// * `None` - 関数が存在しない場合
 ///
 pub fn get_function(name: &str) -> Option<Command> {
-    // FUNCTIONSのロックを取得
-    let functions = FUNCTIONS.lock().unwrap();
+    // 現在のシェルの関数テーブルのロックを取得
+    let state = state::current();
+    let functions = state.functions.lock_or_recover();
 
     functions.get(name).cloned()
 }
@@ -46,13 +43,24 @@ pub fn get_function(name: &str) -> Option<Command> {
 /// * `false` - 関数が存在しない
 ///
 pub fn is_function(name: &str) -> bool {
-    // FUNCTIONSのロックを取得
-    let functions = FUNCTIONS.lock().unwrap();
+    // 現在のシェルの関数テーブルのロックを取得
+    let state = state::current();
+    let functions = state.functions.lock_or_recover();
 
     // キーを所持しているか
     functions.contains_key(name)
 }
 
+/// 定義済みの関数名の一覧を名前順で取得する
+pub fn list_functions() -> Vec<String> {
+    let state = state::current();
+    let functions = state.functions.lock_or_recover();
+
+    let mut names: Vec<String> = functions.keys().cloned().collect();
+    names.sort();
+    names
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,6 +71,7 @@ mod tests {
         // Given: echoコマンドを本体とする関数
         let body = Command::Echo {
             message: "Hello from function!".to_string(),
+            newline: true,
         };
 
         // When: 関数を定義
@@ -79,6 +88,7 @@ mod tests {
         // Given: 関数を定義
         let body = Command::Echo {
             message: "test".to_string(),
+            newline: true,
         };
         define_function("exists", body);
 
@@ -92,23 +102,37 @@ mod tests {
         // Given: 最初の定義
         let body1 = Command::Echo {
             message: "First".to_string(),
+            newline: true,
         };
         define_function("overwrite_test", body1);
 
         // When: 同じ名前で再定義
         let body2 = Command::Echo {
             message: "Second".to_string(),
+            newline: true,
         };
         define_function("overwrite_test", body2);
 
         // Then: 新しい定義で上書きされる
         let retrieved = get_function("overwrite_test").unwrap();
         match retrieved {
-            Command::Echo { message } => assert_eq!(message, "Second"),
+            Command::Echo { message, .. } => assert_eq!(message, "Second"),
             _ => panic!("Expected Echo command"),
         }
     }
 
+    #[test]
+    fn test_list_functions_is_sorted() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+        for name in ["zeta", "alpha", "Mid", "beta"] {
+            define_function(name, Command::Pwd);
+        }
+
+        // 名前のバイト順（大文字が先）で、何度取得しても同じ順序
+        assert_eq!(list_functions(), ["Mid", "alpha", "beta", "zeta"]);
+        assert_eq!(list_functions(), list_functions());
+    }
+
     #[test]
     fn test_get_nonexistent_function() {
         // When: 存在しない関数を取得
@@ -122,7 +146,18 @@ mod tests {
     fn test_complex_function_body() {
         // Given: パイプラインを含む複雑なコマンド
         let body = Command::Pipeline {
-            commands: vec!["echo hello".to_string(), "grep h".to_string()],
+            commands: vec![
+                Command::Echo {
+                    message: "hello".to_string(),
+                    newline: true,
+                },
+                Command::Grep {
+                    pattern: "h".to_string(),
+                    files: Vec::new(),
+                    count: false,
+                    stats: false,
+                },
+            ],
         };
 
         // When: 関数として定義
@@ -1,22 +1,44 @@
 //! 各コマンドの実装を提供するモジュール
 
 use crate::alias::{list_aliases, set_alias};
-use crate::environment::{get_var, list_all_vars, set_var};
+use crate::environment::{
+    export_var, get_session_var, get_var, is_environment_isolated, list_all_vars,
+    list_session_vars, set_var, substitution_deferred, unset_var, with_environment,
+    with_substitution_deferred,
+};
 use crate::error::{Result, RucliError};
-use crate::history::{get_history_by_number, get_history_list, search_history};
-use crate::{functions, job};
+use crate::history::{
+    SEARCH_RESULT_LIMIT, clear_history, delete_history, find_fc_target, get_history_by_number,
+    get_history_list, get_recent_history, search_history, search_pattern,
+};
+use crate::nesting::nested;
+use crate::state::LockExt;
+use crate::{diff, functions, job, state, translate, walk};
 use log::{debug, info, warn};
 use regex::Regex;
-use std::io::{BufRead, BufReader};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, IsTerminal};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, mpsc};
 use std::thread;
-use std::time::Duration;
-use std::{env, fs, io, os::unix::fs::PermissionsExt, path::Path};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{
+    env, fs, io,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+};
 
 use crate::commands::{
-    COMMANDS, Command, CommandResult, EnvironmentAction, HistoryAction, execute_command,
-    execute_command_internal,
+    COMMANDS, Command, CommandResult, CutSelection, DirStackAction, EnvironmentAction, FindFilter,
+    HistoryAction, LsSort, OutputFormat, Redirection, TrapAction, execute_command_collecting,
+    execute_command_internal, execute_expanded,
 };
 use crate::parser::{DEFAULT_HOME_INDICATOR, PREVIOUS_DIR_INDICATOR};
+use crate::redirect::{redirect_process, redirect_shell};
 
 /// ファイルパーミッションのマスク値
 const PERMISSION_MASK: u32 = 0o777;
@@ -30,17 +52,61 @@ fn debug_file_metadata(metadata: &fs::Metadata) {
     );
 }
 
+/// 一覧をJSON配列の文字列に変換する
+fn to_json<T: Serialize>(items: &[T]) -> Result<String> {
+    serde_json::to_string(items)
+        .map_err(|e| RucliError::RuntimeError(format!("failed to serialize JSON: {e}")))
+}
+
+/// `ls --json` の一要素
+#[derive(Serialize)]
+struct LsEntry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: &'static str, // "file" / "directory" / "symlink" / "other"
+    size: u64,  // バイト数
+    mtime: u64, // 最終更新日時（UNIX時間の秒）
+}
+
+/// `jobs --json` の一要素
+#[derive(Serialize)]
+struct JobEntry<'a> {
+    id: u32,
+    status: String,
+    command: &'a str,
+    error: Option<&'a str>,
+}
+
+/// `env --json` の一要素
+#[derive(Serialize)]
+struct EnvEntry<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+/// `history --json` の一要素
+#[derive(Serialize)]
+struct HistoryEntry<'a> {
+    index: usize,
+    command: &'a str,
+}
+
 /// メッセージを文字列として返す
 ///
 /// # Arguments
 ///
 /// * `message` - 表示するメッセージ
+/// * `newline` - 末尾に改行を付けるか（`echo -n` ならfalse）
 ///
 /// # Returns
 ///
 /// メッセージの文字列
-pub fn handle_echo(message: &str) -> String {
-    message.to_string()
+pub fn handle_echo(message: &str, newline: bool) -> String {
+    if newline {
+        format!("{message}\n")
+    } else {
+        message.to_string()
+    }
 }
 
 /// ヘルプメッセージを表示する
@@ -72,26 +138,128 @@ pub fn handle_help() -> String {
     lines.join("\n")
 }
 
-/// 文字列をcount回表示
-pub fn handle_repeat(count: i32, message: &str) -> String {
-    let mut lines = Vec::new();
-    for _ in 0..count {
-        lines.push(message);
+/// 各行を改行で終端して連結する
+///
+/// 最後の行が空でも改行を付けるので、空行で終わる出力も行数が保たれる
+fn terminated_lines<S: AsRef<str>>(lines: &[S]) -> String {
+    lines
+        .iter()
+        .map(|line| format!("{}\n", line.as_ref()))
+        .collect()
+}
+
+/// 画面を消去してカーソルを左上に移動するエスケープシーケンス
+pub fn handle_clear() -> &'static str {
+    "\x1b[H\x1b[2J"
+}
+
+/// 文字列をcount回表示する
+///
+/// 間隔が指定された場合は一行ずつ待ちながら、その都度書き出す
+///
+/// # Arguments
+///
+/// * `count` - 繰り返す回数（0なら何も出力しない）
+/// * `message` - 表示する文字列
+/// * `delay` - 各出力の間に待つ時間
+///
+/// # Errors
+///
+/// - 待っている間に中断が要求された場合
+pub fn handle_repeat(count: i32, message: &str, delay: Option<Duration>) -> Result<String> {
+    let Some(delay) = delay else {
+        return Ok(terminated_lines(&vec![message; count.max(0) as usize]));
+    };
+
+    for i in 0..count {
+        if i > 0 {
+            handle_sleep(delay)?;
+        }
+        crate::output::print_line(message);
     }
-    lines.join("\n")
+
+    Ok(String::new())
 }
 
 /// ファイルの内容を表示する
 ///
+/// ファイル名が空か `-` で、パイプラインの入力もなければ標準入力をEOFまで読む
+///
+/// # Arguments
+/// * `number` - 各行に行番号を付ける（-n）
+/// * `show_nonprinting` - 制御文字を `^X`（DELは `^?`）と表示する（-v）
+/// * `show_ends` - 行末に `$` を表示する（-E、CRLFの行末は `^M$`）
+/// * `show_tabs` - タブを `^I` と表示する（-T）
+///
 /// # Errors
 ///
 /// - ファイルが存在しない場合
 /// - ディレクトリを指定した場合
 /// - 読み取り権限がない場合
-pub fn handle_cat(filename: &str, input: Option<&str>) -> Result<String> {
+pub fn handle_cat(
+    filename: &str,
+    number: bool,
+    show_nonprinting: bool,
+    show_ends: bool,
+    show_tabs: bool,
+    input: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    let contents = read_cat_input(filename, input)?;
+    if !(number || show_nonprinting || show_ends || show_tabs) {
+        return Ok(contents);
+    }
+
+    // オプションで表示を変えるときはテキストとして扱う
+    let contents = String::from_utf8_lossy(&contents);
+
+    // 行番号はcoreutilsと同じく変換した行の前に付ける（番号の後ろのタブは変換しない）
+    let mut output = String::with_capacity(contents.len());
+    for (index, line) in contents.split_inclusive('\n').enumerate() {
+        let (body, newline) = match line.strip_suffix('\n') {
+            Some(body) => (body, true),
+            None => (line, false),
+        };
+        let (body, crlf) = match body.strip_suffix('\r') {
+            Some(body) if newline && show_ends => (body, true),
+            _ => (body, false),
+        };
+
+        if number {
+            output.push_str(&format!("{:>6}\t", index + 1));
+        }
+        for ch in body.chars() {
+            match ch {
+                '\t' if show_tabs => output.push_str("^I"),
+                '\t' => output.push(ch),
+                '\x7f' if show_nonprinting => output.push_str("^?"),
+                ch if show_nonprinting && ch.is_ascii_control() => {
+                    output.push('^');
+                    output.push(char::from(ch as u8 + 64));
+                }
+                ch => output.push(ch),
+            }
+        }
+        if crlf {
+            output.push_str("^M");
+        }
+        if newline {
+            if show_ends {
+                output.push('$');
+            }
+            output.push('\n');
+        }
+    }
+    Ok(output.into_bytes())
+}
+
+/// catで表示する内容を読む（パイプラインの入力、標準入力、ファイルのいずれか）
+fn read_cat_input(filename: &str, input: Option<&[u8]>) -> Result<Vec<u8>> {
     // inputがある場合は標準入力として扱う
     if let Some(input_content) = input {
-        return Ok(input_content.to_string());
+        return Ok(input_content.to_vec());
+    }
+    if filename.is_empty() || filename == "-" {
+        return read_standard_input();
     }
 
     debug!("Attempting to read file: {filename}");
@@ -110,7 +278,7 @@ pub fn handle_cat(filename: &str, input: Option<&str>) -> Result<String> {
         debug_file_metadata(&metadata);
     }
 
-    let contents = fs::read_to_string(filename)?;
+    let contents = fs::read(filename)?;
 
     // ファイル読み込み成功時
     info!("Successfully read file: {filename}");
@@ -118,17 +286,39 @@ pub fn handle_cat(filename: &str, input: Option<&str>) -> Result<String> {
     Ok(contents)
 }
 
+/// パイプラインの入力がないコマンドの入力として、標準入力をEOF（Ctrl-D）まで一行ずつ読む
+///
+/// ループに与えられた入力（`while ... done < file`）があれば、その残りを使う。
+/// バックグラウンドジョブは端末から読まずに空の入力とする。
+/// 標準入力が端末でなければ（スクリプトや `-c` にパイプで渡した場合など）、その終わりまで読む
+fn read_standard_input() -> Result<Vec<u8>> {
+    if crate::input::is_active() {
+        return Ok(crate::input::take_remaining().into_bytes());
+    }
+    if job::in_background() {
+        return Ok(Vec::new());
+    }
+
+    let mut bytes = Vec::new();
+    let mut stdin = io::stdin().lock();
+    while stdin.read_until(b'\n', &mut bytes)? > 0 {}
+    Ok(bytes)
+}
+
 /// ファイルに内容を書き込む
 ///
+/// # Returns
+///
+/// 書き込み完了メッセージ
+///
 /// # Errors
 ///
 /// - 書き込み権限がない場合
 /// - ディスク容量不足の場合
-pub fn handle_write(filename: &str, content: &str) -> Result<()> {
+pub fn handle_write(filename: &str, content: &str) -> Result<String> {
     debug!("Writing to file: {} ({} bytes)", filename, content.len());
 
     fs::write(filename, content)?;
-    println!("File written successfully: {filename}");
 
     // ファイル情報表示
     if log::log_enabled!(log::Level::Debug) {
@@ -136,47 +326,173 @@ pub fn handle_write(filename: &str, content: &str) -> Result<()> {
         debug_file_metadata(&metadata);
     }
 
-    Ok(())
+    Ok(format!("File written successfully: {filename}"))
 }
 
 /// 現在のディレクトリの内容を一覧表示する
 ///
+/// `-R` ではカレントディレクトリから辿ったディレクトリごとに `dir:` の見出しを付けて表示する。
+/// `target` や `.git` などの無視するディレクトリ（`walk::ignore_patterns`）は一覧に出すが中には入らない
+///
+/// # Arguments
+///
+/// * `format` - 出力形式（Jsonなら name/type/size/mtime の配列、`-R` の name はカレントディレクトリからのパス）
+/// * `recursive` - サブディレクトリも表示するか
+/// * `sort` - 並び順（同じ値のエントリは名前順）
+/// * `reverse` - 並び順を逆にするか
+///
 /// # Errors
 ///
-/// - ディレクトリの読み取り権限がない場合
-pub fn handle_ls() -> Result<String> {
+/// - ディレクトリの読み取り権限がない場合（`-R` のサブディレクトリはエラーを表示して続ける）
+pub fn handle_ls(
+    format: OutputFormat,
+    recursive: bool,
+    sort: LsSort,
+    reverse: bool,
+) -> Result<String> {
     debug!("Listing current directory contents");
 
-    let current_dir = env::current_dir()?;
-    debug!("Listing directory: {current_dir:?}");
+    let root = Path::new(".");
+    let directories = if recursive {
+        let options = walk::WalkOptions {
+            include_hidden: true,
+            ignore: walk::ignore_patterns(),
+            ..Default::default()
+        };
+        let mut directories = Vec::new();
+        for entry in walk::walk_with_options(root, options) {
+            match entry {
+                Ok(entry) if entry.metadata.is_dir() => directories.push(entry.path),
+                Ok(_) => {}
+                Err(err) if err.path == root => return Err(err.error.into()),
+                Err(err) => crate::output::eprint_line(&format!(
+                    "ls: cannot open directory '{}': {}",
+                    err.path.display(),
+                    describe_io_error(&err.error)
+                )),
+            }
+        }
+        directories
+    } else {
+        vec![root.to_path_buf()]
+    };
 
     // 出力する文字列の集合
-    let mut lines = Vec::new();
-
-    let entries = fs::read_dir(current_dir)?;
-    for entry in entries {
-        let entry = entry?;
+    let mut blocks = Vec::new();
+    let mut json_entries = Vec::new();
+
+    for directory in directories {
+        let entries = list_directory(&directory, sort, reverse)?;
+
+        if format == OutputFormat::Json {
+            json_entries.extend(entries.into_iter().map(|entry| {
+                let name = directory.join(&entry.name);
+                LsEntry {
+                    name: name
+                        .strip_prefix(root)
+                        .unwrap_or(&name)
+                        .display()
+                        .to_string(),
+                    kind: entry_kind(&entry.metadata),
+                    size: entry.metadata.len(),
+                    mtime: entry
+                        .metadata
+                        .modified()
+                        .ok()
+                        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                        .map_or(0, |duration| duration.as_secs()),
+                }
+            }));
+            continue;
+        }
 
-        let path = entry.path();
-        let file_name = entry.file_name();
-        let name = file_name.to_str().unwrap_or("???");
-        if path.is_dir() {
-            lines.push(format!("{name}/"));
-        } else {
-            lines.push(name.to_string());
+        let mut lines = Vec::new();
+        if recursive {
+            lines.push(format!("{}:", directory.display()));
         }
+        lines.extend(entries.into_iter().map(|entry| {
+            if entry.is_dir {
+                format!("{}/", entry.name)
+            } else {
+                entry.name
+            }
+        }));
+        blocks.push(lines.join("\n"));
+    }
+
+    if format == OutputFormat::Json {
+        return to_json(&json_entries);
+    }
+
+    // -R ではディレクトリごとの一覧を空行で区切る
+    Ok(blocks.join("\n\n"))
+}
+
+/// lsで表示する一つのエントリ
+struct ListedEntry {
+    name: String,
+    metadata: fs::Metadata, // シンボリックリンクは辿らずにリンク自体の情報
+    is_dir: bool,           // ディレクトリか（リンク先がディレクトリの場合も含む）
+}
+
+/// ディレクトリのエントリを並べて取得する
+///
+/// # Arguments
+/// * `directory` - 一覧を取得するディレクトリ
+/// * `sort` - 並び順（同じ値のエントリは名前順、Unsortedなら読んだ順のまま）
+/// * `reverse` - 並び順を逆にするか
+fn list_directory(directory: &Path, sort: LsSort, reverse: bool) -> Result<Vec<ListedEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
 
         // ファイル情報表示
         if log::log_enabled!(log::Level::Debug) {
-            let metadata = entry.metadata()?;
             debug_file_metadata(&metadata);
         }
+
+        entries.push(ListedEntry {
+            name: entry.file_name().to_str().unwrap_or("???").to_string(),
+            is_dir: entry.path().is_dir(),
+            metadata,
+        });
     }
 
-    Ok(lines.join("\n"))
+    if sort != LsSort::Unsorted {
+        entries.sort_by(|a, b| {
+            let by_sort = match sort {
+                LsSort::Name | LsSort::Unsorted => Ordering::Equal,
+                LsSort::Time => b.metadata.modified().ok().cmp(&a.metadata.modified().ok()),
+                LsSort::Size => b.metadata.len().cmp(&a.metadata.len()),
+            };
+            by_sort.then_with(|| a.name.cmp(&b.name))
+        });
+    }
+    if reverse {
+        entries.reverse();
+    }
+    Ok(entries)
 }
 
-/// 現在のディレクトリの内容を一覧表示する
+/// `ls --json` の type（シンボリックリンクは辿らない）
+fn entry_kind(metadata: &fs::Metadata) -> &'static str {
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+/// カレントディレクトリを移動する
+///
+/// `-` はOLDPWD、`~` と `~/...` はHOME（どちらもセッション変数を優先する）を基準にする。
+/// 移動に成功したら、OLDPWDとPWDをセッション変数として更新する
 ///
 /// # Errors
 ///
@@ -187,18 +503,25 @@ pub fn handle_cd(path: &str) -> Result<()> {
     // 移動するディレクトリ
     let target_path = match path {
         // 前のディレクトリを取得
-        PREVIOUS_DIR_INDICATOR => match env::var("OLDPWD") {
-            Ok(old) => old,
-            Err(_) => {
+        PREVIOUS_DIR_INDICATOR => match get_var("OLDPWD") {
+            Some(old) => old,
+            None => {
                 return Err(RucliError::InvalidArgument(
                     "cd: OLDPWD not set".to_string(),
                 ));
             }
         },
-        // ホームディレクトリを取得
-        DEFAULT_HOME_INDICATOR => env::var("HOME").unwrap_or_else(|_| "/".to_string()),
-        // 通常のディレクトリを取得
-        _ => path.to_string(),
+        // ホームディレクトリを取得（引数なしのcd。`~` はパース時のチルダ展開と同じくHOMEを使う）
+        DEFAULT_HOME_INDICATOR => get_var("HOME").unwrap_or_else(|| "/".to_string()),
+        // パース時に展開されなかった `~/...` はHOMEからの相対パス
+        _ => match path.strip_prefix("~/") {
+            Some(rest) => Path::new(&get_var("HOME").unwrap_or_else(|| "/".to_string()))
+                .join(rest)
+                .to_string_lossy()
+                .into_owned(),
+            // 通常のディレクトリを取得
+            None => path.to_string(),
+        },
     };
 
     // ディレクトリ変更前に現在の場所を保存
@@ -207,16 +530,91 @@ pub fn handle_cd(path: &str) -> Result<()> {
     // ディレクトリ変更
     env::set_current_dir(&target_path)?;
 
-    // ディレクトリ移動に成功したらOLDPWDを更新
-    unsafe {
-        env::set_var("OLDPWD", old_dir);
-    }
+    // ディレクトリ移動に成功したらOLDPWDとPWDを更新（セッション変数に持つ）
+    set_var("OLDPWD", &old_dir.to_string_lossy());
+    set_var("PWD", &env::current_dir()?.to_string_lossy());
 
     debug!("change directory to : {target_path}");
 
     Ok(())
 }
 
+/// ディレクトリスタックのハンドラー（pushd / popd / dirs）
+///
+/// 移動はhandle_cdで行うため、OLDPWDも更新される
+///
+/// # Arguments
+/// * `action` - スタックに対する操作
+///
+/// # Returns
+/// * 操作後のスタック（dirsと同じ形式）
+///
+/// # Errors
+/// - 移動先のディレクトリに移動できない場合（スタックは変更しない）
+/// - 入れ替える・戻る先のディレクトリがスタックにない場合
+pub fn handle_dir_stack(action: DirStackAction) -> Result<String> {
+    let state = state::current();
+
+    match action {
+        DirStackAction::Push(Some(dir)) => {
+            let old_dir = env::current_dir()?;
+            handle_cd(&dir)?;
+            state.dir_stack.lock_or_recover().push(old_dir);
+        }
+        DirStackAction::Push(None) => {
+            let Some(top) = state.dir_stack.lock_or_recover().last().cloned() else {
+                return Err(RucliError::InvalidArgument(
+                    "pushd: no other directory".to_string(),
+                ));
+            };
+            let old_dir = env::current_dir()?;
+            handle_cd(&top.to_string_lossy())?;
+
+            let mut stack = state.dir_stack.lock_or_recover();
+            stack.pop();
+            stack.push(old_dir);
+        }
+        DirStackAction::Pop => {
+            let Some(top) = state.dir_stack.lock_or_recover().last().cloned() else {
+                return Err(RucliError::InvalidArgument(
+                    "popd: directory stack empty".to_string(),
+                ));
+            };
+            handle_cd(&top.to_string_lossy())?;
+            state.dir_stack.lock_or_recover().pop();
+        }
+        DirStackAction::Show { verbose: true } => {
+            return Ok(dir_stack_entries()?
+                .iter()
+                .enumerate()
+                .map(|(index, dir)| format!("{index:2}  {dir}"))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        DirStackAction::Show { verbose: false } => {}
+    }
+
+    Ok(dir_stack_entries()?.join(" "))
+}
+
+/// 現在のディレクトリを先頭にしたスタックの一覧（ホームディレクトリは `~` に省略する）
+fn dir_stack_entries() -> Result<Vec<String>> {
+    let current = env::current_dir()?;
+    let stack = state::current().dir_stack.lock_or_recover().clone();
+    let home = get_var("HOME").filter(|home| !home.is_empty());
+
+    Ok(std::iter::once(current)
+        .chain(stack.into_iter().rev())
+        .map(|dir| {
+            let dir = dir.display().to_string();
+            match home.as_deref().and_then(|home| dir.strip_prefix(home)) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{rest}"),
+                _ => dir,
+            }
+        })
+        .collect())
+}
+
 /// 現在の作業ディレクトリを表示
 ///
 /// # Errors
@@ -232,34 +630,89 @@ pub fn handle_pwd() -> Result<String> {
 
 /// ディレクトリを作成する
 ///
+/// 指定した順に作成し、失敗したところで止める
+///
 /// # Errors
 ///
 /// - 既にディレクトリが存在する場合
 /// - 親ディレクトリが存在しない場合
 /// - 書き込み権限がない場合
-pub fn handle_mkdir(path: &str, parents: bool) -> Result<()> {
-    debug!("Creating directory : {path}");
+pub fn handle_mkdir(paths: &[String], parents: bool, mode: Option<u32>) -> Result<()> {
+    for path in paths {
+        debug!("Creating directory : {path}");
 
-    if parents {
-        fs::create_dir_all(path)?;
-        info!("Created directory (with parents): {path}");
-    } else {
-        fs::create_dir(path)?;
-        info!("Created directory: {path}");
+        if parents {
+            fs::create_dir_all(path)?;
+            info!("Created directory (with parents): {path}");
+        } else {
+            fs::create_dir(path)?;
+            info!("Created directory: {path}");
+        }
+
+        // -m は指定したディレクトリだけに適用する（途中の親はumaskに従う）
+        if let Some(mode) = mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
     }
     Ok(())
 }
 
+/// umaskコマンドのハンドラー
+///
+/// マスクはプロセス全体の設定で、以降に作成するファイル・ディレクトリ
+/// （write、リダイレクト、mkdir など）のパーミッションに適用される
+///
+/// # Arguments
+/// * `mask` - 設定するマスク（None なら現在の値を表示する）
+///
+/// # Returns
+/// * 表示する場合は4桁の8進数（例: `0022`）、設定した場合は空文字列
+pub fn handle_umask(mask: Option<u32>) -> String {
+    match mask {
+        Some(mask) => {
+            set_umask(mask);
+            String::new()
+        }
+        None => format!("{:04o}", current_umask()),
+    }
+}
+
+/// 現在のumaskを取得する
+fn current_umask() -> u32 {
+    // 読み取るだけのAPIはないため、一度設定して元に戻す
+    let mask = set_umask(0);
+    set_umask(mask);
+    mask
+}
+
+/// umaskを設定し、以前の値を返す
+fn set_umask(mask: u32) -> u32 {
+    unsafe { libc::umask(mask as libc::mode_t) as u32 }
+}
+
+/// 確認なしで再帰的に削除できるエントリ数の既定値（RUCLI_RM_LIMIT で変更できる）
+const DEFAULT_RM_LIMIT: usize = 100;
+
 /// ファイル/ディレクトリを削除する
 ///
+/// 再帰的な削除では、`/`・ホームディレクトリ・カレントディレクトリを対象にした場合と、
+/// 上限（RUCLI_RM_LIMIT、既定は100）を超えるエントリを削除する場合に確認を求める。
+/// 標準入力が端末でなければ確認せずにエラーにする。
+/// `-f` はエントリ数の確認を、`--no-preserve-root` は対象のディレクトリの確認を省く
+///
 /// # Errors
 ///
 /// - ファイルが存在しない場合
 /// - ディレクトリを指定した場合
 /// - 削除権限がない場合
-pub fn handle_rm(path: &str, recursive: bool, force: bool) -> Result<()> {
+/// - 確認が必要な削除を拒否した（または確認できない）場合
+pub fn handle_rm(path: &str, recursive: bool, force: bool, preserve_root: bool) -> Result<()> {
     debug!("deleting file: {path}");
 
+    if recursive && let Some((reason, override_flag)) = rm_guard(path, force, preserve_root) {
+        confirm_rm(path, &reason, override_flag)?;
+    }
+
     let result = if recursive {
         fs::remove_dir_all(path).or_else(|_| fs::remove_file(path))
     } else {
@@ -276,20 +729,181 @@ pub fn handle_rm(path: &str, recursive: bool, force: bool) -> Result<()> {
                 debug!("force mode : ignoring error - {e}");
                 Ok(())
             } else {
-                Err(RucliError::IoError(e))
+                Err(e.into())
             }
         }
     }
 }
 
+/// 再帰的な削除に確認が必要な理由と、確認を省くオプション
+fn rm_guard(path: &str, force: bool, preserve_root: bool) -> Option<(String, &'static str)> {
+    if preserve_root && is_protected_directory(path) {
+        return Some((
+            "is the root, home or current directory".to_string(),
+            "--no-preserve-root",
+        ));
+    }
+
+    if force {
+        return None;
+    }
+
+    let limit = get_var("RUCLI_RM_LIMIT")
+        .and_then(|limit| limit.trim().parse().ok())
+        .unwrap_or(DEFAULT_RM_LIMIT);
+
+    // 上限を超えたかどうかだけ分かればよいので、上限+1件で数えるのをやめる
+    let options = walk::WalkOptions {
+        include_hidden: true,
+        ..Default::default()
+    };
+    let entries = walk::walk_with_options(path, options)
+        .skip(1)
+        .take(limit + 1)
+        .count();
+    (entries > limit).then(|| (format!("contains more than {limit} entries"), "-f"))
+}
+
+/// `/`・ホームディレクトリ・カレントディレクトリか
+fn is_protected_directory(path: &str) -> bool {
+    let Ok(target) = fs::canonicalize(path) else {
+        return false;
+    };
+
+    let home = env::var_os("HOME").and_then(|home| fs::canonicalize(home).ok());
+    let current = env::current_dir().ok();
+    target == Path::new("/") || Some(&target) == home.as_ref() || Some(&target) == current.as_ref()
+}
+
+/// 再帰的な削除の確認を求める（端末でなければ拒否する）
+///
+/// # Errors
+/// - 拒否された場合、確認できない場合
+fn confirm_rm(path: &str, reason: &str, override_flag: &str) -> Result<()> {
+    if !io::stdin().is_terminal() {
+        return Err(RucliError::RuntimeError(format!(
+            "rm: refusing to remove '{path}', which {reason} (use {override_flag} to override)"
+        )));
+    }
+
+    crate::output::write_stderr(&format!("rm: '{path}' {reason}; remove it? [y/N] "));
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        Err(RucliError::RuntimeError(format!(
+            "rm: '{path}' not removed"
+        )))
+    }
+}
+
+/// 履歴から番号で再実行する破壊的なコマンドを実行してよいか確認する
+///
+/// 確認の答えは端末から読む。端末で対話していない場合は確認できないので実行しない
+///
+/// # Errors
+/// - 確認できない状況の場合（`history exec -y` を案内する）
+/// - 実行しないと答えた場合
+pub fn confirm_history_execute(index: usize, input: Option<&[u8]>) -> Result<()> {
+    if crate::job::in_background()
+        || input.is_some()
+        || crate::input::is_active()
+        || !io::stdin().is_terminal()
+    {
+        return Err(RucliError::RuntimeError(format!(
+            "history: refusing to re-run destructive command {index} without confirmation (use history exec -y {index})"
+        )));
+    }
+
+    crate::output::write_stderr("history: run this destructive command again? [y/N] ");
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        Err(RucliError::RuntimeError(format!(
+            "history: command {index} not executed"
+        )))
+    }
+}
+
+/// -i で上書きの確認を求められる状況か調べる
+///
+/// 確認の答えは端末から読むので、パイプラインで入力を受け取っている場合や
+/// バックグラウンドのジョブの中では使えない
+///
+/// # Errors
+/// - 確認の答えを読めない状況の場合
+pub fn ensure_can_confirm(name: &str, input: Option<&[u8]>) -> Result<()> {
+    if crate::job::in_background() {
+        return Err(RucliError::InvalidArgument(format!(
+            "{name}: -i cannot be used in a background job"
+        )));
+    }
+    if input.is_some() || crate::input::is_active() {
+        return Err(RucliError::InvalidArgument(format!(
+            "{name}: -i cannot be used while reading piped input"
+        )));
+    }
+    Ok(())
+}
+
+/// 既存のファイルを上書きしてよいか確認する（端末でなければ上書きしない）
+///
+/// # Returns
+/// * 上書きしてよい場合、または確認が不要な場合はtrue
+fn confirm_overwrite(name: &str, path: &Path, interactive: bool) -> Result<bool> {
+    if !interactive || !path.exists() {
+        return Ok(true);
+    }
+
+    if !io::stdin().is_terminal() {
+        crate::output::eprint_line(&format!(
+            "{name}: not overwriting '{}' (standard input is not a terminal)",
+            path.display()
+        ));
+        return Ok(false);
+    }
+
+    crate::output::write_stderr(&format!("{name}: overwrite '{}'? [y/N] ", path.display()));
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// -v で表示する1行（`'src' -> 'dst'`）
+fn transfer_line(source: &Path, destination: &Path) -> String {
+    format!("'{}' -> '{}'", source.display(), destination.display())
+}
+
 /// ファイルをコピーする
 ///
+/// # Arguments
+///
+/// * `recursive` - ディレクトリを再帰的にコピーするか（-r）
+/// * `interactive` - 既存のファイルを上書きする前に確認するか（-i）
+/// * `verbose` - コピーしたファイルとディレクトリを表示するか（-v）
+///
+/// # Returns
+///
+/// * -v の場合はコピーしたものの一覧、それ以外は空文字列
+///
 /// # Errors
 ///
 /// - ソースファイルが存在しない場合
 /// - ソースがディレクトリの場合
+/// - コピー先がソースと同じか、ソースのディレクトリの中にある場合
 /// - 書き込み権限がない場合
-pub fn handle_cp(source: &str, destination: &str, recursive: bool) -> Result<()> {
+pub fn handle_cp(
+    source: &str,
+    destination: &str,
+    recursive: bool,
+    interactive: bool,
+    verbose: bool,
+) -> Result<String> {
     debug!("Copying {source} to {destination}");
 
     let source_path = Path::new(source);
@@ -302,8 +916,13 @@ pub fn handle_cp(source: &str, destination: &str, recursive: bool) -> Result<()>
         ));
     }
 
-    let bytes = if recursive {
-        copy_dir_recursive(source_path, destination_path)?
+    // -v で表示する行
+    let mut copied = Vec::new();
+
+    let bytes = if recursive && source_path.is_dir() {
+        // 自分自身や自分の中へのコピーは終わらないので、始める前に断る
+        ensure_distinct("cp", source, destination, destination_path)?;
+        copy_dir_recursive(source_path, destination_path, interactive, &mut copied)?
     } else {
         // destinationがディレクトリであればディレクトリの先にコピー
         let destination_path = if destination_path.is_dir() {
@@ -311,48 +930,97 @@ pub fn handle_cp(source: &str, destination: &str, recursive: bool) -> Result<()>
         } else {
             destination_path.to_path_buf()
         };
+        ensure_distinct("cp", source, destination, &destination_path)?;
 
-        fs::copy(source_path, destination_path)?
+        if confirm_overwrite("cp", &destination_path, interactive)? {
+            let bytes = fs::copy(source_path, &destination_path)?;
+            copied.push(transfer_line(source_path, &destination_path));
+            bytes
+        } else {
+            0
+        }
     };
 
     info!("Copied {bytes} bytes from {source} to {destination}");
 
-    Ok(())
+    Ok(if verbose {
+        copied.join("\n")
+    } else {
+        String::new()
+    })
+}
+
+/// コピー・移動の先が元と同じファイルか、元のディレクトリの中にあればエラーにする
+///
+/// どちらのパスも正規化して比べる。先がまだ存在しない場合は親ディレクトリを正規化して名前をつなぐ
+///
+/// # Arguments
+/// * `source` / `destination` - 入力されたパス（エラーメッセージに使う）
+/// * `target` - 実際に書き込む先（ディレクトリを指定した場合はその中のパス）
+///
+/// # Errors
+/// - 同じファイルか、先が元の中にある場合
+fn ensure_distinct(name: &str, source: &str, destination: &str, target: &Path) -> Result<()> {
+    let Ok(source_path) = fs::canonicalize(source) else {
+        return Ok(());
+    };
+    let target_path =
+        fs::canonicalize(target).or_else(|e| match (target.parent(), target.file_name()) {
+            (Some(parent), Some(file_name)) => {
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                fs::canonicalize(parent).map(|parent| parent.join(file_name))
+            }
+            _ => Err(e),
+        });
+
+    match target_path {
+        Ok(target_path) if target_path.starts_with(&source_path) => {
+            Err(RucliError::InvalidArgument(format!(
+                "{name}: '{source}' and '{destination}' are the same file or nested"
+            )))
+        }
+        _ => Ok(()),
+    }
 }
 
-// 再帰的なコピーを行う
-fn copy_dir_recursive(source: &Path, destination: &Path) -> Result<u64> {
+// 再帰的なコピーを行う（コピーしたものは `copied` に記録する）
+fn copy_dir_recursive(
+    source: &Path,
+    destination: &Path,
+    interactive: bool,
+    copied: &mut Vec<String>,
+) -> Result<u64> {
     // 合計のバイト数
     let mut bytes = 0;
 
-    // destinationがディレクトリの場合、まず作成
+    // destinationがなければまず作成（既にあれば中にコピーする）
     if !destination.exists() {
         fs::create_dir(destination)?;
+        copied.push(transfer_line(source, destination));
     }
 
-    let entries = fs::read_dir(source)?;
+    // 表示の順序が毎回同じになるよう名前順にする
+    let mut entries = fs::read_dir(source)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
 
     for entry in entries {
         debug!("now source directory : {entry:?}");
 
-        let entry = entry?;
-
-        // ディレクトリであれば新しいディレクトリを作成し、再帰的に関数を呼ぶ
-        if entry.path().is_dir() {
-            let new_source: std::path::PathBuf = entry.path();
-            let new_destination = Path::new(destination).join(entry.file_name());
-
-            // 新しいディレクトリを作成
-            fs::create_dir(&new_destination)?;
+        let new_source = entry.path();
+        let new_destination = destination.join(entry.file_name());
 
-            bytes += copy_dir_recursive(&new_source, &new_destination)?;
+        // ディレクトリであれば再帰的に関数を呼ぶ（新しいディレクトリはその中で作成）
+        if new_source.is_dir() {
+            bytes += copy_dir_recursive(&new_source, &new_destination, interactive, copied)?;
         }
         // ファイルなのでコピーをする
-        else {
-            let new_source: std::path::PathBuf = entry.path();
-            let new_destination = Path::new(destination).join(entry.file_name());
-
-            bytes += fs::copy(new_source, new_destination)?;
+        else if confirm_overwrite("cp", &new_destination, interactive)? {
+            bytes += fs::copy(&new_source, &new_destination)?;
+            copied.push(transfer_line(&new_source, &new_destination));
         }
     }
 
@@ -365,13 +1033,25 @@ fn copy_dir_recursive(source: &Path, destination: &Path) -> Result<u64> {
 ///
 /// * `source` - 移動元のファイルまたはディレクトリのパス
 /// * `destination` - 移動先のパス
+/// * `interactive` - 既存のファイルを上書きする前に確認するか（-i）
+/// * `verbose` - 移動したものを表示するか（-v）
+///
+/// # Returns
+///
+/// * -v で移動した場合は `'src' -> 'dst'`、それ以外は空文字列
 ///
 /// # Errors
 ///
 /// - ソースが存在しない場合
+/// - 移動先がソースと同じか、ソースのディレクトリの中にある場合
 /// - 移動先に書き込み権限がない場合
 /// - クロスデバイス移動でコピーに失敗した場合
-pub fn handle_mv(source: &str, destination: &str) -> Result<()> {
+pub fn handle_mv(
+    source: &str,
+    destination: &str,
+    interactive: bool,
+    verbose: bool,
+) -> Result<String> {
     let source_path = Path::new(source);
     let destination_path = Path::new(destination);
 
@@ -381,57 +1061,726 @@ pub fn handle_mv(source: &str, destination: &str) -> Result<()> {
     } else {
         destination_path.to_path_buf()
     };
+    ensure_distinct("mv", source, destination, &destination_path)?;
 
-    fs::rename(source_path, destination_path)?;
-    Ok(())
+    if !confirm_overwrite("mv", &destination_path, interactive)? {
+        return Ok(String::new());
+    }
+
+    fs::rename(source_path, &destination_path)?;
+    Ok(if verbose {
+        transfer_line(source_path, &destination_path)
+    } else {
+        String::new()
+    })
 }
 
-/// ファイルを名前で検索する（ワイルドカード対応）
+/// ワイルドカードのパターンに一致するファイルをまとめて名前変更する
+///
+/// パターンのワイルドカードは最後の要素（ファイル名の部分）だけで扱い、名前は同じ
+/// ディレクトリの中で変える。変更するファイルごとに `'old' -> 'new'` を一行ずつ出力する。
+/// 新しい名前のファイルが既にある、または別のファイルと同じ名前になる場合は、
+/// 警告して上書きせずに飛ばす。一つの名前変更に失敗しても残りは続ける
 ///
 /// # Arguments
 ///
-/// * `path` - 検索を開始するディレクトリ（Noneの場合はカレントディレクトリ）
-/// * `pattern` - 検索パターン（ワイルドカード: *, ? を使用可能）
+/// * `pattern` - 変更するファイルのパターン（例: `photos/*.jpeg`）
+/// * `replacement` - 新しい名前（`*` `?` にパターンで一致した部分が順に入る）
+/// * `dry_run` - 名前を変えずに変更内容だけ表示するか（-n）
 ///
 /// # Errors
 ///
-/// - 検索開始ディレクトリが存在しない場合
-/// - ディレクトリの読み取り権限がない場合
-pub fn handle_find(path: Option<&str>, name: &str) -> Result<String> {
+/// - パターンに一致するファイルがない場合
+/// - 飛ばしたファイルや名前変更に失敗したファイルがあった場合
+///   （ステータス1、変更できた分は出力に含める）
+pub fn handle_rename(pattern: &str, replacement: &str, dry_run: bool) -> Result<String> {
+    let (dir, name_pattern) = match pattern.rfind('/') {
+        Some(pos) => (&pattern[..=pos], &pattern[pos + 1..]),
+        None => ("", pattern),
+    };
+    let dir_path = Path::new(if dir.is_empty() { "." } else { dir });
+
+    // `.` で始まるファイルはパターンも `.` で始まる場合だけ（expand_glob と同じ）
+    let mut names: Vec<String> = fs::read_dir(dir_path)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name_pattern.starts_with('.') || !name.starts_with('.'))
+        .filter(|name| matches_pattern(name, name_pattern))
+        .collect();
+    names.sort();
+
+    if names.is_empty() {
+        return Err(RucliError::RuntimeError(format!(
+            "rename: no files match '{pattern}'"
+        )));
+    }
+
     let mut lines = Vec::new();
+    let mut claimed = HashSet::new();
+    let mut failed = false;
+    for name in names {
+        let Some(captures) = wildcard_captures(&name, name_pattern) else {
+            continue;
+        };
+        let new_name = substitute_wildcards(replacement, &captures);
+        if new_name == name {
+            continue;
+        }
 
-    let search_path = path.unwrap_or(".");
+        let source = PathBuf::from(format!("{dir}{name}"));
+        let destination = PathBuf::from(format!("{dir}{new_name}"));
+        if fs::symlink_metadata(&destination).is_ok() || !claimed.insert(new_name.clone()) {
+            crate::output::eprint_line(&format!(
+                "rename: skipping '{}': '{}' already exists",
+                source.display(),
+                destination.display()
+            ));
+            failed = true;
+            continue;
+        }
 
-    let entries = fs::read_dir(search_path)?;
+        if !dry_run && let Err(e) = fs::rename(&source, &destination) {
+            crate::output::eprint_line(&format!(
+                "rename: {}: {}",
+                transfer_line(&source, &destination),
+                describe_io_error(&e)
+            ));
+            failed = true;
+            continue;
+        }
+        lines.push(transfer_line(&source, &destination));
+    }
 
-    for entry in entries {
-        let entry = entry?;
-        let entry_path = entry.path();
+    let output = lines.join("\n");
+    if failed {
+        return Err(RucliError::CommandFailed {
+            output: output.into(),
+            status: 1,
+        });
+    }
+    Ok(output)
+}
+
+/// ファイルを名前で検索する（ワイルドカード対応）
+///
+/// 条件を指定した場合は、名前が一致してすべての条件を満たすエントリだけを表示する。
+/// `target` や `.git` などの無視するディレクトリ（`walk::ignore_patterns`）は辿らない
+///
+/// # Arguments
+///
+/// * `path` - 検索を開始するディレクトリ（Noneの場合はカレントディレクトリ）
+/// * `pattern` - 検索パターン（ワイルドカード: *, ? を使用可能）
+/// * `filters` - 更新日時・サイズの条件（-mtime / -size / -newer）
+/// * `no_ignore` - 無視するディレクトリも辿るか
+/// * `print0` - 各パスを改行ではなくNULで終える（`xargs -0` で安全に読める）
+///
+/// # Returns
+///
+/// 起点からのパス（`walk::display_path`）。起点の指定がなければ `./` で始まる
+///
+/// # Errors
+///
+/// - 検索開始ディレクトリが存在しない、またはディレクトリでない場合
+/// - ディレクトリの読み取り権限がない場合
+/// - -newer の基準のファイルが存在しない場合
+pub fn handle_find(
+    path: Option<&str>,
+    name: &str,
+    filters: &[FindFilter],
+    no_ignore: bool,
+    print0: bool,
+) -> Result<String> {
+    // -newer の基準の更新日時は最初に一度だけ取得する
+    let mut newer_than = None;
+    for filter in filters {
+        if let FindFilter::Newer(reference) = filter {
+            let modified = fs::metadata(reference)
+                .and_then(|metadata| metadata.modified())
+                .map_err(|e| {
+                    RucliError::IoError(io::Error::new(
+                        e.kind(),
+                        format!("find: '{reference}': {e}"),
+                    ))
+                })?;
+            newer_than = newer_than.max(Some(modified));
+        }
+    }
+
+    let root = path.unwrap_or(".");
+    if !fs::metadata(root)?.is_dir() {
+        return Err(RucliError::IoError(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!("find: '{root}': Not a directory"),
+        )));
+    }
+
+    let criteria = FindCriteria {
+        name,
+        filters,
+        newer_than,
+        now: SystemTime::now(),
+    };
+    let options = walk::WalkOptions {
+        include_hidden: true,
+        follow_symlinks: true,
+        ignore: ignored_directories(no_ignore),
+        ..Default::default()
+    };
+
+    let mut lines = Vec::new();
+    for entry in walk::walk_with_options(root, options) {
+        let entry = entry.map_err(|err| err.error)?;
+
+        // ファイル名が一致し、条件を満たせばパスを出力（起点は対象外）
+        if entry.depth > 0
+            && let Some(filename) = entry.path.file_name().and_then(|n| n.to_str())
+            && matches_pattern(filename, criteria.name)
+            && criteria.matches(&entry.metadata)
+        {
+            lines.push(walk::display_path(root, &entry.path));
+        }
+    }
+
+    // -print0 の出力は改行を付けずにそのまま出す（passes_output_through）
+    if print0 {
+        return Ok(lines.iter().map(|line| format!("{line}\0")).collect());
+    }
+    Ok(lines.join("\n"))
+}
+
+/// -mtime の1日の長さ
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// findで表示するエントリの条件
+struct FindCriteria<'a> {
+    name: &'a str,
+    filters: &'a [FindFilter],
+    newer_than: Option<SystemTime>, // -newer の基準の中で最も新しい更新日時
+    now: SystemTime,                // -mtime の経過日数の基準
+}
+
+impl FindCriteria<'_> {
+    /// メタデータがすべての条件を満たすか（更新日時を取得できなければ満たさない）
+    fn matches(&self, metadata: &fs::Metadata) -> bool {
+        let modified = metadata.modified().ok();
+        self.filters.iter().all(|filter| match filter {
+            FindFilter::ModifiedDays(days) => modified.is_some_and(|modified| {
+                let age = self.now.duration_since(modified).unwrap_or_default();
+                days.matches(age.as_secs() / SECONDS_PER_DAY)
+            }),
+            FindFilter::Size { size, unit } => size.matches(metadata.len().div_ceil(*unit)),
+            FindFilter::Newer(_) => modified.is_some_and(|modified| {
+                self.newer_than.is_none_or(|reference| modified > reference)
+            }),
+        })
+    }
+}
+
+/// ディレクトリごとの使用量（ファイルサイズの合計）を表示する
+///
+/// 各ディレクトリを深い順（子が親より先）に一行ずつ出力し、起点の合計を最後に出す。
+/// シンボリックリンクは辿らず、読み取れないエントリは警告して読み飛ばす。
+///
+/// # Arguments
+///
+/// * `paths` - 対象のパス（空の場合はカレントディレクトリ）
+/// * `human_readable` - K/M/G 単位で表示するか（falseならバイト数）
+/// * `summarize` - 起点の合計のみ表示するか
+/// * `no_ignore` - `target` や `.git` などの無視するディレクトリも集計するか
+///
+/// # Errors
+///
+/// - 対象のパスが存在しない場合
+pub fn handle_du(
+    paths: &[String],
+    human_readable: bool,
+    summarize: bool,
+    no_ignore: bool,
+) -> Result<String> {
+    let default_paths = [".".to_string()];
+    let paths = if paths.is_empty() {
+        &default_paths[..]
+    } else {
+        paths
+    };
+
+    let mut lines = Vec::new();
+
+    for root in paths {
+        // 起点が存在しなければエラー
+        if let Err(e) = fs::symlink_metadata(root) {
+            return Err(RucliError::IoError(io::Error::new(
+                e.kind(),
+                format!("du: cannot access '{root}': {e}"),
+            )));
+        }
+
+        let sizes = directory_sizes(Path::new(root), ignored_directories(no_ignore));
+        let sizes = if summarize {
+            &sizes[sizes.len() - 1..]
+        } else {
+            &sizes[..]
+        };
+
+        for (path, size) in sizes {
+            lines.push(format!(
+                "{}\t{}",
+                format_size(*size, human_readable),
+                path.display()
+            ));
+        }
+    }
 
-        // ファイル名が一致すればパスを出力
-        if let Some(filename) = entry_path.file_name().and_then(|n| n.to_str())
-            && matches_pattern(filename, name)
+    Ok(lines.join("\n"))
+}
+
+/// 辿らないディレクトリのパターン（--no-ignore なら空）
+fn ignored_directories(no_ignore: bool) -> Vec<String> {
+    if no_ignore {
+        Vec::new()
+    } else {
+        walk::ignore_patterns()
+    }
+}
+
+/// 起点以下の各ディレクトリの合計サイズを帰りがけ順（起点が最後）で求める
+///
+/// `ignore` に一致するディレクトリは集計しない
+fn directory_sizes(root: &Path, ignore: Vec<String>) -> Vec<(PathBuf, u64)> {
+    // 集計中のディレクトリ（パス, 深さ, 合計）。末尾ほど深い
+    let mut open: Vec<(PathBuf, usize, u64)> = Vec::new();
+    let mut finished = Vec::new();
+
+    // 集計中の最も深いディレクトリを確定し、親に合計を足す
+    let close_deepest = |open: &mut Vec<(PathBuf, usize, u64)>, finished: &mut Vec<_>| {
+        if let Some((path, _, size)) = open.pop() {
+            if let Some(parent) = open.last_mut() {
+                parent.2 += size;
+            }
+            finished.push((path, size));
+        }
+    };
+
+    let options = walk::WalkOptions {
+        ignore,
+        ..Default::default()
+    };
+    for entry in walk::walk_with_options(root, options) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!("du: skipping {}: {}", err.path.display(), err.error);
+                crate::output::eprint_line(&format!(
+                    "du: cannot read '{}': {}",
+                    err.path.display(),
+                    err.error
+                ));
+                continue;
+            }
+        };
+
+        // このエントリの祖先でないディレクトリは集計が終わっている
+        while open
+            .last()
+            .is_some_and(|(_, depth, _)| *depth >= entry.depth)
         {
-            lines.push(format!("{}", entry_path.display()));
+            close_deepest(&mut open, &mut finished);
+        }
+
+        if entry.metadata.is_dir() {
+            open.push((entry.path, entry.depth, 0));
+        } else if let Some(parent) = open.last_mut() {
+            parent.2 += entry.metadata.len();
+        } else {
+            // 起点がファイルの場合
+            finished.push((entry.path, entry.metadata.len()));
+        }
+    }
+
+    while !open.is_empty() {
+        close_deepest(&mut open, &mut finished);
+    }
+
+    finished
+}
+
+/// バイト数を表示用に整形する（human_readableならK/M/G単位）
+fn format_size(bytes: u64, human_readable: bool) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+    if !human_readable || bytes < 1024 {
+        return bytes.to_string();
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if value < 10.0 {
+        format!("{value:.1}{}", UNITS[unit])
+    } else {
+        format!("{value:.0}{}", UNITS[unit])
+    }
+}
+
+/// 各行からフィールドまたは文字を切り出す
+///
+/// 範囲が行の長さを超えていてもエラーにはせず、その部分を空として扱う
+///
+/// # Arguments
+///
+/// * `selection` - 切り出すフィールド・文字の範囲
+/// * `delimiter` - フィールドの区切り文字
+/// * `only_delimited` - 区切り文字を含まない行を出力しないか（-s）
+/// * `files` - 入力ファイル（空ならパイプラインの入力を使う）
+/// * `input` - パイプラインからの入力
+///
+/// # Errors
+///
+/// - ファイルが読めない場合
+/// - 入力がない場合
+pub fn handle_cut(
+    selection: &CutSelection,
+    delimiter: char,
+    only_delimited: bool,
+    files: &[String],
+    input: Option<&[u8]>,
+) -> Result<String> {
+    let text = if files.is_empty() {
+        let input =
+            input.ok_or_else(|| RucliError::InvalidArgument("cut: no input".to_string()))?;
+        String::from_utf8_lossy(input).into_owned()
+    } else {
+        let mut text = String::new();
+        for file in files {
+            text.push_str(&fs::read_to_string(file)?);
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+        text
+    };
+
+    let mut lines = Vec::new();
+
+    for line in text.lines() {
+        match selection {
+            CutSelection::Fields(ranges) => {
+                // 区切り文字を含まない行はそのまま（-sなら出力しない）
+                if !line.contains(delimiter) {
+                    if !only_delimited {
+                        lines.push(line.to_string());
+                    }
+                    continue;
+                }
+
+                let selected: Vec<&str> = line
+                    .split(delimiter)
+                    .enumerate()
+                    .filter(|(i, _)| ranges.iter().any(|range| range.contains(i + 1)))
+                    .map(|(_, field)| field)
+                    .collect();
+                lines.push(selected.join(&delimiter.to_string()));
+            }
+            CutSelection::Characters(ranges) => {
+                let selected: String = line
+                    .chars()
+                    .enumerate()
+                    .filter(|(i, _)| ranges.iter().any(|range| range.contains(i + 1)))
+                    .map(|(_, ch)| ch)
+                    .collect();
+                lines.push(selected);
+            }
+        }
+    }
+
+    Ok(terminated_lines(&lines))
+}
+
+/// パイプラインの入力の文字を置換・削除・圧縮する
+///
+/// # Arguments
+///
+/// * `set1` - 置換元・削除する文字集合
+/// * `set2` - 置換先（-dと-sを併用する場合は圧縮する）文字集合
+/// * `delete` - set1の文字を削除するか
+/// * `squeeze` - 連続する同じ文字を一つにまとめるか
+/// * `input` - パイプラインからの入力
+///
+/// # Errors
+///
+/// - 文字集合の表記が不正な場合
+/// - パイプラインの入力がない場合（trはファイルを読まない）
+pub fn handle_tr(
+    set1: &str,
+    set2: Option<&str>,
+    delete: bool,
+    squeeze: bool,
+    input: Option<&[u8]>,
+) -> Result<String> {
+    let input = input.ok_or_else(|| {
+        RucliError::InvalidArgument("tr: no input (tr only reads from a pipe)".to_string())
+    })?;
+    let input = String::from_utf8_lossy(input);
+
+    let set1 = translate::parse_set(set1)?;
+    let set2 = set2
+        .map(translate::parse_set)
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(translate::translate(&input, &set1, &set2, delete, squeeze))
+}
+
+/// 二つのファイルを行単位で比較する
+///
+/// 差分はunified形式（前後3行の文脈付き）で出力する
+///
+/// # Arguments
+///
+/// * `file1` - 比較元のファイル
+/// * `file2` - 比較先のファイル
+/// * `brief` - 差分の内容を出さず、異なることだけを報告するか
+///
+/// # Returns
+///
+/// 同一なら空文字列
+///
+/// # Errors
+///
+/// - 差分がある場合（ステータス1の `CommandFailed`、出力は差分）
+/// - ファイルが読めない場合
+pub fn handle_diff(file1: &str, file2: &str, brief: bool) -> Result<String> {
+    let old = fs::read_to_string(file1)?;
+    let new = fs::read_to_string(file2)?;
+
+    let output = if brief {
+        if old == new {
+            return Ok(String::new());
+        }
+        format!("Files {file1} and {file2} differ")
+    } else {
+        match diff::unified_diff(&old, &new, file1, file2) {
+            Some(output) => output,
+            None => return Ok(String::new()),
         }
+    };
+
+    Err(RucliError::CommandFailed {
+        output: output.into(),
+        status: 1,
+    })
+}
+
+/// ディレクトリ構造をツリー表示する
+///
+/// ディレクトリを先に名前順で並べ、最後に "N directories, M files" を出力する。
+/// ディレクトリへのシンボリックリンクは辿るが、同じディレクトリには二度入らない。
+///
+/// # Arguments
+///
+/// * `path` - 起点のディレクトリ（Noneの場合はカレントディレクトリ）
+/// * `max_depth` - 表示する最大の深さ
+/// * `show_hidden` - `.` で始まるエントリも表示するか
+/// * `no_ignore` - `target` や `.git` などの無視するディレクトリも表示するか
+///
+/// # Errors
+///
+/// - 起点が存在しない、またはディレクトリでない場合
+pub fn handle_tree(
+    path: Option<&str>,
+    max_depth: Option<usize>,
+    show_hidden: bool,
+    no_ignore: bool,
+) -> Result<String> {
+    let root = path.unwrap_or(".");
+    if !fs::metadata(root)?.is_dir() {
+        return Err(RucliError::InvalidArgument(format!(
+            "tree: {root}: Not a directory"
+        )));
+    }
+
+    let options = walk::WalkOptions {
+        max_depth,
+        include_hidden: show_hidden,
+        dirs_first: true,
+        follow_symlinks: true,
+        ignore: ignored_directories(no_ignore),
+    };
 
-        // ディレクトリであれば再帰的に探索
-        if entry_path.is_dir() {
-            let sub_results = handle_find(entry_path.to_str(), name)?;
-            if !sub_results.is_empty() {
-                lines.push(sub_results);
+    let mut lines = vec![root.to_string()];
+    let mut directories = 0;
+    let mut files = 0;
+    // 各深さの祖先が同じディレクトリ内で最後のエントリだったか
+    let mut last_flags: Vec<bool> = Vec::new();
+
+    for entry in walk::walk_with_options(root, options).skip(1) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                crate::output::eprint_line(&format!(
+                    "tree: cannot read '{}': {}",
+                    err.path.display(),
+                    err.error
+                ));
+                continue;
             }
+        };
+
+        last_flags.truncate(entry.depth - 1);
+
+        // 祖先ごとの縦線と、このエントリの枝
+        let mut line: String = last_flags
+            .iter()
+            .map(|&last| if last { "    " } else { "│   " })
+            .collect();
+        line.push_str(if entry.is_last {
+            "└── "
+        } else {
+            "├── "
+        });
+
+        let name = entry
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        line.push_str(&name);
+        if entry.is_symlink
+            && let Ok(target) = fs::read_link(&entry.path)
+        {
+            line.push_str(&format!(" -> {}", target.display()));
+        }
+        lines.push(line);
+
+        last_flags.push(entry.is_last);
+
+        if entry.metadata.is_dir() {
+            directories += 1;
+        } else {
+            files += 1;
         }
     }
 
+    let directory_word = if directories == 1 {
+        "directory"
+    } else {
+        "directories"
+    };
+    let file_word = if files == 1 { "file" } else { "files" };
+    lines.push(String::new());
+    lines.push(format!(
+        "{directories} {directory_word}, {files} {file_word}"
+    ));
+
     Ok(lines.join("\n"))
 }
 
 /// パターンがファイル名にマッチするかチェック
-fn matches_pattern(filename: &str, pattern: &str) -> bool {
+///
+/// `*` は任意の文字列、`?` は任意の一文字にマッチする
+pub fn matches_pattern(filename: &str, pattern: &str) -> bool {
     match_helper(filename.as_bytes(), pattern.as_bytes(), 0, 0)
 }
 
+/// パターンの `*` `?` がファイル名のどの部分に一致したかを求める
+///
+/// `*` はなるべく短く一致させる（`matches_pattern` と同じ順に試す）
+///
+/// 例: ("photo.jpeg", "*.jp?g") → Some(["photo", "e"])
+///
+/// # Returns
+/// * 一致した部分（パターンに現れる順）、一致しなければNone
+pub fn wildcard_captures(filename: &str, pattern: &str) -> Option<Vec<String>> {
+    let filename: Vec<char> = filename.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut captures = Vec::new();
+    capture_helper(&filename, &pattern, &mut captures).then_some(captures)
+}
+
+fn capture_helper(filename: &[char], pattern: &[char], captures: &mut Vec<String>) -> bool {
+    let Some((&first, rest)) = pattern.split_first() else {
+        return filename.is_empty();
+    };
+
+    match first {
+        '*' => {
+            for len in 0..=filename.len() {
+                captures.push(filename[..len].iter().collect());
+                if capture_helper(&filename[len..], rest, captures) {
+                    return true;
+                }
+                captures.pop();
+            }
+            false
+        }
+        '?' => {
+            let Some((&c, remaining)) = filename.split_first() else {
+                return false;
+            };
+            captures.push(c.to_string());
+            if capture_helper(remaining, rest, captures) {
+                return true;
+            }
+            captures.pop();
+            false
+        }
+        _ => filename.first() == Some(&first) && capture_helper(&filename[1..], rest, captures),
+    }
+}
+
+/// `wildcard_captures` で求めた部分を、新しい名前の `*` `?` に順に入れる
+///
+/// 例: ("*.jpg", ["photo"]) → "photo.jpg"
+pub fn substitute_wildcards(replacement: &str, captures: &[String]) -> String {
+    let mut captures = captures.iter();
+    replacement
+        .chars()
+        .map(|c| match c {
+            '*' | '?' => captures.next().cloned().unwrap_or_default(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// ワイルドカード（`*` `?`）を含む語を、マッチするパスに展開する
+///
+/// ワイルドカードは最後の要素（ファイル名の部分）だけで扱い、`.` で始まるファイルは
+/// パターンも `.` で始まる場合だけマッチする。マッチしなければ語をそのまま返す。
+///
+/// 例: `src/*.rs` → ["src/lib.rs", "src/main.rs"]
+pub fn expand_glob(word: &str) -> Vec<String> {
+    if !word.contains(['*', '?']) {
+        return vec![word.to_string()];
+    }
+
+    let (dir, pattern) = match word.rfind('/') {
+        Some(pos) => (&word[..=pos], &word[pos + 1..]),
+        None => ("", word),
+    };
+
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return vec![word.to_string()];
+    };
+
+    let mut matches: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| {
+            (pattern.starts_with('.') || !name.starts_with('.')) && matches_pattern(name, pattern)
+        })
+        .map(|name| format!("{dir}{name}"))
+        .collect();
+
+    if matches.is_empty() {
+        return vec![word.to_string()];
+    }
+
+    matches.sort();
+    matches
+}
+
 fn match_helper(filename: &[u8], pattern: &[u8], fi: usize, pi: usize) -> bool {
     // 両方終わった → OK
     if pi >= pattern.len() && fi >= filename.len() {
@@ -477,39 +1826,89 @@ fn match_helper(filename: &[u8], pattern: &[u8], fi: usize, pi: usize) -> bool {
     }
 }
 
+/// `grep --stats` で表示する検索の集計
+#[derive(Debug, Default)]
+struct GrepStats {
+    files_searched: usize,
+    files_matched: usize,
+    matching_lines: usize,
+}
+
+impl GrepStats {
+    /// 一つのファイル（または入力）の検索結果を加える
+    fn record(&mut self, matches: usize) {
+        self.files_searched += 1;
+        if matches > 0 {
+            self.files_matched += 1;
+        }
+        self.matching_lines += matches;
+    }
+}
+
 /// ファイル内でパターンを検索する
 ///
+/// 読めないファイルは `grep: FILE: 理由` を標準エラーに表示し、残りのファイルの検索を続ける
+///
 /// # Arguments
 ///
 /// * `pattern` - 検索する文字列パターン
 /// * `files` - 検索対象のファイルパス一覧
+/// * `count` - 一致した行の代わりに行数を表示する（複数ファイルなら `FILE:N`）
+/// * `stats` - 検索の後、集計を一行で標準エラーに表示する
 ///
 /// # Errors
 ///
-/// - ファイルが存在しない場合
-/// - ファイルの読み取り権限がない場合
-pub fn handle_grep(pattern: &str, files: &[String], input: Option<&str>) -> Result<String> {
+/// - パターンが正規表現として不正な場合
+/// - 読めないファイルがあった場合（ステータス2、見つかった行は出力に含める）
+/// - 一致する行がなかった場合（ステータス1）
+pub fn handle_grep(
+    pattern: &str,
+    files: &[String],
+    count: bool,
+    stats: bool,
+    input: Option<&[u8]>,
+) -> Result<String> {
+    let start = Instant::now();
+    let re = Regex::new(pattern).map_err(|e| RucliError::InvalidRegex(e.to_string()))?;
+    let mut summary = GrepStats::default();
     let mut lines = Vec::new();
+    let mut failed = false;
 
     if files.is_empty() {
-        if let Some(input_text) = input {
-            // パイプラインからの入力を処理
-            let results = grep_from_string(pattern, input_text)?;
-
-            for (line_num, content) in results {
-                if input.is_some() {
-                    lines.push(content);
-                } else {
-                    lines.push(format!("{}: {}", line_num + 1, content));
-                }
+        // パイプラインからの入力を処理（なければ標準入力から読む）
+        // 入力はテキストの行として扱う（UTF-8でないバイトは U+FFFD にする）
+        let stdin;
+        let input = match input {
+            Some(input) => input,
+            None => {
+                stdin = read_standard_input()?;
+                &stdin
             }
+        };
+        let results = grep_from_string(&re, &String::from_utf8_lossy(input), &mut summary);
+        if count {
+            lines.push(results.len().to_string());
+        } else {
+            lines.extend(results.into_iter().map(|(_, content)| content));
         }
     } else {
         // 既存のファイル処理
         for file in files {
-            let results = grep_file(pattern, file)?;
+            let results = match grep_file(&re, file, &mut summary) {
+                Ok(results) => results,
+                Err(e) => {
+                    crate::output::eprint_line(&format!("grep: {file}: {}", describe_io_error(&e)));
+                    failed = true;
+                    continue;
+                }
+            };
 
-            if results.is_empty() {
+            if count {
+                if files.len() > 1 {
+                    lines.push(format!("{file}:{}", results.len()));
+                } else {
+                    lines.push(results.len().to_string());
+                }
                 continue;
             }
 
@@ -523,17 +1922,47 @@ pub fn handle_grep(pattern: &str, files: &[String], input: Option<&str>) -> Resu
         }
     }
 
-    Ok(lines.join("\n"))
+    // 集計は標準エラーに出すので、パイプやリダイレクト先の出力には混ざらない
+    if stats {
+        crate::output::eprint_line(&format!(
+            "grep: files searched: {}, files with matches: {}, matching lines: {}, time: {}",
+            summary.files_searched,
+            summary.files_matched,
+            summary.matching_lines,
+            format_elapsed(start.elapsed())
+        ));
+    }
+
+    // 読めないファイルがあれば2、一致する行がなければ1（POSIXのgrepと同じ）
+    let output = terminated_lines(&lines);
+    match (failed, summary.matching_lines == 0) {
+        (true, _) => Err(RucliError::CommandFailed {
+            output: output.into(),
+            status: 2,
+        }),
+        (false, true) => Err(RucliError::CommandFailed {
+            output: output.into(),
+            status: 1,
+        }),
+        (false, false) => Ok(output),
+    }
 }
 
-/// 単一ファイルを検索
-fn grep_file(pattern: &str, filepath: &str) -> Result<Vec<(usize, String)>> {
-    // 1. 最初に一度だけ正規表現をコンパイル
-    let re = match Regex::new(pattern) {
-        Ok(r) => r,
-        Err(e) => return Err(RucliError::InvalidRegex(e.to_string())),
-    };
+/// 入出力エラーの理由（`No such file or directory` のように末尾の `(os error N)` を除く）
+fn describe_io_error(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.find(" (os error ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
 
+/// 単一ファイルを検索（読み終えたファイルだけを集計に加える）
+fn grep_file(
+    re: &Regex,
+    filepath: &str,
+    summary: &mut GrepStats,
+) -> io::Result<Vec<(usize, String)>> {
     let file = fs::File::open(filepath)?;
     let reader = BufReader::new(file);
 
@@ -546,15 +1975,11 @@ fn grep_file(pattern: &str, filepath: &str) -> Result<Vec<(usize, String)>> {
         }
     }
 
+    summary.record(results.len());
     Ok(results)
 }
 
-fn grep_from_string(pattern: &str, text: &str) -> Result<Vec<(usize, String)>> {
-    let re = match Regex::new(pattern) {
-        Ok(r) => r,
-        Err(e) => return Err(RucliError::InvalidRegex(e.to_string())),
-    };
-
+fn grep_from_string(re: &Regex, text: &str, summary: &mut GrepStats) -> Vec<(usize, String)> {
     let mut results = Vec::new();
 
     for (line_num, line) in text.lines().enumerate() {
@@ -563,7 +1988,8 @@ fn grep_from_string(pattern: &str, text: &str) -> Result<Vec<(usize, String)>> {
         }
     }
 
-    Ok(results)
+    summary.record(results.len());
+    results
 }
 
 // handlers.rs に追加
@@ -574,72 +2000,255 @@ fn grep_from_string(pattern: &str, text: &str) -> Result<Vec<(usize, String)>> {
 /// * `name` - エイリアス名（Noneの場合は一覧表示）
 /// * `command` - エイリアスに設定するコマンド
 ///
+/// # Returns
+///
+/// 一覧表示の場合はエイリアスの一覧、設定時は空文字列
+///
 /// # Errors
 ///
 /// - 無効なエイリアス名の場合
-pub fn handle_alias(name: Option<&str>, command: Option<&str>) -> Result<()> {
+pub fn handle_alias(name: Option<&str>, command: Option<&str>) -> Result<String> {
     match (name, command) {
         (None, None) => {
             // ALIASESから全て取得して一覧表示
-            for (name, cmd) in list_aliases() {
-                println!("{name} = {cmd}");
-            }
+            Ok(list_aliases()
+                .iter()
+                .map(|(name, cmd)| format!("{name} = {cmd}"))
+                .collect::<Vec<_>>()
+                .join("\n"))
         }
         (Some(name), Some(cmd)) => {
             set_alias(name, cmd);
+            Ok(String::new())
         }
         _ => {
             // このパターンは来ないはず（パーサーで防いでいる）
             unreachable!()
         }
     }
-
-    Ok(())
 }
 
 /// バックグラウンド実行
-pub fn handle_background_execution(command: Box<Command>) -> Result<String> {
-    // 表示用のコマンド文字列
-    let cmd_str = format!("{command:?}");
-
+///
+/// # Arguments
+/// * `command` - 実行するコマンド
+/// * `text` - jobs・fgで表示する、入力されたとおりのコマンド
+/// * `stopped` - 停止した状態で作り、bg / fg まで実行しないか
+pub fn handle_background_execution(
+    command: Box<Command>,
+    text: String,
+    stopped: bool,
+) -> Result<String> {
     let job_id = job::get_next_job_id();
 
+    // ジョブは起動したシェルの状態を共有する
+    let shell_state = state::current();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let job_cancel = Arc::clone(&cancel);
+
+    // ジョブの登録より先に完了を通知しないよう、登録を待ってから実行する
+    let (registered_tx, registered_rx) = mpsc::channel::<()>();
+    // 停止した状態で作ったジョブは、bg / fg で開くまで実行を始めない
+    let start_gate = stopped.then(|| Arc::new(job::StartGate::default()));
+    let job_gate = start_gate.clone();
+
     // スレッドを起動
+    // 制御構文の中で起動したジョブは、起動元と同じくコマンド置換を実行時に行う
+    let deferred = substitution_deferred();
+
     let handle = thread::spawn(move || {
-        // ここで実際にコマンドが実行される（遅延）
-        if let Err(e) = execute_command(*command, None) {
-            eprintln!("Background job failed: {e}");
+        let _state = state::enter(shell_state);
+        job::run_as_job(Arc::clone(&job_cancel));
+        let _ = registered_rx.recv();
+        if let Some(gate) = job_gate
+            && !gate.wait(&job_cancel)
+        {
+            job::mark_completed(job_id, String::new(), 0, None);
+            return;
         }
-        // 完了を通知
-        job::mark_completed(job_id);
+
+        // ここで実際にコマンドが実行される（遅延）
+        // 出力はプロンプトの下に表示せず、ジョブに保存してfgで表示する
+        // パニックはこのスレッドで止め、ジョブを失敗にする（シェルは動作を続ける）
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            with_substitution_deferred(deferred, || execute_command_collecting(*command, None))
+        }));
+
+        // 中断を要求されて終わった場合は失敗として報告しない
+        // エラーはここでは表示せず、ジョブに保存して次のプロンプトの前に通知する
+        let (output, status, error) = match result {
+            Ok(Ok(CommandResult::Continue(output) | CommandResult::Exit(output))) => {
+                (output, 0, None)
+            }
+            Ok(Err(RucliError::Interrupted)) => (Vec::new(), 0, None),
+            Ok(Err(RucliError::CommandFailed { output, status })) => (output, status, None),
+            Ok(Err(e)) => (Vec::new(), e.exit_code(), Some(e.to_string())),
+            Err(payload) => {
+                job::mark_failed(job_id, panic_message(payload.as_ref()));
+                return;
+            }
+        };
+        // 完了を通知（出力はfgや通知で表示するだけなのでテキストにする）
+        let output = String::from_utf8_lossy(&output).into_owned();
+        job::mark_completed(job_id, output, status, error);
     });
 
     // スレッドIDを取得
     let thread_id = handle.thread().id();
 
     // ジョブ作成
-    job::create_job_with_id(job_id, cmd_str, thread_id);
+    match start_gate {
+        Some(gate) => job::create_stopped_job_with_id(job_id, text, thread_id, cancel, gate),
+        None => job::create_job_with_id(job_id, text, thread_id, cancel),
+    }
+    let _ = registered_tx.send(());
 
     // ユーザーに通知
     Ok(format!("[{job_id}] {thread_id:?}"))
 }
 
+/// パニックの内容を取り出す
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("panicked: {message}")
+}
+
 /// バージョン情報を表示する
 pub fn handle_version() -> String {
     format!("rucli v{}", env!("CARGO_PKG_VERSION"))
 }
 
-/// 一定秒数スリープ
-pub fn handle_sleep(seconds: u64) -> Result<()> {
-    thread::sleep(Duration::from_secs(seconds));
-    Ok(())
+/// コマンドを実行し、かかった時間を標準エラー出力に表示する
+///
+/// 実時間に加えて、シェル自身と子プロセスのユーザー/システムCPU時間を表示する。
+/// コマンドの出力と結果（エラーを含む）はそのまま返す
+///
+/// # Arguments
+/// * `command` - 計測するコマンド
+/// * `input` - パイプからの入力
+pub fn handle_time(command: Command, input: Option<&[u8]>) -> Result<CommandResult> {
+    let start = Instant::now();
+    let (user_start, sys_start) = cpu_times();
+
+    let result = execute_command_internal(command, input);
+
+    let real = start.elapsed();
+    let (user_end, sys_end) = cpu_times();
+    crate::output::eprint_line(&format!(
+        "real\t{}\nuser\t{}\nsys\t{}",
+        format_elapsed(real),
+        format_elapsed(user_end.saturating_sub(user_start)),
+        format_elapsed(sys_end.saturating_sub(sys_start)),
+    ));
+
+    result
+}
+
+/// evalコマンドを処理する
+///
+/// 展開済みの引数を空白でつなげ、コマンドとしてパースして現在のシェルで実行する。
+/// そのため変数の代入やディレクトリの移動は実行後も残る
+///
+/// # Arguments
+/// * `args` - 変数を展開した引数
+/// * `input` - パイプからの入力
+///
+/// # Errors
+/// * つなげた文字列がパースできない場合（文字列を含めて報告する）
+/// * 実行したコマンドが失敗した場合
+pub fn handle_eval(args: &[String], input: Option<&[u8]>) -> Result<CommandResult> {
+    let text = args.join(" ");
+    if text.trim().is_empty() {
+        return Ok(CommandResult::Continue(Vec::new()));
+    }
+
+    let command = crate::parser::parse_command(&text).map_err(|e| match e {
+        RucliError::ParseError(msg) => RucliError::ParseError(format!("eval '{text}': {msg}")),
+        e => e,
+    })?;
+    execute_command_internal(command, input)
+}
+
+/// シェル自身と終了した子プロセスのCPU時間（ユーザー, システム）の合計を取得する
+fn cpu_times() -> (Duration, Duration) {
+    let mut user = Duration::ZERO;
+    let mut sys = Duration::ZERO;
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            user += timeval_to_duration(usage.ru_utime);
+            sys += timeval_to_duration(usage.ru_stime);
+        }
+    }
+    (user, sys)
+}
+
+fn timeval_to_duration(time: libc::timeval) -> Duration {
+    Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+}
+
+/// 経過時間を `0m1.234s` の形式にする
+fn format_elapsed(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    format!(
+        "{}m{}.{:03}s",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// 一定時間スリープする
+///
+/// 短い間隔で中断の要求を確認し、要求があればすぐに打ち切る
+///
+/// # Errors
+///
+/// - 中断が要求された場合
+pub fn handle_sleep(duration: Duration) -> Result<()> {
+    const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+    let deadline = Instant::now() + duration;
+    loop {
+        if job::is_cancelled() {
+            return Err(RucliError::Interrupted);
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        thread::sleep(remaining.min(CHECK_INTERVAL));
+    }
 }
 
 /// ジョブ一覧表示
-pub fn handle_jobs() -> Result<String> {
+///
+/// 終了したジョブは一度だけ `Done` / `Exit N` / `Failed` と出力やエラーを付けて表示する
+///
+/// # Arguments
+/// * `format` - 出力形式（Jsonなら id/status/command/error の配列）
+pub fn handle_jobs(format: OutputFormat) -> Result<String> {
     // ジョブのリストを取得
     let jobs = job::list_jobs();
 
+    if format == OutputFormat::Json {
+        let entries: Vec<JobEntry> = jobs
+            .iter()
+            .map(|job| JobEntry {
+                id: job.id,
+                status: job_label(job),
+                command: &job.command,
+                error: job.error.as_deref(),
+            })
+            .collect();
+        return to_json(&entries);
+    }
+
     // 何も入っていない
     if jobs.is_empty() {
         return Ok("No jobs".to_string());
@@ -663,60 +2272,134 @@ pub fn handle_jobs() -> Result<String> {
             " "
         };
 
-        // 実際のステータスを表示
-        let status = match job.status {
-            job::JobStatus::Running => "Running",
-            job::JobStatus::Completed => "Done", // 通常は表示されないが念のため
-        };
-
-        lines.push(format!(
-            "[{}]{} {:10} {}",
-            job.id,      // [1]
-            marker,      // +
-            status,      // status   (10文字幅)
-            job.command  // sleep 10
-        ));
+        lines.push(format_job_notice(job, marker));
     }
 
     Ok(lines.join("\n"))
 }
 
+/// 終了したバックグラウンドジョブの通知（対話モードでプロンプトの前に表示する）
+///
+/// 各ジョブは一度だけ通知し、その出力も続けて表示する。失敗したジョブはエラーも表示する:
+/// `[2]- Exit 1     cat missing.txt: IO error: No such file or directory (os error 2)`
+pub fn job_notifications() -> Vec<String> {
+    job::take_finished_notices()
+        .iter()
+        .map(|(job, marker)| format_job_notice(job, marker))
+        .collect()
+}
+
+/// `jobs` と通知の表示（終了したジョブは1行の後に出力を続ける）
+fn format_job_notice(job: &job::Job, marker: &str) -> String {
+    let line = format_job_line(job, marker);
+    let output = job.output.trim_end_matches('\n');
+    if job.is_active() || output.is_empty() {
+        line
+    } else {
+        format!("{line}\n{output}")
+    }
+}
+
+/// `jobs` と通知の1行（`[1]+ Running    sleep 10`）
+fn format_job_line(job: &job::Job, marker: &str) -> String {
+    let line = format!("[{}]{marker} {:10} {}", job.id, job_label(job), job.command);
+    match &job.error {
+        Some(error) => format!("{line}: {error}"),
+        None => line,
+    }
+}
+
+/// ジョブのステータスの表示名（0以外で終了したジョブは `Exit N`）
+fn job_label(job: &job::Job) -> String {
+    match job.status {
+        job::JobStatus::Running => "Running".to_string(),
+        job::JobStatus::Stopped => "Stopped".to_string(),
+        job::JobStatus::Completed if job.exit_status != 0 => format!("Exit {}", job.exit_status),
+        job::JobStatus::Completed => "Done".to_string(),
+        job::JobStatus::Failed => "Failed".to_string(),
+    }
+}
+
 /// フォアグラウンド変更
-pub fn handle_fg(job_id: Option<u32>) -> Result<()> {
-    // 1. 対象ジョブの決定
-    let target_id = match job_id {
-        Some(id) => id,
-        None => {
-            // 最新のジョブIDを取得
-            let jobs = job::list_jobs();
-            if jobs.is_empty() {
-                return Err(RucliError::InvalidArgument("No jobs".to_string()));
-            }
-            jobs.last().unwrap().id
-        }
-    };
+///
+/// ジョブの完了を待ち、その出力を返す（完了済みのジョブならすぐに返す）
+/// 停止しているジョブは実行を始めてから待つ
+///
+/// # Errors
+/// - ジョブが存在しない場合
+/// - 待っている間に中断が要求された場合
+pub fn handle_fg(job: Option<job::JobSpec>) -> Result<String> {
+    // 1. 対象ジョブの決定（省略時は最新のジョブ）
+    let target_id = job.unwrap_or(job::JobSpec::Current).resolve()?;
+    if job::get_job(target_id).is_some_and(|job| matches!(job.status, job::JobStatus::Stopped)) {
+        job::resume_job(target_id)?;
+    }
 
-    // 2. ジョブを取得
-    match job::get_job(target_id) {
-        Some(job) => {
-            // 3. 状態を表示
-            println!("Job [{}] ({}) is still running", job.id, job.command);
-            // 将来: ここで待機処理
-            Ok(())
+    // 2. 完了を待って出力を返す
+    match job::wait_for_job(target_id)? {
+        Some(job) if matches!(job.status, job::JobStatus::Failed) => Err(RucliError::RuntimeError(
+            format!("job {target_id} failed: {}", job.command),
+        )),
+        // 失敗したジョブはエラーを表示し、その終了ステータスを返す
+        Some(job) if job.exit_status != 0 => {
+            if let Some(error) = &job.error {
+                crate::output::eprint_line(error);
+            }
+            Err(RucliError::CommandFailed {
+                output: job.output.into(),
+                status: job.exit_status,
+            })
         }
+        Some(job) => Ok(job.output),
         None => Err(RucliError::InvalidArgument(format!(
             "No such job: {target_id}"
         ))),
     }
 }
 
+/// 停止しているジョブをバックグラウンドで実行する
+///
+/// # Returns
+/// * 実行を始めたジョブ（`[1] sleep 10 &`）
+///
+/// # Errors
+/// - ジョブが存在しない場合
+/// - ジョブがすでに実行中か終了している場合
+pub fn handle_bg(job: Option<job::JobSpec>) -> Result<String> {
+    let target_id = job.unwrap_or(job::JobSpec::Current).resolve()?;
+    let job = job::resume_job(target_id)?;
+    Ok(format!("[{}] {} &", job.id, job.command))
+}
+
 /// 環境変数コマンドのハンドラ
-pub fn handle_environment(action: EnvironmentAction) -> Result<String> {
+///
+/// # Arguments
+/// * `action` - 一覧・表示・設定のいずれか
+/// * `format` - 一覧・表示の出力形式（Jsonなら name/value の配列）
+pub fn handle_environment(action: EnvironmentAction, format: OutputFormat) -> Result<String> {
     let mut lines = Vec::new();
 
     match action {
-        EnvironmentAction::List => {
-            let env_list = list_all_vars();
+        EnvironmentAction::List {
+            pattern,
+            session_only,
+        } => {
+            let mut env_list = if session_only {
+                list_session_vars()
+            } else {
+                list_all_vars()
+            };
+            if let Some(pattern) = pattern {
+                env_list.retain(|(name, _)| matches_pattern(name, &pattern));
+            }
+
+            if format == OutputFormat::Json {
+                let entries: Vec<EnvEntry> = env_list
+                    .iter()
+                    .map(|(name, value)| EnvEntry { name, value })
+                    .collect();
+                return to_json(&entries);
+            }
             for (name, value) in env_list {
                 lines.push(format!("{name}={value}"));
             }
@@ -724,6 +2407,12 @@ pub fn handle_environment(action: EnvironmentAction) -> Result<String> {
         }
         EnvironmentAction::Show(var_name) => {
             if let Some(value) = get_var(&var_name) {
+                if format == OutputFormat::Json {
+                    return to_json(&[EnvEntry {
+                        name: &var_name,
+                        value: &value,
+                    }]);
+                }
                 Ok(value)
             } else {
                 Err(RucliError::InvalidArgument(format!(
@@ -735,7 +2424,522 @@ pub fn handle_environment(action: EnvironmentAction) -> Result<String> {
             set_var(var_name.as_str(), value.as_str());
             Ok(String::new())
         }
+        EnvironmentAction::Run { .. } => {
+            // このパターンは来ないはず（handle_env_runで実行する）
+            unreachable!()
+        }
+    }
+}
+
+/// 変数を一時的に上書きしてコマンドを実行する（`env [-i] VAR=value... command`）
+///
+/// 上書きはコマンドの実行中だけ有効で、セッション変数は変更しない
+///
+/// # Arguments
+/// * `isolated` - 上書きした変数だけで実行するか（-i）
+/// * `overrides` - 上書きする (変数名, 値) の一覧
+/// * `command` - 実行するコマンド
+pub fn handle_env_run(
+    isolated: bool,
+    overrides: &[(String, String)],
+    command: Command,
+    input: Option<&[u8]>,
+) -> Result<CommandResult> {
+    with_environment(isolated, overrides, || {
+        execute_command_internal(command, input)
+    })
+}
+
+/// exportコマンドのハンドラ
+///
+/// 値があればセッション変数に設定してから、システム環境変数にも設定する
+///
+/// # Arguments
+/// * `variables` - エクスポートする (変数名, 値) の一覧
+pub fn handle_export(variables: &[(String, Option<String>)]) {
+    for (name, value) in variables {
+        if let Some(value) = value {
+            set_var(name, value);
+        }
+        export_var(name);
+    }
+}
+
+/// 変数代入を実行する
+///
+/// # Arguments
+/// * `assignments` - 代入する (変数名, 値) の一覧
+/// * `command` - 一時的な代入で実行する、引数を展開済みのコマンド（Noneなら代入を保持）
+///
+/// # Returns
+/// * commandの出力（代入のみの場合は空文字列）
+pub fn handle_assignment(
+    assignments: &[(String, String)],
+    command: Option<Command>,
+    input: Option<&[u8]>,
+) -> Result<CommandResult> {
+    let Some(command) = command else {
+        for (name, value) in assignments {
+            set_var(name, value);
+        }
+        return Ok(CommandResult::Continue(Vec::new()));
+    };
+
+    // 元の値を退避してから一時的に設定
+    let saved: Vec<(String, Option<String>)> = assignments
+        .iter()
+        .map(|(name, _)| (name.clone(), get_session_var(name)))
+        .collect();
+    for (name, value) in assignments {
+        set_var(name, value);
     }
+
+    // 引数は代入前に展開済みなので、改めて展開しない
+    let result = execute_expanded(command, input);
+
+    // 実行結果に関わらず元に戻す（逆順で重複名にも対応）
+    for (name, old) in saved.iter().rev() {
+        match old {
+            Some(value) => set_var(name, value),
+            None => unset_var(name),
+        }
+    }
+
+    result
+}
+
+/// 入力から一行読み込んで変数に代入する
+///
+/// 入力元の優先順位: パイプラインの入力 → ループの入力（`done < file`）→ 標準入力
+///
+/// # Arguments
+/// * `prompt` - 標準入力から読む前に表示するプロンプト
+/// * `variables` - 代入先の変数名（空ならREPLY）
+/// * `input` - パイプラインからの入力
+///
+/// # Errors
+/// * 入力が終端に達した場合
+pub fn handle_read(
+    prompt: Option<&str>,
+    variables: &[String],
+    input: Option<&[u8]>,
+) -> Result<String> {
+    let line = if let Some(input) = input {
+        String::from_utf8_lossy(input)
+            .lines()
+            .next()
+            .map(|line| line.to_string())
+    } else if crate::input::is_active() {
+        crate::input::next_line()
+    } else {
+        if let Some(prompt) = prompt {
+            crate::output::write_stderr(prompt);
+        }
+        let mut buffer = String::new();
+        if io::stdin().read_line(&mut buffer)? == 0 {
+            None
+        } else {
+            Some(buffer.trim_end_matches(['\n', '\r']).to_string())
+        }
+    };
+
+    let line = line.ok_or(RucliError::RuntimeError("read: end of input".to_string()))?;
+    debug!("read: '{line}'");
+
+    if variables.is_empty() {
+        set_var("REPLY", &line);
+        return Ok(String::new());
+    }
+
+    // 空白で分割し、余りは最後の変数にまとめる
+    let mut rest = line.trim_start();
+    for (i, name) in variables.iter().enumerate() {
+        if i == variables.len() - 1 {
+            set_var(name, rest.trim_end());
+            break;
+        }
+        let (word, remaining) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        set_var(name, word);
+        rest = remaining.trim_start();
+    }
+
+    Ok(String::new())
+}
+
+/// 数列を生成する
+///
+/// `seq LAST` / `seq FIRST LAST` / `seq FIRST STEP LAST` に対応する
+///
+/// # Arguments
+/// * `args` - 変数展開済みの数値引数（1〜3個）
+/// * `equal_width` - 先頭をゼロ埋めして桁数を揃えるか
+///
+/// # Returns
+/// * 一行に一つの数値を並べた文字列
+///
+/// # Errors
+/// * 数値でない引数、ステップが0、引数の個数が不正な場合
+pub fn handle_seq(args: &[String], equal_width: bool) -> Result<String> {
+    let usage_error = |message: String| {
+        let usage = COMMANDS
+            .iter()
+            .find(|cmd| cmd.name == "seq")
+            .map(|cmd| cmd.usage)
+            .unwrap_or_default();
+        RucliError::ParseError(format!("seq: {message}\nUsage: {usage}"))
+    };
+
+    let numbers = args
+        .iter()
+        .map(|arg| {
+            arg.parse::<i64>()
+                .map_err(|_| usage_error(format!("invalid number '{arg}'")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (first, step, last) = match numbers[..] {
+        [last] => (1, 1, last),
+        [first, last] => (first, 1, last),
+        [first, step, last] => (first, step, last),
+        _ => return Err(usage_error("expected 1 to 3 numbers".to_string())),
+    };
+
+    if step == 0 {
+        return Err(usage_error("step must not be zero".to_string()));
+    }
+
+    let width = if equal_width {
+        first.to_string().len().max(last.to_string().len())
+    } else {
+        0
+    };
+
+    let mut lines = Vec::new();
+    let mut current = Some(first);
+    while let Some(n) = current {
+        if (step > 0 && n > last) || (step < 0 && n < last) {
+            break;
+        }
+        lines.push(format!("{n:0width$}"));
+        current = n.checked_add(step);
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// 入力を引数としてコマンドを組み立て、実行する
+///
+/// # Arguments
+/// * `command` - 実行するコマンドと初期引数（空ならecho）
+/// * `max_args` - 一回の実行に渡す引数の最大数（Noneなら全て一度に渡す）
+/// * `replace` - 置換文字列（指定時は入力一行ごとに実行）
+/// * `exit_on_error` - 失敗した時点で残りの実行を中止するか
+/// * `input` - パイプラインからの入力
+///
+/// # Returns
+/// * 各実行の出力を連結した文字列（exitした場合はそこで止めてExit）
+///
+/// # Errors
+/// * `exit_on_error` が指定され、いずれかの実行が失敗した場合
+pub fn handle_xargs(
+    command: &[String],
+    max_args: Option<usize>,
+    replace: Option<&str>,
+    exit_on_error: bool,
+    input: Option<&[u8]>,
+) -> Result<CommandResult> {
+    let input = String::from_utf8_lossy(input.unwrap_or_default());
+    let base = if command.is_empty() {
+        "echo".to_string()
+    } else {
+        command.join(" ")
+    };
+
+    // 実行するコマンド文字列を組み立てる
+    let command_lines: Vec<String> = if let Some(replace) = replace {
+        input
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| base.replace(replace, line))
+            .collect()
+    } else {
+        let items: Vec<&str> = input.split_whitespace().collect();
+        let batch_size = max_args.unwrap_or(items.len()).max(1);
+        if items.is_empty() {
+            vec![base]
+        } else {
+            items
+                .chunks(batch_size)
+                .map(|batch| format!("{base} {}", batch.join(" ")))
+                .collect()
+        }
+    };
+
+    let mut outputs = Vec::new();
+    for command_line in command_lines {
+        debug!("xargs: executing '{command_line}'");
+
+        let result = crate::parser::parse_command(&command_line)
+            .and_then(|cmd| execute_command_internal(cmd, None));
+
+        match result {
+            Ok(CommandResult::Continue(output)) => {
+                if !output.is_empty() {
+                    outputs.push(output);
+                }
+            }
+            // exitは残りのコマンドを実行せずにシェルまで伝える
+            Ok(CommandResult::Exit(output)) => {
+                if !output.is_empty() {
+                    outputs.push(output);
+                }
+                return Ok(CommandResult::Exit(outputs.concat()));
+            }
+            Err(e) if exit_on_error => {
+                return Err(RucliError::RuntimeError(format!(
+                    "xargs: {command_line}: {e}"
+                )));
+            }
+            Err(e) => crate::output::eprint_line(&format!("xargs: {command_line}: {e}")),
+        }
+    }
+
+    Ok(CommandResult::Continue(outputs.concat()))
+}
+
+/// コマンド名がどのように解決されるかを表示する
+///
+/// パーサーと同じ順序（エイリアス → ビルトイン → 関数 → PATH）で調べる
+///
+/// # Arguments
+/// * `names` - 調べるコマンド名
+/// * `external_only` - PATH上の外部コマンドのみを調べるか（which）
+///
+/// # Returns
+/// * 一行に一つの解決結果
+///
+/// # Errors
+/// * 解決できない名前が一つでもある場合（`NAME: not found` を標準エラーに表示し、
+///   解決できた分を出力に持つステータス127の失敗として返す）
+pub fn handle_type(names: &[String], external_only: bool) -> Result<String> {
+    let mut lines = Vec::new();
+    let mut not_found = Vec::new();
+
+    for name in names {
+        let resolved = if external_only {
+            find_in_path(name).map(|path| path.display().to_string())
+        } else if let Some(alias) = crate::alias::get_alias(name) {
+            Some(format!("{name} is aliased to `{alias}`"))
+        } else if COMMANDS.iter().any(|cmd| cmd.name == name) {
+            Some(format!("{name} is a shell builtin"))
+        } else if functions::is_function(name) {
+            Some(format!("{name} is a function"))
+        } else {
+            find_in_path(name).map(|path| format!("{name} is {}", path.display()))
+        };
+
+        match resolved {
+            Some(line) => lines.push(line),
+            None => not_found.push(format!("{name}: not found")),
+        }
+    }
+
+    let output = lines.join("\n");
+    if not_found.is_empty() {
+        return Ok(output);
+    }
+
+    for line in &not_found {
+        crate::output::eprint_line(line);
+    }
+    Err(RucliError::CommandFailed {
+        output: output.into(),
+        status: 127,
+    })
+}
+
+/// サブシェル（`( ... )`）のハンドラー
+///
+/// 実行前の変数・エイリアス・関数・オプション・環境変数・カレントディレクトリ・umaskを保存し、
+/// 実行後に元に戻す。中での代入や cd は呼び出し元に影響しない
+///
+/// # Arguments
+/// * `command` - サブシェルで実行するコマンド
+/// * `input` - パイプからの入力
+///
+/// # Returns
+/// * コマンドの出力
+///
+/// # Errors
+/// * 中のコマンドのエラー（exitで終了した場合は0以外のステータス）
+/// * カレントディレクトリを取得・復元できない場合
+pub fn handle_subshell(command: Command, input: Option<&[u8]>) -> Result<Vec<u8>> {
+    // バックグラウンドのジョブは起動したシェルと状態を共有しているので、書き換えて後で戻すと
+    // 実行中にフォアグラウンドから変更が見えてしまう。複製した状態で実行する
+    // （カレントディレクトリと umask は job::run_as_job でスレッド専用になっている）
+    if job::in_background() {
+        let _state = state::enter(Arc::new(state::current().fork()));
+        let result = execute_command_internal(command, input);
+        return subshell_output(result);
+    }
+
+    let state = state::current();
+    let snapshot = state.snapshot();
+    let saved_env: HashMap<OsString, OsString> = env::vars_os().collect();
+    let saved_dir = env::current_dir()?;
+    let saved_umask = current_umask();
+
+    let result = execute_command_internal(command, input);
+
+    state.restore(snapshot);
+    restore_env_vars(saved_env);
+    env::set_current_dir(saved_dir)?;
+    set_umask(saved_umask);
+
+    subshell_output(result)
+}
+
+/// サブシェルの実行結果を出力にする（exitはサブシェルだけを終了する）
+fn subshell_output(result: Result<CommandResult>) -> Result<Vec<u8>> {
+    match result? {
+        CommandResult::Continue(output) => Ok(output),
+        // exitはサブシェルだけを終了する
+        CommandResult::Exit(output) => match crate::environment::get_last_status() {
+            0 => Ok(output),
+            status => Err(RucliError::CommandFailed { output, status }),
+        },
+    }
+}
+
+/// 環境変数を保存しておいた内容に戻す
+fn restore_env_vars(saved: HashMap<OsString, OsString>) {
+    for (name, value) in env::vars_os() {
+        if saved.get(&name) != Some(&value) {
+            unsafe {
+                env::remove_var(&name);
+            }
+        }
+    }
+    for (name, value) in saved {
+        if env::var_os(&name).is_none() {
+            unsafe {
+                env::set_var(name, value);
+            }
+        }
+    }
+}
+
+/// execコマンドのハンドラー
+///
+/// 引数があれば、シェルのプロセスをそのコマンドで置き換える（成功すると戻らない）。
+/// シェルの変数は置き換えたコマンドの環境変数として渡す。
+/// 引数がなければ、リダイレクトをシェル自身に適用する
+///
+/// # Arguments
+/// * `args` - 実行するコマンドと引数
+/// * `redirects` - 適用するリダイレクト
+///
+/// # Errors
+/// * コマンドが見つからない・実行できない場合（シェルはそのまま続行する）
+/// * リダイレクト先のファイルを開けない場合
+pub fn handle_exec(args: &[String], redirects: &[Redirection]) -> Result<String> {
+    let Some((program, program_args)) = args.split_first() else {
+        redirect_shell(redirects)?;
+        return Ok(String::new());
+    };
+
+    let mut process = std::process::Command::new(program);
+    // env -i で実行中なら、上書きした変数だけを渡す
+    if is_environment_isolated() {
+        process.env_clear();
+    }
+    process.args(program_args).envs(list_session_vars());
+    redirect_process(&mut process, redirects)?;
+
+    info!("Replacing the shell with '{program}'");
+    let error = process.exec();
+
+    // ここに来るのはexecに失敗した場合だけ
+    match error.kind() {
+        io::ErrorKind::NotFound => Err(RucliError::UnknownCommand(format!("exec: {program}"))),
+        _ => Err(RucliError::RuntimeError(format!(
+            "exec: {program}: {error}"
+        ))),
+    }
+}
+
+/// setコマンドのハンドラー
+///
+/// # Arguments
+/// * `options` - 設定するオプションと有効・無効（左から順に適用）
+///
+/// # Returns
+/// * 設定がなければ全オプションの状態の一覧、あれば空文字列
+///
+/// # Errors
+/// * 存在しないオプション名の場合
+pub fn handle_set(options: &[(String, bool)]) -> Result<String> {
+    if options.is_empty() {
+        return Ok(crate::options::list_options()
+            .into_iter()
+            .map(|(name, enabled)| format!("{name:<15} {}", if enabled { "on" } else { "off" }))
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+
+    for (name, enabled) in options {
+        crate::options::set_option(name, *enabled)?;
+    }
+
+    Ok(String::new())
+}
+
+/// trapコマンドを処理する
+///
+/// # Returns
+/// * 一覧の表示（`trap -- 'command' SIGNAL` の形式）。登録・削除では空文字列
+pub fn handle_trap(action: TrapAction) -> Result<String> {
+    match action {
+        TrapAction::List => Ok(crate::trap::list_traps()
+            .into_iter()
+            .map(|(signal, command)| format!("trap -- '{command}' {}", signal.name()))
+            .collect::<Vec<_>>()
+            .join("\n")),
+        TrapAction::Set { command, signals } => {
+            for signal in signals {
+                crate::trap::set_trap(signal, &command);
+            }
+            Ok(String::new())
+        }
+        TrapAction::Remove(signals) => {
+            for signal in signals {
+                crate::trap::remove_trap(signal);
+            }
+            Ok(String::new())
+        }
+    }
+}
+
+/// PATHから実行可能ファイルを探す
+fn find_in_path(name: &str) -> Option<std::path::PathBuf> {
+    // パスを含む名前はそのまま確認する
+    if name.contains('/') {
+        let path = Path::new(name);
+        return is_executable(path).then(|| path.to_path_buf());
+    }
+
+    let paths = env::var("PATH").ok()?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+/// 実行権限のある通常ファイルかチェック
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
 }
 
 /// 関数を定義する
@@ -756,35 +2960,24 @@ pub fn handle_function_definition(name: &str, body: Command) -> Result<()> {
 /// * `args` - 関数に渡す引数
 ///
 /// # Returns
-/// * 関数の実行結果（文字列）
+/// * 関数の実行結果（関数内でexitした場合はExit）
 ///
-pub fn handle_function_call(name: &str, args: &[String]) -> Result<String> {
+/// # Errors
+/// - 関数の呼び出しの入れ子が RUCLI_MAX_DEPTH を超えた場合
+pub fn handle_function_call(name: &str, args: &[String]) -> Result<CommandResult> {
     if let Some(cmd) = functions::get_function(name) {
-        // 引数の設定
-        for (i, arg) in args.iter().enumerate() {
-            let var_name = (i + 1).to_string(); // "1", "2", ...
-            unsafe {
-                std::env::set_var(&var_name, arg);
-            }
-        }
+        // 引数はセッション変数 "1", "2", ... に設定し、呼び出し元の引数は終了後に戻す
+        let saved_args = replace_positional_args(args);
 
-        let cmd_str = match execute_command_internal(cmd, None)? {
-            CommandResult::Continue(output) => output,
-            CommandResult::Exit => {
-                // 関数内でのExitは無視して空文字列を返す
-                String::new()
-            }
-        };
+        // 本体はコマンド置換を残してパースしてあるので、実行時に置換する
+        let result = nested(&format!("function '{name}'"), || {
+            with_substitution_deferred(true, || execute_command_internal(cmd, None))
+        });
 
-        // 引数のクリーンアップ
-        for i in 0..args.len() {
-            let var_name = (i + 1).to_string();
-            unsafe {
-                std::env::remove_var(&var_name);
-            }
-        }
+        replace_positional_args(&saved_args);
 
-        Ok(cmd_str)
+        // 関数内のexitは呼び出し元にそのまま伝え、シェルを終了する
+        result
     } else {
         Err(RucliError::UnknownCommand(format!(
             "function '{name}' not found"
@@ -792,38 +2985,176 @@ pub fn handle_function_call(name: &str, args: &[String]) -> Result<String> {
     }
 }
 
+/// 関数の引数（セッション変数 "1", "2", ...）を置き換え、それまでの引数を返す
+fn replace_positional_args(args: &[String]) -> Vec<String> {
+    let mut previous = Vec::new();
+    while let Some(value) = get_session_var(&(previous.len() + 1).to_string()) {
+        unset_var(&(previous.len() + 1).to_string());
+        previous.push(value);
+    }
+
+    for (i, arg) in args.iter().enumerate() {
+        set_var(&(i + 1).to_string(), arg);
+    }
+    previous
+}
+
 /// 履歴コマンドのハンドラー
-pub fn handle_history(action: HistoryAction) -> Result<String> {
+///
+/// # Arguments
+/// * `action` - 履歴に対する操作
+/// * `format` - 一覧・検索結果の出力形式（Jsonなら index/command の配列）
+pub fn handle_history(action: HistoryAction, format: OutputFormat) -> Result<String> {
     match action {
-        HistoryAction::List => {
-            let list = get_history_list();
-
-            Ok(list
-                .iter()
-                .map(|(num, cmd)| format!("{num:4}  {cmd}"))
-                .collect::<Vec<_>>()
-                .join("\n"))
-        }
-        HistoryAction::Search(query) => {
-            let list = search_history(&query);
+        HistoryAction::List => format_history_list(&get_history_list(), format),
+        HistoryAction::Recent(count) => format_history_list(&get_recent_history(count), format),
+        HistoryAction::Search { query, regex, all } => {
+            let pattern = search_pattern(&query, regex)?;
+            let mut matches = search_history(&pattern);
+            if !all {
+                matches.truncate(SEARCH_RESULT_LIMIT);
+            }
 
-            Ok(list
-                .iter()
-                .map(|(num, cmd)| format!("{num:4}  {cmd}"))
-                .collect::<Vec<_>>()
-                .join("\n"))
+            // 端末に表示するときは一致した部分を強調する
+            if format == OutputFormat::Text && crate::output::stdout_is_terminal() {
+                for (_, command) in &mut matches {
+                    *command = highlight_match(&pattern, command);
+                }
+            }
+            format_history_list(&matches, format)
         }
-        HistoryAction::Execute(index) => match get_history_by_number(index) {
+        HistoryAction::Execute { index, .. } => match get_history_by_number(index) {
             Some(cmd) => Ok(cmd),
             None => Err(RucliError::InvalidArgument(format!(
                 "history: {index}: history position out of range",
             ))),
         },
+        HistoryAction::Clear => {
+            clear_history();
+            Ok(String::new())
+        }
+        HistoryAction::Delete(index) => match delete_history(index) {
+            Some(_) => Ok(String::new()),
+            None => Err(RucliError::InvalidArgument(format!(
+                "history: {index}: history position out of range",
+            ))),
+        },
+        HistoryAction::Substitute {
+            replacement,
+            target,
+        } => {
+            let command = resolve_fc_target(target.as_deref())?;
+            Ok(match replacement {
+                Some((old, new)) => command.replace(&old, &new),
+                None => command,
+            })
+        }
+        HistoryAction::Edit(target) => edit_command(&resolve_fc_target(target.as_deref())?),
+    }
+}
+
+/// 最初に一致した部分を太字の赤で強調する（空の一致はそのまま）
+fn highlight_match(pattern: &Regex, text: &str) -> String {
+    match pattern.find(text) {
+        Some(found) if !found.is_empty() => format!(
+            "{}\x1b[1;31m{}\x1b[0m{}",
+            &text[..found.start()],
+            found.as_str(),
+            &text[found.end()..]
+        ),
+        _ => text.to_string(),
+    }
+}
+
+/// fcの対象のコマンドを取得する
+///
+/// # Errors
+/// - 該当するコマンドが履歴にない場合
+fn resolve_fc_target(target: Option<&str>) -> Result<String> {
+    find_fc_target(target).ok_or_else(|| {
+        RucliError::InvalidArgument(format!(
+            "fc: {}: no command found",
+            target.unwrap_or("(last)")
+        ))
+    })
+}
+
+/// コマンドを一時ファイルに書き、エディタで編集した結果を返す
+///
+/// エディタは RUCLI_EDITOR、EDITOR の順に探し、どちらもなければ vi を使う。
+/// RUCLI_FC_NOEDIT が設定されていればエディタを起動せずにそのまま返す（テスト用）。
+/// 複数行に編集した場合は各行を `;` でつなぐ
+///
+/// # Errors
+/// - 一時ファイルを読み書きできない場合
+/// - エディタを起動できない、または失敗した場合
+fn edit_command(command: &str) -> Result<String> {
+    if get_var("RUCLI_FC_NOEDIT").is_some_and(|value| !value.is_empty()) {
+        return Ok(command.to_string());
+    }
+
+    let editor = get_var("RUCLI_EDITOR")
+        .or_else(|| get_var("EDITOR"))
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let path = env::temp_dir().join(format!("rucli-fc-{}.sh", std::process::id()));
+    fs::write(&path, format!("{command}\n"))?;
+
+    // エディタは端末をそのまま使う
+    debug!("Launching editor: {editor} {}", path.display());
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status();
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    let status = status
+        .map_err(|e| RucliError::RuntimeError(format!("fc: {program}: failed to start: {e}")))?;
+    if !status.success() {
+        return Err(RucliError::RuntimeError(format!(
+            "fc: {program}: editor exited with {status}"
+        )));
+    }
+
+    Ok(edited?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("; "))
+}
+
+/// 番号付きの履歴一覧を出力形式に合わせて整形する
+fn format_history_list(list: &[(usize, String)], format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Text => Ok(list
+            .iter()
+            .map(|(num, cmd)| format!("{num:4}  {cmd}"))
+            .collect::<Vec<_>>()
+            .join("\n")),
+        OutputFormat::Json => {
+            let entries: Vec<HistoryEntry> = list
+                .iter()
+                .map(|(index, command)| HistoryEntry {
+                    index: *index,
+                    command,
+                })
+                .collect();
+            to_json(&entries)
+        }
     }
 }
 
 /// プログラムを終了する
-pub fn handle_exit() {
+///
+/// # Returns
+///
+/// 終了時に表示するメッセージ
+pub fn handle_exit() -> String {
     info!("Exiting rucli");
-    println!("good bye");
+    "good bye".to_string()
 }
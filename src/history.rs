@@ -1,18 +1,104 @@
-use crate::error::Result;
+use crate::environment::get_var;
+use crate::error::{Result, RucliError};
+use crate::handlers::matches_pattern;
+use crate::state::{self, LockExt};
 use log::debug;
-use once_cell::sync::Lazy;
-use std::collections::VecDeque;
+use regex::{Regex, RegexBuilder};
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
 use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
 
-// グローバルな履歴インスタンス
-static HISTORY: Lazy<Mutex<History>> = Lazy::new(|| Mutex::new(History::new(1000)));
+/// RUCLI_HISTSIZE が未設定の場合の最大保存数
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+thread_local! {
+    /// 再実行中のコマンド（履歴が自分自身を再実行し続けるのを防ぐ）
+    static EXECUTING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 履歴に追加するコマンドの絞り込み設定
+#[derive(Debug, Clone, PartialEq)]
+struct HistoryFilter {
+    ignore_patterns: Vec<String>, // 記録しないコマンドのパターン
+    ignore_dups: bool,            // 直前と同じコマンドを記録しない
+    erase_dups: bool,             // 同じコマンドの古い履歴を削除する
+    ignore_space: bool,           // 空白で始まるコマンドを記録しない
+}
+
+impl Default for HistoryFilter {
+    /// 設定がない場合は直前との重複のみ除外する
+    fn default() -> Self {
+        HistoryFilter::new(None, None)
+    }
+}
+
+impl HistoryFilter {
+    /// RUCLI_HISTIGNORE / RUCLI_HISTCONTROL 形式の値から作成
+    ///
+    /// # Arguments
+    /// * `ignore` - コロン区切りのワイルドカードパターン
+    /// * `control` - ignoredups / erasedups / ignorespace / ignoreboth（コロンかカンマ区切り）
+    fn new(ignore: Option<&str>, control: Option<&str>) -> Self {
+        let ignore_patterns = ignore
+            .unwrap_or("")
+            .split(':')
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| pattern.to_string())
+            .collect();
+
+        let Some(control) = control else {
+            return HistoryFilter {
+                ignore_patterns,
+                ignore_dups: true,
+                erase_dups: false,
+                ignore_space: false,
+            };
+        };
+
+        let modes: Vec<&str> = control.split([':', ',']).map(|mode| mode.trim()).collect();
+        let has = |name: &str| modes.contains(&name);
+
+        HistoryFilter {
+            ignore_patterns,
+            ignore_dups: has("ignoredups") || has("ignoreboth"),
+            erase_dups: has("erasedups"),
+            ignore_space: has("ignorespace") || has("ignoreboth"),
+        }
+    }
+
+    /// 環境変数から設定を読み込む
+    fn from_env() -> Self {
+        HistoryFilter::new(
+            get_var("RUCLI_HISTIGNORE").as_deref(),
+            get_var("RUCLI_HISTCONTROL").as_deref(),
+        )
+    }
+
+    /// 設定により記録対象外となるコマンドかチェック
+    fn ignores(&self, command: &str) -> bool {
+        if self.ignore_space && command.starts_with([' ', '\t']) {
+            return true;
+        }
+
+        let command = command.trim();
+        self.ignore_patterns
+            .iter()
+            .any(|pattern| matches_pattern(command, pattern))
+    }
+}
 
 // コマンド履歴を保存する構造体
-struct History {
+pub(crate) struct History {
     commands: VecDeque<String>, // 履歴を保存
     max_size: usize,            // 最大保存数
+    filter: HistoryFilter,      // 記録するコマンドの絞り込み
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_HISTORY_SIZE)
+    }
 }
 
 impl History {
@@ -21,6 +107,7 @@ impl History {
         History {
             commands: VecDeque::with_capacity(max_size),
             max_size,
+            filter: HistoryFilter::default(),
         }
     }
 
@@ -31,11 +118,23 @@ impl History {
             return;
         }
 
+        // 記録対象外のコマンドであれば早期return
+        if self.filter.ignores(&command) {
+            return;
+        }
+        let command = command.trim_start().to_string();
+
         // 最後の命令と同じであれば早期return
-        if matches!(self.commands.back(), Some(last) if last == &command) {
+        if self.filter.ignore_dups && matches!(self.commands.back(), Some(last) if last == &command)
+        {
             return;
         }
 
+        // 同じコマンドの古い履歴を削除
+        if self.filter.erase_dups {
+            self.commands.retain(|existing| existing != &command);
+        }
+
         // 保持できる最大個数を超えていれば一番古い履歴を削除
         if self.commands.len() >= self.max_size {
             self.commands.pop_front();
@@ -55,36 +154,115 @@ impl History {
     }
 
     // 履歴リストの削除
-    pub fn _clear(&mut self) {
+    pub fn clear(&mut self) {
         self.commands.clear();
     }
 
-    // 履歴を丸ごと置き換える
-    pub fn set_commands(&mut self, commands: VecDeque<String>) {
-        self.commands = commands;
+    /// 指定番号（1から始まる）の履歴を削除し、以降を繰り上げる
+    pub fn delete(&mut self, number: usize) -> Option<String> {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.commands.remove(index))
+    }
+
+    /// 最大保存数を変更し、超えた分は古い方から削除
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        while self.commands.len() > max_size {
+            self.commands.pop_front();
+        }
+    }
+
+    /// 絞り込み設定を変更
+    fn set_filter(&mut self, filter: HistoryFilter) {
+        self.filter = filter;
+    }
+
+    /// 履歴を丸ごと置き換える（追加時と同じ絞り込みを適用）
+    pub fn load(&mut self, commands: Vec<String>) {
+        self.commands.clear();
+        for command in commands {
+            self.add(command);
+        }
     }
 }
 
+/// RUCLI_HISTSIZE から最大保存数を取得（不正な値ならデフォルト）
+fn configured_history_size() -> usize {
+    get_var("RUCLI_HISTSIZE")
+        .and_then(|size| size.trim().parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_SIZE)
+}
+
 // 公開API
 
 /// 履歴にコマンドを追加
+///
+/// 追加のたびに RUCLI_HISTSIZE / RUCLI_HISTIGNORE / RUCLI_HISTCONTROL を読み直す
 pub fn add_history(command: String) {
-    HISTORY.lock().unwrap().add(command);
+    let max_size = configured_history_size();
+    let filter = HistoryFilter::from_env();
+    let state = state::current();
+    let mut history = state.history.lock_or_recover();
+    history.set_max_size(max_size);
+    history.set_filter(filter);
+    history.add(command);
 }
 
 // インデックス付きの配列を取得
 pub fn get_history_list() -> Vec<(usize, String)> {
-    HISTORY.lock().unwrap().list()
+    state::current().history.lock_or_recover().list()
 }
 
 // コマンド配列を取得
 pub fn get_history_commands() -> VecDeque<String> {
-    HISTORY.lock().unwrap().commands.clone()
+    state::current().history.lock_or_recover().commands.clone()
 }
 
 /// 履歴をクリア
-pub fn _clear_history() {
-    HISTORY.lock().unwrap()._clear();
+pub fn clear_history() {
+    state::current().history.lock_or_recover().clear();
+}
+
+/// 指定番号の履歴を削除する
+///
+/// # Arguments
+/// * `number` - 履歴番号（1から始まる）
+///
+/// # Returns
+/// * 削除したコマンド、範囲外なら None
+pub fn delete_history(number: usize) -> Option<String> {
+    state::current().history.lock_or_recover().delete(number)
+}
+
+/// 履歴のコマンドを再実行中として処理を実行する
+///
+/// # Arguments
+/// * `command` - 再実行するコマンド
+/// * `f` - 再実行の処理（この間 `command` は再実行中として扱う）
+///
+/// # Errors
+/// - `command` をすでに再実行中の場合（`history exec 5` の5番目が `history exec 5` など）
+/// - `f` が返したエラー
+pub fn with_history_executing<T>(command: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    if EXECUTING.with(|executing| executing.borrow().iter().any(|c| c == command)) {
+        return Err(RucliError::RuntimeError(format!(
+            "{command}: recursive history execution"
+        )));
+    }
+
+    EXECUTING.with(|executing| executing.borrow_mut().push(command.to_string()));
+    let result = f();
+    EXECUTING.with(|executing| executing.borrow_mut().pop());
+
+    result
+}
+
+/// 最新のcount件を番号付きで取得
+pub fn get_recent_history(count: usize) -> Vec<(usize, String)> {
+    let list = get_history_list();
+    let skip = list.len().saturating_sub(count);
+    list.into_iter().skip(skip).collect()
 }
 
 // 現在の履歴を指定ファイル、もしくはデフォルトファイルに保存
@@ -167,8 +345,13 @@ pub fn load_history_from_file(file_path: Option<&str>) -> Result<()> {
 
 // 履歴にコマンドを保存
 fn set_history_from_vec(commands: Vec<String>) {
-    let mut history = HISTORY.lock().unwrap();
-    history.set_commands(VecDeque::from(commands));
+    let max_size = configured_history_size();
+    let filter = HistoryFilter::from_env();
+    let state = state::current();
+    let mut history = state.history.lock_or_recover();
+    history.set_max_size(max_size);
+    history.set_filter(filter);
+    history.load(commands);
 }
 
 // 環境変数またはカレントディレクトリ/.rucli_historyを返す
@@ -182,14 +365,36 @@ pub fn get_default_history_file() -> PathBuf {
     PathBuf::from(".rucli_history")
 }
 
-/// 履歴を検索する
+/// `history search` で `--all` を付けないときに表示する最大件数
+pub const SEARCH_RESULT_LIMIT: usize = 20;
+
+/// 履歴の検索条件を正規表現にする
 ///
 /// # Arguments
 /// * `query` - 検索文字列
+/// * `regex` - trueなら正規表現、falseなら大文字・小文字を区別しない部分一致
+///
+/// # Errors
+/// * 正規表現として不正な場合
+pub fn search_pattern(query: &str, regex: bool) -> Result<Regex> {
+    let pattern = if regex {
+        Regex::new(query)
+    } else {
+        RegexBuilder::new(&regex::escape(query))
+            .case_insensitive(true)
+            .build()
+    };
+    pattern.map_err(|e| RucliError::InvalidRegex(e.to_string()))
+}
+
+/// 履歴を検索する
+///
+/// # Arguments
+/// * `pattern` - 検索条件（`search_pattern` で作る）
 ///
 /// # Returns
-/// * マッチした履歴のリスト（番号とコマンド）
-pub fn search_history(query: &str) -> Vec<(usize, String)> {
+/// * マッチした履歴のリスト（番号とコマンド）。新しい順で、同じコマンドは最も新しい番号で一度だけ
+pub fn search_history(pattern: &Regex) -> Vec<(usize, String)> {
     let mut history = get_history_list();
 
     // 最後の要素（現在実行中のコマンド）を除外
@@ -197,13 +402,11 @@ pub fn search_history(query: &str) -> Vec<(usize, String)> {
         history.pop();
     }
 
-    // 文字を小文字で統一
-    let query = query.to_lowercase();
-
-    // 履歴で文字列を含んでいるものだけ残す
+    let mut seen = HashSet::new();
     history
         .into_iter()
-        .filter(|(_, cmd)| cmd.to_lowercase().contains(&query))
+        .rev()
+        .filter(|(_, cmd)| pattern.is_match(cmd) && seen.insert(cmd.clone()))
         .collect()
 }
 
@@ -240,46 +443,41 @@ pub fn get_history_by_number(number: usize) -> Option<String> {
     }
 }
 
-/// 最後のコマンドを取得（現在実行中を除く）
+/// 最後のコマンドを取得（`!!`）
+///
+/// 履歴展開は入力中のコマンドを履歴に追加する前に行うため、
+/// 履歴の最新のコマンドが直前に実行したコマンドになる
 ///
 /// # Returns
-/// * 履歴に2つ以上のコマンドがある場合は最後から2番目のコマンド
-/// * それ以外は None
+/// * 最新のコマンド、履歴が空なら None
 pub fn get_last_command() -> Option<String> {
-    let commands = get_history_commands();
-
-    // コマンド履歴の長さが二つ以上なら返す値がある
-    match commands.len() {
-        n if n >= 1 => Some(commands[n - 1].clone()),
-        _ => None,
-    }
+    get_history_commands().back().cloned()
 }
 
-/// 相対オフセットで履歴を取得
+/// 相対オフセットで履歴を取得（`!-n`）
+///
+/// 入力中のコマンドは履歴に含まれないため、`-1` が最新のコマンドになる
 ///
 /// # Arguments
-/// * `offset` - 現在位置からの相対位置（通常は負の値）
+/// * `offset` - 履歴の末尾からの相対位置（負の値）
 ///
 /// # Returns
 /// * 指定位置のコマンドが存在すれば Some(コマンド)
-/// * 範囲外なら None
+/// * 範囲外、または負の値でなければ None
 pub fn get_history_by_offset(offset: i32) -> Option<String> {
     let commands = get_history_commands();
 
-    // 現在の終端を計算
-    let last_pos = commands.len() as i32;
-
-    // 検索したい位置を計算
-    let target_pos = last_pos + offset;
+    // 末尾から数えた位置を、先頭からの位置に変換する
+    let back = usize::try_from(offset.checked_neg()?).ok()?;
+    let index = commands.len().checked_sub(back)?;
 
-    // 検索したい値が負数ではなく、リスト上限も超えていなければ値を返す
-    match target_pos {
-        n if 0 <= n && n <= last_pos => Some(commands[n as usize].clone()),
-        _ => None,
-    }
+    // `-0` は末尾の次を指すので範囲外になる
+    commands.get(index).cloned()
 }
 
-/// プレフィックスで履歴を検索（最新のものを返す）
+/// プレフィックスで履歴を検索（`!string`）
+///
+/// 入力中のコマンドは履歴に含まれないため、自分自身にはマッチしない
 ///
 /// # Arguments
 /// * `prefix` - 検索するプレフィックス
@@ -288,33 +486,71 @@ pub fn get_history_by_offset(offset: i32) -> Option<String> {
 /// * プレフィックスにマッチする最新のコマンド
 /// * 見つからなければ None
 pub fn search_history_by_prefix(prefix: &str) -> Option<String> {
-    let commands = get_history_commands();
+    get_history_commands()
+        .iter()
+        .rev()
+        .find(|cmd| cmd.starts_with(prefix))
+        .cloned()
+}
+
+/// fcで再実行するコマンドを探す
+///
+/// fc自身の呼び出し（入力中の `fc -s` など）は対象にしない
+///
+/// # Arguments
+/// * `target` - 履歴番号、`-n`（末尾からn番目）、またはコマンドの先頭の文字列。省略時は最新のコマンド
+///
+/// # Returns
+/// * 該当するコマンド、見つからなければ None
+pub fn find_fc_target(target: Option<&str>) -> Option<String> {
+    let Some(target) = target else {
+        return previous_commands().last().cloned();
+    };
+
+    if let Ok(number) = target.parse::<usize>() {
+        return get_history_by_number(number);
+    }
 
-    // コマンド履歴の長さが二つ以上なら検索
-    // プレフィックスコマンドを抜くため、一番最新コマンドは除く
-    match commands.len() {
-        n if n > 1 => commands
+    let commands = previous_commands();
+    match target
+        .strip_prefix('-')
+        .and_then(|n| n.parse::<usize>().ok())
+    {
+        Some(back) => commands
+            .len()
+            .checked_sub(back)
+            .and_then(|index| commands.get(index))
+            .cloned(),
+        None => commands
             .iter()
-            .take(n)
             .rev()
-            .find(|cmd| cmd.starts_with(prefix))
+            .find(|cmd| cmd.starts_with(target))
             .cloned(),
-        _ => None,
     }
 }
 
+/// fc自身の呼び出しを除いた履歴
+fn previous_commands() -> Vec<String> {
+    get_history_commands()
+        .into_iter()
+        .filter(|cmd| cmd.split_whitespace().next() != Some("fc"))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::Mutex;
+    use crate::state::{ShellState, StateGuard};
+    use std::sync::Arc;
 
-    // テスト用のロック（テストが同時実行されないようにする）
-    static TEST_MUTEX: Mutex<()> = Mutex::new(());
+    // テストごとに独立した状態を使う（並列実行しても干渉しない）
+    fn fresh_state() -> StateGuard {
+        state::enter(Arc::new(ShellState::new()))
+    }
 
     #[test]
     fn test_add_history() {
-        let _guard = TEST_MUTEX.lock().unwrap();
-        _clear_history();
+        let _state = fresh_state();
 
         add_history("test_add_1".to_string());
         add_history("test_add_2".to_string());
@@ -327,8 +563,7 @@ mod tests {
 
     #[test]
     fn test_no_duplicate() {
-        let _guard = TEST_MUTEX.lock().unwrap();
-        _clear_history();
+        let _state = fresh_state();
 
         add_history("test_dup".to_string());
         let count_before = get_history_list().len();
@@ -341,7 +576,7 @@ mod tests {
 
     #[test]
     fn test_empty_command() {
-        let _guard = TEST_MUTEX.lock().unwrap();
+        let _state = fresh_state();
 
         let count_before = get_history_list().len();
 
@@ -354,20 +589,19 @@ mod tests {
 
     #[test]
     fn test_clear_history() {
-        let _guard = TEST_MUTEX.lock().unwrap();
+        let _state = fresh_state();
 
         add_history("test_clear_1".to_string());
         add_history("test_clear_2".to_string());
 
-        _clear_history();
+        clear_history();
         let list = get_history_list();
         assert_eq!(list.len(), 0);
     }
 
     #[test]
     fn test_history_order() {
-        let _guard = TEST_MUTEX.lock().unwrap();
-        _clear_history();
+        let _state = fresh_state();
 
         add_history("first".to_string());
         add_history("second".to_string());
@@ -388,8 +622,7 @@ mod tests {
 
     #[test]
     fn test_max_size() {
-        let _guard = TEST_MUTEX.lock().unwrap();
-        _clear_history();
+        let _state = fresh_state();
 
         // 1001個追加
         for i in 0..1001 {
@@ -405,8 +638,7 @@ mod tests {
 
     #[test]
     fn test_get_history_by_number() {
-        let _guard = TEST_MUTEX.lock().unwrap();
-        _clear_history();
+        let _state = fresh_state();
 
         // 履歴を追加
         add_history("echo first".to_string());
@@ -423,11 +655,238 @@ mod tests {
 
     #[test]
     fn test_get_history_by_number_empty() {
-        let _guard = TEST_MUTEX.lock().unwrap();
-        _clear_history();
+        let _state = fresh_state();
 
         // 空の履歴
         assert_eq!(get_history_by_number(1), None);
         assert_eq!(get_history_by_number(100), None);
     }
+
+    #[test]
+    fn test_get_last_command() {
+        let _state = fresh_state();
+        assert_eq!(get_last_command(), None);
+
+        add_history("echo first".to_string());
+        assert_eq!(get_last_command(), Some("echo first".to_string()));
+
+        add_history("echo second".to_string());
+        assert_eq!(get_last_command(), Some("echo second".to_string()));
+    }
+
+    #[test]
+    fn test_get_history_by_offset() {
+        let _state = fresh_state();
+        assert_eq!(get_history_by_offset(-1), None);
+
+        add_history("echo first".to_string());
+        add_history("echo second".to_string());
+        add_history("echo third".to_string());
+
+        // -1 は最新のコマンド
+        assert_eq!(get_history_by_offset(-1), Some("echo third".to_string()));
+        assert_eq!(get_history_by_offset(-3), Some("echo first".to_string()));
+        assert_eq!(get_history_by_offset(-4), None);
+        assert_eq!(get_history_by_offset(0), None);
+        assert_eq!(get_history_by_offset(1), None);
+        assert_eq!(get_history_by_offset(i32::MIN), None);
+    }
+
+    #[test]
+    fn test_search_history_by_prefix() {
+        let _state = fresh_state();
+        assert_eq!(search_history_by_prefix("echo"), None);
+
+        // 履歴が1件でも検索する
+        add_history("echo first".to_string());
+        assert_eq!(
+            search_history_by_prefix("echo"),
+            Some("echo first".to_string())
+        );
+
+        add_history("pwd".to_string());
+        add_history("echo second".to_string());
+
+        // 最新のマッチを返す
+        assert_eq!(
+            search_history_by_prefix("echo"),
+            Some("echo second".to_string())
+        );
+        assert_eq!(search_history_by_prefix("pw"), Some("pwd".to_string()));
+        assert_eq!(search_history_by_prefix("cat"), None);
+    }
+
+    #[test]
+    fn test_search_history_ranks_and_dedupes() {
+        let _state = fresh_state();
+        for command in [
+            "cargo build",
+            "echo Cargo",
+            "cargo test",
+            "cargo build",
+            "ls",
+            "history search cargo",
+        ] {
+            add_history(command.to_string());
+        }
+
+        // 新しい順で、同じコマンドは最新の番号で一度だけ（実行中の検索コマンドは除く）
+        let pattern = search_pattern("cargo", false).unwrap();
+        assert_eq!(
+            search_history(&pattern),
+            vec![
+                (4, "cargo build".to_string()),
+                (3, "cargo test".to_string()),
+                (2, "echo Cargo".to_string()),
+            ]
+        );
+
+        // 正規表現は大文字・小文字を区別する
+        let pattern = search_pattern("^cargo (test|run)$", true).unwrap();
+        assert_eq!(
+            search_history(&pattern),
+            vec![(3, "cargo test".to_string())]
+        );
+
+        // 正規表現の記号は -r なしでは文字として扱う
+        let pattern = search_pattern("cargo (test|run)", false).unwrap();
+        assert!(search_history(&pattern).is_empty());
+        assert!(matches!(
+            search_pattern("cargo (", true),
+            Err(RucliError::InvalidRegex(_))
+        ));
+    }
+
+    #[test]
+    fn test_find_fc_target() {
+        let _state = fresh_state();
+        assert_eq!(find_fc_target(None), None);
+
+        add_history("echo first".to_string());
+        add_history("pwd".to_string());
+        add_history("echo second".to_string());
+        add_history("fc -s".to_string());
+
+        // fc自身は対象にしない
+        assert_eq!(find_fc_target(None), Some("echo second".to_string()));
+        assert_eq!(find_fc_target(Some("-1")), Some("echo second".to_string()));
+        assert_eq!(find_fc_target(Some("-3")), Some("echo first".to_string()));
+        assert_eq!(find_fc_target(Some("-4")), None);
+        assert_eq!(find_fc_target(Some("2")), Some("pwd".to_string()));
+        assert_eq!(find_fc_target(Some("ec")), Some("echo second".to_string()));
+        assert_eq!(find_fc_target(Some("cat")), None);
+    }
+
+    #[test]
+    fn test_delete_history_renumbers() {
+        let _state = fresh_state();
+
+        add_history("echo first".to_string());
+        add_history("echo second".to_string());
+        add_history("echo third".to_string());
+
+        assert_eq!(delete_history(2), Some("echo second".to_string()));
+        assert_eq!(get_history_by_number(2), Some("echo third".to_string()));
+        assert_eq!(delete_history(0), None);
+        assert_eq!(delete_history(3), None);
+    }
+
+    #[test]
+    fn test_get_recent_history() {
+        let _state = fresh_state();
+
+        add_history("echo first".to_string());
+        add_history("echo second".to_string());
+        add_history("echo third".to_string());
+
+        let recent = get_recent_history(2);
+        assert_eq!(
+            recent,
+            vec![
+                (2, "echo second".to_string()),
+                (3, "echo third".to_string())
+            ]
+        );
+        assert_eq!(get_recent_history(10).len(), 3);
+    }
+
+    #[test]
+    fn test_set_max_size_truncates_oldest() {
+        let mut history = History::new(5);
+        for i in 0..5 {
+            history.add(format!("cmd_{i}"));
+        }
+
+        history.set_max_size(2);
+        let list = history.list();
+        assert_eq!(
+            list,
+            vec![(1, "cmd_3".to_string()), (2, "cmd_4".to_string())]
+        );
+
+        history.add("cmd_5".to_string());
+        assert_eq!(history.list().len(), 2);
+    }
+
+    fn commands_of(history: &History) -> Vec<String> {
+        history.list().into_iter().map(|(_, cmd)| cmd).collect()
+    }
+
+    #[test]
+    fn test_filter_default_ignores_consecutive_dups_only() {
+        let mut history = History::new(10);
+        for cmd in ["ls", "ls", "pwd", "ls"] {
+            history.add(cmd.to_string());
+        }
+        assert_eq!(commands_of(&history), vec!["ls", "pwd", "ls"]);
+    }
+
+    #[test]
+    fn test_filter_histignore_patterns() {
+        let mut history = History::new(10);
+        history.set_filter(HistoryFilter::new(Some("ls:echo *secret*"), None));
+
+        for cmd in ["ls", "ls -l", "echo my secret token", "pwd"] {
+            history.add(cmd.to_string());
+        }
+        assert_eq!(commands_of(&history), vec!["ls -l", "pwd"]);
+    }
+
+    #[test]
+    fn test_filter_erasedups() {
+        let mut history = History::new(10);
+        history.set_filter(HistoryFilter::new(None, Some("erasedups")));
+
+        for cmd in ["ls", "pwd", "ls", "cat a", "pwd"] {
+            history.add(cmd.to_string());
+        }
+        assert_eq!(commands_of(&history), vec!["ls", "cat a", "pwd"]);
+    }
+
+    #[test]
+    fn test_filter_ignorespace() {
+        let mut history = History::new(10);
+        history.set_filter(HistoryFilter::new(None, Some("ignorespace")));
+
+        for cmd in [" echo hidden", "echo shown", "echo shown"] {
+            history.add(cmd.to_string());
+        }
+        // ignoredupsを指定していないので連続した重複も残る
+        assert_eq!(commands_of(&history), vec!["echo shown", "echo shown"]);
+
+        history.set_filter(HistoryFilter::new(None, Some("ignoreboth")));
+        history.add("echo shown".to_string());
+        history.add("\techo hidden".to_string());
+        assert_eq!(history.list().len(), 2);
+    }
+
+    #[test]
+    fn test_filter_applied_when_loading() {
+        let mut history = History::new(10);
+        history.set_filter(HistoryFilter::new(Some("exit"), Some("erasedups")));
+
+        let saved = ["ls", "pwd", "exit", "ls", "pwd", "exit"];
+        history.load(saved.iter().map(|cmd| cmd.to_string()).collect());
+        assert_eq!(commands_of(&history), vec!["ls", "pwd"]);
+    }
 }
@@ -0,0 +1,63 @@
+//! コマンドの実行前後に呼ぶフック（rucliを組み込むプログラム向け）を管理するモジュール
+//!
+//! フックは `Shell::add_pre_command_hook` / `Shell::add_post_command_hook` で登録し、
+//! 変数を展開した後のコマンドごとに呼ばれる。if・for・関数などの中のコマンドにも呼ばれるので、
+//! 複合コマンドでは全体と中の各コマンドのそれぞれで呼ばれる。
+
+use crate::commands::Command;
+use crate::error::Result;
+use crate::state::{self, LockExt};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 実行前のフック（エラーを返すとコマンドを実行せず、そのエラーを結果にする）
+pub type PreCommandHook = dyn Fn(&Command) -> Result<()> + Send + Sync;
+
+/// 実行後のフック（コマンド・かかった時間・終了ステータスを受け取る）
+pub type PostCommandHook = dyn Fn(&Command, Duration, i32) + Send + Sync;
+
+/// 登録されているフック
+#[derive(Default, Clone)]
+pub struct CommandHooks {
+    pre: Vec<Arc<PreCommandHook>>,
+    post: Vec<Arc<PostCommandHook>>,
+}
+
+impl CommandHooks {
+    /// 実行前のフックを登録順に呼ぶ
+    ///
+    /// # Errors
+    /// * フックがエラーを返した場合（残りのフックは呼ばない）
+    pub(crate) fn before(&self, command: &Command) -> Result<()> {
+        self.pre.iter().try_for_each(|hook| hook(command))
+    }
+
+    /// 実行後のフックを登録順に呼ぶ
+    pub(crate) fn after(&self, command: &Command, elapsed: Duration, status: i32) {
+        for hook in &self.post {
+            hook(command, elapsed, status);
+        }
+    }
+
+    /// 実行後のフックがあるか（なければコマンドを複製しなくてよい）
+    pub(crate) fn has_post(&self) -> bool {
+        !self.post.is_empty()
+    }
+}
+
+/// 実行前のフックを登録する
+pub fn add_pre_command_hook(hook: Arc<PreCommandHook>) {
+    state::current().hooks.lock_or_recover().pre.push(hook);
+}
+
+/// 実行後のフックを登録する
+pub fn add_post_command_hook(hook: Arc<PostCommandHook>) {
+    state::current().hooks.lock_or_recover().post.push(hook);
+}
+
+/// 現在のシェルのフックを取得
+///
+/// フックの中でコマンドを実行できるよう、ロックを外した複製を返す
+pub(crate) fn current() -> CommandHooks {
+    state::current().hooks.lock_or_recover().clone()
+}
@@ -0,0 +1,98 @@
+//! ループ単位の入力を管理するモジュール
+//!
+//! `while read LINE; do ...; done < file.txt` のように、ループ全体に与えられた入力を
+//! readコマンドが呼ばれるたびに一行ずつ消費できるようにする。
+//! ループがネストした場合は最も内側の入力が使われる。
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+thread_local! {
+    // 入力カーソルのスタック（最後の要素が現在のループの入力）
+    static INPUT_STACK: RefCell<Vec<VecDeque<String>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// ループの入力を登録し、スコープを抜けると解除されるガード
+pub struct InputGuard;
+
+impl Drop for InputGuard {
+    fn drop(&mut self) {
+        INPUT_STACK.with(|stack| stack.borrow_mut().pop());
+    }
+}
+
+/// ループの入力を登録する
+///
+/// # Arguments
+/// * `content` - ループに与えられた入力全体
+///
+/// # Returns
+/// * ドロップ時に入力を解除するガード
+pub fn push_input(content: &str) -> InputGuard {
+    let lines = content.lines().map(|line| line.to_string()).collect();
+    INPUT_STACK.with(|stack| stack.borrow_mut().push(lines));
+    InputGuard
+}
+
+/// ループの入力が有効かチェック
+pub fn is_active() -> bool {
+    INPUT_STACK.with(|stack| !stack.borrow().is_empty())
+}
+
+/// 現在のループの入力から次の一行を取り出す
+///
+/// # Returns
+/// * 次の行、入力が尽きていれば None
+pub fn next_line() -> Option<String> {
+    INPUT_STACK.with(|stack| {
+        stack
+            .borrow_mut()
+            .last_mut()
+            .and_then(|lines| lines.pop_front())
+    })
+}
+
+/// 現在のループの入力の残りをすべて取り出す（catのように入力を最後まで読むコマンド向け）
+pub fn take_remaining() -> String {
+    INPUT_STACK.with(|stack| {
+        stack
+            .borrow_mut()
+            .last_mut()
+            .map(|lines| lines.drain(..).map(|line| line + "\n").collect())
+            .unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_cursor_reads_lines_in_order() {
+        let _guard = push_input("first\nsecond");
+
+        assert!(is_active());
+        assert_eq!(next_line(), Some("first".to_string()));
+        assert_eq!(next_line(), Some("second".to_string()));
+        assert_eq!(next_line(), None);
+    }
+
+    #[test]
+    fn test_nested_input_uses_innermost() {
+        let _outer = push_input("outer");
+        {
+            let _inner = push_input("inner");
+            assert_eq!(next_line(), Some("inner".to_string()));
+        }
+        assert_eq!(next_line(), Some("outer".to_string()));
+    }
+
+    #[test]
+    fn test_take_remaining_drains_current_input() {
+        let _guard = push_input("first\nsecond\nthird");
+
+        assert_eq!(next_line(), Some("first".to_string()));
+        assert_eq!(take_remaining(), "second\nthird\n");
+        assert_eq!(next_line(), None);
+    }
+}
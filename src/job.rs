@@ -1,7 +1,14 @@
 use log::debug;
 // src/job.rs
-use once_cell::sync::Lazy;
-use std::{sync::Mutex, thread};
+use crate::error::{Result, RucliError};
+use crate::state::{self, LockExt};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Job {
@@ -9,74 +16,621 @@ pub struct Job {
     pub _thread_id: thread::ThreadId,
     pub command: String,
     pub status: JobStatus,
+    pub cancel: Arc<AtomicBool>,            // ジョブの中断要求
+    pub output: String,                     // 完了したジョブの出力（fgで表示する）
+    pub exit_status: i32,                   // 完了したジョブの終了ステータス
+    pub error: Option<String>,              // 失敗したジョブのエラーメッセージ
+    pub notified: bool,                     // 終了をプロンプトの前に通知したか
+    pub start_gate: Option<Arc<StartGate>>, // 停止した状態で作ったジョブの開始の合図
+}
+
+/// 停止した状態で作ったジョブが実行を始めるのを待つための合図
+///
+/// ジョブのスレッドは `wait` で待ち、`bg` / `fg` が `open` すると実行を始める
+#[derive(Debug, Default)]
+pub struct StartGate {
+    opened: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl StartGate {
+    /// 待っているスレッドに開始を知らせる
+    pub fn open(&self) {
+        *self.opened.lock_or_recover() = true;
+        self.condvar.notify_all();
+    }
+
+    /// 開始の合図か中断の要求を待つ
+    ///
+    /// # Returns
+    /// * 開始してよいか（中断を要求された場合は false）
+    pub fn wait(&self, cancel: &AtomicBool) -> bool {
+        const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+        let mut opened = self.opened.lock_or_recover();
+        while !*opened {
+            if cancel.load(Ordering::Relaxed) {
+                return false;
+            }
+            opened = match self.condvar.wait_timeout(opened, CHECK_INTERVAL) {
+                Ok((guard, _)) => guard,
+                Err(poisoned) => poisoned.into_inner().0,
+            };
+        }
+        true
+    }
+}
+
+thread_local! {
+    // このスレッドで実行中のジョブの中断要求（フォアグラウンドならNone）
+    static CANCEL_FLAG: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
 }
 
 /// ジョブのステータス
 #[derive(Debug, Clone)]
 pub enum JobStatus {
     Running,
+    Stopped, // 開始を待っている（bg / fg で実行を始める）
     Completed,
+    Failed, // ジョブのスレッドがパニックした
 }
 
-// グローバルなジョブリスト
-static JOBS: Lazy<Mutex<Vec<Job>>> = Lazy::new(|| Mutex::new(Vec::new()));
-static JOB_COUNTER: Lazy<Mutex<u32>> = Lazy::new(|| Mutex::new(0));
+/// ジョブの指定（`fg 2` / `fg %2` / `fg %%` / `fg %sleep` など）
+///
+/// パース時には形式だけを確かめ、どのジョブかは実行時に `resolve` で決める
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum JobSpec {
+    Id(u32),        // N / %N
+    Current,        // %% / %+ / %（最後に起動したジョブ）
+    Previous,       // %-（その一つ前のジョブ）
+    Prefix(String), // %string（コマンドがstringで始まるジョブ）
+}
+
+impl JobSpec {
+    /// ジョブの指定をパースする
+    ///
+    /// # Errors
+    /// - 数字でも `%` で始まる指定でもない場合
+    pub fn parse(spec: &str) -> Result<Self> {
+        let Some(rest) = spec.strip_prefix('%') else {
+            return spec.parse().map(JobSpec::Id).map_err(|_| {
+                RucliError::ParseError(format!("'{spec}' is not a valid job id or %job spec"))
+            });
+        };
+
+        Ok(match rest {
+            "" | "%" | "+" => JobSpec::Current,
+            "-" => JobSpec::Previous,
+            _ => match rest.parse() {
+                Ok(id) => JobSpec::Id(id),
+                Err(_) => JobSpec::Prefix(rest.to_string()),
+            },
+        })
+    }
+
+    /// 指定されたジョブのIDを現在のジョブの一覧から探す（完了してまだfgしていないジョブも含む）
+    ///
+    /// # Errors
+    /// - 該当するジョブがない場合
+    /// - `%string` に当てはまるジョブが複数ある場合
+    pub fn resolve(&self) -> Result<u32> {
+        let mut jobs: Vec<(u32, String)> = state::current()
+            .jobs
+            .lock_or_recover()
+            .iter()
+            .map(|job| (job.id, job.command.clone()))
+            .collect();
+        jobs.sort_by_key(|(id, _)| *id);
+
+        let found = match self {
+            JobSpec::Id(id) => jobs.iter().find(|(job_id, _)| job_id == id),
+            JobSpec::Current => jobs.last(),
+            JobSpec::Previous => jobs.iter().rev().nth(1),
+            JobSpec::Prefix(prefix) => {
+                let matches: Vec<u32> = jobs
+                    .iter()
+                    .filter(|(_, command)| command.starts_with(prefix.as_str()))
+                    .map(|(id, _)| *id)
+                    .collect();
+                if matches.len() > 1 {
+                    let ids: Vec<String> = matches.iter().map(u32::to_string).collect();
+                    return Err(RucliError::InvalidArgument(format!(
+                        "Ambiguous job spec: {self} (matches jobs {})",
+                        ids.join(", ")
+                    )));
+                }
+                jobs.iter().find(|(id, _)| matches.contains(id))
+            }
+        };
+
+        found
+            .map(|(id, _)| *id)
+            .ok_or_else(|| RucliError::InvalidArgument(format!("No such job: {self}")))
+    }
+}
+
+impl fmt::Display for JobSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobSpec::Id(id) => write!(f, "{id}"),
+            JobSpec::Current => write!(f, "%+"),
+            JobSpec::Previous => write!(f, "%-"),
+            JobSpec::Prefix(prefix) => write!(f, "%{prefix}"),
+        }
+    }
+}
 
 // 指定されたjob_idでjobを作成
-pub fn create_job_with_id(job_id: u32, command: String, thread_id: thread::ThreadId) {
+pub fn create_job_with_id(
+    job_id: u32,
+    command: String,
+    thread_id: thread::ThreadId,
+    cancel: Arc<AtomicBool>,
+) {
+    register_job(job_id, command, thread_id, cancel, None);
+}
+
+/// 指定されたjob_idで停止した状態のジョブを作成する
+///
+/// ジョブのスレッドは `start_gate` が開くまで実行を始めない
+pub fn create_stopped_job_with_id(
+    job_id: u32,
+    command: String,
+    thread_id: thread::ThreadId,
+    cancel: Arc<AtomicBool>,
+    start_gate: Arc<StartGate>,
+) {
+    register_job(job_id, command, thread_id, cancel, Some(start_gate));
+}
+
+fn register_job(
+    job_id: u32,
+    command: String,
+    thread_id: thread::ThreadId,
+    cancel: Arc<AtomicBool>,
+    start_gate: Option<Arc<StartGate>>,
+) {
     // 指定されたIDでジョブを作成
     let job = Job {
         id: job_id,
         _thread_id: thread_id,
         command,
-        status: JobStatus::Running,
+        status: if start_gate.is_some() {
+            JobStatus::Stopped
+        } else {
+            JobStatus::Running
+        },
+        cancel,
+        output: String::new(),
+        exit_status: 0,
+        error: None,
+        notified: false,
+        start_gate,
     };
 
     // リストに追加
-    JOBS.lock().unwrap().push(job);
+    state::current().jobs.lock_or_recover().push(job);
 }
 pub fn get_next_job_id() -> u32 {
-    let mut counter = JOB_COUNTER.lock().unwrap();
+    let state = state::current();
+    let mut counter = state.job_counter.lock_or_recover();
     *counter += 1;
     *counter
 }
 
+impl Job {
+    /// まだ終わっていないジョブか（実行中か、開始を待って停止している）
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, JobStatus::Running | JobStatus::Stopped)
+    }
+
+    /// 失敗して終わったジョブか（パニックした、または0以外のステータスで終了した）
+    pub fn has_failed(&self) -> bool {
+        match self.status {
+            JobStatus::Running | JobStatus::Stopped => false,
+            JobStatus::Completed => self.exit_status != 0,
+            JobStatus::Failed => true,
+        }
+    }
+}
+
 // ジョブ一覧を返す
+//
+// 終了したジョブは、まだ通知していなければ一度だけ一覧に含め（出力を表示できる）、その後は取り除く
 pub fn list_jobs() -> Vec<Job> {
+    let finished: Vec<Job> = state::current()
+        .jobs
+        .lock_or_recover()
+        .iter_mut()
+        .filter(|job| !job.is_active() && !job.notified)
+        .map(|job| {
+            job.notified = true;
+            job.clone()
+        })
+        .collect();
     cleanup_completed_jobs();
-    JOBS.lock().unwrap().clone()
+
+    let mut jobs = state::current().jobs.lock_or_recover().clone();
+    jobs.extend(finished);
+    jobs.sort_by_key(|job| job.id);
+    jobs
+}
+
+/// 登録されているジョブの番号を小さい順に返す（一覧と違い、完了したジョブも取り除かない）
+pub fn job_ids() -> Vec<u32> {
+    let mut ids: Vec<u32> = state::current()
+        .jobs
+        .lock_or_recover()
+        .iter()
+        .map(|job| job.id)
+        .collect();
+    ids.sort_unstable();
+    ids
 }
 
 // 特定のジョブを取得
 pub fn get_job(id: u32) -> Option<Job> {
-    // JOBSをロック
-    let jobs = JOBS.lock().unwrap();
+    // 現在のシェルのジョブリストをロック
+    let state = state::current();
+    let jobs = state.jobs.lock_or_recover();
 
     jobs.iter().find(|job| job.id == id).cloned()
 }
 
-// ジョブを完了状態にする
-pub fn mark_completed(job_id: u32) {
-    debug!("Marking job {job_id} as completed");
-    let mut jobs = JOBS.lock().unwrap();
+/// ジョブを完了状態にし、出力と終了ステータスを保存する
+///
+/// # Arguments
+/// * `error` - コマンドがエラーで終わった場合のメッセージ
+pub fn mark_completed(job_id: u32, output: String, exit_status: i32, error: Option<String>) {
+    debug!("Marking job {job_id} as completed with status {exit_status}");
+    let state = state::current();
+    let mut jobs = state.jobs.lock_or_recover();
     if let Some(job) = jobs.iter_mut().find(|job| job.id == job_id) {
         job.status = JobStatus::Completed;
+        job.output = output;
+        job.exit_status = exit_status;
+        job.error = error;
     }
 
     // 完了状態のジョブを削除
     // cleanup_completed_jobs();
 }
 
+/// ジョブを失敗状態にする（スレッドがパニックした場合）
+pub fn mark_failed(job_id: u32, message: String) {
+    debug!("Marking job {job_id} as failed");
+    let state = state::current();
+    let mut jobs = state.jobs.lock_or_recover();
+    if let Some(job) = jobs.iter_mut().find(|job| job.id == job_id) {
+        job.status = JobStatus::Failed;
+        job.error = Some(message);
+    }
+}
+
+/// 終了を通知していないジョブを通知済みにして返す
+///
+/// ジョブは一覧に残す（fgで出力を受け取れる）。
+/// 返す値は `(ジョブ, 印)` で、印は最後に起動したジョブが `+`、その一つ前が `-`
+pub fn take_finished_notices() -> Vec<(Job, &'static str)> {
+    let state = state::current();
+    let mut jobs = state.jobs.lock_or_recover();
+
+    let mut ids: Vec<u32> = jobs.iter().map(|job| job.id).collect();
+    ids.sort_unstable();
+    let current = ids.last().copied();
+    let previous = ids.iter().rev().nth(1).copied();
+
+    let mut notices: Vec<(Job, &'static str)> = jobs
+        .iter_mut()
+        .filter(|job| !job.notified && !job.is_active())
+        .map(|job| {
+            job.notified = true;
+            let marker = if Some(job.id) == current {
+                "+"
+            } else if Some(job.id) == previous {
+                "-"
+            } else {
+                " "
+            };
+            (job.clone(), marker)
+        })
+        .collect();
+    notices.sort_by_key(|(job, _)| job.id);
+    notices
+}
+
+/// 停止しているジョブを実行中にし、実行を始めさせる
+///
+/// # Returns
+/// * 実行を始めたジョブ
+///
+/// # Errors
+/// - ジョブが存在しない場合
+/// - ジョブがすでに実行中か終了している場合
+pub fn resume_job(id: u32) -> Result<Job> {
+    let state = state::current();
+    let mut jobs = state.jobs.lock_or_recover();
+    let Some(job) = jobs.iter_mut().find(|job| job.id == id) else {
+        return Err(RucliError::InvalidArgument(format!("No such job: {id}")));
+    };
+
+    match job.status {
+        JobStatus::Stopped => {}
+        JobStatus::Running => {
+            return Err(RucliError::RuntimeError(format!(
+                "job {id} is already running"
+            )));
+        }
+        JobStatus::Completed | JobStatus::Failed => {
+            return Err(RucliError::RuntimeError(format!("job {id} has finished")));
+        }
+    }
+
+    debug!("Resuming stopped job {id}");
+    job.status = JobStatus::Running;
+    if let Some(gate) = job.start_gate.take() {
+        gate.open();
+    }
+    Ok(job.clone())
+}
+
+/// 実行中のジョブに中断を要求する
+///
+/// # Returns
+/// * ジョブが見つかったか
+pub fn cancel_job(id: u32) -> bool {
+    match get_job(id) {
+        Some(job) => {
+            job.cancel.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// ジョブの完了を待ち、完了したジョブを一覧から取り除いて返す
+///
+/// 短い間隔で状態を確認し、フォアグラウンドへの中断要求があれば待つのをやめる
+///
+/// # Returns
+/// * `None` - ジョブが存在しない場合
+///
+/// # Errors
+/// - 待っている間に中断が要求された場合
+pub fn wait_for_job(id: u32) -> Result<Option<Job>> {
+    const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+    loop {
+        {
+            let state = state::current();
+            let mut jobs = state.jobs.lock_or_recover();
+            match jobs.iter().position(|job| job.id == id) {
+                None => return Ok(None),
+                Some(pos) if !jobs[pos].is_active() => {
+                    return Ok(Some(jobs.remove(pos)));
+                }
+                Some(_) => {}
+            }
+        }
+
+        if is_cancelled() {
+            return Err(RucliError::Interrupted);
+        }
+        thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+/// 現在のスレッドをジョブとして実行し、その中断要求を参照できるようにする
+///
+/// バックグラウンドジョブのスレッドの開始時に呼ぶ。
+/// ジョブの中の `cd` がフォアグラウンドに影響しないよう、カレントディレクトリと
+/// umask をこのスレッド専用にする
+pub fn run_as_job(cancel: Arc<AtomicBool>) {
+    CANCEL_FLAG.with(|flag| *flag.borrow_mut() = Some(cancel));
+    isolate_working_directory();
+}
+
+/// 現在のスレッドのカレントディレクトリと umask を、プロセスの他のスレッドから切り離す
+///
+/// 切り離した時点のディレクトリから始まり、以後の変更は他のスレッドに見えない。
+/// Linux 以外ではスレッドごとに持てないため何もしない
+fn isolate_working_directory() {
+    #[cfg(target_os = "linux")]
+    if unsafe { libc::unshare(libc::CLONE_FS) } != 0 {
+        log::warn!(
+            "failed to isolate the job's working directory: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// このスレッドでバックグラウンドジョブを実行中か
+pub fn in_background() -> bool {
+    CANCEL_FLAG.with(|flag| flag.borrow().is_some())
+}
+
+/// 実行中の処理に中断が要求されているか
+///
+/// ジョブのスレッドならジョブへの要求、それ以外はシェルのフォアグラウンドへの要求と
+/// trap を登録したシグナルの受信を見る
+pub fn is_cancelled() -> bool {
+    CANCEL_FLAG
+        .with(|flag| {
+            flag.borrow()
+                .as_ref()
+                .map(|cancel| cancel.load(Ordering::Relaxed))
+        })
+        .unwrap_or_else(|| {
+            state::current().interrupted.load(Ordering::Relaxed) || crate::trap::signal_pending()
+        })
+}
+
 // 完了したジョブを削除
 fn cleanup_completed_jobs() {
-    let mut jobs = JOBS.lock().unwrap();
+    let state = state::current();
+    let mut jobs = state.jobs.lock_or_recover();
     let initial_count = jobs.len();
 
     // 完了したジョブを削除
-    jobs.retain(|job| matches!(job.status, JobStatus::Running));
+    jobs.retain(Job::is_active);
 
     let removed_count = initial_count - jobs.len();
     if removed_count > 0 {
         debug!("Cleaned up {removed_count} completed jobs");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ShellState, enter};
+
+    /// 実行中のジョブを登録する
+    fn seed_jobs(commands: &[&str]) {
+        for command in commands {
+            let id = get_next_job_id();
+            let cancel = Arc::new(AtomicBool::new(false));
+            create_job_with_id(id, command.to_string(), thread::current().id(), cancel);
+        }
+    }
+
+    /// 停止したジョブを登録し、その開始の合図を返す
+    fn seed_stopped_job(command: &str) -> (u32, Arc<StartGate>) {
+        let id = get_next_job_id();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let gate = Arc::new(StartGate::default());
+        create_stopped_job_with_id(
+            id,
+            command.to_string(),
+            thread::current().id(),
+            cancel,
+            Arc::clone(&gate),
+        );
+        (id, gate)
+    }
+
+    #[test]
+    fn test_parse_job_specs() {
+        assert_eq!(JobSpec::parse("2").unwrap(), JobSpec::Id(2));
+        assert_eq!(JobSpec::parse("%2").unwrap(), JobSpec::Id(2));
+        assert_eq!(JobSpec::parse("%%").unwrap(), JobSpec::Current);
+        assert_eq!(JobSpec::parse("%+").unwrap(), JobSpec::Current);
+        assert_eq!(JobSpec::parse("%").unwrap(), JobSpec::Current);
+        assert_eq!(JobSpec::parse("%-").unwrap(), JobSpec::Previous);
+        assert_eq!(
+            JobSpec::parse("%sleep").unwrap(),
+            JobSpec::Prefix("sleep".to_string())
+        );
+        assert!(JobSpec::parse("sleep").is_err());
+    }
+
+    #[test]
+    fn test_resolve_job_specs() {
+        let _state = enter(Arc::new(ShellState::new()));
+        assert!(JobSpec::Current.resolve().is_err());
+
+        seed_jobs(&["sleep 10", "echo hi", "sleep 20"]);
+        let resolve = |spec: &str| JobSpec::parse(spec).unwrap().resolve();
+
+        assert_eq!(resolve("2").unwrap(), 2);
+        assert_eq!(resolve("%1").unwrap(), 1);
+        assert_eq!(resolve("%%").unwrap(), 3);
+        assert_eq!(resolve("%+").unwrap(), 3);
+        assert_eq!(resolve("%-").unwrap(), 2);
+        assert_eq!(resolve("%echo").unwrap(), 2);
+
+        // 見つからない場合と複数当てはまる場合はメッセージを分ける
+        let missing = resolve("%9").unwrap_err().to_string();
+        assert!(missing.contains("No such job: 9"), "{missing}");
+        let missing = resolve("%cat").unwrap_err().to_string();
+        assert!(missing.contains("No such job: %cat"), "{missing}");
+        let ambiguous = resolve("%sleep").unwrap_err().to_string();
+        assert!(
+            ambiguous.contains("Ambiguous job spec: %sleep (matches jobs 1, 3)"),
+            "{ambiguous}"
+        );
+    }
+
+    #[test]
+    fn test_failed_job_is_noticed_once() {
+        let _state = enter(Arc::new(ShellState::new()));
+        seed_jobs(&["cat missing.txt", "sleep 10"]);
+        mark_completed(1, String::new(), 1, Some("no such file".to_string()));
+
+        // 通知するのは終了したジョブだけで、一度だけ
+        let notices = take_finished_notices();
+        assert_eq!(notices.len(), 1);
+        let (job, marker) = &notices[0];
+        assert_eq!((job.id, *marker), (1, "-"));
+        assert!(job.has_failed());
+        assert_eq!(job.error.as_deref(), Some("no such file"));
+        assert!(take_finished_notices().is_empty());
+
+        // 通知した失敗は jobs に再び表示しないが、fgで受け取れるよう残しておく
+        let listed: Vec<u32> = list_jobs().iter().map(|job| job.id).collect();
+        assert_eq!(listed, vec![2]);
+    }
+
+    #[test]
+    fn test_finished_job_is_listed_once_with_output() {
+        let _state = enter(Arc::new(ShellState::new()));
+        seed_jobs(&["echo hi", "sleep 10"]);
+        mark_completed(1, "hi\n".to_string(), 0, None);
+
+        // 取り除く前に一度だけ、出力と一緒に一覧に含める
+        let listed = list_jobs();
+        assert_eq!(listed.len(), 2);
+        assert_eq!((listed[0].id, listed[0].output.as_str()), (1, "hi\n"));
+        let listed: Vec<u32> = list_jobs().iter().map(|job| job.id).collect();
+        assert_eq!(listed, vec![2]);
+    }
+
+    #[test]
+    fn test_stopped_job_starts_only_when_resumed() {
+        let _state = enter(Arc::new(ShellState::new()));
+        let (id, gate) = seed_stopped_job("sleep 10");
+
+        // 停止したジョブは終わっていないので通知せず、一覧に残す
+        assert!(matches!(get_job(id).unwrap().status, JobStatus::Stopped));
+        assert!(take_finished_notices().is_empty());
+        assert_eq!(list_jobs().len(), 1);
+
+        let waiter = {
+            let gate = Arc::clone(&gate);
+            thread::spawn(move || gate.wait(&AtomicBool::new(false)))
+        };
+        let job = resume_job(id).unwrap();
+        assert!(matches!(job.status, JobStatus::Running));
+        assert!(job.start_gate.is_none());
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn test_resume_rejects_jobs_that_are_not_stopped() {
+        let _state = enter(Arc::new(ShellState::new()));
+        seed_jobs(&["sleep 10", "echo hi"]);
+        mark_completed(2, "hi\n".to_string(), 0, None);
+
+        let running = resume_job(1).unwrap_err().to_string();
+        assert!(running.contains("job 1 is already running"), "{running}");
+        let finished = resume_job(2).unwrap_err().to_string();
+        assert!(finished.contains("job 2 has finished"), "{finished}");
+        let missing = resume_job(9).unwrap_err().to_string();
+        assert!(missing.contains("No such job: 9"), "{missing}");
+
+        // 一度再開したジョブは再び再開できない
+        let (id, _gate) = seed_stopped_job("sleep 20");
+        assert!(resume_job(id).is_ok());
+        assert!(resume_job(id).is_err());
+    }
+
+    #[test]
+    fn test_stopped_job_gives_up_waiting_when_cancelled() {
+        let _state = enter(Arc::new(ShellState::new()));
+        let gate = StartGate::default();
+        assert!(!gate.wait(&AtomicBool::new(true)));
+
+        gate.open();
+        assert!(gate.wait(&AtomicBool::new(true)));
+    }
+}
@@ -2,15 +2,42 @@
 //!
 //! 基本的なファイル操作やテキスト処理を提供する
 //! 学習用のコマンドラインインターフェース
+//!
+//! `Shell` を使うとプログラムからコマンドを評価できる:
+//!
+//! ```
+//! let mut shell = rucli::Shell::new();
+//! let output = shell.eval("echo hi | grep h").unwrap();
+//! assert_eq!(output.stdout, "hi\n");
+//! ```
 
 pub mod alias;
+pub mod arithmetic;
 pub mod commands;
+pub mod completion;
+pub mod diff;
+pub mod display;
 pub mod environment;
 pub mod error;
 pub mod functions;
 pub mod handlers;
 pub mod history;
+pub mod hooks;
+pub mod input;
 pub mod job;
+pub mod nesting;
+pub mod options;
+pub mod output;
+pub mod pager;
 pub mod parser;
 pub mod pipeline;
 pub mod redirect;
+pub mod shell;
+pub mod state;
+pub mod terminal;
+pub mod translate;
+pub mod trap;
+pub mod walk;
+
+pub use shell::{EvalOutput, Shell, parse_to_ast};
+pub use state::ShellState;
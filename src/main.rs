@@ -1,185 +1,110 @@
 //! rucliのエントリポイント
-
-mod alias;
-mod commands;
-mod environment;
-mod error;
-mod functions;
-mod handlers;
-mod history;
-mod job;
-mod parser;
-mod pipeline;
-mod redirect;
-
-use commands::execute_command;
-use log::{debug, error, info};
+//!
+//! 引数の解釈とロガーの初期化だけを行い、実行は `rucli::Shell` に任せる
 
 use env_logger::Builder;
-use history::{load_history_from_file, save_history_to_file};
-use log::LevelFilter;
-use std::io::{self, Write};
-use std::path::Path;
-use std::time::Instant;
-use std::{env, fs};
-
-use crate::history::add_history;
-use crate::parser::expansion;
-use crate::parser::parse_command;
-
-/// ブロック入力を管理する構造体
-struct BlockInputCollector {
-    lines: Vec<String>,
-    depth: i32,
-    pending_keywords: Vec<(String, i32)>,
+use log::{LevelFilter, debug, info};
+use rucli::handlers::handle_version;
+use rucli::{Shell, parse_to_ast};
+use std::env;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: rucli [--debug] [--dry-run] [-v] [--parse-only] [--norc] [--login] [--version] [-c <command> | [--] <script> [args...]]";
+
+/// コマンドライン引数
+#[derive(Debug, Default)]
+struct Args {
+    debug: bool,                    // --debug
+    dry_run: bool, // --dry-run（副作用のあるコマンドを実行せずに表示する、set -n と同じ）
+    verbose: bool, // -v（スクリプトの各行を実行前に表示する、set -v と同じ）
+    parse_only: bool, // --parse-only（実行せずにパースだけを行い、構文木をJSONで出力する）
+    norc: bool,    // --norc（~/.ruclirc を読み込まない）
+    login: bool,   // --login（プロファイルを読み込む）
+    version: bool, // --version
+    command_string: Option<String>, // -c で渡されたコマンド文字列
+    script_file: Option<String>, // 最初のオプション以外の引数（`--` の後なら `-` で始まってもよい）
+    script_args: Vec<String>, // スクリプトより後の引数（$1, $2, ... になる）
 }
 
-impl BlockInputCollector {
-    fn new() -> Self {
-        BlockInputCollector {
-            lines: Vec::new(),
-            depth: 0,
-            pending_keywords: Vec::new(),
-        }
-    }
-
-    /// 行を追加し、次の状態を返す
-    /// Noneなら入力完了
-    fn add_line(&mut self, line: &str) -> bool {
-        // 現在の行に新しく追加
-        self.lines.push(line.to_string());
-
-        // 新規追加：キーワードを抽出して処理
-        let keywords = Self::extract_keywords(line);
-        for keyword in keywords {
-            match keyword.as_str() {
-                "while" | "for" => {
-                    self.depth += 1;
-                    self.pending_keywords.push(("do".to_string(), self.depth));
-                }
-                "if" => {
-                    self.depth += 1;
-                    self.pending_keywords.push(("then".to_string(), self.depth));
-                }
-                "function" => {
-                    self.depth += 1;
-                    self.pending_keywords.push(("{".to_string(), self.depth));
-                }
-                "do" => {
-                    self.pending_keywords
-                        .retain(|(k, d)| !(k == "do" && *d == self.depth));
-                    self.pending_keywords.push(("done".to_string(), self.depth));
-                }
-                "then" => {
-                    self.pending_keywords
-                        .retain(|(k, d)| !(k == "then" && *d == self.depth));
-                    self.pending_keywords.push(("fi".to_string(), self.depth));
-                }
-                "{" => {
-                    self.pending_keywords
-                        .retain(|(k, d)| !(k == "{" && *d == self.depth));
-                    self.pending_keywords.push(("}".to_string(), self.depth));
-                }
-                "done" | "fi" | "}" => {
-                    self.pending_keywords
-                        .retain(|(k, d)| !(k == keyword.as_str() && *d == self.depth));
-                    self.depth -= 1;
-                }
-                "else" => {
-                    // elseは深さを変えない（fiを待ち続ける）
-                }
-                _ => {}
+/// コマンドライン引数を解釈する
+///
+/// オプションはスクリプトファイルより前に置く。スクリプトより後の引数は `-` で始まっていても
+/// すべてスクリプトの引数になる（シバン `#!/usr/bin/env rucli` から `rucli ./x.rsh -v` と
+/// 呼ばれても、`-v` はスクリプトに渡る）。`--` の次の引数は常にスクリプトとして扱う
+///
+/// # Errors
+/// * 知らないオプション、-c のコマンド文字列がない、-c とスクリプトを両方指定した場合
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--debug" => parsed.debug = true,
+            "--dry-run" => parsed.dry_run = true,
+            "-v" => parsed.verbose = true,
+            "--parse-only" => parsed.parse_only = true,
+            "--norc" => parsed.norc = true,
+            "--login" => parsed.login = true,
+            "--version" => parsed.version = true,
+            "-c" => match args.next() {
+                Some(command) => parsed.command_string = Some(command),
+                None => return Err("-c requires a command string".to_string()),
+            },
+            "--" => {
+                parsed.script_file = args.next();
+                break;
             }
-        }
-
-        // pending_keywordsが空 = 完了
-        !self.pending_keywords.is_empty() || self.depth > 0
-    }
-
-    fn extract_keywords(line: &str) -> Vec<String> {
-        let mut keywords = Vec::new();
-        let words: Vec<&str> = line.split_whitespace().collect();
-
-        for word in words.iter() {
-            match *word {
-                "while" | "for" | "if" | "do" | "then" | "done" | "fi" | "else" | "function"
-                | "{" | "}" => {
-                    keywords.push(word.to_string());
-                }
-                _ => {}
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option '{arg}'"));
+            }
+            _ => {
+                parsed.script_file = Some(arg);
+                break;
             }
         }
-
-        keywords
     }
+    parsed.script_args = args.collect();
 
-    /// 蓄積された入力を一行に統合
-    fn get_complete_command(&self) -> String {
-        let mut result = String::new();
+    if parsed.command_string.is_some() && parsed.script_file.is_some() {
+        return Err("-c cannot be combined with a script file".to_string());
+    }
 
-        // 空行を除外したリストを作成
-        let non_empty_lines: Vec<&str> = self
-            .lines
-            .iter()
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .collect();
+    Ok(parsed)
+}
 
-        for (i, line) in non_empty_lines.iter().enumerate() {
-            // 行を追加
-            result.push_str(line);
+/// `--login` で読み込むプロファイル（RUCLI_PROFILE、なければ ~/.rucli_profile）
+fn profile_path() -> Option<PathBuf> {
+    env::var_os("RUCLI_PROFILE")
+        .filter(|file| !file.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".rucli_profile")))
+}
 
-            // 最後の行でなければ区切り文字を追加
-            if i < non_empty_lines.len() - 1 {
-                let next = non_empty_lines[i + 1];
+/// 対話シェルで読み込む設定ファイル（~/.ruclirc）
+fn rc_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".ruclirc"))
+}
 
-                match (*line, next) {
-                    // "for/while/if ..." の後で "do/then" の前にはセミコロン
-                    (curr, "do") if curr.starts_with("for ") || curr.starts_with("while ") => {
-                        result.push_str("; ");
-                    }
-                    (curr, "then") if curr.starts_with("if ") => {
-                        result.push_str("; ");
-                    }
-                    // "do/then/else" の後はスペースのみ
-                    ("do" | "then" | "else", _) => {
-                        result.push(' ');
-                    }
-                    // その他の場合はセミコロン
-                    _ => {
-                        result.push_str("; ");
-                    }
-                }
-            }
+fn main() {
+    // 引数を解釈する（オプションの誤りは実行せずに終了する）
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("Error: {message}\n{USAGE}");
+            std::process::exit(2);
         }
+    };
 
-        result
-    }
-
-    /// 現在のプロンプトを取得
-    fn get_prompt(&self) -> &str {
-        if self.pending_keywords.is_empty() && self.depth == 0 {
-            "> "
-        } else {
-            ">> "
-        }
+    if args.version {
+        println!("{}", handle_version());
+        return;
     }
-}
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 引数を取得
-    let args: Vec<String> = env::args().collect();
 
-    // コマンドライン引数をチェック
-    let debug_mode = args.iter().any(|arg| arg == "--debug");
-
-    // スクリプトファイルのチェック
-    // 引数の最初にスクリプトファイルが入っているかチェック
-    let script_file = if args.len() > 1 && !args[1].starts_with("--") {
-        Some(&args[1])
-    } else {
-        None
-    };
+    let debug_mode = args.debug;
+    let dry_run = args.dry_run;
+    let command_string = args.command_string.as_deref();
+    let script_file = args.script_file.as_deref();
 
     // env_loggerの設定
     let mut builder = Builder::from_default_env();
@@ -198,388 +123,138 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("Debug mode enabled");
     }
 
-    if let Err(e) = load_history_from_file(None) {
-        debug!("Failed to load history: {e}");
+    if args.parse_only {
+        std::process::exit(print_ast(command_string, script_file));
     }
 
-    // 実行モードの分岐
-    if let Some(filename) = script_file {
-        run_script_file(filename)?;
-    } else {
-        run_interactive_mode()?;
-    }
+    let mut shell = Shell::new();
 
-    // 履歴を保存
-    if let Err(e) = save_history_to_file(None) {
-        debug!("Failed to save history: {e}");
-    } else {
-        debug!("succeed to save history");
+    for (enabled, name) in [(dry_run, "dryrun"), (args.verbose, "verbose")] {
+        if enabled && let Err(e) = shell.set_option(name, true) {
+            eprintln!("Error: {e}");
+            std::process::exit(e.exit_code());
+        }
     }
 
-    Ok(())
-}
-
-// 対話モードでの実行関数
-fn run_interactive_mode() -> Result<(), Box<dyn std::error::Error>> {
-    // 起動時の作業ディレクトリを記録（デバッグ用）
-    let initial_dir = env::current_dir()?;
-    debug!("Initial working directory: {initial_dir:?}");
-
-    info!("Starting rucli...");
-    println!("Hello, rucli!");
-
-    // BlockInputCollector を追加
-    let mut block_collector = BlockInputCollector::new();
-
-    loop {
-        // プロンプトを動的に変更
-        print!("{}", block_collector.get_prompt());
-        io::stdout().flush().unwrap();
-
-        let input = read_input();
-        debug!("Received input: {input}");
-
-        // ブロック入力の処理
-        if block_collector.add_line(&input) {
-            // まだ入力継続中
-            continue;
+    // ログインシェルではプロファイルを読み込む（ないファイルは読み飛ばし、失敗しても続ける）
+    if args.login
+        && let Some(profile) = profile_path().filter(|path| path.exists())
+    {
+        debug!("Loading profile: {profile:?}");
+        match shell.source_env_file(&profile) {
+            Ok(None) => {}
+            Ok(Some(status)) => std::process::exit(shell.run_exit_trap(status)),
+            Err(e) => eprintln!("Error: {e}"),
         }
+    }
 
-        // 入力完了 - コマンドを取得
-        let complete_input = block_collector.get_complete_command();
-        block_collector = BlockInputCollector::new(); // リセット
-
-        // 空入力なら次へ
-        if complete_input.trim().is_empty() {
-            continue;
+    // 対話シェルでは ~/.ruclirc を読み込む（--norc なら読み込まない。ないファイルは読み飛ばし、失敗しても続ける）
+    if args.norc {
+        debug!("--norc: not loading ~/.ruclirc");
+    } else if command_string.is_none()
+        && script_file.is_none()
+        && let Some(rc) = rc_path().filter(|path| path.exists())
+    {
+        debug!("Loading rc file: {rc:?}");
+        match shell.source_env_file(&rc) {
+            Ok(None) => {}
+            Ok(Some(status)) => std::process::exit(shell.run_exit_trap(status)),
+            Err(e) => eprintln!("Error: {e}"),
         }
+    }
 
-        if parser::contains_heredoc(&complete_input) {
-            if handle_heredoc_command(&complete_input) {
-                break; // Exitコマンドでループを終了
+    // スクリプト・-c の実行前に RUCLI_ENV のファイルを読み込む（bashのBASH_ENVと同じ）
+    // 失敗した場合は本体を実行しない
+    if (command_string.is_some() || script_file.is_some())
+        && let Some(env_file) = env::var_os("RUCLI_ENV").filter(|file| !file.is_empty())
+    {
+        debug!("Loading RUCLI_ENV: {env_file:?}");
+        match shell.source_env_file(&env_file) {
+            Ok(None) => {}
+            Ok(Some(status)) => std::process::exit(shell.run_exit_trap(status)),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(shell.run_exit_trap(e.exit_code()));
             }
-        } else if handle_normal_command(&complete_input) {
-            break; // Exitコマンドでループを終了
         }
     }
 
-    Ok(())
-}
-
-fn run_script_file(filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // ファイルの存在確認
-    if !Path::new(filename).exists() {
-        eprintln!("Error: Script file {filename} not found");
-        std::process::exit(1);
-    };
-
-    // ファイル全体を読み込む
-    let contents = fs::read_to_string(filename)?;
-
-    let mut block_collector = BlockInputCollector::new();
-
-    for line in contents.lines() {
-        let line = line.trim();
-
-        // シバンコメント、空行スキップ
-        if line.is_empty() || line.starts_with('#') {
-            continue;
+    let result = if let Some(command) = command_string {
+        // -cモードは履歴を読み書きせず、コマンド文字列だけ実行する
+        shell.run(command)
+    } else {
+        if let Err(e) = shell.load_history(None) {
+            debug!("Failed to load history: {e}");
         }
 
-        // すべての行をBlockInputCollectorに渡す（インタラクティブと同じ）
-        if !block_collector.add_line(line) {
-            // 完了したら実行
-            let complete_input = block_collector.get_complete_command();
-
-            if !complete_input.trim().is_empty() {
-                // この行を追加
-                if parser::contains_heredoc(&complete_input) {
-                    if handle_heredoc_command(&complete_input) {
-                        break;
-                    }
-                } else if handle_normal_command(&complete_input) {
-                    break;
-                }
-            }
+        // 実行モードの分岐
+        let result = match script_file {
+            Some(filename) => shell.run_script_with_args(filename, &args.script_args),
+            None => shell.run_interactive(),
+        };
 
-            // リセット
-            block_collector = BlockInputCollector::new();
+        // 履歴を保存（ドライランではファイルを書き換えない）
+        if dry_run {
+            debug!("Dry run: history not saved");
+        } else if let Err(e) = shell.save_history(None) {
+            debug!("Failed to save history: {e}");
+        } else {
+            debug!("succeed to save history");
         }
-    }
-
-    // ファイル終端で未完了のブロックがある場合
-    if block_collector.depth > 0 || !block_collector.pending_keywords.is_empty() {
-        eprintln!("Error: Incomplete block structure at end of file");
-        std::process::exit(1);
-    }
-
-    Ok(())
-}
-
-// 入力された文字列の読み取り
-fn read_input() -> String {
-    let mut input = String::new();
 
-    // 文字列読み取り
-    io::stdin()
-        .read_line(&mut input)
-        .expect("failed to read line");
-
-    // 改行文字をトリミングしてString型にしてから返す
-    input.trim().to_string()
-}
-
-/// ヒアドキュメント付きコマンドを処理
-fn handle_heredoc_command(input: &str) -> bool {
-    // boolを返すように変更
-    if let Some((cmd_str, delimiter, strip_indent)) = parser::parse_heredoc_header(input) {
-        debug!(
-            "Heredoc header: cmd='{cmd_str}', delimiter='{delimiter}', strip_indent={strip_indent}"
-        );
-
-        // 内容を収集
-        let content = read_heredoc_content(&delimiter, strip_indent);
-        debug!(
-            "Collected heredoc content: {} lines",
-            content.lines().count()
-        );
-
-        // 展開を適用
-        let expanded_content = environment::expand_variables(&content);
-        let final_content = match environment::expand_command_substitution(&expanded_content) {
-            Ok(substituted) => substituted,
-            Err(_) => expanded_content,
-        };
+        result
+    };
 
-        // コマンドを実行
-        return execute_with_input(&cmd_str, &final_content); // bool を返す
-    }
-    false
+    // 最後に実行したコマンド（またはexit）のステータスで終了する
+    // 終了前に EXIT の trap を実行する（trap の中で exit した場合はそのステータスになる）
+    let status = match result {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_code()
+        }
+    };
+    std::process::exit(shell.run_exit_trap(status));
 }
 
-/// 通常のコマンドを処理
-fn handle_normal_command(input: &str) -> bool {
-    // 履歴展開を実行
-    let expanded_input = if expansion::contains_history_expansion(input) {
-        match expansion::expand_history(input) {
-            Ok(expanded) => expanded,
+/// スクリプト（または -c のコマンド文字列）をパースし、文ごとの構文木をJSONで出力する
+///
+/// パースできない文があれば、構文木は出力せずに行番号付きの診断を標準エラーに出力する
+///
+/// # Returns
+/// * 終了ステータス（すべてパースできれば0、パースできない文があれば2）
+fn print_ast(command_string: Option<&str>, script_file: Option<&str>) -> i32 {
+    let (name, source) = match (command_string, script_file) {
+        (Some(command), _) => ("-c".to_string(), command.to_string()),
+        (None, Some(path)) => match std::fs::read_to_string(path) {
+            Ok(source) => (path.to_string(), source),
             Err(e) => {
-                eprintln!("{e}");
-                return false; // エラーでも継続
+                eprintln!("Error: {path}: {e}");
+                return 1;
             }
+        },
+        (None, None) => {
+            eprintln!("Error: --parse-only requires a script file or -c");
+            return 2;
         }
-    } else {
-        input.to_string()
     };
 
-    add_history(expanded_input.clone());
-
-    match parse_command(expanded_input.as_str()) {
-        Ok(command) => {
-            debug!("Command parsed successfully");
-            let start = Instant::now();
-            match execute_command(command, None) {
-                Ok(should_exit) => {
-                    if should_exit {
-                        return true; // 終了シグナル
-                    }
-                }
-                Err(err) => {
-                    error!("Command execution failed: {err}");
-                    eprintln!("{err}");
-                }
+    match parse_to_ast(&source) {
+        Ok(statements) => match serde_json::to_string_pretty(&statements) {
+            Ok(json) => {
+                println!("{json}");
+                0
             }
-            let duration = start.elapsed().as_secs_f64() * 1000.0;
-            debug!("処理時間: {duration:?}ms");
-        }
-        Err(error) => {
-            debug!("Parse error occurred: {error}");
-            eprintln!("{error}");
-        }
-    }
-
-    false // 継続
-}
-
-/// 入力付きでコマンドを実行
-fn execute_with_input(cmd_str: &str, input: &str) -> bool {
-    // boolを返すように変更
-    match parse_command(cmd_str) {
-        Ok(command) => {
-            let start = Instant::now();
-            match commands::execute_command(command, Some(input)) {
-                Ok(should_exit) => {
-                    if should_exit {
-                        return true;
-                    }
-                }
-                Err(err) => {
-                    error!("Command execution failed: {err}");
-                    eprintln!("{err}");
-                }
+            Err(e) => {
+                eprintln!("Error: failed to serialize JSON: {e}");
+                1
             }
-            let duration = start.elapsed().as_secs_f64() * 1000.0;
-            debug!("処理時間: {duration:?}ms");
-        }
-        Err(error) => {
-            debug!("Parse error occurred: {error}");
-            eprintln!("{error}");
-        }
-    }
-    false
-}
-
-/// ヒアドキュメントの内容を読み取る
-fn read_heredoc_content(delimiter: &str, strip_indent: bool) -> String {
-    // 空のVec<String>を作成
-    let mut lines = Vec::new();
-    loop {
-        // heredocプロンプト表示
-        print!("heredoc> ");
-        io::stdout().flush().unwrap();
-
-        let mut line = String::new();
-        // 一行読み取り
-        io::stdin()
-            .read_line(&mut line)
-            .expect("failed to read line");
-
-        // デリミタと完全に一致したらbreak
-        let line = line.trim_end_matches('\n').trim_end_matches('\r');
-        if delimiter == line {
-            break;
+        },
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{name}: {diagnostic}");
+            }
+            2
         }
-
-        // strip_indentがtrueなら先頭タブを削除
-        let processed_line = if strip_indent {
-            line.strip_prefix('\t').unwrap_or(line)
-        } else {
-            line
-        };
-
-        // Vecに追加
-        lines.push(processed_line.to_string());
-    }
-    lines.join("\n")
-}
-
-#[cfg(test)]
-mod block_input_tests {
-    use super::*;
-
-    #[test]
-    fn test_simple_for_loop() {
-        let mut collector = BlockInputCollector::new();
-
-        assert!(collector.add_line("for i in 1 2 3")); // 継続
-        assert_eq!(collector.get_prompt(), ">> ");
-
-        assert!(collector.add_line("do")); // 継続
-        assert!(collector.add_line("  echo $i")); // 継続
-        assert!(!collector.add_line("done")); // 完了
-
-        assert_eq!(
-            collector.get_complete_command(),
-            "for i in 1 2 3; do echo $i; done"
-        );
-    }
-
-    #[test]
-    fn test_while_loop() {
-        let mut collector = BlockInputCollector::new();
-
-        assert!(collector.add_line("while test -f flag"));
-        assert!(collector.add_line("do"));
-        assert!(collector.add_line("  cat flag"));
-        assert!(collector.add_line("  rm flag"));
-        assert!(!collector.add_line("done"));
-
-        let cmd = collector.get_complete_command();
-        assert!(cmd.contains("while test -f flag"));
-        assert!(cmd.contains("do cat flag"));
-        assert!(cmd.contains("rm flag"));
-        assert!(cmd.contains("done"));
-    }
-
-    #[test]
-    fn test_if_then_else_fi() {
-        let mut collector = BlockInputCollector::new();
-
-        assert!(collector.add_line("if pwd")); // 継続
-        assert!(collector.add_line("then")); // 継続
-        assert!(collector.add_line("  echo exists")); // 継続
-        assert!(collector.add_line("else")); // 継続
-        assert!(collector.add_line("  echo not found")); // 継続
-        assert!(!collector.add_line("fi")); // 完了
-
-        let cmd = collector.get_complete_command();
-        assert_eq!(cmd, "if pwd; then echo exists; else echo not found; fi");
-    }
-
-    #[test]
-    fn test_nested_for_loops() {
-        let mut collector = BlockInputCollector::new();
-
-        assert!(collector.add_line("for i in 1 2"));
-        assert!(collector.add_line("do"));
-        assert_eq!(collector.depth, 1);
-        assert_eq!(collector.pending_keywords, vec![("done".to_string(), 1)]);
-
-        assert!(collector.add_line("  for j in a b"));
-        assert_eq!(collector.depth, 2);
-        assert_eq!(
-            collector.pending_keywords,
-            vec![("done".to_string(), 1), ("do".to_string(), 2)]
-        );
-
-        assert!(collector.add_line("  do"));
-        assert_eq!(
-            collector.pending_keywords,
-            vec![("done".to_string(), 1), ("done".to_string(), 2)]
-        );
-
-        assert!(collector.add_line("    echo $i$j"));
-        assert!(collector.add_line("  done"));
-        assert_eq!(collector.depth, 1);
-        assert_eq!(collector.pending_keywords, vec![("done".to_string(), 1)]);
-
-        assert!(!collector.add_line("done")); // 完了
-        assert_eq!(collector.depth, 0);
-        assert!(collector.pending_keywords.is_empty());
-
-        let cmd = collector.get_complete_command();
-        assert!(cmd.contains("for i in 1 2"));
-        assert!(cmd.contains("for j in a b"));
-    }
-
-    #[test]
-    fn test_function_multiline() {
-        let mut collector = BlockInputCollector::new();
-
-        assert!(collector.add_line("function test()")); // 継続
-        assert!(collector.add_line("{")); // 継続
-        assert!(collector.add_line("  echo Hello")); // 継続
-        assert!(collector.add_line("  echo World")); // 継続
-        assert!(!collector.add_line("}")); // 完了
-
-        let cmd = collector.get_complete_command();
-        assert!(cmd.contains("function test()"));
-        assert!(cmd.contains("echo Hello"));
-        assert!(cmd.contains("echo World"));
-    }
-
-    #[test]
-    fn test_empty_lines_ignored() {
-        let mut collector = BlockInputCollector::new();
-
-        assert!(collector.add_line("for i in 1 2 3"));
-        assert!(collector.add_line("do"));
-        assert!(collector.add_line("")); // 空行
-        assert!(collector.add_line("  echo $i"));
-        assert!(collector.add_line("")); // 空行
-        assert!(!collector.add_line("done"));
-
-        let cmd = collector.get_complete_command();
-        assert_eq!(cmd, "for i in 1 2 3; do echo $i; done");
     }
 }
@@ -0,0 +1,85 @@
+//! コマンド置換・エイリアス展開・関数呼び出しの入れ子の深さを制限するモジュール
+//!
+//! 自分自身を呼び出す関数や、生成された深い `$( )` の入れ子でスタックを使い切らないよう、
+//! 入れ子の深さがRUCLI_MAX_DEPTH（既定は64）を超えたらエラーにする。
+//! 深さはスレッドごとに数えるので、バックグラウンドジョブはそれぞれ独立に制限される。
+
+use crate::environment::get_var;
+use crate::error::{Result, RucliError};
+use std::cell::Cell;
+
+/// RUCLI_MAX_DEPTH が未設定・不正な場合の入れ子の深さの上限
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+thread_local! {
+    /// 現在の入れ子の深さ
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// 入れ子の深さを一つ増やして処理を実行する
+///
+/// # Arguments
+/// * `what` - 入れ子にする処理の名前（エラーメッセージに使う）
+/// * `f` - 実行する処理
+///
+/// # Errors
+/// - 深さが上限を超える場合（`f` は実行しない）
+/// - `f` が返したエラー
+pub fn nested<T>(what: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let limit = max_depth();
+    let depth = DEPTH.with(Cell::get);
+    if depth >= limit {
+        return Err(RucliError::NestingTooDeep {
+            what: what.to_string(),
+            limit,
+        });
+    }
+
+    DEPTH.with(|d| d.set(depth + 1));
+    let result = f();
+    DEPTH.with(|d| d.set(depth));
+
+    result
+}
+
+/// RUCLI_MAX_DEPTH から入れ子の深さの上限を取得
+fn max_depth() -> usize {
+    get_var("RUCLI_MAX_DEPTH")
+        .and_then(|max| max.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_DEPTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::set_var;
+    use crate::state::{ShellState, enter};
+    use std::sync::Arc;
+
+    fn descend(levels: usize) -> Result<usize> {
+        if levels == 0 {
+            return Ok(DEPTH.with(Cell::get));
+        }
+        nested("test", || descend(levels - 1))
+    }
+
+    #[test]
+    fn test_depth_is_limited_and_restored() {
+        let _state = enter(Arc::new(ShellState::new()));
+
+        assert_eq!(descend(DEFAULT_MAX_DEPTH).unwrap(), DEFAULT_MAX_DEPTH);
+        assert!(matches!(
+            descend(DEFAULT_MAX_DEPTH + 1),
+            Err(RucliError::NestingTooDeep {
+                limit: DEFAULT_MAX_DEPTH,
+                ..
+            })
+        ));
+        assert_eq!(DEPTH.with(Cell::get), 0);
+
+        set_var("RUCLI_MAX_DEPTH", "3");
+        assert!(descend(3).is_ok());
+        let err = descend(4).unwrap_err();
+        assert_eq!(err.to_string(), "test nested too deeply (limit 3)");
+    }
+}
@@ -0,0 +1,125 @@
+//! シェルオプション（`set -o`）を管理するモジュール
+
+use crate::error::{Result, RucliError};
+use crate::state::{self, LockExt};
+
+/// シェルオプションの状態
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShellOptions {
+    pub dryrun: bool,  // 副作用のあるコマンドを実行せず、実行内容を表示する（`set -n`）
+    pub errexit: bool, // 失敗したコマンドがあればそこで止める（`set -e`）
+    pub interactive_clobber: bool, // 対話モードのプロンプトで `>` が空でないファイルを切り詰める前に確認する
+    pub noclobber: bool,           // `>` で既存のファイルを上書きしない（`>|` は上書きする）
+    pub verbose: bool, // スクリプトの各行を実行前に行番号付きで標準エラー出力に表示する（`set -v`）
+}
+
+/// 設定できるオプションの名前（`set -o` の表示順）
+pub const OPTION_NAMES: &[&str] = &[
+    "dryrun",
+    "errexit",
+    "interactive-clobber",
+    "noclobber",
+    "verbose",
+];
+
+impl ShellOptions {
+    /// 名前に対応するオプションへの参照
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dryrun" => Some(&mut self.dryrun),
+            "errexit" => Some(&mut self.errexit),
+            "interactive-clobber" => Some(&mut self.interactive_clobber),
+            "noclobber" => Some(&mut self.noclobber),
+            "verbose" => Some(&mut self.verbose),
+            _ => None,
+        }
+    }
+
+    /// 名前に対応するオプションの状態
+    fn get(mut self, name: &str) -> Option<bool> {
+        self.flag_mut(name).map(|flag| *flag)
+    }
+}
+
+/// 名前がオプションとして存在するか
+pub fn is_option_name(name: &str) -> bool {
+    OPTION_NAMES.contains(&name)
+}
+
+/// 現在のオプションを取得
+pub fn current() -> ShellOptions {
+    *state::current().options.lock_or_recover()
+}
+
+/// オプションを有効・無効にする
+///
+/// # Errors
+/// - 存在しないオプション名の場合
+pub fn set_option(name: &str, enabled: bool) -> Result<()> {
+    let state = state::current();
+    let mut options = state.options.lock_or_recover();
+
+    let flag = options
+        .flag_mut(name)
+        .ok_or_else(|| RucliError::InvalidArgument(format!("set: {name}: invalid option name")))?;
+    *flag = enabled;
+
+    Ok(())
+}
+
+/// 全オプションの名前と状態を取得
+pub fn list_options() -> Vec<(&'static str, bool)> {
+    let options = current();
+    OPTION_NAMES
+        .iter()
+        .map(|&name| (name, options.get(name).unwrap_or(false)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ShellState, enter};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_set_and_list_options() {
+        let _state = enter(Arc::new(ShellState::new()));
+        assert!(!current().noclobber);
+        assert_eq!(
+            list_options(),
+            vec![
+                ("dryrun", false),
+                ("errexit", false),
+                ("interactive-clobber", false),
+                ("noclobber", false),
+                ("verbose", false)
+            ]
+        );
+
+        set_option("noclobber", true).unwrap();
+        assert!(current().noclobber);
+        assert!(!current().errexit);
+        assert_eq!(
+            list_options(),
+            vec![
+                ("dryrun", false),
+                ("errexit", false),
+                ("interactive-clobber", false),
+                ("noclobber", true),
+                ("verbose", false)
+            ]
+        );
+
+        set_option("noclobber", false).unwrap();
+        assert!(!current().noclobber);
+    }
+
+    #[test]
+    fn test_unknown_option_name() {
+        let _state = enter(Arc::new(ShellState::new()));
+        assert!(set_option("nosuchoption", true).is_err());
+        assert!(!is_option_name("nosuchoption"));
+        assert!(is_option_name("noclobber"));
+    }
+}
@@ -0,0 +1,249 @@
+//! コマンド出力の書き出し先を管理するモジュール
+//!
+//! 通常は標準出力・標準エラーに書き出すが、`capture` の実行中は
+//! 同じスレッドからの出力をバッファに溜める（`Shell::eval` で使用）。
+//! `exec > file` で標準出力の書き出し先を変更した場合は、以降の出力をそちらに書き出す。
+//! 標準出力はバイト列のまま書き出し、端末に表示する場合だけUTF-8でないバイトを U+FFFD にする。
+
+use crate::environment::get_var;
+use crate::state::{self, LockExt};
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+
+/// シェルの状態に保持する書き出し先（サブシェルの保存・復元のため共有できる形にする）
+pub type SharedWriter = Arc<Mutex<dyn Write + Send>>;
+
+/// キャプチャした出力
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Captured {
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+}
+
+thread_local! {
+    // キャプチャ中のバッファのスタック（最後の要素が現在の書き出し先）
+    static CAPTURES: RefCell<Vec<Captured>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 標準出力に一行書き出す
+pub fn print_line(text: &str) {
+    write_stdout(format!("{text}\n"));
+}
+
+/// 標準エラーに一行書き出す
+pub fn eprint_line(text: &str) {
+    write_stderr(&format!("{text}\n"));
+}
+
+/// 標準出力に書き出す（改行なし）
+pub fn write_stdout(output: impl AsRef<[u8]>) {
+    let output = output.as_ref();
+    let captured = CAPTURES.with(|captures| match captures.borrow_mut().last_mut() {
+        Some(buffer) => {
+            buffer.stdout.extend_from_slice(output);
+            true
+        }
+        None => false,
+    });
+
+    if captured {
+        return;
+    }
+
+    // exec で変更した書き出し先があればそちらに書く
+    let redirected = state::current().stdout.lock_or_recover().clone();
+    match redirected {
+        Some(writer) => {
+            let mut writer = writer.lock_or_recover();
+            let _ = writer.write_all(output);
+            let _ = writer.flush();
+        }
+        None => write_to(&mut io::stdout(), output),
+    }
+}
+
+/// 端末なら表示用に（UTF-8でないバイトは U+FFFD にして）、それ以外ならそのまま書き出す
+fn write_to(stream: &mut (impl Write + IsTerminal), output: &[u8]) {
+    let _ = if stream.is_terminal() {
+        stream.write_all(String::from_utf8_lossy(output).as_bytes())
+    } else {
+        stream.write_all(output)
+    };
+    let _ = stream.flush();
+}
+
+/// 以降の標準出力の書き出し先を変更する（None なら標準出力に戻す）
+pub fn redirect_stdout(writer: Option<SharedWriter>) {
+    *state::current().stdout.lock_or_recover() = writer;
+}
+
+/// 標準エラーに書き出す（改行なし）
+pub fn write_stderr(text: &str) {
+    let captured = CAPTURES.with(|captures| match captures.borrow_mut().last_mut() {
+        Some(buffer) => {
+            buffer.stderr.push_str(text);
+            true
+        }
+        None => false,
+    });
+
+    if !captured {
+        write_to(&mut io::stderr(), text.as_bytes());
+    }
+}
+
+/// 出力を直接表示する端末の幅を取得する
+///
+/// RUCLI_COLUMNS が設定されていれば、端末かどうかに関係なくその値を使う（テスト用）
+///
+/// # Returns
+/// * 端末の幅。キャプチャ中・exec でリダイレクト中・標準出力が端末でない場合は None
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = get_var("RUCLI_COLUMNS").and_then(|value| value.trim().parse().ok()) {
+        return Some(columns);
+    }
+
+    if !stdout_is_terminal() {
+        return None;
+    }
+
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && size.ws_col > 0 {
+        Some(size.ws_col as usize)
+    } else {
+        None
+    }
+}
+
+/// 出力を直接端末に表示しているか
+///
+/// キャプチャ中・exec でリダイレクト中・標準出力が端末でない場合は false
+pub fn stdout_is_terminal() -> bool {
+    let captured = CAPTURES.with(|captures| !captures.borrow().is_empty());
+    let redirected = state::current().stdout.lock_or_recover().is_some();
+    !captured && !redirected && io::stdout().is_terminal()
+}
+
+/// 名前を幅に収まる列数で並べる（lsと同じく上から下、左から右の順）
+///
+/// 列の間は空白2つ。一つの名前が幅を超える場合は一列にする
+///
+/// # Arguments
+/// * `items` - 並べる名前
+/// * `width` - 一行の最大幅
+pub fn format_columns(items: &[&str], width: usize) -> String {
+    const GAP: usize = 2;
+
+    let lengths: Vec<usize> = items.iter().map(|item| item.chars().count()).collect();
+
+    // 幅に収まる最小の行数を探す
+    let (rows, column_widths) = (1..=items.len().max(1))
+        .map(|rows| {
+            let widths: Vec<usize> = lengths
+                .chunks(rows)
+                .map(|column| column.iter().copied().max().unwrap_or(0))
+                .collect();
+            (rows, widths)
+        })
+        .find(|(_, widths)| {
+            widths.iter().sum::<usize>() + GAP * widths.len().saturating_sub(1) <= width
+        })
+        .unwrap_or_else(|| (items.len(), vec![0]));
+
+    (0..rows.min(items.len()))
+        .map(|row| {
+            let cells: Vec<(usize, &str)> = (row..items.len())
+                .step_by(rows)
+                .map(|index| (column_widths[index / rows], items[index]))
+                .collect();
+            let last = cells.len() - 1;
+            cells
+                .iter()
+                .enumerate()
+                .map(|(i, (column_width, item))| {
+                    if i == last {
+                        item.to_string()
+                    } else {
+                        format!("{item:<width$}", width = column_width + GAP)
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 処理中の出力をキャプチャする
+///
+/// # Arguments
+/// * `f` - 実行する処理
+///
+/// # Returns
+/// * 処理の戻り値とキャプチャした出力
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Captured) {
+    // パニックしてもスタックを戻すためのガード
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CAPTURES.with(|captures| captures.borrow_mut().pop());
+        }
+    }
+
+    CAPTURES.with(|captures| captures.borrow_mut().push(Captured::default()));
+    let guard = Guard;
+    let result = f();
+    let captured = CAPTURES.with(|captures| captures.borrow().last().cloned().unwrap_or_default());
+    drop(guard);
+
+    (result, captured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_stdout_and_stderr() {
+        let (value, captured) = capture(|| {
+            print_line("out");
+            eprint_line("err");
+            42
+        });
+
+        assert_eq!(value, 42);
+        assert_eq!(captured.stdout, b"out\n");
+        assert_eq!(captured.stderr, "err\n");
+    }
+
+    #[test]
+    fn test_format_columns_fills_top_to_bottom() {
+        let items = ["a", "bb", "ccc", "d", "eeeee", "f"];
+
+        // 全部が一行に収まる
+        assert_eq!(format_columns(&items, 80), "a  bb  ccc  d  eeeee  f");
+        // 2行3列: a d / bb eeeee / ccc f
+        assert_eq!(format_columns(&items, 16), "a   ccc  eeeee\nbb  d    f");
+        // 幅が足りなければ一列
+        assert_eq!(format_columns(&items, 3), "a\nbb\nccc\nd\neeeee\nf");
+    }
+
+    #[test]
+    fn test_format_columns_edge_cases() {
+        assert_eq!(format_columns(&[], 80), "");
+        assert_eq!(format_columns(&["only"], 80), "only");
+        assert_eq!(format_columns(&["toolongname", "x"], 5), "toolongname\nx");
+    }
+
+    #[test]
+    fn test_nested_capture_is_isolated() {
+        let (_, outer) = capture(|| {
+            print_line("outer");
+            let (_, inner) = capture(|| print_line("inner"));
+            assert_eq!(inner.stdout, b"inner\n");
+        });
+
+        assert_eq!(outer.stdout, b"outer\n");
+    }
+}
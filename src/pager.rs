@@ -0,0 +1,157 @@
+//! 端末に表示する長い出力をページ送り・切り詰めするモジュール
+//!
+//! RUCLI_PAGE_LINES（既定は未設定で無効）を超える行数の出力を端末に直接表示するとき、
+//! RUCLI_PAGER に従って内蔵のページャ（`internal`、既定）で一画面ずつ表示するか、
+//! 先頭だけを表示して残りの行数を知らせる（`truncate`）。
+//! 端末に直接表示しない出力（パイプライン・リダイレクト・コマンド置換）はそのまま渡す。
+
+use crate::environment::get_var;
+use crate::output;
+use crate::terminal::KeyMode;
+use std::io::{self, IsTerminal, Read};
+
+/// 長い出力の扱い方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PagerMode {
+    Internal, // スペースで次の画面、Enterで次の行、qで終了
+    Truncate, // 先頭だけを表示し、残りの行数を知らせる
+}
+
+/// 出力を標準出力に書き出す（端末に直接表示する長い出力はページ送り・切り詰めする）
+pub fn write_paged(bytes: &[u8]) {
+    let Some(limit) = page_lines() else {
+        return output::write_stdout(bytes);
+    };
+    // 端末に表示するのでUTF-8でないバイトは U+FFFD にしてよい
+    let text = String::from_utf8_lossy(bytes);
+    if !output::stdout_is_terminal() || text.lines().count() <= limit {
+        return output::write_stdout(bytes);
+    }
+
+    // キーを読めなければページ送りできないので切り詰める
+    match pager_mode() {
+        PagerMode::Internal if io::stdin().is_terminal() => page(&text, limit),
+        _ => output::write_stdout(truncate(&text, limit)),
+    }
+}
+
+/// RUCLI_PAGE_LINES から一画面の行数を取得（未設定・0・不正な値ならNone）
+fn page_lines() -> Option<usize> {
+    get_var("RUCLI_PAGE_LINES")
+        .and_then(|lines| lines.trim().parse().ok())
+        .filter(|&lines| lines > 0)
+}
+
+/// RUCLI_PAGER から長い出力の扱い方を取得（未設定・不明な値なら内蔵のページャ）
+fn pager_mode() -> PagerMode {
+    match get_var("RUCLI_PAGER").as_deref().map(str::trim) {
+        Some("truncate") => PagerMode::Truncate,
+        _ => PagerMode::Internal,
+    }
+}
+
+/// 先頭の `limit` 行と、残りの行数の知らせ
+///
+/// 例: 5行を2行に切り詰める → "1\n2\n... (3 more lines, use | cat to see all)\n"
+fn truncate(text: &str, limit: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let rest = lines.len().saturating_sub(limit);
+    if rest == 0 {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    for line in &lines[..limit] {
+        truncated.push_str(line);
+        truncated.push('\n');
+    }
+    let noun = if rest == 1 { "line" } else { "lines" };
+    truncated.push_str(&format!("... ({rest} more {noun}, use | cat to see all)\n"));
+    truncated
+}
+
+/// 内蔵のページャで表示する
+///
+/// 一画面ずつ表示し、キーを読んで次に進む（スペース: 次の画面、Enter: 次の行、q: 終了）
+fn page(text: &str, limit: usize) {
+    let lines: Vec<&str> = text.lines().collect();
+    let Ok(_keys) = KeyMode::enable() else {
+        return output::write_stdout(truncate(text, limit));
+    };
+
+    let mut shown = 0;
+    let mut step = limit;
+    while shown < lines.len() {
+        let end = (shown + step).min(lines.len());
+        for line in &lines[shown..end] {
+            output::print_line(line);
+        }
+        shown = end;
+        if shown == lines.len() {
+            break;
+        }
+
+        output::write_stderr(&format!(
+            "-- more ({} lines left; space: next page, enter: next line, q: quit) --",
+            lines.len() - shown
+        ));
+        let key = read_key();
+        output::write_stderr("\r\x1b[K");
+        step = match key {
+            Some(b' ') => limit,
+            Some(b'\r' | b'\n') => 1,
+            _ => break,
+        };
+    }
+}
+
+/// 一キー読む（入力の終端や読み取りのエラーならNone）
+fn read_key() -> Option<u8> {
+    let mut byte = [0u8; 1];
+    match io::stdin().read(&mut byte) {
+        Ok(1) => Some(byte[0]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::set_var;
+    use crate::state::{ShellState, enter};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_truncate_keeps_the_first_lines() {
+        assert_eq!(
+            truncate("1\n2\n3\n4\n5\n", 2),
+            "1\n2\n... (3 more lines, use | cat to see all)\n"
+        );
+        assert_eq!(
+            truncate("1\n2\n3", 2),
+            "1\n2\n... (1 more line, use | cat to see all)\n"
+        );
+        assert_eq!(truncate("1\n2\n", 2), "1\n2\n");
+    }
+
+    #[test]
+    fn test_settings_from_variables() {
+        let _state = enter(Arc::new(ShellState::new()));
+        assert_eq!(page_lines(), None);
+        assert_eq!(pager_mode(), PagerMode::Internal);
+
+        set_var("RUCLI_PAGE_LINES", "0");
+        assert_eq!(page_lines(), None);
+        set_var("RUCLI_PAGE_LINES", " 20 ");
+        assert_eq!(page_lines(), Some(20));
+        set_var("RUCLI_PAGER", "truncate");
+        assert_eq!(pager_mode(), PagerMode::Truncate);
+
+        // 端末に直接表示しない出力はそのまま
+        let (_, captured) = output::capture(|| write_paged("x\n".repeat(50).as_bytes()));
+        assert_eq!(
+            String::from_utf8_lossy(&captured.stdout).lines().count(),
+            50
+        );
+    }
+}
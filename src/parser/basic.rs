@@ -1,27 +1,78 @@
 //! 基本コマンドのパース関数
 
-use crate::commands::{Command, EnvironmentAction, HistoryAction};
+use crate::commands::{
+    Command, DirStackAction, EnvironmentAction, HistoryAction, LsSort, OutputFormat, TrapAction,
+};
+use crate::environment::{expand_command_substitution, is_valid_var_name, substitution_deferred};
 use crate::error::{Result, RucliError};
-use crate::parser::utils::DEFAULT_HOME_INDICATOR;
+use crate::job::JobSpec;
+use crate::options::is_option_name;
+use crate::parser::parse_command;
+use crate::parser::tokenizer::split_quoted_words;
+use crate::parser::utils::{
+    DEFAULT_HOME_INDICATOR, parse_octal_mode, split_command_flags, take_assignment_word,
+    take_output_format,
+};
+use crate::translate::parse_set;
+use crate::trap::TrapSignal;
+use std::time::Duration;
 
+/// echoコマンドのパース関数（先頭の `-n` で末尾の改行を付けない）
 pub(super) fn parse_echo(args: &[&str]) -> Result<Command> {
+    let (newline, args) = match args {
+        ["-n", rest @ ..] => (false, rest),
+        _ => (true, args),
+    };
     Ok(Command::Echo {
         message: args.join(" "),
+        newline,
     })
 }
 
+/// catコマンドのパース関数（オプションはファイル名の前後どちらに置いてもよい）
+///
+/// 例: `cat -nA notes.txt` → 行番号を付け、タブ・行末・制御文字を見えるように表示
 pub(super) fn parse_cat(args: &[&str]) -> Result<Command> {
-    if args.is_empty() {
-        // 引数なしの場合は、標準入力から読むことを想定
-        // ダミーのファイル名を使う（実際には使われない）
-        Ok(Command::Cat {
-            filename: String::new(),
-        })
-    } else {
-        Ok(Command::Cat {
-            filename: args[0].to_string(),
-        })
+    const USAGE: &str = "Usage: cat [-n] [-A | -v -E -T] [filename]";
+
+    let args = split_command_flags("cat", args)?;
+    // ファイル名がなければ、パイプラインの入力か標準入力から読む（空のファイル名で表す）
+    // 単独の `-` は標準入力を表すファイル名
+    let filename = match args.positionals[..] {
+        [] => "",
+        [filename] => filename,
+        [_, extra, ..] => {
+            return Err(RucliError::InvalidArgument(format!(
+                "cat: unexpected argument '{extra}'\n{USAGE}"
+            )));
+        }
+    };
+
+    let mut number = false;
+    let mut show_nonprinting = false;
+    let mut show_ends = false;
+    let mut show_tabs = false;
+    for &(flag, _) in &args.flags {
+        match flag {
+            "-n" => number = true,
+            "-v" => show_nonprinting = true,
+            "-E" => show_ends = true,
+            "-T" => show_tabs = true,
+            _ => {
+                show_nonprinting = true;
+                show_ends = true;
+                show_tabs = true;
+            }
+        }
     }
+
+    Ok(Command::Cat {
+        filename: filename.to_string(),
+        number,
+        show_nonprinting,
+        show_ends,
+        show_tabs,
+    })
 }
 
 pub(super) fn parse_write(args: &[&str]) -> Result<Command> {
@@ -31,20 +82,55 @@ pub(super) fn parse_write(args: &[&str]) -> Result<Command> {
     })
 }
 
+/// repeatコマンドのパース関数
+///
+/// 例: `repeat -d 0.5 3 tick` → count=3, message="tick", delay=Some(0.5秒)
 pub(super) fn parse_repeat(args: &[&str]) -> Result<Command> {
-    match args[0].parse::<i32>() {
-        Ok(count) if count > 0 => Ok(Command::Repeat {
+    let (delay, args) = match args {
+        ["-d", seconds, rest @ ..] => (Some(parse_duration("repeat", seconds)?), rest),
+        ["-d"] => {
+            return Err(RucliError::ParseError(
+                "repeat: -d requires a number of seconds".to_string(),
+            ));
+        }
+        _ => (None, args),
+    };
+
+    let Some((count, message)) = args.split_first() else {
+        return Err(RucliError::ParseError(
+            "Usage: repeat [-d seconds] <count> <message...>".to_string(),
+        ));
+    };
+
+    match count.parse::<i32>() {
+        Ok(count) if count >= 0 => Ok(Command::Repeat {
             count,
-            message: args[1..].join(" "),
+            message: message.join(" "),
+            delay,
         }),
-        Ok(_) => Err(RucliError::ParseError("count must be positive".to_string())),
+        Ok(_) => Err(RucliError::ParseError(
+            "count must be 0 or more".to_string(),
+        )),
         Err(_) => Err(RucliError::ParseError(format!(
-            "{} isn't a valid number",
-            args[0]
+            "{count} isn't a valid number"
         ))),
     }
 }
 
+pub(super) fn parse_exit(args: &[&str]) -> Result<Command> {
+    match args.first() {
+        None => Ok(Command::Exit { status: None }),
+        Some(status) => match status.parse::<i32>() {
+            Ok(status) => Ok(Command::Exit {
+                status: Some(status),
+            }),
+            Err(_) => Err(RucliError::ParseError(format!(
+                "exit: {status}: numeric argument required"
+            ))),
+        },
+    }
+}
+
 pub(super) fn parse_cd(args: &[&str]) -> Result<Command> {
     Ok(Command::Cd {
         path: args
@@ -54,18 +140,72 @@ pub(super) fn parse_cd(args: &[&str]) -> Result<Command> {
     })
 }
 
+/// pushd・popd・dirsコマンドのパース関数
+///
+/// 例: `pushd src` → Push(Some("src"))、`dirs -v` → Show { verbose: true }
+pub(super) fn parse_dir_stack(cmd_name: &str, args: &[&str]) -> Result<Command> {
+    let action = match (cmd_name, args) {
+        ("pushd", [dir]) => DirStackAction::Push(Some(dir.to_string())),
+        ("pushd", []) => DirStackAction::Push(None),
+        ("popd", []) => DirStackAction::Pop,
+        ("dirs", []) => DirStackAction::Show { verbose: false },
+        ("dirs", ["-v"]) => DirStackAction::Show { verbose: true },
+        _ => {
+            return Err(RucliError::InvalidArgument(format!(
+                "{cmd_name}: invalid argument '{}'",
+                args.join(" ")
+            )));
+        }
+    };
+
+    Ok(Command::DirStack { action })
+}
+
+/// sleepコマンドのパース関数
+///
+/// 秒数は小数でもよい
+/// 例: `sleep 1.5` → duration=1.5秒
 pub(super) fn parse_sleep(args: &[&str]) -> Result<Command> {
-    match args[0].parse::<u64>() {
-        Ok(seconds) => Ok(Command::Sleep { seconds }),
-        Err(_) => Err(RucliError::ParseError(format!(
-            "'{}' is not a valid number",
-            args[0]
-        ))),
+    Ok(Command::Sleep {
+        duration: parse_duration("sleep", args[0])?,
+    })
+}
+
+/// 秒数（小数可）を時間に変換する
+///
+/// # Arguments
+/// * `cmd_name` - エラーメッセージに表示するコマンド名
+/// * `seconds` - 秒数の文字列
+fn parse_duration(cmd_name: &str, seconds: &str) -> Result<Duration> {
+    if seconds.starts_with('-') {
+        return Err(RucliError::InvalidArgument(format!(
+            "{cmd_name}: '{seconds}' is negative; the duration must be 0 or more seconds"
+        )));
     }
+
+    // 数字と小数点一つだけを受け付ける（"inf" や "1e3" は不可）
+    let is_decimal = seconds.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && seconds.matches('.').count() <= 1
+        && seconds.chars().any(|c| c.is_ascii_digit());
+
+    let duration = is_decimal
+        .then(|| seconds.parse::<f64>().ok())
+        .flatten()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+
+    duration.ok_or_else(|| {
+        RucliError::ParseError(format!(
+            "{cmd_name}: '{seconds}' is not a valid number of seconds"
+        ))
+    })
 }
 
+/// aliasコマンドのパース関数
+///
+/// 値はクォートで囲めば空白を含められる（クォートは取り除く）
+/// 例: `alias ll="ls -l"` → name="ll", command="ls -l"
 pub(super) fn parse_alias(args: &[&str]) -> Result<Command> {
-    match args {
+    match split_quoted_words(&args.join(" ")).as_slice() {
         [] => Ok(Command::Alias {
             name: None,
             command: None,
@@ -77,107 +217,682 @@ pub(super) fn parse_alias(args: &[&str]) -> Result<Command> {
             }),
             None => Err(RucliError::ParseError("alias needs =".to_string())),
         },
-        _ => unreachable!(),
+        _ => Err(RucliError::InvalidArgument(
+            "alias accepts at most 1 argument(s)\nUsage: alias [name=command]".to_string(),
+        )),
     }
 }
 
+/// fgコマンドのパース関数（ジョブは番号か `%` の指定）
 pub(super) fn parse_fg(args: &[&str]) -> Result<Command> {
-    match args {
-        [] => Ok(Command::Fg { job_id: None }),
-        [job_id] => match job_id.parse::<u32>() {
-            Ok(job_id) => Ok(Command::Fg {
-                job_id: Some(job_id),
-            }),
-            Err(_) => Err(RucliError::ParseError(format!(
-                "'{}' is not a valid number",
-                args[0]
-            ))),
-        },
-        _ => unreachable!(),
-    }
+    let job = args.first().map(|spec| JobSpec::parse(spec)).transpose()?;
+    Ok(Command::Fg { job })
+}
+
+/// bgコマンドのパース関数（ジョブは番号か `%` の指定）
+pub(super) fn parse_bg(args: &[&str]) -> Result<Command> {
+    let job = args.first().map(|spec| JobSpec::parse(spec)).transpose()?;
+    Ok(Command::Bg { job })
 }
 
 /// envコマンドのパース関数
 pub(super) fn parse_environment(args: &[&str]) -> Result<Command> {
     // 処理パターン:
     // [] => List (引数なし)
+    // ["PATTERN*"] => List (ワイルドカードに一致する名前だけ)
     // ["VAR"] => Show(VAR)
     // ["VAR=value"] => Set(VAR, value)
+    // ["-i", "VAR=value", ..., "command", ...] / ["VAR=value", ..., "command", ...] => Run
+    // いずれも --json / -o json を、一覧には --session（セッション変数のみ）を付けられる
+    const USAGE: &str = "Usage: env [--json | -o json] [--session] [PATTERN | VAR[=value]]\n       env [-i] [VAR=value...] [command [args...]]";
 
-    match args {
-        [] => Ok(Command::Environment {
-            action: EnvironmentAction::List,
-        }),
+    // コマンドの引数の --json などはコマンドに渡す
+    if let Some(action) = parse_env_run(args)? {
+        return Ok(Command::Environment {
+            action,
+            format: OutputFormat::Text,
+        });
+    }
+
+    let (format, args) = take_output_format("env", args)?;
+    let session_only = args.contains(&"--session");
+    let args: Vec<&str> = args.into_iter().filter(|arg| *arg != "--session").collect();
+
+    let action = match args.as_slice() {
+        [] => EnvironmentAction::List {
+            pattern: None,
+            session_only,
+        },
+        [pattern] if pattern.contains(['*', '?']) && !pattern.contains('=') => {
+            EnvironmentAction::List {
+                pattern: Some(pattern.to_string()),
+                session_only,
+            }
+        }
+        [_] if session_only => {
+            return Err(RucliError::InvalidArgument(format!(
+                "env: --session can only be used when listing variables\n{USAGE}"
+            )));
+        }
         [var] => {
             if let Some((name, value)) = var.split_once("=") {
-                Ok(Command::Environment {
-                    action: EnvironmentAction::Set(name.to_string(), value.to_string()),
-                })
+                EnvironmentAction::Set(name.to_string(), value.to_string())
             } else {
-                Ok(Command::Environment {
-                    action: EnvironmentAction::Show(var.to_string()),
-                })
+                EnvironmentAction::Show(var.to_string())
+            }
+        }
+        _ => {
+            return Err(RucliError::InvalidArgument(USAGE.to_string()));
+        }
+    };
+
+    Ok(Command::Environment { action, format })
+}
+
+/// 変数を上書きしてコマンドを実行するenvのパース関数
+///
+/// `-i` があるか、代入の後ろにコマンドがある場合だけRunにする（`env VAR` は変数の表示）。
+/// `-i` でコマンドがなければ、上書きした変数だけを一覧表示する
+///
+/// 例: `env -i LANG=C sort` → isolated, [(LANG, C)], sort
+fn parse_env_run(args: &[&str]) -> Result<Option<EnvironmentAction>> {
+    let (isolated, rest) = match args.split_first() {
+        Some((&("-i" | "--ignore-environment"), rest)) => (true, rest),
+        _ => (false, args),
+    };
+    let count = rest
+        .iter()
+        .take_while(|arg| {
+            arg.split_once('=')
+                .is_some_and(|(name, _)| is_valid_var_name(name))
+        })
+        .count();
+    let (assignments, words) = rest.split_at(count);
+    if !isolated && (assignments.is_empty() || words.is_empty()) {
+        return Ok(None);
+    }
+
+    let overrides = assignments
+        .iter()
+        .filter_map(|arg| arg.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    let command = if words.is_empty() {
+        Command::Environment {
+            action: EnvironmentAction::List {
+                pattern: None,
+                session_only: false,
+            },
+            format: OutputFormat::Text,
+        }
+    } else {
+        parse_command(&words.join(" "))?
+    };
+
+    Ok(Some(EnvironmentAction::Run {
+        isolated,
+        overrides,
+        command: Box::new(command),
+    }))
+}
+
+/// exportコマンドのパース関数
+///
+/// 例: `export PATH_EXTRA=/opt/bin NAME` → (PATH_EXTRA, Some("/opt/bin")), (NAME, None)
+pub(super) fn parse_export(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: export NAME[=value]...";
+
+    if args.is_empty() {
+        return Err(RucliError::InvalidArgument(USAGE.to_string()));
+    }
+
+    let variables = args
+        .iter()
+        .map(|arg| {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (*arg, None),
+            };
+            if !is_valid_var_name(name) {
+                return Err(RucliError::InvalidArgument(format!(
+                    "export: '{name}' is not a valid variable name\n{USAGE}"
+                )));
+            }
+            Ok((name.to_string(), value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Command::Export { variables })
+}
+
+/// lsコマンドのパース関数
+///
+/// `-R` `-t` `-S` `-r` は `-Rtr` のようにまとめて指定できる（`-t` と `-S` は後のものを使う）
+pub(super) fn parse_ls(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: ls [-R] [-t | -S | --unsorted] [-r] [--json | -o json]";
+
+    let (format, rest) = take_output_format("ls", args)?;
+    let args = split_command_flags("ls", &rest)?;
+    if let Some(arg) = args.positionals.first() {
+        return Err(RucliError::InvalidArgument(format!(
+            "ls: unexpected argument '{arg}'\n{USAGE}"
+        )));
+    }
+
+    let mut recursive = false;
+    let mut sort = LsSort::Name;
+    let mut reverse = false;
+    for &(flag, _) in &args.flags {
+        match flag {
+            "-R" => recursive = true,
+            "-t" => sort = LsSort::Time,
+            "-S" => sort = LsSort::Size,
+            "--unsorted" => sort = LsSort::Unsorted,
+            "-r" => reverse = true,
+            _ => {}
+        }
+    }
+
+    Ok(Command::Ls {
+        format,
+        recursive,
+        sort,
+        reverse,
+    })
+}
+
+/// jobsコマンドのパース関数
+pub(super) fn parse_jobs(args: &[&str]) -> Result<Command> {
+    let (format, rest) = take_output_format("jobs", args)?;
+
+    if let Some(arg) = rest.first() {
+        return Err(RucliError::InvalidArgument(format!(
+            "jobs: unexpected argument '{arg}'\nUsage: jobs [--json | -o json]"
+        )));
+    }
+
+    Ok(Command::Jobs { format })
+}
+
+/// letコマンドのパース関数
+///
+/// クォートで囲まれた式は一つの式として扱う
+/// 例: `let I=I+1 "X = 2 * Y"` → ["I=I+1", "X = 2 * Y"]
+pub(super) fn parse_let(args: &[&str]) -> Result<Command> {
+    let expressions = split_quoted_words(&args.join(" "));
+
+    if expressions.is_empty() {
+        return Err(RucliError::ParseError(
+            "let: expression expected".to_string(),
+        ));
+    }
+
+    Ok(Command::Let { expressions })
+}
+
+/// readコマンドのパース関数
+///
+/// 例: `read -p "Name: " FIRST LAST` → prompt="Name: ", variables=["FIRST", "LAST"]
+pub(super) fn parse_read(args: &[&str]) -> Result<Command> {
+    let mut words = split_quoted_words(&args.join(" ")).into_iter();
+    let mut prompt = None;
+    let mut variables = Vec::new();
+
+    while let Some(word) = words.next() {
+        if word == "-p" {
+            prompt = Some(words.next().ok_or(RucliError::InvalidArgument(
+                "read: -p requires a prompt".to_string(),
+            ))?);
+        } else if is_valid_var_name(&word) {
+            variables.push(word);
+        } else {
+            return Err(RucliError::InvalidArgument(format!(
+                "read: '{word}': not a valid identifier"
+            )));
+        }
+    }
+
+    Ok(Command::Read { prompt, variables })
+}
+
+/// seqコマンドのパース関数
+///
+/// 数値の検証は変数展開後の実行時に行う
+/// 例: `seq -w 1 2 10` → args=["1", "2", "10"], equal_width=true
+pub(super) fn parse_seq(args: &[&str]) -> Result<Command> {
+    let equal_width = args.first() == Some(&"-w");
+    let args: Vec<String> = args
+        .iter()
+        .skip(usize::from(equal_width))
+        .map(|arg| arg.to_string())
+        .collect();
+
+    if args.is_empty() || args.len() > 3 {
+        return Err(RucliError::ParseError(
+            "seq: expected 1 to 3 numbers\nUsage: seq [-w] [first [step]] last".to_string(),
+        ));
+    }
+
+    Ok(Command::Seq { args, equal_width })
+}
+
+/// xargsコマンドのパース関数
+///
+/// オプションは最初のコマンド名より前にのみ書ける
+/// 例: `xargs -n 1 cat` → command=["cat"], max_args=Some(1)
+pub(super) fn parse_xargs(args: &[&str]) -> Result<Command> {
+    let mut max_args = None;
+    let mut replace = None;
+    let mut exit_on_error = false;
+    let mut rest = args;
+
+    while let Some((&option, remaining)) = rest.split_first() {
+        match option {
+            "-n" => {
+                let count = remaining.first().and_then(|n| n.parse::<usize>().ok());
+                match count {
+                    Some(count) if count > 0 => max_args = Some(count),
+                    _ => {
+                        return Err(RucliError::InvalidArgument(
+                            "xargs: -n requires a positive number".to_string(),
+                        ));
+                    }
+                }
+                rest = &remaining[1..];
+            }
+            "-I" => {
+                let Some(replacement) = remaining.first() else {
+                    return Err(RucliError::InvalidArgument(
+                        "xargs: -I requires a replacement string".to_string(),
+                    ));
+                };
+                replace = Some(replacement.to_string());
+                rest = &remaining[1..];
             }
+            "-e" => {
+                exit_on_error = true;
+                rest = remaining;
+            }
+            _ => break,
         }
-        _ => unreachable!(),
     }
+
+    Ok(Command::Xargs {
+        command: rest.iter().map(|s| s.to_string()).collect(),
+        max_args,
+        replace,
+        exit_on_error,
+    })
+}
+
+/// 変数代入のパース関数
+///
+/// - `A=1 B="x y"` → 代入のみ（セッション変数に保存）
+/// - `FOO=bar command` → commandの実行中だけFOOを設定
+pub(super) fn parse_assignment(input: &str) -> Result<Command> {
+    let mut assignments = Vec::new();
+    let mut rest = input.trim_start();
+
+    // 先頭から代入語を取り出す（値のコマンド置換は語に分けない）
+    while let Some((name, value, remaining)) = take_assignment_word(rest) {
+        let value = if substitution_deferred() {
+            value
+        } else {
+            expand_command_substitution(&value)?
+        };
+        assignments.push((name, value));
+        rest = remaining.trim_start();
+    }
+
+    let command = if rest.is_empty() {
+        None
+    } else {
+        Some(Box::new(parse_command(rest)?))
+    };
+
+    Ok(Command::Assignment {
+        assignments,
+        command,
+    })
 }
 
+/// historyコマンドの使い方
+const HISTORY_USAGE: &str = "Usage: history [count | -n count | exec [-y] <number> | clear | delete <number> | search [-r] [--all] <query>]";
+
 // historyコマンドの処理
 pub(super) fn parse_history(args: &[&str]) -> Result<Command> {
-    match args {
-        [] => Ok(Command::History {
-            action: HistoryAction::List,
-        }),
-        ["search", query @ ..] => Ok(Command::History {
-            action: HistoryAction::Search(query.join(" ")),
-        }),
-        [index] => {
-            match index.parse::<usize>() {
-                Ok(num) => Ok(Command::History {
-                    action: HistoryAction::Execute(num),
-                }),
-                Err(_) => {
-                    // 数値でない場合
-                    Err(RucliError::InvalidArgument(
-                        "Usage: history [number | search <query>]".to_string(),
+    // 数値引数の解釈（不正なら使い方を返す）
+    let parse_number = |value: &str| {
+        value.parse::<usize>().map_err(|_| {
+            RucliError::InvalidArgument(format!(
+                "history: {value}: numeric argument required\n{HISTORY_USAGE}"
+            ))
+        })
+    };
+
+    let (format, args) = take_output_format("history", args)?;
+
+    let action = match args.as_slice() {
+        [] => HistoryAction::List,
+        ["search", rest @ ..] => {
+            // オプションは検索文字列より前に置く
+            let mut regex = false;
+            let mut all = false;
+            let mut query = rest;
+            while let Some((&option, remaining)) = query.split_first() {
+                match option {
+                    "-r" => regex = true,
+                    "--all" => all = true,
+                    _ => break,
+                }
+                query = remaining;
+            }
+            HistoryAction::Search {
+                query: query.join(" "),
+                regex,
+                all,
+            }
+        }
+        ["clear"] => HistoryAction::Clear,
+        // 数値だけなら最近の件数（再実行は `history exec N` か `!N`）
+        [count] | ["-n", count] => HistoryAction::Recent(parse_number(count)?),
+        ["delete" | "-d", index] => HistoryAction::Delete(parse_number(index)?),
+        ["exec", index] => HistoryAction::Execute {
+            index: parse_number(index)?,
+            assume_yes: false,
+        },
+        ["exec", "-y", index] => HistoryAction::Execute {
+            index: parse_number(index)?,
+            assume_yes: true,
+        },
+        _ => return Err(RucliError::InvalidArgument(HISTORY_USAGE.to_string())),
+    };
+
+    Ok(Command::History { action, format })
+}
+
+/// umaskコマンドのパース関数
+///
+/// 例: `umask 077` → mask=Some(0o077)、`umask` → mask=None（表示）
+pub(super) fn parse_umask(args: &[&str]) -> Result<Command> {
+    Ok(Command::Umask {
+        mask: args
+            .first()
+            .map(|value| parse_octal_mode("umask", value))
+            .transpose()?,
+    })
+}
+
+/// trapコマンドのパース関数
+///
+/// コマンドはクォートで囲んで一つの引数にする。`-` またはシグナルだけを指定すると削除する
+/// 例: `trap "rm -f lock" EXIT INT` → Set、`trap - INT` → Remove、`trap` → List
+pub(super) fn parse_trap(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: trap [command signal... | - signal...]";
+
+    let words = split_quoted_words(&args.join(" "));
+    let parse_signals = |names: &[String]| -> Result<Vec<TrapSignal>> {
+        if names.is_empty() {
+            return Err(RucliError::InvalidArgument(format!(
+                "trap: signal required\n{USAGE}"
+            )));
+        }
+        names
+            .iter()
+            .map(|name| {
+                TrapSignal::parse(name).ok_or_else(|| {
+                    RucliError::InvalidArgument(format!(
+                        "trap: {name}: invalid signal specification"
                     ))
+                })
+            })
+            .collect()
+    };
+
+    let action = match words.as_slice() {
+        [] => TrapAction::List,
+        [dash, signals @ ..] if dash == "-" => TrapAction::Remove(parse_signals(signals)?),
+        // シグナルだけなら削除（bashと同じ）
+        [signal] if TrapSignal::parse(signal).is_some() => {
+            TrapAction::Remove(parse_signals(&words)?)
+        }
+        [command, signals @ ..] => TrapAction::Set {
+            command: command.clone(),
+            signals: parse_signals(signals)?,
+        },
+    };
+
+    Ok(Command::Trap { action })
+}
+
+/// evalコマンドのパース関数
+///
+/// 引数のクォートを外しておき、実行時に変数を展開してからつなげてパースし直す
+/// 例: `eval "$CMD" arg` → ["$CMD", "arg"]
+pub(super) fn parse_eval(args: &[&str]) -> Command {
+    Command::Eval {
+        args: split_quoted_words(&args.join(" ")),
+    }
+}
+
+/// execコマンドのパース関数
+///
+/// 引数はクォートを外して外部コマンドに渡す（リダイレクトはparse_commandで付け加える）
+/// 例: `exec sh -c "echo hi"` → ["sh", "-c", "echo hi"]
+pub(super) fn parse_exec(args: &[&str]) -> Command {
+    Command::Exec {
+        args: split_quoted_words(&args.join(" ")),
+        redirects: Vec::new(),
+    }
+}
+
+/// fcコマンドの使い方
+const FC_USAGE: &str = "Usage: fc -l | fc -s [old=new] [command] | fc [command]";
+
+/// fcコマンドのパース関数
+///
+/// `-l` は最近の履歴の一覧、`-s` は置換して再実行、引数が対象のコマンドだけならエディタで編集する。
+/// 対象のコマンドは履歴番号、`-n`、またはコマンドの先頭の文字列で指定する
+/// 例: `fc -s old=new echo` → echoで始まる最新のコマンドのoldをnewにして再実行
+pub(super) fn parse_fc(args: &[&str]) -> Result<Command> {
+    // 最近の履歴として一覧表示する件数
+    const LIST_COUNT: usize = 16;
+
+    let action = match args {
+        ["-l"] => HistoryAction::Recent(LIST_COUNT),
+        ["-s", rest @ ..] => {
+            let (replacement, rest) = match rest.split_first() {
+                Some((first, remaining)) if first.contains('=') => {
+                    let (old, new) = first.split_once('=').unwrap_or_default();
+                    if old.is_empty() {
+                        return Err(RucliError::InvalidArgument(format!(
+                            "fc: {first}: empty pattern\n{FC_USAGE}"
+                        )));
+                    }
+                    (Some((old.to_string(), new.to_string())), remaining)
                 }
+                _ => (None, rest),
+            };
+            match rest {
+                [] | [_] => HistoryAction::Substitute {
+                    replacement,
+                    target: rest.first().map(|value| value.to_string()),
+                },
+                _ => return Err(RucliError::InvalidArgument(FC_USAGE.to_string())),
             }
         }
-        _ => Err(RucliError::InvalidArgument(
-            "Usage: history [search <query>]".to_string(),
-        )),
+        [] => HistoryAction::Edit(None),
+        [command] if !command.starts_with('-') || command[1..].parse::<usize>().is_ok() => {
+            HistoryAction::Edit(Some(command.to_string()))
+        }
+        _ => return Err(RucliError::InvalidArgument(FC_USAGE.to_string())),
+    };
+
+    Ok(Command::History {
+        action,
+        format: OutputFormat::Text,
+    })
+}
+
+/// setコマンドのパース関数
+///
+/// `-o name` で有効、`+o name` で無効にする。`-e` / `+e` は errexit、`-C` / `+C` は noclobber の短縮形。
+/// 設定がなければ（`set` / `set -o`）オプションの一覧を表示する
+/// 例: `set -o noclobber +o noclobber` → [("noclobber", true), ("noclobber", false)]
+pub(super) fn parse_set_command(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: set [-o | +o option] [-e | +e] [-C | +C] [-n | +n] [-v | +v]";
+
+    let mut options = Vec::new();
+    let mut rest = args;
+
+    while let Some((&flag, remaining)) = rest.split_first() {
+        rest = remaining;
+
+        let (name, enabled) = match flag {
+            "-e" | "+e" => ("errexit", flag == "-e"),
+            "-C" | "+C" => ("noclobber", flag == "-C"),
+            "-n" | "+n" => ("dryrun", flag == "-n"),
+            "-v" | "+v" => ("verbose", flag == "-v"),
+            "-o" | "+o" => match rest.split_first() {
+                Some((&name, remaining)) => {
+                    rest = remaining;
+                    (name, flag == "-o")
+                }
+                // 名前のない -o は一覧の表示
+                None if flag == "-o" => break,
+                None => {
+                    return Err(RucliError::InvalidArgument(format!(
+                        "set: +o: option name required\n{USAGE}"
+                    )));
+                }
+            },
+            _ => {
+                return Err(RucliError::InvalidArgument(format!(
+                    "set: invalid argument '{flag}'\n{USAGE}"
+                )));
+            }
+        };
+
+        if !is_option_name(name) {
+            return Err(RucliError::InvalidArgument(format!(
+                "set: {name}: invalid option name"
+            )));
+        }
+        options.push((name.to_string(), enabled));
     }
+
+    Ok(Command::Set { options })
+}
+
+/// trコマンドのパース関数
+///
+/// オプションは文字集合より前に書く（`-ds` のようにまとめてもよい）
+/// 例: `tr -s a-z A-Z` → set1="a-z", set2=Some("A-Z"), squeeze=true
+pub(super) fn parse_tr(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: tr [-d] [-s] <set1> [set2]";
+
+    let mut delete = false;
+    let mut squeeze = false;
+    let mut rest = args;
+
+    while let Some((&option, remaining)) = rest.split_first() {
+        let Some(flags) = option.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            break;
+        };
+        for flag in flags.chars() {
+            match flag {
+                'd' => delete = true,
+                's' => squeeze = true,
+                _ => {
+                    return Err(RucliError::InvalidArgument(format!(
+                        "tr: invalid option -- '{flag}'\n{USAGE}"
+                    )));
+                }
+            }
+        }
+        rest = remaining;
+    }
+
+    // -dだけならset1のみ、-d -sなら両方、置換なら両方、-sだけならどちらでもよい
+    let (set1, set2) = match (rest, delete, squeeze) {
+        ([set1], true, false) | ([set1], false, true) => (set1, None),
+        ([set1, set2], true, true) | ([set1, set2], false, _) => (set1, Some(set2)),
+        _ => {
+            return Err(RucliError::InvalidArgument(format!(
+                "tr: wrong number of sets\n{USAGE}"
+            )));
+        }
+    };
+
+    // 表記の誤りはパース時に知らせる
+    parse_set(set1)?;
+    if let Some(set2) = set2
+        && parse_set(set2)?.is_empty()
+    {
+        return Err(RucliError::InvalidArgument(
+            "tr: set2 must not be empty".to_string(),
+        ));
+    }
+
+    Ok(Command::Tr {
+        set1: set1.to_string(),
+        set2: set2.map(|set| set.to_string()),
+        delete,
+        squeeze,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::commands::OutputFormat;
 
     #[test]
     fn test_parse_command_echo() {
         // echoコマンドのパース成功
         let result = parse_echo(&["input"]);
-        assert!(matches!(result,Ok(Command::Echo{message}) if message == "input"))
+        assert!(matches!(result,Ok(Command::Echo{message, ..}) if message == "input"))
     }
 
     #[test]
     fn test_parse_echo_multiple_words() {
         let result = parse_echo(&["hello", "world"]);
-        assert!(matches!(result, Ok(Command::Echo { message }) if message == "hello world"));
+        assert!(matches!(result, Ok(Command::Echo { message, .. }) if message == "hello world"));
     }
 
     #[test]
     fn test_parse_cat_with_file() {
         let result = parse_cat(&["test.txt"]);
-        assert!(matches!(result, Ok(Command::Cat { filename }) if filename == "test.txt"));
+        assert!(matches!(result, Ok(Command::Cat { filename, .. }) if filename == "test.txt"));
+    }
+
+    #[test]
+    fn test_parse_cat_display_flags() {
+        let result = parse_cat(&["-nA", "notes.txt"]);
+        assert!(matches!(result, Ok(Command::Cat {
+            filename, number: true, show_nonprinting: true, show_ends: true, show_tabs: true
+        }) if filename == "notes.txt"));
+
+        let result = parse_cat(&["-T", "-E"]);
+        assert!(matches!(result, Ok(Command::Cat {
+            filename, number: false, show_nonprinting: false, show_ends: true, show_tabs: true
+        }) if filename.is_empty()));
+
+        // 単独の - は標準入力、ファイル名より後ろのオプションも受け付ける
+        let result = parse_cat(&["-E", "-"]);
+        assert!(matches!(result, Ok(Command::Cat { filename, show_ends: true, .. }) if filename == "-"));
+        let result = parse_cat(&["-x"]);
+        assert!(result.unwrap_err().to_string().contains("cat: unknown option '-x'"));
+        let result = parse_cat(&["a.txt", "-n"]);
+        assert!(matches!(result, Ok(Command::Cat { filename, number: true, .. }) if filename == "a.txt"));
+        let result = parse_cat(&["a.txt", "b.txt"]);
+        assert!(result.unwrap_err().to_string().contains("cat: unexpected argument 'b.txt'"));
     }
 
     #[test]
     fn test_parse_cat_no_args() {
         let result = parse_cat(&[]);
-        assert!(matches!(result, Ok(Command::Cat { filename }) if filename.is_empty()));
+        assert!(matches!(result, Ok(Command::Cat { filename, .. }) if filename.is_empty()));
     }
 
     #[test]
@@ -195,14 +910,25 @@ mod tests {
     #[test]
     fn test_parse_repeat_valid() {
         let result = parse_repeat(&["3", "hello"]);
-        assert!(matches!(result, Ok(Command::Repeat { count: 3, message }) if message == "hello"));
+        assert!(matches!(result, Ok(Command::Repeat { count: 3, message, delay: None }) if message == "hello"));
+
+        let result = parse_repeat(&["0", "hello"]);
+        assert!(matches!(result, Ok(Command::Repeat { count: 0, .. })));
+
+        let result = parse_repeat(&["-d", "0.5", "2", "tick", "tock"]);
+        assert!(matches!(result, Ok(Command::Repeat { count: 2, message, delay: Some(delay) })
+            if message == "tick tock" && delay == Duration::from_millis(500)));
     }
 
     #[test]
     fn test_parse_repeat_invalid_count() {
         let result = parse_repeat(&["-1", "test"]);
         assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("must be positive"));
+        assert!(result.unwrap_err().to_string().contains("must be 0 or more"));
+
+        assert!(parse_repeat(&["-d", "-1", "2", "x"]).is_err());
+        assert!(parse_repeat(&["-d", "abc", "2", "x"]).is_err());
+        assert!(parse_repeat(&["-d", "1"]).is_err());
 
         let result2 = parse_repeat(&["abc", "test"]);
         assert!(result2.is_err());
@@ -229,13 +955,24 @@ mod tests {
     #[test]
     fn test_parse_sleep_valid() {
         let result = parse_sleep(&["5"]);
-        assert!(matches!(result, Ok(Command::Sleep { seconds: 5 })));
+        assert!(matches!(result, Ok(Command::Sleep { duration }) if duration == Duration::from_secs(5)));
+
+        let result = parse_sleep(&["1.5"]);
+        assert!(matches!(result, Ok(Command::Sleep { duration }) if duration == Duration::from_millis(1500)));
+
+        let result = parse_sleep(&[".25"]);
+        assert!(matches!(result, Ok(Command::Sleep { duration }) if duration == Duration::from_millis(250)));
     }
 
     #[test]
     fn test_parse_sleep_invalid_arg() {
         let result = parse_sleep(&["abc"]);
         assert!(result.is_err());
+
+        for invalid in ["-1", "-0.5", "1.2.3", ".", "inf", "1e3", "nan"] {
+            assert!(parse_sleep(&[invalid]).is_err(), "{invalid} should be rejected");
+        }
+        assert!(parse_sleep(&["-1"]).unwrap_err().to_string().contains("negative"));
     }
 
     #[test]
@@ -262,6 +999,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_alias_quoted_value() {
+        let result = parse_alias(&["ll=\"ls", "-l\""]);
+        assert!(matches!(result, Ok(Command::Alias { name, command })
+            if name.as_deref() == Some("ll") && command.as_deref() == Some("ls -l")));
+
+        assert!(parse_alias(&["a=b", "c=d"]).is_err());
+    }
+
     #[test]
     fn test_parse_alias_invalid() {
         let result = parse_alias(&["invalid"]);
@@ -272,13 +1018,27 @@ mod tests {
     #[test]
     fn test_parse_fg_no_args() {
         let result = parse_fg(&[]);
-        assert!(matches!(result, Ok(Command::Fg { job_id: None })));
+        assert!(matches!(result, Ok(Command::Fg { job: None })));
     }
 
     #[test]
     fn test_parse_fg_with_job_id() {
         let result = parse_fg(&["1"]);
-        assert!(matches!(result, Ok(Command::Fg { job_id: Some(1) })));
+        assert!(matches!(result, Ok(Command::Fg { job: Some(JobSpec::Id(1)) })));
+
+        let result = parse_fg(&["%%"]);
+        assert!(matches!(result, Ok(Command::Fg { job: Some(JobSpec::Current) })));
+    }
+
+    #[test]
+    fn test_parse_bg() {
+        let result = parse_bg(&[]);
+        assert!(matches!(result, Ok(Command::Bg { job: None })));
+
+        let result = parse_bg(&["%2"]);
+        assert!(matches!(result, Ok(Command::Bg { job: Some(JobSpec::Id(2)) })));
+
+        assert!(parse_bg(&["sleep"]).is_err());
     }
 
     #[test]
@@ -287,44 +1047,397 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_let() {
+        let result = parse_let(&["I=I+1"]);
+        assert!(matches!(result, Ok(Command::Let { expressions }) if expressions == vec!["I=I+1"]));
+
+        let result = parse_let(&["\"X", "=", "2", "*", "Y\"", "Z++"]);
+        assert!(matches!(result, Ok(Command::Let { expressions })
+            if expressions == vec!["X = 2 * Y", "Z++"]));
+    }
+
+    #[test]
+    fn test_parse_read() {
+        let result = parse_read(&[]);
+        assert!(
+            matches!(result, Ok(Command::Read { prompt: None, variables }) if variables.is_empty())
+        );
+
+        let result = parse_read(&["-p", "\"Name:", "\"", "FIRST", "LAST"]);
+        assert!(
+            matches!(result, Ok(Command::Read { prompt: Some(p), variables })
+            if p == "Name: " && variables == vec!["FIRST", "LAST"])
+        );
+
+        assert!(parse_read(&["-p"]).is_err());
+        assert!(parse_read(&["1ABC"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_seq() {
+        let result = parse_seq(&["5"]);
+        assert!(
+            matches!(result, Ok(Command::Seq { args, equal_width: false }) if args == vec!["5"])
+        );
+
+        let result = parse_seq(&["-w", "1", "2", "10"]);
+        assert!(
+            matches!(result, Ok(Command::Seq { args, equal_width: true })
+            if args == vec!["1", "2", "10"])
+        );
+
+        assert!(parse_seq(&["-w"]).is_err());
+        assert!(parse_seq(&["1", "2", "3", "4"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_xargs() {
+        let result = parse_xargs(&["-n", "1", "cat"]);
+        assert!(
+            matches!(result, Ok(Command::Xargs { command, max_args: Some(1), replace: None, exit_on_error: false })
+            if command == vec!["cat"])
+        );
+
+        let result = parse_xargs(&["-e", "-I", "{}", "cp", "{}", "backup"]);
+        assert!(
+            matches!(result, Ok(Command::Xargs { command, max_args: None, replace: Some(r), exit_on_error: true })
+            if r == "{}" && command == vec!["cp", "{}", "backup"])
+        );
+
+        assert!(parse_xargs(&["-n", "0", "cat"]).is_err());
+        assert!(parse_xargs(&["-I"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_history_subcommands() {
+        let result = parse_history(&["clear"]);
+        assert!(matches!(
+            result,
+            Ok(Command::History {
+                action: HistoryAction::Clear,
+                format: OutputFormat::Text,
+            })
+        ));
+
+        let result = parse_history(&["-n", "10"]);
+        assert!(matches!(
+            result,
+            Ok(Command::History {
+                action: HistoryAction::Recent(10),
+                format: OutputFormat::Text,
+            })
+        ));
+
+        let result = parse_history(&["delete", "3"]);
+        assert!(matches!(
+            result,
+            Ok(Command::History {
+                action: HistoryAction::Delete(3),
+                format: OutputFormat::Text,
+            })
+        ));
+
+        let result = parse_history(&["-d", "4"]);
+        assert!(matches!(
+            result,
+            Ok(Command::History {
+                action: HistoryAction::Delete(4),
+                format: OutputFormat::Text,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_history_invalid_numbers() {
+        for args in [
+            &["-n", "ten"][..],
+            &["delete", "-1"],
+            &["delete"],
+            &["-n"],
+            &["clear", "all"],
+        ] {
+            let result = parse_history(args);
+            assert!(
+                result.unwrap_err().to_string().contains("Usage"),
+                "{args:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_exit() {
+        assert!(matches!(
+            parse_exit(&[]),
+            Ok(Command::Exit { status: None })
+        ));
+        assert!(matches!(
+            parse_exit(&["3"]),
+            Ok(Command::Exit { status: Some(3) })
+        ));
+        assert!(parse_exit(&["three"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_json_output_flags() {
+        assert!(matches!(
+            parse_ls(&["--json"]),
+            Ok(Command::Ls {
+                format: OutputFormat::Json,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_jobs(&["-o", "json"]),
+            Ok(Command::Jobs {
+                format: OutputFormat::Json
+            })
+        ));
+        assert!(matches!(
+            parse_environment(&["--json", "PATH"]),
+            Ok(Command::Environment {
+                action: EnvironmentAction::Show(var),
+                format: OutputFormat::Json,
+            }) if var == "PATH"
+        ));
+        assert!(matches!(
+            parse_history(&["-n", "3", "--json"]),
+            Ok(Command::History {
+                action: HistoryAction::Recent(3),
+                format: OutputFormat::Json,
+            })
+        ));
+
+        assert!(parse_ls(&["extra"]).is_err());
+        assert!(parse_jobs(&["-o", "xml"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_ls_flags() {
+        assert!(matches!(
+            parse_ls(&["-Rtr", "--json"]),
+            Ok(Command::Ls {
+                format: OutputFormat::Json,
+                recursive: true,
+                sort: LsSort::Time,
+                reverse: true,
+            })
+        ));
+        // -t と -S は後に指定したものを使う
+        assert!(matches!(
+            parse_ls(&["-t", "-S"]),
+            Ok(Command::Ls {
+                recursive: false,
+                sort: LsSort::Size,
+                reverse: false,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_ls(&["--unsorted", "-r"]),
+            Ok(Command::Ls {
+                sort: LsSort::Unsorted,
+                reverse: true,
+                ..
+            })
+        ));
+        assert!(parse_ls(&["-x"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_tr() {
+        match parse_tr(&["a-z", "A-Z"]) {
+            Ok(Command::Tr { set1, set2, delete: false, squeeze: false }) => {
+                assert_eq!(set1, "a-z");
+                assert_eq!(set2, Some("A-Z".to_string()));
+            }
+            _ => panic!("Expected Tr command"),
+        }
+        assert!(matches!(parse_tr(&["-d", "0-9"]), Ok(Command::Tr { set2: None, delete: true, squeeze: false, .. })));
+        assert!(matches!(parse_tr(&["-ds", "0-9", "a"]), Ok(Command::Tr { set2: Some(_), delete: true, squeeze: true, .. })));
+        assert!(parse_tr(&["-s", " "]).is_ok());
+
+        assert!(parse_tr(&["a-z"]).is_err()); // 置換にはset2が必要
+        assert!(parse_tr(&["-d", "a", "b"]).is_err());
+        assert!(parse_tr(&["-x", "a", "b"]).is_err());
+        assert!(parse_tr(&["z-a", "b"]).is_err()); // 逆順の範囲
+    }
+
+    #[test]
+    fn test_parse_dir_stack() {
+        assert!(matches!(parse_dir_stack("pushd", &["src"]),
+            Ok(Command::DirStack { action: DirStackAction::Push(Some(dir)) }) if dir == "src"));
+        assert!(matches!(parse_dir_stack("pushd", &[]), Ok(Command::DirStack { action: DirStackAction::Push(None) })));
+        assert!(matches!(parse_dir_stack("popd", &[]), Ok(Command::DirStack { action: DirStackAction::Pop })));
+        assert!(matches!(parse_dir_stack("dirs", &[]),
+            Ok(Command::DirStack { action: DirStackAction::Show { verbose: false } })));
+        assert!(matches!(parse_dir_stack("dirs", &["-v"]),
+            Ok(Command::DirStack { action: DirStackAction::Show { verbose: true } })));
+
+        assert!(parse_dir_stack("dirs", &["-x"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_fc() {
+        assert!(matches!(parse_fc(&["-l"]), Ok(Command::History { action: HistoryAction::Recent(16), .. })));
+        assert!(matches!(parse_fc(&["-s"]),
+            Ok(Command::History { action: HistoryAction::Substitute { replacement: None, target: None }, .. })));
+        assert!(matches!(parse_fc(&["-s", "old=new", "echo"]),
+            Ok(Command::History { action: HistoryAction::Substitute { replacement: Some((old, new)), target: Some(target) }, .. })
+            if old == "old" && new == "new" && target == "echo"));
+        assert!(matches!(parse_fc(&["-s", "ec"]),
+            Ok(Command::History { action: HistoryAction::Substitute { replacement: None, target: Some(target) }, .. })
+            if target == "ec"));
+        assert!(matches!(parse_fc(&[]), Ok(Command::History { action: HistoryAction::Edit(None), .. })));
+        assert!(matches!(parse_fc(&["-2"]),
+            Ok(Command::History { action: HistoryAction::Edit(Some(target)), .. }) if target == "-2"));
+
+        assert!(parse_fc(&["-s", "=new"]).is_err());
+        assert!(parse_fc(&["-s", "a=b", "echo", "extra"]).is_err());
+        assert!(parse_fc(&["-x"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_command() {
+        let result = parse_set_command(&["-o", "noclobber", "+C"]);
+        assert!(matches!(result, Ok(Command::Set { options })
+            if options == vec![("noclobber".to_string(), true), ("noclobber".to_string(), false)]));
+        assert!(matches!(parse_set_command(&["-e"]), Ok(Command::Set { options })
+            if options == vec![("errexit".to_string(), true)]));
+        assert!(matches!(parse_set_command(&["-n", "+n"]), Ok(Command::Set { options })
+            if options == vec![("dryrun".to_string(), true), ("dryrun".to_string(), false)]));
+        assert!(matches!(parse_set_command(&["-v", "+v"]), Ok(Command::Set { options })
+            if options == vec![("verbose".to_string(), true), ("verbose".to_string(), false)]));
+
+        // 設定なしは一覧
+        assert!(matches!(parse_set_command(&[]), Ok(Command::Set { options }) if options.is_empty()));
+        assert!(matches!(parse_set_command(&["-o"]), Ok(Command::Set { options }) if options.is_empty()));
+
+        assert!(parse_set_command(&["-o", "nosuchoption"]).is_err());
+        assert!(parse_set_command(&["+o"]).is_err());
+        assert!(parse_set_command(&["-z"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_eval() {
+        assert!(matches!(parse_eval(&["\"$CMD\"", "'a", "b'"]), Command::Eval { args }
+            if args == vec!["$CMD", "a b"]));
+        assert!(matches!(parse_eval(&[]), Command::Eval { args } if args.is_empty()));
+    }
+
+    #[test]
+    fn test_parse_trap() {
+        let result = parse_trap(&["\"rm", "-f", "lock\"", "EXIT", "sigint"]);
+        assert!(matches!(result, Ok(Command::Trap { action: TrapAction::Set { command, signals } })
+            if command == "rm -f lock" && signals == vec![TrapSignal::Exit, TrapSignal::Int]));
+
+        assert!(matches!(parse_trap(&["-", "INT", "TERM"]), Ok(Command::Trap { action: TrapAction::Remove(signals) })
+            if signals == vec![TrapSignal::Int, TrapSignal::Term]));
+        assert!(matches!(parse_trap(&["INT"]), Ok(Command::Trap { action: TrapAction::Remove(signals) })
+            if signals == vec![TrapSignal::Int]));
+        assert!(matches!(parse_trap(&[]), Ok(Command::Trap { action: TrapAction::List })));
+
+        assert!(parse_trap(&["echo"]).is_err());
+        assert!(parse_trap(&["-"]).is_err());
+        assert!(parse_trap(&["echo", "HUP"]).is_err());
+    }
+
     #[test]
     fn test_parse_env_command_list() {
         let result = parse_environment(&[]);
         assert!(matches!(
             result,
             Ok(Command::Environment {
-                action: EnvironmentAction::List
+                action: EnvironmentAction::List { pattern: None, session_only: false },
+                format: OutputFormat::Text,
             })
         ));
     }
 
+    #[test]
+    fn test_parse_env_command_list_filters() {
+        let result = parse_environment(&["RUCLI*"]);
+        assert!(matches!(result, Ok(Command::Environment {
+            action: EnvironmentAction::List { pattern: Some(pattern), session_only: false },
+            ..
+        }) if pattern == "RUCLI*"));
+
+        let result = parse_environment(&["--session", "--json"]);
+        assert!(matches!(result, Ok(Command::Environment {
+            action: EnvironmentAction::List { pattern: None, session_only: true },
+            format: OutputFormat::Json,
+        })));
+
+        assert!(parse_environment(&["--session", "PATH"]).is_err());
+        assert!(parse_environment(&["A", "B"]).is_err());
+    }
+
     #[test]
     fn test_parse_env_command_set() {
         let result = parse_environment(&["TEST_VAR=test_value"]);
         assert!(matches!(result, Ok(Command::Environment { 
-            action: EnvironmentAction::Set(var, val) 
+            action: EnvironmentAction::Set(var, val), 
+            format: OutputFormat::Text,
         }) if var == "TEST_VAR" && val == "test_value"));
     }
 
+    #[test]
+    fn test_parse_env_command_run() {
+        let result = parse_environment(&["-i", "A=1", "B=2", "cat", "notes.txt"]);
+        assert!(matches!(result, Ok(Command::Environment {
+            action: EnvironmentAction::Run { isolated: true, ref overrides, ref command },
+            ..
+        }) if overrides.len() == 2 && matches!(**command, Command::Cat { .. })));
+
+        // コマンドのオプションはenvのものとして扱わない
+        let result = parse_environment(&["TZ=UTC", "env", "--json"]);
+        assert!(matches!(result, Ok(Command::Environment {
+            action: EnvironmentAction::Run { isolated: false, ref command, .. },
+            format: OutputFormat::Text,
+        }) if matches!(**command, Command::Environment { format: OutputFormat::Json, .. })));
+
+        // -i だけなら上書きした変数の一覧、代入だけ・名前だけなら従来どおり
+        let result = parse_environment(&["-i"]);
+        assert!(matches!(result, Ok(Command::Environment {
+            action: EnvironmentAction::Run { isolated: true, ref overrides, ref command },
+            ..
+        }) if overrides.is_empty() && matches!(**command, Command::Environment { .. })));
+        assert!(matches!(parse_environment(&["A=1"]), Ok(Command::Environment {
+            action: EnvironmentAction::Set(..), ..
+        })));
+        assert!(matches!(parse_environment(&["PATH"]), Ok(Command::Environment {
+            action: EnvironmentAction::Show(_), ..
+        })));
+    }
+
     #[test]
     fn test_parse_env_command_show() {
         let result = parse_environment(&["PATH"]);
         assert!(matches!(result, Ok(Command::Environment { 
-            action: EnvironmentAction::Show(var) 
+            action: EnvironmentAction::Show(var), 
+            format: OutputFormat::Text,
         }) if var == "PATH"));
     }
 
     #[test]
 fn test_parse_history_execute() {
-    // 正常系：数字
+    // 正常系：数字だけなら最近の件数
     let result = parse_history(&["5"]);
     assert!(matches!(result, Ok(Command::History { 
-        action: HistoryAction::Execute(5) 
+        action: HistoryAction::Recent(5), 
+        format: OutputFormat::Text,
     })));
     
-    let result = parse_history(&["123"]);
+    let result = parse_history(&["exec", "123"]);
     assert!(matches!(result, Ok(Command::History { 
-        action: HistoryAction::Execute(123) 
+        action: HistoryAction::Execute { index: 123, assume_yes: false }, 
+        format: OutputFormat::Text,
+    })));
+
+    let result = parse_history(&["exec", "-y", "7"]);
+    assert!(matches!(result, Ok(Command::History { 
+        action: HistoryAction::Execute { index: 7, assume_yes: true }, 
+        format: OutputFormat::Text,
     })));
 }
 
@@ -345,18 +1458,27 @@ fn test_parse_history_execute() {
         // 既存機能の確認：リスト
         let result = parse_history(&[]);
         assert!(matches!(result, Ok(Command::History { 
-            action: HistoryAction::List 
+            action: HistoryAction::List, 
+            format: OutputFormat::Text,
         })));
         
         // 既存機能の確認：検索
         let result = parse_history(&["search", "echo"]);
         assert!(matches!(result, Ok(Command::History { 
-            action: HistoryAction::Search(s) 
-        }) if s == "echo"));
+            action: HistoryAction::Search { query, regex: false, all: false }, 
+            format: OutputFormat::Text,
+        }) if query == "echo"));
         
         let result = parse_history(&["search", "echo", "hello"]);
         assert!(matches!(result, Ok(Command::History { 
-            action: HistoryAction::Search(s) 
-        }) if s == "echo hello"));
+            action: HistoryAction::Search { query, regex: false, all: false }, 
+            format: OutputFormat::Text,
+        }) if query == "echo hello"));
+
+        let result = parse_history(&["search", "-r", "--all", "^cargo (build|test)"]);
+        assert!(matches!(result, Ok(Command::History { 
+            action: HistoryAction::Search { query, regex: true, all: true }, 
+            format: OutputFormat::Text,
+        }) if query == "^cargo (build|test)"));
     }
 }
@@ -2,7 +2,10 @@
 
 use crate::commands::Command;
 use crate::error::{Result, RucliError};
-use crate::parser::{parse_command, split_by_semicolon};
+use crate::parser::expansion::{expand_braces, expand_tilde};
+use crate::parser::operators::{Keyword, control_keywords};
+use crate::parser::tokenizer::{mask_quoted, split_group, split_words};
+use crate::parser::{parse_command, split_by_semicolon, split_redirects};
 
 // ifを含むかチェック
 pub(super) fn contains_if(input: &str) -> bool {
@@ -24,134 +27,209 @@ pub(super) fn contains_function(input: &str) -> bool {
     input.trim().starts_with("function ")
 }
 
+/// 一番外側の構文のキーワードを、`from` 以降から探す
+///
+/// 入れ子になった構文のキーワード（`for ...; do if ...; then ...; fi; done` の then・fi など）は対象外
+fn find_keyword(keywords: &[Keyword], word: &str, from: usize) -> Option<usize> {
+    keywords
+        .iter()
+        .find(|keyword| keyword.depth == 0 && keyword.word == word && keyword.pos >= from)
+        .map(|keyword| keyword.pos)
+}
+
+/// 構文の中身（キーワードの間）を取り出す（末尾のセミコロンは除く）
+fn section(input: &str, start: usize, end: usize) -> &str {
+    input[start..end].trim().trim_end_matches(';').trim()
+}
+
 /// ifコマンドのパースを行う
+///
+/// then・else・fi は入れ子の深さを数えて対応するものを探す
 pub(super) fn parse_if_statement(input: &str) -> Result<Command> {
     let input = input.trim();
-
-    // 複数の空白を一つにまとめる
-    let input = input.split_whitespace().collect::<Vec<_>>().join(" ");
+    let keywords = control_keywords(input);
 
     // thenの位置を探す
-    let then_pos = input
-        .find(" then ")
+    let then_pos = find_keyword(&keywords, "then", 0)
         .ok_or(RucliError::ParseError("if: 'then' not found".to_string()))?;
 
     // fiの位置を探す
-    let fi_pos = input
-        .rfind(" fi")
+    let fi_pos = find_keyword(&keywords, "fi", then_pos)
         .ok_or(RucliError::ParseError("if: 'fi' not found".to_string()))?;
 
     // else の位置を探す（オプション）
-    let else_pos = input[then_pos..fi_pos]
-        .find(" else ")
-        .map(|pos| then_pos + pos);
-
-    let condition_str = input["if ".len()..then_pos]
-        .trim()
-        .trim_end_matches(';') // 末尾のセミコロンを削除
-        .trim();
-
-    let (then_str, else_str) = if let Some(else_pos) = else_pos {
-        let then_part = input[then_pos + " then ".len()..else_pos].trim();
-        let else_part = input[else_pos + " else ".len()..fi_pos].trim();
-        (then_part, Some(else_part))
-    } else {
-        let then_part = input[then_pos + " then ".len()..fi_pos].trim();
-        (then_part, None)
-    };
+    let else_pos = find_keyword(&keywords, "else", then_pos).filter(|&pos| pos < fi_pos);
+
+    let condition_str = section(input, "if".len(), then_pos);
+    let then_str = section(input, then_pos + "then".len(), else_pos.unwrap_or(fi_pos));
+    let else_str = else_pos.map(|pos| section(input, pos + "else".len(), fi_pos));
 
     // 各部分をパース
     let condition_cmd = parse_command(condition_str)?;
     let then_cmd = parse_multiple_commands(then_str)?;
     let else_cmd = else_str.map(parse_multiple_commands).transpose()?;
 
-    Ok(Command::If {
+    let if_cmd = Command::If {
         condition: Box::new(condition_cmd),
         then_part: Box::new(then_cmd),
         else_part: else_cmd.map(Box::new),
-    })
+    };
+
+    apply_trailing_redirect(if_cmd, &input[fi_pos + "fi".len()..])
 }
 
 /// whileコマンドのパースを行う
 pub(super) fn parse_while_statement(input: &str) -> Result<Command> {
     let input = input.trim();
-
-    // 複数の空白を一つにまとめる
-    let input = input.split_whitespace().collect::<Vec<_>>().join(" ");
+    let keywords = control_keywords(input);
 
     // doの位置を探す
-    let do_pos = input
-        .find(" do ")
+    let do_pos = find_keyword(&keywords, "do", 0)
         .ok_or(RucliError::ParseError("while: 'do' not found".to_string()))?;
 
     // doneの位置を探す
-    let done_pos = input.rfind(" done").ok_or(RucliError::ParseError(
+    let done_pos = find_keyword(&keywords, "done", do_pos).ok_or(RucliError::ParseError(
         "while: 'done' not found".to_string(),
     ))?;
 
-    let condition_str = input["while ".len()..do_pos]
-        .trim()
-        .trim_end_matches(';') // これを追加！
-        .trim();
-    let body_str = input[do_pos + " do ".len()..done_pos].trim();
+    let condition_str = section(input, "while".len(), do_pos);
+    let body_str = section(input, do_pos + "do".len(), done_pos);
 
     // 各部分をパース
     let condition_cmd = parse_command(condition_str)?;
     let body_cmd = parse_multiple_commands(body_str)?;
 
-    Ok(Command::While {
+    let while_cmd = Command::While {
         condition: Box::new(condition_cmd),
         body: Box::new(body_cmd),
-    })
+    };
+
+    apply_trailing_redirect(while_cmd, &input[done_pos + "done".len()..])
 }
 
 /// forコマンドのパースを行う
 pub(super) fn parse_for_statement(input: &str) -> Result<Command> {
     let input = input.trim();
 
-    // 複数の空白を一つにまとめる
-    let input = input.split_whitespace().collect::<Vec<_>>().join(" ");
+    // C言語風のfor文
+    if input["for".len()..].trim_start().starts_with("((") {
+        return parse_arithmetic_for_statement(input);
+    }
 
-    // inの位置を探す
-    let in_pos = input
-        .find(" in ")
-        .ok_or(RucliError::ParseError("for: 'in' not found".to_string()))?;
+    let keywords = control_keywords(input);
 
     // doの位置を探す
-    let do_pos = input
-        .find(" do ")
+    let do_pos = find_keyword(&keywords, "do", 0)
         .ok_or(RucliError::ParseError("for: 'do' not found".to_string()))?;
 
+    // inの位置を探す（`for x in; do` のように項目がなくてもよい）
+    // クォートの中の in は区切りとして扱わない
+    let masked = mask_quoted(&input[..do_pos]);
+    let in_pos = masked
+        .match_indices("in")
+        .map(|(pos, _)| pos)
+        .find(|&pos| {
+            masked[..pos].ends_with(char::is_whitespace)
+                && masked[pos + "in".len()..].starts_with(|c: char| c.is_whitespace() || c == ';')
+        })
+        .ok_or(RucliError::ParseError("for: 'in' not found".to_string()))?;
+
     // doneの位置を探す
-    let done_pos = input
-        .rfind(" done")
+    let done_pos = find_keyword(&keywords, "done", do_pos)
         .ok_or(RucliError::ParseError("for: 'done' not found".to_string()))?;
 
     // 各部分をパース
-    let variable_str = input["for ".len()..in_pos].trim().to_string();
-    let items_str = input[in_pos + " in ".len()..do_pos]
-        .trim()
-        .trim_end_matches(';') // 末尾のセミコロンを削除
-        .trim();
-
-    let items_vec = items_str
-        .split_whitespace()
-        .map(|s| s.to_string())
+    let variable_str = input["for".len()..in_pos].trim().to_string();
+    let items_str = section(input, in_pos + "in".len(), do_pos);
+
+    // クォートで囲まれた項目は空白を含んでも一つの項目にする（クォートは実行時に取り除く）
+    let items_vec = split_words(&expand_tilde(&expand_braces(items_str)))
+        .into_iter()
+        .map(str::to_string)
         .collect();
-    let body_str = input[do_pos + " do ".len()..done_pos].trim();
+    let body_str = section(input, do_pos + "do".len(), done_pos);
 
     // bodyのパース
     let body_cmd = parse_multiple_commands(body_str)?;
 
-    Ok(Command::For {
+    let for_cmd = Command::For {
         variable: variable_str,
         items: items_vec,
         body: Box::new(body_cmd),
-    })
+    };
+
+    apply_trailing_redirect(for_cmd, &input[done_pos + "done".len()..])
+}
+
+/// `done` の後に続くリダイレクトをループ全体に適用する
+///
+/// 例: `while read LINE; do ...; done < input.txt`
+fn apply_trailing_redirect(command: Command, trailing: &str) -> Result<Command> {
+    let trailing = trailing.trim();
+    if !trailing.starts_with(['<', '>']) {
+        return Ok(command);
+    }
+
+    match split_redirects(trailing) {
+        (rest, redirects) if rest.is_empty() && !redirects.is_empty() => Ok(Command::Redirect {
+            command: Box::new(command),
+            redirects,
+        }),
+        _ => Err(RucliError::ParseError(format!(
+            "invalid redirect after 'done': {trailing}"
+        ))),
+    }
+}
+
+/// C言語風のforコマンドのパースを行う
+///
+/// # Arguments
+/// * `input` - "for ((init; condition; step)); do body; done" 形式の文字列
+fn parse_arithmetic_for_statement(input: &str) -> Result<Command> {
+    // ((と))の位置を探す
+    let header_start = input.find("((").map_or(0, |pos| pos + "((".len());
+    let header_end = input
+        .find("))")
+        .ok_or(RucliError::ParseError("for: '))' not found".to_string()))?;
+
+    // init; condition; step に分割
+    let header = &input[header_start..header_end];
+    let parts: Vec<&str> = header.split(';').map(|s| s.trim()).collect();
+    let [init, condition, step] = parts.as_slice() else {
+        return Err(RucliError::ParseError(
+            "for: expected '((init; condition; step))'".to_string(),
+        ));
+    };
+
+    let keywords = control_keywords(input);
+
+    // doの位置を探す
+    let do_pos = find_keyword(&keywords, "do", header_end)
+        .ok_or(RucliError::ParseError("for: 'do' not found".to_string()))?;
+
+    // doneの位置を探す
+    let done_pos = find_keyword(&keywords, "done", do_pos)
+        .ok_or(RucliError::ParseError("for: 'done' not found".to_string()))?;
+
+    // bodyのパース
+    let body_str = section(input, do_pos + "do".len(), done_pos);
+    let body_cmd = parse_multiple_commands(body_str)?;
+
+    let for_cmd = Command::ArithmeticFor {
+        init: init.to_string(),
+        condition: condition.to_string(),
+        step: step.to_string(),
+        body: Box::new(body_cmd),
+    };
+
+    apply_trailing_redirect(for_cmd, &input[done_pos + "done".len()..])
 }
 
 /// 関数定義をパースする
 ///
+/// 本体の `{ ... }` は波括弧の入れ子を数えて取り出す（`${VAR}` や本体の中のグループで終わらない）。
+/// 本体は他の複数のコマンドと同じようにパースするので、if・while・for も書ける
+///
 /// # Arguments
 /// * `input` - "function name() { body }" 形式の文字列
 ///
@@ -162,9 +240,6 @@ pub(super) fn parse_for_statement(input: &str) -> Result<Command> {
 pub(super) fn parse_function_definition(input: &str) -> Result<Command> {
     let input = input.trim();
 
-    // 複数の空白を一つにまとめる
-    let input = input.split_whitespace().collect::<Vec<_>>().join(" ");
-
     // (の位置を探す
     let start_parens_pos = input.find("(").ok_or(RucliError::ParseError(
         "function: '(' not found".to_string(),
@@ -176,27 +251,33 @@ pub(super) fn parse_function_definition(input: &str) -> Result<Command> {
     ))?;
 
     // ()の間が空であることを確認（引数は未対応）
-    if end_parens_pos - start_parens_pos != 1 {
+    if end_parens_pos < start_parens_pos
+        || !input[start_parens_pos + 1..end_parens_pos]
+            .trim()
+            .is_empty()
+    {
         return Err(RucliError::ParseError(
             "function: parameters not supported".to_string(),
         ));
     }
 
-    // {の位置を探す
-    let start_bracket_pos = input.find("{").ok_or(RucliError::ParseError(
-        "function: '{' not found".to_string(),
-    ))?;
-
-    // }の位置を探す
-    let end_bracket_pos = input.find("}").ok_or(RucliError::ParseError(
-        "function: '}' not found".to_string(),
-    ))?;
+    // ()の後ろ全体が一つの { ... } であること
+    let body_str = match split_group(&input[end_parens_pos + 1..]) {
+        Some(('{', Some(body))) => body.trim(),
+        Some(('{', None)) => {
+            return Err(RucliError::ParseError(
+                "function: '}' not found".to_string(),
+            ));
+        }
+        _ => {
+            return Err(RucliError::ParseError(
+                "function: '{' not found".to_string(),
+            ));
+        }
+    };
 
     // 関数名を取得
-    let name_str = input["function ".len()..start_parens_pos].trim();
-
-    // 処理部分を取得
-    let body_str = input[start_bracket_pos + 1..end_bracket_pos].trim();
+    let name_str = input["function".len()..start_parens_pos].trim();
 
     // bodyのパース
     let body_cmd = parse_multiple_commands(body_str)?;
@@ -207,6 +288,36 @@ pub(super) fn parse_function_definition(input: &str) -> Result<Command> {
     })
 }
 
+/// グループ（`( ... )` と `{ ...; }`）をパースする
+///
+/// `( ... )` はサブシェル、`{ ...; }` は現在の環境で実行する複合コマンドになる
+///
+/// # Arguments
+/// * `opener` - 開き括弧（`(` または `{`）
+/// * `body` - 括弧の内側（閉じていない場合は None）
+///
+/// # Errors
+/// - 括弧が閉じていない、または閉じ括弧の後ろに余分な文字がある場合
+/// - 中身が空の場合
+pub(super) fn parse_group(opener: char, body: Option<&str>) -> Result<Command> {
+    let body =
+        body.ok_or_else(|| RucliError::ParseError(format!("syntax error: unmatched '{opener}'")))?;
+    if body.trim().is_empty() {
+        return Err(RucliError::ParseError(format!(
+            "syntax error: empty group '{opener}'"
+        )));
+    }
+
+    let command = parse_multiple_commands(body)?;
+
+    Ok(match opener {
+        '(' => Command::Subshell {
+            command: Box::new(command),
+        },
+        _ => command,
+    })
+}
+
 /// 複数のコマンドをパースする
 pub(super) fn parse_multiple_commands(input: &str) -> Result<Command> {
     // 入力の分割を行う
@@ -281,6 +392,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_if_inside_for() {
+        let input = "for i in 1 2; do if cat $i; then echo ok; else echo no; fi; echo next; done";
+        let Command::For { items, body, .. } = parse_for_statement(input).unwrap() else {
+            panic!("Expected For command");
+        };
+        assert_eq!(items, vec!["1", "2"]);
+
+        let Command::Compound { commands } = *body else {
+            panic!("Expected Compound body");
+        };
+        assert_eq!(commands.len(), 2);
+        assert!(matches!(
+            &commands[0],
+            Command::If { condition, else_part: Some(_), .. }
+            if matches!(**condition, Command::Cat { .. })
+        ));
+        assert!(matches!(&commands[1], Command::Echo { message, .. } if message == "next"));
+    }
+
+    #[test]
+    fn test_parse_if_with_keywords_in_arguments() {
+        // 引数やクォートの中の fi・then はキーワードではない
+        let input = r#"if echo then; then echo "fi; done"; echo fi; fi"#;
+        let Command::If {
+            condition,
+            then_part,
+            else_part,
+        } = parse_if_statement(input).unwrap()
+        else {
+            panic!("Expected If command");
+        };
+        assert!(matches!(*condition, Command::Echo { message, .. } if message == "then"));
+        assert!(else_part.is_none());
+
+        let Command::Compound { commands } = *then_part else {
+            panic!("Expected Compound then-part");
+        };
+        assert!(
+            matches!(&commands[0], Command::Echo { message, .. } if message == r#""fi; done""#)
+        );
+        assert!(matches!(&commands[1], Command::Echo { message, .. } if message == "fi"));
+    }
+
     #[test]
     fn test_parse_if_missing_then() {
         let input = "if echo test; echo OK; fi";
@@ -335,6 +490,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_while_with_input_redirect() {
+        let input = "while read LINE; do echo $LINE; done < lines.txt";
+        let cmd = parse_while_statement(input).unwrap();
+
+        match cmd {
+            Command::Redirect { command, redirects } => {
+                assert!(matches!(*command, Command::While { .. }));
+                assert_eq!(redirects.len(), 1);
+                assert_eq!(redirects[0].redirect_type, "<");
+                assert_eq!(redirects[0].target, "lines.txt");
+            }
+            _ => panic!("Expected Redirect command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_while_inside_while() {
+        let input = "while cat a; do while cat b; do rm b; done; rm a; done < list.txt";
+        let Command::Redirect { command, .. } = parse_while_statement(input).unwrap() else {
+            panic!("Expected Redirect command");
+        };
+        let Command::While { condition, body } = *command else {
+            panic!("Expected While command");
+        };
+        assert!(matches!(*condition, Command::Cat { filename, .. } if filename == "a"));
+
+        let Command::Compound { commands } = *body else {
+            panic!("Expected Compound body");
+        };
+        assert_eq!(commands.len(), 2);
+        assert!(
+            matches!(&commands[0], Command::While { body, .. } if matches!(**body, Command::Rm { .. }))
+        );
+        assert!(matches!(&commands[1], Command::Rm { .. }));
+
+        // 内側のdoneが足りなければ外側のdoneも見つからない
+        let result = parse_while_statement("while cat a; do while cat b; do rm b; done");
+        assert!(result.unwrap_err().to_string().contains("done"));
+    }
+
     #[test]
     fn test_parse_while_missing_do() {
         let input = "while echo test; echo loop; done";
@@ -397,6 +593,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_for_quoted_items() {
+        let input = r#"for name in "Alice Smith" 'Bob Jones' Carol; do echo Hi $name; done"#;
+        let Command::For { items, .. } = parse_for_statement(input).unwrap() else {
+            panic!("Expected For command");
+        };
+        assert_eq!(items, vec!["\"Alice Smith\"", "'Bob Jones'", "Carol"]);
+
+        // クォートの中の do は区切りではない
+        let input = r#"for task in "to do" done; do echo $task; done"#;
+        let Command::For { items, .. } = parse_for_statement(input).unwrap() else {
+            panic!("Expected For command");
+        };
+        assert_eq!(items, vec!["\"to do\"", "done"]);
+    }
+
+    #[test]
+    fn test_parse_for_empty_items() {
+        let input = "for x in; do echo $x; done";
+        let Command::For { items, .. } = parse_for_statement(input).unwrap() else {
+            panic!("Expected For command");
+        };
+        assert!(items.is_empty());
+    }
+
     #[test]
     fn test_parse_for_missing_in() {
         let input = "for i 1 2 3; do echo $i; done";
@@ -421,6 +642,36 @@ mod tests {
         assert!(result.unwrap_err().to_string().contains("done"));
     }
 
+    #[test]
+    fn test_parse_arithmetic_for() {
+        let input = "for ((i=0; i<5; i++)); do echo $i; done";
+        let cmd = parse_for_statement(input).unwrap();
+
+        match cmd {
+            Command::ArithmeticFor {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                assert_eq!(init, "i=0");
+                assert_eq!(condition, "i<5");
+                assert_eq!(step, "i++");
+                assert!(matches!(*body, Command::Echo { .. }));
+            }
+            _ => panic!("Expected ArithmeticFor command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_arithmetic_for_invalid_header() {
+        let result = parse_for_statement("for ((i=0; i<5)); do echo $i; done");
+        assert!(result.is_err());
+
+        let result = parse_for_statement("for ((i=0; i<5; i++)); echo $i");
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_parse_function_simple() {
         let input = "function greet() { echo Hello }";
@@ -442,6 +693,27 @@ mod tests {
         assert!(matches!(result, Command::Function { .. }));
     }
 
+    #[test]
+    fn test_parse_function_with_loop_body() {
+        let input = "function each() { for f in a b; do echo ${f}!; done; { echo x; }; echo end; }";
+        let Command::Function { name, body } = parse_function_definition(input).unwrap() else {
+            panic!("Expected Function command");
+        };
+        assert_eq!(name, "each");
+
+        let Command::Compound { commands } = *body else {
+            panic!("Expected Compound body");
+        };
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(&commands[0], Command::For { items, .. } if items == &["a", "b"]));
+        assert!(matches!(&commands[1], Command::Echo { message, .. } if message == "x"));
+        assert!(matches!(&commands[2], Command::Echo { message, .. } if message == "end"));
+
+        // 本体の閉じ括弧が足りない
+        let result = parse_function_definition("function f() { { echo a; }");
+        assert!(result.unwrap_err().to_string().contains("'}'"));
+    }
+
     #[test]
     fn test_parse_function_missing_parentheses() {
         let input = "function test { echo Hello }";
@@ -461,6 +733,7 @@ mod tests {
         // 関数を定義
         let body = Command::Echo {
             message: "test".to_string(),
+            newline: true,
         };
         functions::define_function("mytest", body);
 
@@ -1,5 +1,7 @@
-//! 各種展開機能（履歴展開、変数展開、コマンド置換）
+//! 各種展開機能（履歴展開、変数展開、コマンド置換、ブレース展開、チルダ展開）
 
+use super::tokenizer::scan_protecting_substitution;
+use crate::environment::get_var;
 use crate::error::{Result, RucliError};
 use crate::history::{
     get_history_by_number, get_history_by_offset, get_last_command, search_history_by_prefix,
@@ -68,3 +70,272 @@ fn expand_history_pattern(pattern: &str) -> Result<String> {
 
     result.ok_or_else(error)
 }
+
+/// ブレース展開を行う
+///
+/// 空白で区切られた語ごとに `{a,b}` と `{1..5}` / `{1..10..2}` を展開し、結果の語を
+/// 空白でつなぐ。入れ子（`{a,b{1,2}}`）と前後の文字（`file{1,2}.txt`）にも対応する。
+/// クォート内・エスケープされた括弧と `${...}` は展開せず、展開できない語は元のまま残す。
+///
+/// 例: `mkdir -p src/{bin,lib}` → `mkdir -p src/bin src/lib`
+/// 例: `echo f{01..03}` → `echo f01 f02 f03`
+pub fn expand_braces(input: &str) -> String {
+    map_words(input, expand_word)
+}
+
+/// チルダ展開を行う
+///
+/// 語の先頭の `~` と `~/` を `$HOME` の値に置き換える。
+/// クォートされたチルダ、語の途中のチルダ、`~user` の形は展開しない。
+/// `$HOME` が設定されていなければそのまま残す
+///
+/// 例: `cat ~/notes.txt` → `cat /home/user/notes.txt`
+pub fn expand_tilde(input: &str) -> String {
+    let Some(home) = get_var("HOME") else {
+        return input.to_string();
+    };
+
+    map_words(input, |word| {
+        let expands = word.first() == Some(&('~', false))
+            && word.get(1).is_none_or(|&next| next == ('/', false));
+        if !expands {
+            return vec![word.to_vec()];
+        }
+
+        let home = home.chars().map(|ch| (ch, true));
+        vec![home.chain(word[1..].iter().copied()).collect()]
+    })
+}
+
+/// 空白で区切られた語ごとに展開し、展開後の語を空白でつなぐ
+///
+/// 語は文字と、クォート等やコマンド置換の中で保護されているかの組で渡す。
+/// 区切りの空白とクォート内の空白は元のまま残す
+fn map_words(input: &str, expand: impl Fn(&[(char, bool)]) -> Vec<Vec<(char, bool)>>) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut word = Vec::new();
+
+    let push_word = |output: &mut String, word: &mut Vec<(char, bool)>| {
+        if word.is_empty() {
+            return;
+        }
+        let words: Vec<String> = expand(word)
+            .iter()
+            .map(|chars| chars.iter().map(|&(ch, _)| ch).collect())
+            .collect();
+        output.push_str(&words.join(" "));
+        word.clear();
+    };
+
+    for (_, ch, protected) in scan_protecting_substitution(input) {
+        if ch.is_whitespace() && !protected {
+            push_word(&mut output, &mut word);
+            output.push(ch);
+        } else {
+            word.push((ch, protected));
+        }
+    }
+    push_word(&mut output, &mut word);
+
+    output
+}
+
+/// 一語を展開する（展開できる括弧がなければ元の語だけを返す）
+fn expand_word(word: &[(char, bool)]) -> Vec<Vec<(char, bool)>> {
+    for open in 0..word.len() {
+        let Some(close) = matching_brace(word, open) else {
+            continue;
+        };
+        let Some(alternatives) = brace_alternatives(&word[open + 1..close]) else {
+            continue;
+        };
+
+        // 前後の文字と組み合わせ、残りの括弧を展開する
+        return alternatives
+            .into_iter()
+            .flat_map(|alternative| {
+                let combined = [&word[..open], &alternative, &word[close + 1..]].concat();
+                expand_word(&combined)
+            })
+            .collect();
+    }
+
+    vec![word.to_vec()]
+}
+
+/// 指定位置の `{` に対応する `}` の位置
+///
+/// `${` の括弧やクォートされた括弧は対象外
+fn matching_brace(word: &[(char, bool)], open: usize) -> Option<usize> {
+    if word[open] != ('{', false) || (open > 0 && word[open - 1].0 == '$') {
+        return None;
+    }
+
+    let mut depth = 0;
+    for (pos, &(ch, protected)) in word.iter().enumerate().skip(open) {
+        match (ch, protected) {
+            ('{', false) => depth += 1,
+            ('}', false) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// 括弧の中身から展開後の候補を作る
+///
+/// # Returns
+/// * 最上位の `,` で区切った候補、または数値の範囲。どちらでもなければ None
+fn brace_alternatives(body: &[(char, bool)]) -> Option<Vec<Vec<(char, bool)>>> {
+    let mut alternatives = vec![Vec::new()];
+    let mut depth = 0;
+
+    for &(ch, protected) in body {
+        match (ch, protected) {
+            ('{', false) => depth += 1,
+            ('}', false) => depth -= 1,
+            (',', false) if depth == 0 => {
+                alternatives.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        alternatives.last_mut()?.push((ch, protected));
+    }
+
+    if alternatives.len() > 1 {
+        return Some(alternatives);
+    }
+
+    // 範囲はクォートを含まない場合だけ
+    if body.iter().any(|&(_, protected)| protected) {
+        return None;
+    }
+    let body: String = body.iter().map(|&(ch, _)| ch).collect();
+    let range = numeric_range(&body)?;
+
+    Some(
+        range
+            .into_iter()
+            .map(|item| item.chars().map(|ch| (ch, false)).collect())
+            .collect(),
+    )
+}
+
+/// `start..end[..step]` の数値の範囲を展開する
+///
+/// `start` か `end` が `0` で始まる場合は、長いほうの桁数に0で揃える
+/// 例: `01..03` → ["01", "02", "03"]、`5..1..2` → ["5", "3", "1"]
+fn numeric_range(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start_str, end_str, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (
+            *start,
+            *end,
+            step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        ),
+        _ => return None,
+    };
+    let start: i64 = start_str.parse().ok()?;
+    let end: i64 = end_str.parse().ok()?;
+
+    let padded =
+        |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+    let width = if padded(start_str) || padded(end_str) {
+        start_str.len().max(end_str.len())
+    } else {
+        0
+    };
+
+    let count = start.abs_diff(end) / step + 1;
+    let items = (0..count)
+        .map(|i| {
+            let offset = (i * step) as i64;
+            let value = if start <= end {
+                start + offset
+            } else {
+                start - offset
+            };
+            format!("{value:0width$}")
+        })
+        .collect();
+
+    Some(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::set_var;
+    use crate::state::{ShellState, enter};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_expand_tilde() {
+        let _state = enter(Arc::new(ShellState::new()));
+        set_var("HOME", "/home/test");
+
+        assert_eq!(
+            expand_tilde("cp ~/a.txt ~ x~ ~/"),
+            "cp /home/test/a.txt /home/test x~ /home/test/"
+        );
+        // クォート・エスケープされたチルダと ~user は展開しない
+        assert_eq!(
+            expand_tilde(r#"echo '~' "~/a" \~ ~root/x"#),
+            r#"echo '~' "~/a" \~ ~root/x"#
+        );
+    }
+
+    #[test]
+    fn test_expand_braces_lists() {
+        assert_eq!(
+            expand_braces("mkdir -p src/{bin,lib,tests}"),
+            "mkdir -p src/bin src/lib src/tests"
+        );
+        assert_eq!(
+            expand_braces("echo a{b,c}d{1,2}"),
+            "echo abd1 abd2 acd1 acd2"
+        );
+        // 入れ子と空の候補
+        assert_eq!(expand_braces("echo {a,b{1,2}}"), "echo a b1 b2");
+        assert_eq!(expand_braces("echo x{,.bak}"), "echo x x.bak");
+    }
+
+    #[test]
+    fn test_expand_braces_ranges() {
+        assert_eq!(
+            expand_braces("echo file{1..3}.txt"),
+            "echo file1.txt file2.txt file3.txt"
+        );
+        assert_eq!(expand_braces("echo {01..03}"), "echo 01 02 03");
+        assert_eq!(expand_braces("echo {3..1}"), "echo 3 2 1");
+        assert_eq!(
+            expand_braces("echo {0..10..5} {-1..1}"),
+            "echo 0 5 10 -1 0 1"
+        );
+    }
+
+    #[test]
+    fn test_expand_braces_no_expansion() {
+        for input in [
+            "echo '{a,b}' \"{1..3}\" \\{a,b}",
+            "echo ${HOME} ${A,B}",
+            "xargs -I {} echo {}",
+            "echo {a} {1..x} {a,b",
+            "{ echo a, b; }",
+            "echo $(echo {a,b}) `echo {1..2}`",
+        ] {
+            assert_eq!(expand_braces(input), input);
+        }
+
+        // 空白やクォートはそのまま残す
+        assert_eq!(expand_braces("echo \"a  b\"  {x,y}"), "echo \"a  b\"  x y");
+    }
+}
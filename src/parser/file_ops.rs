@@ -1,89 +1,451 @@
 //! ファイル操作コマンドのパース関数
 
-use crate::commands::Command;
-use crate::error::Result;
+use crate::commands::{Command, CutRange, CutSelection, FindFilter, FindNumber};
+use crate::error::{Result, RucliError};
+use crate::parser::utils::{SplitArgs, parse_octal_mode, split_command_flags};
 
 pub(super) fn parse_mkdir(args: &[&str]) -> Result<Command> {
-    match args {
-        ["-p", path] => Ok(Command::Mkdir {
-            path: path.to_string(),
-            parents: true,
-        }),
-        [path] => Ok(Command::Mkdir {
-            path: path.to_string(),
-            parents: false,
-        }),
-        _ => unreachable!(),
+    const USAGE: &str = "Usage: mkdir [-p] [-m mode] <directory...>";
+
+    let args = split_command_flags("mkdir", args)?;
+    if args.positionals.is_empty() {
+        return Err(RucliError::InvalidArgument(format!(
+            "mkdir: missing operand\n{USAGE}"
+        )));
     }
+
+    Ok(Command::Mkdir {
+        paths: args
+            .positionals
+            .iter()
+            .map(|path| path.to_string())
+            .collect(),
+        parents: args.has("-p"),
+        mode: args
+            .value("-m")
+            .map(|value| parse_octal_mode("mkdir", value))
+            .transpose()?,
+    })
 }
 
+/// rmコマンドのパース関数
+///
+/// オプションはパスの前後どちらに置いてもよい（`rm -rf dir` も `rm dir -rf` も同じ）
 pub(super) fn parse_rm(args: &[&str]) -> Result<Command> {
-    match args {
-        ["-r", path] => Ok(Command::Rm {
-            path: path.to_string(),
-            recursive: true,
-            force: false,
-        }),
-        ["-f", path] => Ok(Command::Rm {
-            path: path.to_string(),
-            recursive: false,
-            force: true,
-        }),
-        ["-rf", path] | ["-fr", path] => Ok(Command::Rm {
-            path: path.to_string(),
-            recursive: true,
-            force: true,
-        }),
-        [path] => Ok(Command::Rm {
-            path: path.to_string(),
-            recursive: false,
-            force: false,
-        }),
-        _ => unreachable!(),
-    }
+    const USAGE: &str = "Usage: rm [-r] [-f] [--no-preserve-root] <path>";
+
+    let args = split_command_flags("rm", args)?;
+    let path = match args.positionals[..] {
+        [path] => path,
+        [] => {
+            return Err(RucliError::InvalidArgument(format!(
+                "rm: missing operand\n{USAGE}"
+            )));
+        }
+        _ => {
+            return Err(RucliError::InvalidArgument(format!(
+                "rm: only one path can be removed at a time\n{USAGE}"
+            )));
+        }
+    };
+
+    Ok(Command::Rm {
+        path: path.to_string(),
+        recursive: args.has("-r"),
+        force: args.has("-f"),
+        preserve_root: !args.has("--no-preserve-root"),
+    })
 }
 
+/// cpコマンドのパース関数
+///
+/// 例: `cp -rv src dst` → srcを再帰的にコピーし、コピーしたファイルを表示する
 pub(super) fn parse_cp(args: &[&str]) -> Result<Command> {
-    match args {
-        ["-r", src, dst] => Ok(Command::Cp {
-            source: src.to_string(),
-            destination: dst.to_string(),
-            recursive: true,
-        }),
-        [src, dst] => Ok(Command::Cp {
-            source: src.to_string(),
-            destination: dst.to_string(),
-            recursive: false,
-        }),
-        _ => unreachable!(),
-    }
+    const USAGE: &str = "Usage: cp [-r] [-i] [-v] <source> <destination>";
+
+    let (args, source, destination) = split_transfer_args("cp", args, USAGE)?;
+    Ok(Command::Cp {
+        source: source.to_string(),
+        destination: destination.to_string(),
+        recursive: args.has("-r"),
+        interactive: args.has("-i"),
+        verbose: args.has("-v"),
+    })
 }
 
+/// mvコマンドのパース関数
 pub(super) fn parse_mv(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: mv [-i] [-v] <source> <destination>";
+
+    let (args, source, destination) = split_transfer_args("mv", args, USAGE)?;
     Ok(Command::Mv {
-        source: args[0].to_string(),
-        destination: args[1].to_string(),
+        source: source.to_string(),
+        destination: destination.to_string(),
+        interactive: args.has("-i"),
+        verbose: args.has("-v"),
     })
 }
 
+/// renameコマンドのパース関数
+///
+/// 新しい名前の `*` `?` には、パターンの `*` `?` に一致した部分が順に入る
+///
+/// 例: `rename -n *.jpeg *.jpg` → 拡張子を .jpg に変える予定だけを表示する
+pub(super) fn parse_rename(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: rename [-n] <pattern> <replacement>";
+
+    let args = split_command_flags("rename", args)?;
+    let [pattern, replacement] = args.positionals[..] else {
+        return Err(RucliError::InvalidArgument(format!(
+            "rename: expected a pattern and a replacement\n{USAGE}"
+        )));
+    };
+
+    let wildcards = |word: &str| word.chars().filter(|c| matches!(c, '*' | '?')).count();
+    if wildcards(replacement) > wildcards(pattern) {
+        return Err(RucliError::InvalidArgument(format!(
+            "rename: '{replacement}' has more wildcards than '{pattern}'\n{USAGE}"
+        )));
+    }
+    if replacement.contains('/') {
+        return Err(RucliError::InvalidArgument(format!(
+            "rename: '{replacement}' must be a file name, not a path\n{USAGE}"
+        )));
+    }
+
+    Ok(Command::Rename {
+        pattern: pattern.to_string(),
+        replacement: replacement.to_string(),
+        dry_run: args.has("-n"),
+    })
+}
+
+/// cp / mv の引数をオプションと移動元・移動先に分ける（`-iv` のようにまとめてもよい）
+///
+/// # Errors
+/// - 宣言されていないオプションがある場合
+/// - パスがちょうど2つでない場合
+fn split_transfer_args<'a>(
+    name: &str,
+    args: &[&'a str],
+    usage: &str,
+) -> Result<(SplitArgs<'a>, &'a str, &'a str)> {
+    let args = split_command_flags(name, args)?;
+
+    match args.positionals[..] {
+        [source, destination] => Ok((args, source, destination)),
+        _ => Err(RucliError::InvalidArgument(format!(
+            "{name}: expected a source and a destination\n{usage}"
+        ))),
+    }
+}
+
+/// findコマンドのパース関数
+///
+/// 条件と `--no-ignore`・`-print0` はディレクトリとファイル名の前後どこに置いてもよい
+///
+/// 例: `find logs *.log -mtime -1 -size +1M` → 1日以内に更新された1MiBより大きいログ
 pub(super) fn parse_find(args: &[&str]) -> Result<Command> {
-    match args.len() {
-        1 => Ok(Command::Find {
-            path: None,
-            name: args[0].to_string(),
-        }),
-        2 => Ok(Command::Find {
-            path: Some(args[0].to_string()),
-            name: args[1].to_string(),
-        }),
-        _ => unreachable!(),
+    const USAGE: &str = "Usage: find [directory] <filename> [-mtime [+-]N] [-size [+-]N[k|M|G]] [-newer <file>] [--no-ignore] [-print0]";
+
+    let args = split_command_flags("find", args)?;
+    let (path, name) = match args.positionals[..] {
+        [name] => (None, name),
+        [path, name] => (Some(path.to_string()), name),
+        _ => {
+            return Err(RucliError::InvalidArgument(format!(
+                "find: expected [directory] <filename>\n{USAGE}"
+            )));
+        }
+    };
+
+    let mut filters = Vec::new();
+    for &(flag, value) in &args.flags {
+        let value = value.unwrap_or_default();
+        filters.push(match flag {
+            "-mtime" => FindFilter::ModifiedDays(parse_find_number(flag, value)?),
+            "-size" => {
+                let (number, unit) = match value.char_indices().last() {
+                    Some((i, 'k')) => (&value[..i], 1024),
+                    Some((i, 'M')) => (&value[..i], 1024 * 1024),
+                    Some((i, 'G')) => (&value[..i], 1024 * 1024 * 1024),
+                    _ => (value, 1),
+                };
+                FindFilter::Size {
+                    size: parse_find_number(flag, number)?,
+                    unit,
+                }
+            }
+            "-newer" => FindFilter::Newer(value.to_string()),
+            _ => continue,
+        });
     }
+
+    Ok(Command::Find {
+        path,
+        name: name.to_string(),
+        filters,
+        no_ignore: args.has("--no-ignore"),
+        print0: args.has("-print0"),
+    })
+}
+
+/// findの数値の条件（`+N`・`-N`・`N`）をパースする
+fn parse_find_number(flag: &str, value: &str) -> Result<FindNumber> {
+    let (ordering, digits) = match value.split_at_checked(1) {
+        Some(("+", digits)) => (std::cmp::Ordering::Greater, digits),
+        Some(("-", digits)) => (std::cmp::Ordering::Less, digits),
+        _ => (std::cmp::Ordering::Equal, value),
+    };
+
+    let value = digits.parse().map_err(|_| {
+        RucliError::InvalidArgument(format!("find: {flag}: invalid number '{value}'"))
+    })?;
+    Ok(FindNumber { ordering, value })
 }
 
+/// grepコマンドのパース関数
+///
+/// `-c`（行数だけ表示）と `--stats`（集計を標準エラーに表示）はどこに置いてもよい。
+/// `-` で始まるパターンは `--` の後に置く（`grep -- -v notes.txt`）
+///
+/// 例: `grep -c --stats error a.log b.log` → count=true, stats=true, pattern="error"
 pub(super) fn parse_grep(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: grep [-c] [--stats] <pattern> [file...]";
+
+    let args = split_command_flags("grep", args)?;
+    let Some((pattern, files)) = args.positionals.split_first() else {
+        return Err(RucliError::InvalidArgument(format!(
+            "grep: missing pattern\n{USAGE}"
+        )));
+    };
+
     Ok(Command::Grep {
-        pattern: args[0].to_string(),
-        files: args[1..].iter().map(|f| f.to_string()).collect(),
+        pattern: pattern.to_string(),
+        files: files.iter().map(|f| f.to_string()).collect(),
+        count: args.has("-c"),
+        stats: args.has("--stats"),
+    })
+}
+
+/// duコマンドのパース関数
+///
+/// `-h` `-s` は `-sh` のようにまとめて指定できる
+pub(super) fn parse_du(args: &[&str]) -> Result<Command> {
+    let mut human_readable = false;
+    let mut summarize = false;
+    let mut no_ignore = false;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        if *arg == "--no-ignore" {
+            no_ignore = true;
+            continue;
+        }
+
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'h' => human_readable = true,
+                        's' => summarize = true,
+                        _ => {
+                            return Err(RucliError::InvalidArgument(format!(
+                                "du: invalid option -- '{flag}'\nUsage: du [-h] [-s] [--no-ignore] [path...]"
+                            )));
+                        }
+                    }
+                }
+            }
+            _ => paths.push(arg.to_string()),
+        }
+    }
+
+    Ok(Command::Du {
+        paths,
+        human_readable,
+        summarize,
+        no_ignore,
+    })
+}
+
+/// cutコマンドのパース関数
+///
+/// オプションは `-d:` のように値をつなげても、`-d :` のように分けてもよい
+pub(super) fn parse_cut(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: cut (-f list [-d delim] [-s] | -c list) [file...]";
+    let usage_error = |msg: &str| RucliError::InvalidArgument(format!("cut: {msg}\n{USAGE}"));
+
+    let mut fields = None;
+    let mut characters = None;
+    let mut delimiter = None;
+    let mut only_delimited = false;
+    let mut files = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(&arg) = iter.next() {
+        // -f1 / -f 1 のどちらの形でも値を取り出す
+        let mut value_of = |option: &str| -> Result<String> {
+            match arg.strip_prefix(option) {
+                Some("") => iter.next().map(|value| value.to_string()).ok_or_else(|| {
+                    usage_error(&format!(
+                        "option requires an argument -- '{}'",
+                        &option[1..]
+                    ))
+                }),
+                Some(value) => Ok(value.to_string()),
+                None => unreachable!(),
+            }
+        };
+
+        if arg.starts_with("-f") {
+            fields = Some(parse_cut_list(&value_of("-f")?)?);
+        } else if arg.starts_with("-c") {
+            characters = Some(parse_cut_list(&value_of("-c")?)?);
+        } else if arg.starts_with("-d") {
+            let value = value_of("-d")?;
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => delimiter = Some(ch),
+                _ => return Err(usage_error("the delimiter must be a single character")),
+            }
+        } else if arg == "-s" {
+            only_delimited = true;
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            return Err(usage_error(&format!("invalid option '{arg}'")));
+        } else {
+            files.push(arg.to_string());
+        }
+    }
+
+    let selection = match (fields, characters) {
+        (Some(ranges), None) => CutSelection::Fields(ranges),
+        (None, Some(ranges)) => {
+            if delimiter.is_some() || only_delimited {
+                return Err(usage_error("-d and -s can only be used with -f"));
+            }
+            CutSelection::Characters(ranges)
+        }
+        (Some(_), Some(_)) => return Err(usage_error("only one of -f and -c may be specified")),
+        (None, None) => {
+            return Err(usage_error(
+                "you must specify a list of fields or characters",
+            ));
+        }
+    };
+
+    Ok(Command::Cut {
+        selection,
+        delimiter: delimiter.unwrap_or('\t'),
+        only_delimited,
+        files,
+    })
+}
+
+/// `1,3-5`、`-3`、`4-` 形式の範囲リストを解釈する
+fn parse_cut_list(list: &str) -> Result<Vec<CutRange>> {
+    let invalid = || RucliError::InvalidArgument(format!("cut: invalid list '{list}'"));
+
+    // 1始まりの位置（空ならNone）
+    let position = |value: &str| -> Result<Option<usize>> {
+        if value.is_empty() {
+            return Ok(None);
+        }
+        match value.parse::<usize>() {
+            Ok(position) if position > 0 => Ok(Some(position)),
+            _ => Err(invalid()),
+        }
+    };
+
+    list.split(',')
+        .map(|item| {
+            let range = match item.split_once('-') {
+                Some((start, end)) => CutRange {
+                    start: position(start)?.unwrap_or(1),
+                    end: position(end)?,
+                },
+                None => {
+                    let position = position(item)?.ok_or_else(invalid)?;
+                    CutRange {
+                        start: position,
+                        end: Some(position),
+                    }
+                }
+            };
+
+            // "-" だけの指定や逆順の範囲はエラー
+            if item == "-" || range.end.is_some_and(|end| end < range.start) {
+                return Err(invalid());
+            }
+            Ok(range)
+        })
+        .collect()
+}
+
+/// diffコマンドのパース関数
+pub(super) fn parse_diff(args: &[&str]) -> Result<Command> {
+    let (brief, files) = match args {
+        ["-q", rest @ ..] => (true, rest),
+        _ => (false, args),
+    };
+
+    match files {
+        [file1, file2] => Ok(Command::Diff {
+            file1: file1.to_string(),
+            file2: file2.to_string(),
+            brief,
+        }),
+        _ => Err(RucliError::InvalidArgument(
+            "Usage: diff [-q] <file1> <file2>".to_string(),
+        )),
+    }
+}
+
+/// treeコマンドのパース関数
+pub(super) fn parse_tree(args: &[&str]) -> Result<Command> {
+    const USAGE: &str = "Usage: tree [path] [-L depth] [-a] [--no-ignore]";
+
+    let mut path = None;
+    let mut max_depth = None;
+    let mut show_hidden = false;
+    let mut no_ignore = false;
+    let mut iter = args.iter();
+
+    while let Some(&arg) = iter.next() {
+        match arg {
+            "-a" => show_hidden = true,
+            "--no-ignore" => no_ignore = true,
+            "-L" => {
+                let depth = iter
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|depth| *depth > 0)
+                    .ok_or_else(|| {
+                        RucliError::InvalidArgument(format!(
+                            "tree: -L requires a positive depth\n{USAGE}"
+                        ))
+                    })?;
+                max_depth = Some(depth);
+            }
+            _ if arg.starts_with('-') => {
+                return Err(RucliError::InvalidArgument(format!(
+                    "tree: invalid option '{arg}'\n{USAGE}"
+                )));
+            }
+            _ if path.is_none() => path = Some(arg.to_string()),
+            _ => {
+                return Err(RucliError::InvalidArgument(format!(
+                    "tree: too many paths\n{USAGE}"
+                )));
+            }
+        }
+    }
+
+    Ok(Command::Tree {
+        path,
+        max_depth,
+        show_hidden,
+        no_ignore,
     })
 }
 
@@ -91,25 +453,189 @@ pub(super) fn parse_grep(args: &[&str]) -> Result<Command> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_du_flags() {
+        let result = parse_du(&[]);
+        assert!(
+            matches!(result, Ok(Command::Du { paths, human_readable: false, summarize: false, no_ignore: false }) if paths.is_empty())
+        );
+
+        let result = parse_du(&["-sh", "dir"]);
+        assert!(
+            matches!(result, Ok(Command::Du { paths, human_readable: true, summarize: true, .. }) if paths == vec!["dir"])
+        );
+
+        let result = parse_du(&["-h", "a", "b"]);
+        assert!(
+            matches!(result, Ok(Command::Du { paths, human_readable: true, summarize: false, .. }) if paths.len() == 2)
+        );
+
+        let result = parse_du(&["--no-ignore", "dir"]);
+        assert!(
+            matches!(result, Ok(Command::Du { paths, no_ignore: true, .. }) if paths == vec!["dir"])
+        );
+
+        assert!(parse_du(&["-x"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_cut_fields() {
+        let result = parse_cut(&["-d:", "-f1,3-5"]);
+        match result {
+            Ok(Command::Cut {
+                selection: CutSelection::Fields(ranges),
+                delimiter,
+                only_delimited,
+                files,
+            }) => {
+                assert_eq!(delimiter, ':');
+                assert!(!only_delimited);
+                assert!(files.is_empty());
+                assert_eq!(
+                    ranges,
+                    vec![
+                        CutRange {
+                            start: 1,
+                            end: Some(1)
+                        },
+                        CutRange {
+                            start: 3,
+                            end: Some(5)
+                        },
+                    ]
+                );
+            }
+            _ => panic!("Expected Cut with fields"),
+        }
+
+        // 値を分けて書く形と、開いた範囲
+        let result = parse_cut(&["-f", "-2,4-", "-d", ",", "-s", "data.csv"]);
+        match result {
+            Ok(Command::Cut {
+                selection: CutSelection::Fields(ranges),
+                delimiter: ',',
+                only_delimited: true,
+                files,
+            }) => {
+                assert_eq!(files, vec!["data.csv"]);
+                assert_eq!(
+                    ranges,
+                    vec![
+                        CutRange {
+                            start: 1,
+                            end: Some(2)
+                        },
+                        CutRange {
+                            start: 4,
+                            end: None
+                        },
+                    ]
+                );
+            }
+            _ => panic!("Expected Cut with open ranges"),
+        }
+    }
+
+    #[test]
+    fn test_parse_cut_characters_and_errors() {
+        let result = parse_cut(&["-c1-8"]);
+        assert!(matches!(
+            result,
+            Ok(Command::Cut {
+                selection: CutSelection::Characters(_),
+                delimiter: '\t',
+                ..
+            })
+        ));
+
+        assert!(parse_cut(&["file.txt"]).is_err()); // -f/-c がない
+        assert!(parse_cut(&["-f1", "-c1"]).is_err()); // 両方指定
+        assert!(parse_cut(&["-c1", "-d:"]).is_err()); // -c に -d
+        assert!(parse_cut(&["-f0"]).is_err()); // 0は不正
+        assert!(parse_cut(&["-f3-1"]).is_err()); // 逆順
+        assert!(parse_cut(&["-fa"]).is_err());
+        assert!(parse_cut(&["-d::", "-f1"]).is_err()); // 区切りは一文字
+        assert!(parse_cut(&["-f"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_diff() {
+        let result = parse_diff(&["a.txt", "b.txt"]);
+        assert!(
+            matches!(result, Ok(Command::Diff { file1, file2, brief: false }) if file1 == "a.txt" && file2 == "b.txt")
+        );
+
+        let result = parse_diff(&["-q", "a.txt", "b.txt"]);
+        assert!(matches!(result, Ok(Command::Diff { brief: true, .. })));
+
+        assert!(parse_diff(&["a.txt", "b.txt", "c.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_tree_options() {
+        let result = parse_tree(&[]);
+        assert!(matches!(
+            result,
+            Ok(Command::Tree {
+                path: None,
+                max_depth: None,
+                show_hidden: false,
+                no_ignore: false
+            })
+        ));
+
+        let result = parse_tree(&["src", "-L", "2", "-a"]);
+        assert!(
+            matches!(result, Ok(Command::Tree { path: Some(p), max_depth: Some(2), show_hidden: true, .. }) if p == "src")
+        );
+
+        let result = parse_tree(&["--no-ignore"]);
+        assert!(matches!(
+            result,
+            Ok(Command::Tree {
+                no_ignore: true,
+                ..
+            })
+        ));
+
+        assert!(parse_tree(&["-L"]).is_err());
+        assert!(parse_tree(&["-L", "0"]).is_err());
+        assert!(parse_tree(&["a", "b"]).is_err());
+    }
+
     #[test]
     fn test_parse_mkdir_simple() {
         let result = parse_mkdir(&["testdir"]);
-        assert!(matches!(result, Ok(Command::Mkdir { path, parents: false }) if path == "testdir"));
+        assert!(
+            matches!(result, Ok(Command::Mkdir { paths, parents: false, mode: None }) if paths == vec!["testdir"])
+        );
     }
 
     #[test]
     fn test_parse_mkdir_with_parents() {
         let result = parse_mkdir(&["-p", "path/to/dir"]);
         assert!(
-            matches!(result, Ok(Command::Mkdir { path, parents: true }) if path == "path/to/dir")
+            matches!(result, Ok(Command::Mkdir { paths, parents: true, mode: None }) if paths == vec!["path/to/dir"])
+        );
+    }
+
+    #[test]
+    fn test_parse_mkdir_with_mode() {
+        let result = parse_mkdir(&["-p", "-m", "700", "a/b"]);
+        assert!(
+            matches!(result, Ok(Command::Mkdir { paths, parents: true, mode: Some(0o700) }) if paths == vec!["a/b"])
         );
+
+        assert!(parse_mkdir(&["-m", "800", "dir"]).is_err());
+        assert!(parse_mkdir(&["-m", "755"]).is_err());
+        assert!(parse_mkdir(&["-m"]).is_err());
     }
 
     #[test]
     fn test_parse_rm_simple() {
         let result = parse_rm(&["file.txt"]);
         assert!(
-            matches!(result, Ok(Command::Rm { path, recursive: false, force: false }) if path == "file.txt")
+            matches!(result, Ok(Command::Rm { path, recursive: false, force: false, preserve_root: true }) if path == "file.txt")
         );
     }
 
@@ -117,7 +643,7 @@ mod tests {
     fn test_parse_rm_recursive() {
         let result = parse_rm(&["-r", "dir"]);
         assert!(
-            matches!(result, Ok(Command::Rm { path, recursive: true, force: false }) if path == "dir")
+            matches!(result, Ok(Command::Rm { path, recursive: true, force: false, .. }) if path == "dir")
         );
     }
 
@@ -125,7 +651,7 @@ mod tests {
     fn test_parse_rm_force() {
         let result = parse_rm(&["-f", "file"]);
         assert!(
-            matches!(result, Ok(Command::Rm { path, recursive: false, force: true }) if path == "file")
+            matches!(result, Ok(Command::Rm { path, recursive: false, force: true, .. }) if path == "file")
         );
     }
 
@@ -133,13 +659,58 @@ mod tests {
     fn test_parse_rm_recursive_force() {
         let result = parse_rm(&["-rf", "dir"]);
         assert!(
-            matches!(result, Ok(Command::Rm { path, recursive: true, force: true }) if path == "dir")
+            matches!(result, Ok(Command::Rm { path, recursive: true, force: true, .. }) if path == "dir")
         );
 
         let result2 = parse_rm(&["-fr", "dir"]);
         assert!(
-            matches!(result2, Ok(Command::Rm { path, recursive: true, force: true }) if path == "dir")
+            matches!(result2, Ok(Command::Rm { path, recursive: true, force: true, .. }) if path == "dir")
+        );
+    }
+
+    #[test]
+    fn test_parse_rm_no_preserve_root() {
+        let result = parse_rm(&["-r", "-f", "--no-preserve-root", "dir"]);
+        assert!(
+            matches!(result, Ok(Command::Rm { path, recursive: true, force: true, preserve_root: false }) if path == "dir")
+        );
+
+        assert!(parse_rm(&["-x", "dir"]).is_err());
+        assert!(parse_rm(&["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rename() {
+        let result = parse_rename(&["*.jpeg", "*.jpg", "-n"]);
+        assert!(
+            matches!(result, Ok(Command::Rename { pattern, replacement, dry_run: true })
+            if pattern == "*.jpeg" && replacement == "*.jpg")
+        );
+
+        // 新しい名前のワイルドカードが多すぎる、パスを指定した、引数が足りない
+        let err = parse_rename(&["*.jpeg", "*_*.jpg"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("'*_*.jpg' has more wildcards than '*.jpeg'")
+        );
+        assert!(parse_rename(&["*.jpeg", "old/*.jpg"]).is_err());
+        assert!(parse_rename(&["*.jpeg"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_rm_flags_after_path() {
+        let result = parse_rm(&["dir", "-r"]);
+        assert!(
+            matches!(result, Ok(Command::Rm { path, recursive: true, force: false, .. }) if path == "dir")
+        );
+
+        let result = parse_rm(&["dir", "-rf", "--no-preserve-root"]);
+        assert!(
+            matches!(result, Ok(Command::Rm { path, recursive: true, force: true, preserve_root: false }) if path == "dir")
         );
+
+        let err = parse_rm(&["dir", "-rq"]).unwrap_err();
+        assert!(err.to_string().contains("rm: unknown option '-rq'"));
     }
 
     #[test]
@@ -150,6 +721,8 @@ mod tests {
                 source,
                 destination,
                 recursive: false,
+                interactive: false,
+                verbose: false,
             }) => {
                 assert_eq!(source, "src.txt");
                 assert_eq!(destination, "dst.txt");
@@ -166,6 +739,7 @@ mod tests {
                 source,
                 destination,
                 recursive: true,
+                ..
             }) => {
                 assert_eq!(source, "srcdir");
                 assert_eq!(destination, "dstdir");
@@ -181,6 +755,8 @@ mod tests {
             Ok(Command::Mv {
                 source,
                 destination,
+                interactive: false,
+                verbose: false,
             }) => {
                 assert_eq!(source, "old.txt");
                 assert_eq!(destination, "new.txt");
@@ -189,17 +765,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_cp_and_mv_flags() {
+        assert!(matches!(
+            parse_cp(&["-rv", "src", "-i", "dst"]),
+            Ok(Command::Cp {
+                recursive: true,
+                interactive: true,
+                verbose: true,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_mv(&["-i", "a", "b"]),
+            Ok(Command::Mv {
+                interactive: true,
+                verbose: false,
+                ..
+            })
+        ));
+
+        // mv に -r はなく、パスはちょうど2つ
+        assert!(parse_mv(&["-r", "a", "b"]).is_err());
+        assert!(parse_cp(&["-r", "a", "b", "c"]).is_err());
+        assert!(parse_cp(&["-v", "a"]).is_err());
+    }
+
     #[test]
     fn test_parse_find_current_dir() {
         let result = parse_find(&["*.txt"]);
-        assert!(matches!(result, Ok(Command::Find { path: None, name }) if name == "*.txt"));
+        assert!(matches!(result, Ok(Command::Find { path: None, name, .. }) if name == "*.txt"));
     }
 
     #[test]
     fn test_parse_find_with_path() {
         let result = parse_find(&["/home", "*.log"]);
         match result {
-            Ok(Command::Find { path, name }) => {
+            Ok(Command::Find { path, name, .. }) => {
                 assert_eq!(path, Some("/home".to_string()));
                 assert_eq!(name, "*.log");
             }
@@ -207,11 +809,79 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_find_filters() {
+        use std::cmp::Ordering;
+
+        let result = parse_find(&[
+            ".", "*.log", "-mtime", "-1", "-size", "+1M", "-newer", "ref",
+        ]);
+        match result {
+            Ok(Command::Find {
+                path,
+                name,
+                filters,
+                no_ignore: false,
+                print0: false,
+            }) => {
+                assert_eq!(path, Some(".".to_string()));
+                assert_eq!(name, "*.log");
+                assert_eq!(
+                    filters,
+                    vec![
+                        FindFilter::ModifiedDays(FindNumber {
+                            ordering: Ordering::Less,
+                            value: 1
+                        }),
+                        FindFilter::Size {
+                            size: FindNumber {
+                                ordering: Ordering::Greater,
+                                value: 1
+                            },
+                            unit: 1024 * 1024,
+                        },
+                        FindFilter::Newer("ref".to_string()),
+                    ]
+                );
+            }
+            _ => panic!("Expected Find command with filters"),
+        }
+
+        // --no-ignore はどこに置いてもよい
+        let result = parse_find(&["--no-ignore", ".", "*.rs"]);
+        assert!(
+            matches!(result, Ok(Command::Find { path: Some(p), no_ignore: true, .. }) if p == ".")
+        );
+
+        let result = parse_find(&["-print0", "*.txt"]);
+        assert!(matches!(
+            result,
+            Ok(Command::Find {
+                path: None,
+                print0: true,
+                ..
+            })
+        ));
+
+        // 条件をディレクトリとファイル名より前に置いてもよい
+        let result = parse_find(&["-newer", "ref", "logs", "*.log"]);
+        assert!(
+            matches!(result, Ok(Command::Find { path: Some(p), name, filters, .. })
+            if p == "logs" && name == "*.log" && filters == [FindFilter::Newer("ref".to_string())])
+        );
+
+        // 条件だけでファイル名がない、値がない、数値でない、未知の条件
+        assert!(parse_find(&["-mtime", "1"]).is_err());
+        assert!(parse_find(&["*", "-size"]).is_err());
+        assert!(parse_find(&["*", "-size", "+xM"]).is_err());
+        assert!(parse_find(&["*", "-perm", "644"]).is_err());
+    }
+
     #[test]
     fn test_parse_grep_single_file() {
         let result = parse_grep(&["pattern", "file.txt"]);
         match result {
-            Ok(Command::Grep { pattern, files }) => {
+            Ok(Command::Grep { pattern, files, .. }) => {
                 assert_eq!(pattern, "pattern");
                 assert_eq!(files, vec!["file.txt"]);
             }
@@ -223,11 +893,41 @@ mod tests {
     fn test_parse_grep_multiple_files() {
         let result = parse_grep(&["error", "log1.txt", "log2.txt", "log3.txt"]);
         match result {
-            Ok(Command::Grep { pattern, files }) => {
+            Ok(Command::Grep { pattern, files, .. }) => {
                 assert_eq!(pattern, "error");
                 assert_eq!(files, vec!["log1.txt", "log2.txt", "log3.txt"]);
             }
             _ => panic!("Expected Grep command"),
         }
     }
+
+    #[test]
+    fn test_parse_grep_flags() {
+        let result = parse_grep(&["--stats", "-c", "error", "a.log"]);
+        assert!(
+            matches!(result, Ok(Command::Grep { pattern, files, count: true, stats: true })
+            if pattern == "error" && files == ["a.log"])
+        );
+
+        // オプションはパターンやファイルの後ろにも置ける
+        let result = parse_grep(&["error", "a.log", "-c"]);
+        assert!(
+            matches!(result, Ok(Command::Grep { pattern, files, count: true, stats: false })
+            if pattern == "error" && files == ["a.log"])
+        );
+
+        // 知らないオプションはパターンにせずエラー、- で始まるパターンは -- の後
+        let err = parse_grep(&["-q", "foo", "a.log"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "argument error: grep: unknown option '-q'\nUsage: grep [-c] [--stats] <pattern> [file...]"
+        );
+        let result = parse_grep(&["-c", "--", "-x", "-c.txt"]);
+        assert!(
+            matches!(result, Ok(Command::Grep { pattern, files, count: true, stats: false })
+            if pattern == "-x" && files == ["-c.txt"])
+        );
+
+        assert!(parse_grep(&["--stats"]).is_err());
+    }
 }
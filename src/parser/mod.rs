@@ -5,31 +5,51 @@ mod control;
 pub mod expansion;
 mod file_ops;
 mod operators;
+mod tokenizer;
 mod utils;
 
 // Re-export public items
 pub use self::operators::{contains_heredoc, parse_heredoc_header, split_by_pipe};
+pub use self::tokenizer::{
+    ends_with_line_continuation, is_quoted_word, mask_nested, split_quoted_words, strip_comment,
+};
 pub use self::utils::{DEFAULT_HOME_INDICATOR, PREVIOUS_DIR_INDICATOR};
 
-use crate::alias::get_alias;
-use crate::commands::Command;
-use crate::environment::expand_command_substitution;
+use crate::alias::with_alias_expanded;
+use crate::commands::{Command, Redirection};
+use crate::environment::{
+    expand_command_substitution, expand_word_substitution, substitution_deferred,
+    without_command_substitution,
+};
 use crate::error::{Result, RucliError};
 use crate::functions;
+use crate::nesting::nested;
+use expansion::{expand_braces, expand_tilde};
 use log::debug;
+use std::cell::Cell;
 
 // Internal imports
 use self::basic::*;
 use self::control::*;
 use self::file_ops::*;
 use self::operators::*;
+use self::tokenizer::{split_group, split_words};
 use self::utils::*;
 
+thread_local! {
+    /// 組み込みでないコマンドもパースするときに解決するか
+    static RESOLVING: Cell<bool> = const { Cell::new(false) };
+}
+
 /// ユーザー入力をコマンドに変換する
 ///
+/// 組み込みでも定義済みの関数でもないコマンドは `Command::Unresolved` にし、実行するときに
+/// `parse_resolved` で解決する（前の文の alias・eval・関数定義で定義したものも使える）。
+///
+/// 入力が空のとき（コマンド置換の結果が空になった場合を含む）は、何もしない空のコマンド列を返す
+///
 /// # Errors
 ///
-/// - 空入力の場合
 /// - 存在しないコマンドの場合
 /// - 引数の数が不正な場合
 pub fn parse_command(input: &str) -> Result<Command> {
@@ -37,58 +57,81 @@ pub fn parse_command(input: &str) -> Result<Command> {
 
     // "&"があるかチェック
     if contains_background(input) {
-        // "&"を除いた部分をパース
-        let cmd_without_bg = input.trim_end().trim_end_matches('&').trim();
+        // 末尾の"&"を除いた部分をパース
+        let cmd_without_bg = input.trim_end();
+        let cmd_without_bg = cmd_without_bg[..cmd_without_bg.len() - 1].trim();
         let inner_cmd = parse_command(cmd_without_bg)?;
 
         return Ok(Command::Background {
             command: Box::new(inner_cmd),
+            text: cmd_without_bg.to_string(),
+            stopped: false,
         });
     }
 
-    // コマンド置換を追加
-    let substituted_input = expand_command_substitution(input)?;
-
-    let input = substituted_input.as_str();
+    // "&?" は停止した状態のジョブを作る（bg / fg で実行を始める）
+    if let Some(cmd_without_bg) = strip_stopped_background(input) {
+        return Ok(Command::Background {
+            command: Box::new(parse_command(cmd_without_bg)?),
+            text: cmd_without_bg.to_string(),
+            stopped: true,
+        });
+    }
 
-    let parts: Vec<&str> = input.split_whitespace().collect();
+    // 行の残り全体（パイプライン、ループ、リダイレクトを含む）の実行時間を計測する
+    // コマンド置換は中のコマンドをパースするときに展開する
+    if let Some(rest) = strip_time(input) {
+        if rest.is_empty() {
+            return Err(RucliError::ParseError(
+                "syntax error: command expected after 'time'".to_string(),
+            ));
+        }
 
-    let cmd_name = parts[0];
-    let args = &parts[1..];
+        return Ok(Command::Time {
+            command: Box::new(parse_command(rest)?),
+        });
+    }
 
-    // エイリアス展開部分
-    let alias = get_alias(cmd_name);
-    let cmd_name = if cmd_name != "alias" {
-        debug!("alias succeed");
-        alias.as_deref().unwrap_or(cmd_name)
-    } else {
-        debug!("no alias");
-        cmd_name
-    };
+    // セミコロンで区切られた複数のコマンド（制御構文の中のセミコロンでは区切らない）
+    // 例: "echo start; for i in 1 2; do echo $i; done; echo end" → 3つのコマンド
+    if split_by_semicolon(input).len() > 1 {
+        return parse_multiple_commands(input);
+    }
 
-    // ifのチェック
+    // 制御構文の中のコマンド置換はパース時に行わず、実行時に変数と一緒に展開する
+    // （ループ変数や関数の引数を置換の中で参照できるように）
     if contains_if(input) {
-        return parse_if_statement(input);
+        return without_command_substitution(|| parse_if_statement(input));
     }
-
-    // whileのチェック
     if contains_while(input) {
-        return parse_while_statement(input);
+        return without_command_substitution(|| parse_while_statement(input));
     }
-
-    // forのチェック
     if contains_for(input) {
-        return parse_for_statement(input);
+        return without_command_substitution(|| parse_for_statement(input));
     }
-
-    // functionのチェック
     if contains_function(input) {
-        return parse_function_definition(input);
+        return without_command_substitution(|| parse_function_definition(input));
     }
 
-    // セミコロンを含むかチェック
-    if split_by_semicolon(input).len() > 1 {
-        return parse_multiple_commands(input);
+    // 空の入力は何もしない
+    // コマンド置換はパイプ・リダイレクト・代入を分けた後に語ごとに行う
+    let Some(cmd_name) = input.split_whitespace().next() else {
+        return Ok(Command::Compound {
+            commands: Vec::new(),
+        });
+    };
+
+    // 否定（パイプライン全体の終了ステータスを反転する）
+    if let Some(rest) = strip_negation(input) {
+        if rest.is_empty() {
+            return Err(RucliError::ParseError(
+                "syntax error: command expected after '!'".to_string(),
+            ));
+        }
+
+        return Ok(Command::Not {
+            command: Box::new(parse_command(rest)?),
+        });
     }
 
     // まずパイプで分割
@@ -101,27 +144,27 @@ pub fn parse_command(input: &str) -> Result<Command> {
         // 最後のコマンドを特別処理
         let last_part = pipe_parts[last_index];
         if contains_redirect(last_part) {
-            let (cmd_str, redirect_opt) = split_redirect(last_part);
+            let (cmd_str, redirects) = split_redirects(last_part);
+            let redirects = substitute_redirect_targets(redirects)?;
 
             // 最後以外のコマンドを追加
-            for pipe_part in pipe_parts.iter().take(last_index) {
-                commands.push(pipe_part.to_string());
+            for (i, pipe_part) in pipe_parts.iter().take(last_index).enumerate() {
+                commands.push(parse_pipeline_stage(i + 1, pipe_part)?);
             }
             // 最後のコマンド（リダイレクトなし）を追加
-            commands.push(cmd_str);
+            commands.push(parse_pipeline_stage(pipe_parts.len(), &cmd_str)?);
 
-            if let Some((redirect_type, target)) = redirect_opt {
+            if !redirects.is_empty() {
                 // パイプライン全体をリダイレクト
                 return Ok(Command::Redirect {
                     command: Box::new(Command::Pipeline { commands }),
-                    redirect_type,
-                    target,
+                    redirects,
                 });
             }
         } else {
             // リダイレクトなしの通常のパイプライン
-            for part in pipe_parts {
-                commands.push(part.to_string());
+            for (i, part) in pipe_parts.iter().enumerate() {
+                commands.push(parse_pipeline_stage(i + 1, part)?);
             }
         }
 
@@ -130,19 +173,86 @@ pub fn parse_command(input: &str) -> Result<Command> {
 
     // リダイレクトのみ
     if contains_redirect(input) {
-        let (cmd_str, redirect_opt) = split_redirect(input);
+        let (cmd_str, redirects) = split_redirects(input);
+        let redirects = substitute_redirect_targets(redirects)?;
 
-        if let Some((redirect_type, target)) = redirect_opt {
+        if !redirects.is_empty() {
             let inner_command = parse_command(&cmd_str)?;
 
+            // execのリダイレクトは置き換えるコマンドまたはシェル自身に適用する
+            if let Command::Exec { args, .. } = inner_command {
+                return Ok(Command::Exec { args, redirects });
+            }
+
             return Ok(Command::Redirect {
                 command: Box::new(inner_command),
-                redirect_type,
-                target,
+                redirects,
             });
         }
     }
 
+    // グループ（サブシェル・波括弧）
+    if let Some((opener, body)) = split_group(input) {
+        return parse_group(opener, body);
+    }
+
+    // エイリアス展開（コマンドごとに先頭の語だけを置き換え、改めてパースする）
+    if let Some(result) = with_alias_expanded(cmd_name, |value| {
+        debug!("Expanding alias '{cmd_name}' to '{value}'");
+        let rest = &input.trim_start()[cmd_name.len()..];
+        nested("alias expansion", || {
+            parse_command(&format!("{value}{rest}"))
+        })
+    }) {
+        return result;
+    }
+
+    // 組み込みでも定義済みの関数でもないコマンドは、実行するときに改めて解決する
+    // （コマンド名に置換や変数があるときは、置換した名前でパースするときに解決する）
+    if !RESOLVING.with(Cell::get)
+        && find_command(cmd_name).is_none()
+        && !functions::is_function(cmd_name)
+        && !is_assignment_word(cmd_name)
+        && !cmd_name.contains(['$', '`'])
+    {
+        return Ok(Command::Unresolved {
+            text: input.trim().to_string(),
+        });
+    }
+
+    // 変数代入（NAME=value）
+    if is_assignment_word(cmd_name) && find_command(cmd_name).is_none() {
+        return parse_assignment(input);
+    }
+
+    // ブレース展開（{a,b} や {1..3} を複数の引数にする）とチルダ展開
+    // 引数はクォートとコマンド置換の中の空白では区切らない
+    let expanded = expand_tilde(&expand_braces(input));
+
+    // コマンド置換は語ごとに行い、結果は構文として解釈しない（`|` や `>` もただの引数になる）
+    // クォートの中の結果は一つの引数に、外の結果は空白で区切った複数の引数になる
+    let words = split_words(&expanded)
+        .into_iter()
+        .map(|word| {
+            if substitution_deferred() {
+                Ok(vec![word.to_string()])
+            } else {
+                expand_word_substitution(word)
+            }
+        })
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+
+    // `$(cd .)` のように展開するとコマンドがなくなる場合は何もしない
+    let Some((cmd_name, args)) = words.split_first() else {
+        return Ok(Command::Compound {
+            commands: Vec::new(),
+        });
+    };
+    let cmd_name = cmd_name.as_str();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let args = args.as_slice();
+
     // 引数の数チェック
     if let Some(cmd_info) = find_command(cmd_name) {
         validate_args(cmd_info, args)?;
@@ -157,28 +267,53 @@ pub fn parse_command(input: &str) -> Result<Command> {
 
     match cmd_name {
         "help" => Ok(Command::Help),
+        "clear" => Ok(Command::Clear),
         "version" => Ok(Command::Version),
         "pwd" => Ok(Command::Pwd),
-        "ls" => Ok(Command::Ls),
-        "jobs" => Ok(Command::Jobs),
-        "exit" | "quit" => Ok(Command::Exit),
+        "paste" => Ok(Command::Paste),
+        "ls" => parse_ls(args),
+        "jobs" => parse_jobs(args),
+        "exit" | "quit" => parse_exit(args),
 
         "history" => parse_history(args),
+        "fc" => parse_fc(args),
         "echo" => parse_echo(args),
         "cat" => parse_cat(args),
         "write" => parse_write(args),
         "repeat" => parse_repeat(args),
         "cd" => parse_cd(args),
+        "pushd" | "popd" | "dirs" => parse_dir_stack(cmd_name, args),
         "mkdir" => parse_mkdir(args),
         "rm" => parse_rm(args),
         "cp" => parse_cp(args),
         "mv" => parse_mv(args),
+        "rename" => parse_rename(args),
         "find" => parse_find(args),
         "grep" => parse_grep(args),
+        "du" => parse_du(args),
+        "tree" => parse_tree(args),
+        "diff" => parse_diff(args),
+        "cut" => parse_cut(args),
+        "tr" => parse_tr(args),
         "alias" => parse_alias(args),
         "sleep" => parse_sleep(args),
         "fg" => parse_fg(args),
+        "bg" => parse_bg(args),
         "env" => parse_environment(args),
+        "export" => parse_export(args),
+        "let" => parse_let(args),
+        "read" => parse_read(args),
+        "seq" => parse_seq(args),
+        "xargs" => parse_xargs(args),
+        "set" => parse_set_command(args),
+        "exec" => Ok(parse_exec(args)),
+        "eval" => Ok(parse_eval(args)),
+        "umask" => parse_umask(args),
+        "trap" => parse_trap(args),
+        "type" | "which" => Ok(Command::Type {
+            names: args.iter().map(|s| s.to_string()).collect(),
+            external_only: cmd_name == "which",
+        }),
 
         _ => {
             if functions::is_function(cmd_name) {
@@ -187,8 +322,21 @@ pub fn parse_command(input: &str) -> Result<Command> {
                     args: args.iter().map(|s| s.to_string()).collect(),
                 })
             } else {
+                // 近い名前があれば提案する
+                let suggestions = suggest_commands(cmd_name);
+                let hint = match suggestions.as_slice() {
+                    [] => String::new(),
+                    names => format!(
+                        "\nDid you mean {}?",
+                        names
+                            .iter()
+                            .map(|name| format!("'{name}'"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                };
                 Err(RucliError::UnknownCommand(format!(
-                    "{} {}",
+                    "{} {}{hint}",
                     cmd_name,
                     args.join(" ")
                 )))
@@ -197,16 +345,242 @@ pub fn parse_command(input: &str) -> Result<Command> {
     }
 }
 
+/// エイリアスと関数を現在の定義で解決してパースする
+///
+/// `Command::Unresolved` を実行するときと、実行せずにスクリプトをパースするとき
+/// （存在しないコマンドを診断するため）に使う
+///
+/// # Errors
+///
+/// - 存在しないコマンドの場合
+/// - 引数の数が不正な場合
+pub fn parse_resolved(input: &str) -> Result<Command> {
+    let previous = RESOLVING.with(|resolving| resolving.replace(true));
+    let result = parse_command(input);
+    RESOLVING.with(|resolving| resolving.set(previous));
+    result
+}
+
+/// リダイレクト先のコマンド置換を行う（結果は語に分けない）
+///
+/// 実行時に展開するコマンドの中ではそのまま残す
+fn substitute_redirect_targets(redirects: Vec<Redirection>) -> Result<Vec<Redirection>> {
+    if substitution_deferred() {
+        return Ok(redirects);
+    }
+
+    redirects
+        .into_iter()
+        .map(|redirect| {
+            Ok(Redirection {
+                target: expand_command_substitution(&redirect.target)?,
+                ..redirect
+            })
+        })
+        .collect()
+}
+
+/// パイプラインの一つの段をパースする
+///
+/// 存在しないコマンドなどは実行を始める前に、段の番号を付けたエラーにする
+///
+/// # Arguments
+/// * `stage` - 1から数えた段の番号
+/// * `input` - 段のコマンド文字列
+fn parse_pipeline_stage(stage: usize, input: &str) -> Result<Command> {
+    parse_command(input).map_err(|e| RucliError::PipelineStage {
+        stage,
+        command: input.trim().to_string(),
+        source: Box::new(e),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn test_parse_command_expanding_to_nothing() {
+        // コマンド置換の結果が空ならパニックせず、空のコマンド列になる
+        for input in ["$(echo -n)", "`echo -n`", "  "] {
+            assert!(
+                matches!(parse_command(input), Ok(Command::Compound { commands }) if commands.is_empty()),
+                "{input}"
+            );
+        }
+    }
+
     #[test]
     fn test_parse_command_unknown() {
-        // 不明なコマンドのエラー
-        let result = parse_command("abc input");
+        // 不明なコマンドのエラー（解決するとき）
+        let result = parse_resolved("abc input");
         assert!(result.is_err())
     }
 
+    #[test]
+    fn test_parse_assignment() {
+        // 単独の代入は不明なコマンドにならない
+        let result = parse_command("FOO=bar");
+        assert!(
+            matches!(result, Ok(Command::Assignment { assignments, command: None })
+            if assignments == vec![("FOO".to_string(), "bar".to_string())])
+        );
+
+        // 複数の代入とクォート付きの値
+        let result = parse_command(r#"A=1 MSG="hello world""#);
+        assert!(
+            matches!(result, Ok(Command::Assignment { assignments, command: None })
+            if assignments == vec![
+                ("A".to_string(), "1".to_string()),
+                ("MSG".to_string(), "hello world".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_assignment_prefix() {
+        let result = parse_command("FOO=bar echo hello");
+        match result {
+            Ok(Command::Assignment {
+                assignments,
+                command: Some(command),
+            }) => {
+                assert_eq!(assignments, vec![("FOO".to_string(), "bar".to_string())]);
+                assert!(matches!(*command, Command::Echo { .. }));
+            }
+            _ => panic!("Expected Assignment with command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_negation() {
+        // パイプライン全体を否定する
+        match parse_command("! echo hi | grep x") {
+            Ok(Command::Not { command }) => {
+                assert!(matches!(*command, Command::Pipeline { .. }))
+            }
+            _ => panic!("Expected Not with pipeline"),
+        }
+
+        // 二重否定
+        match parse_command("! ! echo hi") {
+            Ok(Command::Not { command }) => {
+                assert!(matches!(*command, Command::Not { .. }))
+            }
+            _ => panic!("Expected nested Not"),
+        }
+
+        assert!(parse_command("!").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        // パイプラインとリダイレクトをまとめて計測する
+        match parse_command("time cat big | grep x > out") {
+            Ok(Command::Time { command }) => {
+                assert!(matches!(*command, Command::Redirect { .. }))
+            }
+            _ => panic!("Expected Time with redirect"),
+        }
+
+        match parse_command("time for i in 1 2; do echo $i; done") {
+            Ok(Command::Time { command }) => assert!(matches!(*command, Command::For { .. })),
+            _ => panic!("Expected Time with for loop"),
+        }
+
+        assert!(parse_command("time").is_err());
+    }
+
+    #[test]
+    fn test_parse_groups() {
+        match parse_command("(cd /tmp; pwd)") {
+            Ok(Command::Subshell { command }) => {
+                assert!(
+                    matches!(*command, Command::Compound { ref commands } if commands.len() == 2)
+                )
+            }
+            _ => panic!("Expected Subshell"),
+        }
+
+        // 波括弧は現在の環境で実行する複合コマンド
+        let result = parse_command("{ echo a; echo b; }");
+        assert!(matches!(result, Ok(Command::Compound { commands }) if commands.len() == 2));
+
+        // グループ全体のリダイレクト・バックグラウンド
+        let result = parse_command("{ echo a; echo b; } > both.txt");
+        assert!(matches!(result, Ok(Command::Redirect { ref command, .. })
+            if matches!(**command, Command::Compound { .. })));
+        let result = parse_command("(sleep 1; echo done) &");
+        assert!(
+            matches!(result, Ok(Command::Background { ref command, ref text, .. })
+            if matches!(**command, Command::Subshell { .. }) && text == "(sleep 1; echo done)")
+        );
+
+        assert!(parse_command("(echo a").is_err());
+        assert!(parse_command("{ echo a").is_err());
+        assert!(parse_command("( )").is_err());
+    }
+
+    #[test]
+    fn test_parse_exec() {
+        // 引数はクォートを外して渡す
+        let result = parse_command(r#"exec sh -c "echo hi""#);
+        assert!(matches!(result, Ok(Command::Exec { args, redirects })
+            if args == ["sh", "-c", "echo hi"] && redirects.is_empty()));
+
+        // リダイレクトはexec自身に付ける
+        let result = parse_command("exec > log.txt");
+        assert!(matches!(result, Ok(Command::Exec { args, redirects })
+            if args.is_empty() && redirects.len() == 1 && redirects[0].target == "log.txt"));
+    }
+
+    #[test]
+    fn test_parse_commands_around_control_structures() {
+        let result = parse_command("echo start; for i in 1 2; do echo $i; done; echo end");
+        let Ok(Command::Compound { commands }) = result else {
+            panic!("Expected Compound command");
+        };
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(&commands[0], Command::Echo { message, .. } if message == "start"));
+        assert!(matches!(&commands[1], Command::For { items, .. } if items == &["1", "2"]));
+        assert!(matches!(&commands[2], Command::Echo { message, .. } if message == "end"));
+
+        // 制御構文で始まる行も、後ろのコマンドを落とさない
+        let result = parse_command("if cat a; then echo yes; fi; echo end");
+        assert!(matches!(result, Ok(Command::Compound { commands })
+            if matches!(commands.as_slice(), [Command::If { .. }, Command::Echo { .. }])));
+
+        // 制御構文の途中で行が終わる
+        let result = parse_command("echo start; for i in 1 2; do echo $i");
+        assert!(result.unwrap_err().to_string().contains("'done' not found"));
+    }
+
+    #[test]
+    fn test_parse_defers_resolving_unknown_names() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+
+        // 組み込みでないコマンドは実行するときに解決する
+        let result = parse_command("alias g=grep; echo x | g x");
+        let Ok(Command::Compound { commands }) = result else {
+            panic!("Expected Compound command");
+        };
+        assert!(matches!(&commands[1], Command::Pipeline { commands }
+            if matches!(&commands[1], Command::Unresolved { text } if text == "g x")));
+        assert!(matches!(parse_command("  f a b "),
+            Ok(Command::Unresolved { text }) if text == "f a b"));
+
+        // 解決するときに定義されていなければエラー
+        assert!(matches!(
+            parse_resolved("g x"),
+            Err(RucliError::UnknownCommand(_))
+        ));
+        crate::alias::set_alias("g", "grep");
+        functions::define_function("f", parse_command("echo hi").unwrap());
+        assert!(matches!(parse_resolved("g x"),
+            Ok(Command::Grep { pattern, .. }) if pattern == "x"));
+        assert!(matches!(parse_command("f a"),
+            Ok(Command::FunctionCall { name, .. }) if name == "f"));
+    }
+
     #[test]
     fn test_parse_background_command() {
         // 基本的なバックグラウンドコマンド
@@ -227,4 +601,22 @@ mod tests {
         let result = parse_command("cat file.txt | grep pattern &");
         assert!(matches!(result, Ok(Command::Background { .. })));
     }
+
+    #[test]
+    fn test_parse_stopped_background() {
+        let result = parse_command("sleep 10 &?");
+        assert!(
+            matches!(result, Ok(Command::Background { ref text, stopped: true, .. }) if text == "sleep 10")
+        );
+        assert_eq!(result.unwrap().to_string(), "sleep 10 &?");
+
+        // クォートの中の &? は区切りではない
+        let result = parse_command("echo 'a &?'");
+        assert!(matches!(result, Ok(Command::Echo { .. })));
+        let result = parse_command("echo hello &");
+        assert!(matches!(
+            result,
+            Ok(Command::Background { stopped: false, .. })
+        ));
+    }
 }
@@ -1,106 +1,280 @@
 //! 演算子（パイプ、リダイレクト、バックグラウンド等）のパース関数
+//!
+//! クォート内やバックスラッシュでエスケープされた演算子、グループ `( ... )` `{ ...; }` の
+//! 中の演算子は検出しない
+
+use super::tokenizer::{mask_nested, mask_quoted, split_unquoted_if, take_quoted_word};
+use crate::commands::Redirection;
 
 /// 入力をパイプで分割する
 /// 例: "echo hello | grep h" → ["echo hello", "grep h"]
+/// 例: `echo "a|b" | grep a` → [`echo "a|b"`, "grep a"]
+///
+/// `>|`（強制上書きのリダイレクト）の `|` はパイプではない
 pub fn split_by_pipe(input: &str) -> Vec<&str> {
-    input
-        .split('|')
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .collect()
+    split_unquoted_if(input, '|', |masked, pos| !masked[..pos].ends_with('>'))
 }
 
-// リダイレクトでコマンドを分割
-/// 例: "echo hello > file.txt" → ("echo hello", Some((">", "file.txt")))
-pub(super) fn split_redirect(input: &str) -> (String, Option<(String, String)>) {
-    if let Some((pos, redirect_op)) = find_redirect_position(input) {
-        let command = input[..pos].trim().to_string();
-        let target = input[pos + redirect_op.len()..].trim().to_string();
-
-        if target.is_empty() {
-            (input.to_string(), None)
+/// リダイレクト演算子（同じ位置で一致する場合は先にあるものを優先）
+///
+/// `2>&1` は標準エラーを標準出力と同じ先に送るもので、リダイレクト先を取らない。
+/// `>|` は noclobber が有効でも上書きする
+const REDIRECT_OPERATORS: [&str; 10] =
+    ["2>&1", "2>>", "2>", "&>>", "&>", ">&", ">>", ">|", ">", "<"];
+
+/// リダイレクトでコマンドを分割する
+///
+/// 一行に複数のリダイレクトを書ける（左から順に適用する）。
+/// リダイレクト先はクォートで囲めば空白を含められる（クォートは取り除く）。
+/// リダイレクト先より後ろの語はコマンドの引数として扱う。
+/// リダイレクト先が欠けている場合はリダイレクトなしとして入力をそのまま返す。
+/// 例: "echo hello > file.txt" → ("echo hello", [">" file.txt])
+/// 例: `cmd > "my out.txt" 2>&1` → ("cmd", [">" my out.txt, "2>&1"])
+pub(super) fn split_redirects(input: &str) -> (String, Vec<Redirection>) {
+    let masked = mask_arithmetic(&mask_nested(input));
+    let mut words: Vec<&str> = Vec::new();
+    let mut redirects = Vec::new();
+    let mut segment_start = 0;
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let Some(operator) = redirect_operator_at(&masked, pos) else {
+            pos += masked[pos..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+
+        words.push(input[segment_start..pos].trim());
+
+        let after = &input[pos + operator.len()..];
+        let (target, rest) = if operator == "2>&1" {
+            (String::new(), after)
         } else {
-            (command, Some((redirect_op.to_string(), target)))
-        }
-    } else {
-        (input.to_string(), None)
+            match take_quoted_word(after) {
+                Some(word) => word,
+                None => return (input.to_string(), Vec::new()),
+            }
+        };
+
+        redirects.push(Redirection {
+            redirect_type: operator.to_string(),
+            target,
+        });
+        pos = input.len() - rest.len();
+        segment_start = pos;
     }
+
+    words.push(input[segment_start..].trim());
+    words.retain(|word| !word.is_empty());
+    (words.join(" "), redirects)
+}
+
+/// 指定位置から始まるリダイレクト演算子
+///
+/// `2>` で始まる演算子は語の先頭にある場合だけ認める（`file2>out` の `2` は引数の一部）
+fn redirect_operator_at(masked: &str, pos: usize) -> Option<&'static str> {
+    let at_word_start = masked[..pos]
+        .chars()
+        .next_back()
+        .is_none_or(char::is_whitespace);
+
+    REDIRECT_OPERATORS
+        .into_iter()
+        .filter(|operator| at_word_start || !operator.starts_with('2'))
+        .find(|operator| masked[pos..].starts_with(operator))
 }
 
 // リダイレクト演算子を検出する共通関数
-pub(super) fn find_redirect_position(input: &str) -> Option<(usize, &str)> {
-    // ">>" を先にチェック（長い方を優先）
-    if let Some(pos) = input.find(">>") {
-        return Some((pos, ">>"));
-    }
-    // 次に ">" をチェック
-    if let Some(pos) = input.find('>') {
-        return Some((pos, ">"));
-    }
-    // 最後に "<" をチェック
-    if let Some(pos) = input.find('<') {
-        return Some((pos, "<"));
-    }
-    None
+pub(super) fn find_redirect_position(input: &str) -> Option<(usize, &'static str)> {
+    // クォート内の記号や算術展開内の比較演算子はリダイレクトではない
+    let masked = mask_arithmetic(&mask_nested(input));
+
+    masked
+        .char_indices()
+        .find_map(|(pos, _)| redirect_operator_at(&masked, pos).map(|operator| (pos, operator)))
 }
 
 /// リダイレクトを含むかチェック
 pub(super) fn contains_redirect(input: &str) -> bool {
-    input.contains(">>") || input.contains(">") || input.contains("<")
+    find_redirect_position(input).is_some()
+}
+
+/// 算術展開 `$((...))` の中身を空白で置き換える
+///
+/// バイト位置を保ったまま演算子の誤検出を防ぐために使う
+fn mask_arithmetic(input: &str) -> String {
+    let mut masked = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("$((") {
+        let body_start = start + "$((".len();
+        masked.push_str(&rest[..body_start]);
+
+        match rest[body_start..].find("))") {
+            Some(len) => {
+                masked.push_str(&" ".repeat(len));
+                rest = &rest[body_start + len..];
+            }
+            None => {
+                rest = &rest[body_start..];
+                break;
+            }
+        }
+    }
+
+    masked.push_str(rest);
+    masked
 }
 
-// バックグラウンドを含むかチェック
+/// 末尾に `&`（バックグラウンド実行）があるかチェック
+///
+/// 途中の `&`（`a=1&b=2` など）、`&&`、`>&` のようにリダイレクトの一部になっている `&` は除く
 pub(super) fn contains_background(input: &str) -> bool {
-    input.contains("&")
+    let masked = mask_nested(input);
+    masked
+        .trim_end()
+        .strip_suffix('&')
+        .is_some_and(|rest| !rest.ends_with(['&', '>']))
 }
 
-/// ヒアドキュメントの情報を抽出
-pub fn parse_heredoc_header(input: &str) -> Option<(String, String, bool)> {
-    // "<<-"を探す(長いほうから)
-    if let Some(pos) = input.find("<<-") {
-        // コマンド部分をトリミング
-        let cmd = input[..pos].trim();
+/// 末尾の `&?`（停止した状態でバックグラウンドジョブを作る）を取り除く
+///
+/// 例: "sleep 10 &?" → Some("sleep 10")
+pub(super) fn strip_stopped_background(input: &str) -> Option<&str> {
+    let masked = mask_nested(input);
+    let rest = masked.trim_end().strip_suffix("&?")?;
+    (!rest.ends_with(['&', '>'])).then(|| input[..rest.len()].trim())
+}
 
-        // デリミタ部分を取得
-        let delimiter_part = input[pos + "<<-".len()..].trim();
-        let delimiter = delimiter_part.split_whitespace().next()?;
+/// 先頭の単独の `!`（否定）を取り除く
+///
+/// `!!` や `!3` のように語の一部になっている `!` は履歴展開なので対象外
+/// 例: "! grep -q error log" → Some("grep -q error log")
+pub(super) fn strip_negation(input: &str) -> Option<&str> {
+    let rest = input.trim_start().strip_prefix('!')?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
 
-        return Some((cmd.to_string(), delimiter.to_string(), true));
-    }
+/// 先頭の `time`（実行時間の計測）を取り除く
+///
+/// 例: "time cat big | grep x > out" → Some("cat big | grep x > out")
+pub(super) fn strip_time(input: &str) -> Option<&str> {
+    let rest = input.trim_start().strip_prefix("time")?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
 
-    // "<<"を探す
-    if let Some(pos) = input.find("<<") {
-        // コマンド部分をトリミング
-        let cmd = input[..pos].trim();
+/// ヒアドキュメントの情報を抽出
+///
+/// デリミタはクォートで囲めば空白を含められる（クォートは取り除く）
+/// 例: `cat <<"END MARKER"` → ("cat", "END MARKER", false)
+pub fn parse_heredoc_header(input: &str) -> Option<(String, String, bool)> {
+    let masked = mask_quoted(input);
 
-        // デリミタ部分を取得
-        let delimiter_part = input[pos + "<<".len()..].trim();
-        let delimiter = delimiter_part.split_whitespace().next()?;
+    // "<<-"を探す(長いほうから)
+    let (pos, operator, strip_indent) = match masked.find("<<-") {
+        Some(pos) => (pos, "<<-", true),
+        None => (masked.find("<<")?, "<<", false),
+    };
 
-        return Some((cmd.to_string(), delimiter.to_string(), false));
-    }
+    // コマンド部分をトリミング
+    let cmd = input[..pos].trim();
 
-    None
+    // デリミタ部分を取得
+    let (delimiter, _) = take_quoted_word(&input[pos + operator.len()..])?;
+
+    Some((cmd.to_string(), delimiter, strip_indent))
 }
 
 /// ヒアドキュメントを含むかチェック
 pub fn contains_heredoc(input: &str) -> bool {
-    input.contains("<<") && !input.contains("<<<")
+    let masked = mask_quoted(input);
+    masked.contains("<<") && !masked.contains("<<<")
 }
 
-/// 入力をセミコロンで分割する
+/// 入力をセミコロンで分割する（空の要素は除外する）
+///
+/// 制御構文（if/fi、for・while/done、case/esac）の中のセミコロンでは分割しない
+/// 例: "for i in 1 2; do echo $i; done; echo end" → ["for i in 1 2; do echo $i; done", "echo end"]
 pub(super) fn split_by_semicolon(input: &str) -> Vec<&str> {
-    input
-        .split(';')
-        .map(|cmd| cmd.trim())
-        .filter(|cmd| !cmd.is_empty()) // 空文字列を除外
-        .collect()
+    split_unquoted_if(input, ';', |masked, pos| keyword_depth(&masked[..pos]) == 0)
+}
+
+/// 制御構文のキーワードの入れ子の深さ（閉じていない構文の数）
+///
+/// `masked` はクォートとグループの中身を空白にした入力
+fn keyword_depth(masked: &str) -> usize {
+    control_keywords(masked)
+        .last()
+        .map_or(0, |keyword| match keyword.word {
+            "fi" | "done" | "esac" => keyword.depth,
+            _ => keyword.depth + 1,
+        })
+}
+
+/// 制御構文のキーワード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Keyword<'a> {
+    pub(super) pos: usize,    // 入力の中のバイト位置
+    pub(super) word: &'a str, // キーワード（直後の `;` は含まない）
+    pub(super) depth: usize,  // キーワードが属する構文を囲んでいる構文の数（一番外側なら0）
+}
+
+/// 入力の中の制御構文のキーワードを、入れ子の深さとともに返す
+///
+/// キーワードはコマンドの先頭にある場合だけ数える（`echo done` の done は対象外）。
+/// クォートとグループ（`$( )` や `{ ...; }`）の中は見ない。
+/// 例: `for i in 1 2; do if a; then b; fi; done` → for・do・done は深さ0、if・then・fi は深さ1
+pub(super) fn control_keywords(input: &str) -> Vec<Keyword<'_>> {
+    let masked = mask_nested(input);
+    let mut keywords = Vec::new();
+    let mut depth = 0usize;
+    let mut command_start = true;
+    let mut offset = 0;
+
+    for word in masked.split_whitespace() {
+        let pos = offset + masked[offset..].find(word).unwrap_or(0);
+        offset = pos + word.len();
+
+        let keyword = word.trim_end_matches(';');
+        if command_start {
+            let keyword_depth = match keyword {
+                "if" | "for" | "while" | "until" | "case" => {
+                    depth += 1;
+                    Some(depth - 1)
+                }
+                "fi" | "done" | "esac" => {
+                    depth = depth.saturating_sub(1);
+                    Some(depth)
+                }
+                "then" | "else" | "elif" | "do" => Some(depth.saturating_sub(1)),
+                _ => None,
+            };
+            if let Some(keyword_depth) = keyword_depth {
+                keywords.push(Keyword {
+                    pos,
+                    word: &input[pos..pos + keyword.len()],
+                    depth: keyword_depth,
+                });
+            }
+        }
+
+        command_start = word.ends_with([';', '|', '&', ')'])
+            || matches!(keyword, "if" | "then" | "else" | "elif" | "do" | "!" | "{");
+    }
+
+    keywords
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn redirection(redirect_type: &str, target: &str) -> Redirection {
+        Redirection {
+            redirect_type: redirect_type.to_string(),
+            target: target.to_string(),
+        }
+    }
+
     #[test]
     fn test_split_by_pipe() {
         let input = "echo hello | grep h | wc -l";
@@ -134,25 +308,25 @@ mod tests {
     #[test]
     fn test_split_redirect_append() {
         // 基本的な >> リダイレクト
-        let (cmd, redirect) = split_redirect("echo hello >> file.txt");
+        let (cmd, redirect) = split_redirects("echo hello >> file.txt");
         assert_eq!(cmd, "echo hello");
-        assert_eq!(redirect, Some((">>".to_string(), "file.txt".to_string())));
+        assert_eq!(redirect, vec![redirection(">>", "file.txt")]);
     }
 
     #[test]
     fn test_split_redirect_append_with_spaces() {
         // スペースありの >> リダイレクト
-        let (cmd, redirect) = split_redirect("echo hello world  >>  output.log");
+        let (cmd, redirect) = split_redirects("echo hello world  >>  output.log");
         assert_eq!(cmd, "echo hello world");
-        assert_eq!(redirect, Some((">>".to_string(), "output.log".to_string())));
+        assert_eq!(redirect, vec![redirection(">>", "output.log")]);
     }
 
     #[test]
     fn test_split_redirect_no_target() {
         // ターゲットなしの >> リダイレクト
-        let (cmd, redirect) = split_redirect("echo hello >> ");
+        let (cmd, redirect) = split_redirects("echo hello >> ");
         assert_eq!(cmd, "echo hello >> ");
-        assert_eq!(redirect, None);
+        assert!(redirect.is_empty());
     }
 
     #[test]
@@ -172,9 +346,9 @@ mod tests {
 
     #[test]
     fn test_split_redirect_input() {
-        let (cmd, redirect) = split_redirect("cat < file.txt");
+        let (cmd, redirect) = split_redirects("cat < file.txt");
         assert_eq!(cmd, "cat");
-        assert_eq!(redirect, Some(("<".to_string(), "file.txt".to_string())));
+        assert_eq!(redirect, vec![redirection("<", "file.txt")]);
     }
 
     #[test]
@@ -191,11 +365,155 @@ mod tests {
         assert!(contains_redirect("cmd >> file"));
     }
 
+    #[test]
+    fn test_redirect_ignores_arithmetic() {
+        assert!(!contains_redirect("echo $((1 < 2))"));
+        assert_eq!(
+            find_redirect_position("echo $((2 > 1)) > out.txt"),
+            Some((16, ">"))
+        );
+    }
+
     #[test]
     fn test_contains_background() {
         assert!(contains_background("echo hello &"));
         assert!(contains_background("cat file.txt | grep pattern &"));
+        assert!(contains_background("for i in 1 2; do echo $i; done &"));
         assert!(!contains_background("echo hello"));
+
+        // 末尾以外の & や && はバックグラウンドではない
+        assert!(!contains_background("echo a && echo b"));
+        assert!(!contains_background("echo a &&"));
+        assert!(!contains_background("echo http://example.com/?a=1&b=2"));
+        assert!(!contains_background("echo & echo"));
+    }
+
+    #[test]
+    fn test_operators_inside_quotes() {
+        // パイプ
+        assert_eq!(
+            split_by_pipe(r#"echo "a|b" | grep a"#),
+            vec![r#"echo "a|b""#, "grep a"]
+        );
+        assert_eq!(
+            split_by_pipe("grep 'foo|bar' file"),
+            vec!["grep 'foo|bar' file"]
+        );
+        assert_eq!(split_by_pipe(r"echo a\|b"), vec![r"echo a\|b"]);
+
+        // セミコロン
+        assert_eq!(
+            split_by_semicolon(r#"echo "a; b"; echo c"#),
+            vec![r#"echo "a; b""#, "echo c"]
+        );
+
+        // バックグラウンド
+        assert!(!contains_background("echo 'Tom & Jerry'"));
+        assert!(!contains_background(r"echo a\&b"));
+        assert!(contains_background("echo '&' &"));
+
+        // リダイレクト
+        assert!(!contains_redirect(r#"echo "a > b""#));
+        assert!(!contains_redirect("echo '<tag>'"));
+        assert_eq!(
+            find_redirect_position(r#"echo "1>2" > out.txt"#),
+            Some((11, ">"))
+        );
+        let (cmd, redirect) = split_redirects("echo '<b>' > out.html");
+        assert_eq!(cmd, "echo '<b>'");
+        assert_eq!(redirect, vec![redirection(">", "out.html")]);
+    }
+
+    #[test]
+    fn test_split_multiple_redirects() {
+        let (cmd, redirects) = split_redirects("cmd > a.txt 2> b.txt");
+        assert_eq!(cmd, "cmd");
+        assert_eq!(
+            redirects,
+            vec![redirection(">", "a.txt"), redirection("2>", "b.txt")]
+        );
+
+        let (cmd, redirects) = split_redirects("cat < in.txt > out.txt 2>&1");
+        assert_eq!(cmd, "cat");
+        assert_eq!(
+            redirects,
+            vec![
+                redirection("<", "in.txt"),
+                redirection(">", "out.txt"),
+                redirection("2>&1", "")
+            ]
+        );
+
+        let (cmd, redirects) = split_redirects("noisy arg &> /dev/null");
+        assert_eq!(cmd, "noisy arg");
+        assert_eq!(redirects, vec![redirection("&>", "/dev/null")]);
+
+        let (_, redirects) = split_redirects("cmd &>>log.txt");
+        assert_eq!(redirects, vec![redirection("&>>", "log.txt")]);
+        let (_, redirects) = split_redirects("cmd >& all.txt");
+        assert_eq!(redirects, vec![redirection(">&", "all.txt")]);
+
+        // 語の途中の2はリダイレクトではなく引数の一部
+        let (cmd, redirects) = split_redirects("echo file2> out.txt");
+        assert_eq!(cmd, "echo file2");
+        assert_eq!(redirects, vec![redirection(">", "out.txt")]);
+
+        // リダイレクトの一部の & はバックグラウンドではない
+        assert!(!contains_background("cmd &> /dev/null"));
+        assert!(!contains_background("cmd > out.txt 2>&1"));
+        assert!(!contains_background("cmd >& out.txt"));
+        assert!(contains_background("cmd 2>&1 &"));
+    }
+
+    #[test]
+    fn test_force_overwrite_redirect_is_not_a_pipe() {
+        assert_eq!(
+            split_by_pipe("echo hi >| out.txt"),
+            vec!["echo hi >| out.txt"]
+        );
+        assert_eq!(
+            split_by_pipe("echo hi | cat >| out.txt"),
+            vec!["echo hi", "cat >| out.txt"]
+        );
+
+        let (cmd, redirects) = split_redirects("echo hi >|out.txt");
+        assert_eq!(cmd, "echo hi");
+        assert_eq!(redirects, vec![redirection(">|", "out.txt")]);
+    }
+
+    #[test]
+    fn test_strip_negation() {
+        assert_eq!(strip_negation("! grep -q x f"), Some("grep -q x f"));
+        assert_eq!(strip_negation("  !  ! echo hi"), Some("! echo hi"));
+        assert_eq!(strip_negation("!"), Some(""));
+        // 履歴展開や語の途中の ! は否定ではない
+        assert_eq!(strip_negation("!!"), None);
+        assert_eq!(strip_negation("!echo"), None);
+        assert_eq!(strip_negation("echo !"), None);
+    }
+
+    #[test]
+    fn test_strip_time() {
+        assert_eq!(strip_time("time sleep 1"), Some("sleep 1"));
+        assert_eq!(strip_time("  time  ls | wc"), Some("ls | wc"));
+        assert_eq!(strip_time("time"), Some(""));
+        assert_eq!(strip_time("timeout 5 ls"), None);
+        assert_eq!(strip_time("echo time"), None);
+    }
+
+    #[test]
+    fn test_operators_inside_groups() {
+        assert_eq!(
+            split_by_pipe("(echo a | cat) | grep a"),
+            vec!["(echo a | cat)", "grep a"]
+        );
+
+        let (cmd, redirects) = split_redirects("{ echo a > x.txt; echo b; } > both.txt");
+        assert_eq!(cmd, "{ echo a > x.txt; echo b; }");
+        assert_eq!(redirects, vec![redirection(">", "both.txt")]);
+
+        assert!(!contains_background("(sleep 1 &)"));
+        assert!(contains_background("(cd build; ls) &"));
     }
 
     #[test]
@@ -219,6 +537,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_quoted_redirect_target_and_heredoc_delimiter() {
+        let (cmd, redirect) = split_redirects(r#"echo hi > "my notes.txt""#);
+        assert_eq!(cmd, "echo hi");
+        assert_eq!(redirect, vec![redirection(">", "my notes.txt")]);
+
+        let (cmd, redirect) = split_redirects("cat < 'my notes.txt'");
+        assert_eq!(cmd, "cat");
+        assert_eq!(redirect, vec![redirection("<", "my notes.txt")]);
+
+        // リダイレクト先の後ろの語は引数になる
+        let (cmd, redirect) = split_redirects(r"echo a > my\ file.txt b");
+        assert_eq!(cmd, "echo a b");
+        assert_eq!(redirect, vec![redirection(">", "my file.txt")]);
+
+        let result = parse_heredoc_header(r#"cat <<"END MARKER""#);
+        assert_eq!(
+            result,
+            Some(("cat".to_string(), "END MARKER".to_string(), false))
+        );
+        let result = parse_heredoc_header("cat <<-'EOF'");
+        assert_eq!(result, Some(("cat".to_string(), "EOF".to_string(), true)));
+        assert!(!contains_heredoc("echo '<<EOF'"));
+    }
+
     #[test]
     fn test_parse_heredoc_header_no_delimiter() {
         let result = parse_heredoc_header("cat <<");
@@ -251,6 +594,55 @@ mod tests {
         assert_eq!(parts, vec!["echo a", "echo b"]);
     }
 
+    #[test]
+    fn test_split_by_semicolon_keeps_control_structures() {
+        let parts =
+            split_by_semicolon("for i in 1 2; do for j in a b; do echo $i$j; done; done; echo end");
+        assert_eq!(
+            parts,
+            vec![
+                "for i in 1 2; do for j in a b; do echo $i$j; done; done",
+                "echo end"
+            ]
+        );
+
+        let parts = split_by_semicolon("if true; then echo yes; fi; while false; do echo no; done");
+        assert_eq!(
+            parts,
+            vec![
+                "if true; then echo yes; fi",
+                "while false; do echo no; done"
+            ]
+        );
+
+        // コマンドの引数のキーワードは数えない
+        let parts = split_by_semicolon("echo for; echo done; echo x");
+        assert_eq!(parts, vec!["echo for", "echo done", "echo x"]);
+    }
+
+    #[test]
+    fn test_control_keywords_depth() {
+        let input = r#"for i in 1; do if echo "then"; then echo done; fi; done"#;
+        let keywords: Vec<(&str, usize)> = control_keywords(input)
+            .iter()
+            .map(|keyword| (keyword.word, keyword.depth))
+            .collect();
+        assert_eq!(
+            keywords,
+            vec![
+                ("for", 0),
+                ("do", 0),
+                ("if", 1),
+                ("then", 1),
+                ("fi", 1),
+                ("done", 0)
+            ]
+        );
+
+        let done = control_keywords(input).last().unwrap().pos;
+        assert_eq!(&input[done..], "done");
+    }
+
     #[test]
     fn test_split_by_semicolon_single() {
         let parts = split_by_semicolon("echo hello");
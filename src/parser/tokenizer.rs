@@ -0,0 +1,575 @@
+//! クォートとエスケープを考慮した字句処理
+//!
+//! シングルクォート・ダブルクォートで囲まれた部分と、バックスラッシュで
+//! エスケープされた文字は演算子（`|` `;` `&` `>` `<` など）として扱わない。
+//! グループ `( ... )` と `{ ...; }` の中の演算子も、外側のコマンドの区切りとしては扱わない。
+
+/// 各文字のバイト位置と、クォート・エスケープで保護されているかを返す
+///
+/// 保護されるのはクォート文字・バックスラッシュ自体と、それらで保護された文字。
+/// シングルクォート内ではバックスラッシュも文字そのものとして扱う。
+/// 閉じていないクォートは入力の最後まで続くものとして扱う。
+pub(super) fn scan(input: &str) -> Vec<(usize, char, bool)> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    input
+        .char_indices()
+        .map(|(pos, ch)| {
+            let protected = if escaped {
+                escaped = false;
+                true
+            } else {
+                match (quote, ch) {
+                    (Some('\''), '\'') | (Some('"'), '"') => {
+                        quote = None;
+                        true
+                    }
+                    (Some('\''), _) => true,
+                    (_, '\\') => {
+                        escaped = true;
+                        true
+                    }
+                    (Some(_), _) => true,
+                    (None, '"' | '\'') => {
+                        quote = Some(ch);
+                        true
+                    }
+                    (None, _) => false,
+                }
+            };
+            (pos, ch, protected)
+        })
+        .collect()
+}
+
+/// クォート・エスケープされた部分を空白で置き換える
+///
+/// バイト位置を保ったまま、クォート内の演算子の誤検出を防ぐために使う。
+///
+/// 例: `echo "a|b" | grep a` → `echo       | grep a`
+pub(super) fn mask_quoted(input: &str) -> String {
+    let mut masked = String::with_capacity(input.len());
+
+    for (_, ch, protected) in scan(input) {
+        if protected {
+            masked.extend(std::iter::repeat_n(' ', ch.len_utf8()));
+        } else {
+            masked.push(ch);
+        }
+    }
+
+    masked
+}
+
+/// クォートとグループの中身を空白で置き換える
+///
+/// グループの括弧自体は残す。演算子で分割するときはこちらを使う。
+///
+/// 例: `(cd a; ls) | grep x` → `(        ) | grep x`
+pub fn mask_nested(input: &str) -> String {
+    let masked = mask_quoted(input);
+    let groups = top_level_groups(&masked);
+
+    masked
+        .char_indices()
+        .map(|(pos, ch)| {
+            let inside = groups
+                .iter()
+                .any(|&(open, close)| pos > open && close.is_none_or(|close| pos < close));
+            if inside {
+                " ".repeat(ch.len_utf8())
+            } else {
+                ch.to_string()
+            }
+        })
+        .collect()
+}
+
+/// 最も外側のグループの開き括弧と閉じ括弧の位置（閉じていなければ None）
+///
+/// `{` は単独の語の場合だけ、`}` は空白か `;` の直後の場合だけグループの括弧として扱う
+/// （`${VAR}` や `xargs -I {}` は対象外）。
+/// `masked` はクォートを空白にした入力（`mask_quoted` の結果）
+fn top_level_groups(masked: &str) -> Vec<(usize, Option<usize>)> {
+    let mut groups: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut stack = Vec::new();
+    let mut previous: Option<char> = None;
+    let mut chars = masked.char_indices().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        let at_word_start = previous
+            .is_none_or(|prev| prev.is_whitespace() || matches!(prev, ';' | '(' | '|' | '&'));
+
+        let (opens, closes) = match ch {
+            '(' => (true, false),
+            '{' => (at_word_start && next.is_none_or(char::is_whitespace), false),
+            ')' => (false, stack.last() == Some(&'(')),
+            '}' => (
+                false,
+                stack.last() == Some(&'{')
+                    && previous.is_some_and(|prev| prev.is_whitespace() || prev == ';'),
+            ),
+            _ => (false, false),
+        };
+
+        if opens {
+            if stack.is_empty() {
+                groups.push((pos, None));
+            }
+            stack.push(ch);
+        } else if closes {
+            stack.pop();
+            if stack.is_empty()
+                && let Some(group) = groups.last_mut()
+            {
+                group.1 = Some(pos);
+            }
+        }
+
+        previous = Some(ch);
+    }
+
+    groups
+}
+
+/// 入力の先頭がグループかを調べ、グループの中身を取り出す
+///
+/// # Returns
+/// * `None` - 先頭がグループではない
+/// * `Some((opener, Some(body)))` - 入力全体が一つのグループ（`body` は括弧の内側）
+/// * `Some((opener, None))` - 先頭はグループだが、閉じていないか後ろに余分な文字がある
+///
+/// 例: `{ echo a; echo b; }` → ('{', Some(" echo a; echo b; "))
+pub(super) fn split_group(input: &str) -> Option<(char, Option<&str>)> {
+    let input = input.trim();
+    let (open, close) = top_level_groups(&mask_quoted(input))
+        .into_iter()
+        .next()
+        .filter(|&(open, _)| open == 0)?;
+    let opener = input[open..].chars().next()?;
+
+    let body = close
+        .filter(|&close| close + 1 == input.len())
+        .map(|close| &input[1..close]);
+    Some((opener, body))
+}
+
+/// コメントを取り除く
+///
+/// クォート外で語の先頭にある `#` から行末までをコメントとする。
+/// クォート内の `#` や `file#1` のように語の途中にある `#` はそのまま残す
+///
+/// 例: `echo hello # greet` → `echo hello`
+pub fn strip_comment(line: &str) -> &str {
+    let mut previous: Option<char> = None;
+
+    for (pos, ch, protected) in scan(line) {
+        let at_word_start = previous.is_none_or(|prev| prev.is_whitespace() || prev == ';');
+        if ch == '#' && !protected && at_word_start {
+            return line[..pos].trim_end();
+        }
+        // 保護された文字の直後は語の途中
+        previous = Some(if protected { 'x' } else { ch });
+    }
+
+    line
+}
+
+/// 行末がクォート外のエスケープされていないバックスラッシュ（行の継続）かを判定する
+///
+/// クォート内のバックスラッシュや、`\\` で終わる場合は継続ではない
+pub fn ends_with_line_continuation(line: &str) -> bool {
+    let Some(body) = line.strip_suffix('\\') else {
+        return false;
+    };
+
+    // 末尾のバックスラッシュの位置がクォート内でもエスケープ中でもなければ継続
+    scan(&format!("{body} "))
+        .last()
+        .is_some_and(|&(_, _, protected)| !protected)
+}
+
+/// `scan` の結果に、各文字がコマンド置換（`$( )` とバッククォート）の一部かを加えて返す
+fn scan_substitution(input: &str) -> Vec<(usize, char, bool, bool)> {
+    let mut depth = 0;
+    let mut in_backticks = false;
+    let mut previous = None;
+
+    scan(input)
+        .into_iter()
+        .map(|(pos, ch, protected)| {
+            let was_inside = depth > 0 || in_backticks;
+            if !protected {
+                match ch {
+                    '`' if depth == 0 => in_backticks = !in_backticks,
+                    '(' if depth > 0 || previous == Some('$') => depth += 1,
+                    ')' if depth > 0 => depth -= 1,
+                    _ => {}
+                }
+            }
+            previous = Some(ch);
+            (pos, ch, protected, was_inside || depth > 0 || in_backticks)
+        })
+        .collect()
+}
+
+/// コマンド置換（`$( )` とバッククォート）の外にある文字だけを `scan` の形で返す
+fn scan_outside_substitution(input: &str) -> Vec<(usize, char, bool)> {
+    scan_substitution(input)
+        .into_iter()
+        .filter(|&(_, _, _, inside)| !inside)
+        .map(|(pos, ch, protected, _)| (pos, ch, protected))
+        .collect()
+}
+
+/// `scan` と同じだが、コマンド置換の中の文字も保護されているものとして返す
+///
+/// 置換の中はパースするときに改めて展開するので、外側の展開では触れない
+pub(super) fn scan_protecting_substitution(input: &str) -> Vec<(usize, char, bool)> {
+    scan_substitution(input)
+        .into_iter()
+        .map(|(pos, ch, protected, inside)| (pos, ch, protected || inside))
+        .collect()
+}
+
+/// クォートとコマンド置換を考慮して空白で分割する（クォートは残す）
+///
+/// 例: `"a b" $(seq 1 3) c` → [`"a b"`, `$(seq 1 3)`, `c`]
+pub(super) fn split_words(input: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+
+    for (pos, ch, protected) in scan_outside_substitution(input) {
+        if ch.is_whitespace() && !protected {
+            words.push(&input[start..pos]);
+            start = pos + ch.len_utf8();
+        }
+    }
+    words.push(&input[start..]);
+
+    words.retain(|word| !word.is_empty());
+    words
+}
+
+/// 語がコマンド置換の外にクォート・エスケープされた部分を含むか
+///
+/// 例: `"$NAME"` や `a\ b` は true、`$(echo "a")` は false
+pub fn is_quoted_word(word: &str) -> bool {
+    scan_outside_substitution(word)
+        .iter()
+        .any(|&(_, _, protected)| protected)
+}
+
+/// クォートを考慮して空白で分割する
+///
+/// ダブルクォート・シングルクォートで囲まれた部分は一つの要素として扱い、
+/// クォート自体は取り除く。クォート外のバックスラッシュは次の文字をそのまま残し、
+/// ダブルクォート内では `\"` と `\\` だけをエスケープとして扱う。
+/// コマンド置換（`$( )` とバッククォート）の中は実行時にパースするので、そのまま残す。
+/// 例: `X=1 "Y = 2"` → ["X=1", "Y = 2"]、`$(echo "a b")` → [`$(echo "a b")`]
+pub fn split_quoted_words(input: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        // コマンド置換は閉じるまでそのまま語に加える
+        if quote != Some('\'') && (ch == '`' || (ch == '$' && chars.peek() == Some(&'('))) {
+            current.push(ch);
+            in_word = true;
+            let mut depth = 0;
+            for next in chars.by_ref() {
+                current.push(next);
+                match next {
+                    '(' if ch == '$' => depth += 1,
+                    ')' if ch == '$' => depth -= 1,
+                    '`' if ch == '`' => break,
+                    _ => {}
+                }
+                if ch == '$' && depth == 0 {
+                    break;
+                }
+            }
+            continue;
+        }
+
+        match (quote, ch) {
+            (Some(q), _) if ch == q => quote = None,
+            (Some('"'), '\\') => match chars.next_if(|next| matches!(next, '"' | '\\')) {
+                Some(next) => current.push(next),
+                None => current.push(ch),
+            },
+            (Some(_), _) => current.push(ch),
+            (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_word = true;
+            }
+            (None, '"' | '\'') => {
+                quote = Some(ch);
+                in_word = true;
+            }
+            (None, _) if ch.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, _) => {
+                current.push(ch);
+                in_word = true;
+            }
+        }
+    }
+
+    if in_word {
+        words.push(current);
+    }
+
+    words
+}
+
+/// 先頭の語を一つ取り出す（クォートは取り除く）
+///
+/// # Returns
+/// * `Some((word, rest))` - 語と、その後ろの残りの文字列
+/// * `None` - 空白しかない場合
+///
+/// 例: `"my notes.txt" extra` → ("my notes.txt", " extra")
+pub(super) fn take_quoted_word(input: &str) -> Option<(String, &str)> {
+    let input = input.trim_start();
+    if input.is_empty() {
+        return None;
+    }
+
+    let end = scan_protecting_substitution(input)
+        .into_iter()
+        .find(|&(_, ch, protected)| ch.is_whitespace() && !protected)
+        .map_or(input.len(), |(pos, _, _)| pos);
+
+    let word = split_quoted_words(&input[..end]).concat();
+    Some((word, &input[end..]))
+}
+
+/// クォート外にある区切り文字のうち、条件を満たすものだけで分割する
+///
+/// グループの中の区切り文字では分割しない。各要素は前後の空白を取り除き、空の要素は除外する。
+/// 条件にはクォートとグループの中身を空白にした入力と、区切り文字の位置が渡される
+pub(super) fn split_unquoted_if(
+    input: &str,
+    separator: char,
+    is_separator: impl Fn(&str, usize) -> bool,
+) -> Vec<&str> {
+    let masked = mask_nested(input);
+    let mut parts = Vec::new();
+    let mut start = 0;
+
+    for (pos, _) in masked
+        .match_indices(separator)
+        .filter(|&(pos, _)| is_separator(&masked, pos))
+    {
+        parts.push(&input[start..pos]);
+        start = pos + separator.len_utf8();
+    }
+    parts.push(&input[start..]);
+
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_quoted_keeps_byte_positions() {
+        let input = r#"echo "a|b" 'c;d' e\&f | grep あ"#;
+        let masked = mask_quoted(input);
+
+        assert_eq!(masked.len(), input.len());
+        assert_eq!(
+            masked,
+            format!("echo {} {} e  f | grep あ", " ".repeat(5), " ".repeat(5))
+        );
+    }
+
+    #[test]
+    fn test_mask_quoted_nested_and_escaped_quotes() {
+        // ダブルクォート内のシングルクォート、エスケープされたダブルクォート
+        assert_eq!(
+            mask_quoted(r#"a "it's|" b"#),
+            format!("a {} b", " ".repeat(7))
+        );
+        assert_eq!(
+            mask_quoted(r#"a "x\"|" b"#),
+            format!("a {} b", " ".repeat(6))
+        );
+        // シングルクォート内のバックスラッシュはエスケープではない
+        assert_eq!(mask_quoted(r"a '\' | b"), "a     | b");
+        // 閉じていないクォートは最後まで
+        assert_eq!(mask_quoted(r#"a "b | c"#), format!("a {}", " ".repeat(6)));
+    }
+
+    #[test]
+    fn test_mask_nested_groups() {
+        assert_eq!(
+            mask_nested("(cd a; ls) | grep x"),
+            format!("({}) | grep x", " ".repeat(8))
+        );
+        assert_eq!(
+            mask_nested("{ echo a; echo b; } > f"),
+            format!("{{{}}} > f", " ".repeat(17))
+        );
+        // 入れ子とクォート内の括弧
+        assert_eq!(
+            mask_nested("(a (b) ')') ; c"),
+            format!("({}) ; c", " ".repeat(9))
+        );
+        // ${VAR} や {} はグループではない
+        assert_eq!(
+            mask_nested("echo ${A}; xargs -I {} echo {}"),
+            "echo ${A}; xargs -I {} echo {}"
+        );
+    }
+
+    #[test]
+    fn test_split_group() {
+        assert_eq!(split_group(" (cd a; ls) "), Some(('(', Some("cd a; ls"))));
+        assert_eq!(
+            split_group("{ echo a; echo b; }"),
+            Some(('{', Some(" echo a; echo b; ")))
+        );
+        assert_eq!(split_group("(echo ')')"), Some(('(', Some("echo ')'"))));
+
+        // 閉じていない・後ろに余分な文字がある
+        assert_eq!(split_group("(echo a"), Some(('(', None)));
+        assert_eq!(split_group("{ echo a"), Some(('{', None)));
+        assert_eq!(split_group("(echo a) b"), Some(('(', None)));
+
+        assert_eq!(split_group("echo (a)"), None);
+        assert_eq!(split_group("{a,b}"), None);
+    }
+
+    #[test]
+    fn test_strip_comment() {
+        assert_eq!(strip_comment("echo hello # greet the user"), "echo hello");
+        assert_eq!(strip_comment("# whole line"), "");
+        assert_eq!(strip_comment("ls;# list"), "ls;");
+        assert_eq!(strip_comment("  echo a"), "  echo a");
+
+        // クォート内・語の途中・エスケープされた # はコメントではない
+        for line in [
+            "echo 'a # b' \"c # d\"",
+            "cat file#1",
+            r"echo \# x",
+            "echo ${#NAME} $#",
+        ] {
+            assert_eq!(strip_comment(line), line);
+        }
+    }
+
+    #[test]
+    fn test_ends_with_line_continuation() {
+        assert!(ends_with_line_continuation(r"echo one two \"));
+        assert!(ends_with_line_continuation(r"\"));
+        assert!(ends_with_line_continuation(r"echo a\\ \"));
+
+        assert!(!ends_with_line_continuation(r"echo one"));
+        assert!(!ends_with_line_continuation(r"echo a\\"));
+        assert!(!ends_with_line_continuation(r"echo 'a\"));
+        assert!(!ends_with_line_continuation(r#"echo "a\"#));
+    }
+
+    #[test]
+    fn test_split_quoted_words() {
+        assert_eq!(split_quoted_words("a b  c"), vec!["a", "b", "c"]);
+        assert_eq!(
+            split_quoted_words(r#"I=I+1 "X = 2 * Y""#),
+            vec!["I=I+1", "X = 2 * Y"]
+        );
+        assert_eq!(
+            split_quoted_words("NAME='hello world'"),
+            vec!["NAME=hello world"]
+        );
+        assert_eq!(split_quoted_words(r#""""#), vec![""]);
+        assert!(split_quoted_words("   ").is_empty());
+    }
+
+    #[test]
+    fn test_split_words_keeps_quotes_and_substitutions() {
+        assert_eq!(
+            split_words(r#" "Alice Smith"  $(seq 1 3) `date +%H %M` 'a b'c "#),
+            vec![r#""Alice Smith""#, "$(seq 1 3)", "`date +%H %M`", "'a b'c"]
+        );
+        assert!(split_words("  ").is_empty());
+
+        assert!(is_quoted_word(r#""$LIST""#));
+        assert!(is_quoted_word(r"\*.txt"));
+        assert!(!is_quoted_word(r#"$(echo "a b")"#));
+        assert!(!is_quoted_word("$LIST"));
+    }
+
+    #[test]
+    fn test_split_quoted_words_escapes() {
+        assert_eq!(
+            split_quoted_words(r"my\ notes.txt x"),
+            vec!["my notes.txt", "x"]
+        );
+        assert_eq!(
+            split_quoted_words(r#""say \"hi\"" '\n'"#),
+            vec![r#"say "hi""#, r"\n"]
+        );
+        assert_eq!(split_quoted_words(r#""a\b""#), vec![r"a\b"]);
+        // コマンド置換の中はクォートも空白もそのまま
+        assert_eq!(
+            split_quoted_words(r#"$(echo "a b") `echo 'c d'` x"#),
+            vec![r#"$(echo "a b")"#, "`echo 'c d'`", "x"]
+        );
+    }
+
+    #[test]
+    fn test_take_quoted_word() {
+        assert_eq!(
+            take_quoted_word(r#"  "my notes.txt" extra"#),
+            Some(("my notes.txt".to_string(), " extra"))
+        );
+        assert_eq!(
+            take_quoted_word("END' 'MARKER"),
+            Some(("END MARKER".to_string(), ""))
+        );
+        assert_eq!(take_quoted_word("   "), None);
+        assert_eq!(
+            take_quoted_word("$(echo out.txt) extra"),
+            Some(("$(echo out.txt)".to_string(), " extra"))
+        );
+    }
+
+    fn split_unquoted(input: &str, separator: char) -> Vec<&str> {
+        split_unquoted_if(input, separator, |_, _| true)
+    }
+
+    #[test]
+    fn test_split_unquoted() {
+        assert_eq!(
+            split_unquoted(r#"echo "a|b" | grep 'x|y'"#, '|'),
+            vec![r#"echo "a|b""#, "grep 'x|y'"]
+        );
+        assert_eq!(
+            split_unquoted(r"echo a\;b; echo c", ';'),
+            vec![r"echo a\;b", "echo c"]
+        );
+        assert!(split_unquoted("  ", ';').is_empty());
+        assert_eq!(
+            split_unquoted("(a; b); { c; d; }; e", ';'),
+            vec!["(a; b)", "{ c; d; }", "e"]
+        );
+    }
+}
@@ -1,7 +1,10 @@
 //! パーサーのユーティリティ関数と定数
 
-use crate::commands::{COMMANDS, CommandInfo};
+use crate::alias::list_aliases;
+use crate::commands::{COMMANDS, CommandInfo, OutputFormat};
+use crate::environment::is_valid_var_name;
 use crate::error::{Result, RucliError};
+use crate::functions::list_functions;
 use log::{debug, trace};
 
 pub const DEFAULT_HOME_INDICATOR: &str = "~";
@@ -14,6 +17,9 @@ pub(super) fn find_command(name: &str) -> Option<&CommandInfo> {
 }
 
 /// コマンドの引数数を検証する
+///
+/// オプションを宣言しているコマンドは、未知のオプションをエラーにし、
+/// オプションを除いた位置引数の個数を検証する
 pub(super) fn validate_args(cmd_info: &CommandInfo, args: &[&str]) -> Result<()> {
     debug!(
         "Validating args for '{}': {} args provided",
@@ -22,7 +28,11 @@ pub(super) fn validate_args(cmd_info: &CommandInfo, args: &[&str]) -> Result<()>
     );
 
     // 引数の個数
-    let arg_count = args.len();
+    let arg_count = if cmd_info.flags.is_empty() {
+        args.len()
+    } else {
+        split_flags(cmd_info, args)?.positionals.len()
+    };
 
     // 最小引数チェック
     if arg_count < cmd_info.min_args {
@@ -55,10 +65,348 @@ pub(super) fn validate_args(cmd_info: &CommandInfo, args: &[&str]) -> Result<()>
     Ok(())
 }
 
+/// オプションと位置引数に分けたコマンドの引数
+#[derive(Debug, Default)]
+pub(super) struct SplitArgs<'a> {
+    /// 指定された順のオプション（値を取るものは値も）
+    pub flags: Vec<(&'static str, Option<&'a str>)>,
+    /// オプション以外の引数
+    pub positionals: Vec<&'a str>,
+}
+
+impl<'a> SplitArgs<'a> {
+    /// オプションが指定されたか
+    pub fn has(&self, flag: &str) -> bool {
+        self.flags.iter().any(|(name, _)| *name == flag)
+    }
+
+    /// 値を取るオプションの値（複数回指定されたら最後のもの）
+    pub fn value(&self, flag: &str) -> Option<&'a str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == flag)
+            .and_then(|(_, value)| *value)
+    }
+}
+
+/// コマンドの引数をCommandInfoで宣言したオプションと位置引数に分ける
+///
+/// オプションは位置引数の前後どこに置いてもよく、値を取らない一文字のオプションは
+/// `-rf` のようにまとめてもよい。`--` より後ろと単独の `-` は位置引数として扱う
+///
+/// # Arguments
+/// * `cmd_info` - オプションを宣言したコマンド
+/// * `args` - コマンドの引数
+///
+/// # Errors
+/// - 宣言されていない `-` で始まる語がある場合（そのオプションと使い方を示す）
+/// - 値を取るオプションに値がない場合
+pub(super) fn split_flags<'a>(cmd_info: &CommandInfo, args: &[&'a str]) -> Result<SplitArgs<'a>> {
+    let usage_error = |msg: String| {
+        RucliError::InvalidArgument(format!(
+            "{}: {msg}\nUsage: {}",
+            cmd_info.name, cmd_info.usage
+        ))
+    };
+    // 宣言からオプション名と値を取るかを引く
+    let lookup = |flag: &str| {
+        cmd_info.flags.iter().find_map(|spec| {
+            let (name, value) = spec.split_once(' ').unwrap_or((spec, ""));
+            (name == flag).then_some((name, !value.is_empty()))
+        })
+    };
+
+    let mut split = SplitArgs::default();
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        if arg == "--" {
+            split.positionals.extend(iter);
+            break;
+        }
+        if arg == "-" || !arg.starts_with('-') {
+            split.positionals.push(arg);
+            continue;
+        }
+
+        if let Some((name, takes_value)) = lookup(arg) {
+            let value = if takes_value {
+                let Some(&value) = iter.next() else {
+                    return Err(usage_error(format!("{name} requires an argument")));
+                };
+                Some(value)
+            } else {
+                None
+            };
+            split.flags.push((name, value));
+            continue;
+        }
+
+        // `-rf` のようにまとめた一文字のオプション
+        // すべての文字が宣言したオプションの場合だけ分け、そうでなければ語全体を示す（`-name`）
+        let letters = arg[1..]
+            .chars()
+            .map(|c| lookup(&format!("-{c}")))
+            .collect::<Option<Vec<_>>>()
+            .filter(|_| !arg.starts_with("--"));
+        let Some(letters) = letters else {
+            return Err(usage_error(format!("unknown option '{arg}'")));
+        };
+        for (name, takes_value) in letters {
+            if takes_value {
+                return Err(usage_error(format!(
+                    "{name} requires an argument and cannot be combined in '{arg}'"
+                )));
+            }
+            split.flags.push((name, None));
+        }
+    }
+
+    Ok(split)
+}
+
+/// 組み込みコマンドの名前で宣言を引いて引数を分ける（[`split_flags`] を参照）
+///
+/// 登録されていないコマンドなら、すべて位置引数として扱う
+pub(super) fn split_command_flags<'a>(name: &str, args: &[&'a str]) -> Result<SplitArgs<'a>> {
+    match find_command(name) {
+        Some(cmd_info) => split_flags(cmd_info, args),
+        None => Ok(SplitArgs {
+            flags: Vec::new(),
+            positionals: args.to_vec(),
+        }),
+    }
+}
+
+/// `NAME=value` 形式の代入語かチェック
+pub(super) fn is_assignment_word(word: &str) -> bool {
+    matches!(word.split_once('='), Some((name, _)) if is_valid_var_name(name))
+}
+
+/// 先頭の代入語を一つ取り出す
+///
+/// # Returns
+/// * `Some((name, value, rest))` - 代入語だった場合（valueのクォートは除去済み、
+///   コマンド置換の中はクォートも空白もそのまま残す）
+/// * `None` - 代入語でない場合
+pub(super) fn take_assignment_word(input: &str) -> Option<(String, String, &str)> {
+    let (name, after) = input.split_once('=')?;
+    if !is_valid_var_name(name) {
+        return None;
+    }
+
+    let mut value = String::new();
+    let mut quote: Option<char> = None;
+    // 読んでいる途中の $( ) の深さと、バッククォートの中か
+    let mut depth = 0;
+    let mut in_backticks = false;
+    let mut end = after.len();
+
+    for (i, ch) in after.char_indices() {
+        if depth > 0 || in_backticks {
+            match ch {
+                '(' if !in_backticks => depth += 1,
+                ')' if !in_backticks => depth -= 1,
+                '`' if depth == 0 => in_backticks = false,
+                _ => {}
+            }
+            value.push(ch);
+            continue;
+        }
+
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some('\'') => value.push(ch),
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None if ch.is_whitespace() => {
+                end = i;
+                break;
+            }
+            _ => {
+                if ch == '`' {
+                    in_backticks = true;
+                } else if ch == '(' && value.ends_with('$') {
+                    depth = 1;
+                }
+                value.push(ch);
+            }
+        }
+    }
+
+    Some((name.to_string(), value, &after[end..]))
+}
+
+/// 出力形式の指定（`--json` / `-o json` / `-o text`）を取り出す
+///
+/// # Arguments
+/// * `cmd_name` - エラーメッセージに使うコマンド名
+/// * `args` - コマンドの引数
+///
+/// # Returns
+/// * 出力形式と、指定を取り除いた残りの引数
+///
+/// # Errors
+/// * `-o` に値がない、または未対応の形式が指定された場合
+pub(super) fn take_output_format<'a>(
+    cmd_name: &str,
+    args: &[&'a str],
+) -> Result<(OutputFormat, Vec<&'a str>)> {
+    let mut format = OutputFormat::Text;
+    let mut rest = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(&arg) = iter.next() {
+        match arg {
+            "--json" => format = OutputFormat::Json,
+            "-o" => {
+                format = match iter.next() {
+                    Some(&"json") => OutputFormat::Json,
+                    Some(&"text") => OutputFormat::Text,
+                    Some(other) => {
+                        return Err(RucliError::InvalidArgument(format!(
+                            "{cmd_name}: unsupported output format '{other}' (expected json or text)"
+                        )));
+                    }
+                    None => {
+                        return Err(RucliError::InvalidArgument(format!(
+                            "{cmd_name}: -o requires an output format"
+                        )));
+                    }
+                }
+            }
+            _ => rest.push(arg),
+        }
+    }
+
+    Ok((format, rest))
+}
+
+/// 候補として提案する最大の編集距離
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// 提案する候補の最大数
+const MAX_SUGGESTIONS: usize = 3;
+
+/// 二つの文字列の編集距離を求める
+///
+/// 一文字の挿入・削除・置換と、隣り合う二文字の入れ替え（`grpe` → `grep`）を
+/// それぞれ1として数える（制限付きダメラウ・レーベンシュタイン距離）
+pub(super) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // distance[i][j] は a の先頭i文字と b の先頭j文字の距離
+    let mut distance = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distance.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distance[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (distance[i - 1][j] + 1)
+                .min(distance[i][j - 1] + 1)
+                .min(distance[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(distance[i - 2][j - 2] + 1);
+            }
+            distance[i][j] = best;
+        }
+    }
+
+    distance[a.len()][b.len()]
+}
+
+/// 不明なコマンド名に近いコマンド・エイリアス・関数の名前を探す
+///
+/// # Returns
+/// * 編集距離が2以内で最も近い名前（名前順に最大3つ）
+pub(super) fn suggest_commands(name: &str) -> Vec<String> {
+    let mut candidates: Vec<(usize, String)> = COMMANDS
+        .iter()
+        .map(|command| command.name.to_string())
+        .chain(list_aliases().into_iter().map(|(alias, _)| alias))
+        .chain(list_functions())
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .collect();
+
+    candidates.sort();
+    candidates.dedup_by(|a, b| a.1 == b.1);
+
+    // より遠い候補は、近い候補があれば提案しない
+    let closest = candidates.first().map(|(distance, _)| *distance);
+    candidates
+        .into_iter()
+        .take_while(|(distance, _)| Some(*distance) == closest)
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// 8進数のパーミッション（`077`、`0755` など）を解釈する
+///
+/// # Errors
+/// * 8進数でない、または `0777` を超える場合
+pub(super) fn parse_octal_mode(cmd_name: &str, value: &str) -> Result<u32> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| {
+            RucliError::InvalidArgument(format!("{cmd_name}: {value}: invalid octal mode"))
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_take_output_format() {
+        let (format, rest) = take_output_format("ls", &[]).unwrap();
+        assert_eq!(format, OutputFormat::Text);
+        assert!(rest.is_empty());
+
+        let (format, rest) = take_output_format("history", &["--json", "-n", "3"]).unwrap();
+        assert_eq!(format, OutputFormat::Json);
+        assert_eq!(rest, vec!["-n", "3"]);
+
+        let (format, rest) = take_output_format("env", &["-o", "json", "PATH"]).unwrap();
+        assert_eq!(format, OutputFormat::Json);
+        assert_eq!(rest, vec!["PATH"]);
+
+        assert!(take_output_format("ls", &["-o", "yaml"]).is_err());
+        assert!(take_output_format("ls", &["-o"]).is_err());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("grep", "grep"), 0);
+        assert_eq!(edit_distance("grpe", "grep"), 1);
+        assert_eq!(edit_distance("ech", "echo"), 1);
+        assert_eq!(edit_distance("cat", "cut"), 1);
+        assert_eq!(edit_distance("", "ls"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_suggest_commands() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+
+        assert_eq!(suggest_commands("grpe"), vec!["grep"]);
+        // 最も近いものを名前順に最大3つ
+        assert_eq!(suggest_commands("cst"), vec!["cat", "cut"]);
+        assert_eq!(suggest_commands("ecoh"), vec!["echo"]);
+        assert!(suggest_commands("xyzzyplugh").is_empty());
+
+        // エイリアスと関数も候補にする
+        crate::alias::set_alias("deploy", "echo deploying");
+        assert_eq!(suggest_commands("deplyo"), vec!["deploy"]);
+    }
+
     #[test]
     fn test_find_command_exists() {
         // "echo" コマンドが見つかることを確認
@@ -84,6 +432,7 @@ mod tests {
             usage: "test_cmd <arg1> <arg2>",
             min_args: 2,
             max_args: None,
+            flags: &[],
         };
 
         // 引数が足りないケース
@@ -105,6 +454,7 @@ mod tests {
             usage: "test_cmd <arg1> <arg2>",
             min_args: 2,
             max_args: Some(3),
+            flags: &[],
         };
 
         // 引数が足りないケース
@@ -126,6 +476,7 @@ mod tests {
             usage: "test_cmd <arg1> <arg2>",
             min_args: 2,
             max_args: Some(3),
+            flags: &[],
         };
 
         // 引数が足りないケース
@@ -145,6 +496,7 @@ mod tests {
             usage: "echo <message...>",
             min_args: 1,
             max_args: None,
+            flags: &[],
         };
 
         // 多数の引数でもOK
@@ -153,6 +505,102 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_validate_args_counts_only_positionals() {
+        let cmd_info = CommandInfo {
+            name: "rm",
+            description: "Remove command",
+            usage: "rm [-r] [-f] <path>",
+            min_args: 1,
+            max_args: Some(1),
+            flags: &["-r", "-f"],
+        };
+
+        // オプションは数えない
+        assert!(validate_args(&cmd_info, &["-r", "-f", "dir"]).is_ok());
+        assert!(validate_args(&cmd_info, &["dir", "-rf"]).is_ok());
+        assert!(validate_args(&cmd_info, &["-r"]).is_err());
+
+        let err = validate_args(&cmd_info, &["-q", "dir"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "argument error: rm: unknown option '-q'\nUsage: rm [-r] [-f] <path>"
+        );
+    }
+
+    #[test]
+    fn test_split_flags() {
+        let cmd_info = CommandInfo {
+            name: "mkdir",
+            description: "Make directories",
+            usage: "mkdir [-p] [-m mode] <directory...>",
+            min_args: 1,
+            max_args: None,
+            flags: &["-p", "-m MODE", "--verbose"],
+        };
+
+        let split = split_flags(&cmd_info, &["a", "-m", "700", "-p", "-", "--", "-b"]).unwrap();
+        assert_eq!(split.flags, [("-m", Some("700")), ("-p", None)]);
+        assert_eq!(split.positionals, ["a", "-", "-b"]);
+        assert!(split.has("-p"));
+        assert_eq!(split.value("-m"), Some("700"));
+        assert!(!split.has("--verbose"));
+
+        for (args, message) in [
+            (&["-x", "a"][..], "unknown option '-x'"),
+            // 宣言していない文字を含む語は分けずに語全体を示す
+            (&["-px", "a"][..], "unknown option '-px'"),
+            (&["-name", "a"][..], "unknown option '-name'"),
+            (&["--parents", "a"][..], "unknown option '--parents'"),
+            (&["a", "-m"][..], "-m requires an argument"),
+            (
+                &["-pm", "700", "a"][..],
+                "-m requires an argument and cannot be combined in '-pm'",
+            ),
+        ] {
+            let err = split_flags(&cmd_info, args).unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("mkdir: {message}\nUsage: mkdir")),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_assignment_word() {
+        assert!(is_assignment_word("COUNT=5"));
+        assert!(is_assignment_word("_X="));
+        assert!(!is_assignment_word("=5"));
+        assert!(!is_assignment_word("1X=5"));
+        assert!(!is_assignment_word("echo"));
+    }
+
+    #[test]
+    fn test_take_assignment_word() {
+        assert_eq!(
+            take_assignment_word("A=1 B=2"),
+            Some(("A".to_string(), "1".to_string(), " B=2"))
+        );
+        assert_eq!(
+            take_assignment_word(r#"MSG="hello world" echo"#),
+            Some(("MSG".to_string(), "hello world".to_string(), " echo"))
+        );
+        assert_eq!(take_assignment_word("echo A=1"), None);
+        // コマンド置換の中の空白とクォートでは区切らない
+        assert_eq!(
+            take_assignment_word(r#"X=$(echo "a b" | tr a b) Y=`echo c d`"#),
+            Some((
+                "X".to_string(),
+                r#"$(echo "a b" | tr a b)"#.to_string(),
+                " Y=`echo c d`"
+            ))
+        );
+        assert_eq!(
+            take_assignment_word("Y=`echo c d`"),
+            Some(("Y".to_string(), "`echo c d`".to_string(), ""))
+        );
+    }
+
     #[test]
     fn test_validate_args_exact_match() {
         // 最小と最大が同じ（固定引数）のケース
@@ -162,6 +610,7 @@ mod tests {
             usage: "mv <source> <destination>",
             min_args: 2,
             max_args: Some(2),
+            flags: &[],
         };
 
         // ちょうど2個
@@ -1,24 +1,52 @@
 //! パイプラインに関連する関数を提供するモジュール
 
 use crate::{
-    commands::{CommandResult, execute_command_internal},
-    error::Result,
-    parser::parse_command,
+    commands::{Command, CommandResult, PassingBytes, execute_command_collecting},
+    environment::set_pipe_status,
+    error::{Result, RucliError},
+    output,
+    parser::parse_resolved,
 };
+use std::cell::Cell;
+
+thread_local! {
+    /// パイプラインのステージを実行中か
+    static IN_PIPELINE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// パイプラインのステージを実行中か（ステージの中のリダイレクトでは確認を求めない）
+pub fn in_pipeline() -> bool {
+    IN_PIPELINE.with(Cell::get)
+}
+
+/// ステージの実行中の印を付け、スコープを抜けると元に戻すガード
+struct StageGuard(bool);
+
+impl StageGuard {
+    fn enter() -> Self {
+        Self(IN_PIPELINE.with(|flag| flag.replace(true)))
+    }
+}
+
+impl Drop for StageGuard {
+    fn drop(&mut self) {
+        IN_PIPELINE.with(|flag| flag.set(self.0));
+    }
+}
 
 /// パイプラインで繋がれた複数のコマンドを表現
 pub struct PipelineCommand {
-    commands: Vec<String>, // 例: ["echo hello", "grep h", "wc -l"]
+    commands: Vec<Command>, // 例: `echo hello | grep h` の Echo と Grep
 }
 
 impl PipelineCommand {
     // コンストラクタ
-    pub fn new(commands: Vec<String>) -> Self {
+    pub fn new(commands: Vec<Command>) -> Self {
         PipelineCommand { commands }
     }
 
     // コマンド群取得
-    pub fn commands(&self) -> &[String] {
+    pub fn commands(&self) -> &[Command] {
         &self.commands
     }
 }
@@ -27,41 +55,235 @@ impl PipelineCommand {
 pub struct PipelineExecutor;
 
 impl PipelineExecutor {
-    pub fn execute(pipeline: &PipelineCommand) -> Result<String> {
-        let commands = pipeline.commands();
+    /// パイプラインを実行する
+    ///
+    /// 各ステージの出力を次のステージの入力にする。途中のステージが失敗した場合は
+    /// ステージの番号とコマンドを付けてエラーを表示し、次のステージには空の入力を渡して続ける。
+    /// 各ステージの終了ステータスは `environment::get_pipe_status` で参照できる。
+    /// ステージがexitを実行した場合は、残りのステージを実行せずに終了を伝える
+    /// （途中のステージの出力は渡す先がないので捨てる）。
+    /// エイリアスや関数として解決する段は、どの段も始める前に解決する。
+    ///
+    /// # Returns
+    /// * 最後のステージの出力
+    ///
+    /// # Errors
+    /// - 存在しないコマンドの段がある場合（どの段も実行しない）
+    /// - 最後のステージが失敗した場合（ステータスは最後のステージのもの）
+    /// - 中断が要求された場合
+    pub fn execute(pipeline: PipelineCommand) -> Result<CommandResult> {
+        let commands = resolve_stages(pipeline.commands)?;
 
         if commands.is_empty() {
-            return Ok(String::new());
+            return Ok(CommandResult::Continue(Vec::new()));
         }
 
-        let mut previous_output = String::new();
+        let stage_count = commands.len();
+        let mut previous_output = Vec::new();
+        let mut statuses = Vec::with_capacity(stage_count);
+        let mut last_error = None;
 
-        for (i, cmd_str) in commands.iter().enumerate() {
-            let cmd = parse_command(cmd_str)?;
+        for (i, command) in commands.into_iter().enumerate() {
             let input = if i == 0 {
                 None
             } else {
-                Some(previous_output.as_str())
+                Some(previous_output.as_slice())
+            };
+            // エラーの表示用（展開前のコマンド）
+            let cmd_str = command.to_string();
+            let result = {
+                let _stage = StageGuard::enter();
+                // 最後以外のステージの出力は次のステージにバイト列のまま渡す
+                let _bytes = (i + 1 < stage_count).then(PassingBytes::enter);
+                execute_command_collecting(command, input)
             };
 
-            match execute_command_internal(cmd, input)? {
-                CommandResult::Continue(output) => {
-                    previous_output = output;
+            let status;
+            (previous_output, status) = match result {
+                Ok(CommandResult::Continue(output)) => (output, 0),
+                Ok(CommandResult::Exit(output)) => {
+                    statuses.push(crate::environment::get_last_status());
+                    set_pipe_status(statuses);
+                    let output = if i + 1 == stage_count {
+                        output
+                    } else {
+                        Vec::new()
+                    };
+                    return Ok(CommandResult::Exit(output));
                 }
-                CommandResult::Exit => {
-                    // パイプライン内でのExitは特殊扱い
-                    previous_output = String::new();
+                // 出力付きで失敗したコマンドは出力を次に渡す
+                Err(RucliError::CommandFailed { output, status }) => (output, status),
+                Err(RucliError::Interrupted) => {
+                    set_pipe_status(statuses);
+                    return Err(RucliError::Interrupted);
                 }
-            }
+                Err(e) => {
+                    let error = RucliError::PipelineStage {
+                        stage: i + 1,
+                        command: cmd_str,
+                        source: Box::new(e),
+                    };
+                    let status = error.exit_code();
+
+                    // 最後のステージのエラーは呼び出し元に返す
+                    if i + 1 == stage_count {
+                        last_error = Some(error);
+                    } else {
+                        output::eprint_line(&error.to_string());
+                    }
+                    (Vec::new(), status)
+                }
+            };
+            statuses.push(status);
         }
 
-        Ok(previous_output)
+        let last_status = statuses.last().copied().unwrap_or(0);
+        set_pipe_status(statuses);
+
+        // 最後のコマンドのステータスがパイプライン全体のステータス
+        if let Some(error) = last_error {
+            return Err(error);
+        }
+        match last_status {
+            0 => Ok(CommandResult::Continue(previous_output)),
+            status => Err(RucliError::CommandFailed {
+                output: previous_output,
+                status,
+            }),
+        }
     }
 }
 
+/// エイリアスや関数として解決する段を、現在の定義で解決する
+///
+/// # Errors
+/// - 存在しないコマンドの段がある場合（段の番号とコマンドを付ける）
+fn resolve_stages(commands: Vec<Command>) -> Result<Vec<Command>> {
+    commands
+        .into_iter()
+        .enumerate()
+        .map(|(i, command)| match command {
+            Command::Unresolved { text } => {
+                parse_resolved(&text).map_err(|e| RucliError::PipelineStage {
+                    stage: i + 1,
+                    command: text,
+                    source: Box::new(e),
+                })
+            }
+            command => Ok(command),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::parser::split_by_pipe;
+    use super::*;
+    use crate::environment::get_pipe_status;
+    use crate::parser::{parse_command, split_by_pipe};
+    use crate::state::{ShellState, enter};
+    use std::sync::Arc;
+
+    fn run(commands: &[&str]) -> Result<String> {
+        match execute(commands)? {
+            CommandResult::Continue(output) => Ok(String::from_utf8(output).unwrap()),
+            CommandResult::Exit(_) => panic!("unexpected exit"),
+        }
+    }
+
+    fn execute(commands: &[&str]) -> Result<CommandResult> {
+        let commands = commands.iter().map(|c| parse_command(c).unwrap()).collect();
+        PipelineExecutor::execute(PipelineCommand::new(commands))
+    }
+
+    #[test]
+    fn test_failing_first_stage() {
+        let _state = enter(Arc::new(ShellState::new()));
+
+        // 失敗したステージのエラーを表示し、次のステージは空の入力で続ける
+        let (result, captured) = output::capture(|| run(&["cd missing-dir", "echo after"]));
+        assert_eq!(result.unwrap(), "after\n");
+        assert!(
+            captured
+                .stderr
+                .contains("pipeline stage 1 ('cd missing-dir'): ")
+        );
+        assert_eq!(get_pipe_status(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_failing_middle_stage() {
+        let _state = enter(Arc::new(ShellState::new()));
+
+        let (result, captured) =
+            output::capture(|| run(&["echo hello", "cd missing-dir", "tr a-z A-Z"]));
+        assert_eq!(result.unwrap(), "");
+        assert!(
+            captured
+                .stderr
+                .contains("pipeline stage 2 ('cd missing-dir')")
+        );
+        assert_eq!(get_pipe_status(), vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn test_failing_last_stage() {
+        let _state = enter(Arc::new(ShellState::new()));
+
+        let error = run(&["echo hello", "cd missing-dir"]).unwrap_err();
+        assert!(matches!(
+            &error,
+            RucliError::PipelineStage { stage: 2, command, .. } if command == "cd missing-dir"
+        ));
+        assert_eq!(error.exit_code(), 1);
+        assert_eq!(get_pipe_status(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_unknown_command_fails_before_running() {
+        let _state = enter(Arc::new(ShellState::new()));
+
+        // 存在しないコマンドはどの段でも、最初の段を実行する前にエラーになる
+        let error = run(&["write created.txt data", "bogus x"]).unwrap_err();
+        assert!(matches!(
+            &error,
+            RucliError::PipelineStage { stage: 2, command, .. } if command == "bogus x"
+        ));
+        assert!(
+            error
+                .to_string()
+                .starts_with("pipeline stage 2 ('bogus x'): unknown command")
+        );
+        assert_eq!(error.exit_code(), 127);
+        assert!(!std::path::Path::new("created.txt").exists());
+    }
+
+    #[test]
+    fn test_stages_resolve_aliases_when_the_pipeline_runs() {
+        let _state = enter(Arc::new(ShellState::new()));
+
+        // パースした後に定義したエイリアスも使える
+        let Ok(Command::Pipeline { commands }) = parse_command("echo x | g x") else {
+            panic!("Expected Pipeline command");
+        };
+        crate::alias::set_alias("g", "grep");
+        let result = PipelineExecutor::execute(PipelineCommand::new(commands)).unwrap();
+        assert!(matches!(result, CommandResult::Continue(output) if output == b"x\n"));
+    }
+
+    #[test]
+    fn test_exit_stops_the_pipeline() {
+        let _state = enter(Arc::new(ShellState::new()));
+
+        // 残りのステージは実行せず、途中のステージの出力は捨てる
+        let result = execute(&["echo a", "exit 3", "write exit-stage.txt x"]).unwrap();
+        assert!(matches!(result, CommandResult::Exit(output) if output.is_empty()));
+        assert_eq!(get_pipe_status(), vec![0, 3]);
+        assert!(!std::path::Path::new("exit-stage.txt").exists());
+
+        // 最後のステージの出力は残す
+        let result = execute(&["echo a", "eval echo last; exit"]).unwrap();
+        assert!(matches!(result, CommandResult::Exit(output) if output == b"last\n"));
+    }
 
     #[test]
     fn test_split_by_pipe_empty_segments() {
@@ -1,58 +1,311 @@
 //! リダイレクト処理を提供するモジュール
+//!
+//! リダイレクトは左から順に適用する。`>` などの書き込み先はその時点で作成（切り詰め）し、
+//! コマンドの実行後に出力を追記する。`/dev/null` への書き込みは出力を捨てるだけで、
+//! ファイルは開かない。
+//! noclobber が有効な場合、`>` `&>` `>&` は既存のファイルを上書きせずエラーにする
+//! （`>|` は常に上書きする）。
+//! interactive-clobber が有効なら、端末のプロンプトで入力したコマンドの `>` `&>` `>&` は
+//! 空でない既存のファイルを切り詰める前に確認する（スクリプト・`-c`・パイプラインの中では確認しない）。
+//! リダイレクト先の変数・算術式は、適用する時点の値で展開する。
+//! `exec` のリダイレクトは、置き換えるプロセスまたはシェル自身に適用する。
 
 use log::debug;
 
-use crate::commands::{Command, CommandResult, execute_command_internal};
+use crate::commands::{
+    Command, CommandResult, PassingBytes, Redirection, execute_command_collecting,
+};
+use crate::environment::expand_word;
 use crate::error::{Result, RucliError};
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use crate::{input, job, options, output, pipeline, state};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+/// 出力を捨てる特別なリダイレクト先
+const NULL_DEVICE: &str = "/dev/null";
+
+/// 出力の送り先
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sink {
+    Stdout,       // 呼び出し元に出力として返す
+    Stderr,       // 標準エラーに書き出す
+    Discard,      // 捨てる（/dev/null）
+    File(String), // ファイルに追記する
+}
 
 /// リダイレクトを実行
-pub fn execute_redirect(command: Command, redirect_type: &str, target: &str) -> Result<String> {
-    match redirect_type {
-        ">" => {
-            // コマンドからの出力を取得
-            let output = match execute_command_internal(command, None)? {
-                CommandResult::Continue(output) => output,
-                CommandResult::Exit => String::new(),
-            };
-
-            // ファイルに書き込み
-            fs::write(target, output)?;
-
-            Ok(String::new())
+///
+/// # Arguments
+/// * `command` - 実行するコマンド
+/// * `redirects` - 左から順に適用するリダイレクト
+///
+/// # Errors
+/// - リダイレクト先の算術式が不正な場合
+/// - リダイレクト先のファイルを開けない・読めない場合
+/// - noclobber が有効で、既存のファイルに `>` で書き込もうとした場合
+/// - interactive-clobber の確認で上書きしないと答えた場合
+/// - 標準エラーをリダイレクトしていない場合のコマンドのエラー
+pub fn execute_redirect(command: Command, redirects: &[Redirection]) -> Result<CommandResult> {
+    let mut stdout = Sink::Stdout;
+    let mut stderr = Sink::Stderr;
+    let mut input = None;
+
+    for redirect in redirects {
+        let target = expand_word(&redirect.target)?;
+        let target = target.as_str();
+        match redirect.redirect_type.as_str() {
+            ">" => stdout = open_sink(confirm_clobber(check_clobber(target)?)?, false)?,
+            ">|" => stdout = open_sink(target, false)?,
+            ">>" => stdout = open_sink(target, true)?,
+            "2>" => stderr = open_sink(target, false)?,
+            "2>>" => stderr = open_sink(target, true)?,
+            "2>&1" => stderr = stdout.clone(),
+            "&>" | ">&" => {
+                stdout = open_sink(confirm_clobber(check_clobber(target)?)?, false)?;
+                stderr = stdout.clone();
+            }
+            "&>>" => {
+                stdout = open_sink(target, true)?;
+                stderr = stdout.clone();
+            }
+            "<" => {
+                debug!("Input redirect from file: '{target}'");
+
+                // ファイルの内容をバイト列のまま読み込む
+                input = Some(if target == NULL_DEVICE {
+                    Vec::new()
+                } else {
+                    fs::read(target)?
+                });
+            }
+            _ => {
+                return Err(RucliError::ParseError(
+                    "undefined redirect command".to_string(),
+                ));
+            }
+        }
+    }
+
+    // ファイルに書き出す出力はバイト列のまま渡す
+    let _bytes = matches!(stdout, Sink::File(_) | Sink::Discard).then(PassingBytes::enter);
+
+    // 標準エラーをリダイレクトしない場合は、コマンドの出力をそのまま扱う
+    if stderr == Sink::Stderr {
+        let (output, ending) = run_for_output(command, input.as_deref())?;
+        return finish(deliver(&stdout, output)?, ending);
+    }
+
+    // 実行中に書き出された標準エラーとエラーメッセージをリダイレクト先に送る
+    let (result, captured) = output::capture(|| run_for_output(command, input.as_deref()));
+    output::write_stdout(&captured.stdout);
+
+    let mut errors = captured.stderr;
+    let (output, ending) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            errors.push_str(&format!("{e}\n"));
+            (Vec::new(), Ending::Failed(e.exit_code()))
         }
-        ">>" => {
-            // コマンドからの出力を取得
-            let output = match execute_command_internal(command, None)? {
-                CommandResult::Continue(output) => output,
-                CommandResult::Exit => String::new(),
-            };
+    };
+
+    // 同じ先なら標準出力、標準エラーの順に書く
+    let mut returned = deliver(&stdout, output)?;
+    returned.extend(deliver(&stderr, errors.into_bytes())?);
+
+    finish(returned, ending)
+}
 
-            // 追記モードでファイルを開く
-            let mut file = OpenOptions::new().append(true).create(true).open(target)?;
+/// シェル自身の標準出力をリダイレクトする（`exec > file`）
+///
+/// 以降のコマンドの出力はすべてリダイレクト先に書き出す
+///
+/// # Errors
+/// - リダイレクト先のファイルを開けない場合
+/// - noclobber が有効で、既存のファイルに `>` で書き込もうとした場合
+/// - 標準出力以外のリダイレクトの場合
+pub fn redirect_shell(redirects: &[Redirection]) -> Result<()> {
+    for redirect in redirects {
+        let target = redirect.target.as_str();
+        let writer: output::SharedWriter = match redirect.redirect_type.as_str() {
+            ">" | ">|" | ">>" if target == NULL_DEVICE => Arc::new(Mutex::new(io::sink())),
+            ">" => Arc::new(Mutex::new(File::create(check_clobber(target)?)?)),
+            ">|" => Arc::new(Mutex::new(File::create(target)?)),
+            ">>" => Arc::new(Mutex::new(
+                OpenOptions::new().append(true).create(true).open(target)?,
+            )),
+            other => {
+                return Err(RucliError::InvalidArgument(format!(
+                    "exec: '{other}' is not supported without a command"
+                )));
+            }
+        };
+        debug!("Redirecting shell output to '{target}'");
+        output::redirect_stdout(Some(writer));
+    }
 
-            // 書き込み
-            write!(file, "{output}")?;
+    Ok(())
+}
 
-            Ok(String::new())
+/// 外部コマンドの標準入出力にリダイレクトを設定する（`exec cmd > file`）
+///
+/// # Errors
+/// - リダイレクト先のファイルを開けない場合
+/// - noclobber が有効で、既存のファイルに `>` で書き込もうとした場合
+pub fn redirect_process(process: &mut process::Command, redirects: &[Redirection]) -> Result<()> {
+    // 2>&1 のために標準出力のファイルを覚えておく
+    let mut stdout: Option<File> = None;
+
+    for redirect in redirects {
+        let target = redirect.target.as_str();
+        let file = match redirect.redirect_type.as_str() {
+            ">" | "&>" | ">&" => Some(File::create(check_clobber(target)?)?),
+            ">|" | "2>" => Some(File::create(target)?),
+            ">>" | "2>>" | "&>>" => {
+                Some(OpenOptions::new().append(true).create(true).open(target)?)
+            }
+            _ => None,
+        };
+
+        match (redirect.redirect_type.as_str(), file) {
+            (">" | ">|" | ">>", Some(file)) => {
+                process.stdout(file.try_clone()?);
+                stdout = Some(file);
+            }
+            ("2>" | "2>>", Some(file)) => {
+                process.stderr(file);
+            }
+            ("&>" | ">&" | "&>>", Some(file)) => {
+                process.stdout(file.try_clone()?);
+                process.stderr(file.try_clone()?);
+                stdout = Some(file);
+            }
+            ("2>&1", _) => {
+                if let Some(file) = &stdout {
+                    process.stderr(file.try_clone()?);
+                }
+            }
+            ("<", _) => {
+                process.stdin(File::open(target)?);
+            }
+            (other, _) => {
+                return Err(RucliError::ParseError(format!(
+                    "undefined redirect command '{other}'"
+                )));
+            }
         }
-        "<" => {
-            debug!("Input redirect from file: '{target}'");
+    }
+
+    Ok(())
+}
 
-            // ファイルの内容を読み込む
-            let input_content = fs::read_to_string(target)?;
+/// noclobber が有効なら、既存の通常ファイルへの上書きを拒否する
+///
+/// `/dev/null` のような通常ファイル以外は対象外
+fn check_clobber(target: &str) -> Result<&str> {
+    if options::current().noclobber && Path::new(target).is_file() {
+        return Err(RucliError::RuntimeError(format!(
+            "{target}: cannot overwrite existing file"
+        )));
+    }
+    Ok(target)
+}
+
+/// interactive-clobber が有効なら、空でない既存のファイルを切り詰めてよいか確認する
+///
+/// 端末のプロンプトで入力したコマンドだけが対象で、スクリプト・`-c`・バックグラウンドのジョブ・
+/// パイプラインのステージ・入力を受け取っているループの中では確認しない
+///
+/// # Errors
+/// - 上書きしないと答えた場合（`y` / `yes` 以外）
+fn confirm_clobber(target: &str) -> Result<&str> {
+    let prompting = options::current().interactive_clobber
+        && state::current().is_interactive()
+        && io::stdin().is_terminal()
+        && !job::in_background()
+        && !pipeline::in_pipeline()
+        && !input::is_active();
+    let non_empty = fs::metadata(target).is_ok_and(|meta| meta.is_file() && meta.len() > 0);
+    if !prompting || !non_empty {
+        return Ok(target);
+    }
+
+    output::write_stderr(&format!("overwrite existing '{target}'? [y/N] "));
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(target)
+    } else {
+        Err(RucliError::RuntimeError(format!(
+            "{target}: not overwritten"
+        )))
+    }
+}
+
+/// 書き込み先を用意する（上書きならこの時点でファイルを切り詰める）
+fn open_sink(target: &str, append: bool) -> Result<Sink> {
+    if target == NULL_DEVICE {
+        return Ok(Sink::Discard);
+    }
+
+    if append {
+        OpenOptions::new().append(true).create(true).open(target)?;
+    } else {
+        File::create(target)?;
+    }
 
-            // コマンドを入力付きで実行
-            let output = match execute_command_internal(command, Some(&input_content))? {
-                CommandResult::Continue(output) => output,
-                CommandResult::Exit => String::new(),
-            };
+    Ok(Sink::File(target.to_string()))
+}
 
-            Ok(output)
+/// 出力を送り先に届ける
+///
+/// # Returns
+/// * 送り先が標準出力の場合はその出力（呼び出し元が表示する）、それ以外は空のバイト列
+fn deliver(sink: &Sink, output: Vec<u8>) -> Result<Vec<u8>> {
+    match sink {
+        Sink::Stdout => return Ok(output),
+        Sink::Stderr => output::write_stderr(&String::from_utf8_lossy(&output)),
+        Sink::Discard => {}
+        Sink::File(path) => {
+            // 追記モードでファイルを開き、バイト列をそのまま書き込む
+            let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+            file.write_all(&output)?;
         }
-        _ => Err(RucliError::ParseError(
-            "undefined redirect command".to_string(),
-        )),
+    }
+
+    Ok(Vec::new())
+}
+
+/// リダイレクトしたコマンドの終わり方
+enum Ending {
+    Success,
+    /// 出力付きで失敗した（ステータス）
+    Failed(i32),
+    /// exitを実行した（出力はリダイレクト先に書いてから終了を伝える）
+    Exit,
+}
+
+/// コマンドを実行して出力を取得する
+///
+/// # Returns
+/// * 出力と、コマンドの終わり方
+fn run_for_output(command: Command, input: Option<&[u8]>) -> Result<(Vec<u8>, Ending)> {
+    match execute_command_collecting(command, input) {
+        Ok(CommandResult::Continue(output)) => Ok((output, Ending::Success)),
+        Ok(CommandResult::Exit(output)) => Ok((output, Ending::Exit)),
+        Err(RucliError::CommandFailed { output, status }) => Ok((output, Ending::Failed(status))),
+        Err(e) => Err(e),
+    }
+}
+
+/// リダイレクト後の結果を返す（コマンドが失敗していればそのステータスを、exitしていれば終了を引き継ぐ）
+fn finish(output: Vec<u8>, ending: Ending) -> Result<CommandResult> {
+    match ending {
+        Ending::Success => Ok(CommandResult::Continue(output)),
+        Ending::Failed(status) => Err(RucliError::CommandFailed { output, status }),
+        Ending::Exit => Ok(CommandResult::Exit(output)),
     }
 }
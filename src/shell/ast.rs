@@ -0,0 +1,178 @@
+//! スクリプトを実行せずにパースし、コマンドの構文木を取り出す（`--parse-only` とリンター向け）
+//!
+//! 行のまとめ方（空行・コメントの読み飛ばし、ブロックと行の継続）はスクリプトの実行と同じ。
+//! パースは新しい空の状態で行い、コマンド置換は実行せずにそのまま残す。
+//! スクリプト内で定義した関数とエイリアスは、後の文のパースのために登録し、
+//! 存在しないコマンドは実行を待たずに診断する。
+
+use super::block_input::BlockInputCollector;
+use crate::alias;
+use crate::commands::Command;
+use crate::environment::without_command_substitution;
+use crate::functions;
+use crate::parser::parse_resolved;
+use crate::state::{self, ShellState};
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+
+/// パースした一つの文
+#[derive(Debug, Clone, Serialize)]
+pub struct Statement {
+    /// 文が始まる行番号（1始まり）
+    pub line: usize,
+    /// パースしたコマンド
+    pub command: Command,
+}
+
+/// パースできなかった文の診断
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// 文が始まる行番号（1始まり）
+    pub line: usize,
+    /// エラーメッセージ
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// スクリプトのすべての文をパースする（コマンドは実行しない）
+///
+/// # Examples
+///
+/// ```
+/// let statements = rucli::parse_to_ast("echo hi\nfor i in 1 2\ndo\n  echo $i\ndone").unwrap();
+///
+/// assert_eq!(statements.len(), 2);
+/// assert_eq!(statements[1].line, 2);
+/// ```
+///
+/// # Returns
+/// * 文ごとの行番号とコマンド
+///
+/// # Errors
+/// * パースできない文があった場合、そのすべての診断（行番号付き）
+pub fn parse_to_ast(script: &str) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+    let _state = state::enter(Arc::new(ShellState::new()));
+    without_command_substitution(|| {
+        let mut statements = Vec::new();
+        let mut diagnostics = Vec::new();
+        let mut collector = BlockInputCollector::new();
+        let mut start = None;
+
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line_number = *start.get_or_insert(index + 1);
+            if collector.add_line(line) {
+                continue;
+            }
+
+            let input = collector.get_complete_command();
+            collector = BlockInputCollector::new();
+            start = None;
+            if input.trim().is_empty() {
+                continue;
+            }
+
+            match parse_resolved(input.trim_start()) {
+                Ok(command) => {
+                    register_definitions(&command);
+                    statements.push(Statement {
+                        line: line_number,
+                        command,
+                    });
+                }
+                Err(e) => diagnostics.push(Diagnostic {
+                    line: line_number,
+                    message: e.to_string(),
+                }),
+            }
+        }
+
+        if let Some(line) = start.filter(|_| collector.is_incomplete()) {
+            diagnostics.push(Diagnostic {
+                line,
+                message: "incomplete block structure at end of file".to_string(),
+            });
+        }
+
+        if diagnostics.is_empty() {
+            Ok(statements)
+        } else {
+            Err(diagnostics)
+        }
+    })
+}
+
+/// 関数とエイリアスの定義を登録し、後の文で呼び出せるようにする
+fn register_definitions(command: &Command) {
+    match command {
+        Command::Function { name, body } => functions::define_function(name, (**body).clone()),
+        Command::Alias {
+            name: Some(name),
+            command: Some(value),
+        } => alias::set_alias(name, value),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_to_ast_collects_statements() {
+        let script = "#!/usr/bin/env rucli\n\
+                      function greet() {\n\
+                      \x20 echo hello $1\n\
+                      }\n\
+                      \n\
+                      greet world\n\
+                      echo a \\\n\
+                      \x20 b | grep a\n";
+
+        let statements = parse_to_ast(script).unwrap();
+        let lines: Vec<usize> = statements.iter().map(|s| s.line).collect();
+        assert_eq!(lines, vec![2, 6, 7]);
+        assert!(
+            matches!(&statements[1].command, Command::FunctionCall { name, .. } if name == "greet")
+        );
+        assert!(
+            matches!(&statements[2].command, Command::Pipeline { commands } if commands.len() == 2)
+        );
+    }
+
+    #[test]
+    fn test_parse_to_ast_does_not_run_substitutions() {
+        let statements = parse_to_ast("echo $(write created.txt data)").unwrap();
+        assert!(
+            matches!(&statements[0].command, Command::Echo { message, .. }
+            if message == "$(write created.txt data)")
+        );
+        assert!(!std::path::Path::new("created.txt").exists());
+    }
+
+    #[test]
+    fn test_parse_to_ast_reports_every_error() {
+        let diagnostics = parse_to_ast("echo ok\nnosuchcmd\ntime\nfor i in 1\ndo").unwrap_err();
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+        assert!(
+            diagnostics[1]
+                .to_string()
+                .starts_with("line 3: Parse error:")
+        );
+        assert_eq!(
+            diagnostics[2].message,
+            "incomplete block structure at end of file"
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! 複数行にまたがるブロック入力（if/while/for/function）と行の継続（末尾の `\`）の収集
+//!
+//! 各行のコメント（`#` から行末まで）はここで取り除く
+
+use crate::parser::{ends_with_line_continuation, mask_nested, strip_comment};
+
+/// ブロック入力を管理する構造体
+pub(super) struct BlockInputCollector {
+    lines: Vec<String>,
+    depth: i32,
+    pending_keywords: Vec<(String, i32)>,
+    continued: bool, // 直前の行が `\` で終わり、次の行に続く
+}
+
+impl BlockInputCollector {
+    pub(super) fn new() -> Self {
+        BlockInputCollector {
+            lines: Vec::new(),
+            depth: 0,
+            pending_keywords: Vec::new(),
+            continued: false,
+        }
+    }
+
+    /// 行を追加し、次の状態を返す
+    /// Noneなら入力完了
+    pub(super) fn add_line(&mut self, line: &str) -> bool {
+        // コメント内の `\` やキーワードは扱わない
+        let line = strip_comment(line);
+
+        // 末尾の `\` を取り除き、継続中なら直前の行にそのままつなげる
+        let continues = ends_with_line_continuation(line);
+        let line = if continues {
+            &line[..line.len() - 1]
+        } else {
+            line
+        };
+
+        match self.lines.last_mut() {
+            Some(last) if self.continued => last.push_str(line),
+            _ => self.lines.push(line.to_string()),
+        }
+        self.continued = continues;
+
+        // 新規追加：キーワードを抽出して処理
+        let keywords = Self::extract_keywords(line);
+        for keyword in keywords {
+            match keyword.as_str() {
+                "while" | "for" => {
+                    self.depth += 1;
+                    self.pending_keywords.push(("do".to_string(), self.depth));
+                }
+                "if" => {
+                    self.depth += 1;
+                    self.pending_keywords.push(("then".to_string(), self.depth));
+                }
+                "function" => {
+                    self.depth += 1;
+                    self.pending_keywords.push(("{".to_string(), self.depth));
+                }
+                "do" => {
+                    self.pending_keywords
+                        .retain(|(k, d)| !(k == "do" && *d == self.depth));
+                    self.pending_keywords.push(("done".to_string(), self.depth));
+                }
+                "then" => {
+                    self.pending_keywords
+                        .retain(|(k, d)| !(k == "then" && *d == self.depth));
+                    self.pending_keywords.push(("fi".to_string(), self.depth));
+                }
+                "{" => {
+                    self.pending_keywords
+                        .retain(|(k, d)| !(k == "{" && *d == self.depth));
+                    self.pending_keywords.push(("}".to_string(), self.depth));
+                }
+                "done" | "fi" | "}" => {
+                    self.pending_keywords
+                        .retain(|(k, d)| !(k == keyword.as_str() && *d == self.depth));
+                    self.depth -= 1;
+                }
+                "else" => {
+                    // elseは深さを変えない（fiを待ち続ける）
+                }
+                _ => {}
+            }
+        }
+
+        // 継続中でなく、pending_keywordsが空 = 完了
+        self.is_incomplete()
+    }
+
+    /// 行からブロックのキーワードを取り出す
+    ///
+    /// クォートの中と、`$( ... )` などのグループの中のキーワードは数えない
+    fn extract_keywords(line: &str) -> Vec<String> {
+        let mut keywords = Vec::new();
+        let masked = mask_nested(line);
+        let words: Vec<&str> = masked.split_whitespace().collect();
+
+        // "};" や "done;" のように直後にセミコロンが続いてもキーワードとして扱う
+        for word in words.iter().map(|word| word.trim_end_matches(';')) {
+            match word {
+                "while" | "for" | "if" | "do" | "then" | "done" | "fi" | "else" | "function"
+                | "{" | "}" => {
+                    keywords.push(word.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        keywords
+    }
+
+    /// 蓄積された入力を一行に統合
+    ///
+    /// 先頭行が空白で始まる場合は、履歴の判定用に先頭の空白を一つ残す
+    pub(super) fn get_complete_command(&self) -> String {
+        let mut result = String::new();
+
+        if self
+            .lines
+            .first()
+            .is_some_and(|line| line.starts_with([' ', '\t']))
+        {
+            result.push(' ');
+        }
+
+        // 空行を除外したリストを作成
+        let non_empty_lines: Vec<&str> = self
+            .lines
+            .iter()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        for (i, line) in non_empty_lines.iter().enumerate() {
+            // 行を追加
+            result.push_str(line);
+
+            // 最後の行でなければ区切り文字を追加
+            if i < non_empty_lines.len() - 1 {
+                let next = non_empty_lines[i + 1];
+
+                match (*line, next) {
+                    // "for/while/if ..." の後で "do/then" の前にはセミコロン
+                    (curr, "do") if curr.starts_with("for ") || curr.starts_with("while ") => {
+                        result.push_str("; ");
+                    }
+                    (curr, "then") if curr.starts_with("if ") => {
+                        result.push_str("; ");
+                    }
+                    // "function name()" の次の行の "{" の前はスペースのみ
+                    (curr, "{") if curr.starts_with("function ") => {
+                        result.push(' ');
+                    }
+                    // "do/then/else" とグループの "{" で終わる行の後はスペースのみ
+                    (curr, _) if Self::ends_with_block_opener(curr) => {
+                        result.push(' ');
+                    }
+                    // その他の場合はセミコロン
+                    _ => {
+                        result.push_str("; ");
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// 行が本体を始めるキーワード（do・then・else・`{`）で終わるか
+    ///
+    /// キーワードはコマンドの先頭にある場合だけ数える（`for f in a b; do` は対象、`echo do` は対象外）
+    fn ends_with_block_opener(line: &str) -> bool {
+        let (before, last) = line.rsplit_once(char::is_whitespace).unwrap_or(("", line));
+        let before = before.trim_end();
+        match last {
+            "{" => true,
+            "do" | "then" | "else" => before.is_empty() || before.ends_with(';'),
+            _ => false,
+        }
+    }
+
+    /// ブロックの途中（閉じられていない構文がある）かチェック
+    pub(super) fn is_incomplete(&self) -> bool {
+        self.continued || self.depth > 0 || !self.pending_keywords.is_empty()
+    }
+
+    /// 現在のプロンプトを取得
+    pub(super) fn get_prompt(&self) -> &str {
+        if !self.is_incomplete() { "> " } else { ">> " }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_for_loop() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("for i in 1 2 3")); // 継続
+        assert_eq!(collector.get_prompt(), ">> ");
+
+        assert!(collector.add_line("do")); // 継続
+        assert!(collector.add_line("  echo $i")); // 継続
+        assert!(!collector.add_line("done")); // 完了
+
+        assert_eq!(
+            collector.get_complete_command(),
+            "for i in 1 2 3; do echo $i; done"
+        );
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("while test -f flag"));
+        assert!(collector.add_line("do"));
+        assert!(collector.add_line("  cat flag"));
+        assert!(collector.add_line("  rm flag"));
+        assert!(!collector.add_line("done"));
+
+        let cmd = collector.get_complete_command();
+        assert!(cmd.contains("while test -f flag"));
+        assert!(cmd.contains("do cat flag"));
+        assert!(cmd.contains("rm flag"));
+        assert!(cmd.contains("done"));
+    }
+
+    #[test]
+    fn test_if_then_else_fi() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("if pwd")); // 継続
+        assert!(collector.add_line("then")); // 継続
+        assert!(collector.add_line("  echo exists")); // 継続
+        assert!(collector.add_line("else")); // 継続
+        assert!(collector.add_line("  echo not found")); // 継続
+        assert!(!collector.add_line("fi")); // 完了
+
+        let cmd = collector.get_complete_command();
+        assert_eq!(cmd, "if pwd; then echo exists; else echo not found; fi");
+    }
+
+    #[test]
+    fn test_nested_for_loops() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("for i in 1 2"));
+        assert!(collector.add_line("do"));
+        assert_eq!(collector.depth, 1);
+        assert_eq!(collector.pending_keywords, vec![("done".to_string(), 1)]);
+
+        assert!(collector.add_line("  for j in a b"));
+        assert_eq!(collector.depth, 2);
+        assert_eq!(
+            collector.pending_keywords,
+            vec![("done".to_string(), 1), ("do".to_string(), 2)]
+        );
+
+        assert!(collector.add_line("  do"));
+        assert_eq!(
+            collector.pending_keywords,
+            vec![("done".to_string(), 1), ("done".to_string(), 2)]
+        );
+
+        assert!(collector.add_line("    echo $i$j"));
+        assert!(collector.add_line("  done"));
+        assert_eq!(collector.depth, 1);
+        assert_eq!(collector.pending_keywords, vec![("done".to_string(), 1)]);
+
+        assert!(!collector.add_line("done")); // 完了
+        assert_eq!(collector.depth, 0);
+        assert!(collector.pending_keywords.is_empty());
+
+        let cmd = collector.get_complete_command();
+        assert!(cmd.contains("for i in 1 2"));
+        assert!(cmd.contains("for j in a b"));
+    }
+
+    #[test]
+    fn test_comments_are_stripped() {
+        let mut collector = BlockInputCollector::new();
+        assert!(!collector.add_line("echo hello # greet the user"));
+        assert_eq!(collector.get_complete_command(), "echo hello");
+
+        // 単独の # の行は空の入力になる
+        let mut collector = BlockInputCollector::new();
+        assert!(!collector.add_line("#"));
+        assert_eq!(collector.get_complete_command(), "");
+
+        // コメント内のキーワードや \ はブロックや継続にならない
+        let mut collector = BlockInputCollector::new();
+        assert!(!collector.add_line("echo 'a # b' # then for \\"));
+        assert_eq!(collector.get_complete_command(), "echo 'a # b'");
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("echo one \\"));
+        assert_eq!(collector.get_prompt(), ">> ");
+        assert!(collector.add_line("  two \\"));
+        assert!(!collector.add_line("three"));
+
+        assert_eq!(collector.get_complete_command(), "echo one   two three");
+    }
+
+    #[test]
+    fn test_line_continuation_pipeline() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("cat file.txt |\\"));
+        assert!(!collector.add_line(" grep error"));
+
+        assert_eq!(
+            collector.get_complete_command(),
+            "cat file.txt | grep error"
+        );
+    }
+
+    #[test]
+    fn test_backslash_without_continuation() {
+        // クォート内のバックスラッシュや `\\` は継続しない
+        let mut collector = BlockInputCollector::new();
+        assert!(!collector.add_line("echo 'a\\"));
+
+        let mut collector = BlockInputCollector::new();
+        assert!(!collector.add_line("echo a\\\\"));
+        assert_eq!(collector.get_complete_command(), "echo a\\\\");
+    }
+
+    #[test]
+    fn test_brace_group() {
+        let mut collector = BlockInputCollector::new();
+
+        // 一行で閉じたグループ
+        assert!(!collector.add_line("{ cd build; }; pwd"));
+
+        let mut collector = BlockInputCollector::new();
+        assert!(collector.add_line("{"));
+        assert!(collector.add_line("  echo a"));
+        assert!(collector.add_line("  echo b"));
+        assert!(!collector.add_line("} > both.txt"));
+
+        assert_eq!(
+            collector.get_complete_command(),
+            "{ echo a; echo b; } > both.txt"
+        );
+    }
+
+    #[test]
+    fn test_keywords_in_substitution_and_quotes() {
+        let mut collector = BlockInputCollector::new();
+        assert!(!collector.add_line("OUT=$(if cd .; then echo yes; fi)"));
+
+        let mut collector = BlockInputCollector::new();
+        assert!(!collector.add_line("echo 'for' \"do then\""));
+    }
+
+    #[test]
+    fn test_function_multiline() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("function test()")); // 継続
+        assert!(collector.add_line("{")); // 継続
+        assert!(collector.add_line("  echo Hello")); // 継続
+        assert!(collector.add_line("  echo World")); // 継続
+        assert!(!collector.add_line("}")); // 完了
+
+        let cmd = collector.get_complete_command();
+        assert_eq!(cmd, "function test() { echo Hello; echo World; }");
+    }
+
+    #[test]
+    fn test_function_with_loop_multiline() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("function each() {"));
+        assert!(collector.add_line("  for f in a b; do"));
+        assert!(collector.add_line("    if cat $f; then"));
+        assert!(collector.add_line("      echo ${f}"));
+        assert!(collector.add_line("    fi"));
+        assert!(collector.add_line("    echo do"));
+        assert!(collector.add_line("  done"));
+        assert!(!collector.add_line("}"));
+
+        assert_eq!(
+            collector.get_complete_command(),
+            "function each() { for f in a b; do if cat $f; then echo ${f}; fi; echo do; done; }"
+        );
+    }
+
+    #[test]
+    fn test_empty_lines_ignored() {
+        let mut collector = BlockInputCollector::new();
+
+        assert!(collector.add_line("for i in 1 2 3"));
+        assert!(collector.add_line("do"));
+        assert!(collector.add_line("")); // 空行
+        assert!(collector.add_line("  echo $i"));
+        assert!(collector.add_line("")); // 空行
+        assert!(!collector.add_line("done"));
+
+        let cmd = collector.get_complete_command();
+        assert_eq!(cmd, "for i in 1 2 3; do echo $i; done");
+    }
+}
@@ -0,0 +1,391 @@
+//! 端末で対話しているときの一行入力
+//!
+//! 端末をカノニカルモードから外して一文字ずつ読み、Tabで補完する（`completion` モジュール）。
+//! カーソル移動（←→、Ctrl-A/Ctrl-E）と削除（Backspace、Delete、Ctrl-U、Ctrl-W）にも対応する。
+//! Ctrl-C などのシグナルは端末に任せるので、これまでどおりに届く。
+//! 括弧付き貼り付け（bracketed paste）を有効にし、複数行の貼り付けは一行ずつ実行せず
+//! まとめて返す。
+
+use crate::completion::{self, Completion};
+use crate::terminal::KeyMode;
+use std::io::{self, IsTerminal, Read, Write};
+
+/// 標準入力と標準出力がどちらも端末なら、この行入力を使う
+pub(super) fn is_available() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// 括弧付き貼り付けの開始・終了を表すエスケープシーケンス（ESCの後ろ）
+const PASTE_START: &[u8] = b"[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// 一回の入力で受け取ったもの
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Input {
+    Line(String),       // Enterで確定した行
+    Paste(Vec<String>), // 複数行の貼り付け（入力中だった行も含む）
+}
+
+/// 端末を一文字ずつ読むモード（[`KeyMode`]）にし、ドロップ時に元へ戻すガード
+///
+/// 有効な間は括弧付き貼り付けも有効にする
+struct RawMode {
+    _keys: KeyMode, // 括弧付き貼り付けを無効にしてから端末の設定を戻す
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let keys = KeyMode::enable()?;
+
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?2004h")?;
+        stdout.flush()?;
+        Ok(Self { _keys: keys })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\x1b[?2004l");
+        let _ = stdout.flush();
+    }
+}
+
+/// 入力中の行とカーソル位置（バイト単位）
+#[derive(Debug, Default)]
+struct LineBuffer {
+    text: String,
+    cursor: usize,
+}
+
+/// Tabを押した結果
+#[derive(Debug, PartialEq, Eq)]
+enum TabOutcome {
+    Completed,              // 語を置き換えた
+    Ambiguous(Vec<String>), // これ以上は絞れないので候補を表示する
+    NoMatch,                // 候補がない
+}
+
+impl LineBuffer {
+    fn insert(&mut self, ch: char) {
+        self.text.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+    }
+
+    /// カーソルの前の位置（先頭ならNone）
+    fn previous_boundary(&self) -> Option<usize> {
+        self.text[..self.cursor]
+            .char_indices()
+            .last()
+            .map(|(i, _)| i)
+    }
+
+    /// カーソルの後の位置（末尾ならNone）
+    fn next_boundary(&self) -> Option<usize> {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map(|ch| self.cursor + ch.len_utf8())
+    }
+
+    fn backspace(&mut self) {
+        if let Some(previous) = self.previous_boundary() {
+            self.text.replace_range(previous..self.cursor, "");
+            self.cursor = previous;
+        }
+    }
+
+    fn delete(&mut self) {
+        if let Some(next) = self.next_boundary() {
+            self.text.replace_range(self.cursor..next, "");
+        }
+    }
+
+    fn move_left(&mut self) {
+        self.cursor = self.previous_boundary().unwrap_or(0);
+    }
+
+    fn move_right(&mut self) {
+        self.cursor = self.next_boundary().unwrap_or(self.cursor);
+    }
+
+    /// カーソルより前を消す（Ctrl-U）
+    fn kill_to_start(&mut self) {
+        self.text.replace_range(..self.cursor, "");
+        self.cursor = 0;
+    }
+
+    /// カーソルの前の一語を消す（Ctrl-W）
+    fn kill_word(&mut self) {
+        let before = self.text[..self.cursor].trim_end();
+        let start = before.rfind(char::is_whitespace).map_or(0, |pos| pos + 1);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// カーソル位置の語を補完する
+    ///
+    /// 候補が一つならその語にし（ディレクトリでなければ空白を続ける）、
+    /// 複数なら共通する先頭部分まで進める
+    fn complete(&mut self) -> TabOutcome {
+        let Completion { start, candidates } = completion::complete(&self.text, self.cursor);
+
+        let replacement = match candidates.as_slice() {
+            [] => return TabOutcome::NoMatch,
+            [only] if only.ends_with('/') => only.clone(),
+            [only] => format!("{only} "),
+            _ => {
+                let prefix = completion::common_prefix(&candidates);
+                if prefix.len() <= self.cursor - start {
+                    return TabOutcome::Ambiguous(candidates);
+                }
+                prefix.to_string()
+            }
+        };
+
+        self.text.replace_range(start..self.cursor, &replacement);
+        self.cursor = start + replacement.len();
+        TabOutcome::Completed
+    }
+
+    /// 貼り付けられた文字列をカーソル位置に入れる
+    ///
+    /// 末尾の改行を除いて一行なら入力中の行に挿入し、複数行なら入力中の行と合わせて返す
+    ///
+    /// # Returns
+    /// * 複数行だった場合はその行（空の行も含む）、一行ならNone
+    fn paste(&mut self, text: &str) -> Option<Vec<String>> {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+
+        if !text.contains('\n') {
+            let text: String = text.chars().filter(|ch| !ch.is_control()).collect();
+            self.text.insert_str(self.cursor, &text);
+            self.cursor += text.len();
+            return None;
+        }
+
+        let (before, after) = self.text.split_at(self.cursor);
+        let joined = format!("{before}{text}{after}");
+        Some(joined.lines().map(str::to_string).collect())
+    }
+}
+
+/// プロンプトを表示して一行（または貼り付けられた複数行）を読む
+///
+/// # Returns
+/// * 入力された行（改行は含まない）か貼り付けられた複数行、
+///   空の行でCtrl-Dを押したか入力の終端ならNone
+pub(super) fn read_line(prompt: &str) -> io::Result<Option<Input>> {
+    let _raw = RawMode::enable()?;
+    let mut stdout = io::stdout();
+    let mut line = LineBuffer::default();
+    redraw(&mut stdout, prompt, &line)?;
+
+    loop {
+        let Some(byte) = read_byte()? else {
+            return Ok(None);
+        };
+
+        match byte {
+            b'\r' | b'\n' => {
+                write!(stdout, "\r\n")?;
+                stdout.flush()?;
+                return Ok(Some(Input::Line(line.text)));
+            }
+            // Ctrl-D: 空の行なら入力の終端、それ以外はカーソル位置の文字を消す
+            0x04 if line.text.is_empty() => {
+                write!(stdout, "\r\n")?;
+                stdout.flush()?;
+                return Ok(None);
+            }
+            0x04 => line.delete(),
+            b'\t' => match line.complete() {
+                TabOutcome::Completed => {}
+                TabOutcome::Ambiguous(candidates) => {
+                    write!(stdout, "\r\n{}\r\n", candidates.join("  "))?;
+                }
+                TabOutcome::NoMatch => write!(stdout, "\x07")?,
+            },
+            0x7f | 0x08 => line.backspace(),
+            0x01 => line.cursor = 0,
+            0x05 => line.cursor = line.text.len(),
+            0x15 => line.kill_to_start(),
+            0x17 => line.kill_word(),
+            0x1b => match read_escape_sequence()?.as_slice() {
+                PASTE_START => {
+                    if let Some(lines) = line.paste(&read_pasted_text()?) {
+                        write!(stdout, "\r\n")?;
+                        stdout.flush()?;
+                        return Ok(Some(Input::Paste(lines)));
+                    }
+                }
+                b"[D" | b"OD" => line.move_left(),
+                b"[C" | b"OC" => line.move_right(),
+                b"[H" | b"OH" | b"[1~" => line.cursor = 0,
+                b"[F" | b"OF" | b"[4~" => line.cursor = line.text.len(),
+                b"[3~" => line.delete(),
+                _ => {}
+            },
+            byte if byte < 0x20 => {}
+            byte => {
+                if let Some(ch) = read_char(byte)? {
+                    line.insert(ch);
+                }
+            }
+        }
+
+        redraw(&mut stdout, prompt, &line)?;
+    }
+}
+
+/// プロンプトと行を書き直し、カーソルを入力位置に戻す
+fn redraw(stdout: &mut io::Stdout, prompt: &str, line: &LineBuffer) -> io::Result<()> {
+    write!(stdout, "\r{prompt}{}\x1b[K", line.text)?;
+    let after = line.text[line.cursor..].chars().count();
+    if after > 0 {
+        write!(stdout, "\x1b[{after}D")?;
+    }
+    stdout.flush()
+}
+
+/// 一バイト読む（入力の終端ならNone）
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    match io::stdin().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// ESCに続くエスケープシーケンス（`[C` や `[3~` など）を読む
+fn read_escape_sequence() -> io::Result<Vec<u8>> {
+    let mut sequence = Vec::new();
+    while let Some(byte) = read_byte()? {
+        sequence.push(byte);
+        // `[` や `O` の後は英字か `~` で終わる
+        if sequence.len() > 1 && (byte.is_ascii_alphabetic() || byte == b'~') {
+            break;
+        }
+        if !matches!(sequence[0], b'[' | b'O') {
+            break;
+        }
+    }
+    Ok(sequence)
+}
+
+/// 括弧付き貼り付けの終わり（`ESC [201~`）までを読む
+fn read_pasted_text() -> io::Result<String> {
+    let mut bytes = Vec::new();
+    while let Some(byte) = read_byte()? {
+        bytes.push(byte);
+        if bytes.ends_with(PASTE_END) {
+            bytes.truncate(bytes.len() - PASTE_END.len());
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// 先頭のバイトに続くUTF-8の残りを読み、一文字にする（不正なバイト列ならNone）
+fn read_char(first: u8) -> io::Result<Option<char>> {
+    let len = match first {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        match read_byte()? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|text| text.chars().next()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ShellState, enter};
+    use std::fs;
+    use std::sync::Arc;
+
+    fn buffer(text: &str) -> LineBuffer {
+        LineBuffer {
+            text: text.to_string(),
+            cursor: text.len(),
+        }
+    }
+
+    #[test]
+    fn test_editing_keeps_cursor_on_char_boundaries() {
+        let mut line = buffer("echo 日本");
+        line.move_left();
+        line.insert('x');
+        assert_eq!(line.text, "echo 日x本");
+        line.backspace();
+        line.backspace();
+        assert_eq!(line.text, "echo 本");
+        line.kill_word();
+        assert_eq!(line.text, "本");
+        line.move_right();
+        line.kill_to_start();
+        assert_eq!((line.text.as_str(), line.cursor), ("", 0));
+    }
+
+    #[test]
+    fn test_paste_inserts_one_line_and_returns_several() {
+        let mut line = buffer("echo ");
+        assert_eq!(line.paste("日本\n"), None);
+        assert_eq!(
+            (line.text.as_str(), line.cursor),
+            ("echo 日本", "echo 日本".len())
+        );
+
+        // 入力中だった行は貼り付けの一行目につながる
+        let mut line = buffer("for i in");
+        assert_eq!(
+            line.paste(" 1 2\r\ndo echo $i\r\n\r\ndone\r\n"),
+            Some(vec![
+                "for i in 1 2".to_string(),
+                "do echo $i".to_string(),
+                String::new(),
+                "done".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_tab_completes_unique_and_common_prefixes() {
+        let _state = enter(Arc::new(ShellState::new()));
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("project-a")).unwrap();
+        fs::create_dir(dir.path().join("project-b")).unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let root = dir.path().display();
+
+        let mut line = buffer(&format!("cd {root}/p"));
+        assert_eq!(line.complete(), TabOutcome::Completed);
+        assert_eq!(line.text, format!("cd {root}/project-"));
+        assert_eq!(
+            line.complete(),
+            TabOutcome::Ambiguous(vec![
+                format!("{root}/project-a/"),
+                format!("{root}/project-b/")
+            ])
+        );
+
+        let mut line = buffer(&format!("cat {root}/n"));
+        assert_eq!(line.complete(), TabOutcome::Completed);
+        assert_eq!(line.text, format!("cat {root}/notes.txt "));
+
+        let mut line = buffer(&format!("cd {root}/n"));
+        assert_eq!(line.complete(), TabOutcome::NoMatch);
+    }
+}
@@ -0,0 +1,928 @@
+//! rucliをライブラリとして利用するためのシェル本体
+//!
+//! 対話モード・スクリプト実行・文字列の評価を `Shell` にまとめる。
+//! バイナリ（main.rs）はこの構造体の薄いラッパーになっている。
+
+mod ast;
+mod block_input;
+mod line_editor;
+
+pub use self::ast::{Diagnostic, Statement, parse_to_ast};
+
+use self::block_input::BlockInputCollector;
+use self::line_editor::Input;
+use crate::commands::Command;
+use crate::commands::execute_command;
+use crate::environment;
+use crate::error::{Result, RucliError};
+use crate::handlers;
+use crate::history::{self, add_history};
+use crate::hooks;
+use crate::options;
+use crate::output;
+use crate::parser::{self, expansion, parse_command, parse_resolved};
+use crate::state::{self, ShellState};
+use crate::trap::{self, TrapSignal};
+use log::{debug, error, info};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use std::{env, fs, io};
+
+/// `Shell::eval` の実行結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalOutput {
+    /// 標準出力に書かれた内容（UTF-8でないバイトは U+FFFD にする）
+    pub stdout: String,
+    /// 標準エラーに書かれた内容
+    pub stderr: String,
+    /// 最後に実行したコマンドの終了ステータス
+    pub status: i32,
+    /// exitコマンドが実行されたか
+    pub exited: bool,
+}
+
+/// rucliのシェル
+///
+/// 入力を一行ずつ受け取り、if/while/for/functionのブロックが閉じた時点で実行する。
+/// 変数・エイリアス・関数・履歴・ジョブはシェルごとに独立しており、
+/// 複数のシェルを同時に使っても互いに影響しない。
+///
+/// ただしカレントディレクトリ・umask・エクスポートした環境変数はプロセスで一つなので、
+/// あるシェルの `cd` や `export` は同じプロセスの他のシェルにも見える
+/// （詳しくは [`crate::state`] を参照）。
+pub struct Shell {
+    state: Arc<ShellState>,
+    block_collector: BlockInputCollector,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shell {
+    /// 新しいシェルを作成する
+    pub fn new() -> Self {
+        Shell {
+            state: Arc::new(ShellState::new()),
+            block_collector: BlockInputCollector::new(),
+        }
+    }
+
+    /// シェルの状態を取得する
+    pub fn state(&self) -> &Arc<ShellState> {
+        &self.state
+    }
+
+    /// シェルオプション（`set -o` と同じ名前）を有効・無効にする
+    ///
+    /// # Errors
+    /// * 存在しないオプション名の場合
+    pub fn set_option(&self, name: &str, enabled: bool) -> Result<()> {
+        let _state = state::enter(Arc::clone(&self.state));
+        options::set_option(name, enabled)
+    }
+
+    /// コマンドの実行前に呼ぶフックを登録する
+    ///
+    /// フックは変数を展開した後のコマンドを受け取る。エラーを返すとコマンドは実行されず、
+    /// そのエラーがコマンドの結果になる（監査やサンドボックスの制限に使う）
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rucli::Shell;
+    /// use rucli::commands::Command;
+    /// use rucli::error::RucliError;
+    ///
+    /// let mut shell = Shell::new();
+    /// shell.add_pre_command_hook(|command| match command {
+    ///     Command::Rm { path, .. } => Err(RucliError::PermissionDenied(format!("rm {path}"))),
+    ///     _ => Ok(()),
+    /// });
+    ///
+    /// let output = shell.eval("rm important.txt").unwrap();
+    /// assert!(output.stderr.contains("permission denied: rm important.txt"));
+    /// ```
+    pub fn add_pre_command_hook(
+        &self,
+        hook: impl Fn(&Command) -> Result<()> + Send + Sync + 'static,
+    ) {
+        let _state = state::enter(Arc::clone(&self.state));
+        hooks::add_pre_command_hook(Arc::new(hook));
+    }
+
+    /// コマンドの実行後に呼ぶフックを登録する
+    ///
+    /// フックはコマンド・実行にかかった時間・終了ステータスを受け取る
+    pub fn add_post_command_hook(
+        &self,
+        hook: impl Fn(&Command, Duration, i32) + Send + Sync + 'static,
+    ) {
+        let _state = state::enter(Arc::clone(&self.state));
+        hooks::add_post_command_hook(Arc::new(hook));
+    }
+
+    /// 履歴をファイルから読み込む
+    ///
+    /// # Arguments
+    /// * `file_path` - 履歴ファイル（None ならデフォルトのファイル）
+    ///
+    /// # Errors
+    /// * ファイルが読めない場合
+    pub fn load_history(&self, file_path: Option<&str>) -> Result<()> {
+        let _state = state::enter(Arc::clone(&self.state));
+        history::load_history_from_file(file_path)
+    }
+
+    /// 履歴をファイルに保存する
+    ///
+    /// # Arguments
+    /// * `file_path` - 履歴ファイル（None ならデフォルトのファイル）
+    ///
+    /// # Errors
+    /// * ファイルに書き込めない場合
+    pub fn save_history(&self, file_path: Option<&str>) -> Result<()> {
+        let _state = state::enter(Arc::clone(&self.state));
+        history::save_history_to_file(file_path)
+    }
+
+    /// 文字列を評価し、出力と終了ステータスを返す
+    ///
+    /// 出力は端末には書き出さずにキャプチャされる。複数行の入力も受け付ける。
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rucli::Shell;
+    ///
+    /// let mut shell = Shell::new();
+    /// let output = shell.eval("echo hi | grep h").unwrap();
+    ///
+    /// assert_eq!(output.stdout, "hi\n");
+    /// assert_eq!(output.status, 0);
+    /// ```
+    ///
+    /// 失敗したコマンドはエラーメッセージと終了ステータスで報告される:
+    ///
+    /// ```
+    /// use rucli::Shell;
+    ///
+    /// let mut shell = Shell::new();
+    /// let output = shell.eval("no_such_command").unwrap();
+    ///
+    /// assert_eq!(output.status, 127);
+    /// assert!(output.stderr.contains("no_such_command"));
+    /// ```
+    ///
+    /// # Errors
+    /// * 入力の終端でブロックが閉じられていない場合
+    pub fn eval(&mut self, input: &str) -> Result<EvalOutput> {
+        let _state = state::enter(Arc::clone(&self.state));
+        let (result, captured) = output::capture(|| self.run_lines(input.lines()));
+        let exited = result?;
+
+        Ok(EvalOutput {
+            stdout: String::from_utf8_lossy(&captured.stdout).into_owned(),
+            stderr: captured.stderr,
+            status: environment::get_last_status(),
+            exited,
+        })
+    }
+
+    /// 文字列を実行し、出力をそのまま端末に書き出す
+    ///
+    /// # Returns
+    /// * 最後に実行したコマンドの終了ステータス
+    ///
+    /// # Errors
+    /// * 入力の終端でブロックが閉じられていない場合
+    pub fn run(&mut self, input: &str) -> Result<i32> {
+        let _state = state::enter(Arc::clone(&self.state));
+        self.run_lines(input.lines())?;
+        Ok(environment::get_last_status())
+    }
+
+    /// スクリプトファイルを実行する
+    ///
+    /// 空行と `#` で始まる行（シバンを含む）は読み飛ばす。
+    /// 行の途中のコメントの除去と、末尾が `\` の行を次の行につなげる処理は対話モードと同じ。
+    /// verbose オプション（`set -v`）が有効な間は、各行を実行前に行番号付きで標準エラー出力に表示する
+    ///
+    /// # Returns
+    /// * スクリプトの最終ステータス（exitされた場合はその値）
+    ///
+    /// # Errors
+    /// * ファイルが存在しない・読めない場合
+    /// * ファイルの終端でブロックが閉じられていない場合
+    pub fn run_script(&mut self, path: impl AsRef<Path>) -> Result<i32> {
+        self.run_script_with_args(path, &[])
+    }
+
+    /// スクリプトファイルを引数付きで実行する
+    ///
+    /// 引数は位置パラメータ `$1`, `$2`, ... に、スクリプトのあるディレクトリの絶対パスは
+    /// `$RUCLI_SCRIPT_DIR` に設定する（隣に置いたファイルを読むのに使う）
+    ///
+    /// # Returns
+    /// * スクリプトの最終ステータス（exitされた場合はその値）
+    ///
+    /// # Errors
+    /// * ファイルが存在しない・読めない場合
+    /// * ファイルの終端でブロックが閉じられていない場合
+    pub fn run_script_with_args(&mut self, path: impl AsRef<Path>, args: &[String]) -> Result<i32> {
+        let path = path.as_ref();
+
+        // ファイルの存在確認
+        if !path.exists() {
+            return Err(RucliError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Script file {} not found", path.display()),
+            )));
+        }
+
+        // ファイル全体を読み込む
+        let contents = fs::read_to_string(path)?;
+
+        // シバンコメント、空行スキップ
+        // 行を取り出すのは前の行を実行した後なので、スクリプトの途中の set -v / +v も反映される
+        let lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim();
+                !line.is_empty() && !line.starts_with('#')
+            })
+            .inspect(|(index, line)| {
+                if options::current().verbose {
+                    output::eprint_line(&format!("{}: {line}", index + 1));
+                }
+            })
+            .map(|(_, line)| line.trim());
+
+        let _state = state::enter(Arc::clone(&self.state));
+        for (i, arg) in args.iter().enumerate() {
+            environment::set_var(&(i + 1).to_string(), arg);
+        }
+        if let Some(dir) = fs::canonicalize(path)?.parent() {
+            environment::set_var("RUCLI_SCRIPT_DIR", &dir.display().to_string());
+        }
+
+        self.run_lines(lines)?;
+        Ok(environment::get_last_status())
+    }
+
+    /// 設定ファイルを読み込んで実行する（スクリプト・`-c` の前に読む RUCLI_ENV 用）
+    ///
+    /// 空行と `#` で始まる行は読み飛ばす。対話モードと違い、失敗したコマンドがあれば
+    /// そこで読み込みをやめてエラーを返す（呼び出し元は本体を実行せずに終了する）
+    ///
+    /// # Returns
+    /// * ファイル内でexitコマンドが実行された場合はその終了ステータス
+    ///
+    /// # Errors
+    /// * ファイルが存在しない・読めない場合
+    /// * コマンドが失敗した場合（ファイル名と行番号付き）
+    /// * ファイルの終端でブロックが閉じられていない場合
+    pub fn source_env_file(&mut self, path: impl AsRef<Path>) -> Result<Option<i32>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| RucliError::RuntimeError(format!("{}: {e}", path.display())))?;
+
+        let _state = state::enter(Arc::clone(&self.state));
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if self.feed_line(line) {
+                return Ok(Some(environment::get_last_status()));
+            }
+
+            let status = environment::get_last_status();
+            if status != 0 {
+                self.block_collector = BlockInputCollector::new();
+                return Err(RucliError::RuntimeError(format!(
+                    "{}: line {}: command exited with status {status}",
+                    path.display(),
+                    index + 1
+                )));
+            }
+        }
+
+        if self.block_collector.is_incomplete() {
+            self.block_collector = BlockInputCollector::new();
+            return Err(RucliError::ParseError(format!(
+                "{}: incomplete block structure at end of file",
+                path.display()
+            )));
+        }
+
+        Ok(None)
+    }
+
+    /// EXITの trap を実行する（シェルを終了する直前に呼ぶ）
+    ///
+    /// trap は一度だけ実行され、実行中の `$?` は `status` になる
+    ///
+    /// # Arguments
+    /// * `status` - シェルの終了ステータス
+    ///
+    /// # Returns
+    /// * trap の中でexitした場合はそのステータス、それ以外は `status`
+    pub fn run_exit_trap(&mut self, status: i32) -> i32 {
+        let _state = state::enter(Arc::clone(&self.state));
+        let Some(command) = trap::take_trap(TrapSignal::Exit) else {
+            return status;
+        };
+
+        environment::set_last_status(status);
+        if handle_normal_command(&command) {
+            environment::get_last_status()
+        } else {
+            status
+        }
+    }
+
+    /// 標準入力から対話的にコマンドを読み込んで実行する
+    ///
+    /// # Returns
+    /// * exitコマンド（または入力の終端）で終了した時のステータス
+    pub fn run_interactive(&mut self) -> Result<i32> {
+        let _state = state::enter(Arc::clone(&self.state));
+
+        // 起動時の作業ディレクトリを記録（デバッグ用）
+        let initial_dir = env::current_dir()?;
+        debug!("Initial working directory: {initial_dir:?}");
+
+        info!("Starting rucli...");
+        output::print_line("Hello, rucli!");
+
+        // 端末で対話している間だけ、interactive-clobber などの確認を行う
+        self.state
+            .interactive
+            .store(line_editor::is_available(), Ordering::Relaxed);
+        let result = self.read_eval_loop();
+        self.state.interactive.store(false, Ordering::Relaxed);
+        result
+    }
+
+    /// 入力の終端かexitまで、一行ずつ読んで実行する
+    fn read_eval_loop(&mut self) -> Result<i32> {
+        loop {
+            // 前のコマンドの間に終了したバックグラウンドジョブを通知する
+            if !self.block_collector.is_incomplete() {
+                for notice in handlers::job_notifications() {
+                    output::eprint_line(&notice);
+                }
+            }
+
+            // 入力の終端ならexitと同じ扱い（プロンプトはブロックの途中かどうかで変わる）
+            let exited = match read_input(self.block_collector.get_prompt())? {
+                None => break,
+                Some(Input::Line(input)) => {
+                    debug!("Received input: {input}");
+                    self.feed_line(&input)
+                }
+                // 貼り付けられた複数行は確認してから実行する
+                Some(Input::Paste(lines)) => self.run_pasted_lines(&lines),
+            };
+            if exited {
+                break; // Exitコマンドでループを終了
+            }
+        }
+
+        Ok(environment::get_last_status())
+    }
+
+    /// 複数行を順に渡して実行する
+    ///
+    /// # Returns
+    /// * exitコマンドが実行された場合は true
+    fn run_lines<'a>(&mut self, lines: impl IntoIterator<Item = &'a str>) -> Result<bool> {
+        for line in lines {
+            if self.feed_line(line) {
+                return Ok(true);
+            }
+
+            // set -e が有効なら、失敗したところで終了する
+            if options::current().errexit && environment::get_last_status() != 0 {
+                return Ok(true);
+            }
+        }
+
+        // 終端で未完了のブロックがある場合
+        if self.block_collector.is_incomplete() {
+            self.block_collector = BlockInputCollector::new();
+            return Err(RucliError::ParseError(
+                "Incomplete block structure at end of input".to_string(),
+            ));
+        }
+
+        Ok(false)
+    }
+
+    /// 一行を渡し、ブロックが完成していれば実行する
+    ///
+    /// # Returns
+    /// * exitコマンドが実行された場合は true
+    fn feed_line(&mut self, line: &str) -> bool {
+        // ブロック入力の処理
+        if self.block_collector.add_line(line) {
+            // まだ入力継続中
+            return false;
+        }
+
+        // 入力完了 - コマンドを取得
+        let complete_input = self.block_collector.get_complete_command();
+        self.block_collector = BlockInputCollector::new(); // リセット
+
+        // 空入力なら次へ
+        if complete_input.trim().is_empty() {
+            return false;
+        }
+
+        let exited = if complete_input.trim() == "paste" {
+            match read_paste_lines() {
+                Ok(lines) => self.run_pasted_lines(&lines),
+                Err(e) => {
+                    output::eprint_line(&e.to_string());
+                    false
+                }
+            }
+        } else if parser::contains_heredoc(&complete_input) {
+            handle_heredoc_command(complete_input.trim_start())
+        } else {
+            handle_normal_command(&complete_input)
+        };
+
+        exited || run_signal_traps()
+    }
+
+    /// 貼り付けられた複数行を行番号付きで表示し、確認できたら一行ずつ実行する
+    ///
+    /// 行はブロック入力として渡すので、ブロックが閉じた時点で実行される。
+    /// 断った場合は何も実行せず、終了ステータスは1になる
+    ///
+    /// # Returns
+    /// * exitコマンドが実行された場合は true
+    fn run_pasted_lines(&mut self, lines: &[String]) -> bool {
+        if lines.is_empty() {
+            return false;
+        }
+
+        for (index, line) in lines.iter().enumerate() {
+            output::print_line(&format!("{:>4}  {line}", index + 1));
+        }
+
+        let count = match lines.len() {
+            1 => "1 line".to_string(),
+            n => format!("{n} lines"),
+        };
+        let answer = match read_input(&format!("Run {count}? [y/N] ")) {
+            Ok(Some(Input::Line(answer))) => answer,
+            Ok(_) => String::new(),
+            Err(e) => {
+                output::eprint_line(&e.to_string());
+                String::new()
+            }
+        };
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            output::eprint_line(&format!("paste: {count} not run"));
+            environment::set_last_status(1);
+            return false;
+        }
+
+        lines.iter().any(|line| self.feed_line(line))
+    }
+}
+
+/// 受け取ったシグナルの trap を実行する（`$?` は実行前の値に戻す）
+///
+/// # Returns
+/// * trap の中でexitした場合は true
+fn run_signal_traps() -> bool {
+    for signal in trap::take_pending_signals() {
+        let Some(command) = trap::get_trap(signal) else {
+            continue;
+        };
+
+        debug!("Running {} trap: {command}", signal.name());
+        let status = environment::get_last_status();
+        if handle_normal_command(&command) {
+            return true;
+        }
+        environment::set_last_status(status);
+    }
+
+    false
+}
+
+// プロンプトを表示し、入力された文字列を読み取る（終端ならNone）
+//
+// 端末で対話している場合は、Tabで補完でき、複数行の貼り付けをまとめて受け取る行入力を使う
+fn read_input(prompt: &str) -> Result<Option<Input>> {
+    if line_editor::is_available() {
+        return Ok(line_editor::read_line(prompt)?.map(|input| match input {
+            Input::Line(line) => Input::Line(line.trim_end().to_string()),
+            paste => paste,
+        }));
+    }
+
+    output::write_stdout(prompt);
+    let mut input = String::new();
+
+    // 文字列読み取り
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Ok(None);
+    }
+
+    // 改行文字をトリミングしてString型にしてから返す
+    // 先頭の空白は履歴のignorespace判定に使うので残す
+    Ok(Some(Input::Line(input.trim_end().to_string())))
+}
+
+/// `paste` の入力を `.` だけの行（または入力の終端）まで読み取る
+fn read_paste_lines() -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    while let Some(input) = read_input("paste> ")? {
+        let chunk = match input {
+            Input::Line(line) => vec![line],
+            Input::Paste(pasted) => pasted,
+        };
+        for line in chunk {
+            if line.trim() == "." {
+                return Ok(lines);
+            }
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+/// ヒアドキュメント付きコマンドを処理
+fn handle_heredoc_command(input: &str) -> bool {
+    if let Some((cmd_str, delimiter, strip_indent)) = parser::parse_heredoc_header(input) {
+        debug!(
+            "Heredoc header: cmd='{cmd_str}', delimiter='{delimiter}', strip_indent={strip_indent}"
+        );
+
+        // 内容を収集
+        let content = read_heredoc_content(&delimiter, strip_indent);
+        debug!(
+            "Collected heredoc content: {} lines",
+            content.lines().count()
+        );
+
+        // 展開を適用
+        let expanded_content = environment::expand_variables(&content);
+        let final_content = match environment::expand_command_substitution(&expanded_content) {
+            Ok(substituted) => substituted,
+            Err(_) => expanded_content,
+        };
+
+        // コマンドを実行
+        return execute_with_input(&cmd_str, &final_content);
+    }
+    false
+}
+
+/// 通常のコマンドを処理
+fn handle_normal_command(input: &str) -> bool {
+    // 履歴展開を実行
+    let expanded_input = if expansion::contains_history_expansion(input) {
+        match expansion::expand_history(input) {
+            Ok(expanded) => expanded,
+            Err(e) => {
+                output::eprint_line(&e.to_string());
+                return false; // エラーでも継続
+            }
+        }
+    } else {
+        input.to_string()
+    };
+
+    add_history(expanded_input.clone());
+
+    execute_parsed(expanded_input.trim_start(), None)
+}
+
+/// 入力付きでコマンドを実行
+fn execute_with_input(cmd_str: &str, input: &str) -> bool {
+    execute_parsed(cmd_str, Some(input.as_bytes()))
+}
+
+/// コマンドをパースして実行し、終了ステータスを記録する
+///
+/// # Returns
+/// * exitコマンドが実行された場合は true
+fn execute_parsed(cmd_str: &str, input: Option<&[u8]>) -> bool {
+    // 前のコマンドへの中断要求は持ち越さない
+    state::current().clear_interrupt();
+
+    // 一つのコマンドだけの行はすぐに実行するので、ここで解決する（存在しなければパースのエラー）
+    let parsed = parse_command(cmd_str).and_then(|command| match command {
+        Command::Unresolved { text } => parse_resolved(&text),
+        command => Ok(command),
+    });
+    match parsed {
+        Ok(command) => {
+            debug!("Command parsed successfully");
+            let start = Instant::now();
+            match execute_command(command, input) {
+                Ok(should_exit) => {
+                    if should_exit {
+                        return true; // 終了シグナル
+                    }
+                    environment::set_last_status(0);
+                }
+                Err(err) => {
+                    // 出力付きで失敗したコマンドはステータスだけ記録する
+                    if !matches!(err, RucliError::CommandFailed { .. }) {
+                        error!("Command execution failed: {err}");
+                        output::eprint_line(&err.to_string());
+                    }
+                    environment::set_last_status(err.exit_code());
+                }
+            }
+            let duration = start.elapsed().as_secs_f64() * 1000.0;
+            debug!("処理時間: {duration:?}ms");
+        }
+        Err(error) => {
+            debug!("Parse error occurred: {error}");
+            output::eprint_line(&error.to_string());
+            environment::set_last_status(error.exit_code());
+        }
+    }
+
+    false // 継続
+}
+
+/// ヒアドキュメントの内容を読み取る
+fn read_heredoc_content(delimiter: &str, strip_indent: bool) -> String {
+    let mut lines = Vec::new();
+    loop {
+        // heredocプロンプト表示
+        output::write_stdout("heredoc> ");
+
+        let mut line = String::new();
+        // 一行読み取り（終端なら打ち切る）
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        // デリミタと完全に一致したらbreak
+        let line = line.trim_end_matches('\n').trim_end_matches('\r');
+        if delimiter == line {
+            break;
+        }
+
+        // strip_indentがtrueなら先頭タブを削除
+        let processed_line = if strip_indent {
+            line.strip_prefix('\t').unwrap_or(line)
+        } else {
+            line
+        };
+
+        lines.push(processed_line.to_string());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_captures_output_and_status() {
+        let mut shell = Shell::new();
+
+        let output = shell.eval("echo captured").unwrap();
+        assert_eq!(output.stdout, "captured\n");
+        assert_eq!(output.stderr, "");
+        assert_eq!(output.status, 0);
+        assert!(!output.exited);
+
+        let output = shell.eval("repeat abc hi").unwrap();
+        assert_eq!(output.status, 2);
+        assert!(!output.stderr.is_empty());
+    }
+
+    #[test]
+    fn test_post_command_hook_counts_commands() {
+        use std::sync::Mutex;
+
+        let mut shell = Shell::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&seen);
+        shell.add_post_command_hook(move |command, _elapsed, status| {
+            let name = format!("{command:?}");
+            let name = name
+                .split([' ', '{', '('])
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            recorder.lock().unwrap().push((name, status));
+        });
+
+        shell
+            .eval("echo one\ncat missing.txt\nfor i in 1 2\ndo\n  echo $i\ndone")
+            .unwrap();
+
+        // forの中の各コマンドとfor全体で呼ばれる
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            vec![
+                ("Echo".to_string(), 0),
+                ("Cat".to_string(), 1),
+                ("Echo".to_string(), 0),
+                ("Echo".to_string(), 0),
+                ("For".to_string(), 0),
+            ]
+        );
+
+        // 別のシェルには影響しない
+        let output = Shell::new().eval("echo other").unwrap();
+        assert_eq!(output.stdout, "other\n");
+        assert_eq!(seen.len(), 5);
+    }
+
+    #[test]
+    fn test_pre_command_hook_blocks_rm() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("keep.txt");
+        fs::write(&target, "data").unwrap();
+
+        let mut shell = Shell::new();
+        shell.add_pre_command_hook(|command| match command {
+            Command::Rm { path, .. } => Err(RucliError::PermissionDenied(format!(
+                "rm is not allowed: {path}"
+            ))),
+            _ => Ok(()),
+        });
+
+        // 変数を展開した後のコマンドが渡され、拒否されたコマンドは実行されない
+        let output = shell
+            .eval(&format!(
+                "FILE={}\nrm $FILE\necho still here",
+                target.display()
+            ))
+            .unwrap();
+        assert!(target.exists());
+        assert!(
+            output
+                .stderr
+                .contains(&format!("rm is not allowed: {}", target.display()))
+        );
+        assert_eq!(output.stdout, "still here\n");
+    }
+
+    #[test]
+    fn test_panicking_background_job_is_marked_failed() {
+        let mut shell = Shell::new();
+        shell.add_pre_command_hook(|command| match command {
+            Command::Sleep { .. } => panic!("hook panicked"),
+            _ => Ok(()),
+        });
+
+        // ジョブのスレッドがパニックしても、ジョブが失敗になるだけでシェルは続く
+        shell.eval("sleep 0 &").unwrap();
+        let _state = state::enter(Arc::clone(shell.state()));
+        let start = Instant::now();
+        while !crate::job::get_job(1)
+            .is_some_and(|job| matches!(job.status, crate::job::JobStatus::Failed))
+        {
+            assert!(start.elapsed().as_secs() < 5, "job was not marked failed");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        assert_eq!(
+            shell.eval("echo still alive").unwrap().stdout,
+            "still alive\n"
+        );
+    }
+
+    #[test]
+    fn test_eval_multiline_block() {
+        let mut shell = Shell::new();
+
+        let output = shell
+            .eval("for i in 1 2\ndo\n  echo item-$i\ndone")
+            .unwrap();
+        assert_eq!(output.stdout, "item-1\nitem-2\n");
+    }
+
+    #[test]
+    fn test_eval_incomplete_block_is_error() {
+        let mut shell = Shell::new();
+
+        assert!(shell.eval("for i in 1 2\ndo").is_err());
+        // 未完了のブロックは破棄され、次の評価に影響しない
+        assert_eq!(shell.eval("echo next").unwrap().stdout, "next\n");
+    }
+
+    #[test]
+    fn test_eval_exit() {
+        let mut shell = Shell::new();
+
+        let output = shell.eval("exit 4\necho unreachable").unwrap();
+        assert!(output.exited);
+        assert_eq!(output.status, 4);
+        assert!(!output.stdout.contains("unreachable"));
+    }
+
+    #[test]
+    fn test_jobs_json_output() {
+        let mut shell = Shell::new();
+        shell.eval("sleep 1 &").unwrap();
+
+        let output = shell.eval("jobs --json").unwrap();
+        let jobs: Vec<serde_json::Value> = serde_json::from_str(&output.stdout).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0]["id"], 1);
+        assert_eq!(jobs[0]["status"], "Running");
+        assert!(jobs[0]["command"].as_str().unwrap().contains("sleep"));
+    }
+
+    #[test]
+    fn test_shells_do_not_share_state() {
+        let mut first = Shell::new();
+        let mut second = Shell::new();
+
+        first
+            .eval("SHARED_VAR=first\nalias greet=version\nfunction only_first() { echo f; }")
+            .unwrap();
+
+        let output = second.eval("echo [$SHARED_VAR]").unwrap();
+        assert_eq!(output.stdout, "[]\n");
+        assert_eq!(second.eval("greet").unwrap().status, 127);
+        assert_eq!(second.eval("only_first").unwrap().status, 127);
+
+        assert_eq!(first.eval("echo $SHARED_VAR").unwrap().stdout, "first\n");
+        assert_eq!(first.eval("greet").unwrap().status, 0);
+        assert_eq!(first.eval("only_first").unwrap().stdout, "f\n");
+    }
+
+    #[test]
+    fn test_sleep_is_interrupted_by_foreground_interrupt() {
+        let mut shell = Shell::new();
+        let state = Arc::clone(shell.state());
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            state.interrupt();
+        });
+
+        let start = Instant::now();
+        let output = shell.eval("sleep 30").unwrap();
+        interrupter.join().unwrap();
+
+        assert!(start.elapsed().as_secs() < 5);
+        assert_eq!(output.status, 130);
+
+        // 中断の要求は次のコマンドに持ち越さない
+        assert_eq!(shell.eval("sleep 0.01").unwrap().status, 0);
+    }
+
+    #[test]
+    fn test_sleep_job_is_cancelled() {
+        let mut shell = Shell::new();
+        shell.eval("sleep 30 &").unwrap();
+
+        let _state = state::enter(Arc::clone(shell.state()));
+        assert!(crate::job::cancel_job(1));
+        assert!(!crate::job::cancel_job(99));
+
+        // ジョブはすぐに終了する
+        let start = Instant::now();
+        while !crate::job::list_jobs().is_empty() {
+            assert!(start.elapsed().as_secs() < 5, "job was not cancelled");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_shells_run_in_parallel() {
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let mut shell = Shell::new();
+                    shell.eval(&format!("N={i}")).unwrap();
+                    for _ in 0..20 {
+                        let output = shell.eval("echo $N").unwrap();
+                        assert_eq!(output.stdout, format!("{i}\n"));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! シェルの状態（変数・エイリアス・関数・履歴・ジョブ・オプション・trap）を管理するモジュール
+//!
+//! 状態は `Shell` ごとに `ShellState` として持ち、実行中はそのスレッドの
+//! 「現在の状態」として登録する。environment / alias / functions / history / job / options / trap の
+//! 各関数は登録された状態を操作するため、複数の `Shell` が同じプロセス内で
+//! 互いに干渉せずに動作できる。
+//! 状態が登録されていないスレッドでは、スレッドごとの既定の状態が使われる。
+//!
+//! カレントディレクトリ・umask・エクスポートした環境変数（`std::env`）は `ShellState` に
+//! 含まれず、プロセスで一つのものを使う。そのため、あるシェルの `cd` や `export` は
+//! 同じプロセスの他のシェルにも見える。サブシェルは実行後に元へ戻し、
+//! バックグラウンドジョブのスレッドは（Linuxでは）カレントディレクトリと umask を
+//! 切り離す（`job::run_as_job`）が、並行して動く複数の `Shell` は区別しない。
+//!
+//! 状態のロックは `LockExt::lock_or_recover` で取る。バックグラウンドジョブのスレッドが
+//! ロック中にパニックしても、シェルは中身をそのまま使って動作を続ける。
+
+use crate::commands::Command;
+use crate::history::History;
+use crate::hooks::CommandHooks;
+use crate::job::Job;
+use crate::options::ShellOptions;
+use crate::output::SharedWriter;
+use crate::trap::TrapSignal;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+/// 一つのシェルが持つ状態
+///
+/// バックグラウンドジョブと共有するため、`Arc` で包んで使う
+#[derive(Default)]
+pub struct ShellState {
+    pub(crate) vars: Mutex<HashMap<String, String>>, // セッション変数
+    pub(crate) aliases: Mutex<HashMap<String, String>>, // エイリアス
+    pub(crate) functions: Mutex<HashMap<String, Command>>, // シェル関数
+    pub(crate) history: Mutex<History>,              // コマンド履歴
+    pub(crate) jobs: Mutex<Vec<Job>>,                // バックグラウンドジョブ
+    pub(crate) job_counter: Mutex<u32>,              // 最後に割り当てたジョブID
+    pub(crate) interrupted: AtomicBool,              // フォアグラウンドの中断要求
+    pub(crate) interactive: AtomicBool, // 端末のプロンプトから対話的に入力を読んでいるか
+    pub(crate) options: Mutex<ShellOptions>, // set -o で設定するオプション
+    pub(crate) stdout: Mutex<Option<SharedWriter>>, // exec で変更した標準出力の書き出し先
+    pub(crate) dir_stack: Mutex<Vec<PathBuf>>, // pushd で積んだディレクトリ（最後が先頭）
+    pub(crate) started: StartTime,      // シェルを作成した時刻（$SECONDS の基準）
+    pub(crate) traps: Mutex<BTreeMap<TrapSignal, String>>, // trap で登録したコマンド
+    pub(crate) hooks: Mutex<CommandHooks>, // コマンドの実行前後に呼ぶフック
+}
+
+/// シェルを作成した時刻
+pub(crate) struct StartTime(pub(crate) Instant);
+
+impl Default for StartTime {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl ShellState {
+    /// 空の状態を作成する
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// フォアグラウンドで実行中のコマンドに中断を要求する
+    ///
+    /// 要求は次のコマンドを実行する前に取り消される
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+    }
+
+    /// 中断の要求を取り消す
+    pub(crate) fn clear_interrupt(&self) {
+        self.interrupted.store(false, Ordering::Relaxed);
+    }
+
+    /// 端末のプロンプトから対話的に入力を読んでいるか（スクリプトや `-c` では false）
+    pub(crate) fn is_interactive(&self) -> bool {
+        self.interactive.load(Ordering::Relaxed)
+    }
+}
+
+/// パニックしたスレッドが残したロックの汚染（poison）を無視してロックを取る
+pub trait LockExt<T: ?Sized> {
+    /// ロックを取得する（汚染されていれば中身をそのまま返す）
+    fn lock_or_recover(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T: ?Sized> LockExt<T> for Mutex<T> {
+    fn lock_or_recover(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// サブシェルの実行前に保存しておく状態
+pub(crate) struct Snapshot {
+    vars: HashMap<String, String>,
+    aliases: HashMap<String, String>,
+    functions: HashMap<String, Command>,
+    options: ShellOptions,
+    stdout: Option<SharedWriter>,
+    dir_stack: Vec<PathBuf>,
+}
+
+impl ShellState {
+    /// 変数・エイリアス・関数・オプション・標準出力の書き出し先・ディレクトリスタックを保存する
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            vars: self.vars.lock_or_recover().clone(),
+            aliases: self.aliases.lock_or_recover().clone(),
+            functions: self.functions.lock_or_recover().clone(),
+            options: *self.options.lock_or_recover(),
+            stdout: self.stdout.lock_or_recover().clone(),
+            dir_stack: self.dir_stack.lock_or_recover().clone(),
+        }
+    }
+
+    /// 変数・エイリアス・関数・オプション・標準出力の書き出し先・ディレクトリスタックを複製した、
+    /// 共有しない新しい状態を作る（バックグラウンドのサブシェル用）
+    pub(crate) fn fork(&self) -> ShellState {
+        let snapshot = self.snapshot();
+        let forked = ShellState::new();
+        forked.restore(snapshot);
+        forked
+    }
+
+    /// 保存しておいた状態に戻す
+    pub(crate) fn restore(&self, snapshot: Snapshot) {
+        *self.vars.lock_or_recover() = snapshot.vars;
+        *self.aliases.lock_or_recover() = snapshot.aliases;
+        *self.functions.lock_or_recover() = snapshot.functions;
+        *self.options.lock_or_recover() = snapshot.options;
+        *self.stdout.lock_or_recover() = snapshot.stdout;
+        *self.dir_stack.lock_or_recover() = snapshot.dir_stack;
+    }
+}
+
+thread_local! {
+    // 登録中の状態のスタック（最後の要素が現在の状態）
+    static CURRENT: RefCell<Vec<Arc<ShellState>>> = const { RefCell::new(Vec::new()) };
+
+    // 状態が登録されていない場合に使うスレッドごとの状態
+    static THREAD_DEFAULT: Arc<ShellState> = Arc::new(ShellState::new());
+}
+
+/// 状態を登録し、スコープを抜けると解除されるガード
+pub struct StateGuard;
+
+impl Drop for StateGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.borrow_mut().pop());
+    }
+}
+
+/// 状態を現在のスレッドの「現在の状態」として登録する
+///
+/// # Arguments
+/// * `state` - 登録する状態
+///
+/// # Returns
+/// * ドロップ時に登録を解除するガード
+pub fn enter(state: Arc<ShellState>) -> StateGuard {
+    CURRENT.with(|current| current.borrow_mut().push(state));
+    StateGuard
+}
+
+/// 現在の状態を取得する
+///
+/// # Returns
+/// * 登録中の状態、なければスレッドごとの既定の状態
+pub fn current() -> Arc<ShellState> {
+    CURRENT
+        .with(|current| current.borrow().last().cloned())
+        .unwrap_or_else(|| THREAD_DEFAULT.with(Arc::clone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{get_session_var, set_var};
+
+    #[test]
+    fn test_states_are_isolated() {
+        let first = Arc::new(ShellState::new());
+        let second = Arc::new(ShellState::new());
+
+        {
+            let _state = enter(Arc::clone(&first));
+            set_var("STATE_TEST", "first");
+        }
+        {
+            let _state = enter(Arc::clone(&second));
+            assert_eq!(get_session_var("STATE_TEST"), None);
+            set_var("STATE_TEST", "second");
+        }
+
+        let _state = enter(first);
+        assert_eq!(get_session_var("STATE_TEST"), Some("first".to_string()));
+    }
+
+    #[test]
+    fn test_nested_enter_restores_outer_state() {
+        let outer = Arc::new(ShellState::new());
+        let _outer = enter(Arc::clone(&outer));
+        set_var("NESTED_TEST", "outer");
+
+        {
+            let _inner = enter(Arc::new(ShellState::new()));
+            assert_eq!(get_session_var("NESTED_TEST"), None);
+        }
+
+        assert!(Arc::ptr_eq(&current(), &outer));
+        assert_eq!(get_session_var("NESTED_TEST"), Some("outer".to_string()));
+    }
+
+    #[test]
+    fn test_forked_state_does_not_share_changes() {
+        let original = Arc::new(ShellState::new());
+        let _outer = enter(Arc::clone(&original));
+        set_var("FORK_TEST", "before");
+
+        {
+            let _forked = enter(Arc::new(original.fork()));
+            assert_eq!(get_session_var("FORK_TEST"), Some("before".to_string()));
+            set_var("FORK_TEST", "inside");
+        }
+
+        assert_eq!(get_session_var("FORK_TEST"), Some("before".to_string()));
+    }
+
+    #[test]
+    fn test_poisoned_lock_is_recovered() {
+        let state = Arc::new(ShellState::new());
+        let shared = Arc::clone(&state);
+
+        // ロック中にパニックしたスレッドがあっても変数を読み書きできる
+        let _ = std::thread::spawn(move || {
+            let _vars = shared.vars.lock_or_recover();
+            panic!("panic while holding the lock");
+        })
+        .join();
+        assert!(state.vars.is_poisoned());
+
+        let _state = enter(state);
+        set_var("POISON_TEST", "ok");
+        assert_eq!(get_session_var("POISON_TEST"), Some("ok".to_string()));
+    }
+}
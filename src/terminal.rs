@@ -0,0 +1,38 @@
+//! 端末の設定を切り替えるモジュール
+//!
+//! 行入力（`shell::line_editor`）と内蔵のページャ（`pager`）は、どちらも
+//! Enterを待たずに一キーずつ読むために端末をカノニカルモードから外す。
+
+use std::io::{self, Write};
+
+/// 端末をEnterを待たずに一キーずつ読み、入力を表示しないモードにし、ドロップ時に元へ戻すガード
+pub struct KeyMode(libc::termios);
+
+impl KeyMode {
+    /// 標準入力の端末をキー入力のモードにする
+    ///
+    /// # Errors
+    /// - 標準入力が端末でないなど、端末の設定を読み書きできない場合
+    pub fn enable() -> io::Result<Self> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut keys = original;
+        keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+        keys.c_cc[libc::VMIN] = 1;
+        keys.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self(original))
+    }
+}
+
+impl Drop for KeyMode {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
@@ -0,0 +1,171 @@
+//! trコマンドの文字集合の解釈と、文字の置換・削除・圧縮を行うモジュール
+
+use crate::error::{Result, RucliError};
+use std::collections::HashMap;
+
+/// 文字集合の表記（`a-z`、`\n`、`\t`、`\\`）を文字の列に展開する
+///
+/// 先頭・末尾の `-` は文字そのものとして扱う
+///
+/// # Arguments
+/// * `set` - 文字集合の表記
+///
+/// # Errors
+/// - 範囲が逆順の場合（`z-a`）
+/// - 末尾が `\` だけの場合
+pub fn parse_set(set: &str) -> Result<Vec<char>> {
+    // エスケープを先に解決する
+    let mut chars = Vec::new();
+    let mut iter = set.chars();
+    while let Some(ch) = iter.next() {
+        if ch != '\\' {
+            chars.push((ch, false));
+            continue;
+        }
+
+        let escaped = match iter.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('\\') => '\\',
+            Some('-') => '-',
+            Some(other) => other,
+            None => {
+                return Err(RucliError::InvalidArgument(format!(
+                    "tr: '{set}' ends with an unescaped backslash"
+                )));
+            }
+        };
+        chars.push((escaped, true));
+    }
+
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, _) = chars[i];
+
+        // エスケープされていない `-` が間にあれば範囲
+        if i + 2 < chars.len() && chars[i + 1] == ('-', false) {
+            let (end, _) = chars[i + 2];
+            if end < start {
+                return Err(RucliError::InvalidArgument(format!(
+                    "tr: range '{start}-{end}' is in reverse order"
+                )));
+            }
+            result.extend(start..=end);
+            i += 3;
+        } else {
+            result.push(start);
+            i += 1;
+        }
+    }
+
+    Ok(result)
+}
+
+/// 入力の文字を置換・削除し、連続する文字を圧縮する
+///
+/// 処理は削除（`delete`）または置換、続いて圧縮（`squeeze`）の順に行う。
+/// 圧縮の対象は `set2` があればその文字、なければ `set1` の文字。
+///
+/// # Arguments
+/// * `input` - 入力テキスト
+/// * `set1` - 置換元または削除する文字
+/// * `set2` - 置換先の文字（`set1` より短ければ最後の文字で埋める）
+/// * `delete` - `set1` の文字を削除するか
+/// * `squeeze` - 連続する同じ文字を一つにまとめるか
+pub fn translate(input: &str, set1: &[char], set2: &[char], delete: bool, squeeze: bool) -> String {
+    // 同じ文字が複数回あれば後の対応を使う
+    let mapping: HashMap<char, char> = match set2.last() {
+        Some(&last) if !delete => set1
+            .iter()
+            .enumerate()
+            .map(|(i, &from)| (from, *set2.get(i).unwrap_or(&last)))
+            .collect(),
+        _ => HashMap::new(),
+    };
+    let squeeze_set = if set2.is_empty() { set1 } else { set2 };
+
+    let mut output = String::with_capacity(input.len());
+    let mut previous = None;
+
+    for ch in input.chars() {
+        if delete && set1.contains(&ch) {
+            continue;
+        }
+
+        let ch = mapping.get(&ch).copied().unwrap_or(ch);
+        if squeeze && previous == Some(ch) && squeeze_set.contains(&ch) {
+            continue;
+        }
+
+        output.push(ch);
+        previous = Some(ch);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(s: &str) -> Vec<char> {
+        parse_set(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_set_ranges_and_escapes() {
+        assert_eq!(set("a-e"), vec!['a', 'b', 'c', 'd', 'e']);
+        assert_eq!(set("0-2x"), vec!['0', '1', '2', 'x']);
+        assert_eq!(set("\\n\\t\\\\"), vec!['\n', '\t', '\\']);
+        // 先頭・末尾・エスケープされた `-` は文字そのもの
+        assert_eq!(set("-a-"), vec!['-', 'a', '-']);
+        assert_eq!(set("a\\-c"), vec!['a', '-', 'c']);
+
+        assert!(parse_set("z-a").is_err());
+        assert!(parse_set("ab\\").is_err());
+    }
+
+    #[test]
+    fn test_translate_extends_set2_with_last_char() {
+        assert_eq!(
+            translate("hello world", &set("a-z"), &set("A-Z"), false, false),
+            "HELLO WORLD"
+        );
+        assert_eq!(
+            translate("abcd", &set("abcd"), &set("xy"), false, false),
+            "xyyy"
+        );
+        assert_eq!(
+            translate("a b\n", &set(" \\n"), &set("_"), false, false),
+            "a_b_"
+        );
+    }
+
+    #[test]
+    fn test_translate_delete() {
+        assert_eq!(
+            translate("h3ll0 w0rld", &set("0-9"), &[], true, false),
+            "hll wrld"
+        );
+        assert_eq!(translate("a\nb\n", &set("\\n"), &[], true, false), "ab");
+    }
+
+    #[test]
+    fn test_translate_squeeze() {
+        assert_eq!(
+            translate("aaabbbccc", &set("ab"), &[], false, true),
+            "abccc"
+        );
+        // 置換後の文字（set2）で圧縮する
+        assert_eq!(
+            translate("a  b   c", &set(" "), &set("_"), false, true),
+            "a_b_c"
+        );
+        // 削除してからset2で圧縮する
+        assert_eq!(
+            translate("a1a2aab", &set("0-9"), &set("a"), true, true),
+            "ab"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! `trap` で登録したコマンド（終了時・シグナル受信時に実行する）を管理するモジュール
+//!
+//! 登録内容はシェルの状態に持つ。INT / TERM は trap を登録したときだけシグナルハンドラを設定し、
+//! ハンドラは受け取ったことを記録するだけにする。記録したシグナルの trap は、
+//! シェルがコマンドの区切りで `take_pending_signals` を呼んで実行する。
+//! シグナルハンドラはプロセスに一つなので、受け取ったシグナルは最初に確認したシェルが処理する。
+
+use crate::state::{self, LockExt};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// trap を登録できる条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum TrapSignal {
+    Exit, // シェルの終了時
+    Int,  // SIGINT（Ctrl-C）
+    Term, // SIGTERM
+}
+
+/// 受け取ったがまだ trap を実行していないシグナル
+static PENDING_INT: AtomicBool = AtomicBool::new(false);
+static PENDING_TERM: AtomicBool = AtomicBool::new(false);
+
+impl TrapSignal {
+    /// 名前または番号から取得する（`SIG` の接頭辞と大文字・小文字は問わない）
+    ///
+    /// 例: `EXIT` / `0`、`INT` / `SIGINT` / `2`、`TERM` / `SIGTERM` / `15`
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+        match name.strip_prefix("SIG").unwrap_or(&name) {
+            "EXIT" | "0" => Some(TrapSignal::Exit),
+            "INT" | "2" => Some(TrapSignal::Int),
+            "TERM" | "15" => Some(TrapSignal::Term),
+            _ => None,
+        }
+    }
+
+    /// 表示用の名前
+    pub fn name(self) -> &'static str {
+        match self {
+            TrapSignal::Exit => "EXIT",
+            TrapSignal::Int => "INT",
+            TrapSignal::Term => "TERM",
+        }
+    }
+
+    /// 対応するシグナル番号とその受信記録（EXITはシグナルではないのでNone）
+    fn signal(self) -> Option<(libc::c_int, &'static AtomicBool)> {
+        match self {
+            TrapSignal::Exit => None,
+            TrapSignal::Int => Some((libc::SIGINT, &PENDING_INT)),
+            TrapSignal::Term => Some((libc::SIGTERM, &PENDING_TERM)),
+        }
+    }
+}
+
+/// シグナルを受け取ったことを記録する（シグナルハンドラの中ではこれ以外のことをしない）
+extern "C" fn record_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGINT => PENDING_INT.store(true, Ordering::Relaxed),
+        libc::SIGTERM => PENDING_TERM.store(true, Ordering::Relaxed),
+        _ => {}
+    }
+}
+
+/// trap を登録する（同じ条件の trap は置き換える）
+pub fn set_trap(signal: TrapSignal, command: &str) {
+    state::current()
+        .traps
+        .lock_or_recover()
+        .insert(signal, command.to_string());
+
+    if let Some((number, _)) = signal.signal() {
+        let handler: extern "C" fn(libc::c_int) = record_signal;
+        unsafe {
+            libc::signal(number, handler as libc::sighandler_t);
+        }
+    }
+}
+
+/// trap を削除し、シグナルの扱いを既定に戻す
+pub fn remove_trap(signal: TrapSignal) {
+    state::current().traps.lock_or_recover().remove(&signal);
+
+    if let Some((number, pending)) = signal.signal() {
+        unsafe {
+            libc::signal(number, libc::SIG_DFL);
+        }
+        pending.store(false, Ordering::Relaxed);
+    }
+}
+
+/// 登録されている trap のコマンドを取得
+pub fn get_trap(signal: TrapSignal) -> Option<String> {
+    state::current()
+        .traps
+        .lock_or_recover()
+        .get(&signal)
+        .cloned()
+}
+
+/// 登録されている trap を取り出して削除する（EXITの trap を一度だけ実行するため）
+pub fn take_trap(signal: TrapSignal) -> Option<String> {
+    state::current().traps.lock_or_recover().remove(&signal)
+}
+
+/// 登録されている trap をすべて取得（EXIT, INT, TERM の順）
+pub fn list_traps() -> Vec<(TrapSignal, String)> {
+    state::current()
+        .traps
+        .lock_or_recover()
+        .iter()
+        .map(|(signal, command)| (*signal, command.clone()))
+        .collect()
+}
+
+/// trap を実行していないシグナルがあるか
+pub fn signal_pending() -> bool {
+    PENDING_INT.load(Ordering::Relaxed) || PENDING_TERM.load(Ordering::Relaxed)
+}
+
+/// 受け取ったシグナルを取り出し、記録を消す
+pub fn take_pending_signals() -> Vec<TrapSignal> {
+    [TrapSignal::Int, TrapSignal::Term]
+        .into_iter()
+        .filter(|signal| {
+            signal
+                .signal()
+                .is_some_and(|(_, pending)| pending.swap(false, Ordering::Relaxed))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{ShellState, enter};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_signal_names() {
+        assert_eq!(TrapSignal::parse("EXIT"), Some(TrapSignal::Exit));
+        assert_eq!(TrapSignal::parse("0"), Some(TrapSignal::Exit));
+        assert_eq!(TrapSignal::parse("SIGINT"), Some(TrapSignal::Int));
+        assert_eq!(TrapSignal::parse("int"), Some(TrapSignal::Int));
+        assert_eq!(TrapSignal::parse("15"), Some(TrapSignal::Term));
+        assert_eq!(TrapSignal::parse("HUP"), None);
+    }
+
+    #[test]
+    fn test_set_list_and_remove_traps() {
+        let _state = enter(Arc::new(ShellState::new()));
+        set_trap(TrapSignal::Exit, "echo bye");
+        assert_eq!(get_trap(TrapSignal::Exit).as_deref(), Some("echo bye"));
+        assert_eq!(
+            list_traps(),
+            vec![(TrapSignal::Exit, "echo bye".to_string())]
+        );
+
+        // EXITの trap は一度だけ取り出せる
+        assert_eq!(take_trap(TrapSignal::Exit).as_deref(), Some("echo bye"));
+        assert_eq!(take_trap(TrapSignal::Exit), None);
+
+        set_trap(TrapSignal::Exit, "echo bye");
+        remove_trap(TrapSignal::Exit);
+        assert!(list_traps().is_empty());
+    }
+}
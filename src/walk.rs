@@ -0,0 +1,435 @@
+//! ディレクトリツリーを反復的に辿るモジュール
+//!
+//! 再帰呼び出しではなく明示的なスタックで深さ優先（行きがけ順）に辿る。
+//! 同じディレクトリ内のエントリは名前順に並べるので、出力は常に同じ順序になる。
+//! 既定ではシンボリックリンクは辿らない。
+//! 無視するディレクトリ（`target` や `.git` など）を指定すると、そのディレクトリは返さず中にも入らない。
+
+use crate::environment::get_var;
+use crate::handlers::matches_pattern;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 辿り方の設定
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// 辿る最大の深さ（Noneなら制限なし）
+    pub max_depth: Option<usize>,
+    /// `.` で始まるエントリを含めるか（起点は常に含める）
+    pub include_hidden: bool,
+    /// 同じディレクトリ内でディレクトリをファイルより先に並べるか
+    pub dirs_first: bool,
+    /// ディレクトリへのシンボリックリンクを辿るか
+    ///
+    /// 辿る場合は訪れたディレクトリの正規化パスを記録し、同じディレクトリには二度入らない
+    pub follow_symlinks: bool,
+    /// 辿らないディレクトリの名前のパターン（`*` `?` を使用可能、起点は対象外）
+    pub ignore: Vec<String>,
+}
+
+/// 既定で無視するディレクトリ
+pub const DEFAULT_IGNORE: &[&str] = &["target", ".git", "node_modules"];
+
+/// 無視するディレクトリのパターンを取得する
+///
+/// 既定のパターンに、RUCLI_IGNORE のコロン区切りのパターンを加える
+pub fn ignore_patterns() -> Vec<String> {
+    let mut patterns: Vec<String> = DEFAULT_IGNORE.iter().map(|name| name.to_string()).collect();
+    if let Some(extra) = get_var("RUCLI_IGNORE") {
+        patterns.extend(
+            extra
+                .split(':')
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| pattern.to_string()),
+        );
+    }
+    patterns
+}
+
+/// 辿った一つのエントリ
+#[derive(Debug)]
+pub struct WalkEntry {
+    /// エントリのパス（起点のパスに名前をつなげたもの）
+    pub path: PathBuf,
+    /// 起点からの深さ（起点自体は0）
+    pub depth: usize,
+    /// エントリのメタデータ（follow_symlinks の場合はリンク先のもの）
+    pub metadata: fs::Metadata,
+    /// エントリ自体がシンボリックリンクか
+    pub is_symlink: bool,
+    /// 同じディレクトリ内の最後のエントリか（起点はtrue）
+    pub is_last: bool,
+}
+
+/// 読み取れなかったエントリ
+#[derive(Debug)]
+pub struct WalkError {
+    /// 読み取りに失敗したパス
+    pub path: PathBuf,
+    /// 発生したエラー
+    pub error: io::Error,
+}
+
+/// ディレクトリツリーのイテレータ
+pub struct Walk {
+    stack: Vec<(PathBuf, usize, bool)>, // これから訪れるパス・深さ・最後のエントリか
+    options: WalkOptions,
+    visited: HashSet<PathBuf>, // 入ったディレクトリの正規化パス（follow_symlinks時）
+}
+
+/// 指定したパスを起点にツリーを辿る
+///
+/// 起点自体も深さ0のエントリとして最初に返す。
+/// 読み取れないエントリやディレクトリは `Err` として返し、その先は辿らない。
+///
+/// # Arguments
+/// * `root` - 起点のパス
+pub fn walk(root: impl AsRef<Path>) -> Walk {
+    walk_with_options(root, WalkOptions::default())
+}
+
+/// 設定を指定してツリーを辿る
+///
+/// # Arguments
+/// * `root` - 起点のパス
+/// * `options` - 深さ・隠しファイル・並び順・シンボリックリンクの扱い
+pub fn walk_with_options(root: impl AsRef<Path>, options: WalkOptions) -> Walk {
+    Walk {
+        stack: vec![(root.as_ref().to_path_buf(), 0, true)],
+        options,
+        visited: HashSet::new(),
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<WalkEntry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, depth, is_last) = self.stack.pop()?;
+
+        let (metadata, is_symlink) = match self.metadata(&path) {
+            Ok(result) => result,
+            Err(error) => return Some(Err(WalkError { path, error })),
+        };
+
+        let below_max_depth = self.options.max_depth.is_none_or(|max| depth < max);
+        if metadata.is_dir() && below_max_depth && self.first_visit(&path) {
+            let children = match self.read_sorted_children(&path) {
+                Ok(children) => children,
+                Err(error) => return Some(Err(WalkError { path, error })),
+            };
+
+            // 先頭から取り出せるよう逆順で積む（積む前の先頭が最後のエントリ）
+            let count = children.len();
+            self.stack.extend(
+                children
+                    .into_iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, child)| (child, depth + 1, i + 1 == count)),
+            );
+        }
+
+        Some(Ok(WalkEntry {
+            path,
+            depth,
+            metadata,
+            is_symlink,
+            is_last,
+        }))
+    }
+}
+
+/// 辿ったエントリを起点からのパスとして表示する
+///
+/// 起点は指定された形を保ち（`.` なら `./` で始まる）、重なった区切りや途中の `.`、
+/// 末尾の区切りは取り除く。起点より下は `/` で区切る
+///
+/// 例: 起点 `./` のエントリ `./sub/a.txt` → `./sub/a.txt`、起点 `logs//` → `logs/a.log`
+///
+/// # Arguments
+/// * `root` - 辿った起点（ユーザーが指定した形）
+/// * `path` - 辿ったエントリのパス（`WalkEntry::path`）
+pub fn display_path(root: &str, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+
+    let mut parts: Vec<String> = Vec::new();
+    for (i, part) in root.split(['/', std::path::MAIN_SEPARATOR]).enumerate() {
+        match part {
+            "" => {}
+            "." if i > 0 => {}
+            _ => parts.push(part.to_string()),
+        }
+    }
+    parts.extend(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+    );
+
+    let joined = parts.join("/");
+    if root.starts_with(['/', std::path::MAIN_SEPARATOR]) {
+        format!("/{joined}")
+    } else {
+        joined
+    }
+}
+
+impl Walk {
+    /// エントリのメタデータと、シンボリックリンクかどうかを取得する
+    fn metadata(&self, path: &Path) -> io::Result<(fs::Metadata, bool)> {
+        let metadata = fs::symlink_metadata(path)?;
+        let is_symlink = metadata.file_type().is_symlink();
+
+        // リンク先の情報を使う（リンク切れならリンク自体の情報のまま）
+        if is_symlink
+            && self.options.follow_symlinks
+            && let Ok(target) = fs::metadata(path)
+        {
+            return Ok((target, true));
+        }
+
+        Ok((metadata, is_symlink))
+    }
+
+    /// ディレクトリに初めて入るか（シンボリックリンクの循環を防ぐ）
+    fn first_visit(&mut self, dir: &Path) -> bool {
+        if !self.options.follow_symlinks {
+            return true;
+        }
+
+        match fs::canonicalize(dir) {
+            Ok(canonical) => self.visited.insert(canonical),
+            Err(_) => false,
+        }
+    }
+
+    /// 無視するディレクトリか
+    fn is_ignored(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+            return false;
+        };
+
+        self.options
+            .ignore
+            .iter()
+            .any(|pattern| matches_pattern(&name, pattern))
+            && self
+                .metadata(path)
+                .is_ok_and(|(metadata, _)| metadata.is_dir())
+    }
+
+    /// ディレクトリ直下のパスを設定に従って並べて取得する
+    fn read_sorted_children(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut children = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if !self.options.include_hidden {
+            children.retain(|child| {
+                !child
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            });
+        }
+
+        if !self.options.ignore.is_empty() {
+            children.retain(|child| !self.is_ignored(child));
+        }
+
+        if self.options.dirs_first {
+            // ディレクトリ（falseのキー）を先に、その中は名前順
+            children.sort_by_cached_key(|child| {
+                let is_dir = self
+                    .metadata(child)
+                    .is_ok_and(|(metadata, _)| metadata.is_dir());
+                (!is_dir, child.clone())
+            });
+        } else {
+            children.sort();
+        }
+
+        Ok(children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_walk_visits_in_preorder() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("b/c")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b/c/d.txt"), "d").unwrap();
+        fs::write(root.join("b/e.txt"), "e").unwrap();
+
+        let visited: Vec<(String, usize)> = walk(root)
+            .map(|entry| entry.unwrap())
+            .map(|entry| {
+                let relative = entry.path.strip_prefix(root).unwrap();
+                (relative.display().to_string(), entry.depth)
+            })
+            .collect();
+
+        assert_eq!(
+            visited,
+            vec![
+                ("".to_string(), 0),
+                ("a.txt".to_string(), 1),
+                ("b".to_string(), 1),
+                ("b/c".to_string(), 2),
+                ("b/c/d.txt".to_string(), 3),
+                ("b/e.txt".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("z_dir/inner")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join(".hidden"), "h").unwrap();
+        fs::write(root.join("z_dir/inner/deep.txt"), "d").unwrap();
+
+        let options = WalkOptions {
+            max_depth: Some(2),
+            dirs_first: true,
+            ..WalkOptions::default()
+        };
+        let visited: Vec<(String, bool)> = walk_with_options(root, options)
+            .skip(1)
+            .map(|entry| entry.unwrap())
+            .map(|entry| {
+                let relative = entry.path.strip_prefix(root).unwrap();
+                (relative.display().to_string(), entry.is_last)
+            })
+            .collect();
+
+        // ディレクトリが先、隠しファイルなし、深さ2まで
+        assert_eq!(
+            visited,
+            vec![
+                ("z_dir".to_string(), false),
+                ("z_dir/inner".to_string(), true),
+                ("a.txt".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_symlink_cycle_is_not_followed_twice() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("dir")).unwrap();
+        std::os::unix::fs::symlink("..", root.join("dir/up")).unwrap();
+
+        let options = WalkOptions {
+            follow_symlinks: true,
+            ..WalkOptions::default()
+        };
+        let entries: Vec<WalkEntry> = walk_with_options(root, options)
+            .map(|entry| entry.unwrap())
+            .collect();
+
+        // 起点・dir・dir/up の3つで止まる
+        assert_eq!(entries.len(), 3);
+        assert!(entries[2].is_symlink);
+        assert!(entries[2].metadata.is_dir());
+    }
+
+    #[test]
+    fn test_walk_skips_ignored_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("target/debug/app"), "").unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        // 同じ名前でもファイルは無視しない
+        fs::write(root.join("src/target"), "").unwrap();
+
+        let options = WalkOptions {
+            ignore: vec!["targ*".to_string()],
+            ..WalkOptions::default()
+        };
+        let visited: Vec<String> = walk_with_options(root.join("src/.."), options)
+            .skip(1)
+            .map(|entry| entry.unwrap())
+            .map(|entry| {
+                entry
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(visited, vec!["src", "main.rs", "target"]);
+
+        // 起点は無視しない
+        let options = WalkOptions {
+            ignore: vec!["target".to_string()],
+            ..WalkOptions::default()
+        };
+        assert_eq!(walk_with_options(root.join("target"), options).count(), 3);
+    }
+
+    #[test]
+    fn test_ignore_patterns_from_variable() {
+        let _state = crate::state::enter(std::sync::Arc::new(crate::state::ShellState::new()));
+        assert_eq!(ignore_patterns(), DEFAULT_IGNORE);
+
+        crate::environment::set_var("RUCLI_IGNORE", "build:*.tmp:");
+        assert_eq!(
+            ignore_patterns(),
+            vec!["target", ".git", "node_modules", "build", "*.tmp"]
+        );
+    }
+
+    #[test]
+    fn test_display_path_is_clean_and_relative_to_root() {
+        let cases = [
+            (".", "./sub/a.txt", "./sub/a.txt"),
+            ("./", "./sub/a.txt", "./sub/a.txt"),
+            ("./.", "././a.txt", "./a.txt"),
+            ("logs", "logs/a.log", "logs/a.log"),
+            ("logs//", "logs//a.log", "logs/a.log"),
+            ("./logs/./", "./logs/./2024/a.log", "./logs/2024/a.log"),
+            ("../src", "../src/main.rs", "../src/main.rs"),
+            ("/", "/etc/hosts", "/etc/hosts"),
+            ("/tmp/", "/tmp/x y", "/tmp/x y"),
+        ];
+        for (root, path, expected) in cases {
+            assert_eq!(display_path(root, Path::new(path)), expected, "{root}");
+        }
+
+        // 実際に辿ったエントリでも同じ
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        fs::write(temp_dir.path().join("sub/a.txt"), "").unwrap();
+        let root = format!("{}//", temp_dir.path().display());
+        let paths: Vec<String> = walk(&root)
+            .skip(1)
+            .map(|entry| display_path(&root, &entry.unwrap().path))
+            .collect();
+        let base = temp_dir.path().display();
+        assert_eq!(paths, [format!("{base}/sub"), format!("{base}/sub/a.txt")]);
+    }
+
+    #[test]
+    fn test_walk_missing_root_is_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut entries = walk(temp_dir.path().join("missing"));
+
+        assert!(entries.next().unwrap().is_err());
+        assert!(entries.next().is_none());
+    }
+}
@@ -207,7 +207,7 @@ fn test_repeat_invalid_count() {
     let output = child.wait_with_output().unwrap();
     let stderr = String::from_utf8_lossy(&output.stderr);
 
-    assert!(stderr.contains("must be positive"));
+    assert!(stderr.contains("must be 0 or more"));
     assert!(stderr.contains("isn't a valid number"));
 }
 
@@ -2,10 +2,24 @@
 //! 複数のコマンドを組み合わせた実践的なワークフローをテスト
 
 use assert_cmd::Command;
+use assert_cmd::assert::Assert;
 use predicates::prelude::*;
 use std::fs;
+use std::path::Path;
 use tempfile::TempDir;
 
+/// `rucli -c COMMAND` を指定したディレクトリで実行するコマンドを作る
+fn rucli_in(dir: impl AsRef<Path>, command: &str) -> Command {
+    let mut rucli = Command::cargo_bin("rucli").unwrap();
+    rucli.args(["-c", command]).current_dir(dir);
+    rucli
+}
+
+/// `rucli -c COMMAND` を指定したディレクトリで実行し、結果を検証する
+fn run_in(dir: impl AsRef<Path>, command: &str) -> Assert {
+    rucli_in(dir, command).assert()
+}
+
 #[test]
 fn test_file_operations_workflow() {
     let temp_dir = TempDir::new().unwrap();
@@ -138,6 +152,54 @@ fn test_alias_workflow() {
         .stdout(predicate::str::contains("test.txt"));
 }
 
+#[test]
+fn test_alias_in_pipeline_stage_and_if_body() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("f.txt"), "foo\nbar").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "alias gf=\"grep foo\"\n\
+             alias say=\"echo said\"\n\
+             cat f.txt | gf\n\
+             if cat f.txt > /dev/null; then say yes; fi\n\
+             alias echo=\"echo again\"\n\
+             echo done\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("> foo\n"))
+        .stdout(predicate::str::contains("bar").not())
+        .stdout(predicate::str::contains("said yes"))
+        // 自分自身を含むエイリアスは一度だけ展開する
+        .stdout(predicate::str::contains("again done"));
+}
+
+#[test]
+fn test_alias_defined_earlier_on_the_same_line() {
+    // 前の文で定義したエイリアスは、同じ行のパイプラインの段でも使える
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "alias g=grep; echo x | g x; echo y | g x"])
+        .assert()
+        .code(1)
+        .stdout("x\n");
+}
+
+#[test]
+fn test_alias_defined_by_eval_is_used_in_a_later_pipeline() {
+    // エイリアスはパイプラインの段を実行するときに解決する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "eval \"alias g=grep\"; echo x | g x"])
+        .assert()
+        .success()
+        .stdout("x\n");
+}
+
 #[test]
 fn test_error_handling_workflow() {
     let temp_dir = TempDir::new().unwrap();
@@ -193,7 +255,7 @@ fn test_wildcard_patterns() {
             "write test.txt content\n\
              write test.rs code\n\
              write data.json {}\n\
-             write README.md # Title\n\
+             write README.md \"# Title\"\n\
              exit\n",
         )
         .assert()
@@ -258,998 +320,3967 @@ fn test_large_file_operations() {
 }
 
 #[test]
-fn test_version_command() {
-    Command::cargo_bin("rucli")
-        .unwrap()
-        .write_stdin("version\nexit\n")
-        .assert()
+fn test_du_directory_sizes() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("sub/deep")).unwrap();
+    fs::create_dir(root.join("empty")).unwrap();
+    fs::write(root.join("a.txt"), vec![b'a'; 10]).unwrap();
+    fs::write(root.join("sub/b.txt"), vec![b'b'; 100]).unwrap();
+    fs::write(root.join("sub/deep/c.txt"), vec![b'c'; 1000]).unwrap();
+    // シンボリックリンクは辿らない（リンク自体のサイズ3バイトだけ数える）
+    std::os::unix::fs::symlink("sub", root.join("link")).unwrap();
+
+    // 深いディレクトリから順に、合計が最後
+    run_in(root, "du")
         .success()
-        .stdout(predicate::str::contains("rucli v"));
+        .stdout("0\t./empty\n1000\t./sub/deep\n1100\t./sub\n1113\t.\n");
+
+    // -s は合計のみ
+    run_in(root, "du -s sub").success().stdout("1100\tsub\n");
+
+    // -h は K/M/G 単位
+    fs::write(root.join("sub/big.bin"), vec![0u8; 3000]).unwrap();
+    run_in(root, "du -sh")
+        .success()
+        .stdout(predicate::str::diff("4.0K\t.\n"));
+
+    run_in(root, "du missing")
+        .code(1)
+        .stderr(predicate::str::contains("du: cannot access 'missing'"));
 }
 
 #[test]
-fn test_append_redirect_basic() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("append_test.txt");
+fn test_tree_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("src/parser")).unwrap();
+    fs::create_dir(root.join("docs")).unwrap();
+    fs::write(root.join("Cargo.toml"), "").unwrap();
+    fs::write(root.join(".hidden"), "").unwrap();
+    fs::write(root.join("src/main.rs"), "").unwrap();
+    fs::write(root.join("src/parser/mod.rs"), "").unwrap();
+    fs::write(root.join("docs/guide.md"), "").unwrap();
+    // 循環するシンボリックリンクは一度しか辿らない
+    std::os::unix::fs::symlink("..", root.join("src/up")).unwrap();
+
+    run_in(root, "tree").success().stdout(predicate::str::diff(
+        ".\n\
+         ├── docs\n\
+         │   └── guide.md\n\
+         ├── src\n\
+         │   ├── parser\n\
+         │   │   └── mod.rs\n\
+         │   ├── up -> ..\n\
+         │   └── main.rs\n\
+         └── Cargo.toml\n\
+         \n\
+         4 directories, 4 files\n",
+    ));
 
-    // 一つのセッションで全て実行
-    Command::cargo_bin("rucli")
-        .unwrap()
-        .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo First line > {}\n\
-             echo Second line >> {}\n\
-             echo Third line >> {}\n\
-             cat {}\n\
-             exit\n",
-            file_path.display(),
-            file_path.display(),
-            file_path.display(),
-            file_path.display()
-        ))
-        .assert()
+    run_in(root, "tree -L 1 -a")
         .success()
-        .stdout(predicate::str::contains("First line"))
-        .stdout(predicate::str::contains("Second line"))
-        .stdout(predicate::str::contains("Third line"));
+        .stdout(predicate::str::diff(
+            ".\n\
+         ├── docs\n\
+         ├── src\n\
+         ├── .hidden\n\
+         └── Cargo.toml\n\
+         \n\
+         2 directories, 2 files\n",
+        ));
+
+    run_in(root, "tree Cargo.toml")
+        .code(2)
+        .stderr(predicate::str::contains("Not a directory"));
 }
 
 #[test]
-fn test_append_redirect_new_file() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("new_append.txt");
+fn test_diff_files() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.txt"), "one\ntwo\nthree\n").unwrap();
+    fs::write(temp_dir.path().join("b.txt"), "zero\none\nTWO\nthree\n").unwrap();
+    fs::write(temp_dir.path().join("same.txt"), "one\ntwo\nthree\n").unwrap();
+
+    // 同一ならステータス0で出力なし
+    run_in(&temp_dir, "diff a.txt same.txt").code(0).stdout("");
+
+    // 差分があればステータス1でunified形式
+    run_in(&temp_dir, "diff a.txt b.txt")
+        .code(1)
+        .stdout(predicate::str::diff(
+            "--- a.txt\n+++ b.txt\n@@ -1,3 +1,4 @@\n+zero\n one\n-two\n+TWO\n three\n",
+        ));
 
-    Command::cargo_bin("rucli")
-        .unwrap()
-        .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo Hello new file >> {}\n\
-             cat {}\n\
-             exit\n",
-            file_path.display(),
-            file_path.display()
-        ))
-        .assert()
+    run_in(&temp_dir, "diff -q a.txt b.txt")
+        .code(1)
+        .stdout("Files a.txt and b.txt differ\n");
+
+    // ifの条件として使える
+    run_in(
+        &temp_dir,
+        "if diff -q a.txt same.txt; then echo same; else echo differ; fi",
+    )
+    .success()
+    .stdout("same\n");
+    run_in(
+        &temp_dir,
+        "if diff -q a.txt b.txt; then echo same; else echo differ; fi",
+    )
+    .success()
+    .stdout(predicate::str::ends_with("differ\n"));
+}
+
+#[test]
+fn test_cut_fields_and_characters() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("passwd"),
+        "root:x:0:0:root:/root:/bin/bash\n\
+         daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin\n\
+         # comment line\n\
+         rust:x:1000:1000:Rust User:/home/rust:/bin/zsh\n",
+    )
+    .unwrap();
+
+    // 区切り文字のない行はそのまま出力される
+    run_in(&temp_dir, "cut -d: -f1,7 passwd")
         .success()
-        .stdout(predicate::str::contains("Hello new file"));
+        .stdout(predicate::str::diff(
+            "root:/bin/bash\ndaemon:/usr/sbin/nologin\n# comment line\nrust:/bin/zsh\n",
+        ));
+
+    // -s なら区切り文字のない行は出力しない
+    run_in(&temp_dir, "cut -d : -f 3-4 -s passwd")
+        .success()
+        .stdout(predicate::str::diff("0:0\n1:1\n1000:1000\n"));
+
+    // 範囲外のフィールドは空になる
+    run_in(&temp_dir, "cut -d: -f9 -s passwd")
+        .success()
+        .stdout(predicate::str::diff("\n\n\n"));
+
+    run_in(&temp_dir, "cut -c1-4 passwd")
+        .success()
+        .stdout(predicate::str::diff("root\ndaem\n# co\nrust\n"));
+
+    // パイプラインの入力を使う
+    run_in(
+        &temp_dir,
+        "cat passwd | grep bin | cut -d: -f1,6- | grep sh",
+    )
+    .success()
+    .stdout(predicate::str::diff(
+        "root:/root:/bin/bash\nrust:/home/rust:/bin/zsh\n",
+    ));
+
+    run_in(&temp_dir, "cut -d: -f0 passwd")
+        .failure()
+        .stderr(predicate::str::contains("invalid list"));
 }
 
 #[test]
-fn test_append_redirect_with_pipeline() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("pipeline_append.txt");
-    let data_file = temp_dir.path().join("data.txt");
+fn test_tr_translates_piped_input() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("words.txt"),
+        "hello world\nrust shell\n",
+    )
+    .unwrap();
 
-    // writeコマンドで複数行は書けないので、echoで複数回書く
+    run_in(&temp_dir, "cat words.txt | tr a-z A-Z")
+        .success()
+        .stdout(predicate::str::diff("HELLO WORLD\nRUST SHELL\n"));
+
+    run_in(&temp_dir, "cat words.txt | tr -d lo | tr -s a-z")
+        .success()
+        .stdout(predicate::str::diff("he wrd\nrust she\n"));
+
+    // trはファイルを読まない
+    run_in(&temp_dir, "tr a-z A-Z words.txt")
+        .failure()
+        .stderr(predicate::str::contains("wrong number of sets"));
+    run_in(&temp_dir, "tr a-z A-Z")
+        .failure()
+        .stderr(predicate::str::contains("no input"));
+}
+
+#[test]
+fn test_pushd_popd_and_dirs() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    fs::create_dir(root.join("a")).unwrap();
+    fs::create_dir(root.join("b")).unwrap();
+    let root = root.display();
+
+    // 各操作の後のスタックとカレントディレクトリを確認する（HOMEは ~ に省略する）
     Command::cargo_bin("rucli")
         .unwrap()
+        .env("HOME", temp_dir.path().canonicalize().unwrap())
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo apple > {}\n\
-             echo banana >> {}\n\
-             echo apricot >> {}\n\
-             echo blueberry >> {}\n\
+        .write_stdin(
+            "pushd a\n\
+             pwd\n\
+             pushd ../b\n\
+             dirs -v\n\
+             pushd\n\
+             pwd\n\
+             popd\n\
+             pwd\n\
+             echo old=$OLDPWD\n\
+             popd\n\
+             pwd\n\
+             popd\n\
              exit\n",
-            data_file.display(),
-            data_file.display(),
-            data_file.display(),
-            data_file.display()
-        ))
+        )
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains(format!("~/a ~\n> {root}/a\n")))
+        .stdout(predicate::str::contains(" 0  ~/b\n 1  ~/a\n 2  ~\n"))
+        // 引数なしのpushdは先頭の二つを入れ替える
+        .stdout(predicate::str::contains(format!("~/a ~/b ~\n> {root}/a\n")))
+        .stdout(predicate::str::contains(format!("~/b ~\n> {root}/b\n")))
+        .stdout(predicate::str::contains(format!("old={root}/a\n")))
+        .stdout(predicate::str::contains(format!("> ~\n> {root}\n")))
+        .stderr(predicate::str::contains("popd: directory stack empty"));
+}
+
+#[test]
+fn test_pushd_errors_keep_stack() {
+    let temp_dir = TempDir::new().unwrap();
 
-    // パイプラインとリダイレクトのテスト
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "cat {} | grep a >> {}\n\
-             cat {} | grep b >> {}\n\
-             cat {}\n\
+        .write_stdin(
+            "pushd\n\
+             pushd no_such_dir\n\
+             dirs -v\n\
              exit\n",
-            data_file.display(),
-            file_path.display(),
-            data_file.display(),
-            file_path.display(),
-            file_path.display()
-        ))
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("apple"))
-        .stdout(predicate::str::contains("banana"))
-        .stdout(predicate::str::contains("apricot"))
-        .stdout(predicate::str::contains("blueberry"));
+        .stdout(predicate::str::contains(" 1  ").not())
+        .stderr(predicate::str::contains("pushd: no other directory"))
+        .stderr(predicate::str::contains("No such file"));
 }
 
 #[test]
-fn test_append_redirect_empty_output() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("empty_append.txt");
+fn test_umask_applies_to_created_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mode = |name: &str| {
+        fs::metadata(temp_dir.path().join(name))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777
+    };
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo Initial content > {}\n\
-             echo hello | grep xyz >> {}\n\
-             cat {}\n\
+        .write_stdin(
+            "umask 077\n\
+             umask\n\
+             write written.txt secret\n\
+             echo secret > redirected.txt\n\
+             mkdir private\n\
+             mkdir -m 755 public\n\
+             (umask 000; umask)\n\
+             umask\n\
+             umask 999\n\
              exit\n",
-            file_path.display(),
-            file_path.display(),
-            file_path.display()
-        ))
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Initial content"))
-        .stdout(predicate::str::contains("hello").not());
+        // サブシェルの中の変更は元に戻る
+        .stdout(predicate::str::contains("0077\n").count(2))
+        .stdout(predicate::str::contains("0000\n"))
+        .stderr(predicate::str::contains("umask: 999: invalid octal mode"));
+
+    assert_eq!(mode("written.txt"), 0o600);
+    assert_eq!(mode("redirected.txt"), 0o600);
+    assert_eq!(mode("private"), 0o700);
+    // -m はumaskに関係なく指定したパーミッションにする
+    assert_eq!(mode("public"), 0o755);
 }
 
 #[test]
-fn test_redirect_overwrite_vs_append() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("compare.txt");
+fn test_ls_columns_and_clear() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.txt"), "").unwrap();
+    fs::write(temp_dir.path().join("b.txt"), "").unwrap();
 
+    // 端末の幅に合わせて列に並べ、パイプでは一行に一つのまま
     Command::cargo_bin("rucli")
         .unwrap()
+        .env("RUCLI_COLUMNS", "80")
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo Line 1 > {}\n\
-             echo Line 2 >> {}\n\
-             echo Line 3 > {}\n\
-             cat {}\n\
+        .write_stdin(
+            "ls\n\
+             ls | grep txt\n\
+             clear\n\
              exit\n",
-            file_path.display(),
-            file_path.display(),
-            file_path.display(),
-            file_path.display()
-        ))
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Line 3"))
-        .stdout(predicate::str::contains("Line 1").not())
-        .stdout(predicate::str::contains("Line 2").not());
+        .stdout(predicate::str::is_match("a\\.txt  b\\.txt|b\\.txt  a\\.txt").unwrap())
+        .stdout(predicate::str::is_match("> (a\\.txt\nb\\.txt|b\\.txt\na\\.txt)\n").unwrap())
+        .stdout(predicate::str::contains("\x1b[H\x1b[2J"));
 }
 
 #[test]
-fn test_input_redirect_basic() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("input.txt");
+fn test_recursive_rm_guards_large_trees_and_protected_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    let big = temp_dir.path().join("big");
+    fs::create_dir(&big).unwrap();
+    for i in 0..150 {
+        fs::write(big.join(format!("file{i}.txt")), "").unwrap();
+    }
+    let small = temp_dir.path().join("small");
+    fs::create_dir(&small).unwrap();
+    for i in 0..10 {
+        fs::write(small.join(format!("file{i}.txt")), "").unwrap();
+    }
 
+    // 端末でなければ確認できないので、上限を超える削除とカレントディレクトリの削除は拒否する
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo Hello, Input Redirect! > {}\n\
-             cat < {}\n\
+        .write_stdin(
+            "rm -r big\n\
+             rm -rf .\n\
              exit\n",
-            file_path.display(),
-            file_path.display()
-        ))
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Hello, Input Redirect!"));
-}
-
-#[test]
-fn test_input_redirect_with_grep() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("data.txt");
+        .stderr(predicate::str::contains(
+            "rm: refusing to remove 'big', which contains more than 100 entries (use -f to override)",
+        ))
+        .stderr(predicate::str::contains(
+            "rm: refusing to remove '.', which is the root, home or current directory",
+        ));
+    assert!(big.exists());
+    assert!(temp_dir.path().exists());
 
+    // -f なら削除する。上限は RUCLI_RM_LIMIT で変えられる
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo apple > {}\n\
-             echo banana >> {}\n\
-             echo apricot >> {}\n\
-             grep a < {}\n\
+        .env("RUCLI_RM_LIMIT", "5")
+        .write_stdin(
+            "rm -rf big\n\
+             rm -r small\n\
              exit\n",
-            file_path.display(),
-            file_path.display(),
-            file_path.display(),
-            file_path.display()
-        ))
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("apple"))
-        .stdout(predicate::str::contains("banana"))
-        .stdout(predicate::str::contains("apricot"));
+        .stderr(predicate::str::contains(
+            "rm: refusing to remove 'small', which contains more than 5 entries",
+        ));
+    assert!(!big.exists());
+    assert!(small.exists());
 }
 
 #[test]
-fn test_input_redirect_nonexistent_file() {
-    let temp_dir = tempfile::tempdir().unwrap();
+fn test_find_filters_by_age_and_size() {
+    let temp_dir = TempDir::new().unwrap();
+    let now = std::time::SystemTime::now();
+    let days_ago = |days: u64| now - std::time::Duration::from_secs(days * 24 * 60 * 60);
+
+    // (名前, サイズ, 何日前に更新したか)
+    let files = [
+        ("fresh.log", 10, 0),
+        ("old.log", 10, 3),
+        ("big.log", 2 * 1024 * 1024, 0),
+        ("reference.txt", 0, 2),
+    ];
+    for (name, size, age) in files {
+        let file = fs::File::create(temp_dir.path().join(name)).unwrap();
+        file.set_len(size).unwrap();
+        file.set_modified(days_ago(age)).unwrap();
+    }
+
+    let find = |args: &str| {
+        let output = Command::cargo_bin("rucli")
+            .unwrap()
+            .current_dir(&temp_dir)
+            .args(["-c", &format!("find . {args}")])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "find {args} failed");
+        let mut lines: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        lines.sort();
+        lines
+    };
+
+    assert_eq!(find("*.log -mtime -1"), vec!["./big.log", "./fresh.log"]);
+    assert_eq!(find("*.log -mtime +1"), vec!["./old.log"]);
+    assert_eq!(find("* -size +1M"), vec!["./big.log"]);
+    assert_eq!(find("*.log -size -1k"), Vec::<String>::new());
+    assert_eq!(find("*.log -size 1k"), vec!["./fresh.log", "./old.log"]);
+    // 条件はすべて満たすものだけ
+    assert_eq!(
+        find("* -newer reference.txt -size -2M"),
+        vec!["./fresh.log"]
+    );
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
-        .write_stdin("cat < nonexistent.txt\nexit\n")
+        .args(["-c", "find . * -newer missing.txt"])
         .assert()
-        .success()
-        .stderr(predicate::str::contains("No such file or directory"));
+        .failure()
+        .stderr(predicate::str::contains("find: 'missing.txt'"));
 }
 
 #[test]
-fn test_input_redirect_with_pipeline() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("pipeline_test.txt");
+fn test_traversal_skips_ignored_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("target/debug/build")).unwrap();
+    fs::create_dir_all(root.join(".git/objects")).unwrap();
+    fs::create_dir_all(root.join("dist")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+    for i in 0..20 {
+        fs::write(root.join(format!("target/debug/build/gen{i}.rs")), "").unwrap();
+    }
+    fs::write(root.join(".git/objects/pack.rs"), "").unwrap();
+    fs::write(root.join("dist/bundle.rs"), "").unwrap();
+
+    let run = |command: &str| {
+        let output = rucli_in(root, command)
+            .env("RUCLI_IGNORE", "dist")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{command} failed");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // 既定では target・.git と RUCLI_IGNORE のディレクトリを辿らない
+    assert_eq!(run("find . *.rs"), "./src/main.rs\n");
+    assert!(!run("tree").contains("target"));
+    assert!(!run("du").contains("target"));
+
+    // --no-ignore ならすべて辿る
+    let all = run("find . *.rs --no-ignore");
+    assert_eq!(all.lines().count(), 23);
+    assert!(all.contains("./target/debug/build/gen0.rs"));
+    assert!(all.contains("./.git/objects/pack.rs"));
+    assert!(run("tree --no-ignore").contains("target"));
+    assert!(run("du --no-ignore").contains("./target/debug/build"));
+}
+
+#[test]
+fn test_cat_and_grep_read_standard_input_without_a_file() {
+    let temp_dir = TempDir::new().unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
+        .args(["-c", "cat > out.txt"])
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "echo hello world > {}\n\
-             echo hello rust >> {}\n\
-             grep hello < {} | grep world\n\
-             exit\n",
-            file_path.display(),
-            file_path.display(),
-            file_path.display()
-        ))
+        .write_stdin("first note\nsecond note\n")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("out.txt")).unwrap(),
+        "first note\nsecond note\n"
+    );
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "grep an"])
+        .write_stdin("apple\nbanana\norange\n")
         .assert()
         .success()
-        .stdout(predicate::str::contains("hello world"));
-}
+        .stdout("banana\norange\n");
 
-#[test]
-fn test_background_execution_immediate_return() {
-    let mut cmd = Command::cargo_bin("rucli").unwrap();
-    let start = std::time::Instant::now();
+    // 対話セッションでは残りの入力を読む
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin("cat > session.txt\nfrom the session\n")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("session.txt")).unwrap(),
+        "from the session\n"
+    );
 
-    // 3秒のsleepをバックグラウンドで実行
-    cmd.write_stdin("sleep 3 &\nexit\n")
+    // 標準入力が空ならすぐに終わる
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "cat; echo done"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("[1]"));
-
-    // 1秒以内に終了することを確認（バックグラウンドなので待たない）
-    let duration = start.elapsed();
-    assert!(duration.as_secs() < 2);
+        .stdout("done\n");
 }
 
 #[test]
-fn test_background_with_output() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("bg_test.txt");
+fn test_grep_reports_each_unreadable_file_and_keeps_searching() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("good.txt"), "hello\nworld\n").unwrap();
+    fs::write(temp_dir.path().join("other.txt"), "hello there\n").unwrap();
+    fs::create_dir(temp_dir.path().join("dir")).unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
+        .args(["-c", "grep hello good.txt missing.txt dir other.txt"])
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "write {} background content &\n\
-             sleep 1\n\
-             cat {}\n\
-             exit\n",
-            file_path.display(),
-            file_path.display()
+        .assert()
+        .code(2)
+        .stdout("good.txt:1: hello\nother.txt:1: hello there\n")
+        .stderr(predicate::str::contains(
+            "grep: missing.txt: No such file or directory\n",
         ))
+        .stderr(predicate::str::contains("grep: dir: Is a directory\n"));
+
+    // 一致する行がなければステータス1、あれば0
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args([
+            "-c",
+            "grep nothing good.txt; echo $?; grep world good.txt; echo $?",
+        ])
+        .current_dir(&temp_dir)
         .assert()
         .success()
-        .stdout(predicate::str::contains("[1]"))
-        .stdout(predicate::str::contains("background content"));
+        .stdout("1\n2: world\n0\n");
 }
 
 #[test]
-fn test_multiple_background_jobs() {
+fn test_ls_recursive_and_sorted_listings() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("sub/deep")).unwrap();
+    fs::create_dir_all(root.join("target/debug")).unwrap();
+    fs::write(root.join("sub/c.txt"), "").unwrap();
+    fs::write(root.join("sub/deep/d.txt"), "").unwrap();
+
+    // 更新日時とサイズを決めたファイル（b と c は同じ値なので名前順になる）
+    let base = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    for (name, size, age) in [("a.txt", 10, 300), ("b.txt", 20, 100), ("c.txt", 20, 100)] {
+        let file = fs::File::create(root.join(name)).unwrap();
+        file.set_len(size).unwrap();
+        file.set_modified(base - std::time::Duration::from_secs(age))
+            .unwrap();
+    }
+
+    let ls = |args: &str| {
+        Command::cargo_bin("rucli")
+            .unwrap()
+            .args(["-c", &format!("ls {args} | grep txt")])
+            .current_dir(root)
+            .output()
+            .unwrap()
+            .stdout
+    };
+    assert_eq!(String::from_utf8(ls("")).unwrap(), "a.txt\nb.txt\nc.txt\n");
+    assert_eq!(
+        String::from_utf8(ls("-t")).unwrap(),
+        "b.txt\nc.txt\na.txt\n"
+    );
+    assert_eq!(
+        String::from_utf8(ls("-S")).unwrap(),
+        "b.txt\nc.txt\na.txt\n"
+    );
+    assert_eq!(
+        String::from_utf8(ls("-tr")).unwrap(),
+        "a.txt\nc.txt\nb.txt\n"
+    );
+
+    // -R はディレクトリごとに見出しを付け、無視するディレクトリの中には入らない
     Command::cargo_bin("rucli")
         .unwrap()
-        .write_stdin(
-            "echo first &\n\
-             echo second &\n\
-             echo third &\n\
-             exit\n",
-        )
+        .args(["-c", "ls -R"])
+        .current_dir(root)
         .assert()
         .success()
-        .stdout(predicate::str::contains("[1]"))
-        .stdout(predicate::str::contains("[2]"))
-        .stdout(predicate::str::contains("[3]"));
+        .stdout(
+            ".:\na.txt\nb.txt\nc.txt\nsub/\ntarget/\n\n\
+             ./sub:\nc.txt\ndeep/\n\n\
+             ./sub/deep:\nd.txt\n",
+        );
 }
 
 #[test]
-fn test_background_with_pipeline() {
-    let temp_dir = tempfile::tempdir().unwrap();
+fn test_cp_and_mv_confirm_overwrites_and_report_verbosely() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(temp_dir.path().join("src/sub")).unwrap();
+    fs::write(temp_dir.path().join("src/a.txt"), "a").unwrap();
+    fs::write(temp_dir.path().join("src/sub/b.txt"), "b").unwrap();
+    fs::write(temp_dir.path().join("new.txt"), "new").unwrap();
+    fs::write(temp_dir.path().join("keep.txt"), "keep").unwrap();
 
+    // 標準入力が端末でないので、-i は上書きを断る
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
         .write_stdin(
-            "echo hello world | grep hello &\n\
+            "cp -rv src dst\n\
+             cp -i new.txt keep.txt\n\
+             mv -iv new.txt keep.txt\n\
+             mv -v new.txt moved.txt\n\
+             echo x | cp -i moved.txt other.txt\n\
+             cp -i moved.txt other.txt &\n\
+             sleep 0.3\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("[1]"));
+        .stdout(predicate::str::contains(
+            "'src' -> 'dst'\n\
+             'src/a.txt' -> 'dst/a.txt'\n\
+             'src/sub' -> 'dst/sub'\n\
+             'src/sub/b.txt' -> 'dst/sub/b.txt'\n",
+        ))
+        .stdout(predicate::str::contains("'new.txt' -> 'moved.txt'"))
+        .stdout(predicate::str::contains("'new.txt' -> 'keep.txt'").not())
+        .stderr(predicate::str::contains(
+            "cp: not overwriting 'keep.txt' (standard input is not a terminal)",
+        ))
+        .stderr(predicate::str::contains(
+            "mv: not overwriting 'keep.txt' (standard input is not a terminal)",
+        ))
+        .stderr(predicate::str::contains(
+            "cp: -i cannot be used while reading piped input",
+        ))
+        .stderr(predicate::str::contains(
+            "cp: -i cannot be used in a background job",
+        ));
+
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("keep.txt")).unwrap(),
+        "keep"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("dst/sub/b.txt")).unwrap(),
+        "b"
+    );
+    assert!(temp_dir.path().join("moved.txt").exists());
+    assert!(!temp_dir.path().join("other.txt").exists());
 }
 
 #[test]
-fn test_background_with_redirect() {
-    let temp_dir = tempfile::tempdir().unwrap();
-    let file_path = temp_dir.path().join("output.txt");
+fn test_cp_and_mv_refuse_same_or_nested_destination() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(temp_dir.path().join("dir/sub")).unwrap();
+    fs::write(temp_dir.path().join("dir/a.txt"), "a").unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "notes").unwrap();
+
+    run_in(&temp_dir, "cp notes.txt notes.txt")
+        .code(2)
+        .stderr(predicate::str::contains(
+            "cp: 'notes.txt' and 'notes.txt' are the same file or nested",
+        ));
+    run_in(&temp_dir, "cp notes.txt .").code(2);
+    run_in(&temp_dir, "cp notes.txt ./dir/../notes.txt").code(2);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(),
+        "notes"
+    );
+
+    run_in(&temp_dir, "mv notes.txt notes.txt")
+        .code(2)
+        .stderr(predicate::str::contains(
+            "mv: 'notes.txt' and 'notes.txt' are the same file or nested",
+        ));
+    run_in(&temp_dir, "mv dir dir/sub/moved").code(2);
+    assert!(temp_dir.path().join("notes.txt").is_file());
+
+    // 自分の中へのコピーは始める前に断り、元のディレクトリは変わらない
+    run_in(&temp_dir, "cp -r dir dir/sub")
+        .code(2)
+        .stderr(predicate::str::contains(
+            "cp: 'dir' and 'dir/sub' are the same file or nested",
+        ));
+    run_in(&temp_dir, "cp -r dir dir/copy").code(2);
+    run_in(&temp_dir, "cp -r dir dir").code(2);
+    let mut entries: Vec<_> = fs::read_dir(temp_dir.path().join("dir"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, ["a.txt", "sub"]);
+    assert_eq!(
+        fs::read_dir(temp_dir.path().join("dir/sub"))
+            .unwrap()
+            .count(),
+        0
+    );
+
+    // 名前が似ているだけの兄弟ディレクトリへはコピーできる
+    run_in(&temp_dir, "cp -r dir dir2").success();
+    assert!(temp_dir.path().join("dir2/a.txt").is_file());
+}
+
+#[test]
+fn test_grep_count_and_stats() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.log"), "error 1\nok\nerror 2\n").unwrap();
+    fs::write(temp_dir.path().join("b.log"), "ok\n").unwrap();
+    fs::write(temp_dir.path().join("c.log"), "error 3\n").unwrap();
+    run_in(&temp_dir, "grep -c error a.log b.log c.log")
+        .success()
+        .stdout("a.log:2\nb.log:0\nc.log:1\n");
+    run_in(&temp_dir, "cat a.log | grep -c error")
+        .success()
+        .stdout("2\n");
+    run_in(&temp_dir, "grep -c missing a.log")
+        .code(1)
+        .stdout("0\n");
+
+    // 集計は標準エラーに出し、標準出力は通常の結果だけ
+    run_in(
+        &temp_dir,
+        "grep --stats error a.log b.log c.log > out.txt\ncat out.txt",
+    )
+    .success()
+    .stdout("a.log:1: error 1\na.log:3: error 2\nc.log:1: error 3\n")
+    .stderr(predicate::str::starts_with(
+        "grep: files searched: 3, files with matches: 2, matching lines: 3, time: 0m",
+    ));
+    run_in(&temp_dir, "grep -c --stats error a.log missing.log")
+        .code(2)
+        .stdout("a.log:2\n")
+        .stderr(predicate::str::contains(
+            "files searched: 1, files with matches: 1, matching lines: 2,",
+        ));
+}
+
+#[test]
+fn test_cd_uses_session_home_and_updates_pwd() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let home = root.join("fakehome");
+    fs::create_dir_all(home.join("sub")).unwrap();
+    fs::create_dir(root.join("work")).unwrap();
+    let (root, home) = (root.display(), home.display());
 
     Command::cargo_bin("rucli")
         .unwrap()
-        .current_dir(&temp_dir)
+        .current_dir(root.to_string())
         .write_stdin(format!(
-            "echo background test > {} &\n\
-             sleep 1\n\
-             cat {}\n\
-             exit\n",
-            file_path.display(),
-            file_path.display()
+            "cd work\n\
+             env HOME={home}\n\
+             cd ~\n\
+             pwd\n\
+             cd ~/sub\n\
+             pwd\n\
+             echo pwd=$PWD old=$OLDPWD\n\
+             cd -\n\
+             echo back=$PWD\n\
+             cd -\n\
+             echo again=$PWD\n\
+             function up() {{ cd ~/sub; }}\n\
+             cd /\n\
+             up\n\
+             echo func=$PWD\n\
+             exit\n"
         ))
         .assert()
         .success()
-        .stdout(predicate::str::contains("[1]"))
-        .stdout(predicate::str::contains("background test"));
+        .stdout(predicate::str::contains(format!(
+            "> {home}\n> > {home}/sub\n"
+        )))
+        .stdout(predicate::str::contains(format!(
+            "pwd={home}/sub old={home}\n"
+        )))
+        .stdout(predicate::str::contains(format!("back={home}\n")))
+        .stdout(predicate::str::contains(format!("again={home}/sub\n")))
+        .stdout(predicate::str::contains(format!("func={home}/sub\n")));
 }
 
 #[test]
-fn test_heredoc_basic_cat() {
+fn test_cat_shows_tabs_line_ends_and_control_characters() {
     let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("ws.txt"), "a\tb\r\nc\x01d\nlast").unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
-        .write_stdin(
-            "cat <<EOF\n\
-             Hello World\n\
-             This is a test\n\
-             EOF\n\
-             exit\n",
-        )
+        .write_stdin("cat -A ws.txt\ncat -nT ws.txt\ncat ws.txt | cat -E\nexit\n")
         .assert()
         .success()
-        .stdout(predicate::str::contains("Hello World"))
-        .stdout(predicate::str::contains("This is a test"));
+        .stdout(predicate::str::contains("a^Ib^M$\nc^Ad$\nlast\n"))
+        // 行番号の後ろのタブは変換しない
+        .stdout(predicate::str::contains(
+            "     1\ta^Ib\r\n     2\tc\x01d\n     3\tlast\n",
+        ))
+        // パイプでは末尾に改行のない行もそのまま渡るので、ファイルを直接読んだときと同じ
+        .stdout(predicate::str::contains("a\tb^M$\nc\x01d$\nlast\n"));
 }
 
 #[test]
-fn test_heredoc_with_grep() {
+fn test_unknown_flags_are_rejected_and_flags_may_follow_operands() {
     let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "foo\nbar\nfoo bar\n").unwrap();
+    fs::create_dir(temp_dir.path().join("build")).unwrap();
+    fs::write(temp_dir.path().join("build/out.o"), "").unwrap();
+
+    // 知らないオプションはパターンとして検索せず、オプション名と使い方を示す
+    run_in(&temp_dir, "grep -q foo notes.txt")
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains("grep: unknown option '-q'"))
+        .stderr(predicate::str::contains(
+            "Usage: grep [-c] [--stats] <pattern> [file...]",
+        ));
+    run_in(&temp_dir, "ls -l")
+        .code(2)
+        .stderr(predicate::str::contains("ls: unknown option '-l'"));
+    run_in(&temp_dir, "find . -name f")
+        .code(2)
+        .stderr(predicate::str::contains("find: unknown option '-name'"));
 
-    Command::cargo_bin("rucli")
-        .unwrap()
-        .current_dir(&temp_dir)
-        .write_stdin(
-            "grep error <<LOG\n\
-             info: starting application\n\
-             error: connection failed\n\
-             info: retrying\n\
-             error: timeout\n\
-             LOG\n\
-             exit\n",
-        )
-        .assert()
+    // オプションは位置引数の後ろでもオプションとして働き、個数に数えない
+    run_in(&temp_dir, "grep foo notes.txt -c")
         .success()
-        .stdout(predicate::str::contains("error: connection failed"))
-        .stdout(predicate::str::contains("error: timeout"))
-        .stdout(predicate::str::contains("info: starting").not())
-        .stdout(predicate::str::contains("info: retrying").not());
+        .stdout("2\n");
+    run_in(&temp_dir, "cat notes.txt -n")
+        .success()
+        .stdout("     1\tfoo\n     2\tbar\n     3\tfoo bar\n");
+    run_in(&temp_dir, "rm build -r").success();
+    assert!(!temp_dir.path().join("build").exists());
+    run_in(&temp_dir, "mkdir a/b -p").success();
+    assert!(temp_dir.path().join("a/b").is_dir());
+
+    // - で始まるパターンは -- の後に置く、echo は - で始まる語もそのまま表示する
+    run_in(&temp_dir, "grep -- -x notes.txt").code(1);
+    run_in(&temp_dir, "echo -x --- y")
+        .success()
+        .stdout("-x --- y\n");
+    run_in(&temp_dir, "echo").success().stdout("\n");
 }
 
 #[test]
-fn test_heredoc_strip_indent() {
+fn test_find_prints_clean_paths_and_nul_terminated_entries() {
     let temp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(temp_dir.path().join("docs/old")).unwrap();
+    fs::write(temp_dir.path().join("docs/read me.txt"), "").unwrap();
+    fs::write(temp_dir.path().join("docs/old/line\nbreak.txt"), "").unwrap();
+
+    let run = |command: &str| {
+        let output = rucli_in(&temp_dir, command).output().unwrap();
+        assert!(output.status.success(), "{command} failed");
+        output.stdout
+    };
+
+    // 起点の書き方によらず、重なった / や ./ のないパスになる
+    for root in [".", "./", "././"] {
+        assert_eq!(
+            run(&format!("find {root} read*")),
+            b"./docs/read me.txt\n",
+            "{root}"
+        );
+    }
+    assert_eq!(run("find docs// read*"), b"docs/read me.txt\n");
 
-    // タブ文字を実際に含む文字列を作成
-    let input = format!(
-        "cat <<-END\n{}First line with tab\n{}{}Second line with two tabs\n    Third line with spaces\nEND\nexit\n",
-        "\t", // 1つのタブ
-        "\t", // 1つ目のタブ
-        "\t"  // 2つ目のタブ
+    // -print0 は各パスをNULで終え、改行を付けない
+    assert_eq!(
+        run("find docs *.txt -print0"),
+        b"docs/old/line\nbreak.txt\0docs/read me.txt\0"
+    );
+    assert_eq!(run("find docs *.md -print0"), b"");
+    run("find -print0 docs *.txt > list.bin");
+    assert_eq!(
+        fs::read(temp_dir.path().join("list.bin")).unwrap(),
+        b"docs/old/line\nbreak.txt\0docs/read me.txt\0"
+    );
+}
+
+#[test]
+fn test_rename_substitutes_wildcards_and_skips_collisions() {
+    let temp_dir = TempDir::new().unwrap();
+    let names = [
+        "beach.jpeg",
+        "city.jpeg",
+        "dog.jpeg",
+        "dog.jpg",
+        "forest.jpeg",
+        "img_01.png",
+        "img_02.png",
+        "img_10.png",
+        "notes.txt",
+        "sunset.JPEG",
+        ".hidden.jpeg",
+        "river.jpeg",
+    ];
+    for name in names {
+        fs::write(temp_dir.path().join(name), name).unwrap();
+    }
+    fs::create_dir(temp_dir.path().join("raw")).unwrap();
+    fs::write(temp_dir.path().join("raw/scan.jpeg"), "").unwrap();
+
+    let listing = || {
+        let mut names: Vec<String> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    };
+    let before = listing();
+
+    // -n は予定を表示するだけで、名前は変えない
+    run_in(&temp_dir, "rename -n *.jpeg *.jpg")
+        .code(1)
+        .stdout(
+            "'beach.jpeg' -> 'beach.jpg'\n'city.jpeg' -> 'city.jpg'\n\
+             'forest.jpeg' -> 'forest.jpg'\n'river.jpeg' -> 'river.jpg'\n",
+        )
+        .stderr(predicate::str::contains(
+            "rename: skipping 'dog.jpeg': 'dog.jpg' already exists",
+        ));
+    assert_eq!(listing(), before);
+
+    // 既にある名前は上書きせずに飛ばし、残りは変える
+    run_in(&temp_dir, "rename *.jpeg *.jpg").code(1);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("dog.jpg")).unwrap(),
+        "dog.jpg"
     );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("beach.jpg")).unwrap(),
+        "beach.jpeg"
+    );
+
+    // ? と * は一致した部分が順に入る、ディレクトリの中も変えられる
+    run_in(&temp_dir, "rename img_?* shot-?-?-?")
+        .code(2)
+        .stderr(predicate::str::contains("more wildcards"));
+    run_in(&temp_dir, "rename img_?*.png shot-*?.png").success();
+    run_in(&temp_dir, "rename raw/*.jpeg *.jpg")
+        .success()
+        .stdout("'raw/scan.jpeg' -> 'raw/scan.jpg'\n");
+    assert!(temp_dir.path().join("raw/scan.jpg").exists());
+
+    assert_eq!(
+        listing(),
+        [
+            ".hidden.jpeg",
+            "beach.jpg",
+            "city.jpg",
+            "dog.jpeg",
+            "dog.jpg",
+            "forest.jpg",
+            "notes.txt",
+            "raw",
+            "river.jpg",
+            "shot-01.png",
+            "shot-02.png",
+            "shot-10.png",
+            "sunset.JPEG",
+        ]
+    );
+
+    run_in(&temp_dir, "rename *.gif *.png")
+        .failure()
+        .stderr(predicate::str::contains("rename: no files match '*.gif'"));
+}
 
+#[test]
+fn test_version_command() {
     Command::cargo_bin("rucli")
         .unwrap()
-        .current_dir(&temp_dir)
-        .write_stdin(input)
+        .write_stdin("version\nexit\n")
         .assert()
         .success()
-        .stdout(predicate::str::contains("First line with tab"))
-        .stdout(predicate::str::contains("\tSecond line with two tabs"))
-        .stdout(predicate::str::contains("    Third line with spaces"));
+        .stdout(predicate::str::contains("rucli v"));
 }
 
 #[test]
-fn test_heredoc_with_variable_expansion() {
+fn test_seq_ranges() {
     let temp_dir = TempDir::new().unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
         .write_stdin(
-            "env USER=Alice\n\
-             env GREETING=Hello\n\
-             cat <<MESSAGE\n\
-             $GREETING, $USER!\n\
-             Welcome to rucli\n\
-             MESSAGE\n\
+            "seq 3\n\
+             seq 10 -3 4\n\
+             seq -w 8 10\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Hello, Alice!"))
-        .stdout(predicate::str::contains("Welcome to rucli"));
+        .stdout(predicate::str::contains("1\n2\n3"))
+        .stdout(predicate::str::contains("10\n7\n4"))
+        .stdout(predicate::str::contains("08\n09\n10"));
 }
 
 #[test]
-fn test_heredoc_with_command_substitution() {
+fn test_seq_in_substitution_and_pipeline() {
     let temp_dir = TempDir::new().unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
         .write_stdin(
-            "cat <<END\n\
-             Echo test: $(echo Hello)\n\
-             END\n\
+            "for i in $(seq 1 3); do echo item-$i; done\n\
+             seq 1 30 | grep 7\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Echo test: Hello"));
+        .stdout(predicate::str::contains("item-1\nitem-2\nitem-3"))
+        .stdout(predicate::str::contains("7\n17\n27"));
 }
 
 #[test]
-fn test_heredoc_custom_delimiter() {
+fn test_seq_invalid_arguments() {
     let temp_dir = TempDir::new().unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
         .write_stdin(
-            "cat <<CUSTOM_END_MARKER\n\
-             This uses a custom delimiter\n\
-             EOF is just text here\n\
-             CUSTOM_END_MARKER\n\
+            "seq 1 0 5\n\
+             seq one\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("This uses a custom delimiter"))
-        .stdout(predicate::str::contains("EOF is just text here"));
+        .stderr(predicate::str::contains("step must not be zero"))
+        .stderr(predicate::str::contains("invalid number 'one'"))
+        .stderr(predicate::str::contains("Usage: seq"));
 }
 
 #[test]
-fn test_heredoc_write_to_file() {
+fn test_xargs_batches_arguments() {
     let temp_dir = TempDir::new().unwrap();
-    let config_file = temp_dir.path().join("config.txt");
+    std::fs::write(temp_dir.path().join("a.txt"), "content-a").unwrap();
+    std::fs::write(temp_dir.path().join("b.txt"), "content-b").unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "cat <<CONFIG > {}\n\
-             server=localhost\n\
-             port=8080\n\
-             debug=true\n\
-             CONFIG\n\
-             cat {}\n\
+        .write_stdin(
+            "seq 1 4 | xargs echo nums:\n\
+             seq 1 4 | xargs -n 2 echo pair:\n\
+             ls | grep txt | xargs -n 1 cat\n\
+             seq 1 2 | xargs -I {} echo [{}]\n\
              exit\n",
-            config_file.display(),
-            config_file.display()
-        ))
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("server=localhost"))
-        .stdout(predicate::str::contains("port=8080"))
-        .stdout(predicate::str::contains("debug=true"));
+        .stdout(predicate::str::contains("nums: 1 2 3 4"))
+        .stdout(predicate::str::contains("pair: 1 2\npair: 3 4"))
+        .stdout(predicate::str::contains("content-a"))
+        .stdout(predicate::str::contains("content-b"))
+        .stdout(predicate::str::contains("[1]\n[2]"));
 }
 
 #[test]
-fn test_heredoc_empty_content() {
+fn test_xargs_continues_after_failure() {
     let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("real.txt"), "real content").unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
         .write_stdin(
-            "cat <<EMPTY\n\
-             EMPTY\n\
+            "echo missing.txt real.txt | xargs -n 1 cat\n\
+             echo missing.txt real.txt | xargs -e -n 1 cat\n\
              exit\n",
         )
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("real content").count(1))
+        .stderr(predicate::str::contains("xargs: cat missing.txt"));
 }
 
 #[test]
-fn test_heredoc_multiple_lines() {
+fn test_type_resolution() {
     let temp_dir = TempDir::new().unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
         .write_stdin(
-            "cat <<MULTILINE\n\
-             Line 1\n\
-             Line 2\n\
-             Line 3\n\
-             Line 4\n\
-             Line 5\n\
-             MULTILINE\n\
+            "alias ll=ls\n\
+             function greet() { echo hi; }\n\
+             type ll greet cat sh\n\
+             which sh\n\
+             type no_such_command_xyz\n\
+             which cat\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Line 1"))
-        .stdout(predicate::str::contains("Line 2"))
+        .stdout(predicate::str::contains("ll is aliased to `ls`"))
+        .stdout(predicate::str::contains("greet is a function"))
+        .stdout(predicate::str::contains("cat is a shell builtin"))
+        .stdout(predicate::str::is_match(r"sh is /\S*/sh").unwrap())
+        .stderr(predicate::str::contains("no_such_command_xyz: not found"))
+        .stdout(predicate::str::is_match(r"> /\S*/cat\n").unwrap());
+}
+
+#[test]
+fn test_json_output_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.txt"), "hello").unwrap();
+    fs::create_dir(temp_dir.path().join("sub")).unwrap();
+
+    // 出力全体が一つのJSON配列としてパースできること
+    let run_json = |command: &str| -> Vec<serde_json::Value> {
+        let output = Command::cargo_bin("rucli")
+            .unwrap()
+            .args(["-c", command])
+            .env("RUCLI_JSON_TEST", "json value")
+            .current_dir(&temp_dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    // ls: name/type/size/mtime
+    let entries = run_json("ls --json");
+    let file = entries.iter().find(|e| e["name"] == "a.txt").unwrap();
+    assert_eq!(file["type"], "file");
+    assert_eq!(file["size"], 5);
+    assert!(file["mtime"].as_u64().unwrap() > 0);
+    let dir = entries.iter().find(|e| e["name"] == "sub").unwrap();
+    assert_eq!(dir["type"], "directory");
+
+    // env: name/value（-o json でも同じ）
+    let vars = run_json("env -o json");
+    let var = vars
+        .iter()
+        .find(|v| v["name"] == "RUCLI_JSON_TEST")
+        .unwrap();
+    assert_eq!(var["value"], "json value");
+
+    // history: index/command
+    let history = run_json("cd .\nhistory --json");
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["index"], 1);
+    assert_eq!(history[0]["command"], "cd .");
+    assert_eq!(history[1]["command"], "history --json");
+
+    // jobs: ジョブがなければ空配列
+    assert!(run_json("jobs --json").is_empty());
+}
+
+#[test]
+fn test_json_output_rejects_unknown_format() {
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "ls -o yaml"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("unsupported output format"));
+}
+
+#[test]
+fn test_repeat_zero_and_redirect() {
+    let temp_dir = TempDir::new().unwrap();
+
+    run_in(&temp_dir, "repeat 0 msg").success().stdout("");
+
+    // 三行とも改行で終わる
+    run_in(&temp_dir, "repeat 3 x > f.txt").success().stdout("");
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("f.txt")).unwrap(),
+        "x\nx\nx\n"
+    );
+
+    // 間隔付きでもリダイレクト先に書かれる
+    run_in(&temp_dir, "repeat -d 0.05 2 tick >> f.txt")
+        .success()
+        .stdout("");
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("f.txt")).unwrap(),
+        "x\nx\nx\ntick\ntick\n"
+    );
+
+    run_in(&temp_dir, "repeat -d 0.05 3 a b | grep a")
+        .success()
+        .stdout(predicate::str::diff("a b\na b\na b\n"));
+}
+
+#[test]
+fn test_repeat_delay_streams_lines() {
+    use std::io::{BufRead, BufReader};
+    use std::time::{Duration, Instant};
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_rucli"))
+        .args(["-c", "repeat -d 0.5 3 tick"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // 各行が届いた時刻を記録する
+    let reader = BufReader::new(child.stdout.take().unwrap());
+    let arrivals: Vec<(String, Instant)> = reader
+        .lines()
+        .map(|line| (line.unwrap(), Instant::now()))
+        .collect();
+    assert!(child.wait().unwrap().success());
+
+    let lines: Vec<&str> = arrivals.iter().map(|(line, _)| line.as_str()).collect();
+    assert_eq!(lines, vec!["tick", "tick", "tick"]);
+
+    // 最後にまとめて出力されるのではなく、間隔を空けて届く（合計1秒の待ち）
+    let spread = arrivals[2].1 - arrivals[0].1;
+    assert!(
+        spread >= Duration::from_millis(800),
+        "lines arrived within {spread:?}"
+    );
+}
+
+#[test]
+fn test_unknown_command_suggests_similar_names() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 近い名前があれば候補を示す
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin("grpe foo file.txt\nexit\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("unknown command error: grpe"))
+        .stderr(predicate::str::contains("Did you mean 'grep'?"));
+
+    // 近い名前がなければ従来どおりのエラーだけを出す
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin("xyzzyplugh\nexit\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "unknown command error: xyzzyplugh",
+        ))
+        .stderr(predicate::str::contains("Did you mean").not());
+}
+
+#[test]
+fn test_time_reports_elapsed_without_changing_output() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("big.txt"), "apple\nbanana\napricot\n").unwrap();
+
+    // 計測結果は標準エラー出力に出し、コマンドの出力とリダイレクトはそのまま
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "time cat big.txt | grep ap > out.txt\n\
+             time for i in 1 2; do echo item$i; done\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("item1\nitem2\n"))
+        .stdout(predicate::str::contains("apple").not())
+        .stderr(
+            predicate::str::is_match(
+                "real\t\\d+m\\d+\\.\\d{3}s\nuser\t\\d+m\\d+\\.\\d{3}s\nsys\t\\d+m\\d+\\.\\d{3}s\n",
+            )
+            .unwrap(),
+        )
+        .stderr(predicate::str::contains("real\t").count(2));
+
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("out.txt")).unwrap(),
+        "apple\napricot\n"
+    );
+}
+
+#[test]
+fn test_env_lists_sorted_and_filtered_variables() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("env.rsh");
+    fs::write(
+        &script_file,
+        "env ZZB=2\nenv ZZA=1\nenv ZZ*\necho --\nenv --session\n",
+    )
+    .unwrap();
+
+    // ZZCはシステム環境変数なので --session には出ない
+    // （スクリプトの場所の RUCLI_SCRIPT_DIR はシェルが設定するセッション変数）
+    let script_dir = fs::canonicalize(temp_dir.path()).unwrap();
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("ZZC", "3")
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(format!(
+            "ZZA=1\nZZB=2\nZZC=3\n--\nRUCLI_SCRIPT_DIR={}\nZZA=1\nZZB=2\n",
+            script_dir.display()
+        ));
+}
+
+#[test]
+fn test_eval_runs_constructed_commands_in_current_shell() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("sub")).unwrap();
+    let script_file = temp_dir.path().join("eval.rsh");
+    fs::write(
+        &script_file,
+        "PATTERN=apple\n\
+         CMD=\"echo apple banana | grep $PATTERN\"\n\
+         eval \"$CMD\"\n\
+         NAME=fruit\n\
+         eval \"$NAME=cherry\"\n\
+         echo fruit=$fruit\n\
+         eval cd sub\n\
+         pwd\n\
+         eval 'eval echo nested $fruit'\n\
+         eval\n\
+         eval time\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .stdout(predicate::str::contains("apple banana\n"))
+        .stdout(predicate::str::contains("fruit=cherry\n"))
+        .stdout(predicate::str::contains("sub\n"))
+        .stdout(predicate::str::contains("nested cherry\n"))
+        .stderr(predicate::str::contains("eval 'time'"));
+}
+
+#[test]
+fn test_handler_messages_follow_pipes_and_redirects() {
+    let temp_dir = TempDir::new().unwrap();
+
+    run_in(
+        &temp_dir,
+        "alias ll='ls -l'\nalias gs='git status'\nalias | grep ll",
+    )
+    .success()
+    .stdout("ll = ls -l\n");
+    run_in(&temp_dir, "write f.txt x > /dev/null")
+        .success()
+        .stdout("");
+    run_in(&temp_dir, "write f.txt x | grep written")
+        .success()
+        .stdout("File written successfully: f.txt\n");
+
+    // 見つからない名前はエラーに、見つかった分はパイプラインへ流す
+    run_in(&temp_dir, "type ls no_such_command_xyz | grep builtin")
+        .success()
+        .stdout("ls is a shell builtin\n")
+        .stderr("no_such_command_xyz: not found\n");
+    run_in(&temp_dir, "type ls no_such_command_xyz > out.txt")
+        .code(127)
+        .stdout("");
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("out.txt")).unwrap(),
+        "ls is a shell builtin\n"
+    );
+}
+
+#[test]
+fn test_paste_runs_lines_only_after_confirmation() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "paste\n\
+             for i in 1 2\n\
+             do\n\
+             echo pasted $i\n\
+             done\n\
+             .\n\
+             y\n\
+             paste\n\
+             echo declined\n\
+             .\n\
+             n\n\
+             echo status $?\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "   1  for i in 1 2\n   2  do\n   3  echo pasted $i\n   4  done\nRun 4 lines? [y/N] ",
+        ))
+        .stdout(predicate::str::contains("pasted 1\npasted 2\n"))
+        .stdout(predicate::str::contains(
+            "   1  echo declined\nRun 1 line? [y/N] ",
+        ))
+        .stdout(predicate::str::contains("declined\n").count(1))
+        .stdout(predicate::str::contains("status 1"))
+        .stderr(predicate::str::contains("paste: 1 line not run"));
+}
+
+#[test]
+fn test_listings_are_sorted_and_repeatable() {
+    let temp_dir = TempDir::new().unwrap();
+    for name in ["zz/b.txt", "zz/a.txt", "B.txt", "a.txt", "aa/c.txt"] {
+        let path = temp_dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x").unwrap();
+    }
+
+    let listings = "ls\n\
+                    find *.txt\n\
+                    alias\n\
+                    env --session\n";
+    let script = format!(
+        "alias zl=ls\n\
+         alias al=pwd\n\
+         env ZVAR=1\n\
+         env AVAR=2\n\
+         {listings}echo ---\n\
+         {listings}exit\n"
+    );
+    let output = Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(script)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (first, second) = stdout.split_once("---").unwrap();
+
+    // 名前のバイト順（大文字が先）で、二回目も同じ出力になる
+    let expected = "B.txt\na.txt\naa/\nzz/\n> \
+                    ./B.txt\n./a.txt\n./aa/c.txt\n./zz/a.txt\n./zz/b.txt\n> \
+                    al = pwd\nzl = ls\n> \
+                    AVAR=2\nZVAR=1\n";
+    assert!(first.ends_with(&format!("{expected}> ")), "{first}");
+    assert!(second.starts_with(&format!("\n> {expected}")), "{second}");
+}
+
+#[test]
+fn test_long_output_on_a_terminal_is_truncated_or_paged() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut session = PtySession::start(temp_dir.path());
+    session.expect("Hello, rucli!");
+    session.expect_prompt();
+
+    session.run("RUCLI_PAGE_LINES=3; RUCLI_PAGER=truncate", "step-1");
+    session.send("repeat 10 row");
+    session.expect("\x1b[?2004lrow\r\nrow\r\nrow\r\n... (7 more lines, use | cat to see all)\r\n");
+    session.expect_prompt();
+
+    // パイプラインの出力と、一画面に収まる出力はそのまま表示する
+    session.send("repeat 10 piped | cat");
+    session.expect(&"piped\r\n".repeat(10));
+    session.expect_prompt();
+    session.send("repeat 3 short");
+    session.expect("short\r\nshort\r\nshort\r\n");
+    session.expect_prompt();
+
+    // 内蔵のページャ: Enterで一行、qで終了
+    session.run("RUCLI_PAGER=internal", "step-2");
+    session.send("repeat 6 page");
+    session.expect("page\r\npage\r\npage\r\n-- more (3 lines left;");
+    session.send("");
+    session.expect("page\r\n-- more (2 lines left;");
+    session.send("q");
+    session.expect_prompt();
+    session.run("echo after", "step-3");
+
+    session.send("exit");
+    assert!(session.child.wait().unwrap().success());
+    let output = session.output.lock().unwrap().clone();
+    assert_eq!(output.matches("row\r\n").count(), 3, "{output}");
+    assert_eq!(output.matches("page\r\n").count(), 4, "{output}");
+}
+
+#[test]
+fn test_env_runs_a_command_with_scoped_overrides() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "env -i A431=1 B431=2 env\n\
+             echo ---\n\
+             env C431=temp env C431\n\
+             env C431\n\
+             echo status=$?\n\
+             env -i env HOME\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        // -i では上書きした変数だけを一覧する
+        .stdout(predicate::str::starts_with(
+            "Hello, rucli!\n> A431=1\nB431=2\n> ---\n> temp\n",
+        ))
+        .stdout(predicate::str::contains("status=2"))
+        .stderr(predicate::str::contains(
+            "Environment variable 'C431' not found",
+        ))
+        .stderr(predicate::str::contains(
+            "Environment variable 'HOME' not found",
+        ));
+
+    // 外部コマンドには上書きした変数だけを渡す
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "env -i ONLY431=yes exec /usr/bin/env"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout("ONLY431=yes\n");
+}
+
+#[test]
+fn test_append_redirect_basic() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("append_test.txt");
+
+    // 一つのセッションで全て実行
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo First line > {}\n\
+             echo Second line >> {}\n\
+             echo Third line >> {}\n\
+             cat {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display(),
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("First line"))
+        .stdout(predicate::str::contains("Second line"))
+        .stdout(predicate::str::contains("Third line"));
+}
+
+#[test]
+fn test_append_redirect_new_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("new_append.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo Hello new file >> {}\n\
+             cat {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello new file"));
+}
+
+#[test]
+fn test_append_redirect_with_pipeline() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("pipeline_append.txt");
+    let data_file = temp_dir.path().join("data.txt");
+
+    // writeコマンドで複数行は書けないので、echoで複数回書く
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo apple > {}\n\
+             echo banana >> {}\n\
+             echo apricot >> {}\n\
+             echo blueberry >> {}\n\
+             exit\n",
+            data_file.display(),
+            data_file.display(),
+            data_file.display(),
+            data_file.display()
+        ))
+        .assert()
+        .success();
+
+    // パイプラインとリダイレクトのテスト
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "cat {} | grep a >> {}\n\
+             cat {} | grep b >> {}\n\
+             cat {}\n\
+             exit\n",
+            data_file.display(),
+            file_path.display(),
+            data_file.display(),
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("apple"))
+        .stdout(predicate::str::contains("banana"))
+        .stdout(predicate::str::contains("apricot"))
+        .stdout(predicate::str::contains("blueberry"));
+}
+
+#[test]
+fn test_append_redirect_empty_output() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("empty_append.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo Initial content > {}\n\
+             echo hello | grep xyz >> {}\n\
+             cat {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Initial content"))
+        .stdout(predicate::str::contains("hello").not());
+}
+
+#[test]
+fn test_redirect_overwrite_vs_append() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("compare.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo Line 1 > {}\n\
+             echo Line 2 >> {}\n\
+             echo Line 3 > {}\n\
+             cat {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display(),
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Line 3"))
+        .stdout(predicate::str::contains("Line 1").not())
+        .stdout(predicate::str::contains("Line 2").not());
+}
+
+#[test]
+fn test_input_redirect_basic() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("input.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo Hello, Input Redirect! > {}\n\
+             cat < {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello, Input Redirect!"));
+}
+
+#[test]
+fn test_input_redirect_with_grep() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("data.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo apple > {}\n\
+             echo banana >> {}\n\
+             echo apricot >> {}\n\
+             grep a < {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display(),
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("apple"))
+        .stdout(predicate::str::contains("banana"))
+        .stdout(predicate::str::contains("apricot"));
+}
+
+#[test]
+fn test_input_redirect_nonexistent_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin("cat < nonexistent.txt\nexit\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("No such file or directory"));
+}
+
+#[test]
+fn test_input_redirect_with_pipeline() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("pipeline_test.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo hello world > {}\n\
+             echo hello rust >> {}\n\
+             grep hello < {} | grep world\n\
+             exit\n",
+            file_path.display(),
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hello world"));
+}
+
+#[test]
+fn test_operators_inside_quotes_are_literal() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 引用符内の | ; & > は分割・リダイレクトに使われない
+    run_in(&temp_dir, r#"echo "a|b" | grep b"#)
+        .success()
+        .stdout(predicate::str::contains("a|b"));
+    run_in(&temp_dir, r#"echo "one; two""#)
+        .success()
+        .stdout(predicate::str::contains("one; two"));
+    run_in(&temp_dir, "echo 'Tom & Jerry'")
+        .success()
+        .stdout(predicate::str::contains("Tom & Jerry"))
+        .stdout(predicate::str::contains("[1]").not());
+    run_in(&temp_dir, "echo '<b>bold</b>'")
+        .success()
+        .stdout(predicate::str::contains("<b>bold</b>"));
+}
+
+#[test]
+fn test_quoted_redirect_target_and_heredoc_delimiter() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 空白を含むファイル名に書き込み、そこから読み込む
+    run_in(
+        &temp_dir,
+        r#"echo first > "my notes.txt"; echo second >> 'my notes.txt'"#,
+    )
+    .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("my notes.txt")).unwrap(),
+        "first\nsecond\n"
+    );
+    run_in(&temp_dir, r#"cat < "my notes.txt""#)
+        .success()
+        .stdout(predicate::str::contains("first\nsecond"));
+
+    // 空白を含むデリミタ
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin("cat <<\"END MARKER\"\nEND\nbody line\nEND MARKER\nexit\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("END\nbody line"));
+}
+
+#[test]
+fn test_redirect_stdout_and_stderr() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let read = |name: &str| fs::read_to_string(temp_dir.path().join(name)).unwrap();
+
+    // &> /dev/null は両方を捨てる（ステータスは残る）
+    run_in(&temp_dir, "cat missing.txt &> /dev/null")
+        .code(1)
+        .stdout("")
+        .stderr("");
+    run_in(&temp_dir, "echo quiet > /dev/null")
+        .success()
+        .stdout("");
+
+    // 標準エラーだけをファイルへ
+    run_in(&temp_dir, "cat missing.txt 2> err.txt")
+        .code(1)
+        .stderr("");
+    assert!(read("err.txt").contains("No such file"));
+
+    // 2>&1 で標準出力と同じファイルへ
+    run_in(&temp_dir, "echo first > out.txt 2>&1").success();
+    run_in(&temp_dir, "cat missing.txt >> out.txt 2>&1")
+        .code(1)
+        .stderr("");
+    let out = read("out.txt");
+    assert!(out.starts_with("first\n"));
+    assert!(out.contains("No such file"));
+
+    // 複数のリダイレクト（先の > も作成される）
+    run_in(&temp_dir, "echo both &> all.txt")
+        .success()
+        .stdout("");
+    run_in(&temp_dir, "echo more &>> all.txt").success();
+    assert_eq!(read("all.txt"), "both\nmore\n");
+    run_in(&temp_dir, "echo last > a.txt > b.txt").success();
+    assert_eq!(read("a.txt"), "");
+    assert_eq!(read("b.txt"), "last\n");
+
+    // パイプラインに標準エラーを流す
+    run_in(&temp_dir, "cat missing.txt 2>&1 | grep such")
+        .success()
+        .stdout(predicate::str::contains("No such file"));
+}
+
+#[test]
+fn test_noclobber_and_force_overwrite() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = temp_dir.path().join("out.txt");
+    fs::write(&file, "original\n").unwrap();
+
+    // 既定では上書きできる
+    run_in(&temp_dir, "echo first > out.txt").success();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "first\n");
+
+    // noclobber では > が拒否される
+    run_in(&temp_dir, "set -o noclobber; echo second > out.txt")
+        .failure()
+        .stderr(predicate::str::contains("cannot overwrite existing file"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "first\n");
+
+    // >| は上書きでき、>> と新しいファイルへの > は影響を受けない
+    run_in(
+        &temp_dir,
+        "set -C; echo forced >| out.txt; echo more >> out.txt; echo new > new.txt",
+    )
+    .success();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "forced\nmore\n");
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("new.txt")).unwrap(),
+        "new\n"
+    );
+
+    // 無効に戻すと再び上書きできる
+    run_in(
+        &temp_dir,
+        "set -o noclobber; set +o noclobber; echo again > out.txt; set -o",
+    )
+    .success()
+    .stdout(predicate::str::contains("noclobber").and(predicate::str::contains("off")));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "again\n");
+}
+
+#[test]
+fn test_redirect_targets_expand_variables() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("in.txt"), "from input\n").unwrap();
+
+    // リダイレクト先の変数は適用する時点の値で展開する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "OUTFILE=out.txt\n\
+             echo hi > $OUTFILE\n\
+             INFILE=in.txt\n\
+             cat < $INFILE\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("from input"));
+
+    let content = fs::read_to_string(temp_dir.path().join("out.txt")).unwrap();
+    assert_eq!(content, "hi\n");
+    assert!(!temp_dir.path().join("$OUTFILE").exists());
+}
+
+#[test]
+fn test_binary_data_round_trips_through_redirects_and_pipes() {
+    let temp_dir = TempDir::new().unwrap();
+    // 末尾に改行のないデータも改行を足さずに書き出す
+    let data = b"\x89PNG\r\n\x1a\n\x00\xff\xfe binary \xe3\x81".to_vec();
+    fs::write(temp_dir.path().join("in.bin"), &data).unwrap();
+
+    // UTF-8でないバイトもそのまま書き出す
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat < in.bin > redirected.bin\n\
+             cat in.bin | cat > piped.bin\n\
+             cat in.bin | grep binary > matched.bin\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stderr("");
+
+    assert_eq!(
+        fs::read(temp_dir.path().join("redirected.bin")).unwrap(),
+        data
+    );
+    assert_eq!(fs::read(temp_dir.path().join("piped.bin")).unwrap(), data);
+    assert_eq!(
+        fs::read(temp_dir.path().join("matched.bin")).unwrap(),
+        "\0\u{FFFD}\u{FFFD} binary \u{FFFD}\n".as_bytes()
+    );
+}
+
+#[test]
+fn test_max_code_point_round_trips_through_cat() {
+    let temp_dir = TempDir::new().unwrap();
+    // U+10FFFD（f4 8f bf bd）は有効なUTF-8なので、そのまま書き出す
+    let data = "max \u{10FFFD}\n".as_bytes().to_vec();
+    fs::write(temp_dir.path().join("in.txt"), &data).unwrap();
+
+    run_in(&temp_dir, "cat < in.txt > redirected.txt").success();
+    rucli_in(&temp_dir, "cat | cat > piped.txt")
+        .write_stdin(data.clone())
+        .assert()
+        .success();
+    run_in(&temp_dir, "cat < in.txt | cat > chained.txt").success();
+
+    for name in ["redirected.txt", "piped.txt", "chained.txt"] {
+        assert_eq!(fs::read(temp_dir.path().join(name)).unwrap(), data);
+    }
+}
+
+#[test]
+fn test_cat_keeps_a_missing_final_newline() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("nn.txt"), "no newline").unwrap();
+
+    run_in(&temp_dir, "cat < nn.txt > out.txt").success();
+
+    assert_eq!(
+        fs::read(temp_dir.path().join("out.txt")).unwrap(),
+        b"no newline"
+    );
+}
+
+#[test]
+fn test_output_ends_with_exactly_one_newline() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let read = |name: &str| fs::read_to_string(temp_dir.path().join(name)).unwrap();
+
+    // リダイレクト先のファイルは端末に出る内容とバイト単位で一致する
+    run_in(&temp_dir, "echo hi > f.txt").success().stdout("");
+    assert_eq!(read("f.txt"), "hi\n");
+    run_in(&temp_dir, "cat f.txt").success().stdout("hi\n");
+
+    run_in(&temp_dir, "echo hi >> g.txt").success();
+    run_in(&temp_dir, "echo hi >> g.txt").success();
+    assert_eq!(read("g.txt"), "hi\nhi\n");
+
+    // echo -n は改行を付けない
+    run_in(&temp_dir, "echo -n hi > h.txt").success();
+    assert_eq!(read("h.txt"), "hi");
+    run_in(&temp_dir, "echo a; echo -n b; echo c")
+        .success()
+        .stdout("a\nbc\n");
+
+    // コマンド置換は末尾の改行だけを取り除く
+    run_in(&temp_dir, "echo x$(echo hi)y")
+        .success()
+        .stdout("xhiy\n");
+    run_in(&temp_dir, "echo hi | cat").success().stdout("hi\n");
+
+    // 空行で終わる出力も行数が変わらない
+    fs::write(temp_dir.path().join("p.txt"), "a:b\nc:d\n").unwrap();
+    run_in(&temp_dir, "cut -d: -f9 p.txt")
+        .success()
+        .stdout("\n\n");
+
+    // 標準エラーのリダイレクトも改行で終わる
+    run_in(&temp_dir, "cat missing.txt 2> err.txt").code(1);
+    let err = read("err.txt");
+    assert!(err.ends_with('\n') && !err.ends_with("\n\n"));
+}
+
+#[test]
+fn test_interactive_clobber_asks_before_truncating_on_a_terminal() {
+    let temp_dir = TempDir::new().unwrap();
+    let report = temp_dir.path().join("report.txt");
+    fs::write(&report, "old\n").unwrap();
+    let mut session = PtySession::start(temp_dir.path());
+    session.expect("Hello, rucli!");
+    session.expect_prompt();
+
+    // 既定では確認しない
+    session.run("echo plain > report.txt", "step-1");
+    assert_eq!(fs::read_to_string(&report).unwrap(), "plain\n");
+
+    session.run("set -o interactive-clobber", "step-2");
+    session.send("echo refused > report.txt");
+    session.expect("overwrite existing 'report.txt'? [y/N] ");
+    session.send("n");
+    session.expect("report.txt: not overwritten");
+    session.expect_prompt();
+    assert_eq!(fs::read_to_string(&report).unwrap(), "plain\n");
+
+    session.send("echo accepted > report.txt");
+    session.expect("overwrite existing 'report.txt'? [y/N] ");
+    session.send("y");
+    session.expect_prompt();
+    session.run("cat report.txt", "step-3");
+    assert_eq!(fs::read_to_string(&report).unwrap(), "accepted\n");
+
+    // 空のファイル・新しいファイル・パイプラインのステージでは確認しない
+    fs::write(temp_dir.path().join("empty.txt"), "").unwrap();
+    session.run("echo e > empty.txt", "step-4");
+    session.run("echo n > new.txt", "step-5");
+    session.run("echo staged > report.txt | cat", "step-6");
+    assert_eq!(fs::read_to_string(&report).unwrap(), "staged\n");
+
+    session.run("set +o interactive-clobber", "step-7");
+    session.run("echo last > report.txt", "step-8");
+    session.send("exit");
+    assert!(session.child.wait().unwrap().success());
+
+    assert_eq!(fs::read_to_string(&report).unwrap(), "last\n");
+    let output = session.output.lock().unwrap().clone();
+    assert_eq!(output.matches("overwrite existing").count(), 2, "{output}");
+}
+
+#[test]
+fn test_interactive_clobber_is_inactive_without_a_terminal() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("report.txt"), "old\n").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin("set -o interactive-clobber\necho new > report.txt\nexit\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("overwrite").not());
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("report.txt")).unwrap(),
+        "new\n"
+    );
+}
+
+#[test]
+fn test_background_execution_immediate_return() {
+    let mut cmd = Command::cargo_bin("rucli").unwrap();
+    let start = std::time::Instant::now();
+
+    // 3秒のsleepをバックグラウンドで実行
+    cmd.write_stdin("sleep 3 &\nexit\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[1]"));
+
+    // 1秒以内に終了することを確認（バックグラウンドなので待たない）
+    let duration = start.elapsed();
+    assert!(duration.as_secs() < 2);
+}
+
+#[test]
+fn test_sleep_fractional_seconds() {
+    let start = std::time::Instant::now();
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "sleep 0.3; echo done"])
+        .assert()
+        .success()
+        .stdout("done\n");
+
+    let elapsed = start.elapsed();
+    assert!(elapsed >= std::time::Duration::from_millis(300));
+    assert!(elapsed.as_secs() < 3);
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "sleep -1"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("negative"));
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "sleep 1.x"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("not a valid number of seconds"));
+}
+
+#[test]
+fn test_background_with_output() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("bg_test.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "write {} background content &\n\
+             sleep 0.5\n\
+             cat {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[1]"))
+        .stdout(predicate::str::contains("background content"));
+}
+
+#[test]
+fn test_multiple_background_jobs() {
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .write_stdin(
+            "echo first &\n\
+             echo second &\n\
+             echo third &\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[1]"))
+        .stdout(predicate::str::contains("[2]"))
+        .stdout(predicate::str::contains("[3]"));
+}
+
+#[test]
+fn test_background_with_pipeline() {
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    // jobsには入力したとおりのコマンドを表示する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo hello world | grep hello &\n\
+             sleep  1 |   cat   &\n\
+             jobs\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[1]"))
+        .stdout(predicate::str::contains("Running    sleep  1 |   cat\n"));
+}
+
+#[test]
+fn test_background_with_redirect() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file_path = temp_dir.path().join("output.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "echo background test > {} &\n\
+             sleep 0.5\n\
+             cat {}\n\
+             exit\n",
+            file_path.display(),
+            file_path.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[1]"))
+        .stdout(predicate::str::contains("background test"));
+}
+
+#[test]
+fn test_background_loop_and_fg() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // バックグラウンドのforループがファイルを書き、fgで完了を待って出力を表示する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "for i in 1 2 3; do write f$i.txt n$i; done &\n\
+             fg\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[1]"))
+        .stdout(predicate::str::contains(
+            "File written successfully: f3.txt",
+        ));
+    for i in 1..=3 {
+        let content = fs::read_to_string(temp_dir.path().join(format!("f{i}.txt"))).unwrap();
+        assert_eq!(content, format!("n{i}"));
+    }
+
+    // ジョブの出力はfgするまでプロンプトの下に表示しない
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .write_stdin(
+            "function greet() { echo hidden-$1; }\n\
+             greet bob &\n\
+             sleep 0.3\n\
+             echo marker\n\
+             fg 1\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("marker\n> hidden-bob"));
+}
+
+#[test]
+fn test_ampersand_inside_command_is_not_background() {
+    let temp_dir = TempDir::new().unwrap();
+
+    run_in(&temp_dir, "echo a && echo b")
+        .success()
+        .stdout(predicate::str::contains("[1]").not());
+    run_in(&temp_dir, "echo http://example.com/?a=1&b=2")
+        .success()
+        .stdout("http://example.com/?a=1&b=2\n");
+}
+
+#[test]
+fn test_finished_background_job_output_is_shown() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // jobs が終了したジョブを取り除く前に、その出力を表示する
+    run_in(&temp_dir, "echo bg-output &; sleep 0.3; jobs; jobs")
+        .success()
+        .stdout(predicate::str::contains(
+            "[1]+ Done       echo bg-output\nbg-output\n",
+        ))
+        .stdout(predicate::str::contains("bg-output").count(2))
+        .stdout(predicate::str::ends_with("No jobs\n"));
+}
+
+#[test]
+fn test_background_command_expands_variables_at_launch() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 起動後に変数を変えても、起動した時点の値で実行する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "F=bg.txt\n\
+             write $F content &\n\
+             F=other.txt\n\
+             fg\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "File written successfully: bg.txt",
+        ));
+
+    let content = fs::read_to_string(temp_dir.path().join("bg.txt")).unwrap();
+    assert_eq!(content, "content");
+    assert!(!temp_dir.path().join("other.txt").exists());
+}
+
+#[test]
+fn test_background_job_failure_is_reported_with_its_error() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // エラーはジョブのスレッドから表示せず、次のプロンプトの前に通知する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat /nonexistent &\n\
+             sleep 0.3\n\
+             jobs\n\
+             cat /nonexistent &\n\
+             sleep 0.3; fg %+\n\
+             echo status=$?\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "[1]+ Exit 1     cat /nonexistent: IO error: No such file or directory",
+        ))
+        .stderr(predicate::str::contains("Background job failed").not())
+        .stdout(predicate::str::contains("No jobs"))
+        .stdout(predicate::str::contains("status=1"));
+}
+
+#[test]
+fn test_stopped_job_runs_only_after_bg() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "sleep 5 &\n\
+             echo started > started.txt &?\n\
+             sleep 0.3\n\
+             jobs\n\
+             cat started.txt\n\
+             bg %1\n\
+             bg %2\n\
+             sleep 0.3; cat started.txt\n\
+             echo later &?\n\
+             fg\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "[2]+ Stopped    echo started > started.txt",
+        ))
+        .stdout(predicate::str::contains(
+            "[2] echo started > started.txt &\n",
+        ))
+        .stderr(predicate::str::contains("job 1 is already running"))
+        .stdout(predicate::str::contains("started\n"))
+        .stdout(predicate::str::contains("later\n"));
+}
+
+#[test]
+fn test_heredoc_basic_cat() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat <<EOF\n\
+             Hello World\n\
+             This is a test\n\
+             EOF\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello World"))
+        .stdout(predicate::str::contains("This is a test"));
+}
+
+#[test]
+fn test_heredoc_with_grep() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "grep error <<LOG\n\
+             info: starting application\n\
+             error: connection failed\n\
+             info: retrying\n\
+             error: timeout\n\
+             LOG\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("error: connection failed"))
+        .stdout(predicate::str::contains("error: timeout"))
+        .stdout(predicate::str::contains("info: starting").not())
+        .stdout(predicate::str::contains("info: retrying").not());
+}
+
+#[test]
+fn test_heredoc_strip_indent() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // タブ文字を実際に含む文字列を作成
+    let input = format!(
+        "cat <<-END\n{}First line with tab\n{}{}Second line with two tabs\n    Third line with spaces\nEND\nexit\n",
+        "\t", // 1つのタブ
+        "\t", // 1つ目のタブ
+        "\t"  // 2つ目のタブ
+    );
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("First line with tab"))
+        .stdout(predicate::str::contains("\tSecond line with two tabs"))
+        .stdout(predicate::str::contains("    Third line with spaces"));
+}
+
+#[test]
+fn test_heredoc_with_variable_expansion() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "env USER=Alice\n\
+             env GREETING=Hello\n\
+             cat <<MESSAGE\n\
+             $GREETING, $USER!\n\
+             Welcome to rucli\n\
+             MESSAGE\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello, Alice!"))
+        .stdout(predicate::str::contains("Welcome to rucli"));
+}
+
+#[test]
+fn test_heredoc_with_command_substitution() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat <<END\n\
+             Echo test: $(echo Hello)\n\
+             END\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Echo test: Hello"));
+}
+
+#[test]
+fn test_heredoc_custom_delimiter() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat <<CUSTOM_END_MARKER\n\
+             This uses a custom delimiter\n\
+             EOF is just text here\n\
+             CUSTOM_END_MARKER\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("This uses a custom delimiter"))
+        .stdout(predicate::str::contains("EOF is just text here"));
+}
+
+#[test]
+fn test_heredoc_write_to_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_file = temp_dir.path().join("config.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "cat <<CONFIG > {}\n\
+             server=localhost\n\
+             port=8080\n\
+             debug=true\n\
+             CONFIG\n\
+             cat {}\n\
+             exit\n",
+            config_file.display(),
+            config_file.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("server=localhost"))
+        .stdout(predicate::str::contains("port=8080"))
+        .stdout(predicate::str::contains("debug=true"));
+}
+
+#[test]
+fn test_heredoc_empty_content() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat <<EMPTY\n\
+             EMPTY\n\
+             exit\n",
+        )
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_heredoc_multiple_lines() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat <<MULTILINE\n\
+             Line 1\n\
+             Line 2\n\
+             Line 3\n\
+             Line 4\n\
+             Line 5\n\
+             MULTILINE\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Line 1"))
+        .stdout(predicate::str::contains("Line 2"))
         .stdout(predicate::str::contains("Line 3"))
         .stdout(predicate::str::contains("Line 4"))
         .stdout(predicate::str::contains("Line 5"));
 }
 
 #[test]
-fn test_heredoc_with_special_characters() {
+fn test_heredoc_with_special_characters() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat <<SPECIAL\n\
+             Special chars: !@#$%^&*()\n\
+             Quotes: \"double\" and 'single'\n\
+             Path: /usr/local/bin\n\
+             SPECIAL\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Special chars: !@#$%^&*()"))
+        .stdout(predicate::str::contains("Quotes: \"double\" and 'single'"))
+        .stdout(predicate::str::contains("Path: /usr/local/bin"));
+}
+
+#[test]
+fn test_heredoc_delimiter_in_content() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "cat <<EOF\n\
+             This line contains EOF in the middle\n\
+             EOF at the start of line is still content\n\
+             Only a line with exactly EOF ends it\n\
+             EOF\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "This line contains EOF in the middle",
+        ))
+        .stdout(predicate::str::contains(
+            "EOF at the start of line is still content",
+        ))
+        .stdout(predicate::str::contains(
+            "Only a line with exactly EOF ends it",
+        ));
+}
+
+#[test]
+fn test_heredoc_with_pipeline_and_redirect() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_file = temp_dir.path().join("filtered.txt");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(format!(
+            "grep line <<DATA | grep important > {}\n\
+             line 1: not important\n\
+             line 2: important data\n\
+             line 3: also important\n\
+             line 4: not relevant\n\
+             DATA\n\
+             cat {}\n\
+             exit\n",
+            output_file.display(),
+            output_file.display()
+        ))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("line 2: important data"))
+        .stdout(predicate::str::contains("line 3: also important"));
+}
+
+#[test]
+fn test_script_basic_execution() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("test.rsh");
+
+    // スクリプトファイルを作成
+    fs::write(
+        &script_file,
+        "echo Hello from script\n\
+         pwd\n\
+         echo Done\n",
+    )
+    .unwrap();
+
+    // スクリプトを実行
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello from script"))
+        .stdout(predicate::str::contains("Done"));
+}
+
+#[test]
+fn test_script_with_shebang_and_comments() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("commented.rsh");
+
+    fs::write(
+        &script_file,
+        "#!/usr/bin/env rucli\n\
+         # This is a comment\n\
+         echo First line\n\
+         # Another comment\n\
+         \n\
+         echo Second line\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("First line"))
+        .stdout(predicate::str::contains("Second line"))
+        .stdout(predicate::str::contains("#!/usr/bin/env rucli").not())
+        .stdout(predicate::str::contains("# This is a comment").not());
+}
+
+#[test]
+fn test_script_with_error_continues() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("error.rsh");
+
+    fs::write(
+        &script_file,
+        "echo Before error\n\
+         cat nonexistent.txt\n\
+         echo After error\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success() // スクリプトは続行
+        .stdout(predicate::str::contains("Before error"))
+        .stdout(predicate::str::contains("After error"))
+        .stderr(predicate::str::contains("No such file"));
+}
+
+#[test]
+fn test_script_exit_status() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("status.rsh");
+
+    fs::write(
+        &script_file,
+        "echo running\n\
+         exit 3\n\
+         echo unreachable\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .code(3)
+        .stdout(predicate::str::contains("running"))
+        .stdout(predicate::str::contains("unreachable").not());
+}
+
+#[test]
+fn test_dry_run_script_prints_commands_without_running_them() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.log"), "a").unwrap();
+    fs::write(temp_dir.path().join("b.log"), "b").unwrap();
+    let script_file = temp_dir.path().join("cleanup.rsh");
+
+    fs::write(
+        &script_file,
+        "DIR=archive\n\
+         for f in a.log b.log\n\
+         do\n\
+           rm $f\n\
+         done\n\
+         mkdir -p $DIR/old\n\
+         echo done > $DIR/status.txt\n\
+         sleep 30\n\
+         echo finished\n",
+    )
+    .unwrap();
+
+    // 展開後のコマンドを順に表示し、ファイルは変更しない（sleepも待たない）
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg("--dry-run")
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .success()
+        .stdout(
+            "would run: rm a.log\n\
+             would run: rm b.log\n\
+             would run: mkdir -p archive/old\n\
+             would run: echo done > archive/status.txt\n\
+             would run: sleep 30\n\
+             finished\n",
+        );
+
+    assert!(temp_dir.path().join("a.log").exists());
+    assert!(temp_dir.path().join("b.log").exists());
+    assert!(!temp_dir.path().join("archive").exists());
+
+    // set -n でも同じように動き、set +n で元に戻る
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "set -n\n\
+             rm a.log\n\
+             set +n\n\
+             rm b.log\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("would run: rm a.log"))
+        .stdout(predicate::str::contains("would run: rm b.log").not());
+
+    assert!(temp_dir.path().join("a.log").exists());
+    assert!(!temp_dir.path().join("b.log").exists());
+}
+
+#[test]
+fn test_env_file_for_script_and_command_string() {
+    let temp_dir = TempDir::new().unwrap();
+    let env_file = temp_dir.path().join("ci.env");
+    let script_file = temp_dir.path().join("build.rsh");
+
+    // RUCLI_ENVで関数・エイリアス・変数を定義する
+    fs::write(
+        &env_file,
+        "# CI settings\n\
+         TARGET=release\n\
+         alias say=echo\n\
+         function build() {\n\
+         \x20 say building $1 as $TARGET\n\
+         }\n",
+    )
+    .unwrap();
+    fs::write(&script_file, "build app\n").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_ENV", &env_file)
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building app as release"));
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_ENV", &env_file)
+        .args(["-c", "build lib"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("building lib as release"));
+
+    // 対話モードでは読み込まない
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_ENV", &env_file)
+        .current_dir(&temp_dir)
+        .write_stdin("echo target=$TARGET\nexit\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("target=\n"));
+}
+
+#[test]
+fn test_env_file_errors_abort_the_run() {
+    let temp_dir = TempDir::new().unwrap();
+    let env_file = temp_dir.path().join("broken.env");
+    let script_file = temp_dir.path().join("build.rsh");
+    fs::write(&script_file, "echo script body\n").unwrap();
+
+    // 失敗したコマンドのファイル名と行番号を表示し、本体を実行しない
+    fs::write(
+        &env_file,
+        "echo setting up\ncat missing.txt\necho unreachable\n",
+    )
+    .unwrap();
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_ENV", &env_file)
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("setting up"))
+        .stdout(predicate::str::contains("unreachable").not())
+        .stdout(predicate::str::contains("script body").not())
+        .stderr(predicate::str::contains(
+            "broken.env: line 2: command exited with status 1",
+        ));
+
+    // 存在しないファイル
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_ENV", temp_dir.path().join("missing.env"))
+        .args(["-c", "echo body"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("body").not())
+        .stderr(predicate::str::contains("missing.env"));
+}
+
+#[test]
+fn test_exec_redirects_shell_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("deploy.rsh");
+
+    // exec以降の出力はすべてファイルに書き出す
+    fs::write(
+        &script_file,
+        "echo before\n\
+         LOG=deploy.log\n\
+         exec > $LOG\n\
+         echo logged\n\
+         for i in 1 2; do echo step$i; done\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("before"))
+        .stdout(predicate::str::contains("logged").not());
+
+    let log = fs::read_to_string(temp_dir.path().join("deploy.log")).unwrap();
+    assert_eq!(log, "logged\nstep1\nstep2\n");
+}
+
+#[test]
+fn test_exec_replaces_shell() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("replace.rsh");
+
+    // 置き換えたコマンドの終了ステータスで終わり、以降の行は実行しない
+    fs::write(
+        &script_file,
+        "NAME=rucli\n\
+         exec sh -c \"echo replaced by $NAME; exit 4\"\n\
+         echo unreachable\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains("replaced by rucli"))
+        .stdout(predicate::str::contains("unreachable").not());
+
+    // 実行できなければシェルはそのまま続行する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "exec no_such_program_for_exec\n\
+             echo status=$?\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("status=127"))
+        .stderr(predicate::str::contains("exec: no_such_program_for_exec"));
+}
+
+#[test]
+fn test_command_string_exit_status() {
+    let temp_dir = TempDir::new().unwrap();
+
+    run_in(&temp_dir, "echo fine")
+        .code(0)
+        .stdout(predicate::str::contains("fine"));
+    run_in(&temp_dir, "cat missing").code(1);
+    run_in(&temp_dir, "no_such_command").code(127);
+    run_in(&temp_dir, "repeat abc hi").code(2);
+    run_in(&temp_dir, "exit 42").code(42);
+}
+
+#[test]
+fn test_interactive_records_status() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "no_such_command\n\
+             echo status=$?\n\
+             cat missing\n\
+             echo status=$?\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("status=127"))
+        .stdout(predicate::str::contains("status=1\n"));
+}
+
+#[test]
+fn test_script_not_found() {
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg("nonexistent.rsh")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Script file nonexistent.rsh not found",
+        ));
+}
+
+#[test]
+fn test_script_with_variables() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("vars.rsh");
+
+    fs::write(
+        &script_file,
+        "env NAME=Script\n\
+         echo Hello $NAME\n\
+         env VERSION=1.0\n\
+         echo Version: $VERSION\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Hello Script"))
+        .stdout(predicate::str::contains("Version: 1.0"));
+}
+
+#[test]
+fn test_script_with_command_substitution() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("subst.rsh");
+
+    fs::write(
+        &script_file,
+        "echo Current dir: $(pwd)\n\
+         echo Echo test: $(echo nested)\n\
+         env VAR=test\n\
+         echo Variable in substitution: $(echo $VAR)\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Current dir:"))
+        .stdout(predicate::str::contains("Echo test: nested"))
+        .stdout(predicate::str::contains("Variable in substitution: test"));
+}
+
+#[test]
+fn test_script_file_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("fileops.rsh");
+
+    fs::write(
+        &script_file,
+        "write test.txt Script created this file\n\
+         cat test.txt\n\
+         cp test.txt backup.txt\n\
+         cat backup.txt\n\
+         rm test.txt\n\
+         rm backup.txt\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("File written successfully"))
+        .stdout(predicate::str::contains("Script created this file").count(2));
+}
+
+#[test]
+fn test_script_with_pipelines() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("pipes.rsh");
+
+    fs::write(
+        &script_file,
+        "echo apple > fruits.txt\n\
+         echo banana >> fruits.txt\n\
+         echo apricot >> fruits.txt\n\
+         cat fruits.txt | grep a\n\
+         cat fruits.txt | grep a | wc -l\n\
+         rm fruits.txt\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("apple"))
+        .stdout(predicate::str::contains("banana"))
+        .stdout(predicate::str::contains("apricot"));
+}
+
+#[test]
+fn test_script_with_redirections() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("redirect.rsh");
+
+    fs::write(
+        &script_file,
+        "echo First line > output.txt\n\
+         echo Second line >> output.txt\n\
+         cat < output.txt\n\
+         rm output.txt\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("First line"))
+        .stdout(predicate::str::contains("Second line"));
+}
+
+#[test]
+fn test_script_with_background_jobs() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("background.rsh");
+
+    fs::write(
+        &script_file,
+        "echo Starting background job\n\
+         sleep 1 &\n\
+         echo Background job started\n\
+         jobs\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Starting background job"))
+        .stdout(predicate::str::contains("[1]+ Running    sleep 1\n"))
+        .stdout(predicate::str::contains("Background job started"));
+}
+
+#[test]
+fn test_script_with_directory_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("dirs.rsh");
+
+    fs::write(
+        &script_file,
+        "mkdir test_dir\n\
+         cd test_dir\n\
+         pwd\n\
+         write file.txt content\n\
+         ls\n\
+         cd ..\n\
+         rm -rf test_dir\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("test_dir"))
+        .stdout(predicate::str::contains("file.txt"));
+}
+
+#[test]
+fn test_script_with_aliases() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("alias.rsh");
+
+    // Note: エイリアスはセッション内でのみ有効
+    fs::write(
+        &script_file,
+        "alias ll=ls\n\
+         alias\n\
+         write test.txt content\n\
+         ll\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ll = ls"))
+        .stdout(predicate::str::contains("test.txt"));
+}
+
+#[test]
+fn test_script_empty_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("empty.rsh");
+
+    fs::write(&script_file, "").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_script_only_comments() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("comments_only.rsh");
+
+    fs::write(
+        &script_file,
+        "#!/usr/bin/env rucli\n\
+         # Just comments\n\
+         # Nothing to execute\n\
+         \n\
+         # More comments\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn test_script_with_find_and_grep() {
     let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("search.rsh");
+
+    fs::write(
+        &script_file,
+        "write test1.txt contains search term\n\
+         write test2.rs rust code\n\
+         write data.json {}\n\
+         find . *.txt\n\
+         grep search test1.txt\n\
+         rm test1.txt\n\
+         rm test2.rs\n\
+         rm data.json\n",
+    )
+    .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
+        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
-        .write_stdin(
-            "cat <<SPECIAL\n\
-             Special chars: !@#$%^&*()\n\
-             Quotes: \"double\" and 'single'\n\
-             Path: /usr/local/bin\n\
-             SPECIAL\n\
-             exit\n",
-        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Special chars: !@#$%^&*()"))
-        .stdout(predicate::str::contains("Quotes: \"double\" and 'single'"))
-        .stdout(predicate::str::contains("Path: /usr/local/bin"));
+        .stdout(predicate::str::contains("test1.txt"))
+        .stdout(predicate::str::contains("contains search term"));
 }
 
 #[test]
-fn test_heredoc_delimiter_in_content() {
+fn test_script_complex_workflow() {
     let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("workflow.rsh");
+
+    fs::write(
+        &script_file,
+        "#!/usr/bin/env rucli\n\
+         # Complex workflow test\n\
+         echo Setting up project...\n\
+         \n\
+         # Create directory structure\n\
+         mkdir -p project/src\n\
+         mkdir -p project/tests\n\
+         \n\
+         # Create files\n\
+         cd project\n\
+         write src/main.rs fn main() {}\n\
+         write Cargo.toml [package]\n\
+         \n\
+         # List created files\n\
+         find . *.rs\n\
+         find . *.toml\n\
+         \n\
+         # Cleanup\n\
+         cd ..\n\
+         rm -rf project\n\
+         echo Workflow completed!\n",
+    )
+    .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
+        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
-        .write_stdin(
-            "cat <<EOF\n\
-             This line contains EOF in the middle\n\
-             EOF at the start of line is still content\n\
-             Only a line with exactly EOF ends it\n\
-             EOF\n\
-             exit\n",
-        )
         .assert()
         .success()
-        .stdout(predicate::str::contains(
-            "This line contains EOF in the middle",
-        ))
-        .stdout(predicate::str::contains(
-            "EOF at the start of line is still content",
-        ))
-        .stdout(predicate::str::contains(
-            "Only a line with exactly EOF ends it",
-        ));
+        .stdout(predicate::str::contains("Setting up project..."))
+        .stdout(predicate::str::contains("main.rs"))
+        .stdout(predicate::str::contains("Cargo.toml"))
+        .stdout(predicate::str::contains("Workflow completed!"));
 }
 
 #[test]
-fn test_heredoc_with_pipeline_and_redirect() {
+fn test_script_line_continuation() {
     let temp_dir = TempDir::new().unwrap();
-    let output_file = temp_dir.path().join("filtered.txt");
+    let script_file = temp_dir.path().join("continued.rsh");
+
+    fs::write(
+        &script_file,
+        "echo one two \\\n  three \\\n  four\n\
+         echo keep | \\\n  grep keep\n\
+         echo 'not\\' continued\n",
+    )
+    .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
+        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
-        .write_stdin(format!(
-            "grep line <<DATA | grep important > {}\n\
-             line 1: not important\n\
-             line 2: important data\n\
-             line 3: also important\n\
-             line 4: not relevant\n\
-             DATA\n\
-             cat {}\n\
-             exit\n",
-            output_file.display(),
-            output_file.display()
-        ))
         .assert()
         .success()
-        .stdout(predicate::str::contains("line 2: important data"))
-        .stdout(predicate::str::contains("line 3: also important"));
+        .stdout("one two three four\nkeep\n'not\\' continued\n");
 }
 
 #[test]
-fn test_script_basic_execution() {
+fn test_comments_in_commands_and_scripts() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("test.rsh");
+    let script_file = temp_dir.path().join("commented_tail.rsh");
+    fs::write(temp_dir.path().join("file#1"), "hash name").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "echo hello # greet the user"])
+        .assert()
+        .success()
+        .stdout("hello\n");
 
-    // スクリプトファイルを作成
     fs::write(
         &script_file,
-        "echo Hello from script\n\
-         pwd\n\
-         echo Done\n",
+        "echo start # first\n\
+         cat file#1 # glued hash is literal\n\
+         echo '# quoted' \"#too\"\n",
     )
     .unwrap();
 
-    // スクリプトを実行
     Command::cargo_bin("rucli")
         .unwrap()
         .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
         .assert()
         .success()
-        .stdout(predicate::str::contains("Hello from script"))
-        .stdout(predicate::str::contains("Done"));
+        .stdout("start\nhash name\n'# quoted' \"#too\"\n");
+
+    // 対話モードの単独の # は何もしない
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .write_stdin("#\necho after\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("after").and(predicate::str::contains("#").not()));
 }
 
 #[test]
-fn test_script_with_shebang_and_comments() {
+fn test_exit_trap_runs_when_script_exits_early() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("commented.rsh");
+    let script_file = temp_dir.path().join("deploy.rsh");
+    fs::write(
+        &script_file,
+        "LOCKFILE=deploy.lock\n\
+         write $LOCKFILE locked\n\
+         trap \"rm -f $LOCKFILE; write marker.txt cleaned\" EXIT\n\
+         cat missing.txt\n\
+         exit 3\n\
+         echo unreachable\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .code(3)
+        .stdout(predicate::str::contains("unreachable").not());
 
+    assert!(temp_dir.path().join("marker.txt").exists());
+    assert!(!temp_dir.path().join("deploy.lock").exists());
+
+    // set -e で止まった場合も実行する
     fs::write(
         &script_file,
-        "#!/usr/bin/env rucli\n\
-         # This is a comment\n\
-         echo First line\n\
-         # Another comment\n\
+        "set -e\ntrap \"echo cleanup\" EXIT\ncat missing.txt\necho unreachable\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("cleanup"))
+        .stdout(predicate::str::contains("unreachable").not());
+}
+
+#[test]
+fn test_verbose_mode_echoes_script_lines() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("ci.rsh");
+    fs::write(
+        &script_file,
+        "# build steps\n\
+         echo one\n\
          \n\
-         echo Second line\n",
+         for i in 1 2\n\
+         do\n\
+         \x20 echo item $i\n\
+         done\n\
+         set +v\n\
+         echo quiet\n\
+         set -v\n\
+         echo loud\n",
     )
     .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
+        .arg("-v")
         .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
         .assert()
         .success()
-        .stdout(predicate::str::contains("First line"))
-        .stdout(predicate::str::contains("Second line"))
-        .stdout(predicate::str::contains("#!/usr/bin/env rucli").not())
-        .stdout(predicate::str::contains("# This is a comment").not());
+        .stdout("one\nitem 1\nitem 2\nquiet\nloud\n")
+        .stderr(
+            "2: echo one\n4: for i in 1 2\n5: do\n6:   echo item $i\n7: done\n8: set +v\n11: echo loud\n",
+        );
 }
 
 #[test]
-fn test_script_with_error_continues() {
+fn test_parse_only_prints_ast_without_running() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("lint.rsh");
+    fs::write(
+        &script_file,
+        "# no side effects\n\
+         write created.txt data\n\
+         for f in a b\n\
+         do\n\
+         \x20 echo $f | grep a\n\
+         done\n",
+    )
+    .unwrap();
+
+    let output = Command::cargo_bin("rucli")
+        .unwrap()
+        .arg("--parse-only")
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!temp_dir.path().join("created.txt").exists());
+
+    let ast: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(ast[0]["line"], 2);
+    assert_eq!(ast[0]["command"]["Write"]["filename"], "created.txt");
+    assert_eq!(ast[1]["line"], 3);
+    let for_loop = &ast[1]["command"]["For"];
+    assert_eq!(for_loop["variable"], "f");
+    assert_eq!(for_loop["items"], serde_json::json!(["a", "b"]));
+    let stages = &for_loop["body"]["Pipeline"]["commands"];
+    assert_eq!(stages[0]["Echo"]["message"], "$f");
+    assert_eq!(stages[1]["Grep"]["pattern"], "a");
+}
+
+#[test]
+fn test_parse_only_reports_syntax_errors_with_line_numbers() {
+    let temp_dir = TempDir::new().unwrap();
+    let script_file = temp_dir.path().join("broken.rsh");
+    fs::write(&script_file, "echo ok\n\ntime\necho fine\nif true\n").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg("--parse-only")
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "broken.rsh: line 3: Parse error: syntax error: command expected after 'time'",
+        ))
+        .stderr(predicate::str::contains(
+            "broken.rsh: line 5: incomplete block structure at end of file",
+        ));
+}
+
+#[test]
+fn test_startup_flags() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("-dash.rsh"), "echo dash script\n").unwrap();
+    fs::write(temp_dir.path().join("profile"), "GREETING=from-profile\n").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .arg("--version")
+        .assert()
+        .success()
+        .stdout(format!("rucli v{}\n", env!("CARGO_PKG_VERSION")));
+
+    // 知らないオプションは実行せずに使い方を表示する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["--bogus", "-c", "echo ran"])
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains("unknown option '--bogus'"))
+        .stderr(predicate::str::contains("Usage: rucli"));
+
+    // -- の後は - で始まってもスクリプト名
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["--norc", "--", "-dash.rsh"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout("dash script\n");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["--login", "-c", "echo $GREETING"])
+        .env("RUCLI_PROFILE", temp_dir.path().join("profile"))
+        .assert()
+        .success()
+        .stdout("from-profile\n");
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "echo none$GREETING"])
+        .env("RUCLI_PROFILE", temp_dir.path().join("profile"))
+        .assert()
+        .success()
+        .stdout("none\n");
+}
+
+#[test]
+fn test_interactive_shell_reads_rc_file_unless_norc() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join(".ruclirc"),
+        "alias hello='echo from-rc'\n",
+    )
+    .unwrap();
+
+    let run = |args: &[&str]| {
+        Command::cargo_bin("rucli")
+            .unwrap()
+            .args(args)
+            .env("HOME", temp_dir.path())
+            .env("RUCLI_HISTFILE", temp_dir.path().join(".history"))
+            .current_dir(&temp_dir)
+            .write_stdin("hello\nexit\n")
+            .assert()
+    };
+
+    // 対話シェルは ~/.ruclirc を読み込む
+    run(&[])
+        .success()
+        .stdout(predicate::str::contains("from-rc"));
+
+    // --norc なら読み込まない
+    run(&["--norc"])
+        .success()
+        .stdout(predicate::str::contains("from-rc").not())
+        .stderr(predicate::str::contains("unknown command"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_script_runs_through_shebang_with_arguments() {
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let script = fixtures.join("show_args.rsh");
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("error.rsh");
 
-    fs::write(
-        &script_file,
-        "echo Before error\n\
-         cat nonexistent.txt\n\
-         echo After error\n",
-    )
-    .unwrap();
+    // シバンの `env rucli` が見つかるよう、ビルドしたバイナリのディレクトリをPATHの先頭に置く
+    let bin_dir = std::path::Path::new(env!("CARGO_BIN_EXE_rucli"))
+        .parent()
+        .unwrap();
+    let path = format!("{}:{}", bin_dir.display(), std::env::var("PATH").unwrap());
+
+    // スクリプトより後の引数は - で始まっていてもスクリプトに渡る
+    Command::new(&script)
+        .args(["one", "-v", "--debug"])
+        .env("PATH", &path)
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout("args: one -v --debug\nsibling file\n")
+        .stderr("");
 
+    // スクリプトより前はrucliのオプション、-- の次はスクリプト
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
+        .arg("--norc")
+        .arg("--")
+        .arg(&script)
+        .args(["--", "two", "three"])
         .current_dir(&temp_dir)
         .assert()
-        .success() // スクリプトは続行
-        .stdout(predicate::str::contains("Before error"))
-        .stdout(predicate::str::contains("After error"))
-        .stderr(predicate::str::contains("No such file"));
-}
+        .success()
+        .stdout("args: -- two three\nsibling file\n");
 
-#[test]
-fn test_script_not_found() {
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg("nonexistent.rsh")
+        .args(["-c", "echo hi", "extra"])
         .assert()
-        .failure()
+        .code(2)
         .stderr(predicate::str::contains(
-            "Script file nonexistent.rsh not found",
+            "-c cannot be combined with a script file",
         ));
 }
 
 #[test]
-fn test_script_with_variables() {
+fn test_compound_continues_after_failure() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("vars.rsh");
 
-    fs::write(
-        &script_file,
-        "env NAME=Script\n\
-         echo Hello $NAME\n\
-         env VERSION=1.0\n\
-         echo Version: $VERSION\n",
-    )
-    .unwrap();
+    // 途中のエラーを表示して、残りのコマンドも実行する
+    run_in(&temp_dir, "echo one; cat missing.txt; echo three")
+        .success()
+        .stdout("one\nthree\n")
+        .stderr(predicate::str::contains("No such file"));
+
+    // $? と全体のステータスは最後のコマンドのもの
+    run_in(&temp_dir, "cat missing.txt; echo status=$?")
+        .success()
+        .stdout("status=1\n");
+    run_in(&temp_dir, "echo one; cat missing.txt")
+        .failure()
+        .stdout("one\n");
+
+    // set -e では最初の失敗で止まる
+    run_in(&temp_dir, "set -e; echo one; cat missing.txt; echo three")
+        .failure()
+        .stdout("one\n");
 
+    let script = temp_dir.path().join("errexit.rsh");
+    fs::write(&script, "set -e\necho one\ncat missing.txt\necho three\n").unwrap();
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
+        .arg(&script)
+        .current_dir(&temp_dir)
         .assert()
-        .success()
-        .stdout(predicate::str::contains("Hello Script"))
-        .stdout(predicate::str::contains("Version: 1.0"));
+        .failure()
+        .stdout("one\n");
 }
 
 #[test]
-fn test_script_with_command_substitution() {
+fn test_variable_assignment() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("subst.rsh");
-
-    fs::write(
-        &script_file,
-        "echo Current dir: $(pwd)\n\
-         echo Echo test: $(echo nested)\n\
-         env VAR=test\n\
-         echo Variable in substitution: $(echo $VAR)\n",
-    )
-    .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
+        .write_stdin(
+            "COUNT=5\n\
+             A=1 MSG=\"hello world\"\n\
+             echo count=$COUNT a=$A msg=$MSG\n\
+             exit\n",
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Current dir:"))
-        .stdout(predicate::str::contains("Echo test: nested"))
-        .stdout(predicate::str::contains("Variable in substitution: test"));
+        .stdout(predicate::str::contains("count=5 a=1 msg=hello world"))
+        .stderr(predicate::str::contains("unknown command").not());
 }
 
 #[test]
-fn test_script_file_operations() {
+fn test_variable_assignment_prefix_does_not_leak() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("fileops.rsh");
-
-    fs::write(
-        &script_file,
-        "write test.txt Script created this file\n\
-         cat test.txt\n\
-         cp test.txt backup.txt\n\
-         cat backup.txt\n\
-         rm test.txt\n\
-         rm backup.txt\n",
-    )
-    .unwrap();
 
+    // 引数は代入前に展開され、代入はコマンドの実行中だけ有効
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
+        .write_stdin(
+            "FOO=outer\n\
+             FOO=inner echo during=$FOO\n\
+             echo after=$FOO\n\
+             BAR=temp echo x\n\
+             echo bar=[$BAR]\n\
+             function show() { echo inside=$BAR; }\n\
+             BAR=temp show\n\
+             exit\n",
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("File written successfully"))
-        .stdout(predicate::str::contains("Script created this file").count(2));
+        .stdout(predicate::str::contains("during=outer"))
+        .stdout(predicate::str::contains("after=outer"))
+        .stdout(predicate::str::contains("bar=[]"))
+        .stdout(predicate::str::contains("inside=temp"));
+
+    run_in(&temp_dir, "X=1 echo [$X]").success().stdout("[]\n");
 }
 
 #[test]
-fn test_script_with_pipelines() {
+fn test_variable_assignment_from_command_substitution_is_not_split() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("pipes.rsh");
-
-    fs::write(
-        &script_file,
-        "echo apple > fruits.txt\n\
-         echo banana >> fruits.txt\n\
-         echo apricot >> fruits.txt\n\
-         cat fruits.txt | grep a\n\
-         cat fruits.txt | grep a | wc -l\n\
-         rm fruits.txt\n",
-    )
-    .unwrap();
 
+    // 複数の語や複数行の結果も一つの値になる（ループの中では実行時に展開する）
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
+        .write_stdin(
+            "X=$(seq 1 2)\n\
+             Y=$(echo a b) Z=`echo c d`\n\
+             echo x=[$X] y=[$Y] z=[$Z]\n\
+             function show_w() { echo w=[$W]; }\n\
+             W=$(echo w1 w2) show_w\n\
+             for i in 1; do L=$(seq 3 4); echo l=[$L]; done\n\
+             exit\n",
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("apple"))
-        .stdout(predicate::str::contains("banana"))
-        .stdout(predicate::str::contains("apricot"));
+        .stdout(predicate::str::contains("x=[1\n2]"))
+        .stdout(predicate::str::contains("y=[a b] z=[c d]"))
+        .stdout(predicate::str::contains("w=[w1 w2]"))
+        .stdout(predicate::str::contains("l=[3\n4]"))
+        .stderr(predicate::str::contains("unknown command").not());
 }
 
 #[test]
-fn test_script_with_redirections() {
+fn test_read_from_stdin() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("redirect.rsh");
-
-    fs::write(
-        &script_file,
-        "echo First line > output.txt\n\
-         echo Second line >> output.txt\n\
-         cat < output.txt\n\
-         rm output.txt\n",
-    )
-    .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
+        .write_stdin(
+            "read -p \"Name: \" FIRST REST\n\
+             Ada King Lovelace\n\
+             echo first=$FIRST rest=$REST\n\
+             read\n\
+             plain line\n\
+             echo reply=$REPLY\n\
+             exit\n",
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("First line"))
-        .stdout(predicate::str::contains("Second line"));
+        .stdout(predicate::str::contains("first=Ada rest=King Lovelace"))
+        .stdout(predicate::str::contains("reply=plain line"))
+        .stderr(predicate::str::contains("Name: "));
 }
 
 #[test]
-fn test_script_with_background_jobs() {
+fn test_read_from_pipeline() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("background.rsh");
-
-    fs::write(
-        &script_file,
-        "echo Starting background job\n\
-         sleep 1 &\n\
-         echo Background job started\n\
-         jobs\n",
-    )
-    .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo a b c d | read X Y Z\n\
+             echo x=$X y=$Y z=$Z\n\
+             exit\n",
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("Starting background job"))
-        .stdout(predicate::str::contains("[1]"))
-        .stdout(predicate::str::contains("Background job started"));
+        .stdout(predicate::str::contains("x=a y=b z=c d"));
 }
 
 #[test]
-fn test_script_with_directory_operations() {
+fn test_read_at_end_of_input_fails() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("dirs.rsh");
-
-    fs::write(
-        &script_file,
-        "mkdir test_dir\n\
-         cd test_dir\n\
-         pwd\n\
-         write file.txt content\n\
-         ls\n\
-         cd ..\n\
-         rm -rf test_dir\n",
-    )
-    .unwrap();
+    std::fs::write(temp_dir.path().join("empty.txt"), "").unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
+        .write_stdin("read LINE < empty.txt\nexit\n")
         .assert()
         .success()
-        .stdout(predicate::str::contains("test_dir"))
-        .stdout(predicate::str::contains("file.txt"));
+        .stderr(predicate::str::contains("end of input"));
 }
 
 #[test]
-fn test_script_with_aliases() {
+fn test_arithmetic_division_by_zero() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("alias.rsh");
-
-    // Note: エイリアスはセッション内でのみ有効
-    fs::write(
-        &script_file,
-        "alias ll=ls\n\
-         alias\n\
-         write test.txt content\n\
-         ll\n",
-    )
-    .unwrap();
 
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
+        .write_stdin("echo $((1 / 0))\nexit\n")
         .assert()
         .success()
-        .stdout(predicate::str::contains("ll = ls"))
-        .stdout(predicate::str::contains("test.txt"));
+        .stderr(predicate::str::contains("division by zero"));
 }
 
 #[test]
-fn test_script_empty_file() {
+fn test_brace_expansion() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("empty.rsh");
 
-    fs::write(&script_file, "").unwrap();
+    run_in(&temp_dir, "mkdir -p src/{bin,lib,tests}").success();
+    for dir in ["bin", "lib", "tests"] {
+        assert!(temp_dir.path().join("src").join(dir).is_dir());
+    }
 
-    Command::cargo_bin("rucli")
-        .unwrap()
-        .arg(script_file.to_str().unwrap())
-        .assert()
-        .success();
+    run_in(&temp_dir, "echo file{1..3}.txt")
+        .success()
+        .stdout("file1.txt file2.txt file3.txt\n");
+    run_in(&temp_dir, "for i in {01..03}; do echo n$i; done")
+        .success()
+        .stdout("n01\nn02\nn03\n");
+
+    // クォート内や xargs の {} は展開しない
+    run_in(&temp_dir, "echo '{a,b}' {x,y}")
+        .success()
+        .stdout("'{a,b}' x y\n");
+    run_in(&temp_dir, "echo a | xargs -I {} echo [{}]")
+        .success()
+        .stdout("[a]\n");
 }
 
 #[test]
-fn test_script_only_comments() {
+fn test_tilde_expansion_in_arguments() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("comments_only.rsh");
+    let home = TempDir::new().unwrap();
+    fs::write(home.path().join("notes.txt"), "from home").unwrap();
+    fs::create_dir(home.path().join("backups")).unwrap();
+    fs::write(temp_dir.path().join("file.txt"), "backup me").unwrap();
+
+    let run = |command: &str| {
+        rucli_in(&temp_dir, command)
+            .env("HOME", home.path())
+            .assert()
+    };
+
+    run("cat ~/notes.txt").success().stdout("from home\n");
+
+    run("cp file.txt ~/backups/").success();
+    assert_eq!(
+        fs::read_to_string(home.path().join("backups/file.txt")).unwrap(),
+        "backup me"
+    );
 
-    fs::write(
-        &script_file,
-        "#!/usr/bin/env rucli\n\
-         # Just comments\n\
-         # Nothing to execute\n\
-         \n\
-         # More comments\n",
-    )
-    .unwrap();
+    run("cd ~; ls")
+        .success()
+        .stdout(predicate::str::contains("notes.txt").and(predicate::str::contains("backups/")));
 
-    Command::cargo_bin("rucli")
-        .unwrap()
-        .arg(script_file.to_str().unwrap())
-        .assert()
+    // クォートされたチルダはそのまま
+    run("echo '~' ~/x")
         .success()
-        .stdout(predicate::str::is_empty());
+        .stdout(format!("'~' {}/x\n", home.path().display()));
 }
 
 #[test]
-fn test_script_with_find_and_grep() {
-    let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("search.rsh");
-
-    fs::write(
-        &script_file,
-        "write test1.txt contains search term\n\
-         write test2.rs rust code\n\
-         write data.json {}\n\
-         find . *.txt\n\
-         grep search test1.txt\n\
-         rm test1.txt\n\
-         rm test2.rs\n\
-         rm data.json\n",
-    )
-    .unwrap();
-
+fn test_shell_variables_reach_substitutions_and_pipeline_stages() {
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
-        .current_dir(&temp_dir)
+        .write_stdin(
+            "for i in a b; do echo $(echo x$i); done\n\
+             NAME=session\n\
+             echo one | echo stage=$NAME\n\
+             function show() {\n\
+             echo arg=$(echo $1)\n\
+             }\n\
+             show first\n\
+             echo after=$1.\n\
+             exit\n",
+        )
         .assert()
         .success()
-        .stdout(predicate::str::contains("test1.txt"))
-        .stdout(predicate::str::contains("contains search term"));
+        .stdout(predicate::str::contains("xa\nxb\n"))
+        .stdout(predicate::str::contains("stage=session"))
+        .stdout(predicate::str::contains("arg=first"))
+        .stdout(predicate::str::contains("after=."));
 }
 
 #[test]
-fn test_script_complex_workflow() {
+fn test_recursive_substitution_reports_nesting_limit() {
     let temp_dir = TempDir::new().unwrap();
-    let script_file = temp_dir.path().join("workflow.rsh");
-
-    fs::write(
-        &script_file,
-        "#!/usr/bin/env rucli\n\
-         # Complex workflow test\n\
-         echo Setting up project...\n\
-         \n\
-         # Create directory structure\n\
-         mkdir -p project/src\n\
-         mkdir -p project/tests\n\
-         \n\
-         # Create files\n\
-         cd project\n\
-         write src/main.rs fn main() {}\n\
-         write Cargo.toml [package]\n\
-         \n\
-         # List created files\n\
-         find . *.rs\n\
-         find . *.toml\n\
-         \n\
-         # Cleanup\n\
-         cd ..\n\
-         rm -rf project\n\
-         echo Workflow completed!\n",
-    )
-    .unwrap();
 
+    // 自分自身を置換で呼ぶ関数はスタックを使い切らずにエラーで止まる
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
         .current_dir(&temp_dir)
+        .write_stdin("function loop() {\necho $(loop)\n}\nloop\necho status $?\n")
         .assert()
         .success()
-        .stdout(predicate::str::contains("Setting up project..."))
-        .stdout(predicate::str::contains("main.rs"))
-        .stdout(predicate::str::contains("Cargo.toml"))
-        .stdout(predicate::str::contains("Workflow completed!"));
+        .stdout(predicate::str::contains("status 1"))
+        .stderr(predicate::str::contains(
+            "function 'loop' nested too deeply (limit 64)",
+        ));
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .args(["-c", "RUCLI_MAX_DEPTH=2\necho $(echo $(echo $(echo x)))"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "command substitution nested too deeply (limit 2)",
+        ));
+}
+
+#[test]
+fn test_quoted_command_substitution_is_one_argument() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo \"Lines: $(seq 2)\"\n\
+             function count() { echo \"$1|$2|$3\"; }\n\
+             count $(seq 3)\n\
+             count \"$(seq 2)\" x\n\
+             for i in 1; do count $(seq 2 3); done\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"Lines: 1\n2\""))
+        .stdout(predicate::str::contains("\"1|2|3\""))
+        .stdout(predicate::str::contains("\"1\n2|x|\""))
+        .stdout(predicate::str::contains("\"2|3|\""));
+}
+
+#[test]
+fn test_command_that_expands_to_nothing_does_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let cases = [
+        ("$(cd .)", ""),
+        ("`echo -n`", ""),
+        ("echo a; $(echo -n); echo b", "a\nb\n"),
+    ];
+
+    for (command, expected) in cases {
+        Command::cargo_bin("rucli")
+            .unwrap()
+            .args(["-c", command])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(expected)
+            .stderr("");
+    }
+}
+
+#[test]
+fn test_substitution_output_is_not_parsed_as_syntax() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("pipe.txt"), "a | write inj1.txt z").unwrap();
+    fs::write(temp_dir.path().join("redirect.txt"), "a > inj2.txt").unwrap();
+    fs::write(temp_dir.path().join("semicolon.txt"), "a; write inj3.txt z").unwrap();
+
+    // 置換結果の | > ; はただの引数になる
+    run_in(&temp_dir, "echo $(cat pipe.txt)")
+        .success()
+        .stdout("a | write inj1.txt z\n");
+    run_in(&temp_dir, "echo $(cat redirect.txt)")
+        .success()
+        .stdout("a > inj2.txt\n");
+    run_in(&temp_dir, "echo $(cat semicolon.txt)")
+        .success()
+        .stdout("a; write inj3.txt z\n");
+    run_in(&temp_dir, "for i in 1; do echo $(cat redirect.txt); done")
+        .success()
+        .stdout("a > inj2.txt\n");
+    for name in ["inj1.txt", "inj2.txt", "inj3.txt"] {
+        assert!(!temp_dir.path().join(name).exists(), "{name} was created");
+    }
+
+    // リダイレクト先の置換は一つのファイル名になる
+    run_in(&temp_dir, "echo $(seq 2) > $(echo out.txt)").success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("out.txt")).unwrap(),
+        "1 2\n"
+    );
 }
 
 #[test]
@@ -1265,8 +4296,7 @@ fn test_if_condition_success() {
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("test"))
-        .stdout(predicate::str::contains("OK"))
+        .stdout(predicate::str::contains("test\nOK\n"))
         .stdout(predicate::str::contains("FAIL").not());
 }
 
@@ -1392,6 +4422,239 @@ fn test_if_in_pipeline() {
         .stdout(predicate::str::contains("Pattern found"));
 }
 
+#[test]
+fn test_negation_inverts_status() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("log.txt"), "all good").unwrap();
+
+    run_in(&temp_dir, "if ! cat missing.txt; then echo clean; fi")
+        .success()
+        .stdout("clean\n");
+    run_in(&temp_dir, "if ! cat log.txt; then echo dirty; fi")
+        .success()
+        .stdout("all good\n");
+
+    // 出力はそのまま、ステータスだけ反転する
+    run_in(&temp_dir, "! echo hi").code(1).stdout("hi\n");
+    run_in(&temp_dir, "! cat log.txt | grep good")
+        .code(1)
+        .stdout("all good\n");
+    run_in(&temp_dir, "! ! echo hi").success().stdout("hi\n");
+
+    // 失敗したコマンドのメッセージは表示され、ステータスは成功になる
+    run_in(&temp_dir, "! cat missing.txt; echo $?")
+        .success()
+        .stdout("0\n")
+        .stderr(predicate::str::contains("No such file"));
+}
+
+#[test]
+fn test_subshell_and_brace_groups() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("build")).unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+
+    // サブシェルの cd と代入は外に影響しない
+    run_in(&temp_dir, "(cd build; pwd; X=inner); pwd; echo x=$X")
+        .success()
+        .stdout(format!(
+            "{}\n{}\nx=\n",
+            root.join("build").display(),
+            root.display()
+        ));
+
+    // 波括弧は現在の環境で実行される
+    run_in(&temp_dir, "{ cd build; Y=outer; }; pwd; echo y=$Y")
+        .success()
+        .stdout(format!("{}\ny=outer\n", root.join("build").display()));
+
+    // グループ全体のリダイレクトとパイプ
+    run_in(&temp_dir, "{ echo a; echo b; } > both.txt")
+        .success()
+        .stdout("");
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("both.txt")).unwrap(),
+        "a\nb\n"
+    );
+    run_in(&temp_dir, "(echo one; echo two) | grep two")
+        .success()
+        .stdout("two\n");
+
+    run_in(&temp_dir, "(echo a")
+        .code(2)
+        .stderr(predicate::str::contains("unmatched"));
+}
+
+#[test]
+fn test_background_subshell_does_not_change_foreground_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("build")).unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+
+    // ジョブの cd と代入は、実行中もフォアグラウンドから見えない
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "(cd build; X=job; sleep 1) &\n\
+             sleep 0.3\n\
+             pwd\n\
+             echo pwd=$PWD x=$X\n\
+             cd build &\n\
+             sleep 0.3\n\
+             pwd\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("> {}\n", root.display())).count(2))
+        .stdout(predicate::str::contains("x=\n"))
+        .stdout(predicate::str::contains("/build").not());
+}
+
+#[test]
+fn test_control_structures_return_output_to_substitution() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 制御構文の出力は端末に漏れず、コマンド置換の結果になる
+    run_in(
+        &temp_dir,
+        "OUT=$(if cd .; then echo yes; fi); echo got=$OUT",
+    )
+    .success()
+    .stdout("got=yes\n");
+    run_in(
+        &temp_dir,
+        "echo got $(if cat missing.txt; then echo a; else echo b; fi)",
+    )
+    .success()
+    .stdout("got b\n");
+    run_in(&temp_dir, "echo got $(for i in 1 2 3; do echo n$i; done)")
+        .success()
+        .stdout("got n1 n2 n3\n");
+
+    // トップレベルでは従来どおり順に表示する
+    run_in(&temp_dir, "for i in 1 2; do echo n$i; done")
+        .success()
+        .stdout("n1\nn2\n");
+}
+
+#[test]
+fn test_unknown_commands_in_pipelines_fail_before_running() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "write first.txt data | bogus x"])
+        .current_dir(&temp_dir)
+        .assert()
+        .code(127)
+        .stderr(predicate::str::contains(
+            "pipeline stage 2 ('bogus x'): unknown command",
+        ));
+    assert!(!temp_dir.path().join("first.txt").exists());
+
+    // ループの本体の文は、実行するときに一つずつ解決する（失敗したところで止まる）
+    run_in(&temp_dir, "for i in 1 2; do write $i.txt data; bogus; done")
+        .code(127)
+        .stderr(predicate::str::contains("unknown command"));
+    assert!(temp_dir.path().join("1.txt").exists());
+    assert!(!temp_dir.path().join("2.txt").exists());
+}
+
+#[test]
+fn test_exit_inside_each_construct_ends_the_shell() {
+    let temp_dir = TempDir::new().unwrap();
+    let cases = [
+        (
+            "if echo a; then echo b; exit 3; echo c; fi; echo after",
+            "a\nb\n",
+            3,
+        ),
+        (
+            "for i in 1 2; do echo $i; exit 4; done; echo after",
+            "1\n",
+            4,
+        ),
+        ("while echo w; do exit 5; done; echo after", "w\n", 5),
+        ("echo g; exit 6; echo h", "g\n", 6),
+        ("echo x | exit 7; echo after", "", 7),
+        ("{ echo f; exit 8; } > exit.txt; echo after", "", 8),
+    ];
+
+    for (script, output, code) in cases {
+        Command::cargo_bin("rucli")
+            .unwrap()
+            .args(["-c", script])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(code)
+            .stdout(predicate::str::starts_with(format!("{output}good bye")))
+            .stdout(predicate::str::contains("after").not());
+    }
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("exit.txt")).unwrap(),
+        "f\n"
+    );
+
+    // 関数の中のexitも呼び出し元に戻らずにシェルを終了する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .write_stdin("function leave() {\necho in\nexit 9\necho no\n}\nleave\necho after\n")
+        .assert()
+        .code(9)
+        .stdout(predicate::str::contains("in\ngood bye"))
+        .stdout(predicate::str::contains("after").not());
+}
+
+#[test]
+fn test_nested_control_structures_on_one_line() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("1"), "").unwrap();
+    run_in(
+        &temp_dir,
+        "for i in 1 2; do if cat $i; then echo found $i; else echo missing $i; fi; done",
+    )
+    .success()
+    .stdout("found 1\nmissing 2\n");
+
+    run_in(
+        &temp_dir,
+        "write outer o\n\
+         while cat outer; do rm outer; write inner i; \
+         while cat inner; do rm inner; echo inner; done; echo outer; done",
+    )
+    .success()
+    .stdout(predicate::str::ends_with("i\ninner\nouter\n"));
+
+    run_in(
+        &temp_dir,
+        r#"if echo then; then echo "fi"; echo fi done; fi"#,
+    )
+    .success()
+    .stdout("then\n\"fi\"\nfi done\n");
+}
+
+#[test]
+fn test_semicolons_around_control_structures() {
+    let temp_dir = TempDir::new().unwrap();
+
+    run_in(
+        &temp_dir,
+        "echo start; for i in 1 2; do echo $i; done; echo end",
+    )
+    .success()
+    .stdout("start\n1\n2\nend\n");
+    run_in(&temp_dir, "for i in 1 2; do echo $i; done; echo end")
+        .success()
+        .stdout("1\n2\nend\n");
+
+    // 制御構文が閉じていなければ何も実行しない
+    run_in(&temp_dir, "echo start; for i in 1 2; do echo $i")
+        .failure()
+        .stdout("");
+}
+
 #[test]
 fn test_while_loop_basic() {
     let temp_dir = TempDir::new().unwrap();
@@ -1488,49 +4751,365 @@ fn test_while_in_script() {
 
     Command::cargo_bin("rucli")
         .unwrap()
-        .arg(script_file.to_str().unwrap())
-        .current_dir(&temp_dir)
+        .arg(script_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("test"))
+        .stdout(predicate::str::contains("Script completed"));
+}
+
+#[test]
+fn test_while_with_variables() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "env FILENAME=test.txt\n\
+             write $FILENAME content\n\
+             while cat $FILENAME; do rm $FILENAME; done\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("content"));
+}
+
+#[test]
+fn test_while_body_error_continues() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // ボディでエラーが発生してもループは継続（今回の実装では停止）
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "write test.txt line\n\
+             while cat test.txt; do cat nonexistent.txt; done\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("line"))
+        .stderr(predicate::str::contains("No such file"));
+}
+
+#[test]
+fn test_while_loop_arithmetic_counter() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // I/3 が0の間だけ x0 が存在するので3回で止まる
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "write x0 exists\n\
+             env I=0\n\
+             while cat x$((I/3)); do env I=$((I+1)); done\n\
+             echo count=$I\n\
+             echo $((I * 2 + 1))\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("count=3"))
+        .stdout(predicate::str::contains("7"));
+}
+
+#[test]
+fn test_let_counter_loop() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "write x0 exists\n\
+             let I=0\n\
+             while cat x$((I/5)); do echo n$I; let I=I+1; done\n\
+             let \"X = 2 * I\"\n\
+             echo X=$X\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("n0\nexists\nn1"))
+        .stdout(predicate::str::contains("n4"))
+        .stdout(predicate::str::contains("n5").not())
+        .stdout(predicate::str::contains("X=10"));
+}
+
+#[test]
+fn test_arithmetic_for_loop() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "for ((i=0; i<5; i++)); do echo n$i; done\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("n0\nn1\nn2\nn3\nn4"))
+        .stdout(predicate::str::contains("n5").not());
+}
+
+#[test]
+fn test_while_read_lines_from_file() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("lines.txt"), "alpha\nbeta\ngamma\n").unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "while read LINE; do echo got:$LINE; done < lines.txt\n\
+             echo finished\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("got:alpha\ngot:beta\ngot:gamma"))
+        .stdout(predicate::str::contains("finished"));
+}
+
+#[test]
+fn test_for_items_are_expanded_at_runtime() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.txt"), "alpha").unwrap();
+    fs::write(temp_dir.path().join("b.txt"), "beta").unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+    // ワイルドカードはマッチするファイル名に展開する
+    run_in(&temp_dir, "for f in *.txt; do echo file $f; done")
+        .success()
+        .stdout("file a.txt\nfile b.txt\n");
+    run_in(&temp_dir, "for f in *.txt; do cat $f; done")
+        .success()
+        .stdout(predicate::str::contains("alpha"))
+        .stdout(predicate::str::contains("beta"))
+        .stdout(predicate::str::contains("main").not());
+
+    // コマンド置換の結果は空白で分割する
+    run_in(&temp_dir, "for f in $(find . *.rs); do echo found $f; done")
+        .success()
+        .stdout("found ./main.rs\n");
+
+    // 変数の値も分割し、空の展開なら一度も実行しない
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin("LIST=\"x y z\"\nfor w in $LIST; do echo w$w; done\nexit\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("wx\nwy\nwz\n"));
+    run_in(&temp_dir, "for w in $NO_SUCH_VAR; do echo never; done")
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_for_loop_variables_are_session_variables() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 入れ子のループは全ての組み合わせを実行する
+    run_in(
+        &temp_dir,
+        "for i in 1 2; do for j in a b; do echo $i$j; done; done",
+    )
+    .success()
+    .stdout("1a\n1b\n2a\n2b\n");
+
+    // ループ後も変数は最後の値のまま残る
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .write_stdin("for i in x y z; do echo $i; done\necho last=$i\nexit\n")
         .assert()
         .success()
-        .stdout(predicate::str::contains("test"))
-        .stdout(predicate::str::contains("Script completed"));
+        .stdout(predicate::str::contains("x\ny\nz\n"))
+        .stdout(predicate::str::contains("last=z\n"));
+
+    // 同じ名前のセッション変数は上書きされる
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .write_stdin(
+            "N=before\n\
+             for N in 1 2 3\n\
+             do\n\
+               for M in 4 5; do echo $N-$M; done\n\
+             done\n\
+             echo after=$N/$M\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1-4\n1-5\n2-4\n2-5\n3-4\n3-5\n"))
+        .stdout(predicate::str::contains("after=3/5"));
 }
 
 #[test]
-fn test_while_with_variables() {
+fn test_while_loop_has_no_iteration_limit_by_default() {
     let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("x0"), "ok").unwrap();
 
+    // x0 がある間（I が 1500 未満の間）だけ繰り返す
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
         .write_stdin(
-            "env FILENAME=test.txt\n\
-             write $FILENAME content\n\
-             while cat $FILENAME; do rm $FILENAME; done\n\
+            "I=0\n\
+             while cat x$((I/1500)) > /dev/null; do let I=I+1; done\n\
+             echo I=$I\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("content"));
+        .stdout(predicate::str::contains("I=1500"))
+        .stderr(predicate::str::contains("maximum iterations").not());
 }
 
 #[test]
-fn test_while_body_error_continues() {
+fn test_while_loop_respects_configured_max_iterations() {
     let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("x0"), "ok").unwrap();
 
-    // ボディでエラーが発生してもループは継続（今回の実装では停止）
     Command::cargo_bin("rucli")
         .unwrap()
         .current_dir(&temp_dir)
+        .env("RUCLI_MAX_ITERATIONS", "5")
         .write_stdin(
-            "write test.txt line\n\
-             while cat test.txt; do cat nonexistent.txt; done\n\
+            "I=0\n\
+             while cat x0 > /dev/null; do let I=I+1; done\n\
+             echo I=$I\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("line"))
-        .stderr(predicate::str::contains("No such file"));
+        .stdout(predicate::str::contains("I=5"))
+        .stderr(predicate::str::contains(
+            "While loop exceeded maximum iterations (5)",
+        ));
+}
+
+#[test]
+fn test_for_loop_keeps_quoted_items_whole() {
+    let temp_dir = TempDir::new().unwrap();
+
+    run_in(
+        &temp_dir,
+        r#"for name in "Alice Smith" "Bob Jones"; do echo Hi $name; done"#,
+    )
+    .success()
+    .stdout("Hi Alice Smith\nHi Bob Jones\n");
+
+    // クォートの中の変数は展開しても分割しない
+    run_in(
+        &temp_dir,
+        "LIST=\"x y\"\nfor w in \"$LIST\" $LIST; do echo [$w]; done",
+    )
+    .success()
+    .stdout("[x y]\n[x]\n[y]\n");
+
+    // 項目がなければ一度も実行しない
+    run_in(&temp_dir, "for x in; do echo never; done\necho after")
+        .success()
+        .stdout("after\n");
+}
+
+/// 疑似端末の上で起動したrucli（端末で対話しているときの動作を確かめる）
+struct PtySession {
+    child: std::process::Child,
+    master: fs::File,
+    output: std::sync::Arc<std::sync::Mutex<String>>,
+    seen: usize, // expectで読み終えた出力の長さ
+}
+
+impl PtySession {
+    fn start(dir: &std::path::Path) -> Self {
+        use std::os::fd::FromRawFd;
+
+        let (mut master, mut slave) = (0, 0);
+        let status = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        assert_eq!(status, 0, "openpty failed");
+        let master = unsafe { fs::File::from_raw_fd(master) };
+        let slave = unsafe { fs::File::from_raw_fd(slave) };
+
+        let child = std::process::Command::new(env!("CARGO_BIN_EXE_rucli"))
+            .current_dir(dir)
+            .stdin(slave.try_clone().unwrap())
+            .stdout(slave.try_clone().unwrap())
+            .stderr(slave)
+            .spawn()
+            .unwrap();
+
+        // 子プロセスが端末を閉じると読み取りがエラーになって終わる
+        let output = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+        let mut reader = master.try_clone().unwrap();
+        let sink = std::sync::Arc::clone(&output);
+        std::thread::spawn(move || {
+            use std::io::Read;
+            let mut buffer = [0u8; 1024];
+            while let Ok(n @ 1..) = reader.read(&mut buffer) {
+                sink.lock()
+                    .unwrap()
+                    .push_str(&String::from_utf8_lossy(&buffer[..n]));
+            }
+        });
+
+        PtySession {
+            child,
+            master,
+            output,
+            seen: 0,
+        }
+    }
+
+    /// 一行入力する
+    fn send(&mut self, line: &str) {
+        use std::io::Write;
+        write!(self.master, "{line}\r").unwrap();
+    }
+
+    /// 前回の続きから `text` が出力されるまで待つ
+    fn expect(&mut self, text: &str) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        loop {
+            let output = self.output.lock().unwrap().clone();
+            if let Some(pos) = output[self.seen..].find(text) {
+                self.seen += pos + text.len();
+                return;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "timed out waiting for {text:?} in {output:?}"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    }
+
+    /// 行入力が次の入力を待つまで待つ（有効にした括弧付き貼り付けが目印）
+    fn expect_prompt(&mut self) {
+        self.expect("\x1b[?2004h");
+    }
+
+    /// コマンドを入力し、続けて出力させた印と次のプロンプトが表示されるまで待つ
+    fn run(&mut self, command: &str, marker: &str) {
+        self.send(&format!("{command}; echo {marker}"));
+        self.expect(&format!("{marker}\r\n"));
+        self.expect_prompt();
+    }
 }
 
 #[test]
@@ -1550,6 +5129,17 @@ fn test_function_definition_and_call() {
         .stdout(predicate::str::contains("Hello, World!"));
 }
 
+#[test]
+fn test_function_defined_and_called_on_one_line() {
+    // 前の文で定義した関数は、同じ行の後ろの文とパイプラインから呼べる
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args(["-c", "function f() { echo hi; }; f; f | tr a-z A-Z"])
+        .assert()
+        .success()
+        .stdout("hi\nHI\n");
+}
+
 #[test]
 fn test_function_with_arguments() {
     let temp_dir = TempDir::new().unwrap();
@@ -1734,7 +5324,7 @@ fn test_function_with_background() {
         .write_stdin(
             "function background_task() { echo Running in background; }\n\
              background_task &\n\
-             sleep 1\n\
+             sleep 0.5\n\
              exit\n",
         )
         .assert()
@@ -1813,6 +5403,37 @@ fn test_function_with_variables() {
         .stdout(predicate::str::contains("Hello, World!"));
 }
 
+#[test]
+fn test_function_body_with_loop() {
+    // 複数行で定義する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .write_stdin(
+            "function each() {\n\
+             \x20 for f in a b; do\n\
+             \x20   echo item ${f}\n\
+             \x20 done\n\
+             }\n\
+             each\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("item a\nitem b\n"))
+        .stdout(predicate::str::contains("error").not());
+
+    // 一行で定義する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .args([
+            "-c",
+            "function each() { for f in a b; do echo item $f; done }\neach",
+        ])
+        .assert()
+        .success()
+        .stdout("item a\nitem b\n");
+}
+
 #[test]
 fn test_history_command_interactive() {
     let temp_dir = TempDir::new().unwrap();
@@ -2228,7 +5849,9 @@ fn test_history_argument_validation() {
         )
         .assert()
         .success()
-        .stderr(predicate::str::contains("Usage: history [search <query>]"));
+        .stderr(predicate::str::contains(
+            "Usage: history [count | -n count | exec [-y] <number> | clear | delete <number> | search [-r] [--all] <query>]",
+        ));
 }
 
 #[test]
@@ -2603,6 +6226,37 @@ fn test_history_search_special_characters() {
         .stdout(predicate::str::contains("2  echo test > file.txt"));
 }
 
+#[test]
+fn test_history_search_ranked_regex_and_capped() {
+    let temp_dir = TempDir::new().unwrap();
+    let history_file = temp_dir.path().join(".test_search_ranked");
+    let mut input: String = (1..=25).map(|i| format!("echo item {i}\n")).collect();
+    input.push_str("echo item 3\nhistory search item\nhistory search -r --all item [12]$\nexit\n");
+
+    let output = Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_HISTFILE", history_file.to_str().unwrap())
+        .current_dir(&temp_dir)
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.trim_start_matches("> ").trim())
+        .filter(|line| line.contains("echo item"))
+        .collect();
+
+    // 最新の20件（重複した "echo item 3" は最新の26番だけ）、続いて正規表現に一致したもの
+    assert_eq!(lines.len(), 22);
+    assert_eq!(lines[0], "26  echo item 3");
+    assert_eq!(lines[1], "25  echo item 25");
+    assert_eq!(lines[19], "7  echo item 7");
+    assert_eq!(&lines[20..], ["2  echo item 2", "1  echo item 1"]);
+}
+
 #[test]
 fn test_history_navigation_basic() {
     let temp_dir = TempDir::new().unwrap();
@@ -2617,9 +6271,9 @@ fn test_history_navigation_basic() {
              echo second command\n\
              cat test.txt\n\
              history\n\
-             history 1\n\
-             history 2\n\
-             history 3\n\
+             history exec 1\n\
+             history exec 2\n\
+             history exec 3\n\
              exit\n",
         )
         .assert()
@@ -2632,6 +6286,272 @@ fn test_history_navigation_basic() {
         .stdout(predicate::str::contains("   3  cat test.txt"));
 }
 
+#[test]
+fn test_history_recent_delete_and_clear() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo one\n\
+             echo two\n\
+             echo three\n\
+             history -n 2\n\
+             history delete 1\n\
+             history\n\
+             history clear\n\
+             history\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "   3  echo three\n   4  history -n 2\n",
+        ))
+        .stdout(predicate::str::contains("   1  echo two\n   2  echo three"))
+        .stdout(predicate::str::contains("   1  history\n"));
+
+    // 数値だけなら再実行せずに最近の件数を表示する
+    let temp_dir = TempDir::new().unwrap();
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo one\n\
+             echo two\n\
+             history 2\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "   2  echo two\n   3  history 2\n",
+        ))
+        .stdout(predicate::str::contains("   1  echo one").not())
+        .stdout(predicate::str::contains("two").count(2));
+}
+
+#[test]
+fn test_history_size_from_environment() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_HISTSIZE", "2")
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo a\n\
+             echo b\n\
+             history\n\
+             env RUCLI_HISTSIZE=3\n\
+             echo c\n\
+             history\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("   1  echo b\n   2  history\n"))
+        .stdout(predicate::str::contains(
+            "   1  env RUCLI_HISTSIZE=3\n   2  echo c\n   3  history",
+        ));
+}
+
+#[test]
+fn test_history_ignore_and_control_settings() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_HISTIGNORE", "pwd:echo *token*")
+        .env("RUCLI_HISTCONTROL", "erasedups:ignorespace")
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo one\n\
+             pwd\n\
+             echo my token\n\
+             \x20echo hidden\n\
+             echo two\n\
+             echo one\n\
+             history\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hidden"))
+        .stdout(predicate::str::contains(
+            "   1  echo two\n   2  echo one\n   3  history\n",
+        ));
+}
+
+#[test]
+fn test_history_execute_prints_and_records_command() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 実行するコマンドを表示してから出力を表示し、履歴に追加する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo first\n\
+             echo second\n\
+             history exec 1\n\
+             history\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("echo first\nfirst\n"))
+        .stdout(predicate::str::contains(
+            "   3  history exec 1\n   4  echo first\n   5  history",
+        ));
+
+    // 削除後の番号で再実行する
+    let temp_dir = TempDir::new().unwrap();
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo one\n\
+             echo two\n\
+             history -d 1\n\
+             history exec 1\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("echo two\ntwo\n"));
+}
+
+#[test]
+fn test_history_execute_rejects_recursion() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // 1番目が `history exec 1` 自身の場合は再実行し続けない
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "history exec 1\n\
+             echo after\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("after"))
+        .stderr(predicate::str::contains(
+            "history exec 1: recursive history execution",
+        ));
+}
+
+#[test]
+fn test_fc_list_and_substitute() {
+    let temp_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo alpha\n\
+             pwd\n\
+             fc -s alpha=beta echo\n\
+             fc -s\n\
+             fc -l\n\
+             fc -s nothing\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        // 置換したコマンドを表示して実行する
+        .stdout(predicate::str::contains("echo beta\nbeta\n"))
+        // 省略時はfc自身を除いた最新のコマンド
+        .stdout(predicate::str::contains("echo beta\nbeta\n").count(2))
+        .stdout(predicate::str::contains(
+            "   3  fc -s alpha=beta echo\n   4  echo beta\n   5  fc -s\n   6  echo beta\n   7  fc -l",
+        ))
+        .stderr(predicate::str::contains("fc: nothing: no command found"));
+}
+
+#[test]
+fn test_fc_edit_with_editor() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // エディタが書き換えたコマンドを実行する
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_EDITOR", "sed -i s/one/two/")
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo one\n\
+             fc\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("echo two\ntwo\n"));
+
+    // RUCLI_FC_NOEDIT ならエディタを起動せずに実行する
+    let temp_dir = TempDir::new().unwrap();
+    Command::cargo_bin("rucli")
+        .unwrap()
+        .env("RUCLI_EDITOR", "no_such_editor")
+        .env("RUCLI_FC_NOEDIT", "1")
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "echo one\n\
+             fc 1\n\
+             exit\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("echo one\none\n"));
+}
+
+#[test]
+fn test_history_execute_refuses_destructive_commands_without_confirmation() {
+    let temp_dir = TempDir::new().unwrap();
+    let history_file = temp_dir.path().join("history");
+    fs::create_dir(temp_dir.path().join("build")).unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "keep me\n").unwrap();
+    fs::write(
+        &history_file,
+        "rm -r build\necho replaced > notes.txt\necho safe\n",
+    )
+    .unwrap();
+
+    let run = |stdin: &str| {
+        Command::cargo_bin("rucli")
+            .unwrap()
+            .current_dir(&temp_dir)
+            .env("RUCLI_HISTFILE", history_file.to_str().unwrap())
+            .write_stdin(stdin.to_string())
+            .assert()
+    };
+
+    // 端末でなければ確認できないので、rmや > を含むコマンドは再実行しない
+    run("history exec 1\nhistory exec 2\nhistory exec 3\n")
+        .success()
+        .stdout(predicate::str::contains("echo safe\nsafe\n"))
+        .stderr(predicate::str::contains(
+            "history: refusing to re-run destructive command 1 without confirmation (use history exec -y 1)",
+        ))
+        .stderr(predicate::str::contains(
+            "history: refusing to re-run destructive command 2 without confirmation (use history exec -y 2)",
+        ));
+    assert!(temp_dir.path().join("build").is_dir());
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(),
+        "keep me\n"
+    );
+
+    // -y を付ければ確認せずに実行する
+    run("history exec -y 2\n").success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(),
+        "replaced\n"
+    );
+}
+
 #[test]
 fn test_history_navigation_errors() {
     let temp_dir = TempDir::new().unwrap();
@@ -2641,8 +6561,8 @@ fn test_history_navigation_errors() {
         .current_dir(&temp_dir)
         .write_stdin(
             "echo test\n\
-             history 0\n\
-             history 999\n\
+             history exec 0\n\
+             history exec 999\n\
              history abc\n\
              exit\n",
         )
@@ -2668,14 +6588,15 @@ fn test_history_navigation_complex_commands() {
             "echo hello | grep h\n\
              echo test > output.txt\n\
              for i in 1 2 3; do echo $i; done\n\
-             history 1\n\
-             history 3\n\
+             history exec 1\n\
+             history exec 3\n\
              exit\n",
         )
         .assert()
         .success()
-        // パイプラインの再実行
-        .stdout(predicate::str::contains("hello").count(2))
+        // パイプラインの再実行（実行するコマンドも表示する）
+        .stdout(predicate::str::contains("echo hello | grep h\nhello\n"))
+        .stdout(predicate::str::contains("hello").count(3))
         // forループの再実行
         .stdout(predicate::str::contains("1\n2\n3").count(2));
 }
@@ -2691,7 +6612,7 @@ fn test_history_navigation_with_functions() {
             "function greet() { echo Hello, $1!; }\n\
              greet World\n\
              history\n\
-             history 2\n\
+             history exec 2\n\
              exit\n",
         )
         .assert()
@@ -2722,7 +6643,7 @@ fn test_history_navigation_persistence() {
         .env("RUCLI_HISTFILE", history_file.to_str().unwrap())
         .current_dir(&temp_dir)
         .write_stdin(
-            "history 1\n\
+            "history exec 1\n\
              exit\n",
         )
         .assert()
@@ -2740,13 +6661,14 @@ fn test_history_navigation_edge_cases() {
         .current_dir(&temp_dir)
         .write_stdin(
             "echo only command\n\
-             history 1\n\
-             history 2\n\
+             history exec 1\n\
+             history exec 2\n\
              exit\n",
         )
         .assert()
         .success()
-        .stdout(predicate::str::contains("only command").count(3));
+        // 2番目は `history exec 1` で、実行するコマンドを表示しながら1番目を再実行する
+        .stdout(predicate::str::contains("only command").count(5));
 }
 
 #[test]
@@ -2891,7 +6813,7 @@ fn test_history_expansion_with_background() {
         .write_stdin(
             "echo background test\n\
              !! &\n\
-             sleep 1\n\
+             sleep 0.5\n\
              exit\n",
         )
         .assert()